@@ -117,6 +117,47 @@ fn main() {
         }
     "#;
 
+    // Benchmark 6: Array reads (copying or passing an array shares it)
+    let elements: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+    let array_reads = format!(
+        r#"
+        to first(items: [Int]) -> Int {{
+            give back items[0];
+        }}
+
+        to main() {{
+            remember items = [{}];
+            remember sum = 0;
+            repeat 100 times {{
+                remember copy = items;
+                sum = sum + first(copy) + len(copy);
+            }}
+            give back sum;
+        }}
+    "#,
+        elements.join(", ")
+    );
+
+    // Benchmark 7: String reads
+    let string_reads = format!(
+        r#"
+        to size(text: String) -> Int {{
+            give back len(text);
+        }}
+
+        to main() {{
+            remember text = "{}";
+            remember sum = 0;
+            repeat 100 times {{
+                remember copy = text;
+                sum = sum + size(copy);
+            }}
+            give back sum;
+        }}
+    "#,
+        "woke ".repeat(2000)
+    );
+
     let iterations = 1000;
 
     let benchmarks = [
@@ -125,6 +166,8 @@ fn main() {
         ("Conditionals", conditionals),
         ("Loops", loops),
         ("Recursion", recursion),
+        ("Array Reads", array_reads.as_str()),
+        ("String Reads", string_reads.as_str()),
     ];
 
     for (name, source) in benchmarks {
@@ -30,6 +30,7 @@
 #ifndef WOKELANG_H
 #define WOKELANG_H
 
+#include <stddef.h>
 #include <stdint.h>
 
 #ifdef __cplusplus
@@ -56,9 +57,31 @@ typedef enum WokeValueType {
     WOKE_TYPE_STRING = 2,
     WOKE_TYPE_BOOL = 3,
     WOKE_TYPE_ARRAY = 4,
-    WOKE_TYPE_UNIT = 5
+    WOKE_TYPE_UNIT = 5,
+    WOKE_TYPE_OKAY = 6,
+    WOKE_TYPE_OOPS = 7,
+    /* Records, bytes, times, functions and channels */
+    WOKE_TYPE_OTHER = 8
 } WokeValueType;
 
+/* What went wrong in the last call that failed on this thread. The strings
+ * belong to the library and stay valid until the next call that can fail. */
+typedef struct WokeErrorInfo {
+    WokeResult kind;
+    const char* code;     /* e.g. "WOKE-E0303", or NULL */
+    const char* message;
+    uint32_t line;        /* where in the source, from 1; 0 if unknown */
+    uint32_t column;
+} WokeErrorInfo;
+
+/* A host function. Set *out_result to a value you create to give it back
+ * (the interpreter takes ownership), or leave it NULL to give back Unit.
+ * Return anything but WOKE_OK to fail the call, after woke_set_error. */
+typedef WokeResult (*WokeNativeFn)(void* user_data,
+                                   const WokeValue* const* args,
+                                   size_t arg_count,
+                                   WokeValue** out_result);
+
 /* === Interpreter lifecycle === */
 
 /**
@@ -77,24 +100,69 @@ WokeInterpreter* woke_interpreter_new(void);
 void woke_interpreter_free(WokeInterpreter* interp);
 
 /**
- * Execute WokeLang source code.
+ * Execute WokeLang source code. Definitions are kept for later calls, then
+ * statements run, and a main the source defines is run.
  *
  * @param interp The interpreter instance.
  * @param source Null-terminated WokeLang source code.
- * @return WOKE_OK on success, error code otherwise.
+ * @return WOKE_OK on success, error code otherwise; see woke_last_error_info.
  */
 WokeResult woke_exec(WokeInterpreter* interp, const char* source);
 
 /**
- * Evaluate an expression and get the result.
+ * Execute source as woke_exec does and get the value of its final
+ * expression, the one with no semicolon after it, or Unit if it has none.
  *
  * @param interp The interpreter instance.
- * @param source Null-terminated expression to evaluate.
- * @param out_value Pointer to receive the result value.
- * @return WOKE_OK on success, error code otherwise.
+ * @param source Null-terminated source, e.g. "double(21)".
+ * @param out_value Pointer to receive the result value; free it with
+ *        woke_value_free.
+ * @return WOKE_OK on success, error code otherwise; see woke_last_error_info.
  */
 WokeResult woke_eval(WokeInterpreter* interp, const char* source, WokeValue** out_value);
 
+/**
+ * Make a C function callable from programs.
+ *
+ * @param interp The interpreter instance.
+ * @param name Null-terminated name programs call it by.
+ * @param arity How many arguments it takes.
+ * @param capabilities Superpowers asked for before every call, written as
+ *        woke shows them ("notify", "file:read:data"). May be NULL when
+ *        capability_count is 0.
+ * @param capability_count How many superpowers there are.
+ * @param callback The function.
+ * @param user_data Passed to every call as it is.
+ * @return WOKE_OK on success, WOKE_ERROR for an unknown superpower.
+ */
+WokeResult woke_register_function(WokeInterpreter* interp,
+                                  const char* name,
+                                  size_t arity,
+                                  const char* const* capabilities,
+                                  size_t capability_count,
+                                  WokeNativeFn callback,
+                                  void* user_data);
+
+/**
+ * Give a global variable a copy of a value, defining it if need be.
+ *
+ * @param interp The interpreter instance.
+ * @param name Null-terminated variable name.
+ * @param value The value to copy.
+ * @return WOKE_OK on success, error code otherwise.
+ */
+WokeResult woke_set_global(WokeInterpreter* interp, const char* name, const WokeValue* value);
+
+/**
+ * Get a copy of a global variable's value.
+ *
+ * @param interp The interpreter instance.
+ * @param name Null-terminated variable name.
+ * @return New value, or NULL if there is no such variable.
+ *         Caller must free with woke_value_free.
+ */
+WokeValue* woke_get_global(const WokeInterpreter* interp, const char* name);
+
 /* === Value operations === */
 
 /**
@@ -104,6 +172,14 @@ WokeResult woke_eval(WokeInterpreter* interp, const char* source, WokeValue** ou
  */
 void woke_value_free(WokeValue* value);
 
+/**
+ * Copy a WokeValue.
+ *
+ * @param value The value to copy.
+ * @return New value, or NULL if value is NULL.
+ */
+WokeValue* woke_value_clone(const WokeValue* value);
+
 /**
  * Get the type of a WokeValue.
  *
@@ -155,6 +231,43 @@ char* woke_value_as_string(const WokeValue* value);
  */
 void woke_string_free(char* s);
 
+/**
+ * Get the number of elements in an array.
+ *
+ * @param value The array.
+ * @param out Pointer to receive the length.
+ * @return WOKE_OK on success, WOKE_ERROR if not an array.
+ */
+WokeResult woke_value_array_len(const WokeValue* value, size_t* out);
+
+/**
+ * Get a copy of an array's element.
+ *
+ * @param value The array.
+ * @param index Index of the element, from 0.
+ * @return New value, or NULL if not an array or out of bounds.
+ *         Caller must free with woke_value_free.
+ */
+WokeValue* woke_value_array_get(const WokeValue* value, size_t index);
+
+/**
+ * Get a copy of the value inside an Okay.
+ *
+ * @param value The result.
+ * @return New value, or NULL if value is not an Okay.
+ *         Caller must free with woke_value_free.
+ */
+WokeValue* woke_value_okay_value(const WokeValue* value);
+
+/**
+ * Get the message of an Oops.
+ *
+ * @param value The result.
+ * @return Newly allocated string, or NULL if value is not an Oops.
+ *         Caller must free with woke_string_free.
+ */
+char* woke_value_oops_message(const WokeValue* value);
+
 /* === Value creation === */
 
 /**
@@ -189,6 +302,41 @@ WokeValue* woke_value_from_bool(int b);
  */
 WokeValue* woke_value_from_string(const char* s);
 
+/**
+ * Create the Unit WokeValue.
+ */
+WokeValue* woke_value_unit(void);
+
+/**
+ * Create an empty array, to add elements to with woke_value_array_push.
+ */
+WokeValue* woke_value_array_new(void);
+
+/**
+ * Add a copy of a value to the end of an array.
+ *
+ * @param array The array to add to.
+ * @param item The value to copy; still owned by the caller.
+ * @return WOKE_OK on success, WOKE_ERROR if array is not an array.
+ */
+WokeResult woke_value_array_push(WokeValue* array, const WokeValue* item);
+
+/**
+ * Create an Okay holding a copy of a value.
+ *
+ * @param value The value to copy; still owned by the caller.
+ * @return New value, or NULL if value is NULL.
+ */
+WokeValue* woke_value_okay(const WokeValue* value);
+
+/**
+ * Create an Oops with a message.
+ *
+ * @param message Null-terminated message to copy.
+ * @return New value, or NULL if message is NULL or not UTF-8.
+ */
+WokeValue* woke_value_oops(const char* message);
+
 /* === Utility === */
 
 /**
@@ -199,13 +347,85 @@ WokeValue* woke_value_from_string(const char* s);
 const char* woke_version(void);
 
 /**
- * Get the last error message.
+ * Get the last error message on this thread.
  *
- * @return Error message, or NULL if no error.
- *         Valid until the next woke_* call.
+ * @return Error message, or NULL if the last call did not fail.
+ *         Valid until the next woke_* call that can fail.
  */
 const char* woke_last_error(void);
 
+/**
+ * Get what went wrong in the last call on this thread.
+ *
+ * @param out Pointer to receive the error's kind, code, message and place.
+ * @return 1 if the last call failed and out was filled, 0 otherwise.
+ */
+int woke_last_error_info(WokeErrorInfo* out);
+
+/**
+ * Say why a host function is failing, from inside its callback, before it
+ * returns an error code.
+ *
+ * @param message Null-terminated message to copy.
+ */
+void woke_set_error(const char* message);
+
+/* === Plugins === */
+
+/* A plugin is a shared library that adds a module to the standard library.
+ * It defines its register function with WOKE_PLUGIN_REGISTER and reaches
+ * the C API only through the table it is handed, which stays valid for as
+ * long as the program runs:
+ *
+ *     WOKE_PLUGIN_REGISTER(plugin, api) {
+ *         api->set_module(plugin, "gpio");
+ *         api->require(plugin, "custom:gpio");
+ *         return api->add_function(plugin, "read", 1, gpio_read, NULL);
+ *     }
+ *
+ * Loading a plugin needs the custom:plugin:<path> superpower, and the
+ * superpowers it requires are asked for before every call. */
+
+#define WOKE_PLUGIN_API_VERSION 1
+
+typedef struct WokePlugin WokePlugin;
+
+typedef struct WokePluginApi {
+    uint32_t version; /* WOKE_PLUGIN_API_VERSION */
+    /* Name the module: "gpio" adds std.gpio */
+    WokeResult (*set_module)(WokePlugin* plugin, const char* name);
+    /* Declare a superpower the functions use, e.g. "network:broker.local" */
+    WokeResult (*require)(WokePlugin* plugin, const char* capability);
+    /* Add a function to the module, as woke_register_function does */
+    WokeResult (*add_function)(WokePlugin* plugin, const char* name, size_t arity,
+                               WokeNativeFn callback, void* user_data);
+    void (*set_error)(const char* message);
+    void (*value_free)(WokeValue* value);
+    WokeValue* (*value_clone)(const WokeValue* value);
+    WokeValueType (*value_type)(const WokeValue* value);
+    WokeResult (*value_as_int)(const WokeValue* value, int64_t* out);
+    WokeResult (*value_as_float)(const WokeValue* value, double* out);
+    WokeResult (*value_as_bool)(const WokeValue* value, int* out);
+    char* (*value_as_string)(const WokeValue* value);
+    void (*string_free)(char* s);
+    WokeResult (*value_array_len)(const WokeValue* value, size_t* out);
+    WokeValue* (*value_array_get)(const WokeValue* value, size_t index);
+    WokeValue* (*value_okay_value)(const WokeValue* value);
+    char* (*value_oops_message)(const WokeValue* value);
+    WokeValue* (*value_from_int)(int64_t n);
+    WokeValue* (*value_from_float)(double n);
+    WokeValue* (*value_from_bool)(int b);
+    WokeValue* (*value_from_string)(const char* s);
+    WokeValue* (*value_unit)(void);
+    WokeValue* (*value_array_new)(void);
+    WokeResult (*value_array_push)(WokeValue* array, const WokeValue* value);
+    WokeValue* (*value_okay)(const WokeValue* value);
+    WokeValue* (*value_oops)(const char* message);
+} WokePluginApi;
+
+#define WOKE_PLUGIN_REGISTER(plugin, api) \
+    WokeResult woke_plugin_register_v1(WokePlugin* plugin, const WokePluginApi* api)
+
 #ifdef __cplusplus
 }
 #endif
@@ -1,10 +1,12 @@
+pub use crate::lexer::Symbol;
+use serde::{Deserialize, Serialize};
 use std::ops::Range;
 
 /// Source span for error reporting
 pub type Span = Range<usize>;
 
 /// A spanned AST node
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Spanned<T> {
     pub node: T,
     pub span: Span,
@@ -17,13 +19,23 @@ pub fn new(node: T, span: Span) -> Self {
 }
 
 /// The root of a WokeLang program
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Program {
     pub items: Vec<TopLevelItem>,
 }
 
+impl Program {
+    /// Every entry of the program's `thanks to` blocks, in the order written
+    pub fn gratitude(&self) -> impl Iterator<Item = &GratitudeEntry> {
+        self.items.iter().flat_map(|item| match item {
+            TopLevelItem::GratitudeDecl(decl) => decl.entries.as_slice(),
+            _ => &[],
+        })
+    }
+}
+
 /// Top-level items in a program
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TopLevelItem {
     Function(FunctionDef),
     ConsentBlock(ConsentBlock),
@@ -35,32 +47,56 @@ pub enum TopLevelItem {
     Pragma(Pragma),
     TypeDef(TypeDef),
     ConstDef(ConstDef),
+    Expect(ExpectBlock),
+    /// A variable every function can read and assign: `remember x = expr;`
+    /// outside any function
+    Global(VarDecl),
 }
 
-/// Module import: `use foo.bar renamed baz;`
-#[derive(Debug, Clone)]
+/// Module import: `use foo.bar renamed baz must have read "data";`
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModuleImport {
     pub path: QualifiedName,
     pub rename: Option<String>,
+    /// Capabilities the module may use; `None` if the import declares none
+    pub needs: Option<Vec<CapabilityNeed>>,
+    /// Why the module needs them: `must have ... because "..."`
+    pub reason: Option<String>,
+    pub span: Span,
+}
+
+impl ModuleImport {
+    /// The name the module is known by where it is imported: its `renamed`
+    /// name, or the last part of its path
+    pub fn alias(&self) -> String {
+        self.rename.clone().or_else(|| self.path.parts.last().cloned()).unwrap_or_default()
+    }
+}
+
+/// A capability declared by an import: `read "data"`, `network`, `crypto`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapabilityNeed {
+    pub kind: String,
+    pub value: Option<String>,
     pub span: Span,
 }
 
 /// Qualified name: `foo.bar.baz`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QualifiedName {
     pub parts: Vec<String>,
     pub span: Span,
 }
 
 /// Generic type parameter: `<T: Trait>` or just `<T>`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TypeParam {
     pub name: String,
     pub bounds: Vec<String>, // Trait bounds (future use)
 }
 
 /// Function definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDef {
     pub emote: Option<EmoteTag>,
     pub name: String,
@@ -71,33 +107,39 @@ pub struct FunctionDef {
     pub body: Vec<Statement>,
     pub goodbye: Option<String>,
     pub span: Span,
+    /// Module an imported function is from (`shapes.circle`); `None` for
+    /// the program's own functions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
 }
 
 /// Function parameter
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Parameter {
-    pub name: String,
+    pub name: Symbol,
     pub ty: Option<Type>,
     pub span: Span,
 }
 
 /// Consent block: `only if okay "permission" { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsentBlock {
     pub permission: String,
+    /// Why the program asks, shown in the prompt: `because "..."`
+    pub reason: Option<String>,
     pub body: Vec<Statement>,
     pub span: Span,
 }
 
 /// Gratitude declaration: `thanks to { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GratitudeDecl {
     pub entries: Vec<GratitudeEntry>,
     pub span: Span,
 }
 
 /// Single gratitude entry: `"name" → "reason";`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GratitudeEntry {
     pub recipient: String,
     pub reason: String,
@@ -105,7 +147,7 @@ pub struct GratitudeEntry {
 }
 
 /// Statement types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Statement {
     /// `remember x = expr;`
     VarDecl(VarDecl),
@@ -133,32 +175,54 @@ pub enum Statement {
     Decide(DecideStmt),
 }
 
-/// Variable declaration: `remember x = expr measured in unit;`
-#[derive(Debug, Clone)]
+impl Statement {
+    /// Source span of the whole statement
+    pub fn span(&self) -> &Span {
+        match self {
+            Statement::VarDecl(s) => &s.span,
+            Statement::Assignment(s) => &s.span,
+            Statement::Return(s) => &s.span,
+            Statement::Conditional(s) => &s.span,
+            Statement::Loop(s) => &s.span,
+            Statement::AttemptBlock(s) => &s.span,
+            Statement::ConsentBlock(s) => &s.span,
+            Statement::Expression(s) => &s.span,
+            Statement::WorkerSpawn(s) => &s.span,
+            Statement::Complain(s) => &s.span,
+            Statement::EmoteAnnotated(s) => &s.span,
+            Statement::Decide(s) => &s.span,
+        }
+    }
+}
+
+/// Variable declaration: `remember x = expr measured in unit;`, or
+/// `remember secret x = expr;` for a value logs and errors must not show
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VarDecl {
-    pub name: String,
+    pub name: Symbol,
     pub value: Spanned<Expr>,
     pub unit: Option<String>,
+    pub secret: bool,
     pub span: Span,
 }
 
 /// Assignment: `x = expr;`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Assignment {
-    pub target: String,
+    pub target: Symbol,
     pub value: Spanned<Expr>,
     pub span: Span,
 }
 
 /// Return statement: `give back expr;`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReturnStmt {
     pub value: Spanned<Expr>,
     pub span: Span,
 }
 
 /// Conditional: `when expr { ... } otherwise { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Conditional {
     pub condition: Spanned<Expr>,
     pub then_branch: Vec<Statement>,
@@ -167,7 +231,7 @@ pub struct Conditional {
 }
 
 /// Loop: `repeat n times { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Loop {
     pub count: Spanned<Expr>,
     pub body: Vec<Statement>,
@@ -175,7 +239,7 @@ pub struct Loop {
 }
 
 /// Attempt block: `attempt safely { ... } or reassure "msg";`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttemptBlock {
     pub body: Vec<Statement>,
     pub reassurance: String,
@@ -183,21 +247,21 @@ pub struct AttemptBlock {
 }
 
 /// Worker spawn: `spawn worker name;`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkerSpawn {
     pub worker_name: String,
     pub span: Span,
 }
 
 /// Complain statement: `complain "message";`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComplainStmt {
     pub message: String,
     pub span: Span,
 }
 
 /// Emote-annotated statement: `@emote statement`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmoteAnnotatedStmt {
     pub emote: EmoteTag,
     pub statement: Box<Statement>,
@@ -205,7 +269,7 @@ pub struct EmoteAnnotatedStmt {
 }
 
 /// Decide statement (pattern matching): `decide based on expr { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DecideStmt {
     pub scrutinee: Spanned<Expr>,
     pub arms: Vec<MatchArm>,
@@ -213,7 +277,7 @@ pub struct DecideStmt {
 }
 
 /// Match arm: `pattern → { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchArm {
     pub pattern: Pattern,
     pub body: Vec<Statement>,
@@ -221,12 +285,12 @@ pub struct MatchArm {
 }
 
 /// Pattern for matching
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Pattern {
     /// Literal pattern: `42`, `"hello"`, `true`
     Literal(Literal),
     /// Identifier pattern (binds value): `x`
-    Identifier(String),
+    Identifier(Symbol),
     /// Wildcard pattern: `_`
     Wildcard,
     /// Constructor pattern: `Okay(x)`, `Oops(e)`
@@ -234,18 +298,18 @@ pub enum Pattern {
 }
 
 /// Expression types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Expr {
     /// Literal value
     Literal(Literal),
     /// Variable reference
-    Identifier(String),
+    Identifier(Symbol),
     /// Binary operation
     Binary(BinaryOp, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
     /// Unary operation
     Unary(UnaryOp, Box<Spanned<Expr>>),
     /// Function call by name
-    Call(String, Vec<Spanned<Expr>>),
+    Call(Symbol, Vec<Spanned<Expr>>),
     /// Call expression: `expr(args)` - for calling closures
     CallExpr(Box<Spanned<Expr>>, Vec<Spanned<Expr>>),
     /// Unit measurement: `expr measured in unit`
@@ -267,7 +331,7 @@ pub enum Expr {
 }
 
 /// Binary operators
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -285,24 +349,26 @@ pub enum BinaryOp {
 }
 
 /// Unary operators
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum UnaryOp {
     Neg,
     Not,
 }
 
 /// Literal values
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Literal {
     Integer(i64),
     Float(f64),
+    /// `1.50d`, exact to the places written
+    Decimal(rust_decimal::Decimal),
     String(String),
     Bool(bool),
     Unit, // The () value
 }
 
 /// Lambda expression body
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LambdaBody {
     /// Expression body: `|x| -> x + 1`
     Expr(Box<Spanned<Expr>>),
@@ -311,7 +377,7 @@ pub enum LambdaBody {
 }
 
 /// Lambda/closure expression: `|x, y| -> expr` or `|x, y| { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LambdaExpr {
     pub params: Vec<Parameter>,
     pub return_type: Option<Type>,
@@ -319,22 +385,22 @@ pub struct LambdaExpr {
 }
 
 /// Emote tag: `@name(params)`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmoteTag {
     pub name: String,
     pub params: Vec<EmoteParam>,
     pub span: Span,
 }
 
-/// Emote parameter: `name=value`
-#[derive(Debug, Clone)]
+/// Emote parameter: `name=value`, or a bare `name` as in `@allow(unused)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmoteParam {
     pub name: String,
-    pub value: EmoteValue,
+    pub value: Option<EmoteValue>,
 }
 
 /// Emote parameter value
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EmoteValue {
     Number(f64),
     String(String),
@@ -342,15 +408,25 @@ pub enum EmoteValue {
 }
 
 /// Worker definition: `worker name { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkerDef {
     pub name: String,
     pub body: Vec<Statement>,
     pub span: Span,
 }
 
+/// Test expectation: `expect "description" { ... }`
+///
+/// Skipped by a normal run; the test runner executes each block on its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpectBlock {
+    pub description: String,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
 /// Side quest definition: `side quest name { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SideQuestDef {
     pub name: String,
     pub body: Vec<Statement>,
@@ -358,7 +434,7 @@ pub struct SideQuestDef {
 }
 
 /// Superpower declaration: `superpower name { ... }`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SuperpowerDecl {
     pub name: String,
     pub body: Vec<Statement>,
@@ -366,22 +442,28 @@ pub struct SuperpowerDecl {
 }
 
 /// Pragma: `#care on;`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Pragma {
     pub directive: PragmaDirective,
     pub enabled: bool,
     pub span: Span,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum PragmaDirective {
     Care,
     Strict,
     Verbose,
+    /// `#side quests off;` skips the program's side quests
+    SideQuests,
+    /// `#timeout 5s;` stops a run that takes longer
+    Timeout(std::time::Duration),
+    /// `#maxmemory 64mb;` stops a run whose values hold more bytes
+    MaxMemory(u64),
 }
 
 /// Type annotation
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Type {
     /// Basic types: String, Int, Float, Bool, or custom
     Basic(String),
@@ -400,7 +482,7 @@ pub enum Type {
 }
 
 /// Type definition: `type Name = ...;`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TypeDef {
     pub name: String,
     pub definition: TypeVariant,
@@ -408,7 +490,7 @@ pub struct TypeDef {
 }
 
 /// Type variant for type definitions
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TypeVariant {
     /// Struct: `{ field: Type, ... }`
     Struct(Vec<Field>),
@@ -419,21 +501,21 @@ pub enum TypeVariant {
 }
 
 /// Struct field
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Field {
     pub name: String,
     pub ty: Type,
 }
 
 /// Enum variant
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Variant {
     pub name: String,
     pub fields: Vec<Type>,
 }
 
 /// Constant definition: `const NAME: Type = expr;`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConstDef {
     pub name: String,
     pub ty: Type,
@@ -0,0 +1,322 @@
+//! Micro-Benchmarks for WokeLang
+//!
+//! Finds a program's `to bench_*()` functions without parameters and times
+//! them, on the interpreter and on the bytecode VM, to track the
+//! performance of both. Each function is called a few times to warm up and
+//! then timed over many runs. Like tests, benchmarks run with every
+//! superpower refused unless the runner's policy allows it.
+//!
+//! Results can be saved as a JSON baseline and later runs compared with
+//! it; `woke bench` does both.
+
+use crate::ast::{Program, TopLevelItem};
+use crate::interpreter::Interpreter;
+use crate::security::{CapabilityRegistry, ConsentMode, Policy};
+use crate::vm::{BytecodeCompiler, VirtualMachine};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+pub use crate::pipeline::Engine;
+
+/// Prefix of the functions that are benchmarks
+pub const BENCH_PREFIX: &str = "bench_";
+
+/// Summary of a benchmark's timed runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub runs: usize,
+    pub mean: Duration,
+    pub median: Duration,
+    pub stddev: Duration,
+}
+
+impl Stats {
+    /// Summarize the times of some runs; there must be at least one
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let n = sorted.len();
+        let median = if n.is_multiple_of(2) {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2
+        } else {
+            sorted[n / 2]
+        };
+        let secs: Vec<f64> = samples.iter().map(Duration::as_secs_f64).collect();
+        let mean = secs.iter().sum::<f64>() / n as f64;
+        let variance = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n as f64;
+        Stats {
+            runs: n,
+            mean: Duration::from_secs_f64(mean),
+            median,
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        }
+    }
+}
+
+/// How one benchmark went on one engine
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    pub engine: Engine,
+    /// The timings, or why the benchmark could not run
+    pub stats: Result<Stats, String>,
+}
+
+/// Every benchmark function in a program, in source order
+pub fn discover(program: &Program) -> Vec<String> {
+    program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            TopLevelItem::Function(f) if f.name.starts_with(BENCH_PREFIX) && f.params.is_empty() => {
+                Some(f.name.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Times a program's benchmarks
+#[derive(Debug, Clone)]
+pub struct BenchRunner {
+    iterations: usize,
+    warmup: usize,
+    filter: Option<String>,
+    engines: Vec<Engine>,
+    policy: Policy,
+}
+
+impl BenchRunner {
+    pub fn new() -> Self {
+        Self {
+            iterations: 100,
+            warmup: 3,
+            filter: None,
+            engines: vec![Engine::Interpreter, Engine::Vm],
+            policy: Policy::default(),
+        }
+    }
+
+    /// Time this many runs of each benchmark (at least one)
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    /// Only run benchmarks whose name contains `filter`
+    pub fn with_filter(mut self, filter: &str) -> Self {
+        self.filter = Some(filter.to_string());
+        self
+    }
+
+    /// Run on these engines only
+    pub fn with_engines(mut self, engines: &[Engine]) -> Self {
+        self.engines = engines.to_vec();
+        self
+    }
+
+    /// Let benchmarks use what `policy` allows; everything else is refused
+    pub fn with_policy(mut self, policy: &Policy) -> Self {
+        self.policy = policy.clone();
+        self
+    }
+
+    /// The program's benchmarks that pass the filter
+    pub fn selected(&self, program: &Program) -> Vec<String> {
+        discover(program)
+            .into_iter()
+            .filter(|name| self.filter.as_ref().is_none_or(|f| name.contains(f.as_str())))
+            .collect()
+    }
+
+    /// Time every selected benchmark on every engine
+    pub fn run(&self, program: &Program) -> Vec<BenchResult> {
+        let mut results = Vec::new();
+        for name in self.selected(program) {
+            for &engine in &self.engines {
+                let stats = match engine {
+                    Engine::Interpreter => self.time_interpreter(program, &name),
+                    Engine::Vm => self.time_vm(program, &name),
+                };
+                results.push(BenchResult { name: name.clone(), engine, stats });
+            }
+        }
+        results
+    }
+
+    fn capabilities(&self) -> CapabilityRegistry {
+        let mut caps = CapabilityRegistry::new();
+        caps.apply_policy(&self.policy);
+        caps.set_consent_mode(ConsentMode::Deny);
+        caps
+    }
+
+    fn time_interpreter(&self, program: &Program, name: &str) -> Result<Stats, String> {
+        let mut interpreter = Interpreter::new()
+            .with_output(std::io::sink())
+            .with_capabilities(self.capabilities())
+            .with_deny_all();
+        interpreter.load(program);
+        let mut samples = Vec::with_capacity(self.iterations);
+        for run in 0..self.warmup + self.iterations {
+            let started = Instant::now();
+            interpreter.call(name, vec![]).map_err(|e| e.to_string())?;
+            if run >= self.warmup {
+                samples.push(started.elapsed());
+            }
+        }
+        Ok(Stats::from_samples(&samples))
+    }
+
+    fn time_vm(&self, program: &Program, name: &str) -> Result<Stats, String> {
+        let mut compiled = BytecodeCompiler::new()
+            .compile(program)
+            .map_err(|e| format!("not supported by the VM: {}", e))?;
+        compiled.entry = compiled.function_index(name);
+        let mut samples = Vec::with_capacity(self.iterations);
+        for run in 0..self.warmup + self.iterations {
+            let mut vm = VirtualMachine::new(compiled.clone()).with_capabilities(self.capabilities());
+            let started = Instant::now();
+            vm.run().map_err(|e| e.to_string())?;
+            if run >= self.warmup {
+                samples.push(started.elapsed());
+            }
+        }
+        Ok(Stats::from_samples(&samples))
+    }
+}
+
+impl Default for BenchRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Saved timings to compare later runs with, keyed by benchmark and engine
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Baseline {
+    medians: BTreeMap<String, BTreeMap<String, Duration>>,
+}
+
+impl Baseline {
+    /// The medians of the benchmarks that ran
+    pub fn from_results(results: &[BenchResult]) -> Self {
+        let mut baseline = Baseline::default();
+        for result in results {
+            if let Ok(stats) = &result.stats {
+                baseline
+                    .medians
+                    .entry(result.name.clone())
+                    .or_default()
+                    .insert(result.engine.name().to_string(), stats.median);
+            }
+        }
+        baseline
+    }
+
+    /// Read a baseline saved with [`Baseline::to_json`]
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let json: serde_json::Value =
+            serde_json::from_str(text).map_err(|e| format!("Invalid baseline: {}", e))?;
+        let invalid = || "Invalid baseline: expected {\"bench_name\": {\"engine\": median_ns}}".to_string();
+        let mut baseline = Baseline::default();
+        for (name, engines) in json.as_object().ok_or_else(invalid)? {
+            for (engine, nanos) in engines.as_object().ok_or_else(invalid)? {
+                let nanos = nanos.as_u64().ok_or_else(invalid)?;
+                baseline
+                    .medians
+                    .entry(name.clone())
+                    .or_default()
+                    .insert(engine.clone(), Duration::from_nanos(nanos));
+            }
+        }
+        Ok(baseline)
+    }
+
+    /// The baseline as JSON, with medians in nanoseconds
+    pub fn to_json(&self) -> String {
+        let json: serde_json::Map<String, serde_json::Value> = self
+            .medians
+            .iter()
+            .map(|(name, engines)| {
+                let engines = engines
+                    .iter()
+                    .map(|(engine, median)| (engine.clone(), (median.as_nanos() as u64).into()))
+                    .collect();
+                (name.clone(), serde_json::Value::Object(engines))
+            })
+            .collect();
+        serde_json::to_string_pretty(&json).unwrap_or_default()
+    }
+
+    /// The saved median of a benchmark on an engine
+    pub fn median(&self, name: &str, engine: Engine) -> Option<Duration> {
+        self.medians.get(name)?.get(engine.name()).copied()
+    }
+
+    /// How much slower (positive) or faster (negative) `stats` is than
+    /// the baseline, as a percentage of the saved median
+    pub fn change(&self, name: &str, engine: Engine, stats: &Stats) -> Option<f64> {
+        let saved = self.median(name, engine)?.as_secs_f64();
+        (saved > 0.0).then(|| (stats.median.as_secs_f64() / saved - 1.0) * 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Pipeline;
+
+    #[test]
+    fn test_stats() {
+        let ms = Duration::from_millis;
+        let stats = Stats::from_samples(&[ms(4), ms(1), ms(3), ms(2)]);
+        assert_eq!(stats.runs, 4);
+        assert_eq!(stats.mean, Duration::from_micros(2500));
+        assert_eq!(stats.median, Duration::from_micros(2500));
+        assert!((stats.stddev.as_secs_f64() - 0.001118).abs() < 1e-6);
+        assert_eq!(Stats::from_samples(&[ms(5)]).stddev, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_run_benchmarks() {
+        let source = r#"
+            to fib(n: Int) -> Int {
+                when n < 2 {
+                    give back n;
+                }
+                give back fib(n - 1) + fib(n - 2);
+            }
+
+            to bench_fib() {
+                fib(10);
+            }
+
+            to bench_env() {
+                std.env.get("HOME");
+            }
+        "#;
+        let program = Pipeline::new().parse(source).unwrap();
+
+        let results = BenchRunner::new().with_iterations(5).with_filter("fib").run(&program);
+        assert_eq!(results.len(), 2);
+        for result in &results {
+            assert_eq!(result.name, "bench_fib");
+            assert_eq!(result.stats.as_ref().unwrap().runs, 5);
+        }
+
+        let results = BenchRunner::new()
+            .with_engines(&[Engine::Interpreter])
+            .with_filter("env")
+            .run(&program);
+        assert!(results[0].stats.is_err(), "environment access is refused");
+
+        let baseline = Baseline::parse(r#"{"bench_fib": {"vm": 2000000}}"#).unwrap();
+        assert_eq!(baseline.median("bench_fib", Engine::Vm), Some(Duration::from_millis(2)));
+        assert_eq!(Baseline::parse(&baseline.to_json()).unwrap(), baseline);
+        let stats = Stats::from_samples(&[Duration::from_millis(3)]);
+        assert_eq!(baseline.change("bench_fib", Engine::Vm, &stats), Some(50.0));
+        assert_eq!(baseline.change("bench_fib", Engine::Interpreter, &stats), None);
+        assert!(Baseline::parse("[1]").is_err());
+    }
+}
@@ -0,0 +1,63 @@
+// Reference host for WokeLang WASM modules.
+//
+// Provides the `woke` import module expected by code built with
+// `woke compile`:
+//
+//   print(ptr, len)             print a UTF-8 string from linear memory
+//   print_int(n)                print a 64-bit integer (passed as a BigInt)
+//   print_float(x)              print a float
+//   request_consent(ptr, len)   ask for a permission; returns 1 if granted
+//
+// Consent decisions are cached per permission for the lifetime of the host,
+// matching the interpreter. Modules targeting WASI import only
+// `request_consent` from `woke`.
+//
+// Usage:
+//
+//   import { createWokeHost } from "./host.js";
+//
+//   const host = createWokeHost({
+//     requestConsent: (permission) => permission.startsWith("network"),
+//   });
+//   const { instance } = await WebAssembly.instantiate(bytes, host.imports);
+//   host.bind(instance);
+//   instance.exports.__woke_init?.();
+//   instance.exports.main();
+
+export function createWokeHost({
+  print = (line) => console.log(line),
+  requestConsent = () => false,
+} = {}) {
+  const decoder = new TextDecoder();
+  const decisions = new Map();
+  let memory = null;
+
+  const readString = (ptr, len) => {
+    if (memory === null) {
+      throw new Error("woke host used before bind(instance)");
+    }
+    return decoder.decode(new Uint8Array(memory.buffer, ptr, len));
+  };
+
+  return {
+    imports: {
+      woke: {
+        print: (ptr, len) => print(readString(ptr, len)),
+        print_int: (n) => print(n.toString()),
+        print_float: (x) => print(x.toString()),
+        request_consent: (ptr, len) => {
+          const permission = readString(ptr, len);
+          if (!decisions.has(permission)) {
+            decisions.set(permission, Boolean(requestConsent(permission)));
+          }
+          return decisions.get(permission) ? 1 : 0;
+        },
+      },
+    },
+
+    // Attach the instance whose exported memory the imports read from
+    bind(instance) {
+      memory = instance.exports.memory;
+    },
+  };
+}
@@ -1,3 +1,10 @@
+mod runtime;
+mod sourcemap;
+mod wasi;
 mod wasm;
 
-pub use wasm::WasmCompiler;
+pub use wasi::WASI_MODULE;
+pub use wasm::{WasmCompiler, WasmTarget, HOST_MODULE, INIT_FUNCTION};
+
+/// Reference JavaScript implementation of the `woke` host imports
+pub const JS_HOST: &str = include_str!("host.js");
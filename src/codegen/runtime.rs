@@ -0,0 +1,225 @@
+//! Runtime support emitted into every WASM module
+//!
+//! Strings live in linear memory as a length-prefixed block: a little-endian
+//! `u32` byte length followed by the UTF-8 bytes. A string value is an `i32`
+//! pointer to that block. Address 0 is never allocated and reads as the
+//! empty string.
+//!
+//! Memory is managed by a bump allocator whose next free address is kept in
+//! the mutable global [`HEAP_GLOBAL`]; it grows the memory as needed and
+//! never frees.
+//!
+//! Low memory layout:
+//!
+//! | Address | Contents                                  |
+//! |---------|-------------------------------------------|
+//! | 0..4    | empty string (length 0)                   |
+//! | 8..16   | WASI iovec scratch                        |
+//! | 16..20  | WASI `nwritten` scratch                   |
+//! | 20..23  | `\n`, `-`, `.` characters (WASI only)     |
+//! | 24..48  | number formatting buffer                  |
+//! | 48..    | string literals, then the heap            |
+
+use wasm_encoder::{BlockType, Function, MemArg, ValType};
+
+/// Address of the iovec used for `fd_write`
+pub const IOVEC: u32 = 8;
+
+/// Address receiving the byte count written by `fd_write`
+pub const NWRITTEN: u32 = 16;
+
+/// Address of a newline character
+pub const NEWLINE: u32 = 20;
+
+/// Address of a minus sign
+pub const MINUS: u32 = 21;
+
+/// Address of a decimal point
+pub const DOT: u32 = 22;
+
+/// Start of the number formatting buffer
+pub const NUMBUF: u32 = 24;
+
+/// Size of the number formatting buffer (fits any u64)
+pub const NUMBUF_LEN: u32 = 24;
+
+/// Offset at which string literals are placed in linear memory
+pub const DATA_START: u32 = 48;
+
+/// Index of the global holding the next free heap address
+pub const HEAP_GLOBAL: u32 = 0;
+
+/// Size of a WASM page in bytes
+pub const PAGE_SIZE: u32 = 65536;
+
+/// Round `n` up to the allocator's 8-byte alignment
+pub fn align(n: u32) -> u32 {
+    (n + 7) & !7
+}
+
+/// Memory argument for a 32-bit load/store at `offset`
+pub fn mem_i32(offset: u64) -> MemArg {
+    MemArg {
+        offset,
+        align: 2,
+        memory_index: 0,
+    }
+}
+
+fn mem_u8(offset: u64) -> MemArg {
+    MemArg {
+        offset,
+        align: 0,
+        memory_index: 0,
+    }
+}
+
+/// `__alloc(size: i32) -> i32`
+pub fn alloc() -> Function {
+    const SIZE: u32 = 0;
+    const PTR: u32 = 1;
+
+    let mut f = Function::new([(1, ValType::I32)]);
+    let mut ins = f.instructions();
+
+    // ptr = heap; heap = align(heap + size)
+    ins.global_get(HEAP_GLOBAL).local_set(PTR);
+    ins.global_get(HEAP_GLOBAL)
+        .local_get(SIZE)
+        .i32_add()
+        .i32_const(7)
+        .i32_add()
+        .i32_const(-8)
+        .i32_and()
+        .global_set(HEAP_GLOBAL);
+
+    // Grow memory when the heap runs past the current size
+    ins.block(BlockType::Empty);
+    ins.global_get(HEAP_GLOBAL)
+        .memory_size(0)
+        .i32_const(16)
+        .i32_shl()
+        .i32_le_u()
+        .br_if(0);
+    ins.global_get(HEAP_GLOBAL)
+        .memory_size(0)
+        .i32_const(16)
+        .i32_shl()
+        .i32_sub()
+        .i32_const(PAGE_SIZE as i32 - 1)
+        .i32_add()
+        .i32_const(16)
+        .i32_shr_u()
+        .memory_grow(0)
+        .i32_const(-1)
+        .i32_eq();
+    ins.if_(BlockType::Empty).unreachable().end();
+    ins.end();
+
+    ins.local_get(PTR).end();
+    f
+}
+
+/// `__concat(a: i32, b: i32) -> i32`, calling the allocator at `alloc_idx`
+pub fn concat(alloc_idx: u32) -> Function {
+    const A: u32 = 0;
+    const B: u32 = 1;
+    const LEN_A: u32 = 2;
+    const LEN_B: u32 = 3;
+    const RESULT: u32 = 4;
+
+    let mut f = Function::new([(3, ValType::I32)]);
+    let mut ins = f.instructions();
+
+    ins.local_get(A).i32_load(mem_i32(0)).local_set(LEN_A);
+    ins.local_get(B).i32_load(mem_i32(0)).local_set(LEN_B);
+
+    // result = alloc(4 + len_a + len_b); *result = len_a + len_b
+    ins.local_get(LEN_A)
+        .local_get(LEN_B)
+        .i32_add()
+        .i32_const(4)
+        .i32_add()
+        .call(alloc_idx)
+        .local_set(RESULT);
+    ins.local_get(RESULT)
+        .local_get(LEN_A)
+        .local_get(LEN_B)
+        .i32_add()
+        .i32_store(mem_i32(0));
+
+    // Copy both payloads after the length prefix
+    ins.local_get(RESULT)
+        .i32_const(4)
+        .i32_add()
+        .local_get(A)
+        .i32_const(4)
+        .i32_add()
+        .local_get(LEN_A)
+        .memory_copy(0, 0);
+    ins.local_get(RESULT)
+        .i32_const(4)
+        .i32_add()
+        .local_get(LEN_A)
+        .i32_add()
+        .local_get(B)
+        .i32_const(4)
+        .i32_add()
+        .local_get(LEN_B)
+        .memory_copy(0, 0);
+
+    ins.local_get(RESULT).end();
+    f
+}
+
+/// `__str_eq(a: i32, b: i32) -> i32`
+pub fn str_eq() -> Function {
+    const A: u32 = 0;
+    const B: u32 = 1;
+    const LEN: u32 = 2;
+    const I: u32 = 3;
+
+    let mut f = Function::new([(2, ValType::I32)]);
+    let mut ins = f.instructions();
+
+    // Different lengths can never be equal
+    ins.local_get(A)
+        .i32_load(mem_i32(0))
+        .local_tee(LEN)
+        .local_get(B)
+        .i32_load(mem_i32(0))
+        .i32_ne();
+    ins.if_(BlockType::Empty).i32_const(0).return_().end();
+
+    // Compare byte by byte
+    ins.block(BlockType::Empty).loop_(BlockType::Empty);
+    ins.local_get(I).local_get(LEN).i32_ge_u().br_if(1);
+    ins.local_get(A)
+        .local_get(I)
+        .i32_add()
+        .i32_load8_u(mem_u8(4))
+        .local_get(B)
+        .local_get(I)
+        .i32_add()
+        .i32_load8_u(mem_u8(4))
+        .i32_ne();
+    ins.if_(BlockType::Empty).i32_const(0).return_().end();
+    ins.local_get(I).i32_const(1).i32_add().local_set(I).br(0);
+    ins.end().end();
+
+    ins.i32_const(1).end();
+    f
+}
+
+/// Encode a string literal as a length-prefixed block
+pub fn encode_string(s: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(4 + s.len());
+    bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(s.as_bytes());
+    bytes
+}
+
+/// Pages needed to hold `bytes` bytes (at least one)
+pub fn pages_for(bytes: u32) -> u64 {
+    (bytes.div_ceil(PAGE_SIZE) as u64).max(1)
+}
@@ -0,0 +1,177 @@
+//! Source maps for generated modules
+//!
+//! Follows the WebAssembly convention used by browser devtools: a version 3
+//! source map with a single generated line, where the generated column is the
+//! byte offset of an instruction in the `.wasm` file. The module points at the
+//! map through a `sourceMappingURL` custom section.
+
+/// Name of the custom section holding the source map URL
+pub const URL_SECTION: &str = "sourceMappingURL";
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Collects mappings from module byte offsets to source positions
+pub struct SourceMapBuilder<'a> {
+    source_name: &'a str,
+    source: &'a str,
+    /// Byte offset of the start of each source line
+    line_starts: Vec<usize>,
+    /// (module offset, source line, source column), all zero-based
+    mappings: Vec<(u32, u32, u32)>,
+}
+
+impl<'a> SourceMapBuilder<'a> {
+    pub fn new(source_name: &'a str, source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            source_name,
+            source,
+            line_starts,
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Map the instruction at `module_offset` to the source byte `source_offset`
+    pub fn add(&mut self, module_offset: u32, source_offset: usize) {
+        let line = self.line_starts.partition_point(|&start| start <= source_offset) - 1;
+        let line_start = self.line_starts[line];
+        let column = self
+            .source
+            .get(line_start..source_offset)
+            .map_or(0, |prefix| prefix.chars().count());
+        self.mappings.push((module_offset, line as u32, column as u32));
+    }
+
+    /// Render the map as JSON
+    pub fn finish(mut self) -> String {
+        self.mappings.sort_by_key(|&(offset, _, _)| offset);
+        self.mappings.dedup_by_key(|&mut (offset, _, _)| offset);
+
+        // Every field is delta-encoded against the previous segment
+        let mut encoded = String::new();
+        let (mut prev_offset, mut prev_line, mut prev_column) = (0i64, 0i64, 0i64);
+        for (i, &(offset, line, column)) in self.mappings.iter().enumerate() {
+            if i > 0 {
+                encoded.push(',');
+            }
+            encode_vlq(offset as i64 - prev_offset, &mut encoded);
+            encode_vlq(0, &mut encoded);
+            encode_vlq(line as i64 - prev_line, &mut encoded);
+            encode_vlq(column as i64 - prev_column, &mut encoded);
+            (prev_offset, prev_line, prev_column) = (offset as i64, line as i64, column as i64);
+        }
+
+        format!(
+            "{{\"version\":3,\"sources\":[{}],\"sourcesContent\":[{}],\"names\":[],\"mappings\":\"{}\"}}",
+            json_string(self.source_name),
+            json_string(self.source),
+            encoded
+        )
+    }
+}
+
+/// Append a base64 VLQ encoded value
+fn encode_vlq(value: i64, out: &mut String) {
+    let mut vlq = if value < 0 {
+        ((-value as u64) << 1) | 1
+    } else {
+        (value as u64) << 1
+    };
+    loop {
+        let mut digit = (vlq & 0b11111) as usize;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Decode a `mappings` string back to (module offset, line, column) triples
+#[cfg(test)]
+pub(crate) fn decode_mappings(mappings: &str) -> Vec<(u32, u32, u32)> {
+    let mut result = Vec::new();
+    let mut state = [0i64; 4];
+    for segment in mappings.split(',').filter(|s| !s.is_empty()) {
+        let mut fields = Vec::new();
+        let (mut value, mut shift) = (0u64, 0);
+        for c in segment.bytes() {
+            let digit = BASE64.iter().position(|&b| b == c).unwrap() as u64;
+            value |= (digit & 0b11111) << shift;
+            shift += 5;
+            if digit & 0b100000 == 0 {
+                let magnitude = (value >> 1) as i64;
+                fields.push(if value & 1 == 1 { -magnitude } else { magnitude });
+                (value, shift) = (0, 0);
+            }
+        }
+        for (field, delta) in state.iter_mut().zip(fields) {
+            *field += delta;
+        }
+        result.push((state[0] as u32, state[2] as u32, state[3] as u32));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vlq() {
+        let mut out = String::new();
+        for value in [0, 1, -1, 15, 16, 123] {
+            encode_vlq(value, &mut out);
+            out.push(' ');
+        }
+        assert_eq!(out, "A C D e gB 2H ");
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let mut map = SourceMapBuilder::new("a.woke", "x\ny\n  z");
+        map.add(100, 0);
+        map.add(7, 6);
+        map.add(50, 2);
+        let json = map.finish();
+        let mappings = json.split("\"mappings\":\"").nth(1).unwrap().trim_end_matches("\"}");
+        assert_eq!(decode_mappings(mappings), [(7, 2, 2), (50, 1, 0), (100, 0, 0)]);
+    }
+
+    #[test]
+    fn test_source_map() {
+        let source = "to main() {\n    print(\"a\\tb\");\n}\n";
+        let mut map = SourceMapBuilder::new("main.woke", source);
+        map.add(40, 16);
+        map.add(30, 0);
+        assert_eq!(
+            map.finish(),
+            "{\"version\":3,\"sources\":[\"main.woke\"],\
+             \"sourcesContent\":[\"to main() {\\n    print(\\\"a\\\\tb\\\");\\n}\\n\"],\
+             \"names\":[],\"mappings\":\"8BAAA,UACI\"}"
+        );
+    }
+}
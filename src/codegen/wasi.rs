@@ -0,0 +1,200 @@
+//! WASI support for generated modules
+//!
+//! When targeting WASI, `print` is implemented inside the module on top of
+//! the `fd_write` import instead of calling `woke.*` host functions, and a
+//! `_start` export runs `main`. Numbers are formatted to decimal in the
+//! scratch area reserved at the bottom of linear memory (see [`runtime`]).
+
+use super::runtime::{self, mem_i32};
+use wasm_encoder::{BlockType, Function, MemArg, ValType};
+
+/// Module name for WASI preview 1 imports
+pub const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+/// Standard output file descriptor
+const STDOUT: i32 = 1;
+
+/// Maximum fractional digits printed for floats
+const FLOAT_DIGITS: i32 = 6;
+
+fn mem_u8() -> MemArg {
+    MemArg {
+        offset: 0,
+        align: 0,
+        memory_index: 0,
+    }
+}
+
+/// Initial contents of the scratch characters at [`runtime::NEWLINE`]
+pub fn scratch_chars() -> &'static [u8] {
+    b"\n-."
+}
+
+/// `__write(ptr: i32, len: i32)`: write bytes to stdout via `fd_write`
+pub fn write(fd_write_idx: u32) -> Function {
+    const PTR: u32 = 0;
+    const LEN: u32 = 1;
+
+    let mut f = Function::new([]);
+    let mut ins = f.instructions();
+
+    // Single iovec { ptr, len }
+    ins.i32_const(runtime::IOVEC as i32)
+        .local_get(PTR)
+        .i32_store(mem_i32(0));
+    ins.i32_const(runtime::IOVEC as i32)
+        .local_get(LEN)
+        .i32_store(mem_i32(4));
+    ins.i32_const(STDOUT)
+        .i32_const(runtime::IOVEC as i32)
+        .i32_const(1)
+        .i32_const(runtime::NWRITTEN as i32)
+        .call(fd_write_idx)
+        .drop();
+    ins.end();
+    f
+}
+
+/// `__print(ptr: i32, len: i32)`: write bytes followed by a newline
+pub fn print(write_idx: u32) -> Function {
+    let mut f = Function::new([]);
+    let mut ins = f.instructions();
+    ins.local_get(0).local_get(1).call(write_idx);
+    ins.i32_const(runtime::NEWLINE as i32).i32_const(1).call(write_idx);
+    ins.end();
+    f
+}
+
+/// `__write_u64(n: i64, width: i32)`: write an unsigned integer in decimal,
+/// zero-padded to at least `width` digits
+pub fn write_u64(write_idx: u32) -> Function {
+    const N: u32 = 0;
+    const WIDTH: u32 = 1;
+    const POS: u32 = 2;
+    const COUNT: u32 = 3;
+
+    let mut f = Function::new([(2, ValType::I32)]);
+    let mut ins = f.instructions();
+    let end = (runtime::NUMBUF + runtime::NUMBUF_LEN) as i32;
+
+    ins.i32_const(end).local_set(POS);
+    ins.loop_(BlockType::Empty);
+    // *--pos = '0' + n % 10
+    ins.local_get(POS).i32_const(1).i32_sub().local_tee(POS);
+    ins.local_get(N)
+        .i64_const(10)
+        .i64_rem_u()
+        .i32_wrap_i64()
+        .i32_const(b'0' as i32)
+        .i32_add()
+        .i32_store8(mem_u8());
+    ins.local_get(N).i64_const(10).i64_div_u().local_set(N);
+    ins.local_get(COUNT).i32_const(1).i32_add().local_set(COUNT);
+    // Continue while digits remain or padding is needed
+    ins.local_get(N)
+        .i64_const(0)
+        .i64_ne()
+        .local_get(COUNT)
+        .local_get(WIDTH)
+        .i32_lt_s()
+        .i32_or()
+        .br_if(0);
+    ins.end();
+
+    ins.local_get(POS)
+        .i32_const(end)
+        .local_get(POS)
+        .i32_sub()
+        .call(write_idx);
+    ins.end();
+    f
+}
+
+/// `__print_int(n: i64)`
+pub fn print_int(write_idx: u32, write_u64_idx: u32) -> Function {
+    const N: u32 = 0;
+
+    let mut f = Function::new([]);
+    let mut ins = f.instructions();
+
+    ins.local_get(N).i64_const(0).i64_lt_s();
+    ins.if_(BlockType::Empty);
+    ins.i32_const(runtime::MINUS as i32).i32_const(1).call(write_idx);
+    // Negating i64::MIN wraps to itself, which is correct when read unsigned
+    ins.i64_const(0).local_get(N).i64_sub().local_set(N);
+    ins.end();
+
+    ins.local_get(N).i32_const(1).call(write_u64_idx);
+    ins.i32_const(runtime::NEWLINE as i32).i32_const(1).call(write_idx);
+    ins.end();
+    f
+}
+
+/// `__print_float(x: f64)`: integer part plus up to six fractional digits
+pub fn print_float(write_idx: u32, write_u64_idx: u32) -> Function {
+    const X: u32 = 0;
+    const INT: u32 = 1;
+    const FRAC: u32 = 2;
+    const DIGITS: u32 = 3;
+
+    let scale = 10i64.pow(FLOAT_DIGITS as u32);
+    let mut f = Function::new([(2, ValType::I64), (1, ValType::I32)]);
+    let mut ins = f.instructions();
+
+    ins.local_get(X).f64_const(0.0.into()).f64_lt();
+    ins.if_(BlockType::Empty);
+    ins.i32_const(runtime::MINUS as i32).i32_const(1).call(write_idx);
+    ins.local_get(X).f64_neg().local_set(X);
+    ins.end();
+
+    // Split into integer and rounded fractional parts
+    ins.local_get(X).i64_trunc_sat_f64_u().local_set(INT);
+    ins.local_get(X)
+        .local_get(X)
+        .f64_trunc()
+        .f64_sub()
+        .f64_const((scale as f64).into())
+        .f64_mul()
+        .f64_nearest()
+        .i64_trunc_sat_f64_u()
+        .local_set(FRAC);
+
+    // Rounding may carry into the integer part
+    ins.local_get(FRAC).i64_const(scale).i64_ge_u();
+    ins.if_(BlockType::Empty);
+    ins.local_get(INT).i64_const(1).i64_add().local_set(INT);
+    ins.local_get(FRAC).i64_const(scale).i64_sub().local_set(FRAC);
+    ins.end();
+
+    ins.local_get(INT).i32_const(1).call(write_u64_idx);
+
+    ins.local_get(FRAC).i64_const(0).i64_ne();
+    ins.if_(BlockType::Empty);
+    // Drop trailing zeros
+    ins.i32_const(FLOAT_DIGITS).local_set(DIGITS);
+    ins.block(BlockType::Empty).loop_(BlockType::Empty);
+    ins.local_get(FRAC).i64_const(10).i64_rem_u().i64_const(0).i64_ne().br_if(1);
+    ins.local_get(FRAC).i64_const(10).i64_div_u().local_set(FRAC);
+    ins.local_get(DIGITS).i32_const(1).i32_sub().local_set(DIGITS);
+    ins.br(0);
+    ins.end().end();
+    ins.i32_const(runtime::DOT as i32).i32_const(1).call(write_idx);
+    ins.local_get(FRAC).local_get(DIGITS).call(write_u64_idx);
+    ins.end();
+
+    ins.i32_const(runtime::NEWLINE as i32).i32_const(1).call(write_idx);
+    ins.end();
+    f
+}
+
+/// `_start()`: run the init function (if any), then `main`, discarding
+/// their results
+pub fn start(init_idx: Option<u32>, main_idx: u32) -> Function {
+    let mut f = Function::new([]);
+    let mut ins = f.instructions();
+    if let Some(init_idx) = init_idx {
+        ins.call(init_idx).drop();
+    }
+    ins.call(main_idx).drop().end();
+    f
+}
@@ -1,11 +1,45 @@
+use super::sourcemap::{self, SourceMapBuilder};
+use super::{runtime, wasi};
 use crate::ast::*;
-use std::collections::HashMap;
+use crate::typechecker::{CheckedProgram, InferredType};
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
+use std::borrow::Cow;
 use wasm_encoder::{
-    CodeSection, ExportKind, ExportSection, Function, FunctionSection, Instruction, Module,
-    TypeSection, ValType,
+    BlockType, CodeSection, ConstExpr, CustomSection, DataSection, ElementSection, Elements,
+    Encode, EntityType, ExportKind, ExportSection, Function, FunctionSection, GlobalSection,
+    GlobalType, ImportSection, IndirectNameMap, Instruction, MemArg, MemorySection, MemoryType,
+    Module, NameMap, NameSection, RefType, TableSection, TableType, TypeSection, ValType,
 };
 
+/// Module name for host functions imported by generated code
+pub const HOST_MODULE: &str = "woke";
+
+/// Exported function running top-level consent blocks
+pub const INIT_FUNCTION: &str = "__woke_init";
+
+/// Environment a generated module is built to run in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WasmTarget {
+    /// Embedded in a host that provides the `woke.*` imports
+    #[default]
+    Host,
+    /// Standalone WASI command with a `_start` entry point
+    Wasi,
+}
+
+impl std::str::FromStr for WasmTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "host" => Ok(WasmTarget::Host),
+            "wasi" => Ok(WasmTarget::Wasi),
+            other => Err(format!("unknown WASM target '{}' (expected host or wasi)", other)),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum CompileError {
     #[error("Unsupported feature: {0}")]
@@ -23,183 +57,812 @@ pub enum CompileError {
 
 type Result<T> = std::result::Result<T, CompileError>;
 
+/// WASM representation of a WokeLang value
+///
+/// Ints and Bools are `i64` (Bools as 0/1); Floats are `f64`; Strings are
+/// `i32` pointers into linear memory (see [`runtime`]). Function values are
+/// `i32` pointers to closure records; the payload indexes the compiler's
+/// closure signatures.
+///
+/// A closure record holds the closure's function table slot as an `i32`,
+/// followed by its captured variables in 8-byte slots starting at offset 8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WasmType {
+    Int,
+    Float,
+    Str,
+    Func(u32),
+}
+
+impl WasmType {
+    fn val_type(self) -> ValType {
+        match self {
+            WasmType::Int => ValType::I64,
+            WasmType::Float => ValType::F64,
+            WasmType::Str | WasmType::Func(_) => ValType::I32,
+        }
+    }
+
+    fn of_literal(lit: &Literal) -> Self {
+        match lit {
+            Literal::Float(_) => WasmType::Float,
+            Literal::String(_) => WasmType::Str,
+            _ => WasmType::Int,
+        }
+    }
+
+    /// Common type two operands are promoted to
+    fn unify(self, other: Self) -> Result<Self> {
+        match (self, other) {
+            (a, b) if a == b => Ok(a),
+            (WasmType::Str, _) | (_, WasmType::Str) => Err(CompileError::TypeError(
+                "cannot mix String with numbers; use toString first".into(),
+            )),
+            (WasmType::Func(_), _) | (_, WasmType::Func(_)) => Err(CompileError::TypeError(
+                "function values cannot be combined with other values".into(),
+            )),
+            (WasmType::Float, _) | (_, WasmType::Float) => Ok(WasmType::Float),
+            _ => Ok(WasmType::Int),
+        }
+    }
+}
+
+/// Host functions a module may import from [`HOST_MODULE`]
+///
+/// All are only imported when the program calls `print`.
+const PRINT_IMPORTS: [(&str, &[ValType]); 3] = [
+    // print(ptr, len): UTF-8 bytes in linear memory
+    ("print", &[ValType::I32, ValType::I32]),
+    ("print_int", &[ValType::I64]),
+    ("print_float", &[ValType::F64]),
+];
+
+/// Indices of the WASI helper functions in the module
+#[derive(Debug, Clone, Copy)]
+struct WasiFunctions {
+    fd_write: u32,
+    write: u32,
+    print: u32,
+    write_u64: u32,
+    print_int: u32,
+    print_float: u32,
+}
+
+/// Indices of the runtime helper functions in the module
+#[derive(Debug, Clone, Copy, Default)]
+struct RuntimeFunctions {
+    alloc: u32,
+    concat: u32,
+    str_eq: u32,
+}
+
+/// Function body under construction
+///
+/// Instructions are buffered so locals can be declared as they are
+/// discovered and the local declarations written once the body is done.
+struct FunctionBuilder {
+    param_count: u32,
+    locals: Vec<ValType>,
+    body: Vec<Instruction<'static>>,
+    /// Names of parameters and variables, for the name section
+    local_names: Vec<(u32, String)>,
+    /// Source offsets of statements, keyed by the index of their first instruction
+    marks: Vec<(usize, usize)>,
+}
+
+/// Function generated for a lambda or a function used as a value
+struct LiftedFunction {
+    name: String,
+    type_idx: u32,
+    compiled: CompiledFunction,
+}
+
+/// A finished user function with its debug information
+struct CompiledFunction {
+    body: Function,
+    local_names: Vec<(u32, String)>,
+    /// Byte offset within the body to source offset
+    source_offsets: Vec<(usize, usize)>,
+}
+
+impl FunctionBuilder {
+    fn new(param_count: u32) -> Self {
+        Self {
+            param_count,
+            locals: Vec::new(),
+            body: Vec::new(),
+            local_names: Vec::new(),
+            marks: Vec::new(),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction<'static>) -> &mut Self {
+        self.body.push(instruction.clone());
+        self
+    }
+
+    /// Declare a new local and return its index
+    fn add_local(&mut self, ty: ValType) -> u32 {
+        self.locals.push(ty);
+        self.param_count + self.locals.len() as u32 - 1
+    }
+
+    fn name_local(&mut self, idx: u32, name: &str) {
+        self.local_names.push((idx, name.to_string()));
+    }
+
+    /// Attribute the next instruction to `source_offset`
+    fn mark(&mut self, source_offset: usize) {
+        self.marks.push((self.body.len(), source_offset));
+    }
+
+    fn finish(self) -> CompiledFunction {
+        let mut func = Function::new_with_locals_types(self.locals);
+        let mut marks = self.marks.into_iter().peekable();
+        let mut source_offsets: Vec<(usize, usize)> = Vec::new();
+        for (i, instruction) in self.body.iter().enumerate() {
+            // Statements that emitted nothing share an index with the next
+            // one; the last mark is the statement the instruction belongs to
+            while let Some((_, source_offset)) = marks.next_if(|&(at, _)| at == i) {
+                let offset = func.byte_len();
+                match source_offsets.last_mut() {
+                    Some(last) if last.0 == offset => last.1 = source_offset,
+                    _ => source_offsets.push((offset, source_offset)),
+                }
+            }
+            func.instruction(instruction);
+        }
+        CompiledFunction {
+            body: func,
+            local_names: self.local_names,
+            source_offsets,
+        }
+    }
+}
+
 /// Compiles WokeLang to WebAssembly
 pub struct WasmCompiler {
+    /// Environment the module is built for
+    target: WasmTarget,
     /// Function name to index mapping
     functions: HashMap<String, u32>,
-    /// Function signatures (param count, return count)
-    signatures: HashMap<String, (Vec<ValType>, Vec<ValType>)>,
+    /// Function signatures (param types, return type)
+    signatures: HashMap<String, (Vec<WasmType>, WasmType)>,
+    /// Types the type checker found for the functions, for the parameters
+    /// and results they do not declare
+    inferred: HashMap<String, (Vec<InferredType>, InferredType)>,
     /// Local variable mappings per function
-    locals: HashMap<String, u32>,
-    /// Current local index
-    local_index: u32,
+    locals: HashMap<String, (u32, WasmType)>,
+    /// Deduplicated function types for the type section
+    types: Vec<(Vec<ValType>, Vec<ValType>)>,
+    /// Imported host function name to index mapping
+    imports: HashMap<&'static str, u32>,
+    /// Runtime helper function indices
+    runtime: RuntimeFunctions,
+    /// String literal to memory address mapping
+    strings: HashMap<String, u32>,
+    /// Contents of the data segment starting at [`runtime::DATA_START`]
+    data: Vec<u8>,
+    /// Signatures of function values, indexed by [`WasmType::Func`]
+    closure_types: Vec<(Vec<WasmType>, WasmType)>,
+    /// Functions callable through the table; slot `i + 1` holds entry `i`
+    /// so that a zeroed closure record traps when called
+    table: Vec<u32>,
+    /// Lifted functions in index order, filled in once compiled
+    lifted: Vec<Option<LiftedFunction>>,
+    /// Function index of the first lifted function
+    lifted_base: u32,
+    /// Static closure records for named functions used as values
+    function_refs: HashMap<String, u32>,
+    /// Source to map generated code back to, if source maps are enabled
+    debug_source: Option<DebugSource>,
+    /// Source map produced by the last compilation
+    source_map: Option<String>,
+}
+
+/// Source file a module's source map refers to
+struct DebugSource {
+    name: String,
+    text: String,
+    map_url: String,
 }
 
 impl WasmCompiler {
     pub fn new() -> Self {
         Self {
+            target: WasmTarget::default(),
             functions: HashMap::new(),
             signatures: HashMap::new(),
+            inferred: HashMap::new(),
             locals: HashMap::new(),
-            local_index: 0,
+            types: Vec::new(),
+            imports: HashMap::new(),
+            runtime: RuntimeFunctions::default(),
+            strings: HashMap::new(),
+            data: Vec::new(),
+            closure_types: Vec::new(),
+            table: Vec::new(),
+            lifted: Vec::new(),
+            lifted_base: 0,
+            function_refs: HashMap::new(),
+            debug_source: None,
+            source_map: None,
+        }
+    }
+
+    /// Build modules for the given target
+    pub fn with_target(mut self, target: WasmTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Also produce a source map for `source`, which is named `source_name`
+    /// in the map; the module references the map as `map_url`
+    pub fn with_source_map(mut self, source_name: &str, source: &str, map_url: &str) -> Self {
+        self.debug_source = Some(DebugSource {
+            name: source_name.to_string(),
+            text: source.to_string(),
+            map_url: map_url.to_string(),
+        });
+        self
+    }
+
+    /// Source map JSON from the last call to [`compile`](Self::compile), if
+    /// enabled with [`with_source_map`](Self::with_source_map)
+    pub fn source_map(&self) -> Option<&str> {
+        self.source_map.as_deref()
+    }
+
+    /// Index of a function type, adding it to the type section if new
+    fn type_index(&mut self, params: Vec<ValType>, results: Vec<ValType>) -> u32 {
+        let ty = (params, results);
+        match self.types.iter().position(|t| *t == ty) {
+            Some(idx) => idx as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
         }
     }
 
+    /// Map a declared WokeLang type to its WASM representation
+    fn wasm_type(&mut self, ty: Option<&Type>) -> WasmType {
+        match ty {
+            Some(Type::Basic(name)) if name == "Float" => WasmType::Float,
+            Some(Type::Basic(name)) if name == "String" => WasmType::Str,
+            Some(Type::Function(params, ret)) => {
+                let params = params.iter().map(|p| self.wasm_type(Some(p))).collect();
+                let ret = self.wasm_type(Some(ret));
+                self.closure_type(params, ret)
+            }
+            _ => WasmType::Int,
+        }
+    }
+
+    /// WASM representation of a type the type checker found
+    fn inferred_wasm_type(&mut self, ty: &InferredType) -> WasmType {
+        match ty {
+            InferredType::Float => WasmType::Float,
+            InferredType::String => WasmType::Str,
+            InferredType::Function { params, ret } => {
+                let params = params.iter().map(|p| self.inferred_wasm_type(p)).collect();
+                let ret = self.inferred_wasm_type(ret);
+                self.closure_type(params, ret)
+            }
+            _ => WasmType::Int,
+        }
+    }
+
+    /// WASM representation of a parameter or result, as declared, or else
+    /// as the type checker found it
+    fn declared_or_inferred(&mut self, declared: Option<&Type>, inferred: Option<&InferredType>) -> WasmType {
+        match (declared, inferred) {
+            (None, Some(ty)) => self.inferred_wasm_type(ty),
+            (declared, _) => self.wasm_type(declared),
+        }
+    }
+
+    /// Function value type with the given signature
+    fn closure_type(&mut self, params: Vec<WasmType>, ret: WasmType) -> WasmType {
+        let sig = (params, ret);
+        match self.closure_types.iter().position(|t| *t == sig) {
+            Some(idx) => WasmType::Func(idx as u32),
+            None => {
+                self.closure_types.push(sig);
+                WasmType::Func(self.closure_types.len() as u32 - 1)
+            }
+        }
+    }
+
+    /// WASM type of a lifted function taking a closure environment
+    fn lifted_type_index(&mut self, params: &[WasmType], ret: WasmType) -> u32 {
+        let mut wasm_params = vec![ValType::I32];
+        wasm_params.extend(params.iter().map(|p| p.val_type()));
+        self.type_index(wasm_params, vec![ret.val_type()])
+    }
+
+    /// Reserve a function index for a lifted function
+    fn reserve_lifted(&mut self) -> u32 {
+        self.lifted.push(None);
+        self.lifted_base + self.lifted.len() as u32 - 1
+    }
+
+    /// Store a compiled lifted function and give it a table slot
+    fn finish_lifted(&mut self, func_idx: u32, lifted: LiftedFunction) -> u32 {
+        self.lifted[(func_idx - self.lifted_base) as usize] = Some(lifted);
+        self.table.push(func_idx);
+        self.table.len() as u32
+    }
+
+    /// Memory address of a closure record without captures, in the data segment
+    fn static_closure(&mut self, slot: u32) -> u32 {
+        let addr = runtime::DATA_START + self.data.len() as u32;
+        self.data.extend(slot.to_le_bytes());
+        addr
+    }
+
+    /// Memory address of a string literal, adding it to the data segment if new
+    fn intern_string(&mut self, s: &str) -> u32 {
+        if let Some(&addr) = self.strings.get(s) {
+            return addr;
+        }
+        let addr = runtime::DATA_START + self.data.len() as u32;
+        self.data.extend(runtime::encode_string(s));
+        // Keep every block 4-byte aligned for the length prefix
+        self.data.resize(self.data.len().next_multiple_of(4), 0);
+        self.strings.insert(s.to_string(), addr);
+        addr
+    }
+
+    /// Compile a program the type checker passed, giving parameters and
+    /// results without a declared type the types it found for them
+    pub fn compile_checked(&mut self, checked: &CheckedProgram) -> Result<Vec<u8>> {
+        self.inferred = checked
+            .functions()
+            .iter()
+            .map(|f| (f.name().to_string(), (f.params.clone(), f.ret.clone())))
+            .collect();
+        let compiled = self.compile(checked.program());
+        self.inferred.clear();
+        compiled
+    }
+
     /// Compile a WokeLang program to WASM binary
     pub fn compile(&mut self, program: &Program) -> Result<Vec<u8>> {
         let mut module = Module::new();
+        self.table.clear();
+        self.lifted.clear();
+        self.function_refs.clear();
 
         // Collect function definitions first
         let mut func_defs: Vec<&FunctionDef> = Vec::new();
+        let mut top_level_consent = Vec::new();
         for item in &program.items {
-            if let TopLevelItem::Function(f) = item {
-                func_defs.push(f);
+            match item {
+                TopLevelItem::Function(f) => func_defs.push(f),
+                TopLevelItem::ConsentBlock(c) => {
+                    top_level_consent.push(Statement::ConsentBlock(c.clone()))
+                }
+                _ => {}
             }
         }
 
-        // Build type section (function signatures)
-        let mut types = TypeSection::new();
+        // Top-level consent blocks run from an init function, which the
+        // WASI entry point calls before main
+        let init = FunctionDef {
+            emote: None,
+            name: INIT_FUNCTION.to_string(),
+            type_params: Vec::new(),
+            params: Vec::new(),
+            return_type: None,
+            hello: None,
+            body: top_level_consent,
+            goodbye: None,
+            span: 0..0,
+            module: None,
+        };
+        if !init.body.is_empty() {
+            func_defs.push(&init);
+        }
+
+        // Imported host functions come first in the function index space
+        let usage = scan_program(program);
+        let uses_print = usage.calls.contains("print");
+        let mut imports = ImportSection::new();
+        let mut import_names = Vec::new();
+        let mut import_count = 0;
+        if uses_print {
+            match self.target {
+                WasmTarget::Host => {
+                    for (name, params) in PRINT_IMPORTS {
+                        let ty = self.type_index(params.to_vec(), vec![]);
+                        imports.import(HOST_MODULE, name, EntityType::Function(ty));
+                        import_names.push(name);
+                        self.imports.insert(name, import_count);
+                        import_count += 1;
+                    }
+                }
+                WasmTarget::Wasi => {
+                    let ty = self.type_index(vec![ValType::I32; 4], vec![ValType::I32]);
+                    imports.import(wasi::WASI_MODULE, "fd_write", EntityType::Function(ty));
+                    import_names.push("fd_write");
+                    import_count += 1;
+                }
+            }
+        }
+        if usage.consent {
+            // Consent is always delegated to the embedder, on every target
+            let ty = self.type_index(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+            imports.import(HOST_MODULE, "request_consent", EntityType::Function(ty));
+            import_names.push("request_consent");
+            self.imports.insert("request_consent", import_count);
+            import_count += 1;
+        }
+
+        // Function signatures. Every function returns exactly one value;
+        // types neither declared nor found by the type checker are Int.
+        let mut defined_types = Vec::new();
         for (idx, func) in func_defs.iter().enumerate() {
-            let params: Vec<ValType> = func.params.iter().map(|_| ValType::I64).collect();
-            let results: Vec<ValType> = if func.return_type.is_some() {
-                vec![ValType::I64]
-            } else {
-                vec![]
+            let (inferred_params, inferred_ret) = match self.inferred.get(&func.name) {
+                Some((params, ret)) => (params.clone(), Some(ret.clone())),
+                None => (Vec::new(), None),
             };
+            let params: Vec<WasmType> = func
+                .params
+                .iter()
+                .enumerate()
+                .map(|(i, p)| self.declared_or_inferred(p.ty.as_ref(), inferred_params.get(i)))
+                .collect();
+            let ret = self.declared_or_inferred(func.return_type.as_ref(), inferred_ret.as_ref());
+
+            defined_types.push(self.type_index(
+                params.iter().map(|p| p.val_type()).collect(),
+                vec![ret.val_type()],
+            ));
+            self.functions.insert(func.name.clone(), import_count + idx as u32);
+            self.signatures.insert(func.name.clone(), (params, ret));
+        }
 
-            types.ty().function(params.clone(), results.clone());
-            self.functions.insert(func.name.clone(), idx as u32);
-            self.signatures
-                .insert(func.name.clone(), (params, results));
+        // Runtime helpers follow the user functions
+        let helper_base = import_count + func_defs.len() as u32;
+        self.runtime = RuntimeFunctions {
+            alloc: helper_base,
+            concat: helper_base + 1,
+            str_eq: helper_base + 2,
+        };
+        defined_types.push(self.type_index(vec![ValType::I32], vec![ValType::I32]));
+        for _ in 0..2 {
+            defined_types.push(
+                self.type_index(vec![ValType::I32, ValType::I32], vec![ValType::I32]),
+            );
+        }
+
+        // WASI builds implement print in the module on top of fd_write
+        let mut next_index = helper_base + 3;
+        let wasi_functions = if self.target == WasmTarget::Wasi && uses_print {
+            let funcs = WasiFunctions {
+                fd_write: 0,
+                write: next_index,
+                print: next_index + 1,
+                write_u64: next_index + 2,
+                print_int: next_index + 3,
+                print_float: next_index + 4,
+            };
+            next_index += 5;
+            for params in [
+                vec![ValType::I32, ValType::I32],
+                vec![ValType::I32, ValType::I32],
+                vec![ValType::I64, ValType::I32],
+                vec![ValType::I64],
+                vec![ValType::F64],
+            ] {
+                defined_types.push(self.type_index(params, vec![]));
+            }
+            self.imports.insert("print", funcs.print);
+            self.imports.insert("print_int", funcs.print_int);
+            self.imports.insert("print_float", funcs.print_float);
+            Some(funcs)
+        } else {
+            None
+        };
+
+        // WASI entry point calling main
+        let start = if self.target == WasmTarget::Wasi {
+            let main = func_defs
+                .iter()
+                .find(|f| f.name == "main")
+                .ok_or_else(|| CompileError::UndefinedFunction("main".into()))?;
+            if !main.params.is_empty() {
+                return Err(CompileError::TypeError(
+                    "main must not take parameters when targeting WASI".into(),
+                ));
+            }
+            defined_types.push(self.type_index(vec![], vec![]));
+            let init_idx = self.functions.get(INIT_FUNCTION).copied();
+            next_index += 1;
+            Some((next_index - 1, init_idx, self.functions["main"]))
+        } else {
+            None
+        };
+
+        // Lambdas and function references are lifted into functions
+        // appended as they are discovered
+        self.lifted_base = next_index;
+
+        // Compile bodies before emitting sections so that every string
+        // literal and function type is known
+        let mut compiled = Vec::new();
+        for func in &func_defs {
+            compiled.push(self.compile_function(func)?);
+        }
+        let mut bodies = Vec::new();
+        bodies.push(runtime::alloc());
+        bodies.push(runtime::concat(self.runtime.alloc));
+        bodies.push(runtime::str_eq());
+        if let Some(w) = wasi_functions {
+            bodies.push(wasi::write(w.fd_write));
+            bodies.push(wasi::print(w.write));
+            bodies.push(wasi::write_u64(w.write));
+            bodies.push(wasi::print_int(w.write, w.write_u64));
+            bodies.push(wasi::print_float(w.write, w.write_u64));
+        }
+        if let Some((_, init_idx, main_idx)) = start {
+            bodies.push(wasi::start(init_idx, main_idx));
+        }
+        let lifted: Vec<LiftedFunction> = std::mem::take(&mut self.lifted)
+            .into_iter()
+            .map(|f| f.expect("lifted function was not compiled"))
+            .collect();
+        defined_types.extend(lifted.iter().map(|f| f.type_idx));
+
+        // Build type section (function signatures)
+        let mut types = TypeSection::new();
+        for (params, results) in &self.types {
+            types.ty().function(params.iter().copied(), results.iter().copied());
         }
         module.section(&types);
 
+        if import_count > 0 {
+            module.section(&imports);
+        }
+
         // Build function section (type indices)
         let mut functions = FunctionSection::new();
-        for idx in 0..func_defs.len() {
-            functions.function(idx as u32);
+        for ty in defined_types {
+            functions.function(ty);
         }
         module.section(&functions);
 
+        // Function table for indirect calls; slot 0 stays empty
+        if !self.table.is_empty() {
+            let mut tables = TableSection::new();
+            let size = self.table.len() as u64 + 1;
+            tables.table(TableType {
+                element_type: RefType::FUNCREF,
+                minimum: size,
+                maximum: Some(size),
+                table64: false,
+                shared: false,
+            });
+            module.section(&tables);
+        }
+
+        // Linear memory large enough for the string literals
+        let heap_start = runtime::align(runtime::DATA_START + self.data.len() as u32);
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType {
+            minimum: runtime::pages_for(heap_start),
+            maximum: None,
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        module.section(&memories);
+
+        // Heap pointer for the bump allocator
+        let mut globals = GlobalSection::new();
+        globals.global(
+            GlobalType {
+                val_type: ValType::I32,
+                mutable: true,
+                shared: false,
+            },
+            &ConstExpr::i32_const(heap_start as i32),
+        );
+        module.section(&globals);
+
         // Build export section
         let mut exports = ExportSection::new();
-        for (name, idx) in &self.functions {
-            exports.export(name, ExportKind::Func, *idx);
+        for func in &func_defs {
+            exports.export(&func.name, ExportKind::Func, self.functions[&func.name]);
+        }
+        if let Some((start_idx, _, _)) = start {
+            exports.export("_start", ExportKind::Func, start_idx);
         }
+        exports.export("memory", ExportKind::Memory, 0);
         module.section(&exports);
 
-        // Build code section
+        if !self.table.is_empty() {
+            let mut elements = ElementSection::new();
+            elements.active(
+                None,
+                &ConstExpr::i32_const(1),
+                Elements::Functions(Cow::Borrowed(&self.table)),
+            );
+            module.section(&elements);
+        }
+
+        // Build code section, noting where each body starts
         let mut codes = CodeSection::new();
-        for func in &func_defs {
-            let wasm_func = self.compile_function(func)?;
-            codes.function(&wasm_func);
+        let mut body_starts = Vec::new();
+        let lifted_bodies = lifted.iter().map(|f| &f.compiled.body);
+        for body in compiled.iter().map(|c| &c.body).chain(&bodies).chain(lifted_bodies) {
+            body_starts.push(codes.byte_len() + leb128_len(body.byte_len()));
+            codes.function(body);
         }
         module.section(&codes);
+        // The function entries are the tail of the module so far
+        let entries_start = module.as_slice().len() - codes.byte_len();
+
+        // String literals and WASI scratch characters
+        let mut data = DataSection::new();
+        if !self.data.is_empty() {
+            data.active(
+                0,
+                &ConstExpr::i32_const(runtime::DATA_START as i32),
+                self.data.iter().copied(),
+            );
+        }
+        if wasi_functions.is_some() {
+            data.active(
+                0,
+                &ConstExpr::i32_const(runtime::NEWLINE as i32),
+                wasi::scratch_chars().iter().copied(),
+            );
+        }
+        if !data.is_empty() {
+            module.section(&data);
+        }
+
+        // Debug names for functions and their parameters and variables
+        let mut function_names = NameMap::new();
+        let helper_names = ["__alloc", "__concat", "__str_eq"];
+        let wasi_names = match wasi_functions {
+            Some(_) => &["__write", "__print", "__write_u64", "__print_int", "__print_float"][..],
+            None => &[],
+        };
+        let start_name = start.map(|_| "_start");
+        let names = import_names
+            .iter()
+            .copied()
+            .chain(func_defs.iter().map(|f| f.name.as_str()))
+            .chain(helper_names)
+            .chain(wasi_names.iter().copied())
+            .chain(start_name)
+            .chain(lifted.iter().map(|f| f.name.as_str()));
+        for (idx, name) in names.enumerate() {
+            function_names.append(idx as u32, name);
+        }
+        // User and lifted functions, by index
+        let debug_functions: Vec<(u32, &CompiledFunction)> = (import_count..)
+            .zip(&compiled)
+            .chain((self.lifted_base..).zip(lifted.iter().map(|f| &f.compiled)))
+            .collect();
+        let mut local_names = IndirectNameMap::new();
+        for &(idx, func) in &debug_functions {
+            let mut locals = NameMap::new();
+            for (local, name) in &func.local_names {
+                locals.append(*local, name);
+            }
+            local_names.append(idx, &locals);
+        }
+        let mut name_section = NameSection::new();
+        name_section.functions(&function_names);
+        name_section.locals(&local_names);
+        module.section(&name_section);
+
+        // Map statements back to the source
+        self.source_map = None;
+        if let Some(debug) = &self.debug_source {
+            let mut map = SourceMapBuilder::new(&debug.name, &debug.text);
+            for &(idx, func) in &debug_functions {
+                let body_start = body_starts[(idx - import_count) as usize];
+                for &(offset, source_offset) in &func.source_offsets {
+                    map.add((entries_start + body_start + offset) as u32, source_offset);
+                }
+            }
+            self.source_map = Some(map.finish());
+
+            let mut url = Vec::new();
+            debug.map_url.as_str().encode(&mut url);
+            module.section(&CustomSection {
+                name: Cow::Borrowed(sourcemap::URL_SECTION),
+                data: Cow::Owned(url),
+            });
+        }
 
         Ok(module.finish())
     }
 
-    fn compile_function(&mut self, func: &FunctionDef) -> Result<Function> {
+    fn compile_function(&mut self, func: &FunctionDef) -> Result<CompiledFunction> {
         self.locals.clear();
-        self.local_index = 0;
+
+        let (params, ret) = self.signatures[&func.name].clone();
 
         // Register parameters as locals
-        for param in &func.params {
-            self.locals.insert(param.name.clone(), self.local_index);
-            self.local_index += 1;
+        for (idx, (param, ty)) in func.params.iter().zip(params).enumerate() {
+            self.locals.insert(param.name.to_string(), (idx as u32, ty));
         }
 
-        // Count additional locals needed
-        let additional_locals = self.count_locals(&func.body);
-
-        let mut wasm_func = Function::new(vec![(additional_locals, ValType::I64)]);
+        let mut builder = FunctionBuilder::new(func.params.len() as u32);
+        for (idx, param) in func.params.iter().enumerate() {
+            builder.name_local(idx as u32, &param.name);
+        }
+        builder.mark(func.span.start);
 
         // Compile function body
         for stmt in &func.body {
-            self.compile_statement(stmt, &mut wasm_func)?;
+            self.compile_statement(stmt, ret, &mut builder)?;
         }
 
-        // Add implicit return if no explicit return
-        if func.return_type.is_none() {
-            wasm_func.instruction(&Instruction::End);
-        } else {
-            // If there's a return type but no return statement, push 0
-            wasm_func.instruction(&Instruction::End);
-        }
-
-        Ok(wasm_func)
-    }
+        // Fall-through return value for bodies without an explicit return
+        Self::emit_zero(ret, &mut builder);
+        builder.instruction(&Instruction::End);
 
-    fn count_locals(&self, stmts: &[Statement]) -> u32 {
-        let mut count = 0;
-        for stmt in stmts {
-            match stmt {
-                Statement::VarDecl(_) => count += 1,
-                Statement::Conditional(c) => {
-                    count += self.count_locals(&c.then_branch);
-                    if let Some(else_branch) = &c.else_branch {
-                        count += self.count_locals(else_branch);
-                    }
-                }
-                Statement::Loop(l) => {
-                    count += self.count_locals(&l.body);
-                }
-                Statement::AttemptBlock(a) => {
-                    count += self.count_locals(&a.body);
-                }
-                _ => {}
-            }
-        }
-        count
+        Ok(builder.finish())
     }
 
-    fn compile_statement(&mut self, stmt: &Statement, func: &mut Function) -> Result<()> {
+    fn compile_statement(
+        &mut self,
+        stmt: &Statement,
+        ret: WasmType,
+        func: &mut FunctionBuilder,
+    ) -> Result<()> {
+        func.mark(stmt.span().start);
         match stmt {
             Statement::VarDecl(decl) => {
                 // Compile the value expression
-                self.compile_expr(&decl.value, func)?;
+                let ty = self.compile_expr(&decl.value, func)?;
 
-                // Store in local
-                let local_idx = self.local_index;
-                self.locals.insert(decl.name.clone(), local_idx);
-                self.local_index += 1;
+                // Store in a local of the value's type
+                let local_idx = func.add_local(ty.val_type());
+                func.name_local(local_idx, &decl.name);
+                self.locals.insert(decl.name.to_string(), (local_idx, ty));
 
                 func.instruction(&Instruction::LocalSet(local_idx));
             }
 
             Statement::Assignment(assign) => {
-                // Compile the value expression
-                self.compile_expr(&assign.value, func)?;
-
-                // Store in local
-                let local_idx = *self
+                let (local_idx, ty) = *self
                     .locals
-                    .get(&assign.target)
-                    .ok_or_else(|| CompileError::UndefinedVariable(assign.target.clone()))?;
+                    .get(assign.target.as_str())
+                    .ok_or_else(|| CompileError::UndefinedVariable(assign.target.to_string()))?;
 
+                // Compile the value, converted to the variable's type
+                self.compile_expr_as(&assign.value, ty, func)?;
                 func.instruction(&Instruction::LocalSet(local_idx));
             }
 
-            Statement::Return(ret) => {
-                self.compile_expr(&ret.value, func)?;
+            Statement::Return(ret_stmt) => {
+                self.compile_expr_as(&ret_stmt.value, ret, func)?;
                 func.instruction(&Instruction::Return);
             }
 
             Statement::Conditional(cond) => {
                 // Compile condition
-                self.compile_expr(&cond.condition, func)?;
+                self.compile_condition(&cond.condition, func)?;
 
                 // If-else block
-                func.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
+                func.instruction(&Instruction::If(BlockType::Empty));
 
                 for s in &cond.then_branch {
-                    self.compile_statement(s, func)?;
+                    self.compile_statement(s, ret, func)?;
                 }
 
                 if let Some(else_branch) = &cond.else_branch {
                     func.instruction(&Instruction::Else);
                     for s in else_branch {
-                        self.compile_statement(s, func)?;
+                        self.compile_statement(s, ret, func)?;
                     }
                 }
 
@@ -208,16 +871,15 @@ fn compile_statement(&mut self, stmt: &Statement, func: &mut Function) -> Result
 
             Statement::Loop(loop_stmt) => {
                 // Compile loop count
-                self.compile_expr(&loop_stmt.count, func)?;
+                self.compile_expr_as(&loop_stmt.count, WasmType::Int, func)?;
 
                 // Store count in a local
-                let count_local = self.local_index;
-                self.local_index += 1;
+                let count_local = func.add_local(ValType::I64);
                 func.instruction(&Instruction::LocalSet(count_local));
 
                 // Loop structure
-                func.instruction(&Instruction::Block(wasm_encoder::BlockType::Empty));
-                func.instruction(&Instruction::Loop(wasm_encoder::BlockType::Empty));
+                func.instruction(&Instruction::Block(BlockType::Empty));
+                func.instruction(&Instruction::Loop(BlockType::Empty));
 
                 // Check if count > 0
                 func.instruction(&Instruction::LocalGet(count_local));
@@ -227,7 +889,7 @@ fn compile_statement(&mut self, stmt: &Statement, func: &mut Function) -> Result
 
                 // Execute body
                 for s in &loop_stmt.body {
-                    self.compile_statement(s, func)?;
+                    self.compile_statement(s, ret, func)?;
                 }
 
                 // Decrement counter
@@ -248,16 +910,25 @@ fn compile_statement(&mut self, stmt: &Statement, func: &mut Function) -> Result
                 func.instruction(&Instruction::Drop); // Discard result
             }
 
-            Statement::ConsentBlock(_) => {
-                // Consent blocks are runtime-only, skip in WASM
-                // Could be implemented with host imports
+            Statement::ConsentBlock(consent) => {
+                // Ask the host before running guarded code
+                let addr = self.intern_string(&consent.permission);
+                func.instruction(&Instruction::I32Const(addr as i32 + 4));
+                func.instruction(&Instruction::I32Const(consent.permission.len() as i32));
+                func.instruction(&Instruction::Call(self.imports["request_consent"]));
+
+                func.instruction(&Instruction::If(BlockType::Empty));
+                for s in &consent.body {
+                    self.compile_statement(s, ret, func)?;
+                }
+                func.instruction(&Instruction::End);
             }
 
             Statement::AttemptBlock(attempt) => {
                 // Try-catch can be implemented with WASM exception handling
                 // For now, just compile the body
                 for s in &attempt.body {
-                    self.compile_statement(s, func)?;
+                    self.compile_statement(s, ret, func)?;
                 }
             }
 
@@ -273,63 +944,67 @@ fn compile_statement(&mut self, stmt: &Statement, func: &mut Function) -> Result
 
             Statement::EmoteAnnotated(annotated) => {
                 // Emote tags are metadata, compile the inner statement
-                self.compile_statement(&annotated.statement, func)?;
+                self.compile_statement(&annotated.statement, ret, func)?;
             }
 
             Statement::Decide(decide) => {
                 // Pattern matching - simplified to if-else chain
-                self.compile_expr(&decide.scrutinee, func)?;
-                let scrutinee_local = self.local_index;
-                self.local_index += 1;
+                let scrutinee_ty = self.compile_expr(&decide.scrutinee, func)?;
+                let scrutinee_local = func.add_local(scrutinee_ty.val_type());
                 func.instruction(&Instruction::LocalSet(scrutinee_local));
 
-                for (i, arm) in decide.arms.iter().enumerate() {
-                    let is_last = i == decide.arms.len() - 1;
-
+                let mut open_blocks = 0;
+                for arm in &decide.arms {
                     match &arm.pattern {
                         Pattern::Wildcard => {
                             // Wildcard always matches
                             for s in &arm.body {
-                                self.compile_statement(s, func)?;
+                                self.compile_statement(s, ret, func)?;
                             }
                             break;
                         }
-                        Pattern::Literal(lit) => {
-                            // Compare with literal
+                        Pattern::Identifier(name) => {
+                            // Bind the value to the identifier
                             func.instruction(&Instruction::LocalGet(scrutinee_local));
-                            self.compile_literal(lit, func)?;
-                            func.instruction(&Instruction::I64Eq);
+                            let bind_local = func.add_local(scrutinee_ty.val_type());
+                            func.name_local(bind_local, name);
+                            self.locals.insert(name.to_string(), (bind_local, scrutinee_ty));
+                            func.instruction(&Instruction::LocalSet(bind_local));
 
-                            func.instruction(&Instruction::If(wasm_encoder::BlockType::Empty));
                             for s in &arm.body {
-                                self.compile_statement(s, func)?;
-                            }
-
-                            if !is_last {
-                                func.instruction(&Instruction::Else);
+                                self.compile_statement(s, ret, func)?;
                             }
+                            break;
                         }
-                        Pattern::Identifier(name) => {
-                            // Bind the value to the identifier
+                        Pattern::Literal(lit) => {
+                            // Compare with literal in their common type
+                            let lit_ty = WasmType::of_literal(lit);
+                            let cmp_ty = scrutinee_ty.unify(lit_ty)?;
                             func.instruction(&Instruction::LocalGet(scrutinee_local));
-                            let bind_local = self.local_index;
-                            self.locals.insert(name.clone(), bind_local);
-                            self.local_index += 1;
-                            func.instruction(&Instruction::LocalSet(bind_local));
+                            Self::convert(scrutinee_ty, cmp_ty, func)?;
+                            self.compile_literal(lit, func)?;
+                            Self::convert(lit_ty, cmp_ty, func)?;
+                            self.emit_eq(cmp_ty, func);
 
+                            func.instruction(&Instruction::If(BlockType::Empty));
                             for s in &arm.body {
-                                self.compile_statement(s, func)?;
+                                self.compile_statement(s, ret, func)?;
                             }
-                            break;
+                            func.instruction(&Instruction::Else);
+                            open_blocks += 1;
+                        }
+                        Pattern::Constructor(name, _) => {
+                            return Err(CompileError::Unsupported(format!(
+                                "{} patterns not supported in WASM",
+                                name
+                            )));
                         }
                     }
-                }next stage
+                }
 
                 // Close all if blocks
-                for arm in &decide.arms {
-                    if !matches!(arm.pattern, Pattern::Wildcard | Pattern::Identifier(_)) {
-                        func.instruction(&Instruction::End);
-                    }
+                for _ in 0..open_blocks {
+                    func.instruction(&Instruction::End);
                 }
             }
         }
@@ -337,67 +1012,192 @@ fn compile_statement(&mut self, stmt: &Statement, func: &mut Function) -> Result
         Ok(())
     }
 
-    fn compile_expr(&mut self, expr: &Spanned<Expr>, func: &mut Function) -> Result<()> {
-        match &expr.node {
+    /// Emit an equality test of two values of type `ty`, leaving an i32
+    fn emit_eq(&self, ty: WasmType, func: &mut FunctionBuilder) {
+        func.instruction(&match ty {
+            WasmType::Int => Instruction::I64Eq,
+            WasmType::Float => Instruction::F64Eq,
+            WasmType::Str => Instruction::Call(self.runtime.str_eq),
+            // Function values compare by identity
+            WasmType::Func(_) => Instruction::I32Eq,
+        });
+    }
+
+    /// Compile an expression as a WASM `if`/`br_if` condition (i32)
+    fn compile_condition(&mut self, expr: &Spanned<Expr>, func: &mut FunctionBuilder) -> Result<()> {
+        self.compile_expr_as(expr, WasmType::Int, func)?;
+        func.instruction(&Instruction::I64Const(0));
+        func.instruction(&Instruction::I64Ne);
+        Ok(())
+    }
+
+    /// Compile an expression and convert the result to `target`
+    fn compile_expr_as(
+        &mut self,
+        expr: &Spanned<Expr>,
+        target: WasmType,
+        func: &mut FunctionBuilder,
+    ) -> Result<()> {
+        let ty = self.compile_expr(expr, func)?;
+        Self::convert(ty, target, func)
+    }
+
+    /// Emit a numeric conversion between WASM value types
+    fn convert(from: WasmType, to: WasmType, func: &mut FunctionBuilder) -> Result<()> {
+        match (from, to) {
+            (WasmType::Int, WasmType::Float) => {
+                func.instruction(&Instruction::F64ConvertI64S);
+            }
+            (WasmType::Float, WasmType::Int) => {
+                func.instruction(&Instruction::I64TruncSatF64S);
+            }
+            (a, b) if a == b => {}
+            (WasmType::Str | WasmType::Func(_), _) | (_, WasmType::Str | WasmType::Func(_)) => {
+                return Err(CompileError::TypeError(format!(
+                    "expected {:?}, found {:?}",
+                    to, from
+                )));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn emit_zero(ty: WasmType, func: &mut FunctionBuilder) {
+        match ty {
+            WasmType::Int => func.instruction(&Instruction::I64Const(0)),
+            WasmType::Float => func.instruction(&Instruction::F64Const(0.0.into())),
+            // Address 0 reads as the empty string, and as a closure record
+            // for the empty table slot
+            WasmType::Str | WasmType::Func(_) => func.instruction(&Instruction::I32Const(0)),
+        };
+    }
+
+    /// Determine the WASM type an expression evaluates to without emitting code
+    fn infer_type(&self, expr: &Expr) -> Result<WasmType> {
+        Ok(match expr {
+            Expr::Literal(lit) => WasmType::of_literal(lit),
+            Expr::Identifier(name) => match self.locals.get(name.as_str()) {
+                Some(&(_, ty)) => ty,
+                None if self.signatures.contains_key(name.as_str()) => {
+                    return Err(CompileError::TypeError(format!(
+                        "function {} cannot be used as an operand",
+                        name
+                    )));
+                }
+                None => return Err(CompileError::UndefinedVariable(name.to_string())),
+            },
+            Expr::Binary(
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod,
+                left,
+                right,
+            ) => self.infer_type(&left.node)?.unify(self.infer_type(&right.node)?)?,
+            Expr::Unary(UnaryOp::Neg, operand) => self.infer_type(&operand.node)?,
+            Expr::Call(name, _) if name == "print" => WasmType::Int,
+            Expr::Call(name, _) => match self.locals.get(name.as_str()) {
+                Some(&(_, ty)) => self.closure_signature(ty)?.1,
+                None => {
+                    self.signatures
+                        .get(name.as_str())
+                        .ok_or_else(|| CompileError::UndefinedFunction(name.to_string()))?
+                        .1
+                }
+            },
+            Expr::CallExpr(callee, _) => {
+                let callee_ty = self.infer_type(&callee.node)?;
+                self.closure_signature(callee_ty)?.1
+            }
+            Expr::UnitMeasurement(inner, _) => self.infer_type(&inner.node)?,
+            _ => WasmType::Int,
+        })
+    }
+
+    fn compile_expr(&mut self, expr: &Spanned<Expr>, func: &mut FunctionBuilder) -> Result<WasmType> {
+        let ty = match &expr.node {
             Expr::Literal(lit) => {
                 self.compile_literal(lit, func)?;
+                WasmType::of_literal(lit)
             }
 
-            Expr::Identifier(name) => {
-                let local_idx = *self
-                    .locals
-                    .get(name)
-                    .ok_or_else(|| CompileError::UndefinedVariable(name.clone()))?;
-                func.instruction(&Instruction::LocalGet(local_idx));
-            }
+            Expr::Identifier(name) => match self.locals.get(name.as_str()) {
+                Some(&(local_idx, ty)) => {
+                    func.instruction(&Instruction::LocalGet(local_idx));
+                    ty
+                }
+                None if self.signatures.contains_key(name.as_str()) => {
+                    self.compile_function_ref(name, func)?
+                }
+                None => return Err(CompileError::UndefinedVariable(name.to_string())),
+            },
 
             Expr::Binary(op, left, right) => {
-                self.compile_expr(left, func)?;
-                self.compile_expr(right, func)?;
-
-                match op {
-                    BinaryOp::Add => func.instruction(&Instruction::I64Add),
-                    BinaryOp::Sub => func.instruction(&Instruction::I64Sub),
-                    BinaryOp::Mul => func.instruction(&Instruction::I64Mul),
-                    BinaryOp::Div => func.instruction(&Instruction::I64DivS),
-                    BinaryOp::Mod => func.instruction(&Instruction::I64RemS),
-                    BinaryOp::Eq => func.instruction(&Instruction::I64Eq),
-                    BinaryOp::NotEq => func.instruction(&Instruction::I64Ne),
-                    BinaryOp::Lt => func.instruction(&Instruction::I64LtS),
-                    BinaryOp::Gt => func.instruction(&Instruction::I64GtS),
-                    BinaryOp::LtEq => func.instruction(&Instruction::I64LeS),
-                    BinaryOp::GtEq => func.instruction(&Instruction::I64GeS),
-                    BinaryOp::And => func.instruction(&Instruction::I64And),
-                    BinaryOp::Or => func.instruction(&Instruction::I64Or),
-                };
+                self.compile_binary(*op, left, right, func)?
             }
 
-            Expr::Unary(op, operand) => {
-                match op {
-                    UnaryOp::Neg => {
-                        func.instruction(&Instruction::I64Const(0));
-                        self.compile_expr(operand, func)?;
-                        func.instruction(&Instruction::I64Sub);
-                    }
-                    UnaryOp::Not => {
-                        self.compile_expr(operand, func)?;
-                        func.instruction(&Instruction::I64Eqz);
+            Expr::Unary(op, operand) => match op {
+                UnaryOp::Neg => {
+                    let ty = self.compile_expr(operand, func)?;
+                    match ty {
+                        WasmType::Int => {
+                            func.instruction(&Instruction::I64Const(-1));
+                            func.instruction(&Instruction::I64Mul);
+                        }
+                        WasmType::Float => {
+                            func.instruction(&Instruction::F64Neg);
+                        }
+                        WasmType::Str | WasmType::Func(_) => {
+                            return Err(CompileError::TypeError(
+                                "only numbers can be negated".into(),
+                            ));
+                        }
                     }
-                };
+                    ty
+                }
+                UnaryOp::Not => {
+                    self.compile_expr_as(operand, WasmType::Int, func)?;
+                    func.instruction(&Instruction::I64Eqz);
+                    func.instruction(&Instruction::I64ExtendI32U);
+                    WasmType::Int
+                }
+            },
+
+            Expr::Call(name, args) if name == "print" => {
+                self.compile_print(args, func)?;
+                WasmType::Int
+            }
+
+            Expr::Call(name, args) if self.locals.contains_key(name.as_str()) => {
+                // Calling a function value held in a variable
+                let (local_idx, ty) = self.locals[name.as_str()];
+                func.instruction(&Instruction::LocalGet(local_idx));
+                self.compile_indirect_call(ty, args, func)?
             }
 
             Expr::Call(name, args) => {
-                // Compile arguments
-                for arg in args {
-                    self.compile_expr(arg, func)?;
+                let (params, ret) = self
+                    .signatures
+                    .get(name.as_str())
+                    .cloned()
+                    .ok_or_else(|| CompileError::UndefinedFunction(name.to_string()))?;
+
+                if params.len() != args.len() {
+                    return Err(CompileError::TypeError(format!(
+                        "{} expects {} arguments, got {}",
+                        name,
+                        params.len(),
+                        args.len()
+                    )));
+                }
+
+                // Compile arguments, converted to the parameter types
+                for (arg, ty) in args.iter().zip(params) {
+                    self.compile_expr_as(arg, ty, func)?;
                 }
 
                 // Call function
-                let func_idx = *self
-                    .functions
-                    .get(name)
-                    .ok_or_else(|| CompileError::UndefinedFunction(name.clone()))?;
+                let func_idx = self.functions[name.as_str()];
                 func.instruction(&Instruction::Call(func_idx));
+                ret
             }
 
             Expr::Array(_) => {
@@ -406,35 +1206,406 @@ fn compile_expr(&mut self, expr: &Spanned<Expr>, func: &mut Function) -> Result<
                 ));
             }
 
+            Expr::UnitMeasurement(_, unit) if crate::stdlib::duration::unit_millis(unit).is_some() => {
+                return Err(CompileError::Unsupported(
+                    "Durations not yet supported in WASM compilation".into(),
+                ));
+            }
+
             Expr::UnitMeasurement(inner, _) => {
                 // Just compile the inner expression, ignore units
-                self.compile_expr(inner, func)?;
+                self.compile_expr(inner, func)?
             }
 
             Expr::GratitudeLiteral(_) => {
                 // Push 0 as placeholder
                 func.instruction(&Instruction::I64Const(0));
+                WasmType::Int
+            }
+
+            Expr::CallExpr(callee, args) => {
+                let ty = self.compile_expr(callee, func)?;
+                self.compile_indirect_call(ty, args, func)?
+            }
+
+            Expr::Lambda(lambda) => self.compile_lambda(lambda, expr.span.start, func)?,
+
+            Expr::Index(..) => {
+                return Err(CompileError::Unsupported(
+                    "Indexing not yet supported in WASM compilation".into(),
+                ));
             }
+
+            Expr::Okay(_) | Expr::Oops(_) | Expr::Unwrap(_) => {
+                return Err(CompileError::Unsupported(
+                    "Result types not yet supported in WASM compilation".into(),
+                ));
+            }
+        };
+
+        Ok(ty)
+    }
+
+    /// Signature of a function value type
+    fn closure_signature(&self, ty: WasmType) -> Result<(Vec<WasmType>, WasmType)> {
+        match ty {
+            WasmType::Func(idx) => Ok(self.closure_types[idx as usize].clone()),
+            other => Err(CompileError::TypeError(format!(
+                "cannot call a value of type {:?}",
+                other
+            ))),
         }
+    }
 
+    /// Call the closure whose record pointer is on the stack
+    fn compile_indirect_call(
+        &mut self,
+        callee_ty: WasmType,
+        args: &[Spanned<Expr>],
+        func: &mut FunctionBuilder,
+    ) -> Result<WasmType> {
+        let (params, ret) = self.closure_signature(callee_ty)?;
+        if params.len() != args.len() {
+            return Err(CompileError::TypeError(format!(
+                "function value expects {} arguments, got {}",
+                params.len(),
+                args.len()
+            )));
+        }
+
+        // The record doubles as the callee's environment argument
+        let closure = func.add_local(ValType::I32);
+        func.instruction(&Instruction::LocalTee(closure));
+        for (arg, &ty) in args.iter().zip(&params) {
+            self.compile_expr_as(arg, ty, func)?;
+        }
+        func.instruction(&Instruction::LocalGet(closure));
+        func.instruction(&Instruction::I32Load(runtime::mem_i32(0)));
+        func.instruction(&Instruction::CallIndirect {
+            type_index: self.lifted_type_index(&params, ret),
+            table_index: 0,
+        });
+        Ok(ret)
+    }
+
+    /// Closure record for a named function, via a trampoline that ignores
+    /// the environment argument
+    fn compile_function_ref(&mut self, name: &str, func: &mut FunctionBuilder) -> Result<WasmType> {
+        let (params, ret) = self.signatures[name].clone();
+        let ty = self.closure_type(params.clone(), ret);
+
+        let addr = match self.function_refs.get(name) {
+            Some(&addr) => addr,
+            None => {
+                let func_idx = self.reserve_lifted();
+                let mut builder = FunctionBuilder::new(1 + params.len() as u32);
+                for i in 0..params.len() as u32 {
+                    builder.instruction(&Instruction::LocalGet(i + 1));
+                }
+                builder.instruction(&Instruction::Call(self.functions[name]));
+                builder.instruction(&Instruction::End);
+
+                let type_idx = self.lifted_type_index(&params, ret);
+                let slot = self.finish_lifted(
+                    func_idx,
+                    LiftedFunction {
+                        name: format!("__ref_{}", name),
+                        type_idx,
+                        compiled: builder.finish(),
+                    },
+                );
+                let addr = self.static_closure(slot);
+                self.function_refs.insert(name.to_string(), addr);
+                addr
+            }
+        };
+
+        func.instruction(&Instruction::I32Const(addr as i32));
+        Ok(ty)
+    }
+
+    /// Lift a lambda into its own function and build its closure record
+    fn compile_lambda(
+        &mut self,
+        lambda: &LambdaExpr,
+        source_offset: usize,
+        outer: &mut FunctionBuilder,
+    ) -> Result<WasmType> {
+        let params: Vec<WasmType> = lambda
+            .params
+            .iter()
+            .map(|p| self.wasm_type(p.ty.as_ref()))
+            .collect();
+
+        // Variables of the enclosing function the body refers to are
+        // copied into the closure record
+        let param_names: HashSet<&str> = lambda.params.iter().map(|p| p.name.as_str()).collect();
+        let captures: Vec<(String, u32, WasmType)> = lambda_identifiers(lambda)
+            .into_iter()
+            .filter(|name| !param_names.contains(name.as_str()))
+            .filter_map(|name| {
+                let (idx, ty) = *self.locals.get(&name)?;
+                Some((name, idx, ty))
+            })
+            .collect();
+
+        let func_idx = self.reserve_lifted();
+        let outer_locals = std::mem::take(&mut self.locals);
+        let mut builder = FunctionBuilder::new(1 + params.len() as u32);
+        builder.name_local(0, "env");
+        for (idx, (param, &ty)) in lambda.params.iter().zip(&params).enumerate() {
+            builder.name_local(idx as u32 + 1, &param.name);
+            self.locals.insert(param.name.to_string(), (idx as u32 + 1, ty));
+        }
+        builder.mark(source_offset);
+
+        // Unpack captured variables into locals
+        for (slot, (name, _, ty)) in captures.iter().enumerate() {
+            let local = builder.add_local(ty.val_type());
+            builder.name_local(local, name);
+            builder.instruction(&Instruction::LocalGet(0));
+            builder.instruction(&capture_load(*ty, capture_offset(slot)));
+            builder.instruction(&Instruction::LocalSet(local));
+            self.locals.insert(name.clone(), (local, *ty));
+        }
+
+        let declared = lambda.return_type.as_ref().map(|t| self.wasm_type(Some(t)));
+        let body = match &lambda.body {
+            LambdaBody::Expr(expr) => {
+                builder.mark(expr.span.start);
+                self.compile_expr(expr, &mut builder).and_then(|ty| {
+                    let ret = declared.unwrap_or(ty);
+                    Self::convert(ty, ret, &mut builder)?;
+                    Ok(ret)
+                })
+            }
+            LambdaBody::Block(stmts) => {
+                let ret = declared.unwrap_or(WasmType::Int);
+                stmts
+                    .iter()
+                    .try_for_each(|s| self.compile_statement(s, ret, &mut builder))
+                    .map(|()| {
+                        Self::emit_zero(ret, &mut builder);
+                        ret
+                    })
+            }
+        };
+        self.locals = outer_locals;
+        let ret = body?;
+        builder.instruction(&Instruction::End);
+
+        let type_idx = self.lifted_type_index(&params, ret);
+        let slot = self.finish_lifted(
+            func_idx,
+            LiftedFunction {
+                name: format!("__lambda{}", func_idx - self.lifted_base),
+                type_idx,
+                compiled: builder.finish(),
+            },
+        );
+
+        if captures.is_empty() {
+            outer.instruction(&Instruction::I32Const(self.static_closure(slot) as i32));
+        } else {
+            let record = outer.add_local(ValType::I32);
+            outer.instruction(&Instruction::I32Const(capture_offset(captures.len()) as i32));
+            outer.instruction(&Instruction::Call(self.runtime.alloc));
+            outer.instruction(&Instruction::LocalTee(record));
+            outer.instruction(&Instruction::I32Const(slot as i32));
+            outer.instruction(&Instruction::I32Store(runtime::mem_i32(0)));
+            for (i, (_, local, ty)) in captures.iter().enumerate() {
+                outer.instruction(&Instruction::LocalGet(record));
+                outer.instruction(&Instruction::LocalGet(*local));
+                outer.instruction(&capture_store(*ty, capture_offset(i)));
+            }
+            outer.instruction(&Instruction::LocalGet(record));
+        }
+
+        Ok(self.closure_type(params, ret))
+    }
+
+    /// Compile a call to the host `print` imports; evaluates to Unit (0)
+    fn compile_print(&mut self, args: &[Spanned<Expr>], func: &mut FunctionBuilder) -> Result<()> {
+        if args.len() != 1 {
+            return Err(CompileError::TypeError(format!(
+                "print expects 1 argument, got {}",
+                args.len()
+            )));
+        }
+
+        match self.compile_expr(&args[0], func)? {
+            WasmType::Str => {
+                // Pass the payload pointer and byte length
+                let ptr = func.add_local(ValType::I32);
+                func.instruction(&Instruction::LocalTee(ptr));
+                func.instruction(&Instruction::I32Const(4));
+                func.instruction(&Instruction::I32Add);
+                func.instruction(&Instruction::LocalGet(ptr));
+                func.instruction(&Instruction::I32Load(runtime::mem_i32(0)));
+                func.instruction(&Instruction::Call(self.imports["print"]));
+            }
+            WasmType::Int => {
+                func.instruction(&Instruction::Call(self.imports["print_int"]));
+            }
+            WasmType::Float => {
+                func.instruction(&Instruction::Call(self.imports["print_float"]));
+            }
+            WasmType::Func(_) => {
+                return Err(CompileError::TypeError("cannot print a function".into()));
+            }
+        }
+
+        func.instruction(&Instruction::I64Const(0));
         Ok(())
     }
 
-    fn compile_literal(&self, lit: &Literal, func: &mut Function) -> Result<()> {
+    fn compile_binary(
+        &mut self,
+        op: BinaryOp,
+        left: &Spanned<Expr>,
+        right: &Spanned<Expr>,
+        func: &mut FunctionBuilder,
+    ) -> Result<WasmType> {
+        if matches!(op, BinaryOp::And | BinaryOp::Or) {
+            // Booleans are 0/1 integers
+            self.compile_expr_as(left, WasmType::Int, func)?;
+            self.compile_expr_as(right, WasmType::Int, func)?;
+            func.instruction(match op {
+                BinaryOp::And => &Instruction::I64And,
+                _ => &Instruction::I64Or,
+            });
+            return Ok(WasmType::Int);
+        }
+
+        // Promote both operands to their common type
+        let operand_ty = self
+            .infer_type(&left.node)?
+            .unify(self.infer_type(&right.node)?)?;
+
+        if operand_ty == WasmType::Str {
+            self.compile_expr(left, func)?;
+            self.compile_expr(right, func)?;
+            return match op {
+                BinaryOp::Add => {
+                    func.instruction(&Instruction::Call(self.runtime.concat));
+                    Ok(WasmType::Str)
+                }
+                BinaryOp::Eq | BinaryOp::NotEq => {
+                    func.instruction(&Instruction::Call(self.runtime.str_eq));
+                    if op == BinaryOp::NotEq {
+                        func.instruction(&Instruction::I32Eqz);
+                    }
+                    func.instruction(&Instruction::I64ExtendI32U);
+                    Ok(WasmType::Int)
+                }
+                _ => Err(CompileError::TypeError(format!(
+                    "operator {:?} is not defined for String",
+                    op
+                ))),
+            };
+        }
+
+        if let WasmType::Func(_) = operand_ty {
+            // Function values compare by identity
+            let instruction = match op {
+                BinaryOp::Eq => Instruction::I32Eq,
+                BinaryOp::NotEq => Instruction::I32Ne,
+                _ => {
+                    return Err(CompileError::TypeError(format!(
+                        "operator {:?} is not defined for functions",
+                        op
+                    )))
+                }
+            };
+            self.compile_expr(left, func)?;
+            self.compile_expr(right, func)?;
+            func.instruction(&instruction);
+            func.instruction(&Instruction::I64ExtendI32U);
+            return Ok(WasmType::Int);
+        }
+
+        if op == BinaryOp::Mod && operand_ty == WasmType::Float {
+            // WASM has no f64 remainder: a - trunc(a / b) * b
+            self.compile_expr_as(left, WasmType::Float, func)?;
+            let a = func.add_local(ValType::F64);
+            func.instruction(&Instruction::LocalSet(a));
+            self.compile_expr_as(right, WasmType::Float, func)?;
+            let b = func.add_local(ValType::F64);
+            func.instruction(&Instruction::LocalSet(b));
+
+            func.instruction(&Instruction::LocalGet(a));
+            func.instruction(&Instruction::LocalGet(a));
+            func.instruction(&Instruction::LocalGet(b));
+            func.instruction(&Instruction::F64Div);
+            func.instruction(&Instruction::F64Trunc);
+            func.instruction(&Instruction::LocalGet(b));
+            func.instruction(&Instruction::F64Mul);
+            func.instruction(&Instruction::F64Sub);
+            return Ok(WasmType::Float);
+        }
+
+        self.compile_expr_as(left, operand_ty, func)?;
+        self.compile_expr_as(right, operand_ty, func)?;
+
+        let instruction = match (operand_ty, op) {
+            (WasmType::Int, BinaryOp::Add) => Instruction::I64Add,
+            (WasmType::Int, BinaryOp::Sub) => Instruction::I64Sub,
+            (WasmType::Int, BinaryOp::Mul) => Instruction::I64Mul,
+            (WasmType::Int, BinaryOp::Div) => Instruction::I64DivS,
+            (WasmType::Int, BinaryOp::Mod) => Instruction::I64RemS,
+            (WasmType::Int, BinaryOp::Eq) => Instruction::I64Eq,
+            (WasmType::Int, BinaryOp::NotEq) => Instruction::I64Ne,
+            (WasmType::Int, BinaryOp::Lt) => Instruction::I64LtS,
+            (WasmType::Int, BinaryOp::Gt) => Instruction::I64GtS,
+            (WasmType::Int, BinaryOp::LtEq) => Instruction::I64LeS,
+            (WasmType::Int, BinaryOp::GtEq) => Instruction::I64GeS,
+            (WasmType::Float, BinaryOp::Add) => Instruction::F64Add,
+            (WasmType::Float, BinaryOp::Sub) => Instruction::F64Sub,
+            (WasmType::Float, BinaryOp::Mul) => Instruction::F64Mul,
+            (WasmType::Float, BinaryOp::Div) => Instruction::F64Div,
+            (WasmType::Float, BinaryOp::Eq) => Instruction::F64Eq,
+            (WasmType::Float, BinaryOp::NotEq) => Instruction::F64Ne,
+            (WasmType::Float, BinaryOp::Lt) => Instruction::F64Lt,
+            (WasmType::Float, BinaryOp::Gt) => Instruction::F64Gt,
+            (WasmType::Float, BinaryOp::LtEq) => Instruction::F64Le,
+            (WasmType::Float, BinaryOp::GtEq) => Instruction::F64Ge,
+            (WasmType::Float, BinaryOp::Mod)
+            | (_, BinaryOp::And | BinaryOp::Or)
+            | (WasmType::Str | WasmType::Func(_), _) => unreachable!(),
+        };
+        func.instruction(&instruction);
+
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                Ok(operand_ty)
+            }
+            _ => {
+                // Comparisons produce i32; widen to a 0/1 Bool
+                func.instruction(&Instruction::I64ExtendI32U);
+                Ok(WasmType::Int)
+            }
+        }
+    }
+
+    fn compile_literal(&mut self, lit: &Literal, func: &mut FunctionBuilder) -> Result<()> {
         match lit {
             Literal::Integer(n) => {
                 func.instruction(&Instruction::I64Const(*n));
             }
             Literal::Float(f) => {
-                // Convert to i64 bits for now (simplified)
-                func.instruction(&Instruction::I64Const(f.to_bits() as i64));
+                func.instruction(&Instruction::F64Const((*f).into()));
+            }
+            Literal::Decimal(_) => {
+                return Err(CompileError::Unsupported("Decimals not supported in WASM".into()));
             }
             Literal::Bool(b) => {
                 func.instruction(&Instruction::I64Const(if *b { 1 } else { 0 }));
             }
-            Literal::String(_) => {
-                // Strings would need memory allocation
-                // For now, push 0 as placeholder
+            Literal::String(s) => {
+                let addr = self.intern_string(s);
+                func.instruction(&Instruction::I32Const(addr as i32));
+            }
+            Literal::Unit => {
                 func.instruction(&Instruction::I64Const(0));
             }
         }
@@ -442,6 +1613,218 @@ fn compile_literal(&self, lit: &Literal, func: &mut Function) -> Result<()> {
     }
 }
 
+/// Offset of the `slot`th captured variable in a closure record
+fn capture_offset(slot: usize) -> u64 {
+    8 * (slot as u64 + 1)
+}
+
+fn capture_memarg(offset: u64) -> MemArg {
+    MemArg {
+        offset,
+        align: 3,
+        memory_index: 0,
+    }
+}
+
+/// Load a captured variable of type `ty` from a closure record
+fn capture_load(ty: WasmType, offset: u64) -> Instruction<'static> {
+    match ty {
+        WasmType::Int => Instruction::I64Load(capture_memarg(offset)),
+        WasmType::Float => Instruction::F64Load(capture_memarg(offset)),
+        WasmType::Str | WasmType::Func(_) => Instruction::I32Load(runtime::mem_i32(offset)),
+    }
+}
+
+/// Store a captured variable of type `ty` into a closure record
+fn capture_store(ty: WasmType, offset: u64) -> Instruction<'static> {
+    match ty {
+        WasmType::Int => Instruction::I64Store(capture_memarg(offset)),
+        WasmType::Float => Instruction::F64Store(capture_memarg(offset)),
+        WasmType::Str | WasmType::Func(_) => Instruction::I32Store(runtime::mem_i32(offset)),
+    }
+}
+
+/// Identifiers a lambda body refers to, in order of first use
+fn lambda_identifiers(lambda: &LambdaExpr) -> Vec<String> {
+    fn visit_stmts(stmts: &[Statement], names: &mut Vec<String>) {
+        for stmt in stmts {
+            visit_stmt(stmt, names);
+        }
+    }
+
+    fn visit_stmt(stmt: &Statement, names: &mut Vec<String>) {
+        match stmt {
+            Statement::VarDecl(d) => visit_expr(&d.value, names),
+            Statement::Assignment(a) => {
+                add(&a.target, names);
+                visit_expr(&a.value, names);
+            }
+            Statement::Return(r) => visit_expr(&r.value, names),
+            Statement::Conditional(c) => {
+                visit_expr(&c.condition, names);
+                visit_stmts(&c.then_branch, names);
+                if let Some(else_branch) = &c.else_branch {
+                    visit_stmts(else_branch, names);
+                }
+            }
+            Statement::Loop(l) => {
+                visit_expr(&l.count, names);
+                visit_stmts(&l.body, names);
+            }
+            Statement::AttemptBlock(a) => visit_stmts(&a.body, names),
+            Statement::ConsentBlock(c) => visit_stmts(&c.body, names),
+            Statement::Expression(e) => visit_expr(e, names),
+            Statement::EmoteAnnotated(e) => visit_stmt(&e.statement, names),
+            Statement::Decide(d) => {
+                visit_expr(&d.scrutinee, names);
+                for arm in &d.arms {
+                    visit_stmts(&arm.body, names);
+                }
+            }
+            Statement::WorkerSpawn(_) | Statement::Complain(_) => {}
+        }
+    }
+
+    fn visit_expr(expr: &Spanned<Expr>, names: &mut Vec<String>) {
+        match &expr.node {
+            Expr::Identifier(name) => add(name, names),
+            Expr::Call(name, args) => {
+                add(name, names);
+                args.iter().for_each(|a| visit_expr(a, names));
+            }
+            Expr::CallExpr(callee, args) => {
+                visit_expr(callee, names);
+                args.iter().for_each(|a| visit_expr(a, names));
+            }
+            Expr::Binary(_, left, right) | Expr::Index(left, right) => {
+                visit_expr(left, names);
+                visit_expr(right, names);
+            }
+            Expr::Unary(_, e)
+            | Expr::UnitMeasurement(e, _)
+            | Expr::Okay(e)
+            | Expr::Unwrap(e) => visit_expr(e, names),
+            Expr::Array(items) => items.iter().for_each(|i| visit_expr(i, names)),
+            Expr::Lambda(inner) => match &inner.body {
+                LambdaBody::Expr(e) => visit_expr(e, names),
+                LambdaBody::Block(stmts) => visit_stmts(stmts, names),
+            },
+            _ => {}
+        }
+    }
+
+    fn add(name: &str, names: &mut Vec<String>) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    let mut names = Vec::new();
+    match &lambda.body {
+        LambdaBody::Expr(e) => visit_expr(e, &mut names),
+        LambdaBody::Block(stmts) => visit_stmts(stmts, &mut names),
+    }
+    names
+}
+
+/// Size of `n` encoded as unsigned LEB128
+fn leb128_len(n: usize) -> usize {
+    let bits = usize::BITS - n.leading_zeros();
+    (bits as usize).div_ceil(7).max(1)
+}
+
+/// Features of a program that decide which imports a module needs
+#[derive(Debug, Default)]
+struct ProgramUsage {
+    /// Names of all functions called by name
+    calls: HashSet<String>,
+    /// Whether any consent block appears
+    consent: bool,
+}
+
+fn scan_program(program: &Program) -> ProgramUsage {
+    fn visit_stmts(stmts: &[Statement], usage: &mut ProgramUsage) {
+        for stmt in stmts {
+            visit_stmt(stmt, usage);
+        }
+    }
+
+    fn visit_stmt(stmt: &Statement, calls: &mut ProgramUsage) {
+        match stmt {
+            Statement::VarDecl(decl) => visit_expr(&decl.value, calls),
+            Statement::Assignment(assign) => visit_expr(&assign.value, calls),
+            Statement::Return(ret) => visit_expr(&ret.value, calls),
+            Statement::Conditional(cond) => {
+                visit_expr(&cond.condition, calls);
+                visit_stmts(&cond.then_branch, calls);
+                if let Some(else_branch) = &cond.else_branch {
+                    visit_stmts(else_branch, calls);
+                }
+            }
+            Statement::Loop(l) => {
+                visit_expr(&l.count, calls);
+                visit_stmts(&l.body, calls);
+            }
+            Statement::AttemptBlock(a) => visit_stmts(&a.body, calls),
+            Statement::ConsentBlock(c) => {
+                calls.consent = true;
+                visit_stmts(&c.body, calls);
+            }
+            Statement::Expression(e) => visit_expr(e, calls),
+            Statement::EmoteAnnotated(a) => visit_stmt(&a.statement, calls),
+            Statement::Decide(d) => {
+                visit_expr(&d.scrutinee, calls);
+                for arm in &d.arms {
+                    visit_stmts(&arm.body, calls);
+                }
+            }
+            Statement::WorkerSpawn(_) | Statement::Complain(_) => {}
+        }
+    }
+
+    fn visit_expr(expr: &Spanned<Expr>, calls: &mut ProgramUsage) {
+        match &expr.node {
+            Expr::Call(name, args) => {
+                calls.calls.insert(name.to_string());
+                args.iter().for_each(|a| visit_expr(a, calls));
+            }
+            Expr::CallExpr(callee, args) => {
+                visit_expr(callee, calls);
+                args.iter().for_each(|a| visit_expr(a, calls));
+            }
+            Expr::Binary(_, l, r) | Expr::Index(l, r) => {
+                visit_expr(l, calls);
+                visit_expr(r, calls);
+            }
+            Expr::Unary(_, e)
+            | Expr::UnitMeasurement(e, _)
+            | Expr::Okay(e)
+            | Expr::Oops(e)
+            | Expr::Unwrap(e) => visit_expr(e, calls),
+            Expr::Array(items) => items.iter().for_each(|i| visit_expr(i, calls)),
+            Expr::Lambda(lambda) => match &lambda.body {
+                LambdaBody::Expr(e) => visit_expr(e, calls),
+                LambdaBody::Block(stmts) => visit_stmts(stmts, calls),
+            },
+            Expr::Literal(_) | Expr::Identifier(_) | Expr::GratitudeLiteral(_) => {}
+        }
+    }
+
+    let mut usage = ProgramUsage::default();
+    for item in &program.items {
+        match item {
+            TopLevelItem::Function(f) => visit_stmts(&f.body, &mut usage),
+            TopLevelItem::ConsentBlock(c) => {
+                usage.consent = true;
+                visit_stmts(&c.body, &mut usage);
+            }
+            TopLevelItem::WorkerDef(w) => visit_stmts(&w.body, &mut usage),
+            _ => {}
+        }
+    }
+    usage
+}
+
 impl Default for WasmCompiler {
     fn default() -> Self {
         Self::new()
@@ -490,6 +1873,424 @@ fn test_compile_factorial() {
         assert!(!wasm.is_empty());
     }
 
+    /// Instantiate a module with a reference host that records printed lines
+    fn instantiate(wasm: &[u8]) -> (wasmi::Store<Vec<String>>, wasmi::Instance) {
+        use wasmi::{Caller, Extern};
+
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, wasm).expect("invalid module");
+        let mut store = wasmi::Store::new(&engine, Vec::new());
+        let mut linker = wasmi::Linker::<Vec<String>>::new(&engine);
+        linker
+            .func_wrap(
+                HOST_MODULE,
+                "print",
+                |mut caller: Caller<'_, Vec<String>>, ptr: i32, len: i32| {
+                    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+                        panic!("module does not export memory");
+                    };
+                    let mut bytes = vec![0; len as usize];
+                    memory.read(&caller, ptr as usize, &mut bytes).unwrap();
+                    caller.data_mut().push(String::from_utf8(bytes).unwrap());
+                },
+            )
+            .unwrap();
+        linker
+            .func_wrap(HOST_MODULE, "print_int", |mut caller: Caller<'_, Vec<String>>, n: i64| {
+                caller.data_mut().push(n.to_string());
+            })
+            .unwrap();
+        linker
+            .func_wrap(HOST_MODULE, "print_float", |mut caller: Caller<'_, Vec<String>>, n: f64| {
+                caller.data_mut().push(n.to_string());
+            })
+            .unwrap();
+        // Grants permissions starting with "ok" and logs every request
+        linker
+            .func_wrap(
+                HOST_MODULE,
+                "request_consent",
+                |mut caller: Caller<'_, Vec<String>>, ptr: i32, len: i32| -> i32 {
+                    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+                        panic!("module does not export memory");
+                    };
+                    let mut bytes = vec![0; len as usize];
+                    memory.read(&caller, ptr as usize, &mut bytes).unwrap();
+                    let permission = String::from_utf8(bytes).unwrap();
+                    let granted = permission.starts_with("ok");
+                    caller.data_mut().push(format!("consent {}", permission));
+                    granted as i32
+                },
+            )
+            .unwrap();
+        let instance = linker
+            .instantiate_and_start(&mut store, &module)
+            .expect("instantiation failed");
+        (store, instance)
+    }
+
+    #[test]
+    fn test_run_int_arithmetic() {
+        let source = r#"
+            to factorial(n: Int) -> Int {
+                when n <= 1 {
+                    give back 1;
+                }
+                give back n * factorial(n - 1);
+            }
+        "#;
+        let (mut store, instance) = instantiate(&compile(source).unwrap());
+        let factorial = instance
+            .get_typed_func::<i64, i64>(&store, "factorial")
+            .unwrap();
+        assert_eq!(factorial.call(&mut store, 10).unwrap(), 3_628_800);
+    }
+
+    #[test]
+    fn test_run_float_arithmetic() {
+        let source = r#"
+            to area(r: Float) -> Float {
+                remember pi = 3.5;
+                give back pi * r * r;
+            }
+
+            to mixed(n: Int) -> Float {
+                give back n / 4.0 + 1;
+            }
+
+            to wrap(x: Float) -> Float {
+                give back x % 2.0;
+            }
+        "#;
+        let (mut store, instance) = instantiate(&compile(source).unwrap());
+
+        let area = instance.get_typed_func::<f64, f64>(&store, "area").unwrap();
+        assert_eq!(area.call(&mut store, 2.0).unwrap(), 14.0);
+
+        let mixed = instance.get_typed_func::<i64, f64>(&store, "mixed").unwrap();
+        assert_eq!(mixed.call(&mut store, 10).unwrap(), 3.5);
+
+        let wrap = instance.get_typed_func::<f64, f64>(&store, "wrap").unwrap();
+        assert_eq!(wrap.call(&mut store, 5.5).unwrap(), 1.5);
+    }
+
+    #[test]
+    fn test_run_checked_signatures() {
+        let source = r#"
+            to half(n) -> Float {
+                give back n / 2.0;
+            }
+
+            to main() -> Float {
+                give back half(3.0);
+            }
+        "#;
+        // Without checking, a parameter of no declared type is an Int
+        let (store, instance) = instantiate(&compile(source).unwrap());
+        assert!(instance.get_typed_func::<i64, f64>(&store, "half").is_ok());
+
+        // With it, it is the type its calls give it
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap(), source).parse().unwrap();
+        let checked = crate::typechecker::TypeChecker::new().check(&program).unwrap();
+        let wasm = WasmCompiler::new().compile_checked(&checked).unwrap();
+        let (mut store, instance) = instantiate(&wasm);
+        let half = instance.get_typed_func::<f64, f64>(&store, "half").unwrap();
+        assert_eq!(half.call(&mut store, 3.0).unwrap(), 1.5);
+        let main = instance.get_typed_func::<(), f64>(&store, "main").unwrap();
+        assert_eq!(main.call(&mut store, ()).unwrap(), 1.5);
+    }
+
+    #[test]
+    fn test_run_float_comparison_and_conversion() {
+        let source = r#"
+            to bigger(a: Float, b: Int) -> Int {
+                when a > b {
+                    give back 1;
+                }
+                give back 0;
+            }
+
+            to truncate(x: Float) -> Int {
+                give back x;
+            }
+        "#;
+        let (mut store, instance) = instantiate(&compile(source).unwrap());
+
+        let bigger = instance
+            .get_typed_func::<(f64, i64), i64>(&store, "bigger")
+            .unwrap();
+        assert_eq!(bigger.call(&mut store, (2.5, 2)).unwrap(), 1);
+        assert_eq!(bigger.call(&mut store, (1.5, 2)).unwrap(), 0);
+
+        let truncate = instance.get_typed_func::<f64, i64>(&store, "truncate").unwrap();
+        assert_eq!(truncate.call(&mut store, 7.9).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_run_hello_world() {
+        let source = r#"
+            to greet(name: String) -> String {
+                give back "Hello, " + name + "!";
+            }
+
+            to main() {
+                print(greet("WokeLang"));
+                print(6 * 7);
+                print(1.5);
+            }
+        "#;
+        let (mut store, instance) = instantiate(&compile(source).unwrap());
+        let main = instance.get_typed_func::<(), i64>(&store, "main").unwrap();
+        main.call(&mut store, ()).unwrap();
+        assert_eq!(store.data(), &["Hello, WokeLang!", "42", "1.5"]);
+    }
+
+    #[test]
+    fn test_run_string_equality_and_decide() {
+        let source = r#"
+            to check() -> Int {
+                remember total = 0;
+                when "abc" == "ab" + "c" { total = total + 1; }
+                when "abc" != "abd" { total = total + 10; }
+                total = total + mood("sad");
+                give back total;
+            }
+
+            to mood(m: String) -> Int {
+                decide based on m {
+                    "happy" -> { give back 1; }
+                    "sad" -> { give back 2; }
+                    _ -> { give back 0; }
+                }
+                give back 0;
+            }
+        "#;
+        let (mut store, instance) = instantiate(&compile(source).unwrap());
+        let check = instance.get_typed_func::<(), i64>(&store, "check").unwrap();
+        assert_eq!(check.call(&mut store, ()).unwrap(), 13);
+    }
+
+    #[test]
+    fn test_allocator_grows_memory() {
+        let source = r#"
+            to main() -> Int {
+                remember s = "0123456789abcdef";
+                repeat 13 times {
+                    s = s + s;
+                }
+                print(s == "");
+                give back 0;
+            }
+        "#;
+        let (mut store, instance) = instantiate(&compile(source).unwrap());
+        let main = instance.get_typed_func::<(), i64>(&store, "main").unwrap();
+        main.call(&mut store, ()).unwrap();
+        assert_eq!(store.data(), &["0"]);
+    }
+
+    #[test]
+    fn test_string_number_mix_is_rejected() {
+        let source = r#"
+            to main() {
+                print("n = " + 1);
+            }
+        "#;
+        assert!(matches!(compile(source), Err(CompileError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_run_consent_blocks() {
+        let source = r#"
+            only if okay "ok.files" {
+                print("init granted");
+            }
+
+            to main() {
+                only if okay "ok.network" {
+                    print("granted");
+                }
+                only if okay "camera" {
+                    print("denied");
+                }
+                print("done");
+            }
+        "#;
+        let (mut store, instance) = instantiate(&compile(source).unwrap());
+
+        let init = instance.get_typed_func::<(), i64>(&store, INIT_FUNCTION).unwrap();
+        init.call(&mut store, ()).unwrap();
+        let main = instance.get_typed_func::<(), i64>(&store, "main").unwrap();
+        main.call(&mut store, ()).unwrap();
+
+        assert_eq!(
+            store.data(),
+            &[
+                "consent ok.files",
+                "init granted",
+                "consent ok.network",
+                "granted",
+                "consent camera",
+                "done",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_consent_import_without_consent_blocks() {
+        let wasm = compile("to main() { print(1); }").unwrap();
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &wasm[..]).unwrap();
+        assert!(module.imports().all(|import| import.name() != "request_consent"));
+        assert!(module.get_export(INIT_FUNCTION).is_none());
+    }
+
+    #[test]
+    fn test_name_section_and_source_map() {
+        use wasmparser::{KnownCustom, Name, Operator, Payload};
+
+        let source = "to double(n: Int) -> Int {\n    remember twice = n * 2;\n    give back twice;\n}\n";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let mut compiler = WasmCompiler::new().with_source_map("double.woke", source, "double.wasm.map");
+        let wasm = compiler.compile(&program).unwrap();
+
+        let mut function_names = HashMap::new();
+        let mut local_names = HashMap::new();
+        let mut map_url = None;
+        let mut instruction_offsets = HashSet::new();
+        for payload in wasmparser::Parser::new(0).parse_all(&wasm) {
+            match payload.unwrap() {
+                Payload::CodeSectionEntry(body) => {
+                    let mut reader = body.get_operators_reader().unwrap();
+                    while !reader.eof() {
+                        let (op, offset) = reader.read_with_offset().unwrap();
+                        if !matches!(op, Operator::End) {
+                            instruction_offsets.insert(offset as u32);
+                        }
+                    }
+                }
+                Payload::CustomSection(section) => match section.as_known() {
+                    KnownCustom::Name(reader) => {
+                        for name in reader {
+                            match name.unwrap() {
+                                Name::Function(map) => {
+                                    for naming in map {
+                                        let naming = naming.unwrap();
+                                        function_names.insert(naming.index, naming.name.to_string());
+                                    }
+                                }
+                                Name::Local(map) => {
+                                    for indirect in map {
+                                        let indirect = indirect.unwrap();
+                                        for naming in indirect.names {
+                                            let naming = naming.unwrap();
+                                            local_names.insert(
+                                                (indirect.index, naming.index),
+                                                naming.name.to_string(),
+                                            );
+                                        }
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ if section.name() == "sourceMappingURL" => {
+                        let mut reader = wasmparser::BinaryReader::new(section.data(), 0);
+                        map_url = Some(reader.read_string().unwrap().to_string());
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        assert_eq!(function_names[&0], "double");
+        assert_eq!(function_names[&1], "__alloc");
+        assert_eq!(local_names[&(0, 0)], "n");
+        assert_eq!(local_names[&(0, 1)], "twice");
+        assert_eq!(map_url.as_deref(), Some("double.wasm.map"));
+
+        // Each statement maps to the first instruction it compiled to
+        let map = compiler.source_map().unwrap();
+        assert!(map.contains("\"sources\":[\"double.woke\"]"));
+        let mappings = map.split("\"mappings\":\"").nth(1).unwrap().trim_end_matches("\"}");
+        let decoded = sourcemap::decode_mappings(mappings);
+        let lines: Vec<u32> = decoded.iter().map(|&(_, line, _)| line).collect();
+        assert_eq!(lines, [1, 2]);
+        for (offset, _, column) in decoded {
+            assert!(instruction_offsets.contains(&offset), "offset {} is not an instruction", offset);
+            assert_eq!(column, 4);
+        }
+    }
+
+    #[test]
+    fn test_run_higher_order_functions() {
+        let source = r#"
+            to double(n: Int) -> Int {
+                give back n * 2;
+            }
+
+            to apply(f: (Int) -> Int, x: Int) -> Int {
+                give back f(x);
+            }
+
+            to compose(f: (Int) -> Int, g: (Int) -> Int) -> (Int) -> Int {
+                give back |x: Int| -> g(f(x));
+            }
+
+            to main() -> Int {
+                remember offset = 100;
+                remember add_offset = |x: Int| -> x + offset;
+                remember both = compose(double, add_offset);
+                give back apply(double, 5) + apply(|x: Int| -> x - 1, 5) * 10 + both(1) * 1000;
+            }
+        "#;
+        let (mut store, instance) = instantiate(&compile(source).unwrap());
+        let main = instance.get_typed_func::<(), i64>(&store, "main").unwrap();
+        assert_eq!(main.call(&mut store, ()).unwrap(), 10 + 40 + 102_000);
+    }
+
+    #[test]
+    fn test_run_closures_capture_by_value() {
+        let source = r#"
+            to make_greeter(greeting: String, scale: Float) -> (String) -> String {
+                give back |name: String|: String {
+                    remember scaled = scale * 2.0;
+                    when scaled > 2.0 {
+                        give back greeting + ", " + name + "!!";
+                    }
+                    give back greeting + ", " + name;
+                };
+            }
+
+            to main() {
+                remember counter = 1;
+                remember read = || -> counter;
+                counter = 2;
+                print(read());
+                print(make_greeter("Hi", 1.0)("Ada"));
+                print(make_greeter("Hey", 1.5)("Bob"));
+            }
+        "#;
+        let (mut store, instance) = instantiate(&compile(source).unwrap());
+        let main = instance.get_typed_func::<(), i64>(&store, "main").unwrap();
+        main.call(&mut store, ()).unwrap();
+        assert_eq!(store.data(), &["1", "Hi, Ada", "Hey, Bob!!"]);
+    }
+
+    #[test]
+    fn test_closure_type_errors() {
+        let wrong_arity = "to main() -> Int { remember f = |x: Int| -> x; give back f(1, 2); }";
+        assert!(matches!(compile(wrong_arity), Err(CompileError::TypeError(_))));
+
+        let not_callable = "to main() -> Int { remember f = 1; give back f(1); }";
+        assert!(matches!(compile(not_callable), Err(CompileError::TypeError(_))));
+
+        let arithmetic = "to main() -> Int { remember f = || -> 1; give back f + 1; }";
+        assert!(matches!(compile(arithmetic), Err(CompileError::TypeError(_))));
+    }
+
     #[test]
     fn test_compile_loop() {
         let source = r#"
@@ -0,0 +1,267 @@
+//! Code Coverage for WokeLang
+//!
+//! A [`Coverage`] recorder is given to the interpreter like a debugger, so
+//! it sees every statement before it runs. Afterwards it reports, for each
+//! file of the program, how often each line with a statement on it ran.
+//! `woke test --coverage` shows the report, and `--lcov` writes it in the
+//! LCOV format CI coverage dashboards read.
+//!
+//! Statements are told apart by the function they are in, so that those of
+//! modules merged into the program are counted against the module's file.
+
+use crate::ast::{Expr, LambdaBody, Program, Span, Spanned, Statement, TopLevelItem};
+use crate::interpreter::{DebugAction, DebugView, Debugger};
+use crate::testing::line_col;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Records which statements run; clones share what they record
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    /// How often each statement ran, by the function it is in (empty at
+    /// the top level) and where it starts
+    hits: Rc<RefCell<HashMap<(String, usize), u64>>>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lines covered in each file of `program`, which was read from `file`.
+    /// `origin` names the file a function merged in from a module is from.
+    pub fn report<'a>(
+        &self,
+        program: &Program,
+        file: &Path,
+        origin: impl Fn(&str) -> Option<&'a Path>,
+    ) -> CoverageReport {
+        let hits = self.hits.borrow();
+        let mut report = CoverageReport::default();
+        let mut sources: HashMap<PathBuf, Option<String>> = HashMap::new();
+        let mut count = |function: &str, path: &Path, stmts: &[Statement]| {
+            let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            let source = sources.entry(path.clone()).or_insert_with(|| fs::read_to_string(&path).ok());
+            let Some(source) = source else {
+                return;
+            };
+            let lines = &mut report.files.entry(path).or_default().lines;
+            for start in statement_starts(stmts) {
+                let (line, _) = line_col(source, start);
+                let ran = hits.get(&(function.to_string(), start)).copied().unwrap_or(0);
+                let entry = lines.entry(line).or_insert(0);
+                *entry = (*entry).max(ran);
+            }
+        };
+
+        for item in &program.items {
+            match item {
+                TopLevelItem::Function(f) => count(&f.name, origin(&f.name).unwrap_or(file), &f.body),
+                TopLevelItem::Expect(block) => count("", file, &block.body),
+                TopLevelItem::ConsentBlock(block) => count("", file, &block.body),
+                _ => {}
+            }
+        }
+        report
+    }
+}
+
+impl Debugger for Coverage {
+    fn before_statement(&mut self, span: &Span, view: &DebugView<'_>) -> DebugAction {
+        let function = view.function().unwrap_or_default().to_string();
+        *self.hits.borrow_mut().entry((function, span.start)).or_insert(0) += 1;
+        DebugAction::Continue
+    }
+}
+
+/// Where every statement in `stmts` starts, nested ones included
+fn statement_starts(stmts: &[Statement]) -> Vec<usize> {
+    fn visit_stmts(stmts: &[Statement], starts: &mut Vec<usize>) {
+        for stmt in stmts {
+            visit_stmt(stmt, starts);
+        }
+    }
+
+    fn visit_stmt(stmt: &Statement, starts: &mut Vec<usize>) {
+        starts.push(stmt.span().start);
+        match stmt {
+            Statement::VarDecl(d) => visit_expr(&d.value, starts),
+            Statement::Assignment(a) => visit_expr(&a.value, starts),
+            Statement::Return(r) => visit_expr(&r.value, starts),
+            Statement::Conditional(c) => {
+                visit_expr(&c.condition, starts);
+                visit_stmts(&c.then_branch, starts);
+                if let Some(else_branch) = &c.else_branch {
+                    visit_stmts(else_branch, starts);
+                }
+            }
+            Statement::Loop(l) => {
+                visit_expr(&l.count, starts);
+                visit_stmts(&l.body, starts);
+            }
+            Statement::AttemptBlock(a) => visit_stmts(&a.body, starts),
+            Statement::ConsentBlock(c) => visit_stmts(&c.body, starts),
+            Statement::Expression(e) => visit_expr(e, starts),
+            Statement::EmoteAnnotated(e) => visit_stmt(&e.statement, starts),
+            Statement::Decide(d) => {
+                visit_expr(&d.scrutinee, starts);
+                for arm in &d.arms {
+                    visit_stmts(&arm.body, starts);
+                }
+            }
+            Statement::WorkerSpawn(_) | Statement::Complain(_) => {}
+        }
+    }
+
+    // Statements inside lambda blocks
+    fn visit_expr(expr: &Spanned<Expr>, starts: &mut Vec<usize>) {
+        match &expr.node {
+            Expr::Call(_, args) => args.iter().for_each(|a| visit_expr(a, starts)),
+            Expr::CallExpr(callee, args) => {
+                visit_expr(callee, starts);
+                args.iter().for_each(|a| visit_expr(a, starts));
+            }
+            Expr::Binary(_, left, right) | Expr::Index(left, right) => {
+                visit_expr(left, starts);
+                visit_expr(right, starts);
+            }
+            Expr::Unary(_, e) | Expr::UnitMeasurement(e, _) | Expr::Okay(e) | Expr::Oops(e) | Expr::Unwrap(e) => {
+                visit_expr(e, starts)
+            }
+            Expr::Array(items) => items.iter().for_each(|i| visit_expr(i, starts)),
+            Expr::Lambda(lambda) => match &lambda.body {
+                LambdaBody::Expr(e) => visit_expr(e, starts),
+                LambdaBody::Block(stmts) => visit_stmts(stmts, starts),
+            },
+            Expr::Literal(_) | Expr::Identifier(_) | Expr::GratitudeLiteral(_) => {}
+        }
+    }
+
+    let mut starts = Vec::new();
+    visit_stmts(stmts, &mut starts);
+    starts
+}
+
+/// How often the lines of one file ran
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileCoverage {
+    /// Runs of each line with a statement on it, by 1-based line number
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl FileCoverage {
+    /// Lines with a statement on them
+    pub fn found(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Lines that ran
+    pub fn hit(&self) -> usize {
+        self.lines.values().filter(|&&runs| runs > 0).count()
+    }
+
+    /// Lines with a statement on them that never ran
+    pub fn missed(&self) -> Vec<usize> {
+        self.lines.iter().filter(|(_, &runs)| runs == 0).map(|(&line, _)| line).collect()
+    }
+}
+
+/// Line coverage of the files of one or more programs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CoverageReport {
+    /// By canonical path
+    pub files: BTreeMap<PathBuf, FileCoverage>,
+}
+
+impl CoverageReport {
+    /// Add the runs in `other`, e.g. of another test file using the same
+    /// modules
+    pub fn merge(&mut self, other: CoverageReport) {
+        for (path, file) in other.files {
+            let lines = &mut self.files.entry(path).or_default().lines;
+            for (line, runs) in file.lines {
+                *lines.entry(line).or_insert(0) += runs;
+            }
+        }
+    }
+
+    /// Lines with statements and lines that ran, over every file
+    pub fn totals(&self) -> (usize, usize) {
+        self.files.values().fold((0, 0), |(found, hit), f| (found + f.found(), hit + f.hit()))
+    }
+
+    /// The report in the LCOV tracefile format
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for (path, file) in &self.files {
+            let _ = writeln!(lcov, "TN:\nSF:{}", path.display());
+            for (line, runs) in &file.lines {
+                let _ = writeln!(lcov, "DA:{},{}", line, runs);
+            }
+            let _ = writeln!(lcov, "LF:{}\nLH:{}\nend_of_record", file.found(), file.hit());
+        }
+        lcov
+    }
+}
+
+/// `hit` of `found` as a percentage, 100 when there is nothing to cover
+pub fn percent(hit: usize, found: usize) -> f64 {
+    if found == 0 {
+        100.0
+    } else {
+        hit as f64 * 100.0 / found as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::project::ModuleLoader;
+    use crate::testing::TestRunner;
+
+    #[test]
+    fn test_coverage() {
+        let dir = std::env::temp_dir().join("wokelang_test_coverage");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let module = "to sign(n: Int) -> Int {\n    when n < 0 {\n        give back -1;\n    }\n    give back 1;\n}\n";
+        fs::write(dir.join("numbers.woke"), module).unwrap();
+        let source = "use std.test;\nuse numbers;\n\n\
+                      to test_sign() {\n    test.assertEqual(numbers.sign(5), 1);\n}\n\n\
+                      expect \"twice\" {\n    repeat 2 times {\n        numbers.sign(1);\n    }\n}\n";
+        let file = dir.join("numbers_test.woke");
+        fs::write(&file, source).unwrap();
+
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let mut loader = ModuleLoader::new();
+        let program = loader.load(&file, Parser::new(tokens, source).parse().unwrap()).unwrap();
+        let coverage = Coverage::new();
+        let results = TestRunner::new().with_coverage(&coverage).run(&program);
+        assert!(results.iter().all(|r| r.passed()));
+
+        let report = coverage.report(&program, &file, |f| loader.origin(f));
+        let numbers = &report.files[&fs::canonicalize(dir.join("numbers.woke")).unwrap()];
+        // The `when` ran three times, its `give back -1` never
+        assert_eq!(numbers.lines, BTreeMap::from([(2, 3), (3, 0), (5, 3)]));
+        assert_eq!(numbers.missed(), [3]);
+        let tests = &report.files[&fs::canonicalize(&file).unwrap()];
+        assert_eq!(tests.lines, BTreeMap::from([(5, 1), (9, 1), (10, 2)]));
+        assert_eq!(report.totals(), (6, 5));
+
+        let mut merged = report.clone();
+        merged.merge(report.clone());
+        assert_eq!(merged.files[&fs::canonicalize(&file).unwrap()].lines[&10], 4);
+        let lcov = report.to_lcov();
+        assert!(lcov.contains("DA:3,0\nDA:5,3\nLF:3\nLH:2\nend_of_record\n"), "{}", lcov);
+        assert_eq!(lcov.matches("SF:").count(), 2);
+        assert_eq!(percent(5, 6).round(), 83.0);
+        assert_eq!(percent(0, 0), 100.0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,729 @@
+//! Debug Adapter Protocol Server for WokeLang
+//!
+//! `woke debug` speaks the Debug Adapter Protocol on stdin and stdout, so
+//! editors such as VS Code can debug `.woke` programs: launch one, stop at
+//! breakpoints by line, step over, into and out of functions, and look at
+//! the variables of every call on the stack. Program output is sent to the
+//! editor as `output` events.
+//!
+//! Consent blocks and superpower requests cannot prompt on stdin, which
+//! carries the protocol, so the program stops with the reason `consent`
+//! and the question is shown in the debug console, where typing `yes` or
+//! `no` answers it.
+
+use crate::interpreter::{DebugAction, DebugView, Debugger, Interpreter, RuntimeError, Value};
+use crate::pipeline::Pipeline;
+use crate::security::{CapabilityRegistry, Policy};
+use crate::testing::line_col;
+use serde_json::{json, Value as Json};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// The only thread a WokeLang program has
+const THREAD_ID: i64 = 1;
+
+/// Read one protocol message: headers, a blank line, then a JSON body of
+/// the `Content-Length` the headers give. `None` at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>().map_err(|e| invalid(e.to_string()))?);
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| invalid(e.to_string()))
+}
+
+/// Write one protocol message
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Serves one debugging session
+#[derive(Debug, Clone, Default)]
+pub struct DebugAdapter {
+    policy: Policy,
+}
+
+impl DebugAdapter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let the program use what `policy` allows without asking
+    pub fn with_policy(mut self, policy: &Policy) -> Self {
+        self.policy = policy.clone();
+        self
+    }
+
+    /// Answer requests from `input` on `output` until the client
+    /// disconnects or the input ends
+    pub fn serve(&self, input: impl BufRead + Send + 'static, output: impl Write + 'static) -> io::Result<()> {
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let mut input = input;
+            while let Ok(Some(message)) = read_message(&mut input) {
+                if message["type"] == "request" && sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut session = Session::new(Box::new(output), requests);
+        let Some(launch) = session.configure()? else {
+            return Ok(());
+        };
+        let session = Rc::new(RefCell::new(session));
+        let exit_code = self.run(&session, &launch);
+
+        let mut session = session.borrow_mut();
+        session.event("exited", json!({ "exitCode": exit_code }))?;
+        session.event("terminated", json!({}))?;
+        while !session.stop_requested {
+            let Some(request) = session.recv() else {
+                break;
+            };
+            if !session.handle_common(&request)? {
+                session.fail(&request, "The program has finished")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the launched program under the debugger, returning its exit code
+    fn run(&self, session: &Rc<RefCell<Session>>, launch: &Launch) -> i32 {
+        let source = match fs::read_to_string(&launch.program) {
+            Ok(source) => source,
+            Err(e) => {
+                let message = format!("Could not read {}: {}\n", launch.program.display(), e);
+                session.borrow_mut().output("stderr", &message);
+                return 4;
+            }
+        };
+        let pipeline = Pipeline::new().file_name(&launch.program.display().to_string());
+        let program = pipeline.parse(&source).and_then(|program| {
+            let program = pipeline.load(&launch.program, program)?;
+            pipeline.check(&program)?;
+            Ok(program)
+        });
+        let program = match program {
+            Ok(program) => program,
+            Err(e) => {
+                session.borrow_mut().output("stderr", &format!("{}\n", e));
+                return 2;
+            }
+        };
+        session.borrow_mut().source = source;
+        crate::stdlib::env::set_script_args(launch.args.clone());
+
+        let mut capabilities = CapabilityRegistry::new();
+        capabilities.apply_policy(&self.policy);
+        let asker = session.clone();
+        capabilities.set_prompt_handler(move |question| asker.borrow_mut().ask(question));
+        let asker = session.clone();
+        let module_name = launch
+            .program
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "main".to_string());
+        let mut interpreter = Interpreter::new()
+            .with_output(ConsoleOutput(session.clone()))
+            .with_module_name(&module_name)
+            .with_capabilities(capabilities)
+            .with_consent_handler(move |permission| asker.borrow_mut().ask(&format!("Allow '{}'?", permission)))
+            .with_debugger(SessionDebugger(session.clone()));
+
+        match interpreter.run_main(&program) {
+            Ok(value) => i32::from(value.exit_status()),
+            Err(RuntimeError::Stopped) => 0,
+            Err(e) => {
+                session.borrow_mut().output("stderr", &format!("Runtime error [{}]: {}\n", e.code(), e));
+                3
+            }
+        }
+    }
+}
+
+/// What the client asked to debug
+#[derive(Debug, Clone)]
+struct Launch {
+    program: PathBuf,
+    args: Vec<String>,
+    stop_on_entry: bool,
+}
+
+/// When the running program should next stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Only at breakpoints
+    Run,
+    /// At the next statement, for this reason
+    Stop(&'static str),
+    /// At the next statement no deeper than this many calls
+    StepOver(usize),
+    /// At the next statement shallower than this many calls
+    StepOut(usize),
+}
+
+/// Something the client can expand in the variables view
+#[derive(Debug, Clone)]
+enum Handle {
+    Locals(usize),
+    Globals,
+    Value(Value),
+}
+
+struct Session {
+    output: Box<dyn Write>,
+    seq: i64,
+    requests: Receiver<Json>,
+    /// Requests that arrived while the program ran, kept for when it stops
+    deferred: VecDeque<Json>,
+    /// The launched program and its source, to map spans to lines
+    program: Option<PathBuf>,
+    source: String,
+    /// Breakpoint lines by source path
+    breakpoints: HashMap<PathBuf, HashSet<usize>>,
+    mode: Mode,
+    /// Line of the statement that ran last, so a line with several
+    /// statements stops only once
+    last_line: usize,
+    /// Variable references handed out since the program last stopped
+    handles: Vec<Handle>,
+    /// The client disconnected, so the program should end
+    stop_requested: bool,
+}
+
+impl Session {
+    fn new(output: Box<dyn Write>, requests: Receiver<Json>) -> Self {
+        Self {
+            output,
+            seq: 0,
+            requests,
+            deferred: VecDeque::new(),
+            program: None,
+            source: String::new(),
+            breakpoints: HashMap::new(),
+            mode: Mode::Run,
+            last_line: 0,
+            handles: Vec::new(),
+            stop_requested: false,
+        }
+    }
+
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    /// Show text in the client's debug console; failures to send it are
+    /// noticed when the next request cannot be answered
+    fn output(&mut self, category: &str, text: &str) {
+        let _ = self.event("output", json!({ "category": category, "output": text }));
+    }
+
+    /// The next request, waiting for it; `None` once the client is gone
+    fn recv(&mut self) -> Option<Json> {
+        if let Some(request) = self.deferred.pop_front() {
+            return Some(request);
+        }
+        let request = self.requests.recv().ok();
+        if request.is_none() {
+            self.stop_requested = true;
+        }
+        request
+    }
+
+    /// Handle requests up to `configurationDone`, returning what to launch
+    fn configure(&mut self) -> io::Result<Option<Launch>> {
+        let mut launch = None;
+        let mut configured = false;
+        while launch.is_none() || !configured {
+            let Some(request) = self.recv() else {
+                return Ok(None);
+            };
+            match request["command"].as_str().unwrap_or_default() {
+                "initialize" => {
+                    self.respond(
+                        &request,
+                        json!({
+                            "supportsConfigurationDoneRequest": true,
+                            "supportsTerminateRequest": true,
+                        }),
+                    )?;
+                    self.event("initialized", json!({}))?;
+                }
+                "launch" => {
+                    let arguments = &request["arguments"];
+                    let Some(program) = arguments["program"].as_str() else {
+                        self.fail(&request, "launch needs the `program` to debug")?;
+                        continue;
+                    };
+                    let args = arguments["args"]
+                        .as_array()
+                        .map(|args| args.iter().filter_map(|a| a.as_str().map(str::to_string)).collect())
+                        .unwrap_or_default();
+                    self.program = Some(canonical(Path::new(program)));
+                    launch = Some(Launch {
+                        program: PathBuf::from(program),
+                        args,
+                        stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+                    });
+                    self.respond(&request, json!({}))?;
+                }
+                "configurationDone" => {
+                    configured = true;
+                    self.respond(&request, json!({}))?;
+                }
+                _ => {
+                    if !self.handle_common(&request)? {
+                        self.fail(&request, "Launch a program first")?;
+                    }
+                    if self.stop_requested {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+        if launch.as_ref().is_some_and(|l| l.stop_on_entry) {
+            self.mode = Mode::Stop("entry");
+        }
+        Ok(launch)
+    }
+
+    /// Answer the requests that mean the same whether the program is
+    /// running, stopped or finished; false for any other request
+    fn handle_common(&mut self, request: &Json) -> io::Result<bool> {
+        match request["command"].as_str().unwrap_or_default() {
+            "threads" => self.respond(request, json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }))?,
+            "setBreakpoints" => {
+                let arguments = &request["arguments"];
+                let path = canonical(Path::new(arguments["source"]["path"].as_str().unwrap_or_default()));
+                let lines: Vec<usize> = arguments["breakpoints"]
+                    .as_array()
+                    .map(|bps| bps.iter().filter_map(|bp| bp["line"].as_u64()).map(|l| l as usize).collect())
+                    .unwrap_or_default();
+                let verified: Vec<Json> = lines.iter().map(|line| json!({ "verified": true, "line": line })).collect();
+                self.breakpoints.insert(path, lines.into_iter().collect());
+                self.respond(request, json!({ "breakpoints": verified }))?;
+            }
+            "setExceptionBreakpoints" => self.respond(request, json!({ "breakpoints": [] }))?,
+            "disconnect" | "terminate" => {
+                self.stop_requested = true;
+                self.respond(request, json!({}))?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Whether a breakpoint is set on a line of the program
+    fn has_breakpoint(&self, line: usize) -> bool {
+        self.program
+            .as_ref()
+            .and_then(|program| self.breakpoints.get(program))
+            .is_some_and(|lines| lines.contains(&line))
+    }
+
+    /// Decide before a statement whether to stop there, and if so wait
+    /// for the client to resume
+    fn before_statement(&mut self, line: usize, view: &DebugView<'_>) -> io::Result<DebugAction> {
+        // Requests that arrive while the program runs; once one has to wait
+        // for the program to stop, the rest wait behind it to keep them in order
+        loop {
+            match self.requests.try_recv() {
+                Ok(request) => {
+                    if !self.deferred.is_empty() {
+                        self.deferred.push_back(request);
+                    } else if request["command"] == "pause" {
+                        self.mode = Mode::Stop("pause");
+                        self.respond(&request, json!({}))?;
+                    } else if !self.handle_common(&request)? {
+                        self.deferred.push_back(request);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.stop_requested = self.deferred.is_empty();
+                    break;
+                }
+            }
+        }
+        if self.stop_requested {
+            return Ok(DebugAction::Stop);
+        }
+
+        let depth = view.depth();
+        let new_line = line != self.last_line;
+        self.last_line = line;
+        let reason = match self.mode {
+            _ if new_line && self.has_breakpoint(line) => "breakpoint",
+            Mode::Stop(reason) => reason,
+            Mode::StepOver(from) if depth <= from => "step",
+            Mode::StepOut(from) if depth < from => "step",
+            _ => return Ok(DebugAction::Continue),
+        };
+        self.mode = Mode::Run;
+        self.handles.clear();
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )?;
+
+        loop {
+            let Some(request) = self.recv() else {
+                return Ok(DebugAction::Stop);
+            };
+            let command = request["command"].as_str().unwrap_or_default();
+            let resume = match command {
+                "continue" => Some(Mode::Run),
+                "next" => Some(Mode::StepOver(depth)),
+                "stepIn" => Some(Mode::Stop("step")),
+                "stepOut" => Some(Mode::StepOut(depth)),
+                _ => None,
+            };
+            if let Some(mode) = resume {
+                self.mode = mode;
+                let body = if command == "continue" { json!({ "allThreadsContinued": true }) } else { json!({}) };
+                self.respond(&request, body)?;
+                return Ok(DebugAction::Continue);
+            }
+            match command {
+                "stackTrace" => self.stack_trace(&request, Some(view))?,
+                "scopes" => {
+                    let frame = request["arguments"]["frameId"].as_u64().unwrap_or(1).saturating_sub(1) as usize;
+                    let locals = self.handle(Handle::Locals(frame));
+                    let globals = self.handle(Handle::Globals);
+                    self.respond(
+                        &request,
+                        json!({ "scopes": [
+                            { "name": "Locals", "variablesReference": locals, "expensive": false },
+                            { "name": "Globals", "variablesReference": globals, "expensive": false },
+                        ]}),
+                    )?;
+                }
+                "variables" => {
+                    let reference = request["arguments"]["variablesReference"].as_u64().unwrap_or(0) as usize;
+                    let variables = match self.handles.get(reference.wrapping_sub(1)).cloned() {
+                        Some(Handle::Locals(frame)) => view.locals(frame),
+                        Some(Handle::Globals) => view.globals(),
+                        Some(Handle::Value(value)) => children(&value),
+                        None => Vec::new(),
+                    };
+                    let variables: Vec<Json> =
+                        variables.into_iter().map(|(name, value)| self.variable(&name, value)).collect();
+                    self.respond(&request, json!({ "variables": variables }))?;
+                }
+                "evaluate" => {
+                    let expression = request["arguments"]["expression"].as_str().unwrap_or_default().trim();
+                    match view.lookup(expression).cloned() {
+                        Some(value) => {
+                            let mut variable = self.variable(expression, value);
+                            variable["result"] = variable["value"].take();
+                            self.respond(&request, variable)?;
+                        }
+                        None => self.fail(&request, "Only variable names can be evaluated")?,
+                    }
+                }
+                "pause" => self.respond(&request, json!({}))?,
+                _ => {
+                    if !self.handle_common(&request)? {
+                        self.fail(&request, &format!("Unsupported request: {}", command))?;
+                    }
+                    if self.stop_requested {
+                        return Ok(DebugAction::Stop);
+                    }
+                }
+            }
+        }
+    }
+
+    fn stack_trace(&mut self, request: &Json, view: Option<&DebugView<'_>>) -> io::Result<()> {
+        let source = self.program.as_ref().map(|path| {
+            json!({
+                "name": path.file_name().map(|n| n.to_string_lossy().into_owned()),
+                "path": path,
+            })
+        });
+        let frames: Vec<Json> = view
+            .map(|view| view.stack())
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let (line, column) = line_col(&self.source, frame.span.start);
+                json!({ "id": i + 1, "name": frame.function, "source": source, "line": line, "column": column })
+            })
+            .collect();
+        self.respond(request, json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn handle(&mut self, handle: Handle) -> usize {
+        self.handles.push(handle);
+        self.handles.len()
+    }
+
+    /// A variable as the client shows it, with secrets masked
+    fn variable(&mut self, name: &str, value: Value) -> Json {
+        let shown = match &value {
+            Value::String(s) => format!("{:?}", s),
+            other => other.redacted().to_string(),
+        };
+        let kind = type_name(&value);
+        let reference = if children(&value).is_empty() { 0 } else { self.handle(Handle::Value(value)) };
+        json!({
+            "name": name,
+            "value": shown,
+            "type": kind,
+            "variablesReference": reference,
+        })
+    }
+
+    /// Ask the client's user a yes-or-no question through the debug console
+    fn ask(&mut self, question: &str) -> bool {
+        self.output("console", &format!("🔐 {}\n   Type yes or no in the debug console.\n", question));
+        let _ = self.event(
+            "stopped",
+            json!({
+                "reason": "consent",
+                "description": "Waiting for consent",
+                "text": question,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        );
+        loop {
+            let Some(request) = self.recv() else {
+                return false;
+            };
+            let answered = match request["command"].as_str().unwrap_or_default() {
+                "evaluate" => {
+                    match request["arguments"]["expression"].as_str().unwrap_or_default().trim() {
+                        "yes" | "y" => Some(true),
+                        "no" | "n" => Some(false),
+                        _ => {
+                            let _ = self.fail(&request, "Type yes or no to answer the consent request");
+                            None
+                        }
+                    }
+                }
+                // Carrying on without an answer is saying no
+                "continue" | "next" | "stepIn" | "stepOut" => Some(false),
+                "stackTrace" => {
+                    let _ = self.stack_trace(&request, None);
+                    None
+                }
+                _ => {
+                    if !self.handle_common(&request).unwrap_or(false) {
+                        let _ = self.fail(&request, "Answer the consent request first");
+                    }
+                    if self.stop_requested {
+                        return false;
+                    }
+                    None
+                }
+            };
+            if let Some(granted) = answered {
+                let result = if granted { "Granted" } else { "Denied" };
+                let _ = self.respond(&request, json!({ "result": result, "variablesReference": 0 }));
+                let _ = self.event("continued", json!({ "threadId": THREAD_ID, "allThreadsContinued": true }));
+                return granted;
+            }
+        }
+    }
+}
+
+/// Sends program output to the client's debug console
+struct ConsoleOutput(Rc<RefCell<Session>>);
+
+impl Write for ConsoleOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().output("stdout", &String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Lets the session stop the interpreter
+struct SessionDebugger(Rc<RefCell<Session>>);
+
+impl Debugger for SessionDebugger {
+    fn before_statement(&mut self, span: &crate::ast::Span, view: &DebugView<'_>) -> DebugAction {
+        let mut session = self.0.borrow_mut();
+        let (line, _) = line_col(&session.source, span.start);
+        // A client that cannot be written to is gone
+        session.before_statement(line, view).unwrap_or(DebugAction::Stop)
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The parts of a value the client can expand it into
+fn children(value: &Value) -> Vec<(String, Value)> {
+    match value {
+        Value::Array(items) => items.iter().enumerate().map(|(i, v)| (format!("[{}]", i), v.clone())).collect(),
+        Value::Record(fields) => {
+            let mut fields: Vec<(String, Value)> = fields.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            fields
+        }
+        Value::Okay(inner) => vec![("value".to_string(), (**inner).clone())],
+        _ => Vec::new(),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Int(_) => "Int",
+        Value::Float(_) => "Float",
+        Value::Decimal(_) => "Decimal",
+        Value::String(_) => "String",
+        Value::Bytes(_) => "Bytes",
+        Value::DateTime(_) => "DateTime",
+        Value::Duration(_) => "Duration",
+        Value::Bool(_) => "Bool",
+        Value::Array(_) => "Array",
+        Value::Record(_) => "Record",
+        Value::Unit => "Unit",
+        Value::Okay(_) | Value::Oops(_) => "Result",
+        Value::Function(_) | Value::VmFunction(_) => "Function",
+        Value::Channel(_) => "Channel",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::OutputBuffer;
+    use std::io::Cursor;
+
+    fn request(seq: i64, command: &str, arguments: Json) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let message = json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments });
+        write_message(&mut bytes, &message).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_debug_session() {
+        let dir = std::env::temp_dir().join("wokelang_test_dap");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("main.woke");
+        fs::write(
+            &program,
+            "to square(n: Int) -> Int {\n    remember result = n * n;\n    give back result;\n}\n\n\
+             to main() {\n    only if okay \"camera\" {\n        print(\"click\");\n    }\n    \
+             print(square(5));\n    print(\"done\");\n}\n",
+        )
+        .unwrap();
+        let path = program.display().to_string();
+
+        let input: Vec<u8> = [
+            request(1, "initialize", json!({ "adapterID": "woke" })),
+            request(2, "launch", json!({ "program": path })),
+            request(3, "setBreakpoints", json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }] })),
+            request(4, "configurationDone", json!({})),
+            // Answers the consent block
+            request(5, "evaluate", json!({ "expression": "yes", "context": "repl" })),
+            // At the breakpoint in square
+            request(6, "stackTrace", json!({ "threadId": 1 })),
+            request(7, "scopes", json!({ "frameId": 1 })),
+            request(8, "variables", json!({ "variablesReference": 1 })),
+            request(9, "stepOut", json!({ "threadId": 1 })),
+            request(10, "continue", json!({ "threadId": 1 })),
+            request(11, "disconnect", json!({})),
+        ]
+        .concat();
+        let output = OutputBuffer::new();
+        DebugAdapter::new().serve(Cursor::new(input), output.clone()).unwrap();
+
+        let written = output.contents();
+        let mut reader = Cursor::new(written.as_bytes());
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+        let find = |kind: &str, name: &str| {
+            messages
+                .iter()
+                .filter(|m| m["type"] == kind && (m["command"] == name || m["event"] == name))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let stops: Vec<Json> = find("event", "stopped").iter().map(|m| m["body"]["reason"].clone()).collect();
+        assert_eq!(stops, ["consent", "breakpoint", "step"]);
+        let frames = &find("response", "stackTrace")[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "square");
+        assert_eq!(frames[0]["line"], 3);
+        assert_eq!(frames[1]["name"], "main");
+        let variables = &find("response", "variables")[0]["body"]["variables"];
+        assert_eq!(variables[0]["name"], "n");
+        assert_eq!(variables[0]["value"], "5");
+        assert_eq!(variables[1]["name"], "result");
+        assert_eq!(variables[1]["value"], "25");
+
+        let printed: String = find("event", "output")
+            .iter()
+            .filter(|m| m["body"]["category"] == "stdout")
+            .map(|m| m["body"]["output"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(printed, "click\n25\ndone\n");
+        assert_eq!(find("event", "exited")[0]["body"]["exitCode"], 0);
+        assert_eq!(find("event", "terminated").len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
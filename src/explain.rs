@@ -0,0 +1,208 @@
+//! Error Code Catalog for WokeLang
+//!
+//! Every lexer, parser, type and runtime error has a stable code such as
+//! `WOKE-E0303`, which diagnostics show, and so does every lint warning,
+//! such as `WOKE-W0001`. The catalog of what each code means, with
+//! examples and fixes, is kept as Markdown in `explain/catalog.md` and
+//! built into the crate; `woke explain` prints its entries.
+
+/// The catalog: a `## WOKE-Exxxx: Title` heading per code, then its text;
+/// warnings' codes start with W instead of E
+const CATALOG: &str = include_str!("explain/catalog.md");
+
+/// Prefix of every error code
+pub const CODE_PREFIX: &str = "WOKE-";
+
+/// The catalog's entry for one error code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explanation {
+    /// The full code, e.g. `WOKE-E0303`
+    pub code: &'static str,
+    /// A short name for the error
+    pub title: &'static str,
+    /// What causes the error and how to fix it, as Markdown
+    pub text: &'static str,
+}
+
+/// Every entry in the catalog, in code order
+pub fn catalog() -> Vec<Explanation> {
+    let mut entries = Vec::new();
+    for section in CATALOG.split("\n## ").skip(1) {
+        let (heading, text) = section.split_once('\n').unwrap_or((section, ""));
+        let Some((code, title)) = heading.split_once(": ") else {
+            continue;
+        };
+        entries.push(Explanation {
+            code: code.trim(),
+            title: title.trim(),
+            text: text.trim(),
+        });
+    }
+    entries
+}
+
+/// The entry for a code, given in full (`WOKE-E0303`) or without the
+/// prefix (`E0303`), in any case
+pub fn lookup(code: &str) -> Option<Explanation> {
+    let code = code.trim().to_ascii_uppercase();
+    let code = code.strip_prefix(CODE_PREFIX).unwrap_or(&code);
+    catalog()
+        .into_iter()
+        .find(|entry| entry.code.strip_prefix(CODE_PREFIX) == Some(code))
+}
+
+/// The line diagnostics end with, pointing to the catalog
+pub fn hint(code: &str) -> String {
+    let kind = match code.strip_prefix(CODE_PREFIX).unwrap_or(code).starts_with('W') {
+        true => "warning",
+        false => "error",
+    };
+    format!("run `woke explain {}` for more about this {}", code, kind)
+}
+
+/// The candidate a misspelled `name` most likely meant: the closest by
+/// edit distance, if within a third of the name's length
+pub fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(&name.to_lowercase(), &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance: the fewest characters to insert, delete or
+/// replace to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::RuntimeError;
+    use crate::lexer::Lexer;
+    use crate::parser::{ParseError, Parser};
+    use crate::security::LimitExceeded;
+    use crate::typechecker::TypeError;
+    use miette::Diagnostic;
+    use std::time::Duration;
+
+    #[test]
+    fn test_lookup() {
+        let entry = lookup("WOKE-E0303").unwrap();
+        assert_eq!(entry.title, "Division by zero");
+        assert!(entry.text.contains("10 / 0"));
+        assert_eq!(lookup("e0303"), Some(entry));
+        assert_eq!(lookup("E9999"), None);
+        assert_eq!(lookup("WOKE-"), None);
+
+        let codes: Vec<&str> = catalog().iter().map(|e| e.code).collect();
+        let mut sorted = codes.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(codes, sorted, "codes are unique and in order");
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let names = ["print", "println", "count", "std.string.length"];
+        assert_eq!(did_you_mean("printt", names), Some("print"));
+        assert_eq!(did_you_mean("Count", names), Some("count"));
+        assert_eq!(did_you_mean("std.string.lenght", names), Some("std.string.length"));
+        assert_eq!(did_you_mean("print", names), None);
+        assert_eq!(did_you_mean("total", names), None);
+        assert_eq!(did_you_mean("x", names), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_hint() {
+        assert_eq!(hint("WOKE-E0303"), "run `woke explain WOKE-E0303` for more about this error");
+        assert_eq!(hint("WOKE-W0001"), "run `woke explain WOKE-W0001` for more about this warning");
+    }
+
+    #[test]
+    fn test_every_error_is_explained() {
+        let mut codes = Vec::new();
+
+        let lex = || Lexer::new("$").tokenize().unwrap_err();
+        let lexed = lex();
+        assert_eq!(lexed.code(), "WOKE-E0001");
+        assert_eq!(Diagnostic::code(&lexed).unwrap().to_string(), "WOKE-E0001");
+        codes.push(lexed.code());
+
+        let source = "to main() { remember x = 1 }";
+        let parsed = Parser::new(Lexer::new(source).tokenize().unwrap(), source).parse().unwrap_err();
+        assert_eq!(parsed.code(), "WOKE-E0100");
+        let src = crate::lexer::SourceFile::new("");
+        let span = (0, 0).into();
+        for error in [
+            parsed,
+            ParseError::UnexpectedEof,
+            ParseError::General { message: String::new(), src: src.clone(), span },
+            ParseError::TooDeep { limit: 0, src, span },
+        ] {
+            assert_eq!(Diagnostic::code(&error).unwrap().to_string(), error.code());
+            codes.push(error.code());
+        }
+        // A lexer error met while parsing keeps its own code
+        let streamed = ParseError::from(lex());
+        assert_eq!(Diagnostic::code(&streamed).unwrap().to_string(), lexed.code());
+        assert_eq!(streamed.code(), lexed.code());
+
+        let s = String::new;
+        for error in [
+            TypeError::TypeMismatch { expected: s(), actual: s() },
+            TypeError::UndefinedVariable { name: s(), suggestion: None },
+            TypeError::UndefinedFunction { name: s(), suggestion: None },
+            TypeError::InferenceError(s()),
+            TypeError::ArityMismatch { expected: 0, actual: 1 },
+            TypeError::AnnotationRequired(s()),
+            TypeError::CannotIndex(s()),
+            TypeError::NotCallable(s()),
+            TypeError::HostArgument { function: s(), parameter: s(), expected: s(), actual: s(), span: 0..0 },
+        ] {
+            codes.push(error.code());
+        }
+
+        for error in [
+            RuntimeError::UndefinedVariable { name: s(), suggestion: None },
+            RuntimeError::UndefinedFunction { name: s(), suggestion: None },
+            RuntimeError::TypeError(s()),
+            RuntimeError::DivisionByZero,
+            RuntimeError::ConsentDenied(s()),
+            RuntimeError::Complaint(s()),
+            RuntimeError::IndexOutOfBounds(0),
+            RuntimeError::NegativeIndex(-1),
+            RuntimeError::ArityMismatch { expected: 0, got: 1 },
+            RuntimeError::RecursionLimitExceeded,
+            RuntimeError::IoError(s()),
+            RuntimeError::Stdlib { function: s(), message: s() },
+            RuntimeError::Stopped,
+            RuntimeError::LimitExceeded(LimitExceeded::Timeout(Duration::ZERO)),
+            RuntimeError::LimitExceeded(LimitExceeded::Memory(0)),
+            RuntimeError::Exit(0),
+        ] {
+            codes.push(error.code());
+        }
+
+        codes.extend(crate::lint::Lint::ALL.iter().map(|lint| lint.code()));
+
+        let catalog: Vec<&str> = catalog().iter().map(|e| e.code).collect();
+        assert_eq!(codes, catalog, "every error has its own entry");
+    }
+}
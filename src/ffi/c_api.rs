@@ -2,13 +2,21 @@
 //!
 //! This module provides extern "C" functions that can be called from Zig, C,
 //! or any language supporting the C ABI.
+//!
+//! Functions that can fail record why on the calling thread, for
+//! `woke_last_error` and `woke_last_error_info`, and clear it when they
+//! succeed.
 
 use crate::interpreter::{Interpreter, Value};
-use crate::lexer::Lexer;
-use crate::parser::Parser;
-use std::ffi::{CStr, CString};
+use crate::pipeline::{Diagnostics, Pipeline, Stage};
+use crate::security::Capability;
+use crate::stdlib::StdlibError;
+use crate::testing::line_col;
+use std::cell::RefCell;
+use std::ffi::{c_void, CStr, CString};
 use std::os::raw::{c_char, c_double, c_int, c_longlong};
 use std::ptr;
+use std::rc::Rc;
 
 /// Opaque handle to a WokeLang interpreter instance
 pub struct WokeInterpreter {
@@ -16,12 +24,17 @@ pub struct WokeInterpreter {
 }
 
 /// Opaque handle to a WokeLang value
+///
+/// Transparent, so a callback can be handed the interpreter's own values
+/// without copying them.
+#[repr(transparent)]
 pub struct WokeValue {
     inner: Value,
 }
 
 /// Result code for FFI operations
 #[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WokeResult {
     Ok = 0,
     Error = 1,
@@ -32,6 +45,7 @@ pub enum WokeResult {
 
 /// Value type tag for FFI
 #[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WokeValueType {
     Int = 0,
     Float = 1,
@@ -39,6 +53,139 @@ pub enum WokeValueType {
     Bool = 3,
     Array = 4,
     Unit = 5,
+    Okay = 6,
+    Oops = 7,
+    /// Records, bytes, times, functions and channels, which C sees only
+    /// through `woke_value_as_string`
+    Other = 8,
+}
+
+/// What went wrong in the last call that failed on this thread
+///
+/// The strings belong to the library and stay valid until the next call
+/// that fails or succeeds.
+#[repr(C)]
+pub struct WokeErrorInfo {
+    pub kind: WokeResult,
+    /// The error's code in the `woke explain` catalog, e.g. `WOKE-E0303`,
+    /// or null
+    pub code: *const c_char,
+    pub message: *const c_char,
+    /// Where in the source it was found, counting from 1, or 0 if it was
+    /// not found at one place
+    pub line: u32,
+    pub column: u32,
+}
+
+/// A function the host provides, called with the arguments a program gave
+/// it
+///
+/// It can set `*out_result` to a value it creates, which the interpreter
+/// takes ownership of; leaving it null gives back Unit. Returning anything
+/// but `Ok` fails the call, with the message given to `woke_set_error` if
+/// the callback gave one.
+pub type WokeNativeFn = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    args: *const *const WokeValue,
+    arg_count: usize,
+    out_result: *mut *mut WokeValue,
+) -> WokeResult;
+
+/// An error as `woke_last_error_info` reports it
+struct LastError {
+    kind: WokeResult,
+    code: Option<CString>,
+    message: CString,
+    line: u32,
+    column: u32,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
+
+/// Record an error for this thread, giving back its kind
+pub(super) fn fail(kind: WokeResult, code: Option<&str>, message: &str, position: Option<(usize, usize)>) -> WokeResult {
+    let (line, column) = position.unwrap_or((0, 0));
+    let error = LastError {
+        kind,
+        code: code.and_then(|code| CString::new(code).ok()),
+        message: CString::new(message.replace('\0', "")).unwrap_or_default(),
+        line: line as u32,
+        column: column as u32,
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+    kind
+}
+
+pub(super) fn clear_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+/// Take the message of the error recorded for this thread, if any
+pub(super) fn take_error() -> Option<String> {
+    LAST_ERROR
+        .with(|last| last.borrow_mut().take())
+        .map(|error| error.message.to_string_lossy().into_owned())
+}
+
+/// Record what stopped the pipeline, with where it was found in `source`
+fn fail_diagnostics(diagnostics: Diagnostics, source: &str) -> WokeResult {
+    let diagnostic = diagnostics.first();
+    let kind = match diagnostic.stage {
+        Stage::Lex | Stage::Parse => WokeResult::ParseError,
+        _ => WokeResult::RuntimeError,
+    };
+    let position = diagnostic.span.as_ref().map(|span| line_col(source, span.start));
+    fail(kind, diagnostic.code, &diagnostic.to_string(), position)
+}
+
+/// A C string argument as UTF-8, recording an error if it is not
+pub(super) unsafe fn utf8<'a>(s: *const c_char, what: &str) -> Result<&'a str, WokeResult> {
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| fail(WokeResult::Error, None, &format!("The {} is not valid UTF-8", what), None))
+}
+
+/// Run source on an interpreter as the REPL does: its definitions, then
+/// its statements, then the value of its final expression, if any
+fn evaluate(interp: &mut Interpreter, source: &str) -> Result<Value, WokeResult> {
+    Pipeline::new()
+        .evaluate(interp, source)
+        .map_err(|e| fail_diagnostics(e, source))
+}
+
+/// Call a C host function with a program's arguments, taking the value
+/// it gives back
+///
+/// # Safety
+/// `callback` must be safe to call with `user_data` whenever the returned
+/// closure is.
+pub(super) unsafe fn host_function(
+    callback: WokeNativeFn,
+    user_data: *mut c_void,
+) -> impl FnMut(&[Value]) -> Result<Value, StdlibError> {
+    move |args| {
+        let pointers: Vec<*const WokeValue> = args.iter().map(|arg| arg as *const Value as *const WokeValue).collect();
+        let mut result: *mut WokeValue = ptr::null_mut();
+        clear_error();
+        let outcome = unsafe { callback(user_data, pointers.as_ptr(), pointers.len(), &mut result) };
+        let value = match result.is_null() {
+            true => Value::Unit,
+            false => unsafe { Box::from_raw(result) }.inner,
+        };
+        match outcome {
+            WokeResult::Ok => Ok(value),
+            _ => {
+                let message = take_error().unwrap_or_else(|| "The host function failed".to_string());
+                Err(StdlibError::RuntimeError(message))
+            }
+        }
+    }
+}
+
+fn new_value(inner: Value) -> *mut WokeValue {
+    Box::into_raw(Box::new(WokeValue { inner }))
 }
 
 // === Interpreter lifecycle ===
@@ -57,7 +204,8 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
 /// Free a WokeLang interpreter
 ///
 /// # Safety
-/// The pointer must be valid and not null.
+/// The pointer must be null or from `woke_interpreter_new`, and not
+/// freed already.
 #[no_mangle]
 pub unsafe extern "C" fn woke_interpreter_free(interp: *mut WokeInterpreter) {
     if !interp.is_null() {
@@ -67,40 +215,33 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
 
 /// Execute WokeLang source code
 ///
+/// Definitions are kept for later calls, and a `main` the source defines
+/// is run.
+///
 /// # Safety
 /// - `interp` must be a valid pointer from `woke_interpreter_new`
 /// - `source` must be a valid null-terminated C string
 #[no_mangle]
 pub unsafe extern "C" fn woke_exec(interp: *mut WokeInterpreter, source: *const c_char) -> WokeResult {
     if interp.is_null() || source.is_null() {
-        return WokeResult::NullPointer;
+        return fail(WokeResult::NullPointer, None, "woke_exec was given a null pointer", None);
     }
+    clear_error();
 
-    let interp = &mut *interp;
-    let source = match CStr::from_ptr(source).to_str() {
+    let source = match utf8(source, "source") {
         Ok(s) => s,
-        Err(_) => return WokeResult::Error,
+        Err(result) => return result,
     };
-
-    let lexer = Lexer::new(source);
-    let tokens = match lexer.tokenize() {
-        Ok(t) => t,
-        Err(_) => return WokeResult::ParseError,
-    };
-
-    let mut parser = Parser::new(tokens, source);
-    let program = match parser.parse() {
-        Ok(p) => p,
-        Err(_) => return WokeResult::ParseError,
-    };
-
-    match interp.inner.run(&program) {
+    match evaluate(&mut (*interp).inner, source) {
         Ok(_) => WokeResult::Ok,
-        Err(_) => WokeResult::RuntimeError,
+        Err(result) => result,
     }
 }
 
-/// Execute WokeLang source and get return value
+/// Execute WokeLang source and get the value of its final expression
+///
+/// The source runs as with `woke_exec`; it may end in an expression with
+/// no semicolon after it, whose value is given back, or else Unit is.
 ///
 /// # Safety
 /// - All pointers must be valid
@@ -112,49 +253,138 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
     out_value: *mut *mut WokeValue,
 ) -> WokeResult {
     if interp.is_null() || source.is_null() || out_value.is_null() {
-        return WokeResult::NullPointer;
+        return fail(WokeResult::NullPointer, None, "woke_eval was given a null pointer", None);
     }
+    clear_error();
 
-    let interp = &mut *interp;
-    let source = match CStr::from_ptr(source).to_str() {
+    let source = match utf8(source, "source") {
         Ok(s) => s,
-        Err(_) => return WokeResult::Error,
+        Err(result) => return result,
     };
+    match evaluate(&mut (*interp).inner, source) {
+        Ok(value) => {
+            *out_value = new_value(value);
+            WokeResult::Ok
+        }
+        Err(result) => result,
+    }
+}
 
-    // Wrap the expression in a function that returns it
-    let wrapped = format!(
-        "to __ffi_eval__() {{ give back {}; }} to main() {{ __ffi_eval__(); }}",
-        source.trim_end_matches(';')
-    );
-
-    let lexer = Lexer::new(&wrapped);
-    let tokens = match lexer.tokenize() {
-        Ok(t) => t,
-        Err(_) => return WokeResult::ParseError,
+/// Make a C function callable from programs as `name`, with `arity`
+/// arguments
+///
+/// Each of the `capability_count` superpowers in `capabilities`, written as
+/// `woke` shows them (`notify`, `file:read:data`), is asked for before
+/// every call; `capabilities` may be null when there are none. `user_data`
+/// is passed to every call as it is.
+///
+/// # Safety
+/// - `interp` must be a valid pointer from `woke_interpreter_new`
+/// - `name` and each of the capabilities must be valid null-terminated
+///   C strings
+/// - `callback` must be safe to call with `user_data` for as long as the
+///   interpreter lives
+#[no_mangle]
+pub unsafe extern "C" fn woke_register_function(
+    interp: *mut WokeInterpreter,
+    name: *const c_char,
+    arity: usize,
+    capabilities: *const *const c_char,
+    capability_count: usize,
+    callback: Option<WokeNativeFn>,
+    user_data: *mut c_void,
+) -> WokeResult {
+    let Some(callback) = callback else {
+        return fail(WokeResult::NullPointer, None, "woke_register_function was given a null callback", None);
     };
+    if interp.is_null() || name.is_null() || (capabilities.is_null() && capability_count > 0) {
+        return fail(WokeResult::NullPointer, None, "woke_register_function was given a null pointer", None);
+    }
+    clear_error();
 
-    let mut parser = Parser::new(tokens, &wrapped);
-    let program = match parser.parse() {
-        Ok(p) => p,
-        Err(_) => return WokeResult::ParseError,
+    let name = match utf8(name, "function name") {
+        Ok(s) => s,
+        Err(result) => return result,
     };
+    let mut required = Vec::new();
+    for i in 0..capability_count {
+        let capability = *capabilities.add(i);
+        if capability.is_null() {
+            return fail(WokeResult::NullPointer, None, "woke_register_function was given a null superpower", None);
+        }
+        let text = match utf8(capability, "superpower") {
+            Ok(s) => s,
+            Err(result) => return result,
+        };
+        match text.parse::<Capability>() {
+            Ok(capability) => required.push(capability),
+            Err(_) => return fail(WokeResult::Error, None, &format!("Unknown superpower: {}", text), None),
+        }
+    }
+
+    let native = (*interp).inner.register_native(name, arity, host_function(callback, user_data));
+    for capability in required {
+        native.requires(capability);
+    }
+    WokeResult::Ok
+}
+
+/// Give a global variable a copy of `value`, defining it if need be
+///
+/// # Safety
+/// - `interp` must be a valid pointer from `woke_interpreter_new`
+/// - `name` must be a valid null-terminated C string
+/// - `value` must be a valid WokeValue
+#[no_mangle]
+pub unsafe extern "C" fn woke_set_global(
+    interp: *mut WokeInterpreter,
+    name: *const c_char,
+    value: *const WokeValue,
+) -> WokeResult {
+    if interp.is_null() || name.is_null() || value.is_null() {
+        return fail(WokeResult::NullPointer, None, "woke_set_global was given a null pointer", None);
+    }
+    clear_error();
 
-    match interp.inner.run(&program) {
-        Ok(_) => {
-            // Return unit value for now (full implementation would capture return value)
-            *out_value = Box::into_raw(Box::new(WokeValue { inner: Value::Unit }));
+    match utf8(name, "variable name") {
+        Ok(name) => {
+            (*interp).inner.define_global(name, (*value).inner.clone());
             WokeResult::Ok
         }
-        Err(_) => WokeResult::RuntimeError,
+        Err(result) => result,
     }
 }
 
+/// Get a copy of a global variable's value, or null if there is none
+///
+/// The returned WokeValue must be freed with `woke_value_free`.
+///
+/// # Safety
+/// - `interp` must be a valid pointer from `woke_interpreter_new`
+/// - `name` must be a valid null-terminated C string
+#[no_mangle]
+pub unsafe extern "C" fn woke_get_global(interp: *const WokeInterpreter, name: *const c_char) -> *mut WokeValue {
+    if interp.is_null() || name.is_null() {
+        return ptr::null_mut();
+    }
+    let Ok(name) = CStr::from_ptr(name).to_str() else {
+        return ptr::null_mut();
+    };
+    (*interp)
+        .inner
+        .globals()
+        .into_iter()
+        .find(|(global, _)| *global == name)
+        .map_or(ptr::null_mut(), |(_, value)| new_value(value.clone()))
+}
+
 // === Value operations ===
 
 /// Free a WokeValue
 ///
 /// # Safety
-/// The pointer must be valid and from a woke_* function.
+/// The pointer must be null or from a woke_* function, and not freed
+/// already.
 #[no_mangle]
 pub unsafe extern "C" fn woke_value_free(value: *mut WokeValue) {
     if !value.is_null() {
@@ -162,7 +392,22 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
     }
 }
 
+/// Copy a WokeValue
+///
+/// # Safety
+/// The pointer must be null or a valid WokeValue.
+#[no_mangle]
+pub unsafe extern "C" fn woke_value_clone(value: *const WokeValue) -> *mut WokeValue {
+    if value.is_null() {
+        return ptr::null_mut();
+    }
+    new_value((*value).inner.clone())
+}
+
 /// Get the type of a WokeValue
+///
+/// # Safety
+/// The pointer must be null or a valid WokeValue.
 #[no_mangle]
 pub unsafe extern "C" fn woke_value_type(value: *const WokeValue) -> WokeValueType {
     if value.is_null() {
@@ -176,10 +421,23 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
         Value::Bool(_) => WokeValueType::Bool,
         Value::Array(_) => WokeValueType::Array,
         Value::Unit => WokeValueType::Unit,
+        Value::Okay(_) => WokeValueType::Okay,
+        Value::Oops(_) => WokeValueType::Oops,
+        Value::Decimal(_)
+        | Value::Bytes(_)
+        | Value::DateTime(_)
+        | Value::Duration(_)
+        | Value::Record(_)
+        | Value::Function(_)
+        | Value::VmFunction(_)
+        | Value::Channel(_) => WokeValueType::Other,
     }
 }
 
 /// Get an integer from a WokeValue
+///
+/// # Safety
+/// The pointers must be null or valid.
 #[no_mangle]
 pub unsafe extern "C" fn woke_value_as_int(value: *const WokeValue, out: *mut c_longlong) -> WokeResult {
     if value.is_null() || out.is_null() {
@@ -196,6 +454,9 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
 }
 
 /// Get a float from a WokeValue
+///
+/// # Safety
+/// The pointers must be null or valid.
 #[no_mangle]
 pub unsafe extern "C" fn woke_value_as_float(value: *const WokeValue, out: *mut c_double) -> WokeResult {
     if value.is_null() || out.is_null() {
@@ -216,6 +477,9 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
 }
 
 /// Get a boolean from a WokeValue
+///
+/// # Safety
+/// The pointers must be null or valid.
 #[no_mangle]
 pub unsafe extern "C" fn woke_value_as_bool(value: *const WokeValue, out: *mut c_int) -> WokeResult {
     if value.is_null() || out.is_null() {
@@ -234,6 +498,9 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
 /// Get a string from a WokeValue
 ///
 /// The returned string must be freed with `woke_string_free`.
+///
+/// # Safety
+/// The pointer must be null or a valid WokeValue.
 #[no_mangle]
 pub unsafe extern "C" fn woke_value_as_string(value: *const WokeValue) -> *mut c_char {
     if value.is_null() {
@@ -253,6 +520,10 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
 }
 
 /// Free a string returned by woke_value_as_string
+///
+/// # Safety
+/// The pointer must be null or from a woke_* function that says to free
+/// it here, and not freed already.
 #[no_mangle]
 pub unsafe extern "C" fn woke_string_free(s: *mut c_char) {
     if !s.is_null() {
@@ -260,30 +531,98 @@ pub extern "C" fn woke_interpreter_new() -> *mut WokeInterpreter {
     }
 }
 
+/// Get the number of elements in an array WokeValue
+///
+/// # Safety
+/// The pointers must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn woke_value_array_len(value: *const WokeValue, out: *mut usize) -> WokeResult {
+    if value.is_null() || out.is_null() {
+        return WokeResult::NullPointer;
+    }
+
+    match &(*value).inner {
+        Value::Array(items) => {
+            *out = items.len();
+            WokeResult::Ok
+        }
+        _ => WokeResult::Error,
+    }
+}
+
+/// Get a copy of an array's element, or null if the value is not an array
+/// or the index is out of bounds
+///
+/// The returned WokeValue must be freed with `woke_value_free`.
+///
+/// # Safety
+/// The pointer must be null or a valid WokeValue.
+#[no_mangle]
+pub unsafe extern "C" fn woke_value_array_get(value: *const WokeValue, index: usize) -> *mut WokeValue {
+    if value.is_null() {
+        return ptr::null_mut();
+    }
+
+    match &(*value).inner {
+        Value::Array(items) => items.get(index).map_or(ptr::null_mut(), |item| new_value(item.clone())),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Get a copy of the value inside an Okay, or null for anything else
+///
+/// The returned WokeValue must be freed with `woke_value_free`.
+///
+/// # Safety
+/// The pointer must be null or a valid WokeValue.
+#[no_mangle]
+pub unsafe extern "C" fn woke_value_okay_value(value: *const WokeValue) -> *mut WokeValue {
+    if value.is_null() {
+        return ptr::null_mut();
+    }
+
+    match &(*value).inner {
+        Value::Okay(inner) => new_value((**inner).clone()),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Get the message of an Oops, or null for anything else
+///
+/// The returned string must be freed with `woke_string_free`.
+///
+/// # Safety
+/// The pointer must be null or a valid WokeValue.
+#[no_mangle]
+pub unsafe extern "C" fn woke_value_oops_message(value: *const WokeValue) -> *mut c_char {
+    if value.is_null() {
+        return ptr::null_mut();
+    }
+
+    match &(*value).inner {
+        Value::Oops(error) => CString::new(error.error_message()).map_or(ptr::null_mut(), CString::into_raw),
+        _ => ptr::null_mut(),
+    }
+}
+
 // === Value creation ===
 
 /// Create an integer WokeValue
 #[no_mangle]
 pub extern "C" fn woke_value_from_int(n: c_longlong) -> *mut WokeValue {
-    Box::into_raw(Box::new(WokeValue {
-        inner: Value::Int(n),
-    }))
+    new_value(Value::Int(n))
 }
 
 /// Create a float WokeValue
 #[no_mangle]
 pub extern "C" fn woke_value_from_float(f: c_double) -> *mut WokeValue {
-    Box::into_raw(Box::new(WokeValue {
-        inner: Value::Float(f),
-    }))
+    new_value(Value::Float(f))
 }
 
 /// Create a boolean WokeValue
 #[no_mangle]
 pub extern "C" fn woke_value_from_bool(b: c_int) -> *mut WokeValue {
-    Box::into_raw(Box::new(WokeValue {
-        inner: Value::Bool(b != 0),
-    }))
+    new_value(Value::Bool(b != 0))
 }
 
 /// Create a string WokeValue
@@ -297,9 +636,68 @@ pub extern "C" fn woke_value_from_bool(b: c_int) -> *mut WokeValue {
     }
 
     match CStr::from_ptr(s).to_str() {
-        Ok(str) => Box::into_raw(Box::new(WokeValue {
-            inner: Value::String(str.to_string()),
-        })),
+        Ok(str) => new_value(Value::String(str.to_string().into())),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Create the Unit WokeValue
+#[no_mangle]
+pub extern "C" fn woke_value_unit() -> *mut WokeValue {
+    new_value(Value::Unit)
+}
+
+/// Create an empty array WokeValue, to add elements to with
+/// `woke_value_array_push`
+#[no_mangle]
+pub extern "C" fn woke_value_array_new() -> *mut WokeValue {
+    new_value(Value::Array(Vec::new().into()))
+}
+
+/// Add a copy of `item` to the end of an array
+///
+/// # Safety
+/// The pointers must be null or valid, and `item` must not be `array`.
+#[no_mangle]
+pub unsafe extern "C" fn woke_value_array_push(array: *mut WokeValue, item: *const WokeValue) -> WokeResult {
+    if array.is_null() || item.is_null() {
+        return WokeResult::NullPointer;
+    }
+
+    let item = (*item).inner.clone();
+    match &mut (*array).inner {
+        Value::Array(items) => {
+            Rc::make_mut(items).push(item);
+            WokeResult::Ok
+        }
+        _ => WokeResult::Error,
+    }
+}
+
+/// Create an Okay WokeValue holding a copy of `value`
+///
+/// # Safety
+/// The pointer must be null or a valid WokeValue.
+#[no_mangle]
+pub unsafe extern "C" fn woke_value_okay(value: *const WokeValue) -> *mut WokeValue {
+    if value.is_null() {
+        return ptr::null_mut();
+    }
+    new_value(Value::Okay(Box::new((*value).inner.clone())))
+}
+
+/// Create an Oops WokeValue with the given message
+///
+/// # Safety
+/// `message` must be a valid null-terminated C string.
+#[no_mangle]
+pub unsafe extern "C" fn woke_value_oops(message: *const c_char) -> *mut WokeValue {
+    if message.is_null() {
+        return ptr::null_mut();
+    }
+
+    match CStr::from_ptr(message).to_str() {
+        Ok(message) => new_value(Value::oops(message)),
         Err(_) => ptr::null_mut(),
     }
 }
@@ -315,9 +713,249 @@ pub extern "C" fn woke_version() -> *const c_char {
 
 /// Get the last error message (if any)
 ///
-/// Returns null if no error. The returned string is valid until the next woke_* call.
+/// Returns null if the last call on this thread did not fail. The string
+/// is valid until the next woke_* call that can fail.
 #[no_mangle]
 pub extern "C" fn woke_last_error() -> *const c_char {
-    // TODO: Implement thread-local error storage
-    ptr::null()
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |error| error.message.as_ptr()))
+}
+
+/// Fill `out` with what went wrong in the last call on this thread,
+/// returning 1, or return 0 if it did not fail
+///
+/// # Safety
+/// `out` must be null or point to a WokeErrorInfo.
+#[no_mangle]
+pub unsafe extern "C" fn woke_last_error_info(out: *mut WokeErrorInfo) -> c_int {
+    if out.is_null() {
+        return 0;
+    }
+    LAST_ERROR.with(|last| match last.borrow().as_ref() {
+        Some(error) => {
+            *out = WokeErrorInfo {
+                kind: error.kind,
+                code: error.code.as_ref().map_or(ptr::null(), |code| code.as_ptr()),
+                message: error.message.as_ptr(),
+                line: error.line,
+                column: error.column,
+            };
+            1
+        }
+        None => 0,
+    })
+}
+
+/// Say why a host function is failing, from inside its callback, before
+/// it returns an error
+///
+/// # Safety
+/// `message` must be a valid null-terminated C string.
+#[no_mangle]
+pub unsafe extern "C" fn woke_set_error(message: *const c_char) {
+    if message.is_null() {
+        return;
+    }
+    let message = CStr::from_ptr(message).to_string_lossy();
+    fail(WokeResult::Error, None, &message, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn eval(interp: *mut WokeInterpreter, source: &str) -> Result<*mut WokeValue, WokeResult> {
+        let source = CString::new(source).unwrap();
+        let mut value = ptr::null_mut();
+        match woke_eval(interp, source.as_ptr(), &mut value) {
+            WokeResult::Ok => Ok(value),
+            error => Err(error),
+        }
+    }
+
+    unsafe fn string(s: *mut c_char) -> String {
+        let owned = CStr::from_ptr(s).to_string_lossy().into_owned();
+        woke_string_free(s);
+        owned
+    }
+
+    unsafe extern "C" fn sum(
+        user_data: *mut c_void,
+        args: *const *const WokeValue,
+        arg_count: usize,
+        out_result: *mut *mut WokeValue,
+    ) -> WokeResult {
+        *(user_data as *mut usize) += 1;
+        let array = *args;
+        assert_eq!(arg_count, 1);
+        let mut len = 0;
+        if woke_value_array_len(array, &mut len) != WokeResult::Ok {
+            let message = CString::new("sum needs an array").unwrap();
+            woke_set_error(message.as_ptr());
+            return WokeResult::Error;
+        }
+        let mut total = 0;
+        for i in 0..len {
+            let item = woke_value_array_get(array, i);
+            let mut n = 0;
+            woke_value_as_int(item, &mut n);
+            total += n;
+            woke_value_free(item);
+        }
+        let total = woke_value_from_int(total);
+        *out_result = woke_value_okay(total);
+        woke_value_free(total);
+        WokeResult::Ok
+    }
+
+    #[test]
+    fn test_eval_and_values() {
+        unsafe {
+            let interp = woke_interpreter_new();
+            let source = CString::new("to double(n: Int) -> Int { give back n * 2; }").unwrap();
+            assert_eq!(woke_exec(interp, source.as_ptr()), WokeResult::Ok);
+            assert!(woke_last_error().is_null());
+
+            let list = woke_value_array_new();
+            for n in [1, 2] {
+                let item = woke_value_from_int(n);
+                assert_eq!(woke_value_array_push(list, item), WokeResult::Ok);
+                woke_value_free(item);
+            }
+            let name = CString::new("xs").unwrap();
+            assert_eq!(woke_set_global(interp, name.as_ptr(), list), WokeResult::Ok);
+            woke_value_free(list);
+
+            let value = eval(interp, "remember y = double(xs[1]);\n[y, len(xs)]").unwrap();
+            assert_eq!(woke_value_type(value), WokeValueType::Array);
+            let mut len = 0;
+            assert_eq!(woke_value_array_len(value, &mut len), WokeResult::Ok);
+            assert_eq!(len, 2);
+            let first = woke_value_array_get(value, 0);
+            let mut n = 0;
+            assert_eq!(woke_value_as_int(first, &mut n), WokeResult::Ok);
+            assert_eq!(n, 4);
+            assert!(woke_value_array_get(value, 2).is_null());
+            woke_value_free(first);
+            woke_value_free(value);
+
+            let name = CString::new("y").unwrap();
+            let y = woke_get_global(interp, name.as_ptr());
+            assert_eq!(string(woke_value_as_string(y)), "4");
+            woke_value_free(y);
+
+            let message = CString::new("no luck").unwrap();
+            let oops = woke_value_oops(message.as_ptr());
+            assert_eq!(woke_value_type(oops), WokeValueType::Oops);
+            assert_eq!(string(woke_value_oops_message(oops)), "no luck");
+            assert!(woke_value_okay_value(oops).is_null());
+            woke_value_free(oops);
+
+            let value = eval(interp, "remember z = 1;").unwrap();
+            assert_eq!(woke_value_type(value), WokeValueType::Unit);
+            woke_value_free(value);
+
+            woke_interpreter_free(interp);
+        }
+    }
+
+    #[test]
+    fn test_error_info() {
+        unsafe {
+            let interp = woke_interpreter_new();
+            let mut info = WokeErrorInfo {
+                kind: WokeResult::Ok,
+                code: ptr::null(),
+                message: ptr::null(),
+                line: 0,
+                column: 0,
+            };
+
+            assert_eq!(eval(interp, "remember x = 1;\nx +").unwrap_err(), WokeResult::ParseError);
+            assert_eq!(woke_last_error_info(&mut info), 1);
+            assert_eq!(info.kind, WokeResult::ParseError);
+            assert_eq!(CStr::from_ptr(info.code).to_str().unwrap(), "WOKE-E0102");
+            assert!(CStr::from_ptr(info.message).to_str().unwrap().starts_with("Parse error [WOKE-E0102]"));
+
+            assert_eq!(eval(interp, "remember x = 1;\nx $ 2").unwrap_err(), WokeResult::ParseError);
+            assert_eq!(woke_last_error_info(&mut info), 1);
+            assert_eq!(CStr::from_ptr(info.code).to_str().unwrap(), "WOKE-E0001");
+            assert_eq!((info.line, info.column), (2, 3));
+
+            assert_eq!(eval(interp, "1 / 0").unwrap_err(), WokeResult::RuntimeError);
+            assert_eq!(
+                CStr::from_ptr(woke_last_error()).to_str().unwrap(),
+                "Runtime error [WOKE-E0303]: Division by zero"
+            );
+            assert_eq!(woke_last_error_info(&mut info), 1);
+            assert_eq!((info.line, info.column), (0, 0));
+
+            let value = eval(interp, "1").unwrap();
+            woke_value_free(value);
+            assert_eq!(woke_last_error_info(&mut info), 0);
+
+            assert_eq!(woke_exec(interp, ptr::null()), WokeResult::NullPointer);
+            assert!(!woke_last_error().is_null());
+            woke_interpreter_free(interp);
+        }
+    }
+
+    #[test]
+    fn test_register_function() {
+        unsafe {
+            let interp = woke_interpreter_new();
+            let mut calls = 0usize;
+            let name = CString::new("sum").unwrap();
+            let result = woke_register_function(
+                interp,
+                name.as_ptr(),
+                1,
+                ptr::null(),
+                0,
+                Some(sum),
+                &mut calls as *mut usize as *mut c_void,
+            );
+            assert_eq!(result, WokeResult::Ok);
+
+            let value = eval(interp, "unwrapOr(sum([1, 2, 3]), 0) * 2").unwrap();
+            let mut n = 0;
+            assert_eq!(woke_value_as_int(value, &mut n), WokeResult::Ok);
+            assert_eq!(n, 12);
+            woke_value_free(value);
+
+            assert_eq!(eval(interp, "sum(5)").unwrap_err(), WokeResult::RuntimeError);
+            assert_eq!(
+                CStr::from_ptr(woke_last_error()).to_str().unwrap(),
+                "Runtime error [WOKE-E0311]: sum: Runtime error: sum needs an array"
+            );
+            assert_eq!(eval(interp, "sum()").unwrap_err(), WokeResult::RuntimeError);
+            assert_eq!(calls, 2);
+
+            // Superpowers are asked for before the callback runs
+            let name = CString::new("secretSum").unwrap();
+            let notify = CString::new("notify").unwrap();
+            let capabilities = [notify.as_ptr()];
+            let result = woke_register_function(
+                interp,
+                name.as_ptr(),
+                1,
+                capabilities.as_ptr(),
+                1,
+                Some(sum),
+                &mut calls as *mut usize as *mut c_void,
+            );
+            assert_eq!(result, WokeResult::Ok);
+            (*interp).inner = std::mem::take(&mut (*interp).inner).with_deny_all();
+            assert_eq!(eval(interp, "secretSum([1])").unwrap_err(), WokeResult::RuntimeError);
+            assert!(CStr::from_ptr(woke_last_error()).to_str().unwrap().contains("[WOKE-E0304]"));
+            assert_eq!(calls, 2);
+
+            let bogus = CString::new("telepathy").unwrap();
+            let capabilities = [bogus.as_ptr()];
+            let result =
+                woke_register_function(interp, name.as_ptr(), 1, capabilities.as_ptr(), 1, Some(sum), ptr::null_mut());
+            assert_eq!(result, WokeResult::Error);
+            assert_eq!(CStr::from_ptr(woke_last_error()).to_str().unwrap(), "Unknown superpower: telepathy");
+            woke_interpreter_free(interp);
+        }
+    }
 }
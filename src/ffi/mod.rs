@@ -4,5 +4,9 @@
 //! language that supports the C ABI.
 
 mod c_api;
+#[cfg(not(target_arch = "wasm32"))]
+mod plugin;
 
 pub use c_api::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use plugin::{Plugin, PluginError, WokePlugin, WokePluginApi, PLUGIN_API_VERSION};
@@ -0,0 +1,433 @@
+//! Native Plugins
+//!
+//! A plugin is a shared library that adds a module to the standard
+//! library, such as `std.gpio` or `std.mqtt`. It exports
+//! `woke_plugin_register_v1`, named for the version of this API it was
+//! built against, which is handed the plugin being loaded and a table of
+//! the C API's functions. Plugins are loaded by the `woke` executable,
+//! which does not export the C API's symbols, so they reach it only
+//! through that table.
+//!
+//! Loading a plugin runs its code, so it needs the `custom:plugin:<path>`
+//! superpower first, and a sandbox never loads one. A plugin declares the
+//! superpowers its functions use, and they are asked for on every call.
+
+use super::c_api::{self, clear_error, fail, host_function, utf8, WokeNativeFn, WokeResult, WokeValue, WokeValueType};
+use crate::interpreter::NativeFunction;
+use crate::security::{Capability, CapabilityRegistry, HOST_SCOPE};
+use crate::stdlib::StdlibRegistry;
+use libloading::Library;
+use std::ffi::c_void;
+use std::os::raw::{c_char, c_double, c_int, c_longlong};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use thiserror::Error;
+
+/// The version of the plugin API, in the name of the symbol plugins export
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// The newest plugin API version looked for to explain a mismatch
+const NEWEST_KNOWN_VERSION: u32 = 8;
+
+#[derive(Error, Debug)]
+pub enum PluginError {
+    #[error("Loading plugin {0} was not allowed")]
+    NotAllowed(String),
+
+    #[error("Plugins cannot be loaded in the sandbox: {0}")]
+    Sandboxed(String),
+
+    #[error("Could not open plugin {path}: {message}")]
+    Open { path: String, message: String },
+
+    #[error("{0} is not a WokeLang plugin: it does not export woke_plugin_register_v1")]
+    NotAPlugin(String),
+
+    #[error("{path} was built for plugin API v{found}, but this WokeLang supports v{}", PLUGIN_API_VERSION)]
+    Version { path: String, found: u32 },
+
+    #[error("Plugin {path} failed to register: {message}")]
+    Register { path: String, message: String },
+}
+
+/// What a plugin's register function is called with
+type RegisterFn = unsafe extern "C" fn(plugin: *mut WokePlugin, api: *const WokePluginApi) -> WokeResult;
+
+/// A plugin while it registers, as `woke_plugin_register_v1` is handed it
+pub struct WokePlugin {
+    module: Option<String>,
+    capabilities: Vec<Capability>,
+    functions: Vec<PluginFunction>,
+    /// Why a call made while registering failed, if one did
+    error: Option<String>,
+}
+
+struct PluginFunction {
+    name: String,
+    arity: usize,
+    callback: WokeNativeFn,
+    user_data: *mut c_void,
+}
+
+/// The C API, handed to a plugin when it registers
+///
+/// The table lives as long as the program, so a plugin can keep the
+/// pointer for its functions to use when they are called.
+#[repr(C)]
+pub struct WokePluginApi {
+    /// [`PLUGIN_API_VERSION`]
+    pub version: u32,
+    /// Name the module the plugin adds: `gpio` adds `std.gpio`
+    pub set_module: unsafe extern "C" fn(*mut WokePlugin, *const c_char) -> WokeResult,
+    /// Declare a superpower the plugin's functions use, written as `woke`
+    /// shows them (`network:broker.local`, `custom:gpio`)
+    pub require: unsafe extern "C" fn(*mut WokePlugin, *const c_char) -> WokeResult,
+    /// Add a function to the module, as `woke_register_function` does
+    pub add_function:
+        unsafe extern "C" fn(*mut WokePlugin, *const c_char, usize, Option<WokeNativeFn>, *mut c_void) -> WokeResult,
+    pub set_error: unsafe extern "C" fn(*const c_char),
+    pub value_free: unsafe extern "C" fn(*mut WokeValue),
+    pub value_clone: unsafe extern "C" fn(*const WokeValue) -> *mut WokeValue,
+    pub value_type: unsafe extern "C" fn(*const WokeValue) -> WokeValueType,
+    pub value_as_int: unsafe extern "C" fn(*const WokeValue, *mut c_longlong) -> WokeResult,
+    pub value_as_float: unsafe extern "C" fn(*const WokeValue, *mut c_double) -> WokeResult,
+    pub value_as_bool: unsafe extern "C" fn(*const WokeValue, *mut c_int) -> WokeResult,
+    pub value_as_string: unsafe extern "C" fn(*const WokeValue) -> *mut c_char,
+    pub string_free: unsafe extern "C" fn(*mut c_char),
+    pub value_array_len: unsafe extern "C" fn(*const WokeValue, *mut usize) -> WokeResult,
+    pub value_array_get: unsafe extern "C" fn(*const WokeValue, usize) -> *mut WokeValue,
+    pub value_okay_value: unsafe extern "C" fn(*const WokeValue) -> *mut WokeValue,
+    pub value_oops_message: unsafe extern "C" fn(*const WokeValue) -> *mut c_char,
+    pub value_from_int: extern "C" fn(c_longlong) -> *mut WokeValue,
+    pub value_from_float: extern "C" fn(c_double) -> *mut WokeValue,
+    pub value_from_bool: extern "C" fn(c_int) -> *mut WokeValue,
+    pub value_from_string: unsafe extern "C" fn(*const c_char) -> *mut WokeValue,
+    pub value_unit: extern "C" fn() -> *mut WokeValue,
+    pub value_array_new: extern "C" fn() -> *mut WokeValue,
+    pub value_array_push: unsafe extern "C" fn(*mut WokeValue, *const WokeValue) -> WokeResult,
+    pub value_okay: unsafe extern "C" fn(*const WokeValue) -> *mut WokeValue,
+    pub value_oops: unsafe extern "C" fn(*const c_char) -> *mut WokeValue,
+}
+
+static API: WokePluginApi = WokePluginApi {
+    version: PLUGIN_API_VERSION,
+    set_module: plugin_set_module,
+    require: plugin_require,
+    add_function: plugin_add_function,
+    set_error: c_api::woke_set_error,
+    value_free: c_api::woke_value_free,
+    value_clone: c_api::woke_value_clone,
+    value_type: c_api::woke_value_type,
+    value_as_int: c_api::woke_value_as_int,
+    value_as_float: c_api::woke_value_as_float,
+    value_as_bool: c_api::woke_value_as_bool,
+    value_as_string: c_api::woke_value_as_string,
+    string_free: c_api::woke_string_free,
+    value_array_len: c_api::woke_value_array_len,
+    value_array_get: c_api::woke_value_array_get,
+    value_okay_value: c_api::woke_value_okay_value,
+    value_oops_message: c_api::woke_value_oops_message,
+    value_from_int: c_api::woke_value_from_int,
+    value_from_float: c_api::woke_value_from_float,
+    value_from_bool: c_api::woke_value_from_bool,
+    value_from_string: c_api::woke_value_from_string,
+    value_unit: c_api::woke_value_unit,
+    value_array_new: c_api::woke_value_array_new,
+    value_array_push: c_api::woke_value_array_push,
+    value_okay: c_api::woke_value_okay,
+    value_oops: c_api::woke_value_oops,
+};
+
+/// Whether `name` can name a module or function: letters, digits and
+/// underscores, not starting with a digit
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Record that a call made while registering failed
+unsafe fn reject(plugin: *mut WokePlugin, message: String) -> WokeResult {
+    (*plugin).error.get_or_insert_with(|| message.clone());
+    fail(WokeResult::Error, None, &message, None)
+}
+
+unsafe extern "C" fn plugin_set_module(plugin: *mut WokePlugin, name: *const c_char) -> WokeResult {
+    if plugin.is_null() || name.is_null() {
+        return fail(WokeResult::NullPointer, None, "set_module was given a null pointer", None);
+    }
+    clear_error();
+    let name = match utf8(name, "module name") {
+        Ok(name) => name,
+        Err(result) => return result,
+    };
+    if !is_identifier(name) {
+        return reject(plugin, format!("'{}' is not a valid module name", name));
+    }
+    if StdlibRegistry::new().has_module(&format!("std.{}", name)) {
+        return reject(plugin, format!("std.{} is already part of the standard library", name));
+    }
+    (*plugin).module = Some(name.to_string());
+    WokeResult::Ok
+}
+
+unsafe extern "C" fn plugin_require(plugin: *mut WokePlugin, capability: *const c_char) -> WokeResult {
+    if plugin.is_null() || capability.is_null() {
+        return fail(WokeResult::NullPointer, None, "require was given a null pointer", None);
+    }
+    clear_error();
+    let text = match utf8(capability, "superpower") {
+        Ok(text) => text,
+        Err(result) => return result,
+    };
+    match text.parse::<Capability>() {
+        Ok(capability) => {
+            (*plugin).capabilities.push(capability);
+            WokeResult::Ok
+        }
+        Err(_) => reject(plugin, format!("Unknown superpower: {}", text)),
+    }
+}
+
+unsafe extern "C" fn plugin_add_function(
+    plugin: *mut WokePlugin,
+    name: *const c_char,
+    arity: usize,
+    callback: Option<WokeNativeFn>,
+    user_data: *mut c_void,
+) -> WokeResult {
+    let Some(callback) = callback else {
+        return fail(WokeResult::NullPointer, None, "add_function was given a null callback", None);
+    };
+    if plugin.is_null() || name.is_null() {
+        return fail(WokeResult::NullPointer, None, "add_function was given a null pointer", None);
+    }
+    clear_error();
+    let name = match utf8(name, "function name") {
+        Ok(name) => name,
+        Err(result) => return result,
+    };
+    if !is_identifier(name) {
+        return reject(plugin, format!("'{}' is not a valid function name", name));
+    }
+    (*plugin).functions.retain(|function| function.name != name);
+    (*plugin).functions.push(PluginFunction {
+        name: name.to_string(),
+        arity,
+        callback,
+        user_data,
+    });
+    WokeResult::Ok
+}
+
+/// A plugin that has been loaded and registered
+pub struct Plugin {
+    path: PathBuf,
+    module: String,
+    capabilities: Vec<Capability>,
+    functions: Vec<PluginFunction>,
+    /// Kept open for as long as any of its functions is registered
+    library: Option<Rc<Library>>,
+}
+
+impl Plugin {
+    /// Load the plugin at `path`, once the `custom:plugin:<path>`
+    /// superpower has been granted
+    pub fn load(path: &Path, capabilities: &mut CapabilityRegistry) -> Result<Plugin, PluginError> {
+        let shown = path.display().to_string();
+        if capabilities.sandbox_root().is_some() {
+            return Err(PluginError::Sandboxed(shown));
+        }
+        capabilities
+            .request(HOST_SCOPE, &Self::capability(path))
+            .map_err(|_| PluginError::NotAllowed(shown.clone()))?;
+
+        let library = unsafe { Library::new(path) }.map_err(|e| PluginError::Open {
+            path: shown.clone(),
+            message: e.to_string(),
+        })?;
+        let symbol = format!("woke_plugin_register_v{}", PLUGIN_API_VERSION);
+        let register = match unsafe { library.get::<RegisterFn>(symbol.as_bytes()) } {
+            Ok(register) => *register,
+            Err(_) => {
+                let found = (1..=NEWEST_KNOWN_VERSION).find(|version| {
+                    let symbol = format!("woke_plugin_register_v{}", version);
+                    unsafe { library.get::<RegisterFn>(symbol.as_bytes()) }.is_ok()
+                });
+                return Err(match found {
+                    Some(found) => PluginError::Version { path: shown, found },
+                    None => PluginError::NotAPlugin(shown),
+                });
+            }
+        };
+        let mut plugin = unsafe { Self::register(path, register) }?;
+        plugin.library = Some(Rc::new(library));
+        Ok(plugin)
+    }
+
+    /// The superpower loading the plugin at `path` needs
+    pub fn capability(path: &Path) -> Capability {
+        Capability::Custom(format!("plugin:{}", path.display()))
+    }
+
+    /// Call a plugin's register function and check what it registered
+    unsafe fn register(path: &Path, register: RegisterFn) -> Result<Plugin, PluginError> {
+        let failed = |message: String| PluginError::Register {
+            path: path.display().to_string(),
+            message,
+        };
+        let mut plugin = WokePlugin {
+            module: None,
+            capabilities: Vec::new(),
+            functions: Vec::new(),
+            error: None,
+        };
+        clear_error();
+        let result = register(&mut plugin, &API);
+        if let Some(message) = plugin.error {
+            return Err(failed(message));
+        }
+        if result != WokeResult::Ok {
+            let message = c_api::take_error().unwrap_or_else(|| "its register function failed".to_string());
+            return Err(failed(message));
+        }
+        let module = plugin
+            .module
+            .ok_or_else(|| failed("it did not name its module with set_module".to_string()))?;
+        Ok(Plugin {
+            path: path.to_path_buf(),
+            module,
+            capabilities: plugin.capabilities,
+            functions: plugin.functions,
+            library: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The module it adds, e.g. `std.gpio`
+    pub fn module(&self) -> String {
+        format!("std.{}", self.module)
+    }
+
+    /// The superpowers asked for before each call to one of its functions
+    pub fn capabilities(&self) -> &[Capability] {
+        &self.capabilities
+    }
+
+    /// The full name and arity of each of its functions
+    pub fn functions(&self) -> impl Iterator<Item = (String, usize)> + '_ {
+        self.functions
+            .iter()
+            .map(|function| (format!("std.{}.{}", self.module, function.name), function.arity))
+    }
+
+    /// Its functions, ready to register with an interpreter or VM
+    pub(crate) fn natives(&self) -> Vec<NativeFunction> {
+        self.functions
+            .iter()
+            .map(|function| {
+                let library = self.library.clone();
+                let mut call = unsafe { host_function(function.callback, function.user_data) };
+                let mut native = NativeFunction::new(
+                    &format!("std.{}.{}", self.module, function.name),
+                    function.arity,
+                    move |args| {
+                        let _open = &library;
+                        call(args)
+                    },
+                );
+                for capability in &self.capabilities {
+                    native.requires(capability.clone());
+                }
+                native
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, OutputBuffer};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::security::ConsentMode;
+
+    unsafe extern "C" fn add(
+        _user_data: *mut c_void,
+        args: *const *const WokeValue,
+        _arg_count: usize,
+        out_result: *mut *mut WokeValue,
+    ) -> WokeResult {
+        let (mut a, mut b) = (0, 0);
+        (API.value_as_int)(*args, &mut a);
+        (API.value_as_int)(*args.add(1), &mut b);
+        *out_result = (API.value_from_int)(a + b);
+        WokeResult::Ok
+    }
+
+    unsafe extern "C" fn register_math(plugin: *mut WokePlugin, api: *const WokePluginApi) -> WokeResult {
+        let api = &*api;
+        (api.set_module)(plugin, c"extra".as_ptr());
+        (api.require)(plugin, c"custom:abacus".as_ptr());
+        (api.add_function)(plugin, c"add".as_ptr(), 2, Some(add), std::ptr::null_mut())
+    }
+
+    unsafe extern "C" fn register_builtin(plugin: *mut WokePlugin, api: *const WokePluginApi) -> WokeResult {
+        ((*api).set_module)(plugin, c"io".as_ptr());
+        WokeResult::Ok
+    }
+
+    unsafe extern "C" fn register_nothing(_plugin: *mut WokePlugin, api: *const WokePluginApi) -> WokeResult {
+        ((*api).set_error)(c"no hardware found".as_ptr());
+        WokeResult::Error
+    }
+
+    fn run(interpreter: Interpreter, source: &str) -> Result<String, String> {
+        let output = OutputBuffer::new();
+        let mut interpreter = interpreter.with_output(output.clone());
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        interpreter.run(&program).map_err(|e| e.to_string())?;
+        Ok(output.contents())
+    }
+
+    #[test]
+    fn test_plugin_registers_a_module() {
+        let plugin = unsafe { Plugin::register(Path::new("libextra.so"), register_math) }.unwrap();
+        assert_eq!(plugin.module(), "std.extra");
+        assert_eq!(plugin.functions().collect::<Vec<_>>(), vec![("std.extra.add".to_string(), 2)]);
+        assert_eq!(plugin.capabilities(), [Capability::Custom("abacus".to_string())]);
+
+        let source = "use std.extra; to main() { print(extra.add(2, 3)); print(std.extra.add(1, 1)); }";
+        let allowed = Interpreter::new().with_consent_mode(ConsentMode::Allow).with_plugin(&plugin);
+        assert_eq!(run(allowed, source).unwrap(), "5\n2\n");
+
+        // Its functions need the superpowers it declared
+        let denied = Interpreter::new().with_deny_all().with_plugin(&plugin);
+        assert!(run(denied, source).unwrap_err().contains("custom:abacus"));
+    }
+
+    #[test]
+    fn test_plugin_registration_errors() {
+        let err = unsafe { Plugin::register(Path::new("libio.so"), register_builtin) }.err().unwrap();
+        assert!(err.to_string().contains("std.io is already part of the standard library"), "{}", err);
+
+        let err = unsafe { Plugin::register(Path::new("libnone.so"), register_nothing) }.err().unwrap();
+        assert_eq!(err.to_string(), "Plugin libnone.so failed to register: no hardware found");
+    }
+
+    #[test]
+    fn test_loading_needs_a_grant() {
+        let mut capabilities = CapabilityRegistry::new();
+        capabilities.set_consent_mode(ConsentMode::Deny);
+        let err = Plugin::load(Path::new("libmissing.so"), &mut capabilities).err().unwrap();
+        assert!(matches!(err, PluginError::NotAllowed(_)), "{}", err);
+
+        capabilities.grant(HOST_SCOPE, Plugin::capability(Path::new("libmissing.so")), "test");
+        let err = Plugin::load(Path::new("libmissing.so"), &mut capabilities).err().unwrap();
+        assert!(matches!(err, PluginError::Open { .. }), "{}", err);
+    }
+}
@@ -0,0 +1,497 @@
+//! Source Formatter
+//!
+//! Turns a parsed program back into WokeLang source: four-space indents,
+//! one statement a line, and a blank line between top-level items.
+//! Parentheses are only written where precedence needs them, so the
+//! output parses back into the same tree. Comments are not part of the
+//! AST and are not kept.
+
+use crate::ast::*;
+use crate::security::limits::{Bytes, Timeout};
+
+const INDENT: &str = "    ";
+
+/// The source of a whole program
+pub fn format_program(program: &Program) -> String {
+    let items: Vec<String> = program.items.iter().map(format_item).collect();
+    match items.is_empty() {
+        true => String::new(),
+        false => items.join("\n\n") + "\n",
+    }
+}
+
+/// The source of one top-level item, without a trailing newline
+pub fn format_item(item: &TopLevelItem) -> String {
+    Formatter::default().item(item)
+}
+
+/// The source of an expression
+pub fn format_expr(expr: &Expr) -> String {
+    Formatter::default().expr(expr)
+}
+
+/// A function's name, type parameters, parameters and return type:
+/// `double(n: Int) -> Int`
+pub fn format_signature(f: &FunctionDef) -> String {
+    let mut out = f.name.clone();
+    if !f.type_params.is_empty() {
+        let params = list(&f.type_params, |p| match p.bounds.is_empty() {
+            true => p.name.clone(),
+            false => format!("{}: {}", p.name, p.bounds.join(" + ")),
+        });
+        out.push_str(&format!("<{}>", params));
+    }
+    out.push_str(&format!("({})", list(&f.params, parameter)));
+    if let Some(ty) = &f.return_type {
+        out.push_str(&format!(" -> {}", format_type(ty)));
+    }
+    out
+}
+
+/// The source of a type annotation
+pub fn format_type(ty: &Type) -> String {
+    match ty {
+        Type::Basic(name) | Type::TypeVar(name) => name.clone(),
+        Type::Array(inner) => format!("[{}]", format_type(inner)),
+        Type::Optional(inner) => format!("Maybe {}", format_type(inner)),
+        Type::Reference(inner) => format!("&{}", format_type(inner)),
+        Type::Function(params, ret) => format!("({}) -> {}", list(params, format_type), format_type(ret)),
+        Type::Generic(name, args) => format!("{}<{}>", name, list(args, format_type)),
+    }
+}
+
+/// Tracks how deep in blocks the statement being written is
+#[derive(Default)]
+struct Formatter {
+    indent: usize,
+}
+
+impl Formatter {
+    fn item(&mut self, item: &TopLevelItem) -> String {
+        match item {
+            TopLevelItem::Function(f) => self.function(f),
+            TopLevelItem::ConsentBlock(c) => self.consent_block(c),
+            TopLevelItem::GratitudeDecl(g) => {
+                let entries = g
+                    .entries
+                    .iter()
+                    .map(|e| format!("{} -> {};", quote(&e.recipient), quote(&e.reason)))
+                    .collect();
+                format!("thanks to {}", self.braces(entries))
+            }
+            TopLevelItem::WorkerDef(w) => format!("worker {} {}", w.name, self.block(&w.body)),
+            TopLevelItem::SideQuestDef(s) => format!("side quest {} {}", s.name, self.block(&s.body)),
+            TopLevelItem::SuperpowerDecl(s) => format!("superpower {} {}", s.name, self.block(&s.body)),
+            TopLevelItem::ModuleImport(import) => {
+                let mut out = format!("use {}", import.path.parts.join("."));
+                if let Some(rename) = &import.rename {
+                    out.push_str(&format!(" renamed {}", rename));
+                }
+                if let Some(needs) = &import.needs {
+                    let needs = list(needs, |need| match &need.value {
+                        Some(value) => format!("{} {}", need.kind, quote(value)),
+                        None => need.kind.clone(),
+                    });
+                    out.push_str(&format!(" must have {}", needs));
+                    if let Some(reason) = &import.reason {
+                        out.push_str(&format!(" because {}", quote(reason)));
+                    }
+                }
+                out + ";"
+            }
+            TopLevelItem::Pragma(p) => {
+                let directive = match p.directive {
+                    PragmaDirective::Timeout(timeout) => return format!("#timeout {};", Timeout(timeout)),
+                    PragmaDirective::MaxMemory(bytes) => return format!("#maxmemory {};", Bytes(bytes)),
+                    PragmaDirective::Care => "care",
+                    PragmaDirective::Strict => "strict",
+                    PragmaDirective::Verbose => "verbose",
+                    PragmaDirective::SideQuests => "side quests",
+                };
+                format!("#{} {};", directive, if p.enabled { "on" } else { "off" })
+            }
+            TopLevelItem::TypeDef(t) => {
+                let definition = match &t.definition {
+                    TypeVariant::Struct(fields) => match fields.is_empty() {
+                        true => "{}".to_string(),
+                        false => format!("{{ {} }}", list(fields, |f| format!("{}: {}", f.name, format_type(&f.ty)))),
+                    },
+                    TypeVariant::Enum(variants) => variants
+                        .iter()
+                        .map(|v| match v.fields.is_empty() {
+                            true => v.name.clone(),
+                            false => format!("{}({})", v.name, list(&v.fields, format_type)),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" | "),
+                    TypeVariant::Alias(ty) => format_type(ty),
+                };
+                format!("type {} = {};", t.name, definition)
+            }
+            TopLevelItem::ConstDef(c) => {
+                format!("const {}: {} = {};", c.name, format_type(&c.ty), self.expr(&c.value.node))
+            }
+            TopLevelItem::Expect(e) => format!("expect {} {}", quote(&e.description), self.block(&e.body)),
+            TopLevelItem::Global(decl) => self.var_decl(decl),
+        }
+    }
+
+    fn var_decl(&mut self, decl: &VarDecl) -> String {
+        let secret = if decl.secret { "secret " } else { "" };
+        let unit = match &decl.unit {
+            Some(unit) => format!(" measured in {}", unit),
+            None => String::new(),
+        };
+        format!("remember {}{} = {}{};", secret, decl.name, self.expr(&decl.value.node), unit)
+    }
+
+    fn function(&mut self, f: &FunctionDef) -> String {
+        let mut out = String::new();
+        if let Some(emote) = &f.emote {
+            out.push_str(&emote_tag(emote));
+            out.push(' ');
+        }
+        out.push_str(&format!("to {}", format_signature(f)));
+
+        self.indent += 1;
+        let mut lines = Vec::new();
+        if let Some(hello) = &f.hello {
+            lines.push(format!("hello {};", quote(hello)));
+        }
+        lines.extend(f.body.iter().map(|s| self.statement(s)));
+        if let Some(goodbye) = &f.goodbye {
+            lines.push(format!("goodbye {};", quote(goodbye)));
+        }
+        self.indent -= 1;
+        format!("{} {}", out, self.braces(lines))
+    }
+
+    fn consent_block(&mut self, c: &ConsentBlock) -> String {
+        let mut out = format!("only if okay {}", quote(&c.permission));
+        if let Some(reason) = &c.reason {
+            out.push_str(&format!(" because {}", quote(reason)));
+        }
+        format!("{} {}", out, self.block(&c.body))
+    }
+
+    fn statement(&mut self, stmt: &Statement) -> String {
+        match stmt {
+            Statement::VarDecl(decl) => self.var_decl(decl),
+            Statement::Assignment(a) => format!("{} = {};", a.target, self.expr(&a.value.node)),
+            Statement::Return(r) => format!("give back {};", self.expr(&r.value.node)),
+            Statement::Conditional(c) => {
+                let mut out = format!("when {} {}", self.expr(&c.condition.node), self.block(&c.then_branch));
+                if let Some(otherwise) = &c.else_branch {
+                    out.push_str(&format!(" otherwise {}", self.block(otherwise)));
+                }
+                out
+            }
+            Statement::Loop(l) => format!("repeat {} times {}", self.expr(&l.count.node), self.block(&l.body)),
+            Statement::AttemptBlock(a) => {
+                format!("attempt safely {} or reassure {};", self.block(&a.body), quote(&a.reassurance))
+            }
+            Statement::ConsentBlock(c) => self.consent_block(c),
+            Statement::Expression(e) => format!("{};", self.expr(&e.node)),
+            Statement::WorkerSpawn(w) => format!("spawn worker {};", w.worker_name),
+            Statement::Complain(c) => format!("complain {};", quote(&c.message)),
+            Statement::EmoteAnnotated(e) => format!("{} {}", emote_tag(&e.emote), self.statement(&e.statement)),
+            Statement::Decide(d) => {
+                let scrutinee = self.expr(&d.scrutinee.node);
+                self.indent += 1;
+                let arms = d
+                    .arms
+                    .iter()
+                    .map(|arm| format!("{} -> {}", pattern(&arm.pattern), self.block(&arm.body)))
+                    .collect();
+                self.indent -= 1;
+                format!("decide based on {} {}", scrutinee, self.braces(arms))
+            }
+        }
+    }
+
+    /// A block of statements one level deeper than the current one
+    fn block(&mut self, stmts: &[Statement]) -> String {
+        self.indent += 1;
+        let lines = stmts.iter().map(|s| self.statement(s)).collect();
+        self.indent -= 1;
+        self.braces(lines)
+    }
+
+    /// Lines already formatted one level deeper, in braces
+    fn braces(&self, lines: Vec<String>) -> String {
+        if lines.is_empty() {
+            return "{}".to_string();
+        }
+        let mut out = "{\n".to_string();
+        for line in lines {
+            out.push_str(&INDENT.repeat(self.indent + 1));
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str(&INDENT.repeat(self.indent));
+        out.push('}');
+        out
+    }
+
+    fn expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Literal(lit) => literal(lit),
+            Expr::Identifier(name) => name.to_string(),
+            Expr::Binary(op, left, right) => {
+                let level = precedence(expr);
+                let left = self.operand(&left.node, level);
+                let right = self.operand(&right.node, level + 1);
+                format!("{} {} {}", left, binary_op(*op), right)
+            }
+            Expr::Unary(UnaryOp::Not, operand) => format!("not {}", self.operand(&operand.node, UNARY)),
+            Expr::Unary(UnaryOp::Neg, operand) => {
+                let operand = match self.operand(&operand.node, UNARY) {
+                    // `--` would read as two minus signs either way, but is hard to read
+                    negative if negative.starts_with('-') => format!("({})", negative),
+                    operand => operand,
+                };
+                format!("-{}", operand)
+            }
+            Expr::Call(name, args) => format!("{}({})", name, self.args(args)),
+            Expr::CallExpr(callee, args) => {
+                format!("{}({})", self.operand(&callee.node, POSTFIX), self.args(args))
+            }
+            Expr::UnitMeasurement(value, unit) => {
+                format!("{} measured in {}", self.operand(&value.node, POSTFIX), unit)
+            }
+            Expr::GratitudeLiteral(name) => format!("thanks({})", quote(name)),
+            Expr::Array(elements) => format!("[{}]", self.args(elements)),
+            Expr::Index(value, index) => {
+                format!("{}[{}]", self.operand(&value.node, POSTFIX), self.expr(&index.node))
+            }
+            Expr::Okay(value) => format!("Okay({})", self.expr(&value.node)),
+            Expr::Oops(value) => format!("Oops({})", self.expr(&value.node)),
+            Expr::Unwrap(value) => format!("unwrap({})", self.expr(&value.node)),
+            Expr::Lambda(lambda) => {
+                let mut out = format!("|{}|", list(&lambda.params, parameter));
+                if let Some(ty) = &lambda.return_type {
+                    out.push_str(&format!(": {}", format_type(ty)));
+                }
+                match &lambda.body {
+                    LambdaBody::Expr(body) => format!("{} -> {}", out, self.expr(&body.node)),
+                    LambdaBody::Block(body) => format!("{} {}", out, self.block(body)),
+                }
+            }
+        }
+    }
+
+    /// An expression where only one binding at least as tightly as `level`
+    /// can go without parentheses
+    fn operand(&mut self, expr: &Expr, level: u8) -> String {
+        match precedence(expr) < level {
+            true => format!("({})", self.expr(expr)),
+            false => self.expr(expr),
+        }
+    }
+
+    fn args(&mut self, args: &[Spanned<Expr>]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| self.expr(&arg.node)).collect();
+        args.join(", ")
+    }
+}
+
+const UNARY: u8 = 7;
+const POSTFIX: u8 = 8;
+
+/// How tightly an expression binds, from a lambda, whose body takes in
+/// everything after it, up to postfix and primary expressions
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Lambda(_) => 0,
+        Expr::Binary(op, _, _) => match op {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::NotEq => 3,
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::LtEq | BinaryOp::GtEq => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 6,
+        },
+        Expr::Unary(_, _) => UNARY,
+        Expr::Literal(Literal::Integer(n)) if *n < 0 => UNARY,
+        Expr::Literal(Literal::Float(n)) if n.is_sign_negative() => UNARY,
+        Expr::Literal(Literal::Decimal(n)) if n.is_sign_negative() => UNARY,
+        _ => POSTFIX,
+    }
+}
+
+fn binary_op(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::Eq => "==",
+        BinaryOp::NotEq => "!=",
+        BinaryOp::Lt => "<",
+        BinaryOp::Gt => ">",
+        BinaryOp::LtEq => "<=",
+        BinaryOp::GtEq => ">=",
+        BinaryOp::And => "and",
+        BinaryOp::Or => "or",
+    }
+}
+
+fn literal(lit: &Literal) -> String {
+    match lit {
+        Literal::Integer(n) => n.to_string(),
+        Literal::Float(n) => {
+            // The lexer only reads floats with digits on both sides of the point
+            let mut out = n.to_string();
+            if !out.contains('.') {
+                out.push_str(".0");
+            }
+            out
+        }
+        Literal::Decimal(n) => format!("{}d", n),
+        Literal::String(s) => quote(s),
+        Literal::Bool(b) => b.to_string(),
+        Literal::Unit => "()".to_string(),
+    }
+}
+
+fn pattern(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Literal(lit) => literal(lit),
+        Pattern::Identifier(name) => name.to_string(),
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Constructor(name, inner) => {
+            format!("{}({})", name, inner.as_deref().map(self::pattern).unwrap_or_default())
+        }
+    }
+}
+
+fn parameter(param: &Parameter) -> String {
+    match &param.ty {
+        Some(ty) => format!("{}: {}", param.name, format_type(ty)),
+        None => param.name.to_string(),
+    }
+}
+
+fn emote_tag(emote: &EmoteTag) -> String {
+    if emote.params.is_empty() {
+        return format!("@{}", emote.name);
+    }
+    let params = list(&emote.params, |p| {
+        let value = match &p.value {
+            Some(EmoteValue::Number(n)) => n.to_string(),
+            Some(EmoteValue::String(s)) => quote(s),
+            Some(EmoteValue::Identifier(name)) => name.clone(),
+            None => return p.name.clone(),
+        };
+        format!("{}={}", p.name, value)
+    });
+    format!("@{}({})", emote.name, params)
+}
+
+/// A string literal, escaped the way the lexer reads it back
+pub(crate) fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn list<T>(items: &[T], format: impl Fn(&T) -> String) -> String {
+    items.iter().map(format).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Program {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        Parser::new(tokens, source).parse().unwrap()
+    }
+
+    #[test]
+    fn test_format_program() {
+        let source = r#"use std.io must have read "data" because "config";
+#care on;
+#timeout 1.5 min;
+#maxmemory 65536kb;
+type Shape = Circle | Rect(Int, Int);
+remember limit = 10 measured in km;
+@happy(level=2) to area<T: Show>(s: Maybe [Int], f: (Int) -> Int) -> Int {
+    hello "hi";
+    remember x = (1 + 2) * -(3 - 4) - (5 - 6);
+    remember cost = 1.50d * 3d;
+    when not (x > 1 and true) { give back x; } otherwise { complain "no\n\"really\""; }
+    decide based on Okay(x) { Okay(n) -> { print(n); } _ -> {} }
+    give back (|n| -> n + 1)(x)[0] measured in km;
+    goodbye "bye";
+}"#;
+        let expected = r#"use std.io must have read "data" because "config";
+
+#care on;
+
+#timeout 90s;
+
+#maxmemory 64mb;
+
+type Shape = Circle | Rect(Int, Int);
+
+remember limit = 10 measured in km;
+
+@happy(level=2) to area<T: Show>(s: Maybe [Int], f: (Int) -> Int) -> Int {
+    hello "hi";
+    remember x = (1 + 2) * -(3 - 4) - (5 - 6);
+    remember cost = 1.50d * 3d;
+    when not (x > 1 and true) {
+        give back x;
+    } otherwise {
+        complain "no\n\"really\"";
+    }
+    decide based on Okay(x) {
+        Okay(n) -> {
+            print(n);
+        }
+        _ -> {}
+    }
+    give back (|n| -> n + 1)(x)[0] measured in km;
+    goodbye "bye";
+}
+"#;
+        assert_eq!(format_program(&parse(source)), expected);
+        assert_eq!(format_program(&parse(expected)), expected);
+        assert_eq!(format_program(&Program { items: Vec::new() }), "");
+    }
+
+    #[test]
+    fn test_format_examples_round_trip() {
+        let mut formatted = 0;
+        for entry in std::fs::read_dir("examples").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "woke") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            let Ok(tokens) = Lexer::new(&source).tokenize() else { continue };
+            let Ok(program) = Parser::new(tokens, &source).parse() else { continue };
+
+            let once = format_program(&program);
+            let twice = format_program(&parse(&once));
+            assert_eq!(once, twice, "{}", path.display());
+            formatted += 1;
+        }
+        assert!(formatted > 0);
+    }
+}
@@ -0,0 +1,368 @@
+//! Typed Host Functions
+//!
+//! [`super::Interpreter::bind`] makes a Rust closure callable from programs
+//! under a WokeLang signature, written as a function is:
+//! `to greet(name: String, count: Int) → String`. Declared to a type
+//! checker with [`crate::typechecker::TypeChecker::declare_signature`], the
+//! signature has calls to it checked before the program runs, with the
+//! argument at fault pointed to. At run time the arguments are checked
+//! against it and converted to the closure's Rust types, so a call that
+//! does not fit fails with an error naming the parameter.
+
+use super::native::NativeFunction;
+use super::Value;
+use crate::ast::TopLevelItem;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::stdlib::StdlibError;
+use crate::typechecker::InferredType;
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum BindingError {
+    #[error("Invalid signature '{signature}': {message}")]
+    Signature { signature: String, message: String },
+
+    #[error("{name} declares {declared} parameters, but its Rust function takes {taken}")]
+    Arity { name: String, declared: usize, taken: usize },
+}
+
+/// The WokeLang type of a host function
+#[derive(Debug, Clone)]
+pub struct Signature {
+    name: String,
+    params: Vec<(String, InferredType)>,
+    ret: InferredType,
+}
+
+impl Signature {
+    /// Parse a function header, `to name(param: Type, ...) → Type`; every
+    /// parameter needs a type, and a function without `→` gives back Unit
+    pub fn parse(text: &str) -> Result<Self, BindingError> {
+        let invalid = |message: String| BindingError::Signature {
+            signature: text.to_string(),
+            message,
+        };
+        let source = format!("{} {{}}", text.trim());
+        let tokens = Lexer::new(&source).tokenize().map_err(|e| invalid(e.to_string()))?;
+        let program = Parser::new(tokens, &source).parse().map_err(|e| invalid(e.to_string()))?;
+        let func = match program.items.as_slice() {
+            [TopLevelItem::Function(func)] => func,
+            _ => return Err(invalid("expected one function header".to_string())),
+        };
+        let params = func
+            .params
+            .iter()
+            .map(|param| match &param.ty {
+                Some(ty) => Ok((param.name.to_string(), InferredType::from(ty))),
+                None => Err(invalid(format!("parameter {} needs a type", param.name))),
+            })
+            .collect::<Result<_, _>>()?;
+        let ret = func.return_type.as_ref().map(InferredType::from).unwrap_or(InferredType::Unit);
+        Ok(Self {
+            name: func.name.clone(),
+            params,
+            ret,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The parameters' names and types, in order
+    pub fn params(&self) -> &[(String, InferredType)] {
+        &self.params
+    }
+
+    pub fn return_type(&self) -> &InferredType {
+        &self.ret
+    }
+
+    /// The signature as the type checker sees a function
+    pub fn function_type(&self) -> InferredType {
+        InferredType::Function {
+            params: self.params.iter().map(|(_, ty)| ty.clone()).collect(),
+            ret: Box::new(self.ret.clone()),
+        }
+    }
+
+    /// A host function calling `func` with the arguments converted, after
+    /// checking them against the signature
+    pub(crate) fn bind<Args>(self, mut func: impl HostFunction<Args>) -> Result<NativeFunction, BindingError> {
+        if func.arity() != self.params.len() {
+            return Err(BindingError::Arity {
+                name: self.name,
+                declared: self.params.len(),
+                taken: func.arity(),
+            });
+        }
+        let params = self.params.clone();
+        let ret = self.ret.clone();
+        let mut native = NativeFunction::new(&self.name, self.params.len(), move |args| {
+            let mismatch = |index: usize| {
+                let (name, ty) = &params[index];
+                StdlibError::TypeError {
+                    expected: format!("{} for {}", ty, name),
+                    got: args[index].to_string(),
+                }
+            };
+            if let Some(index) = (0..args.len()).find(|&i| !fits(&args[i], &params[i].1)) {
+                return Err(mismatch(index));
+            }
+            let result = func.call(args).map_err(mismatch)?;
+            if !fits(&result, &ret) {
+                return Err(StdlibError::TypeError {
+                    expected: format!("{} to be given back", ret),
+                    got: result.to_string(),
+                });
+            }
+            Ok(result)
+        });
+        native.signature = Some(self);
+        Ok(native)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+        write!(f, "to {}({}) → {}", self.name, params.join(", "), self.ret)
+    }
+}
+
+/// Whether `value` is of type `ty`, as far as can be told from the value;
+/// type variables and named types take anything
+fn fits(value: &Value, ty: &InferredType) -> bool {
+    match (ty, value) {
+        (InferredType::Int, Value::Int(_))
+        | (InferredType::Float, Value::Float(_) | Value::Int(_))
+        | (InferredType::Decimal, Value::Decimal(_) | Value::Int(_))
+        | (InferredType::String, Value::String(_))
+        | (InferredType::Bytes, Value::Bytes(_))
+        | (InferredType::DateTime, Value::DateTime(_))
+        | (InferredType::Duration, Value::Duration(_))
+        | (InferredType::Bool, Value::Bool(_))
+        | (InferredType::Unit, Value::Unit)
+        | (InferredType::Function { .. }, Value::Function(_) | Value::VmFunction(_))
+        | (InferredType::Result { .. }, Value::Oops(_))
+        | (InferredType::Maybe(_), Value::Unit)
+        | (InferredType::TypeVar(_) | InferredType::Unknown(_), _) => true,
+        (InferredType::Array(inner), Value::Array(items)) => items.iter().all(|item| fits(item, inner)),
+        (InferredType::Result { ok, .. }, Value::Okay(value)) => fits(value, ok),
+        (InferredType::Maybe(inner), value) => fits(value, inner),
+        _ => false,
+    }
+}
+
+/// A Rust type a host function can take an argument as
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+/// A Rust type a host function can give back
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(n) => Some(*n),
+            Value::Int(n) => Some(*n as f64),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(s.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for DateTime<FixedOffset> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::DateTime(t) => Some(*t),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for TimeDelta {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Duration(d) => Some(*d),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(items) => items.iter().map(T::from_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Record(fields) => fields
+                .iter()
+                .map(|(key, value)| Some((key.clone(), T::from_value(value)?)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string().into())
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Unit
+    }
+}
+
+impl IntoValue for DateTime<FixedOffset> {
+    fn into_value(self) -> Value {
+        Value::DateTime(self)
+    }
+}
+
+impl IntoValue for TimeDelta {
+    fn into_value(self) -> Value {
+        Value::Duration(self)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::Array(self.into_iter().map(T::into_value).collect::<Vec<_>>().into())
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        Value::Record(self.into_iter().map(|(key, value)| (key, value.into_value())).collect())
+    }
+}
+
+/// `Ok` gives back `Okay(value)` and `Err` an `Oops` with its message
+impl<T: IntoValue, E: fmt::Display> IntoValue for Result<T, E> {
+    fn into_value(self) -> Value {
+        match self {
+            Ok(value) => Value::Okay(Box::new(value.into_value())),
+            Err(e) => Value::oops(e.to_string()),
+        }
+    }
+}
+
+/// A Rust closure taking up to six arguments that convert from values
+pub trait HostFunction<Args>: 'static {
+    fn arity(&self) -> usize;
+
+    /// Call with the arguments converted, or say which one would not
+    fn call(&mut self, args: &[Value]) -> Result<Value, usize>;
+}
+
+macro_rules! host_function {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg: FromValue),*> HostFunction<($($arg,)*)> for F
+        where
+            F: FnMut($($arg),*) -> R + 'static,
+            R: IntoValue,
+        {
+            fn arity(&self) -> usize {
+                <[&str]>::len(&[$(stringify!($arg)),*])
+            }
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn call(&mut self, args: &[Value]) -> Result<Value, usize> {
+                let mut args = args.iter().enumerate();
+                $(
+                    let (index, value) = args.next().expect("the arity was checked");
+                    let $arg = $arg::from_value(value).ok_or(index)?;
+                )*
+                Ok(self($($arg),*).into_value())
+            }
+        }
+    };
+}
+
+host_function!();
+host_function!(A);
+host_function!(A, B);
+host_function!(A, B, C);
+host_function!(A, B, C, D);
+host_function!(A, B, C, D, E);
+host_function!(A, B, C, D, E, G);
+
@@ -0,0 +1,126 @@
+//! Debugger Hooks for the WokeLang Interpreter
+//!
+//! An interpreter given a [`Debugger`] calls it before every statement,
+//! with a [`DebugView`] of the call stack and the variables each function
+//! can see, and as each function call starts and ends. The debugger
+//! decides before a statement whether to pause, e.g. at a breakpoint or
+//! after a step, and can stop the program. `woke debug` builds a Debug
+//! Adapter Protocol server on this, and `woke run --profile` times calls
+//! with it.
+
+use super::{Environment, Value};
+use crate::ast::{Span, Symbol};
+use std::collections::HashMap;
+
+/// What the program does after a debugger has been called
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Run the statement
+    Continue,
+    /// End the program with [`super::RuntimeError::Stopped`]
+    Stop,
+}
+
+/// Called by the interpreter before each statement runs
+pub trait Debugger {
+    /// `span` is the statement about to run; the innermost frame of
+    /// `view` is the function it is in
+    fn before_statement(&mut self, span: &Span, view: &DebugView<'_>) -> DebugAction;
+
+    /// Called as a call to the WokeLang function `function` starts
+    fn enter_function(&mut self, _function: &str) {}
+
+    /// Called as the innermost call ends, however it ends
+    fn leave_function(&mut self) {}
+}
+
+/// A function call on the interpreter's stack
+#[derive(Debug, Clone)]
+pub(super) struct Frame {
+    pub(super) function: String,
+    /// How many variable scopes there were when the call started
+    pub(super) scope_base: usize,
+    /// The statement running in this call
+    pub(super) span: Span,
+}
+
+/// The paused program, as a debugger sees it
+pub struct DebugView<'a> {
+    pub(super) env: &'a Environment,
+    pub(super) frames: &'a [Frame],
+}
+
+/// One frame of the call stack shown to a debugger
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    /// The function's name, or `<top level>` outside any function
+    pub function: String,
+    /// The statement running in the frame
+    pub span: Span,
+}
+
+impl DebugView<'_> {
+    /// How many function calls deep the program is
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The function running, or `None` outside any function
+    pub fn function(&self) -> Option<&str> {
+        self.frames.last().map(|frame| frame.function.as_str())
+    }
+
+    /// The call stack, innermost call first
+    pub fn stack(&self) -> Vec<StackFrame> {
+        let mut stack: Vec<StackFrame> = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| StackFrame {
+                function: frame.function.clone(),
+                span: frame.span.clone(),
+            })
+            .collect();
+        if stack.is_empty() {
+            stack.push(StackFrame {
+                function: "<top level>".to_string(),
+                span: 0..0,
+            });
+        }
+        stack
+    }
+
+    /// Variables local to a frame, counting from the innermost call at 0,
+    /// sorted by name; an inner block's variable hides an outer one
+    pub fn locals(&self, frame: usize) -> Vec<(String, Value)> {
+        let scopes = &self.env.scopes;
+        let Some(index) = self.frames.len().checked_sub(frame + 1) else {
+            return Vec::new();
+        };
+        let start = self.frames[index].scope_base;
+        let end = self.frames.get(index + 1).map_or(scopes.len(), |f| f.scope_base);
+        sorted(scopes.get(start..end.min(scopes.len())).unwrap_or_default())
+    }
+
+    /// Variables outside every function, sorted by name
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        let base = self.frames.first().map_or(self.env.scopes.len(), |f| f.scope_base);
+        sorted(&self.env.scopes[..base.min(self.env.scopes.len())])
+    }
+
+    /// The value a name has in the innermost frame
+    pub fn lookup(&self, name: &str) -> Option<&Value> {
+        self.env.get(Symbol::intern(name))
+    }
+}
+
+fn sorted(scopes: &[HashMap<Symbol, Value>]) -> Vec<(String, Value)> {
+    let mut visible: HashMap<&Symbol, &Value> = HashMap::new();
+    for scope in scopes {
+        visible.extend(scope);
+    }
+    let mut variables: Vec<(String, Value)> =
+        visible.into_iter().map(|(name, value)| (name.to_string(), value.clone())).collect();
+    variables.sort_by(|a, b| a.0.cmp(&b.0));
+    variables
+}
@@ -0,0 +1,304 @@
+//! Serde Support for Values
+//!
+//! [`Value`] implements `Serialize` and `Deserialize`, so a host can hand a
+//! script structured data, and keep what it gives back, in any serde
+//! format. Values JSON already has a shape for are written as plain data:
+//! `Unit` as null, records as objects, arrays as arrays. The rest are
+//! written as an object with a single `$`-prefixed key, so that reading a
+//! value back gives exactly the value written:
+//!
+//! | Value | Written as |
+//! |-------|------------|
+//! | `Okay(v)` | `{"$okay": v}` |
+//! | `Oops(msg)` | `{"$oops": "msg"}` |
+//! | `Bytes` | `{"$bytes": "<base64>"}` |
+//! | `Decimal` | `{"$decimal": "1.50"}` |
+//! | `DateTime` | `{"$datetime": "<RFC 3339>"}` |
+//! | `Duration` | `{"$duration": [seconds, nanoseconds]}` |
+//! | NaN and infinite floats | `{"$float": "NaN"}`, `"inf"` or `"-inf"` |
+//!
+//! A record key that starts with `$` is written with a second `$` in front
+//! of it, so no record is ever mistaken for one of these. Functions and
+//! channels only exist while a program runs and cannot be serialized.
+//!
+//! Unlike `std.json`, which turns values into whatever JSON is most natural
+//! for other programs to read, this mapping never loses anything. Secrets
+//! are written as they are; use [`Value::redacted`] first when the result
+//! leaves the host.
+
+use super::Value;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::SecondsFormat;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+
+impl Value {
+    /// The value as JSON, in the lossless form described in this module
+    pub fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+
+    /// Read a value back from JSON written by [`Value::to_json`], or from
+    /// any plain JSON a host has
+    pub fn from_json(json: serde_json::Value) -> Result<Value, serde_json::Error> {
+        Value::deserialize(json)
+    }
+}
+
+fn tagged<S: Serializer, T: Serialize + ?Sized>(serializer: S, tag: &str, value: &T) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(tag, value)?;
+    map.end()
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Int(n) => serializer.serialize_i64(*n),
+            Value::Float(f) if f.is_nan() => tagged(serializer, "$float", "NaN"),
+            Value::Float(f) if f.is_infinite() => {
+                tagged(serializer, "$float", if *f > 0.0 { "inf" } else { "-inf" })
+            }
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::Decimal(d) => tagged(serializer, "$decimal", &d.to_string()),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Unit => serializer.serialize_unit(),
+            Value::Array(items) => serializer.collect_seq(items.iter()),
+            Value::Record(fields) => {
+                let mut keys: Vec<_> = fields.keys().collect();
+                keys.sort();
+                let mut map = serializer.serialize_map(Some(keys.len()))?;
+                for key in keys {
+                    if key.starts_with('$') {
+                        map.serialize_entry(&format!("${}", key), &fields[key])?;
+                    } else {
+                        map.serialize_entry(key, &fields[key])?;
+                    }
+                }
+                map.end()
+            }
+            Value::Okay(inner) => tagged(serializer, "$okay", inner.as_ref()),
+            Value::Oops(error) => tagged(serializer, "$oops", error.as_ref()),
+            Value::Bytes(bytes) => tagged(serializer, "$bytes", &BASE64.encode(bytes)),
+            Value::DateTime(dt) => {
+                tagged(serializer, "$datetime", &dt.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            Value::Duration(d) => {
+                let secs = d.num_seconds();
+                let nanos = d.subsec_nanos();
+                tagged(serializer, "$duration", &(secs, nanos))
+            }
+            Value::Function(_) | Value::VmFunction(_) => Err(ser::Error::custom("a function cannot be serialized")),
+            Value::Channel(_) => Err(ser::Error::custom("a channel cannot be serialized")),
+        }
+    }
+}
+
+/// Turn the payload of a `$`-tagged object back into the value it stands for
+fn untag<E: de::Error>(tag: &str, payload: Value) -> Result<Value, E> {
+    let invalid = || E::custom(format!("invalid {} value: {}", tag, payload));
+    match (tag, &payload) {
+        ("$okay", _) => Ok(Value::Okay(Box::new(payload))),
+        ("$oops", _) => Ok(Value::Oops(Box::new(payload))),
+        ("$bytes", Value::String(text)) => BASE64.decode(text.as_bytes()).map(Value::Bytes).map_err(|_| invalid()),
+        ("$decimal", Value::String(text)) => text.parse().map(Value::Decimal).map_err(|_| invalid()),
+        ("$datetime", Value::String(text)) => chrono::DateTime::parse_from_rfc3339(text)
+            .map(Value::DateTime)
+            .map_err(|_| invalid()),
+        ("$duration", Value::Array(parts)) => match parts.as_slice() {
+            [Value::Int(secs), Value::Int(nanos)] => chrono::TimeDelta::try_seconds(*secs)
+                .and_then(|d| d.checked_add(&chrono::TimeDelta::nanoseconds(*nanos)))
+                .map(Value::Duration)
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        },
+        ("$float", Value::String(text)) => match text.as_str() {
+            "NaN" => Ok(Value::Float(f64::NAN)),
+            "inf" => Ok(Value::Float(f64::INFINITY)),
+            "-inf" => Ok(Value::Float(f64::NEG_INFINITY)),
+            _ => Err(invalid()),
+        },
+        _ => Err(invalid()),
+    }
+}
+
+const TAGS: &[&str] = &["$okay", "$oops", "$bytes", "$decimal", "$datetime", "$duration", "$float"];
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a WokeLang value")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Bool(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<Value, E> {
+        Ok(Value::Int(n))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Value, E> {
+        i64::try_from(n)
+            .map(Value::Int)
+            .map_err(|_| E::custom(format!("integer {} is too large for an Int", n)))
+    }
+
+    fn visit_f64<E>(self, f: f64) -> Result<Value, E> {
+        Ok(Value::Float(f))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.to_string().into()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Value, E> {
+        Ok(Value::String(s.into()))
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(bytes.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(bytes))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Unit)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Unit)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1024));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items.into()))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries: Vec<(String, Value)> = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        if let [(key, _)] = entries.as_slice() {
+            if TAGS.contains(&key.as_str()) {
+                let (key, payload) = entries.pop().expect("one entry");
+                return untag(&key, payload);
+            }
+        }
+        let fields: HashMap<String, Value> = entries
+            .into_iter()
+            .map(|(key, value)| match key.strip_prefix('$') {
+                Some(rest) if rest.starts_with('$') => (rest.to_string(), value),
+                _ => (key, value),
+            })
+            .collect();
+        Ok(Value::Record(fields))
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Closure;
+    use serde_json::json;
+
+    fn round_trip(value: &Value) -> Value {
+        let text = serde_json::to_string(value).unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn test_plain_values_are_plain_json() {
+        let mut record = HashMap::new();
+        record.insert("name".to_string(), Value::String("Ada".to_string().into()));
+        record.insert("tags".to_string(), Value::Array(vec![Value::Int(1), Value::Float(2.5)].into()));
+        record.insert("done".to_string(), Value::Bool(false));
+        record.insert("note".to_string(), Value::Unit);
+        let value = Value::Record(record);
+
+        let json = value.to_json().unwrap();
+        assert_eq!(json, json!({"name": "Ada", "tags": [1, 2.5], "done": false, "note": null}));
+        assert_eq!(Value::from_json(json).unwrap(), value);
+    }
+
+    #[test]
+    fn test_round_trip_is_lossless() {
+        let mut record = HashMap::new();
+        record.insert("$okay".to_string(), Value::Int(1));
+        record.insert("$$cash".to_string(), Value::Float(1.0));
+        let values = vec![
+            Value::Okay(Box::new(Value::Okay(Box::new(Value::Unit)))),
+            Value::oops("no luck"),
+            Value::Oops(Box::new(Value::Record(HashMap::from([("code".to_string(), Value::Int(404))])))),
+            Value::Bytes(vec![0, 0x7f, 0xff]),
+            Value::Decimal("-12.50".parse().unwrap()),
+            Value::DateTime(chrono::DateTime::parse_from_rfc3339("2024-03-01T09:30:00.25+05:30").unwrap()),
+            Value::Duration(chrono::TimeDelta::milliseconds(-1500)),
+            Value::Duration(chrono::TimeDelta::days(3) + chrono::TimeDelta::nanoseconds(7)),
+            Value::Float(f64::INFINITY),
+            Value::Float(f64::NEG_INFINITY),
+            Value::Float(1.0),
+            Value::Int(i64::MIN),
+            Value::Record(record),
+        ];
+        for value in &values {
+            assert_eq!(&round_trip(value), value, "{}", serde_json::to_string(value).unwrap());
+        }
+        assert!(matches!(round_trip(&Value::Float(f64::NAN)), Value::Float(f) if f.is_nan()));
+
+        // Another self-describing format reads the same shapes
+        let yaml = serde_yaml::to_string(&values).unwrap();
+        assert_eq!(serde_yaml::from_str::<Vec<Value>>(&yaml).unwrap(), values);
+    }
+
+    #[test]
+    fn test_tagged_forms() {
+        assert_eq!(Value::Okay(Box::new(Value::Int(3))).to_json().unwrap(), json!({"$okay": 3}));
+        assert_eq!(Value::Bytes(b"hi".to_vec()).to_json().unwrap(), json!({"$bytes": "aGk="}));
+        assert_eq!(Value::Decimal("1.50".parse().unwrap()).to_json().unwrap(), json!({"$decimal": "1.50"}));
+        assert_eq!(
+            Value::Duration(chrono::TimeDelta::milliseconds(1500)).to_json().unwrap(),
+            json!({"$duration": [1, 500000000]})
+        );
+
+        // Unknown `$` keys are just record fields; bad payloads are errors
+        let record = Value::from_json(json!({"$other": 1})).unwrap();
+        assert!(matches!(record, Value::Record(ref fields) if fields.contains_key("$other")));
+        assert!(Value::from_json(json!({"$bytes": 5})).is_err());
+        assert!(Value::from_json(json!(u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_functions_cannot_be_serialized() {
+        let closure = Closure {
+            params: Vec::new(),
+            body: crate::ast::LambdaBody::Block(Vec::new()),
+            env: Default::default(),
+        };
+        let err = Value::Array(vec![Value::Function(closure)].into()).to_json().unwrap_err();
+        assert!(err.to_string().contains("function"));
+    }
+}
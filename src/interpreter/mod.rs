@@ -1,21 +1,42 @@
+mod binding;
+mod debug;
+mod interop;
+mod native;
 mod value;
 
-pub use value::{CapturedEnv, ChannelHandle, Closure, Value};
+pub use binding::{BindingError, FromValue, HostFunction, IntoValue, Signature};
+pub use debug::{DebugAction, DebugView, Debugger, StackFrame};
+pub use native::{NativeFn, NativeFunction};
+pub use value::{CapturedEnv, ChannelHandle, Closure, Text, Value, VmClosure};
 
 use crate::ast::*;
+use crate::explain;
+use crate::security::policy::parse_capability;
+use crate::security::{
+    consent, function_scope, limits, secrets, Budget, Capability, CapabilityRegistry,
+    ConsentDuration, ConsentError, ConsentMode, ConsentStore, Footprint, LimitExceeded, Limits,
+    Manifest, Policy, SuperpowerDeclaration, CONSENT_SCOPE,
+};
+use crate::stdlib::io::Answer;
+use crate::stdlib::{decimal, duration, log, math, string, StdlibError, StdlibRegistry};
+use rust_decimal::prelude::ToPrimitive;
+use crate::testing::{TestCase, TestKind};
+use crate::typechecker::{CheckedProgram, ModuleScope};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::io::{self, Write};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
 use std::rc::Rc;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum RuntimeError {
-    #[error("Undefined variable: {0}")]
-    UndefinedVariable(String),
+    #[error("Undefined variable: {name}")]
+    UndefinedVariable { name: String, suggestion: Option<String> },
 
-    #[error("Undefined function: {0}")]
-    UndefinedFunction(String),
+    #[error("Undefined function: {name}")]
+    UndefinedFunction { name: String, suggestion: Option<String> },
 
     #[error("Type error: {0}")]
     TypeError(String),
@@ -43,20 +64,104 @@ pub enum RuntimeError {
 
     #[error("I/O error: {0}")]
     IoError(String),
+
+    #[error("{function}: {message}")]
+    Stdlib { function: String, message: String },
+
+    #[error("Stopped by the debugger")]
+    Stopped,
+
+    #[error(transparent)]
+    LimitExceeded(#[from] LimitExceeded),
+
+    /// `exit(code)`, on its way out of the program
+    #[error("Exited with code {0}")]
+    Exit(i64),
+}
+
+impl RuntimeError {
+    /// The error's code in the `woke explain` catalog
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeError::UndefinedVariable { .. } => "WOKE-E0300",
+            RuntimeError::UndefinedFunction { .. } => "WOKE-E0301",
+            RuntimeError::TypeError(_) => "WOKE-E0302",
+            RuntimeError::DivisionByZero => "WOKE-E0303",
+            RuntimeError::ConsentDenied(_) => "WOKE-E0304",
+            RuntimeError::Complaint(_) => "WOKE-E0305",
+            RuntimeError::IndexOutOfBounds(_) => "WOKE-E0306",
+            RuntimeError::NegativeIndex(_) => "WOKE-E0307",
+            RuntimeError::ArityMismatch { .. } => "WOKE-E0308",
+            RuntimeError::RecursionLimitExceeded => "WOKE-E0309",
+            RuntimeError::IoError(_) => "WOKE-E0310",
+            RuntimeError::Stdlib { .. } => "WOKE-E0311",
+            RuntimeError::Stopped => "WOKE-E0312",
+            RuntimeError::LimitExceeded(e) => e.code(),
+            RuntimeError::Exit(_) => "WOKE-E0315",
+        }
+    }
+
+    /// Whether the error ends the run wherever it happens, so that
+    /// `attempt safely` does not catch it
+    pub fn ends_run(&self) -> bool {
+        matches!(self, RuntimeError::Stopped | RuntimeError::LimitExceeded(_) | RuntimeError::Exit(_))
+    }
+
+    /// What to try instead, for a name that looks like a typo of another
+    pub fn help(&self) -> Option<String> {
+        match self {
+            RuntimeError::UndefinedVariable { suggestion: Some(name), .. }
+            | RuntimeError::UndefinedFunction { suggestion: Some(name), .. } => {
+                Some(format!("did you mean `{}`?", name))
+            }
+            _ => None,
+        }
+    }
+
+    /// The error with the secret text being handled masked in its message
+    /// (see [`secrets::handling`])
+    pub fn redacted(self) -> Self {
+        let redact = |text: String| secrets::redact(&text).into_owned();
+        match self {
+            RuntimeError::TypeError(message) => RuntimeError::TypeError(redact(message)),
+            RuntimeError::ConsentDenied(message) => RuntimeError::ConsentDenied(redact(message)),
+            RuntimeError::Complaint(message) => RuntimeError::Complaint(redact(message)),
+            RuntimeError::IoError(message) => RuntimeError::IoError(redact(message)),
+            RuntimeError::Stdlib { function, message } => RuntimeError::Stdlib {
+                function,
+                message: redact(message),
+            },
+            other => other,
+        }
+    }
 }
 
 type Result<T> = std::result::Result<T, RuntimeError>;
 
+/// Make a call of a builtin, standard library or host function given
+/// `args`: while it runs, the secret text of its arguments is masked in the
+/// audit entries it makes and in the error it fails with, and what it gives
+/// back, passed through `taint`, is secret if any argument was
+fn handling_secrets<T>(args: &[Value], call: impl FnOnce() -> Result<T>, taint: impl FnOnce(T) -> T) -> Result<T> {
+    let texts: Vec<String> = args.iter().flat_map(Value::secret_texts).collect();
+    if texts.is_empty() {
+        return call();
+    }
+    let _handling = secrets::handling(texts);
+    call().map(taint).map_err(RuntimeError::redacted)
+}
+
 /// Control flow signals for return statements
 enum ControlFlow {
     Continue,
     Return(Value),
 }
 
-/// Runtime environment for variable bindings
+/// Runtime environment for variable bindings, keyed by interned name so a
+/// lookup hashes an address rather than the name's text
 #[derive(Clone)]
 struct Environment {
-    scopes: Vec<HashMap<String, Value>>,
+    scopes: Vec<HashMap<Symbol, Value>>,
 }
 
 impl Environment {
@@ -74,25 +179,38 @@ fn pop_scope(&mut self) {
         self.scopes.pop();
     }
 
-    fn define(&mut self, name: String, value: Value) {
+    fn define(&mut self, name: Symbol, value: Value) {
         if let Some(scope) = self.scopes.last_mut() {
             scope.insert(name, value);
         }
     }
 
-    fn get(&self, name: &str) -> Option<&Value> {
+    fn get(&self, name: Symbol) -> Option<&Value> {
         for scope in self.scopes.iter().rev() {
-            if let Some(value) = scope.get(name) {
+            if let Some(value) = scope.get(&name) {
                 return Some(value);
             }
         }
         None
     }
 
-    fn set(&mut self, name: &str, value: Value) -> bool {
+    /// Let go of the binding's hold on `text` if it still has it, leaving
+    /// the caller the only holder, free to append to it in place
+    fn release(&mut self, name: Symbol, text: &Text) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(slot) = scope.get_mut(&name) {
+                if matches!(slot, Value::String(held) if Text::ptr_eq(held, text)) {
+                    *slot = Value::Unit;
+                }
+                return;
+            }
+        }
+    }
+
+    fn set(&mut self, name: Symbol, value: Value) -> bool {
         for scope in self.scopes.iter_mut().rev() {
-            if scope.contains_key(name) {
-                scope.insert(name.to_string(), value);
+            if let Some(slot) = scope.get_mut(&name) {
+                *slot = value;
                 return true;
             }
         }
@@ -101,18 +219,190 @@ fn set(&mut self, name: &str, value: Value) -> bool {
 }
 
 /// The WokeLang interpreter
+/// Functions `call_builtin` knows by name
+const BUILTINS: &[&str] = &[
+    "print",
+    "prettyPrint",
+    "toString",
+    "len",
+    "isOkay",
+    "isOops",
+    "toInt",
+    "unwrapOr",
+    "getError",
+    "format",
+    "args",
+    "listSuperpowers",
+    "hasSuperpower",
+    "renounce",
+    "gratitudeEntries",
+    "ask",
+    "askNumber",
+    "askYesNo",
+    "exit",
+];
+
 /// Maximum recursion depth to prevent stack overflow
 const MAX_RECURSION_DEPTH: usize = 1000;
 
+/// Decides consent requests that are not already cached
+pub type ConsentHandler = Box<dyn FnMut(&str) -> bool>;
+
+
+/// The result of one `expect` block
+#[derive(Debug)]
+pub struct ExpectOutcome {
+    pub description: String,
+    pub span: Span,
+    /// Why the block failed, or `None` if it passed
+    pub error: Option<RuntimeError>,
+}
+
+impl ExpectOutcome {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// What became of a side quest
+#[derive(Debug)]
+pub enum SideQuestStatus {
+    Done,
+    /// It stopped with an error, which the program does not fail for,
+    /// given by its catalog code, if it has one, and message, as either
+    /// engine reports it
+    Failed {
+        code: Option<&'static str>,
+        message: String,
+    },
+    /// Skipped by `#side quests off;` or by the host
+    Skipped,
+}
+
+/// The result of one side quest, shown as `tidyUp: done`
+#[derive(Debug)]
+pub struct SideQuestOutcome {
+    pub name: String,
+    pub span: Span,
+    pub status: SideQuestStatus,
+}
+
+impl fmt::Display for SideQuestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.status {
+            SideQuestStatus::Done => write!(f, "{}: done", self.name),
+            SideQuestStatus::Failed { message, .. } => write!(f, "{}: failed: {}", self.name, message),
+            SideQuestStatus::Skipped => write!(f, "{}: skipped", self.name),
+        }
+    }
+}
+
+/// In-memory output sink that can be read back after a run
+#[derive(Clone, Default)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far, lossily decoded as UTF-8
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    /// Everything written so far, as `contents` gives it, leaving the
+    /// buffer empty
+    pub fn take(&self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut *self.0.borrow_mut())).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What an interpreter has built up over a session, kept by
+/// [`Interpreter::snapshot`] to go back to with [`Interpreter::restore`]:
+/// the functions, workers and side quests defined, the modules imported,
+/// the global variables, the answers to consent requests and the gratitude
+/// declared
+#[derive(Clone)]
+pub struct InterpreterSnapshot {
+    functions: HashMap<String, FunctionDef>,
+    workers: HashMap<String, WorkerDef>,
+    side_quests: Vec<SideQuestDef>,
+    scope: ModuleScope,
+    globals: HashMap<Symbol, Value>,
+    consent_cache: HashMap<String, bool>,
+    gratitude: Vec<(String, String)>,
+}
+
+impl InterpreterSnapshot {
+    /// The definitions alone, without the variables, consent answers and
+    /// gratitude, for starting over with the functions kept
+    pub fn definitions(mut self) -> Self {
+        self.globals.clear();
+        self.consent_cache.clear();
+        self.gratitude.clear();
+        self
+    }
+
+    /// How many global variables it holds
+    pub fn globals(&self) -> usize {
+        self.globals.len()
+    }
+
+    /// How many functions it holds
+    pub fn functions(&self) -> usize {
+        self.functions.len()
+    }
+}
+
 pub struct Interpreter {
     env: Environment,
     functions: HashMap<String, FunctionDef>,
     workers: HashMap<String, WorkerDef>,
     gratitude: Vec<(String, String)>,
+    /// Side quests loaded, to run once `main` is done
+    side_quests: Vec<SideQuestDef>,
+    skip_side_quests: bool,
+    side_quest_outcomes: Vec<SideQuestOutcome>,
     consent_cache: HashMap<String, bool>,
+    /// Decides consent requests; `None` asks on stdin
+    consent_handler: Option<ConsentHandler>,
+    output: Box<dyn Write>,
+    /// Where `ask` reads answers from; `None` reads stdin
+    input: Option<Box<dyn BufRead>>,
+    stdlib: StdlibRegistry,
+    capabilities: CapabilityRegistry,
+    /// The modules imported and functions defined by the programs loaded
+    scope: ModuleScope,
+    /// Name of the program's own module, the first part of the
+    /// capability scope of its functions (`main:readConfig`)
+    module_name: String,
     verbose: bool,
     care_mode: bool,
     recursion_depth: usize,
+    /// Statements run and expressions evaluated so far
+    steps: u64,
+    /// Limits on a run, from the host and `#timeout`/`#maxmemory`
+    limits: Limits,
+    /// How far the run in progress has got towards its limits
+    budget: Option<Budget>,
+    /// Called before each statement, when debugging
+    debugger: Option<Box<dyn Debugger>>,
+    /// Function calls in progress, kept while debugging
+    frames: Vec<debug::Frame>,
+    /// Functions the host registered
+    natives: HashMap<String, NativeFunction>,
 }
 
 impl Interpreter {
@@ -122,15 +412,573 @@ pub fn new() -> Self {
             functions: HashMap::new(),
             workers: HashMap::new(),
             gratitude: Vec::new(),
+            side_quests: Vec::new(),
+            skip_side_quests: false,
+            side_quest_outcomes: Vec::new(),
             consent_cache: HashMap::new(),
+            consent_handler: None,
+            output: Box::new(io::stdout()),
+            input: None,
+            stdlib: StdlibRegistry::new(),
+            capabilities: CapabilityRegistry::new(),
+            scope: ModuleScope::new(),
+            module_name: "main".to_string(),
             verbose: false,
             care_mode: true,
             recursion_depth: 0,
+            steps: 0,
+            limits: Limits::default(),
+            budget: None,
+            debugger: None,
+            frames: Vec::new(),
+            natives: HashMap::new(),
+        }
+    }
+
+    /// Send program output (`print` and verbose tracing) to `output`
+    /// instead of stdout
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Read the answers to `ask`, `askNumber` and `askYesNo` from `input`
+    /// instead of stdin, e.g. to script them in a test
+    pub fn with_input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Read answers from `input` from now on, giving back the input read
+    /// so far, e.g. for a host to keep between runs
+    pub(crate) fn swap_input(&mut self, input: Option<Box<dyn BufRead>>) -> Option<Box<dyn BufRead>> {
+        std::mem::replace(&mut self.input, input)
+    }
+
+    /// Seed `std.math`'s random functions so every run draws the same
+    /// values; the generator is shared by everything on this thread
+    pub fn with_seed(self, seed: u64) -> Self {
+        math::set_seed(seed);
+        self
+    }
+
+    /// Decide consent requests with `handler` instead of prompting on stdin
+    pub fn with_consent_handler(mut self, handler: impl FnMut(&str) -> bool + 'static) -> Self {
+        self.consent_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Allow what a project policy (`woke.policy.toml`) lists without
+    /// asking; everything else is still asked for
+    pub fn with_policy(mut self, policy: &Policy) -> Self {
+        self.capabilities.apply_policy(policy);
+        self
+    }
+
+    /// Start from capabilities the host has already set up, e.g. with
+    /// policies, a consent store and a sandbox root applied; grants made
+    /// so far are replaced
+    pub fn with_capabilities(mut self, capabilities: CapabilityRegistry) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The capabilities granted so far, e.g. to keep the consent store
+    /// for a later run
+    pub fn capabilities_mut(&mut self) -> &mut CapabilityRegistry {
+        &mut self.capabilities
+    }
+
+    /// Trace execution as the `#verbose on` pragma does, including
+    /// `debug` log records
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        log::set_min_level(if verbose { log::Level::Debug } else { log::Level::Info });
+        self
+    }
+
+    /// Skip side quests, as the `#side quests off` pragma does
+    pub fn with_skip_side_quests(mut self, skip: bool) -> Self {
+        self.skip_side_quests = skip;
+        self
+    }
+
+    /// Stop runs that take longer or hold more memory than `limits`; where
+    /// the program's own pragmas set lower limits, those hold
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits.tighten(limits);
+        self
+    }
+
+    /// Allow exactly what a script's signed manifest declares, refusing
+    /// everything else without asking. Check the manifest with
+    /// [`Manifest::verify`] and get the user's agreement first.
+    pub fn with_manifest(self, manifest: &Manifest) -> Self {
+        self.with_policy(&manifest.allowed()).with_deny_all()
+    }
+
+    /// Refuse every consent and superpower that has not been allowed
+    /// already, e.g. by [`Interpreter::with_policy`], without asking
+    pub fn with_deny_all(self) -> Self {
+        self.with_consent_mode(ConsentMode::Deny)
+    }
+
+    /// Run confined to `root`: the network, programs, processes and
+    /// environment variables are unavailable, their `std` functions do not
+    /// exist, and files can only be used inside `root`, whatever is granted
+    pub fn with_sandbox(mut self, root: &Path) -> Self {
+        self.stdlib = StdlibRegistry::sandboxed();
+        self.capabilities.sandbox(root);
+        self
+    }
+
+    /// Answer consent blocks and superpower requests the way `mode` says
+    pub fn with_consent_mode(mut self, mode: ConsentMode) -> Self {
+        self.set_consent_mode(mode);
+        self
+    }
+
+    /// Answer requests as the `WOKE_CONSENT`, `WOKE_CONSENT_FILE` and
+    /// `WOKE_CONSENT_TIMEOUT` environment variables say, returning the mode
+    pub fn configure_consent_from_env(&mut self) -> std::result::Result<ConsentMode, ConsentError> {
+        let mode = self.capabilities.configure_from_env()?;
+        self.set_consent_mode(mode);
+        Ok(mode)
+    }
+
+    fn set_consent_mode(&mut self, mode: ConsentMode) {
+        self.capabilities.set_consent_mode(mode);
+        match mode {
+            // Keep any handler the host installed
+            ConsentMode::Ask => {}
+            ConsentMode::Allow => self.consent_handler = Some(Box::new(|_| true)),
+            ConsentMode::Deny | ConsentMode::Policy => self.consent_handler = Some(Box::new(|_| false)),
+        }
+    }
+
+    /// Name the program's module, e.g. after its file, so superpowers are
+    /// granted to `name:function` rather than `main:function`
+    pub fn with_module_name(mut self, name: &str) -> Self {
+        self.module_name = name.to_string();
+        self
+    }
+
+    /// Remember consent decisions in `store`, for consent blocks and
+    /// superpowers alike, and use the ones it already holds. Only answers
+    /// given at the stdin prompt are remembered beyond this run.
+    pub fn with_consent_store(mut self, store: ConsentStore) -> Self {
+        self.capabilities.set_consent_store(store);
+        self
+    }
+
+    /// Remember consent decisions for the program at `path`, and use the
+    /// ones made for it before. Without this, nothing is remembered.
+    pub fn with_program(mut self, path: &Path) -> Self {
+        self.capabilities.set_program(Some(path));
+        self
+    }
+
+    /// Call `debugger` before every statement, letting it pause the
+    /// program, look at its variables or stop it, and as each function
+    /// call starts and ends
+    pub fn with_debugger(mut self, debugger: impl Debugger + 'static) -> Self {
+        self.debugger = Some(Box::new(debugger));
+        self
+    }
+
+    /// Make the Rust function `func` callable from programs as `name`,
+    /// with `arity` arguments, replacing any registered before under that
+    /// name. Tag it with the superpowers it uses through
+    /// [`NativeFunction::requires`].
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl FnMut(&[Value]) -> std::result::Result<Value, StdlibError> + 'static,
+    ) -> &mut NativeFunction {
+        self.add_native(NativeFunction::new(name, arity, func))
+    }
+
+    /// Make the Rust closure `func` callable from programs under a
+    /// WokeLang signature, `to greet(name: String, count: Int) → String`,
+    /// with its arguments converted to the closure's types. Declare it to
+    /// a type checker through [`Interpreter::type_checker`] to have calls
+    /// checked before they run.
+    pub fn bind<Args>(
+        &mut self,
+        signature: &str,
+        func: impl HostFunction<Args>,
+    ) -> std::result::Result<&mut NativeFunction, BindingError> {
+        let native = Signature::parse(signature)?.bind(func)?;
+        Ok(self.add_native(native))
+    }
+
+    /// A type checker that knows the host functions registered so far,
+    /// checking calls to those bound with a signature against it
+    pub fn type_checker(&self) -> crate::typechecker::TypeChecker {
+        crate::typechecker::TypeChecker::with_natives(self.natives.values())
+    }
+
+    /// Add the functions of a loaded plugin, callable by their full names
+    /// (`std.gpio.write`) or through an import (`gpio.write`)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_plugin(mut self, plugin: &crate::ffi::Plugin) -> Self {
+        for native in plugin.natives() {
+            self.add_native(native);
+        }
+        self
+    }
+
+    pub(crate) fn add_native(&mut self, native: NativeFunction) -> &mut NativeFunction {
+        let name = native.name().to_string();
+        self.natives.insert(name.clone(), native);
+        self.natives.get_mut(&name).expect("just registered")
+    }
+
+    /// The host functions, leaving none registered
+    pub(crate) fn take_natives(&mut self) -> Vec<NativeFunction> {
+        self.natives.drain().map(|(_, native)| native).collect()
+    }
+
+    /// Show the debugger the statement about to run
+    fn debug_pause(&mut self, span: &Span) -> Result<()> {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.span = span.clone();
+        }
+        let Some(mut debugger) = self.debugger.take() else {
+            return Ok(());
+        };
+        let view = DebugView {
+            env: &self.env,
+            frames: &self.frames,
+        };
+        let action = debugger.before_statement(span, &view);
+        self.debugger = Some(debugger);
+        match action {
+            DebugAction::Continue => Ok(()),
+            DebugAction::Stop => Err(RuntimeError::Stopped),
         }
     }
 
+    /// Write one line of program output
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        writeln!(self.output, "{}", line)
+            .map_err(|e| RuntimeError::IoError(format!("Failed to write output: {}", e)))
+    }
+
+    /// Write a verbose trace line, with the secret text being handled
+    /// masked
+    fn trace(&mut self, line: &str) -> Result<()> {
+        let line = secrets::redact(line).into_owned();
+        self.write_line(&line)
+    }
+
+    /// Run a program's consent blocks and `main`, then its side quests
     pub fn run(&mut self, program: &Program) -> Result<()> {
-        // First pass: collect all function and worker definitions
+        self.run_main(program).map(|_| ())
+    }
+
+    /// Run a program as [`Interpreter::run`] does, giving back the value
+    /// its `main` gives back, the Int it calls `exit` with, or Unit if it
+    /// has no `main`
+    pub fn run_main(&mut self, program: &Program) -> Result<Value> {
+        self.load(program);
+        self.run_loaded(program)
+    }
+
+    /// Run a program the type checker passed, as [`Interpreter::run_main`]
+    /// does, with the scope the checker found
+    pub fn run_checked(&mut self, checked: &CheckedProgram) -> Result<Value> {
+        self.load_checked(checked);
+        self.run_loaded(checked.program())
+    }
+
+    fn run_loaded(&mut self, program: &Program) -> Result<Value> {
+        self.budget = (!self.limits.is_none()).then(|| Budget::start(self.limits));
+        let result = match self.run_items(program) {
+            // Exiting gives back the code, as `main` giving it back does
+            Err(RuntimeError::Exit(code)) => Ok(Value::Int(code)),
+            result => result,
+        };
+        // The run may have gone over its time since the clock was last read
+        let result = result.and_then(|value| self.check_clock().map(|()| value));
+        self.budget = None;
+        result
+    }
+
+    fn run_items(&mut self, program: &Program) -> Result<Value> {
+        // Show gratitude if verbose
+        if self.verbose && !self.gratitude.is_empty() {
+            self.trace("=== Gratitude ===")?;
+            for (recipient, reason) in self.gratitude.clone() {
+                self.trace(&format!("  Thanks to {} for: {}", recipient, reason))?;
+            }
+            self.trace("")?;
+        }
+
+        // Second pass: execute top-level items
+        for item in &program.items {
+            match item {
+                TopLevelItem::ConsentBlock(c) => {
+                    self.execute_consent_block(c)?;
+                }
+                TopLevelItem::Global(decl) => self.remember(decl)?,
+                TopLevelItem::Function(_)
+                | TopLevelItem::WorkerDef(_)
+                | TopLevelItem::GratitudeDecl(_)
+                | TopLevelItem::Pragma(_)
+                | TopLevelItem::Expect(_) => {
+                    // Already processed, or only run by the test runner
+                }
+                _ => {}
+            }
+        }
+
+        // Look for and execute main function
+        let value = match self.functions.contains_key("main") {
+            true => self.call_function(Symbol::intern("main"), vec![])?,
+            false => Value::Unit,
+        };
+        self.run_side_quests();
+        Ok(value)
+    }
+
+    /// Run the side quests loaded, in the order they are defined, unless
+    /// they are skipped. One failing stops neither the others nor the
+    /// program; see [`Interpreter::side_quests`] for how each went.
+    fn run_side_quests(&mut self) {
+        for quest in std::mem::take(&mut self.side_quests) {
+            let status = match self.skip_side_quests {
+                true => SideQuestStatus::Skipped,
+                false => match self.execute_side_quest(&quest) {
+                    Ok(()) => SideQuestStatus::Done,
+                    Err(e) => SideQuestStatus::Failed {
+                        code: Some(e.code()),
+                        message: e.to_string(),
+                    },
+                },
+            };
+            self.side_quest_outcomes.push(SideQuestOutcome {
+                name: quest.name,
+                span: quest.span,
+                status,
+            });
+        }
+    }
+
+    /// Run a side quest in a scope of its own, until its end or a
+    /// `give back`
+    fn execute_side_quest(&mut self, quest: &SideQuestDef) -> Result<()> {
+        let saved = self.env.clone();
+        self.env.push_scope();
+        let mut result = Ok(());
+        for stmt in &quest.body {
+            match self.execute_statement(stmt) {
+                Ok(ControlFlow::Continue) => {}
+                Ok(ControlFlow::Return(_)) => break,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.env = saved;
+        self.recursion_depth = 0;
+        result
+    }
+
+    /// How each side quest of the last run went, in the order they ran
+    pub fn side_quests(&self) -> &[SideQuestOutcome] {
+        &self.side_quest_outcomes
+    }
+
+    /// The side quest outcomes, leaving none
+    pub(crate) fn take_side_quests(&mut self) -> Vec<SideQuestOutcome> {
+        std::mem::take(&mut self.side_quest_outcomes)
+    }
+
+    /// Run each `expect` block in the program instead of `main`
+    ///
+    /// Every block starts from the same global state, and a failing block
+    /// does not stop the ones after it.
+    pub fn run_expectations(&mut self, program: &Program) -> Vec<ExpectOutcome> {
+        self.load(program);
+        if let Err(error) = self.define_globals(program) {
+            // No block can run without the globals, so the one that could
+            // not be bound is the failure
+            let decl = program.items.iter().find_map(|item| match item {
+                TopLevelItem::Global(decl) => Some(decl),
+                _ => None,
+            });
+            return vec![ExpectOutcome {
+                description: decl.map_or_else(String::new, |decl| format!("remember {}", decl.name)),
+                span: decl.map_or_else(Span::default, |decl| decl.span.clone()),
+                error: Some(error),
+            }];
+        }
+
+        let mut outcomes = Vec::new();
+        for item in &program.items {
+            let TopLevelItem::Expect(block) = item else {
+                continue;
+            };
+            let result = self.execute_expect_block(block);
+            outcomes.push(ExpectOutcome {
+                description: block.description.clone(),
+                span: block.span.clone(),
+                error: result.err(),
+            });
+        }
+        outcomes
+    }
+
+    /// Run one test found by [`crate::testing::discover`] instead of `main`
+    pub fn run_test(&mut self, program: &Program, test: &TestCase) -> Result<()> {
+        self.load(program);
+        self.define_globals(program)?;
+        match (test.kind, program.items.get(test.item)) {
+            (TestKind::Expect, Some(TopLevelItem::Expect(block))) => self.execute_expect_block(block),
+            (TestKind::Function, _) => self.call_function(Symbol::intern(&test.name), vec![]).map(|_| ()),
+            _ => Err(RuntimeError::UndefinedFunction {
+                name: test.name.clone(),
+                suggestion: None,
+            }),
+        }
+    }
+
+    /// Run an `expect` block in a scope of its own, leaving the global
+    /// state as it was
+    fn execute_expect_block(&mut self, block: &ExpectBlock) -> Result<()> {
+        let saved = self.env.clone();
+        self.env.push_scope();
+        let result = block
+            .body
+            .iter()
+            .try_for_each(|stmt| self.execute_statement(stmt).map(|_| ()));
+        self.env = saved;
+        self.recursion_depth = 0;
+        result
+    }
+
+    /// Call a function of a program already loaded with [`Interpreter::load`]
+    /// or run, e.g. many times over to time it
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let result = self.call_function(Symbol::intern(name), args);
+        self.recursion_depth = 0;
+        result
+    }
+
+    /// Evaluate an expression typed at a REPL, with the functions and
+    /// imports of the programs loaded or run so far
+    pub fn eval_snippet(&mut self, expr: &Spanned<Expr>) -> Result<Value> {
+        self.capabilities.set_scope(self.module_name.clone());
+        let result = self.evaluate(expr);
+        self.recursion_depth = 0;
+        result
+    }
+
+    /// Run statements typed at a REPL at the top level, so the variables
+    /// they declare stay defined for the snippets after them
+    pub fn exec_snippet(&mut self, statements: &[Statement]) -> Result<()> {
+        self.capabilities.set_scope(self.module_name.clone());
+        let mut result = Ok(());
+        for stmt in statements {
+            match self.execute_statement(stmt) {
+                Ok(ControlFlow::Continue) => {}
+                Ok(ControlFlow::Return(_)) => break,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.recursion_depth = 0;
+        result
+    }
+
+    /// Variables defined at the top level, by name
+    pub fn globals(&self) -> Vec<(&str, &Value)> {
+        let mut globals: Vec<_> = self.env.scopes[0].iter().map(|(name, value)| (name.as_str(), value)).collect();
+        globals.sort_by_key(|(name, _)| *name);
+        globals
+    }
+
+    /// Give a global variable a value, defining it if need be
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.env.scopes[0].insert(Symbol::intern(name), value);
+    }
+
+    /// Functions defined so far, by name
+    pub fn functions(&self) -> Vec<&FunctionDef> {
+        let mut functions: Vec<_> = self.functions.values().collect();
+        functions.sort_by_key(|f| &f.name);
+        functions
+    }
+
+    /// Workers defined so far, by name
+    pub fn workers(&self) -> Vec<&str> {
+        let mut workers: Vec<_> = self.workers.keys().map(String::as_str).collect();
+        workers.sort();
+        workers
+    }
+
+    /// Permissions asked for so far and whether each was granted, by name
+    pub fn consents(&self) -> Vec<(&str, bool)> {
+        let mut consents: Vec<_> = self.consent_cache.iter().map(|(p, granted)| (p.as_str(), *granted)).collect();
+        consents.sort();
+        consents
+    }
+
+    /// Statements run and expressions evaluated so far: the work a program
+    /// did, whatever machine it ran on
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Keep what the session has built up so far, to go back to later
+    pub fn snapshot(&self) -> InterpreterSnapshot {
+        InterpreterSnapshot {
+            functions: self.functions.clone(),
+            workers: self.workers.clone(),
+            side_quests: self.side_quests.clone(),
+            scope: self.scope.clone(),
+            globals: self.env.scopes[0].clone(),
+            consent_cache: self.consent_cache.clone(),
+            gratitude: self.gratitude.clone(),
+        }
+    }
+
+    /// Go back to a snapshot, forgetting whatever was defined, assigned or
+    /// consented to since. How the interpreter is set up, e.g. its output,
+    /// capabilities, host functions and limits, stays as it is.
+    pub fn restore(&mut self, snapshot: &InterpreterSnapshot) {
+        let snapshot = snapshot.clone();
+        self.functions = snapshot.functions;
+        self.workers = snapshot.workers;
+        self.side_quests = snapshot.side_quests;
+        self.scope = snapshot.scope;
+        self.env = Environment::new();
+        self.env.scopes[0] = snapshot.globals;
+        self.consent_cache = snapshot.consent_cache;
+        self.gratitude = snapshot.gratitude;
+    }
+
+    /// First pass: collect definitions, pragmas and imports, without
+    /// running anything
+    pub fn load(&mut self, program: &Program) {
+        self.load_in(program, &ModuleScope::of(program));
+    }
+
+    /// Load a program the type checker passed, as [`Interpreter::load`]
+    /// does, with the scope the checker found
+    pub fn load_checked(&mut self, checked: &CheckedProgram) {
+        self.load_in(checked.program(), checked.scope());
+    }
+
+    fn load_in(&mut self, program: &Program, scope: &ModuleScope) {
+        self.scope.extend(scope);
+        // Code outside any function runs in the module's own scope
+        self.capabilities.set_scope(self.module_name.clone());
         for item in &program.items {
             match item {
                 TopLevelItem::Function(f) => {
@@ -139,6 +987,7 @@ pub fn run(&mut self, program: &Program) -> Result<()> {
                 TopLevelItem::WorkerDef(w) => {
                     self.workers.insert(w.name.clone(), w.clone());
                 }
+                TopLevelItem::SideQuestDef(quest) => self.side_quests.push(quest.clone()),
                 TopLevelItem::GratitudeDecl(g) => {
                     for entry in &g.entries {
                         self.gratitude
@@ -147,66 +996,100 @@ pub fn run(&mut self, program: &Program) -> Result<()> {
                 }
                 TopLevelItem::Pragma(p) => {
                     match p.directive {
-                        PragmaDirective::Verbose => self.verbose = p.enabled,
+                        PragmaDirective::Verbose => {
+                            self.verbose = p.enabled;
+                            log::set_min_level(if p.enabled { log::Level::Debug } else { log::Level::Info });
+                        }
                         PragmaDirective::Care => self.care_mode = p.enabled,
+                        PragmaDirective::SideQuests => self.skip_side_quests = !p.enabled,
+                        PragmaDirective::Timeout(_) | PragmaDirective::MaxMemory(_) => {
+                            self.limits.apply(p.directive)
+                        }
                         PragmaDirective::Strict => {} // TODO
                     }
                 }
+                TopLevelItem::ModuleImport(import) => {
+                    if let Some(needs) = &import.needs {
+                        // The parser has already rejected unknown capabilities
+                        self.capabilities.declare(SuperpowerDeclaration {
+                            name: import.path.parts.join("."),
+                            capabilities: needs
+                                .iter()
+                                .filter_map(|n| parse_capability(&n.kind, n.value.as_deref()).ok())
+                                .collect(),
+                            description: import.reason.clone(),
+                        });
+                    }
+                }
                 _ => {}
             }
         }
+    }
 
-        // Show gratitude if verbose
-        if self.verbose && !self.gratitude.is_empty() {
-            println!("=== Gratitude ===");
-            for (recipient, reason) in &self.gratitude {
-                println!("  Thanks to {} for: {}", recipient, reason);
+    /// Bind a `remember` in the innermost scope, which at the top level
+    /// is the globals' scope
+    fn remember(&mut self, decl: &VarDecl) -> Result<()> {
+        let value = self.evaluate(&decl.value)?.secret_if(decl.secret);
+        if self.verbose {
+            // Lines after the first line up under the trace's indent
+            let shown = value.redacted().pretty().replace('\n', "\n  ");
+            if let Some(unit) = &decl.unit {
+                self.trace(&format!(
+                    "  remember {} = {} measured in {}",
+                    decl.name, shown, unit
+                ))?;
+            } else {
+                self.trace(&format!("  remember {} = {}", decl.name, shown))?;
             }
-            println!();
         }
+        self.env.define(decl.name, value);
+        Ok(())
+    }
 
-        // Second pass: execute top-level items
+    /// Bind the program's top-level `remember`s, in the order written,
+    /// for code run without going through `main`, such as a test
+    pub fn define_globals(&mut self, program: &Program) -> Result<()> {
         for item in &program.items {
-            match item {
-                TopLevelItem::ConsentBlock(c) => {
-                    self.execute_consent_block(c)?;
-                }
-                TopLevelItem::Function(_)
-                | TopLevelItem::WorkerDef(_)
-                | TopLevelItem::GratitudeDecl(_)
-                | TopLevelItem::Pragma(_) => {
-                    // Already processed
-                }
-                _ => {}
+            if let TopLevelItem::Global(decl) = item {
+                self.remember(decl)?;
             }
         }
-
-        // Look for and execute main function
-        if self.functions.contains_key("main") {
-            self.call_function("main", vec![])?;
-        }
-
         Ok(())
     }
 
     fn execute_statement(&mut self, stmt: &Statement) -> Result<ControlFlow> {
+        self.steps += 1;
+        if self.budget.is_some() {
+            self.check_limits()?;
+        }
+        if self.debugger.is_some() {
+            self.debug_pause(stmt.span())?;
+        }
         match stmt {
             Statement::VarDecl(decl) => {
-                let value = self.evaluate(&decl.value)?;
-                if self.verbose {
-                    if let Some(unit) = &decl.unit {
-                        println!("  remember {} = {:?} measured in {}", decl.name, value, unit);
-                    } else {
-                        println!("  remember {} = {:?}", decl.name, value);
-                    }
-                }
-                self.env.define(decl.name.clone(), value);
+                self.remember(decl)?;
                 Ok(ControlFlow::Continue)
             }
             Statement::Assignment(assign) => {
-                let value = self.evaluate(&assign.value)?;
-                if !self.env.set(&assign.target, value) {
-                    return Err(RuntimeError::UndefinedVariable(assign.target.clone()));
+                let value = match &assign.value.node {
+                    // `text = text + more;` appends to the string in place
+                    // rather than copying it, so building one up in a loop
+                    // stays linear
+                    Expr::Binary(BinaryOp::Add, left, right)
+                        if matches!(left.node, Expr::Identifier(name) if name == assign.target) =>
+                    {
+                        self.steps += 1;
+                        let left = self.evaluate(left)?;
+                        let right = self.evaluate(right)?;
+                        if let Value::String(text) = &left {
+                            self.env.release(assign.target, text);
+                        }
+                        self.apply_binary_op(BinaryOp::Add, left, right)?
+                    }
+                    _ => self.evaluate(&assign.value)?,
+                };
+                if !self.env.set(assign.target, value) {
+                    return Err(self.undefined_variable(&assign.target));
                 }
                 Ok(ControlFlow::Continue)
             }
@@ -261,9 +1144,10 @@ fn execute_statement(&mut self, stmt: &Statement) -> Result<ControlFlow> {
 
                 match result {
                     Ok(cf) => Ok(cf),
+                    Err(e) if e.ends_run() => Err(e),
                     Err(_) => {
                         if self.verbose {
-                            println!("  Reassurance: {}", attempt.reassurance);
+                            self.trace(&format!("  Reassurance: {}", attempt.reassurance))?;
                         }
                         Ok(ControlFlow::Continue)
                     }
@@ -279,7 +1163,7 @@ fn execute_statement(&mut self, stmt: &Statement) -> Result<ControlFlow> {
             }
             Statement::WorkerSpawn(spawn) => {
                 if self.verbose {
-                    println!("  Spawning worker: {}", spawn.worker_name);
+                    self.trace(&format!("  Spawning worker: {}", spawn.worker_name))?;
                 }
                 // In a real implementation, this would spawn a thread/task
                 // For now, we just execute the worker synchronously
@@ -300,7 +1184,7 @@ fn execute_statement(&mut self, stmt: &Statement) -> Result<ControlFlow> {
             }
             Statement::EmoteAnnotated(annotated) => {
                 if self.verbose {
-                    println!("  @{}", annotated.emote.name);
+                    self.trace(&format!("  @{}", annotated.emote.name))?;
                 }
                 self.execute_statement(&annotated.statement)
             }
@@ -333,19 +1217,32 @@ fn execute_consent_block(&mut self, consent: &ConsentBlock) -> Result<()> {
         // Check cache first
         let granted = if let Some(&cached) = self.consent_cache.get(permission) {
             cached
+        } else if self
+            .capabilities
+            .has_capability(CONSENT_SCOPE, &Capability::Custom(permission.clone()))
+        {
+            // Allowed by the project policy
+            self.consent_cache.insert(permission.clone(), true);
+            true
+        } else if let Some(stored) = self.capabilities.stored_consent(CONSENT_SCOPE, permission) {
+            self.consent_cache.insert(permission.clone(), stored);
+            stored
         } else {
             // Ask user for consent
-            print!("Permission requested: '{}'. Allow? [y/N]: ", permission);
-            io::stdout()
-                .flush()
-                .map_err(|e| RuntimeError::IoError(format!("Failed to flush stdout: {}", e)))?;
-
-            let mut input = String::new();
-            io::stdin()
-                .read_line(&mut input)
-                .map_err(|e| RuntimeError::IoError(format!("Failed to read input: {}", e)))?;
-            let granted = input.trim().eq_ignore_ascii_case("y");
-
+            let (granted, duration) = match &mut self.consent_handler {
+                Some(handler) => (handler(permission), ConsentDuration::Session),
+                None => {
+                    let question = match &consent.reason {
+                        Some(reason) => format!("Permission requested: '{}' ({}). Allow?", permission, reason),
+                        None => format!("Permission requested: '{}'. Allow?", permission),
+                    };
+                    consent::ask(&question, self.capabilities.prompt_timeout())
+                }
+            };
+            self.capabilities
+                .remember_consent(CONSENT_SCOPE, permission, granted, duration);
+            self.capabilities
+                .audit_consent(permission, granted, consent.reason.as_deref());
             self.consent_cache.insert(permission.clone(), granted);
             granted
         };
@@ -357,7 +1254,7 @@ fn execute_consent_block(&mut self, consent: &ConsentBlock) -> Result<()> {
             }
             self.env.pop_scope();
         } else if self.verbose {
-            println!("  Consent denied for: {}", permission);
+            self.trace(&format!("  Consent denied for: {}", permission))?;
         }
 
         Ok(())
@@ -379,10 +1276,12 @@ fn pattern_matches(&self, pattern: &Pattern, value: &Value) -> bool {
                         true
                     }
                 }
-                ("Oops", Value::Oops(_)) => {
-                    // Oops pattern matches any Oops value
-                    // The inner pattern (if any) can bind the error message
-                    true
+                ("Oops", Value::Oops(error)) => {
+                    if let Some(pat) = inner_pattern {
+                        self.pattern_matches(pat, error)
+                    } else {
+                        true
+                    }
                 }
                 _ => false,
             },
@@ -392,7 +1291,7 @@ fn pattern_matches(&self, pattern: &Pattern, value: &Value) -> bool {
     fn bind_pattern(&mut self, pattern: &Pattern, value: &Value) {
         match pattern {
             Pattern::Identifier(name) => {
-                self.env.define(name.clone(), value.clone());
+                self.env.define(*name, value.clone());
             }
             Pattern::Constructor(name, inner_pattern) => {
                 if let Some(pat) = inner_pattern {
@@ -400,8 +1299,8 @@ fn bind_pattern(&mut self, pattern: &Pattern, value: &Value) {
                         ("Okay", Value::Okay(inner_val)) => {
                             self.bind_pattern(pat, inner_val);
                         }
-                        ("Oops", Value::Oops(err_msg)) => {
-                            self.bind_pattern(pat, &Value::String(err_msg.clone()));
+                        ("Oops", Value::Oops(error)) => {
+                            self.bind_pattern(pat, error);
                         }
                         _ => {}
                     }
@@ -417,20 +1316,49 @@ fn literal_to_value(&self, lit: &Literal) -> Value {
         match lit {
             Literal::Integer(n) => Value::Int(*n),
             Literal::Float(n) => Value::Float(*n),
-            Literal::String(s) => Value::String(s.clone()),
+            Literal::Decimal(n) => Value::Decimal(*n),
+            Literal::String(s) => Value::String(s.clone().into()),
             Literal::Bool(b) => Value::Bool(*b),
             Literal::Unit => Value::Unit,
         }
     }
 
+    /// Count a statement towards the run's limits, measuring the memory
+    /// its variables hold every so often
+    fn check_limits(&mut self) -> Result<()> {
+        let Some(budget) = &mut self.budget else {
+            return Ok(());
+        };
+        budget.step()?;
+        if !budget.should_measure() {
+            return Ok(());
+        }
+        let mut footprint = Footprint::default();
+        self.env.scopes.iter().flat_map(|scope| scope.values()).for_each(|v| v.measure(&mut footprint));
+        if let Some(budget) = &mut self.budget {
+            budget.check_memory(&footprint)?;
+        }
+        Ok(())
+    }
+
+    /// Check the run has not gone over its timeout, after something that
+    /// may have taken a while without counting steps
+    fn check_clock(&self) -> Result<()> {
+        match &self.budget {
+            Some(budget) => Ok(budget.check_clock()?),
+            None => Ok(()),
+        }
+    }
+
     fn evaluate(&mut self, expr: &Spanned<Expr>) -> Result<Value> {
+        self.steps += 1;
         match &expr.node {
             Expr::Literal(lit) => Ok(self.literal_to_value(lit)),
             Expr::Identifier(name) => self
                 .env
-                .get(name)
+                .get(*name)
                 .cloned()
-                .ok_or_else(|| RuntimeError::UndefinedVariable(name.clone())),
+                .ok_or_else(|| self.undefined_variable(name)),
             Expr::Binary(op, left, right) => {
                 let left_val = self.evaluate(left)?;
                 let right_val = self.evaluate(right)?;
@@ -447,29 +1375,39 @@ fn evaluate(&mut self, expr: &Spanned<Expr>) -> Result<Value> {
                     .collect::<Result<_>>()?;
 
                 // Check for built-in functions first
-                if let Some(result) = self.call_builtin(name, &arg_values)? {
+                let builtin = handling_secrets(
+                    &arg_values,
+                    || self.call_builtin(name, &arg_values),
+                    |result| result.map(Value::into_secret),
+                )?;
+                if let Some(result) = builtin {
                     return Ok(result);
                 }
 
-                self.call_function(name, arg_values)
+                self.call_function(*name, arg_values)
             }
-            Expr::UnitMeasurement(inner, _unit) => {
-                // For now, just evaluate the inner expression
-                // A full implementation would track units
-                self.evaluate(inner)
+            Expr::UnitMeasurement(inner, unit) => {
+                let value = self.evaluate(inner)?;
+                // Time units make a Duration; other units are not tracked yet
+                if duration::unit_millis(unit).is_none() {
+                    return Ok(value);
+                }
+                duration::from_amount(&value, unit)
+                    .map(Value::Duration)
+                    .map_err(|e| RuntimeError::TypeError(format!("measured in {}: {}", unit, e)))
             }
             Expr::GratitudeLiteral(name) => {
                 if self.verbose {
-                    println!("  Expressing gratitude to: {}", name);
+                    self.trace(&format!("  Expressing gratitude to: {}", name))?;
                 }
-                Ok(Value::String(format!("Thanks to {}", name)))
+                Ok(Value::String(format!("Thanks to {}", name).into()))
             }
             Expr::Array(elements) => {
                 let values: Vec<Value> = elements
                     .iter()
                     .map(|e| self.evaluate(e))
                     .collect::<Result<_>>()?;
-                Ok(Value::Array(values))
+                Ok(Value::Array(values.into()))
             }
             Expr::Index(target, index) => {
                 let target_val = self.evaluate(target)?;
@@ -482,16 +1420,13 @@ fn evaluate(&mut self, expr: &Spanned<Expr>) -> Result<Value> {
             }
             Expr::Oops(inner) => {
                 let val = self.evaluate(inner)?;
-                match val {
-                    Value::String(s) => Ok(Value::Oops(s)),
-                    other => Ok(Value::Oops(other.to_string())),
-                }
+                Ok(Value::Oops(Box::new(val)))
             }
             Expr::Unwrap(inner) => {
                 let val = self.evaluate(inner)?;
                 match val {
                     Value::Okay(v) => Ok(*v),
-                    Value::Oops(e) => Err(RuntimeError::Complaint(e)),
+                    Value::Oops(e) => Err(RuntimeError::Complaint(e.redacted().error_message())),
                     other => Ok(other), // Non-result values pass through
                 }
             }
@@ -524,7 +1459,7 @@ fn capture_environment(&self) -> CapturedEnv {
         let mut bindings = HashMap::new();
         for scope in &self.env.scopes {
             for (name, value) in scope {
-                bindings.insert(name.clone(), value.clone());
+                bindings.insert(*name, value.clone());
             }
         }
         CapturedEnv::from_map(bindings)
@@ -547,13 +1482,13 @@ fn call_closure(&mut self, closure: &Closure, args: Vec<Value>) -> Result<Value>
         // Add captured bindings
         let captured = closure.env.borrow();
         for (name, value) in &captured.bindings {
-            self.env.define(name.clone(), value.clone());
+            self.env.define(*name, value.clone());
         }
 
         // Push new scope for parameters
         self.env.push_scope();
         for (param, arg) in closure.params.iter().zip(args) {
-            self.env.define(param.name.clone(), arg);
+            self.env.define(param.name, arg);
         }
 
         // Execute the closure body
@@ -581,6 +1516,14 @@ fn call_closure(&mut self, closure: &Closure, args: Vec<Value>) -> Result<Value>
     }
 
     fn apply_index(&self, target: Value, index: Value) -> Result<Value> {
+        // Maps are indexed by key
+        if let (Value::Record(map), Value::String(key)) = (&target, &index) {
+            return map
+                .get(key.as_str())
+                .cloned()
+                .ok_or_else(|| RuntimeError::TypeError(format!("Key not found: {}", key.shown())));
+        }
+
         let idx = match index {
             Value::Int(n) => {
                 if n < 0 {
@@ -596,13 +1539,13 @@ fn apply_index(&self, target: Value, index: Value) -> Result<Value> {
                 .get(idx)
                 .cloned()
                 .ok_or(RuntimeError::IndexOutOfBounds(idx)),
-            Value::String(s) => {
-                // Use chars().nth() for proper UTF-8 character indexing
-                s.chars()
-                    .nth(idx)
-                    .map(|c| Value::String(c.to_string()))
-                    .ok_or(RuntimeError::IndexOutOfBounds(idx))
-            }
+            Value::Bytes(bytes) => bytes
+                .get(idx)
+                .map(|b| Value::Int(*b as i64))
+                .ok_or(RuntimeError::IndexOutOfBounds(idx)),
+            Value::String(s) => string::nth_char(&s, idx)
+                .map(|c| Value::String(c.to_string().into()).secret_if(s.is_secret()))
+                .ok_or(RuntimeError::IndexOutOfBounds(idx)),
             _ => Err(RuntimeError::TypeError(
                 "Cannot index this type".into(),
             )),
@@ -612,13 +1555,22 @@ fn apply_index(&self, target: Value, index: Value) -> Result<Value> {
     fn call_builtin(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>> {
         match name {
             "print" => {
-                for (i, arg) in args.iter().enumerate() {
-                    if i > 0 {
-                        print!(" ");
-                    }
-                    print!("{}", arg);
+                let line = args
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                self.write_line(&line)?;
+                Ok(Some(Value::Unit))
+            }
+            "prettyPrint" => {
+                if args.len() != 1 {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: 1,
+                        got: args.len(),
+                    });
                 }
-                println!();
+                self.write_line(&args[0].pretty())?;
                 Ok(Some(Value::Unit))
             }
             "len" => {
@@ -629,10 +1581,12 @@ fn call_builtin(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>>
                     });
                 }
                 match &args[0] {
-                    // Use chars().count() for proper UTF-8 character count
+                    // Characters, as indexing counts them
                     Value::String(s) => Ok(Some(Value::Int(s.chars().count() as i64))),
+                    Value::Bytes(b) => Ok(Some(Value::Int(b.len() as i64))),
                     Value::Array(a) => Ok(Some(Value::Int(a.len() as i64))),
-                    _ => Err(RuntimeError::TypeError("len() requires string or array".into())),
+                    Value::Record(m) => Ok(Some(Value::Int(m.len() as i64))),
+                    _ => Err(RuntimeError::TypeError("len() requires string, bytes, array or map".into())),
                 }
             }
             "toString" => {
@@ -642,7 +1596,7 @@ fn call_builtin(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>>
                         got: args.len(),
                     });
                 }
-                Ok(Some(Value::String(args[0].to_string())))
+                Ok(Some(Value::String(args[0].to_string().into())))
             }
             "toInt" => {
                 if args.len() != 1 {
@@ -659,6 +1613,9 @@ fn call_builtin(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>>
                         Ok(Some(Value::Int(n)))
                     }
                     Value::Float(f) => Ok(Some(Value::Int(*f as i64))),
+                    Value::Decimal(d) => d.trunc().to_i64().map(|n| Some(Value::Int(n))).ok_or_else(|| {
+                        RuntimeError::TypeError(format!("Cannot convert {} to Int", d))
+                    }),
                     Value::Int(n) => Ok(Some(Value::Int(*n))),
                     _ => Err(RuntimeError::TypeError("Cannot convert to Int".into())),
                 }
@@ -694,195 +1651,1430 @@ fn call_builtin(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>>
                     other => Ok(Some(other.clone())),
                 }
             }
-            "getError" => {
-                if args.len() != 1 {
-                    return Err(RuntimeError::ArityMismatch {
-                        expected: 1,
-                        got: args.len(),
-                    });
-                }
-                match &args[0] {
-                    Value::Oops(e) => Ok(Some(Value::String(e.clone()))),
-                    _ => Ok(Some(Value::Unit)),
-                }
+            "getError" => {
+                if args.len() != 1 {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: 1,
+                        got: args.len(),
+                    });
+                }
+                match &args[0] {
+                    Value::Oops(e) => Ok(Some((**e).clone())),
+                    _ => Ok(Some(Value::Unit)),
+                }
+            }
+            "format" => self.call_stdlib("std.string.format", args),
+            "args" => self.call_stdlib("std.env.args", args),
+            "listSuperpowers" => self.call_stdlib("std.superpowers.list", args),
+            "hasSuperpower" => self.call_stdlib("std.superpowers.has", args),
+            "renounce" => self.call_stdlib("std.superpowers.renounce", args),
+            "gratitudeEntries" => {
+                if !args.is_empty() {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: 0,
+                        got: args.len(),
+                    });
+                }
+                let entries = self.gratitude.iter().map(|(recipient, reason)| (recipient.as_str(), reason.as_str()));
+                Ok(Some(Value::gratitude(entries)))
+            }
+            "exit" => {
+                if args.len() != 1 {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: 1,
+                        got: args.len(),
+                    });
+                }
+                match &args[0] {
+                    Value::Int(code) => Err(RuntimeError::Exit(*code)),
+                    _ => Err(RuntimeError::TypeError("exit() requires an Int exit code".into())),
+                }
+            }
+            "ask" | "askNumber" | "askYesNo" => {
+                if args.len() != 1 {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: 1,
+                        got: args.len(),
+                    });
+                }
+                let Value::String(prompt) = &args[0] else {
+                    return Err(RuntimeError::TypeError(format!("{}() requires a string prompt", name)));
+                };
+                let answer = Answer::of_builtin(name).expect("matched above");
+                let mut stdin;
+                let input: &mut dyn BufRead = match &mut self.input {
+                    Some(input) => input.as_mut(),
+                    None => {
+                        stdin = io::stdin().lock();
+                        &mut stdin
+                    }
+                };
+                answer.ask(prompt, input, &mut self.output).map(Some).map_err(|e| stdlib_error(name, e))
+            }
+            _ => self.call_stdlib(name, args),
+        }
+    }
+
+    /// An unknown variable, with the name in scope it may be a typo of
+    fn undefined_variable(&self, name: &str) -> RuntimeError {
+        let names = self.env.scopes.iter().flat_map(|scope| scope.keys());
+        RuntimeError::UndefinedVariable {
+            name: name.to_string(),
+            suggestion: explain::did_you_mean(name, names.map(Symbol::as_str)).map(str::to_string),
+        }
+    }
+
+    /// An unknown function, with the function, builtin or standard library
+    /// function it may be a typo of, written through the same module alias
+    fn undefined_function(&self, name: &str) -> RuntimeError {
+        let full_name = self.scope.resolve(name);
+        let names = self
+            .functions
+            .keys()
+            .chain(self.natives.keys())
+            .map(String::as_str)
+            .chain(self.env.scopes.iter().flat_map(|scope| scope.keys()).map(|name| &**name))
+            .chain(BUILTINS.iter().copied())
+            .chain(self.stdlib.list());
+        let suggestion = explain::did_you_mean(&full_name, names).map(|found| self.scope.written_as(name, found));
+        RuntimeError::UndefinedFunction {
+            name: name.to_string(),
+            suggestion,
+        }
+    }
+
+    /// Call a standard library function by its full name (`std.string.split`)
+    /// or through an imported module alias (`string.split`)
+    fn call_stdlib(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>> {
+        if !name.contains('.') {
+            return Ok(None);
+        }
+        let full_name = self.scope.resolve(name);
+        let builtin = self.stdlib.has(&full_name);
+        if !builtin && !self.natives.contains_key(&full_name) {
+            return Ok(None);
+        }
+
+        let module = full_name.rsplit_once('.').map(|(module, _)| module.to_string());
+        let caller_module = self.capabilities.set_module(module);
+        let deadline = limits::enter_call(self.budget.as_ref());
+        let result = if builtin {
+            self.call_stdlib_function(&full_name, args)
+        } else {
+            // A function a plugin added to the standard library
+            let native = self.natives.get_mut(&full_name).expect("checked above");
+            native.call(args, &mut self.capabilities).map_err(|e| stdlib_error(&full_name, e))
+        };
+        drop(deadline);
+        self.capabilities.set_module(caller_module);
+        // A call cut short by the timeout fails as the timeout
+        self.check_clock()?;
+        result.map(Some)
+    }
+
+    fn call_stdlib_function(&mut self, full_name: &str, args: &[Value]) -> Result<Value> {
+        let result = match self.stdlib.get_higher_order(full_name) {
+            Some(func) => {
+                // Errors raised inside user callbacks are passed through unchanged
+                let mut callback_error = None;
+                let result = self
+                    .stdlib
+                    .authorize(full_name, args, &mut self.capabilities)
+                    .and_then(|()| {
+                        func(args, &mut |callee, callee_args| {
+                            let outcome = match callee {
+                                Value::Function(closure) => self.call_closure(closure, callee_args),
+                                other => Err(RuntimeError::TypeError(format!(
+                                    "{}: expected a function, got {}",
+                                    full_name, other
+                                ))),
+                            };
+                            outcome.map_err(|e| {
+                                let message = e.to_string();
+                                callback_error.get_or_insert(e);
+                                StdlibError::RuntimeError(message)
+                            })
+                        })
+                    });
+                if let Some(e) = callback_error {
+                    return Err(e);
+                }
+                result
+            }
+            None => self.stdlib.call(full_name, args, &mut self.capabilities),
+        };
+
+        result.map_err(|e| stdlib_error(full_name, e))
+    }
+
+    fn call_function(&mut self, name: Symbol, args: Vec<Value>) -> Result<Value> {
+        // Check recursion depth limit
+        if self.recursion_depth >= MAX_RECURSION_DEPTH {
+            return Err(RuntimeError::RecursionLimitExceeded);
+        }
+        self.recursion_depth += 1;
+
+        // First, check if name refers to a variable holding a closure
+        if let Some(Value::Function(closure)) = self.env.get(name).cloned() {
+            let result = self.call_closure(&closure, args);
+            self.recursion_depth -= 1;
+            return result;
+        }
+
+        // Then a function the host registered, unless the program defines
+        // one of the same name
+        if !self.functions.contains_key(name.as_str()) {
+            if let Some(native) = self.natives.get_mut(name.as_str()) {
+                let deadline = limits::enter_call(self.budget.as_ref());
+                let capabilities = &mut self.capabilities;
+                let result = handling_secrets(
+                    &args,
+                    || native.call(&args, capabilities).map_err(|e| stdlib_error(&name, e)),
+                    Value::into_secret,
+                );
+                drop(deadline);
+                self.recursion_depth -= 1;
+                return self.check_clock().and(result);
+            }
+        }
+
+        // Otherwise, look up as a named function
+        let Some(func) = self.functions.get(name.as_str()).cloned() else {
+            self.recursion_depth -= 1;
+            return Err(self.undefined_function(&name));
+        };
+
+        if func.params.len() != args.len() {
+            self.recursion_depth -= 1;
+            return Err(RuntimeError::ArityMismatch {
+                expected: func.params.len(),
+                got: args.len(),
+            });
+        }
+
+        // Print hello message
+        if let Some(hello) = &func.hello {
+            if self.verbose {
+                self.trace(&format!("[{}] {}", name, hello))?;
+            }
+        }
+
+        if let Some(debugger) = &mut self.debugger {
+            debugger.enter_function(&name);
+            self.frames.push(debug::Frame {
+                function: name.to_string(),
+                scope_base: self.env.scopes.len(),
+                span: func.span.clone(),
+            });
+        }
+
+        // Create new scope and bind parameters
+        self.env.push_scope();
+        for (param, arg) in func.params.iter().zip(args) {
+            self.env.define(param.name, arg);
+        }
+
+        // Execute function body, with superpowers requested in its own
+        // scope, in the module it was imported from if it was
+        let module = func.module.as_deref().unwrap_or(&self.module_name);
+        let caller_scope = self.capabilities.set_scope(function_scope(module, &name));
+        let mut outcome = Ok(Value::Unit);
+        for stmt in &func.body {
+            match self.execute_statement(stmt) {
+                Ok(ControlFlow::Return(v)) => {
+                    outcome = Ok(v);
+                    break;
+                }
+                Ok(ControlFlow::Continue) => {}
+                Err(e) => {
+                    outcome = Err(e);
+                    break;
+                }
+            }
+        }
+        self.capabilities.set_scope(caller_scope);
+        if let Some(debugger) = &mut self.debugger {
+            debugger.leave_function();
+            self.frames.pop();
+        }
+        let result = outcome?;
+
+        self.env.pop_scope();
+        self.recursion_depth -= 1;
+
+        // Print goodbye message
+        if let Some(goodbye) = &func.goodbye {
+            if self.verbose {
+                self.trace(&format!("[{}] {}", name, goodbye))?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn apply_binary_op(&self, op: BinaryOp, left: Value, right: Value) -> Result<Value> {
+        match op {
+            BinaryOp::Add => match (left, right) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a + b)),
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
+                (Value::Int(a), Value::Float(b)) => Ok(Value::Float(a as f64 + b)),
+                (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a + b as f64)),
+                (a, b) if decimal::involves(&a, &b) => decimal::add(&a, &b).map_err(RuntimeError::TypeError),
+                (Value::String(mut a), Value::String(b)) => {
+                    a.make_mut().push_str(&b);
+                    Ok(Value::String(a).secret_if(b.is_secret()))
+                }
+                (Value::Bytes(mut a), Value::Bytes(b)) => {
+                    a.extend(b);
+                    Ok(Value::Bytes(a))
+                }
+                (
+                    a @ (Value::Duration(_) | Value::DateTime(_)),
+                    b @ (Value::Duration(_) | Value::DateTime(_)),
+                ) => duration::add(&a, &b).map_err(RuntimeError::TypeError),
+                (Value::String(mut a), b) => {
+                    a.make_mut().push_str(&b.to_string());
+                    Ok(Value::String(a).secret_if(b.is_secret()))
+                }
+                (a, Value::String(b)) => {
+                    let secret = a.is_secret() || b.is_secret();
+                    Ok(Value::String((a.to_string() + &b).into()).secret_if(secret))
+                }
+                _ => Err(RuntimeError::TypeError("Cannot add these types".into())),
+            },
+            BinaryOp::Sub => match (left, right) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a - b)),
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
+                (Value::Int(a), Value::Float(b)) => Ok(Value::Float(a as f64 - b)),
+                (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a - b as f64)),
+                (a, b) if decimal::involves(&a, &b) => decimal::subtract(&a, &b).map_err(RuntimeError::TypeError),
+                (
+                    a @ (Value::Duration(_) | Value::DateTime(_)),
+                    b @ (Value::Duration(_) | Value::DateTime(_)),
+                ) => duration::subtract(&a, &b).map_err(RuntimeError::TypeError),
+                _ => Err(RuntimeError::TypeError("Cannot subtract these types".into())),
+            },
+            BinaryOp::Mul => match (left, right) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a * b)),
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
+                (Value::Int(a), Value::Float(b)) => Ok(Value::Float(a as f64 * b)),
+                (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a * b as f64)),
+                (a, b) if decimal::involves(&a, &b) => decimal::multiply(&a, &b).map_err(RuntimeError::TypeError),
+                (a @ Value::Duration(_), b @ Value::Int(_)) | (a @ Value::Int(_), b @ Value::Duration(_)) => {
+                    duration::multiply(&a, &b).map_err(RuntimeError::TypeError)
+                }
+                _ => Err(RuntimeError::TypeError("Cannot multiply these types".into())),
+            },
+            BinaryOp::Div => match (left, right) {
+                (_, Value::Int(0)) => Err(RuntimeError::DivisionByZero),
+                (_, Value::Float(0.0)) => Err(RuntimeError::DivisionByZero),
+                (_, Value::Decimal(d)) if d.is_zero() => Err(RuntimeError::DivisionByZero),
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a / b)),
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
+                (Value::Int(a), Value::Float(b)) => Ok(Value::Float(a as f64 / b)),
+                (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a / b as f64)),
+                (a, b) if decimal::involves(&a, &b) => decimal::divide(&a, &b).map_err(RuntimeError::TypeError),
+                _ => Err(RuntimeError::TypeError("Cannot divide these types".into())),
+            },
+            BinaryOp::Mod => match (left, right) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a % b)),
+                (Value::Decimal(_), Value::Int(0)) => Err(RuntimeError::DivisionByZero),
+                (_, Value::Decimal(d)) if d.is_zero() => Err(RuntimeError::DivisionByZero),
+                (a, b) if decimal::involves(&a, &b) => decimal::remainder(&a, &b).map_err(RuntimeError::TypeError),
+                _ => Err(RuntimeError::TypeError("Modulo requires integers or Decimals".into())),
+            },
+            BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::Gt | BinaryOp::LtEq | BinaryOp::GtEq
+                if decimal::involves(&left, &right) =>
+            {
+                let ordering = decimal::compare(&left, &right).map_err(RuntimeError::TypeError)?;
+                Ok(Value::Bool(match op {
+                    BinaryOp::Eq => ordering.is_eq(),
+                    BinaryOp::NotEq => ordering.is_ne(),
+                    BinaryOp::Lt => ordering.is_lt(),
+                    BinaryOp::Gt => ordering.is_gt(),
+                    BinaryOp::LtEq => ordering.is_le(),
+                    _ => ordering.is_ge(),
+                }))
+            }
+            BinaryOp::Eq => Ok(Value::Bool(left == right)),
+            BinaryOp::NotEq => Ok(Value::Bool(left != right)),
+            BinaryOp::Lt => match (left, right) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a < b)),
+                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a < b)),
+                (Value::DateTime(a), Value::DateTime(b)) => Ok(Value::Bool(a < b)),
+                (Value::Duration(a), Value::Duration(b)) => Ok(Value::Bool(a < b)),
+                _ => Err(RuntimeError::TypeError("Cannot compare these types".into())),
+            },
+            BinaryOp::Gt => match (left, right) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a > b)),
+                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a > b)),
+                (Value::DateTime(a), Value::DateTime(b)) => Ok(Value::Bool(a > b)),
+                (Value::Duration(a), Value::Duration(b)) => Ok(Value::Bool(a > b)),
+                _ => Err(RuntimeError::TypeError("Cannot compare these types".into())),
+            },
+            BinaryOp::LtEq => match (left, right) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a <= b)),
+                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a <= b)),
+                (Value::DateTime(a), Value::DateTime(b)) => Ok(Value::Bool(a <= b)),
+                (Value::Duration(a), Value::Duration(b)) => Ok(Value::Bool(a <= b)),
+                _ => Err(RuntimeError::TypeError("Cannot compare these types".into())),
+            },
+            BinaryOp::GtEq => match (left, right) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
+                (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a >= b)),
+                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a >= b)),
+                (Value::DateTime(a), Value::DateTime(b)) => Ok(Value::Bool(a >= b)),
+                (Value::Duration(a), Value::Duration(b)) => Ok(Value::Bool(a >= b)),
+                _ => Err(RuntimeError::TypeError("Cannot compare these types".into())),
+            },
+            BinaryOp::And => Ok(Value::Bool(left.is_truthy() && right.is_truthy())),
+            BinaryOp::Or => Ok(Value::Bool(left.is_truthy() || right.is_truthy())),
+        }
+    }
+
+    fn apply_unary_op(&self, op: UnaryOp, val: Value) -> Result<Value> {
+        match op {
+            UnaryOp::Neg => match val {
+                Value::Int(n) => Ok(Value::Int(-n)),
+                Value::Float(f) => Ok(Value::Float(-f)),
+                Value::Decimal(d) => Ok(Value::Decimal(-d)),
+                Value::Duration(d) => Ok(Value::Duration(-d)),
+                _ => Err(RuntimeError::TypeError("Cannot negate this type".into())),
+            },
+            UnaryOp::Not => Ok(Value::Bool(!val.is_truthy())),
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A standard library or host function's error as a runtime error
+pub(crate) fn stdlib_error(function: &str, e: StdlibError) -> RuntimeError {
+    match e {
+        StdlibError::ArityError { expected, got } => RuntimeError::ArityMismatch { expected, got },
+        StdlibError::TypeError { expected, got } => {
+            RuntimeError::TypeError(format!("{}: expected {}, got {}", function, expected, got))
+        }
+        StdlibError::PermissionDenied(capability) => RuntimeError::ConsentDenied(capability),
+        StdlibError::IoError(message) => RuntimeError::IoError(message),
+        other => RuntimeError::Stdlib {
+            function: function.to_string(),
+            message: other.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn run_program(source: &str) -> Result<()> {
+        let lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().expect("Lexer failed");
+        let mut parser = Parser::new(tokens, source);
+        let program = parser.parse().expect("Parser failed");
+        let mut interpreter = Interpreter::new();
+        interpreter.run(&program)
+    }
+
+    #[test]
+    fn test_output_and_consent_handler() {
+        let source = r#"
+            only if okay "camera" {
+                print("camera on");
+            }
+
+            to main() {
+                only if okay "network" {
+                    print("online", 1, 2.5);
+                }
+                only if okay "camera" {
+                    print("camera again");
+                }
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+
+        let output = OutputBuffer::new();
+        let asked = Rc::new(RefCell::new(Vec::new()));
+        let asked_log = Rc::clone(&asked);
+        let mut interpreter = Interpreter::new()
+            .with_output(output.clone())
+            .with_consent_handler(move |permission| {
+                asked_log.borrow_mut().push(permission.to_string());
+                permission == "network"
+            });
+        interpreter.run(&program).unwrap();
+
+        assert_eq!(output.contents(), "online 1 2.5\n");
+        // Decisions are cached, so each permission is asked once
+        assert_eq!(*asked.borrow(), ["camera", "network"]);
+    }
+
+    #[test]
+    fn test_consent_reason_is_audited() {
+        let source = r#"
+            to main() {
+                only if okay "camera" because "to scan the QR code" {
+                    print("scanning");
+                }
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+
+        let mut interpreter = Interpreter::new()
+            .with_output(OutputBuffer::new())
+            .with_consent_handler(|_| false);
+        interpreter.run(&program).unwrap();
+
+        let entry = interpreter.capabilities.get_audit_log().last().unwrap();
+        assert_eq!(entry.capability, Capability::Custom("camera".to_string()));
+        assert!(!entry.success);
+        assert_eq!(entry.reason.as_deref(), Some("to scan the QR code"));
+    }
+
+    #[test]
+    fn test_policy_allows_without_asking() {
+        let source = r#"
+            to main() {
+                only if okay "camera" {
+                    print(std.env.get("WOKE_POLICY_TEST"));
+                }
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let policy =
+            Policy::parse("[allow]\nenv = [\"WOKE_POLICY_TEST\"]\nconsents = [\"camera\"]\n").unwrap();
+
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new()
+            .with_output(output.clone())
+            .with_policy(&policy)
+            .with_consent_handler(|permission| panic!("asked for {}", permission));
+        interpreter.capabilities.set_interactive(false);
+        interpreter.run(&program).unwrap();
+        assert!(output.contents().contains("WOKE_POLICY_TEST"));
+
+        // Anything the policy does not list is still refused
+        let source = r#"to main() { print(std.env.get("HOME")); }"#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let mut interpreter = Interpreter::new().with_policy(&policy);
+        interpreter.capabilities.set_interactive(false);
+        assert!(matches!(
+            interpreter.run(&program),
+            Err(RuntimeError::ConsentDenied(_))
+        ));
+    }
+
+    #[test]
+    fn test_deny_all() {
+        let source = r#"
+            to main() {
+                only if okay "camera" {
+                    print("camera on");
+                }
+                print(isOkay(std.env.get("WOKE_DENY_ALL_TEST")) or isOops(std.env.get("WOKE_DENY_ALL_TEST")));
+                print(std.env.get("HOME"));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let mut allowed = Policy::default();
+        allowed.add_flag("--allow-env=WOKE_DENY_ALL_TEST").unwrap();
+
+        let output = OutputBuffer::new();
+        let err = Interpreter::new()
+            .with_output(output.clone())
+            .with_policy(&allowed)
+            .with_deny_all()
+            .run(&program)
+            .unwrap_err();
+        assert_eq!(output.contents(), "true\n");
+        assert!(matches!(err, RuntimeError::ConsentDenied(_)));
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let root = std::env::temp_dir().join("wokelang_test_interpreter_sandbox");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("input.txt"), "inside").unwrap();
+        let source = format!(
+            r#"
+            to main() {{
+                print(std.io.readFile("{}"));
+                print(std.env.get("HOME"));
+            }}
+            "#,
+            root.join("input.txt").display()
+        );
+        let tokens = Lexer::new(&source).tokenize().unwrap();
+        let program = Parser::new(tokens, &source).parse().unwrap();
+
+        let output = OutputBuffer::new();
+        let err = Interpreter::new()
+            .with_output(output.clone())
+            .with_consent_mode(ConsentMode::Allow)
+            .with_sandbox(&root)
+            .run(&program)
+            .unwrap_err();
+        assert_eq!(output.contents(), "inside\n");
+        assert!(matches!(err, RuntimeError::UndefinedFunction { name, .. } if name == "std.env.get"));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_consent_mode_allow() {
+        let source = r#"
+            to main() {
+                only if okay "camera" {
+                    print("camera on");
+                }
+                print(isOkay(std.env.get("WOKE_CONSENT_MODE_TEST")) or isOops(std.env.get("WOKE_CONSENT_MODE_TEST")));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .with_consent_mode(ConsentMode::Allow)
+            .run(&program)
+            .unwrap();
+        assert_eq!(output.contents(), "camera on\ntrue\n");
+    }
+
+    #[test]
+    fn test_superpowers_are_scoped_by_module_and_function() {
+        let source = r#"
+            to readIt() -> String {
+                give back unwrapOr(std.env.get("WOKE_SCOPE_TEST"), "unset");
+            }
+
+            to main() {
+                print(readIt());
+                print(std.env.get("WOKE_SCOPE_TEST"));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let env = Capability::Environment(None);
+
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new()
+            .with_output(output.clone())
+            .with_module_name("tools")
+            .with_deny_all();
+        interpreter.capabilities.grant("tools:readIt", env.clone(), "test");
+        let err = interpreter.run(&program).unwrap_err();
+        assert_eq!(output.contents(), "unset\n");
+        assert!(matches!(err, RuntimeError::ConsentDenied(_)));
+
+        // A function of the same name in another module gets nothing
+        let mut interpreter = Interpreter::new().with_deny_all();
+        interpreter.capabilities.grant("tools:readIt", env.clone(), "test");
+        assert!(matches!(
+            interpreter.run(&program),
+            Err(RuntimeError::ConsentDenied(_))
+        ));
+
+        // A grant to the module covers all of its functions
+        let mut interpreter = Interpreter::new()
+            .with_output(OutputBuffer::new())
+            .with_module_name("tools")
+            .with_deny_all();
+        interpreter.capabilities.grant("tools", env, "test");
+        interpreter.run(&program).unwrap();
+    }
+
+    #[test]
+    fn test_imports_limit_their_module_to_declared_superpowers() {
+        let source = r#"
+            use std.env must have env "WOKE_DECLARED_TEST";
+
+            to main() {
+                print(isOops(env.get("WOKE_DECLARED_TEST")));
+                print(std.env.get("HOME"));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        interpreter.capabilities = CapabilityRegistry::permissive();
+        let err = interpreter.run(&program).unwrap_err();
+        assert_eq!(output.contents(), "true\n");
+        assert!(matches!(err, RuntimeError::ConsentDenied(_)));
+    }
+
+    #[test]
+    fn test_side_quests_run_after_main() {
+        let source = r#"
+            side quest tidyUp {
+                print("tidying up");
+            }
+            side quest warmCache {
+                remember n = 1 / 0;
+                print("never");
+            }
+            to main() -> Int {
+                remember n = 1;
+                print("main done");
+                give back 42;
+            }
+            side quest last {
+                when true {
+                    give back 0;
+                }
+                print("not reached");
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        assert_eq!(interpreter.run_main(&program).unwrap(), Value::Int(42));
+        assert_eq!(output.contents(), "main done\ntidying up\n");
+        let outcomes: Vec<String> = interpreter.side_quests().iter().map(|o| o.to_string()).collect();
+        assert_eq!(outcomes, ["tidyUp: done", "warmCache: failed: Division by zero", "last: done"]);
+        assert!(matches!(
+            interpreter.side_quests()[1].status,
+            SideQuestStatus::Failed { code: Some("WOKE-E0303"), .. }
+        ));
+
+        // Skipped by the pragma, or when main fails
+        let skipped = format!("#side quests off;\n{}", source);
+        let tokens = Lexer::new(&skipped).tokenize().unwrap();
+        let program = Parser::new(tokens, &skipped).parse().unwrap();
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        interpreter.run(&program).unwrap();
+        assert_eq!(output.contents(), "main done\n");
+        assert!(interpreter.side_quests().iter().all(|o| matches!(o.status, SideQuestStatus::Skipped)));
+
+        let source = "side quest tidyUp { print(\"tidying up\"); } to main() { remember n = 1 / 0; }";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        assert!(interpreter.run(&program).is_err());
+        assert_eq!(output.contents(), "");
+        assert!(interpreter.side_quests().is_empty());
+    }
+
+    #[test]
+    fn test_exit() {
+        let source = r#"
+            side quest tidyUp {
+                print("tidying up");
+            }
+            to check(n: Int) {
+                attempt safely {
+                    exit(n);
+                } or reassure "caught";
+                print("not reached");
+            }
+            to main() -> Int {
+                check(7);
+                give back 0;
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        assert_eq!(interpreter.run_main(&program).unwrap(), Value::Int(7));
+        assert_eq!(output.contents(), "");
+        assert!(interpreter.side_quests().is_empty());
+
+        assert_eq!(Value::Int(7).exit_status(), 7);
+        assert_eq!(Value::Int(256).exit_status(), 1);
+        assert_eq!(Value::Int(-1).exit_status(), 1);
+        assert_eq!(Value::oops("bad").exit_status(), 1);
+        assert_eq!(Value::Bool(true).exit_status(), 0);
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let record = |fields: &[(&str, Value)]| {
+            Value::Record(fields.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+        };
+        assert_eq!(Value::String("bad".to_string().into()).error_message(), "bad");
+        assert_eq!(Value::Int(404).error_message(), "404");
+        let described = record(&[("code", Value::Int(503)), ("message", Value::String("asleep".to_string().into()))]);
+        assert_eq!(described.error_message(), "asleep");
+        assert_eq!(record(&[("code", Value::Int(503))]).error_message(), "{code: 503}");
+
+        assert_eq!(Value::oops("bad").to_string(), "Oops(\"bad\")");
+        assert_eq!(Value::Oops(Box::new(Value::Int(404))).to_string(), "Oops(404)");
+        assert_eq!(Value::Oops(Box::new(described)).unwrap(), Err("asleep".to_string()));
+    }
+
+    #[test]
+    fn test_ask() {
+        let source = r#"
+            to main() {
+                remember name = ask("Name? ");
+                remember age = askNumber("Age? ");
+                when askYesNo("Proceed? ") {
+                    print(name, age + 1.0);
+                }
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new()
+            .with_output(output.clone())
+            .with_input(&b"Ada Lovelace\nthirty-six\n36\nperhaps\r\nY\n"[..]);
+        interpreter.run(&program).unwrap();
+        assert_eq!(
+            output.contents(),
+            "Name? Age? Please answer with a number.\nAge? Proceed? Please answer yes or no.\nProceed? Ada Lovelace 37\n"
+        );
+
+        // Out of answers
+        let mut interpreter = Interpreter::new().with_output(OutputBuffer::new()).with_input(&b"Ada\n"[..]);
+        let err = interpreter.run(&program).unwrap_err();
+        assert_eq!(err.to_string(), "I/O error: input ended before an answer was given");
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        let source = r#"
+            to main() {
+                remember notes = ["Analytical Engine notes", "Note G", "Bernoulli numbers", "a\"quote"];
+                remember user = std.map.set(std.map.set(std.map.new(), "name", "Ada"), "notes", notes);
+                prettyPrint(user);
+                prettyPrint([[1, 2], Okay([])]);
+                prettyPrint("text");
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new().with_output(output.clone()).run(&program).unwrap();
+        let expected = r#"{
+    name: "Ada",
+    notes: [
+        "Analytical Engine notes",
+        "Note G",
+        "Bernoulli numbers",
+        "a\"quote"
+    ]
+}
+[[1, 2], Okay([])]
+"text"
+"#;
+        assert_eq!(output.contents(), expected);
+
+        // Shared arrays are shown in full each time they appear
+        let row = Value::Array(vec![Value::Int(1)].into());
+        assert_eq!(Value::Array(vec![row.clone(), row].into()).pretty(), "[[1], [1]]");
+
+        let source = "to main() { prettyPrint(1, 2); }";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let err = Interpreter::new().with_output(OutputBuffer::new()).run(&program).unwrap_err();
+        assert!(matches!(err, RuntimeError::ArityMismatch { expected: 1, got: 2 }));
+    }
+
+    #[test]
+    fn test_limit_pragmas() {
+        let run = |source: &str, limits: Limits| {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            let program = Parser::new(tokens, source).parse().unwrap();
+            Interpreter::new().with_output(OutputBuffer::new()).with_limits(limits).run_main(&program)
+        };
+        let spin = "to main() { remember n = 0; repeat 100000000 times { n = n + 1; } }";
+        let err = run(&format!("#timeout 20ms;\n{}", spin), Limits::default()).unwrap_err();
+        assert_eq!(err.code(), "WOKE-E0313");
+        assert_eq!(err.to_string(), "Timed out after 20ms");
+        // A host's lower limit holds over the program's
+        let host = Limits { timeout: Some(std::time::Duration::from_millis(10)), max_memory: None };
+        let err = run(&format!("#timeout 1 h;\n{}", spin), host).unwrap_err();
+        assert_eq!(err.to_string(), "Timed out after 10ms");
+
+        let grow = r#"to main() { remember s = "x"; repeat 30 times { s = s + s; } }"#;
+        let err = run(&format!("#maxmemory 1mb;\n{}", grow), Limits::default()).unwrap_err();
+        assert!(matches!(err, RuntimeError::LimitExceeded(LimitExceeded::Memory(1048576))));
+        assert_eq!(err.code(), "WOKE-E0314");
+        assert!(run(&format!("#maxmemory 1mb;\n{}", grow.replace("30", "10")), Limits::default()).is_ok());
+    }
+
+    #[test]
+    fn test_renounce_drops_a_superpower() {
+        let source = r#"
+            to main() {
+                print(hasSuperpower("env:WOKE_RENOUNCE_TEST"));
+                print(listSuperpowers());
+                renounce("env");
+                print(hasSuperpower("env:WOKE_RENOUNCE_TEST"));
+                print(std.env.get("WOKE_RENOUNCE_TEST"));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let mut allowed = Policy::default();
+        allowed.add_flag("--allow-env").unwrap();
+
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new()
+            .with_output(output.clone())
+            .with_policy(&allowed);
+        interpreter.capabilities.set_interactive(false);
+        interpreter.capabilities.set_default_consent(true);
+        let err = interpreter.run(&program).unwrap_err();
+        assert_eq!(output.contents(), "true\n[env:*]\nfalse\n");
+        assert!(matches!(err, RuntimeError::ConsentDenied(_)));
+    }
+
+    #[test]
+    fn test_gratitude_entries() {
+        let source = r#"
+            thanks to {
+                "Ada Lovelace" → "the first program";
+            }
+            to main() {
+                give back gratitudeEntries();
+            }
+            thanks to {
+                "Grace Hopper" → "the compiler";
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let entries = Interpreter::new().run_main(&program).unwrap();
+        assert_eq!(
+            entries,
+            Value::gratitude([("Ada Lovelace", "the first program"), ("Grace Hopper", "the compiler")])
+        );
+        let Value::Array(records) = &entries else { panic!("not an array: {}", entries) };
+        let Value::Record(fields) = &records[1] else { panic!("not a record: {}", records[1]) };
+        assert_eq!(fields["recipient"], Value::String("Grace Hopper".to_string().into()));
+
+        let source = "to main() { give back gratitudeEntries(1); }";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        assert!(matches!(
+            Interpreter::new().run_main(&program),
+            Err(RuntimeError::ArityMismatch { expected: 0, got: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_stored_consents_skip_the_prompt() {
+        let source = r#"
+            to main() {
+                only if okay "camera" {
+                    print("camera on");
+                }
+                only if okay "microphone" {
+                    print("listening");
+                }
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+
+        let path = std::env::temp_dir().join("wokelang_test_interpreter_consents.json");
+        let _ = std::fs::remove_file(&path);
+        let file = std::env::temp_dir().join("wokelang_test_interpreter_consents.woke");
+        std::fs::write(&file, source).unwrap();
+        let file_name = file.canonicalize().unwrap().display().to_string();
+        let mut store = ConsentStore::new(path.clone());
+        store.store(&file_name, CONSENT_SCOPE, "camera", true, ConsentDuration::Forever).unwrap();
+
+        let output = OutputBuffer::new();
+        let asked = Rc::new(RefCell::new(Vec::new()));
+        let asked_log = Rc::clone(&asked);
+        Interpreter::new()
+            .with_output(output.clone())
+            .with_consent_store(store)
+            .with_program(&file)
+            .with_consent_handler(move |permission| {
+                asked_log.borrow_mut().push(permission.to_string());
+                true
+            })
+            .run(&program)
+            .unwrap();
+
+        assert_eq!(output.contents(), "camera on\nlistening\n");
+        assert_eq!(*asked.borrow(), ["microphone"]);
+
+        // Handler decisions last for this run only
+        let mut reloaded = ConsentStore::new(path.clone());
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.check(&file_name, CONSENT_SCOPE, "camera"), Some(true));
+        assert_eq!(reloaded.check(&file_name, CONSENT_SCOPE, "microphone"), None);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn test_string_module() {
+        let source = r#"
+            use std.string;
+            use std.string renamed text;
+
+            to main() {
+                remember words = std.string.split("  a,b,c ", ",");
+                print(len(words), string.trim(words[0]));
+                print(text.join(string.chars("xyz"), "-"), string.toUpper("shout"));
+                print(string.substring("hello", 1, 3), string.repeat("ab", 2));
+                print(string.startsWith("woke", "wo"), string.endsWith("woke", "x"));
+                print(string.replace("a-b", "-", "+"), string.contains("abc", "b"));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        assert_eq!(
+            output.contents(),
+            "3 a\nx-y-z SHOUT\nel abab\ntrue false\na+b true\n"
+        );
+
+        let err = run_program(r#"to main() { std.string.repeat("a", "b"); }"#).unwrap_err();
+        assert!(matches!(err, RuntimeError::TypeError(_)));
+        let err = run_program(r#"to main() { string.trim("a"); }"#).unwrap_err();
+        assert!(matches!(err, RuntimeError::UndefinedFunction { .. }));
+
+        let err = run_program(r#"use std.string; to main() { string.trmi("a"); }"#).unwrap_err();
+        assert_eq!(err.help().as_deref(), Some("did you mean `string.trim`?"));
+        let err = run_program("to helper() { } to main() { remember total = 1; helpr(totl); }").unwrap_err();
+        assert_eq!(err.help().as_deref(), Some("did you mean `total`?"));
+        let err = run_program("to helper() { } to main() { helpr(); }").unwrap_err();
+        assert_eq!(err.help().as_deref(), Some("did you mean `helper`?"));
+    }
+
+    #[test]
+    fn test_format_builtin() {
+        let source = r#"
+            to main() {
+                remember name = "Ada";
+                print(format("Hello {0}, you have {1} items", name, 3));
+                print(format("[{:>5}] [{:<5}] [{:^7.2}]", name, 42, 3.14159));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        assert_eq!(
+            output.contents(),
+            "Hello Ada, you have 3 items\n[  Ada] [42   ] [ 3.14  ]\n"
+        );
+
+        let err = run_program(r#"to main() { format("{1}", "only one"); }"#).unwrap_err();
+        assert!(matches!(err, RuntimeError::Stdlib { .. }));
+        let err = run_program(r#"to main() { format(); }"#).unwrap_err();
+        assert!(matches!(err, RuntimeError::ArityMismatch { .. }));
+    }
+
+    #[test]
+    fn test_array_module_callbacks() {
+        let source = r#"
+            use std.array;
+
+            to main() {
+                remember offset = 10;
+                remember nums = array.range(1, 6);
+                remember shifted = array.map(nums, |n| -> n + offset);
+                remember evens = array.filter(shifted, |n| -> n % 2 == 0);
+                remember total = array.reduce(evens, |acc, n| -> acc + n, 0);
+                print(shifted, evens, total);
+                print(array.sort([3, 1, 2]), array.sort(nums, |a, b| -> b - a));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        assert_eq!(
+            output.contents(),
+            "[11, 12, 13, 14, 15] [12, 14] 26\n[1, 2, 3] [5, 4, 3, 2, 1]\n"
+        );
+
+        // Errors from inside a callback are reported as themselves
+        let err = run_program("to main() { std.array.map([1, 0], |n| -> 1 / n); }").unwrap_err();
+        assert!(matches!(err, RuntimeError::DivisionByZero));
+        let err = run_program("to main() { std.array.map([1], 5); }").unwrap_err();
+        assert!(matches!(err, RuntimeError::TypeError(_)));
+    }
+
+    #[test]
+    fn test_map_module() {
+        let source = r#"
+            use std.map;
+
+            to main() {
+                remember config = std.json.parse("{\"name\": \"woke\", \"tags\": [\"a\"]}");
+                remember config = map.set(config, "version", 2);
+                print(config["name"], len(config), map.has(config, "tags"));
+                print(map.keys(config), map.get(config, "missing"));
+
+                remember merged = map.merge(map.new([["name", "lang"]]), map.remove(config, "tags"));
+                map.forEach(merged, |key, value| -> print(key, value));
+                print(std.json.stringify(merged));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        assert_eq!(
+            output.contents(),
+            "woke 3 true\n\
+             [name, tags, version] Oops(\"key not found: missing\")\n\
+             name woke\nversion 2\n\
+             {\"name\":\"woke\",\"version\":2}\n"
+        );
+
+        let err = run_program(r#"to main() { remember m = std.map.new(); print(m["x"]); }"#).unwrap_err();
+        assert!(matches!(err, RuntimeError::TypeError(_)));
+    }
+
+    #[test]
+    fn test_regex_module() {
+        let source = r#"
+            use std.regex;
+
+            to main() {
+                print(regex.match("^w\\w+$", "woke"), regex.findAll("[0-9]+", "a1b22"));
+                remember groups = unwrapOr(regex.captures("(?P<k>\\w+)=(\\w+)", "lang=woke"), 0);
+                print(groups["k"], groups["2"]);
+                print(regex.replace("(", "x", "y"));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        let lines: Vec<String> = output.contents().lines().map(String::from).collect();
+        assert_eq!(lines[0], "Okay(true) Okay([1, 22])");
+        assert_eq!(lines[1], "lang woke");
+        assert!(lines[2].starts_with("Oops(\"invalid regex"));
+    }
+
+    #[test]
+    fn test_json_module() {
+        let source = r#"
+            use std.json;
+
+            to main() {
+                remember data = json.parse("{\"name\": \"woke\", \"tags\": [1, 2.5, null]}");
+                print(data["name"], len(data["tags"]), data["tags"][1]);
+                print(json.stringify(data));
             }
-            _ => Ok(None), // Not a builtin
-        }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        let lines: Vec<String> = output.contents().lines().map(String::from).collect();
+        assert_eq!(lines[0], "woke 3 2.5");
+        assert_eq!(lines[1], r#"{"name":"woke","tags":[1,2.5,null]}"#);
     }
 
-    fn call_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
-        // Check recursion depth limit
-        if self.recursion_depth >= MAX_RECURSION_DEPTH {
-            return Err(RuntimeError::RecursionLimitExceeded);
-        }
-        self.recursion_depth += 1;
+    #[test]
+    fn test_bytes_value() {
+        let source = r#"
+            use std.bytes;
 
-        // First, check if name refers to a variable holding a closure
-        if let Some(value) = self.env.get(name).cloned() {
-            if let Value::Function(closure) = value {
-                let result = self.call_closure(&closure, args);
-                self.recursion_depth -= 1;
-                return result;
+            to main() {
+                remember data = bytes.fromString("wo") + bytes.fromArray([107, 101, 255]);
+                print(len(data), data[0], data[4]);
+                print(bytes.slice(data, 1, 4), bytes.toString(bytes.slice(data, 0, 4)));
             }
-        }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        let lines: Vec<String> = output.contents().lines().map(String::from).collect();
+        assert_eq!(lines[0], "5 119 255");
+        assert_eq!(lines[1], r#"b"oke" Okay(woke)"#);
+    }
 
-        // Otherwise, look up as a named function
-        let func = self
-            .functions
-            .get(name)
-            .cloned()
-            .ok_or_else(|| {
-                self.recursion_depth -= 1;
-                RuntimeError::UndefinedFunction(name.to_string())
-            })?;
+    #[test]
+    fn test_log_module_follows_verbose_pragma() {
+        let source = r#"
+            #verbose on;
+            use std.log;
 
-        if func.params.len() != args.len() {
-            self.recursion_depth -= 1;
-            return Err(RuntimeError::ArityMismatch {
-                expected: func.params.len(),
-                got: args.len(),
-            });
-        }
+            to main() {
+                log.debug("tracing");
+                log.info("ready", std.map.set(std.map.new(), "port", 8080));
+            }
+        "#;
+        let records = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&records);
+        log::set_sink(move |record| sink.borrow_mut().push(record.to_text()));
+
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        Interpreter::new()
+            .with_output(OutputBuffer::new())
+            .run(&program)
+            .unwrap();
+        log::reset_sink();
+        log::set_min_level(log::Level::Info);
+
+        let records = records.borrow();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].ends_with("DEBUG tracing"));
+        assert!(records[1].ends_with("INFO  ready port=8080"));
+    }
 
-        // Print hello message
-        if let Some(hello) = &func.hello {
-            if self.verbose {
-                println!("[{}] {}", name, hello);
+    #[test]
+    fn test_secrets_are_redacted() {
+        let source = r#"
+            #verbose on;
+            use std.log;
+
+            to main() {
+                remember secret token = "sk-live-0123456789";
+                log.info("calling with " + token, std.map.set(std.map.new(), "token", token));
+                print(token);
+                remember n = toInt(token);
             }
-        }
+        "#;
+        let records = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&records);
+        log::set_sink(move |record| sink.borrow_mut().push(record.to_text()));
+
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        let err = Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap_err();
+        log::reset_sink();
+        log::set_min_level(log::Level::Info);
+
+        // Printed on purpose, so shown; traced, logged or in an error, masked
+        let output = output.contents();
+        assert!(output.contains("remember token = \"***\""), "{}", output);
+        assert!(output.contains("\nsk-live-0123456789\n"), "{}", output);
+        assert!(records.borrow()[0].ends_with("INFO  *** token=***"), "{:?}", records.borrow());
+        assert_eq!(err.to_string(), "Type error: Cannot convert '***' to Int");
+    }
 
-        // Create new scope and bind parameters
-        self.env.push_scope();
-        for (param, arg) in func.params.iter().zip(args) {
-            self.env.define(param.name.clone(), arg);
-        }
+    #[test]
+    fn test_expect_blocks() {
+        let source = r#"
+            use std.test;
 
-        // Execute function body
-        let mut result = Value::Unit;
-        for stmt in &func.body {
-            match self.execute_statement(stmt)? {
-                ControlFlow::Return(v) => {
-                    result = v;
-                    break;
-                }
-                ControlFlow::Continue => {}
+            to double(n: Int) -> Int {
+                give back n * 2;
             }
-        }
 
-        self.env.pop_scope();
-        self.recursion_depth -= 1;
+            to main() {
+                print("main is not run by the test runner");
+            }
 
-        // Print goodbye message
-        if let Some(goodbye) = &func.goodbye {
-            if self.verbose {
-                println!("[{}] {}", name, goodbye);
+            expect "double doubles" {
+                remember result = double(21);
+                test.assertEqual(result, 42);
             }
-        }
 
-        Ok(result)
+            expect "failing check" {
+                test.assertTrue(double(1) == 3, "double(1)");
+                print("not reached");
+            }
+
+            expect "later blocks still run" {
+                test.assertOops(std.regex.match("(", "text"));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        let outcomes = Interpreter::new()
+            .with_output(output.clone())
+            .run_expectations(&program);
+
+        let summary: Vec<(&str, bool)> = outcomes
+            .iter()
+            .map(|o| (o.description.as_str(), o.passed()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("double doubles", true),
+                ("failing check", false),
+                ("later blocks still run", true),
+            ]
+        );
+        let error = outcomes[1].error.as_ref().unwrap().to_string();
+        assert!(error.contains("double(1): expected true"), "{}", error);
+        assert_eq!(output.contents(), "");
+
+        // A normal run skips the blocks
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        assert_eq!(output.contents(), "main is not run by the test runner\n");
     }
 
-    fn apply_binary_op(&self, op: BinaryOp, left: Value, right: Value) -> Result<Value> {
-        match op {
-            BinaryOp::Add => match (left, right) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a + b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
-                (Value::Int(a), Value::Float(b)) => Ok(Value::Float(a as f64 + b)),
-                (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a + b as f64)),
-                (Value::String(a), Value::String(b)) => Ok(Value::String(a + &b)),
-                (Value::String(a), b) => Ok(Value::String(a + &b.to_string())),
-                (a, Value::String(b)) => Ok(Value::String(a.to_string() + &b)),
-                _ => Err(RuntimeError::TypeError("Cannot add these types".into())),
-            },
-            BinaryOp::Sub => match (left, right) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a - b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
-                (Value::Int(a), Value::Float(b)) => Ok(Value::Float(a as f64 - b)),
-                (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a - b as f64)),
-                _ => Err(RuntimeError::TypeError("Cannot subtract these types".into())),
-            },
-            BinaryOp::Mul => match (left, right) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a * b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
-                (Value::Int(a), Value::Float(b)) => Ok(Value::Float(a as f64 * b)),
-                (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a * b as f64)),
-                _ => Err(RuntimeError::TypeError("Cannot multiply these types".into())),
-            },
-            BinaryOp::Div => match (left, right) {
-                (_, Value::Int(0)) => Err(RuntimeError::DivisionByZero),
-                (_, Value::Float(f)) if f == 0.0 => Err(RuntimeError::DivisionByZero),
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a / b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
-                (Value::Int(a), Value::Float(b)) => Ok(Value::Float(a as f64 / b)),
-                (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a / b as f64)),
-                _ => Err(RuntimeError::TypeError("Cannot divide these types".into())),
-            },
-            BinaryOp::Mod => match (left, right) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a % b)),
-                _ => Err(RuntimeError::TypeError("Modulo requires integers".into())),
-            },
-            BinaryOp::Eq => Ok(Value::Bool(left == right)),
-            BinaryOp::NotEq => Ok(Value::Bool(left != right)),
-            BinaryOp::Lt => match (left, right) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a < b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a < b)),
-                _ => Err(RuntimeError::TypeError("Cannot compare these types".into())),
-            },
-            BinaryOp::Gt => match (left, right) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a > b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a > b)),
-                _ => Err(RuntimeError::TypeError("Cannot compare these types".into())),
-            },
-            BinaryOp::LtEq => match (left, right) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a <= b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a <= b)),
-                _ => Err(RuntimeError::TypeError("Cannot compare these types".into())),
-            },
-            BinaryOp::GtEq => match (left, right) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a >= b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a >= b)),
-                _ => Err(RuntimeError::TypeError("Cannot compare these types".into())),
-            },
-            BinaryOp::And => Ok(Value::Bool(left.is_truthy() && right.is_truthy())),
-            BinaryOp::Or => Ok(Value::Bool(left.is_truthy() || right.is_truthy())),
-        }
+    #[test]
+    fn test_datetime_values() {
+        let source = r#"
+            use std.time;
+
+            to main() {
+                remember meeting = time.dateTime(2024, 3, 30, 15, 0, 0, "+01:00");
+                remember followUp = time.addDays(meeting, 3);
+                print(followUp, time.inZone(followUp, "UTC"));
+                print(followUp > meeting, time.weekday(followUp), time.format(followUp, "%d/%m %H:%M %z"));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        let lines: Vec<String> = output.contents().lines().map(String::from).collect();
+        assert_eq!(lines[0], "2024-04-02T15:00:00+01:00 2024-04-02T14:00:00Z");
+        assert_eq!(lines[1], "true 2 02/04 15:00 +0100");
     }
 
-    fn apply_unary_op(&self, op: UnaryOp, val: Value) -> Result<Value> {
-        match op {
-            UnaryOp::Neg => match val {
-                Value::Int(n) => Ok(Value::Int(-n)),
-                Value::Float(f) => Ok(Value::Float(-f)),
-                _ => Err(RuntimeError::TypeError("Cannot negate this type".into())),
-            },
-            UnaryOp::Not => Ok(Value::Bool(!val.is_truthy())),
-        }
+    #[test]
+    fn test_duration_values() {
+        let source = r#"
+            use std.time;
+
+            to main() {
+                remember meeting = time.dateTime(2024, 3, 30, 15, 0, 0, "+01:00");
+                remember length = 90 measured in minutes;
+                remember end = meeting + length;
+                print(length, end, end - meeting == length);
+                print(meeting - end, -length < length, 2 * length, time.duration(1.5, "s"));
+                time.sleep(5 measured in ms);
+                print(time.toSeconds(end - length - meeting), 3 measured in metres);
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        let lines: Vec<String> = output.contents().lines().map(String::from).collect();
+        assert_eq!(lines[0], "1h 30m 2024-03-30T16:30:00+01:00 true");
+        assert_eq!(lines[1], "-1h 30m true 3h 1s 500ms");
+        assert_eq!(lines[2], "0 3");
     }
-}
 
-impl Default for Interpreter {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn test_seeded_random_is_reproducible() {
+        let source = r#"
+            use std.math;
+
+            to main() {
+                print(math.randomInt(1, 100), math.shuffle([1, 2, 3, 4, 5]), math.random());
+                math.randomSeed(7);
+                print(math.randomChoice(["a", "b", "c"]));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let run = || {
+            let output = OutputBuffer::new();
+            Interpreter::new()
+                .with_seed(2024)
+                .with_output(output.clone())
+                .run(&program)
+                .unwrap();
+            output.contents()
+        };
+        assert_eq!(run(), run());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::Lexer;
-    use crate::parser::Parser;
+    #[test]
+    fn test_csv_module() {
+        let source = r#"
+            use std.csv;
 
-    fn run_program(source: &str) -> Result<()> {
-        let lexer = Lexer::new(source);
-        let tokens = lexer.tokenize().expect("Lexer failed");
-        let mut parser = Parser::new(tokens, source);
-        let program = parser.parse().expect("Parser failed");
-        let mut interpreter = Interpreter::new();
-        interpreter.run(&program)
+            to main() {
+                remember rows = csv.parse("name,score\nAda,3\nGrace,5\n");
+                print(len(rows), rows[1]["name"]);
+                print(csv.stringify(rows, ["score"]));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        assert_eq!(output.contents(), "2 Grace\nscore\n3\n5\n\n");
+    }
+
+    #[test]
+    fn test_config_module() {
+        let source = r#"
+            use std.config;
+
+            to main() {
+                remember settings = config.parseToml("[server]\nport = 8080\n");
+                remember hosts = config.parseYaml("hosts: [a, b]");
+                print(settings["server"]["port"] + 1, hosts["hosts"][1]);
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        assert_eq!(output.contents(), "8081 b\n");
     }
 
     #[test]
@@ -995,6 +3187,34 @@ fn test_string_indexing() {
         assert!(run_program(source).is_ok());
     }
 
+    #[test]
+    fn test_values_are_shared_until_changed() {
+        let source = r#"
+            to main() {
+                remember word = "woke";
+                remember copy = word;
+                copy = copy + "ness";
+                remember items = [1, 2];
+                remember more = items;
+                print(word, copy, len(items), len(more));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new()
+            .with_output(output.clone())
+            .run(&program)
+            .unwrap();
+        assert_eq!(output.contents(), "woke wokeness 2 2\n");
+
+        let items = Value::Array(vec![Value::Int(1)].into());
+        let (Value::Array(a), Value::Array(b)) = (&items, &items.clone()) else {
+            unreachable!()
+        };
+        assert!(Rc::ptr_eq(a, b));
+    }
+
     #[test]
     fn test_decide_with_result() {
         let source = r#"
@@ -1097,4 +3317,228 @@ fn test_lambda_no_params() {
         "#;
         assert!(run_program(source).is_ok());
     }
+
+    #[test]
+    fn test_args_builtin() {
+        // The same arguments as the std.env test, which sets them too
+        crate::stdlib::env::set_script_args(vec!["--name".to_string(), "Ada".to_string()]);
+        let source = r#"
+            use std.env;
+
+            to main() {
+                print(args());
+                print(len(args()) == len(env.args()));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        crate::typechecker::TypeChecker::new().check_program(&program).unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new().with_output(output.clone()).run(&program).unwrap();
+        assert_eq!(output.contents(), "[--name, Ada]\ntrue\n");
+    }
+
+    #[test]
+    fn test_native_functions() {
+        let source = r#"
+            to shout(s: String) -> String {
+                give back s + "!";
+            }
+
+            to main() {
+                record("started");
+                print(greet("Ada"), shout("hi"), scale(2));
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+
+        let output = OutputBuffer::new();
+        let recorded = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::clone(&recorded);
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        interpreter.register_native("record", 1, move |args| {
+            log.borrow_mut().push(args[0].to_string());
+            Ok(Value::Unit)
+        });
+        interpreter.register_native("greet", 1, |args| Ok(Value::String(format!("Hello, {}", args[0]).into())));
+        // The program's own function comes first
+        interpreter.register_native("shout", 1, |_| Ok(Value::Unit));
+        interpreter.register_native("scale", 1, |args| match &args[0] {
+            Value::Int(n) => Ok(Value::Int(n * 10)),
+            other => Err(StdlibError::TypeError { expected: "Int".to_string(), got: other.to_string() }),
+        });
+        interpreter.run(&program).unwrap();
+        assert_eq!(output.contents(), "Hello, Ada hi! 20\n");
+        assert_eq!(*recorded.borrow(), ["started"]);
+
+        let call = |interpreter: &mut Interpreter, code: &str| {
+            let tokens = Lexer::new(code).tokenize().unwrap();
+            let expr = Parser::new(tokens, code).parse_single_expression().unwrap();
+            interpreter.eval_snippet(&expr)
+        };
+        assert!(matches!(
+            call(&mut interpreter, "greet()"),
+            Err(RuntimeError::ArityMismatch { expected: 1, got: 0 })
+        ));
+        assert_eq!(call(&mut interpreter, "scale(\"x\")").unwrap_err().to_string(), "Type error: scale: expected Int, got x");
+
+        // Superpowers a host function is tagged with are asked for first
+        let native = interpreter
+            .register_native("notify", 1, |_| Err(StdlibError::RuntimeError("unreachable".to_string())))
+            .requires(Capability::Notify);
+        assert_eq!(native.capabilities(), [Capability::Notify]);
+        interpreter.set_consent_mode(ConsentMode::Deny);
+        assert!(matches!(call(&mut interpreter, "notify(1)"), Err(RuntimeError::ConsentDenied(c)) if c == "notify"));
+        interpreter.set_consent_mode(ConsentMode::Allow);
+        assert_eq!(call(&mut interpreter, "notify(1)").unwrap_err().to_string(), "notify: Runtime error: unreachable");
+    }
+
+    #[test]
+    fn test_bound_functions() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .bind("to greet(name: String, count: Int) → String", |name: String, count: i64| {
+                name.repeat(count as usize)
+            })
+            .unwrap();
+        interpreter
+            .bind("to half(n: Float) → Result<Float, String>", |n: f64| {
+                if n.is_finite() { Ok(n / 2.0) } else { Err("not a number") }
+            })
+            .unwrap();
+        interpreter.bind("to mean(values: [Float]) → Float", |values: Vec<f64>| {
+            values.iter().sum::<f64>() / values.len() as f64
+        }).unwrap();
+        // Declared types are checked at run time, even when the Rust side takes any value
+        interpreter.bind("to wrong(x: Int) → String", |x: Value| x).unwrap();
+
+        let call = |interpreter: &mut Interpreter, code: &str| {
+            let tokens = Lexer::new(code).tokenize().unwrap();
+            let expr = Parser::new(tokens, code).parse_single_expression().unwrap();
+            interpreter.eval_snippet(&expr)
+        };
+        assert_eq!(call(&mut interpreter, "greet(\"ab\", 3)").unwrap(), Value::String("ababab".to_string().into()));
+        assert_eq!(call(&mut interpreter, "half(3)").unwrap(), Value::Okay(Box::new(Value::Float(1.5))));
+        assert_eq!(call(&mut interpreter, "mean([1.0, 2, 6.0])").unwrap(), Value::Float(3.0));
+        assert_eq!(
+            call(&mut interpreter, "greet(\"ab\", \"x\")").unwrap_err().to_string(),
+            "Type error: greet: expected Int for count, got x"
+        );
+        assert_eq!(
+            call(&mut interpreter, "mean([1.0, \"two\"])").unwrap_err().to_string(),
+            "Type error: mean: expected [Float] for values, got [1, two]"
+        );
+        assert_eq!(
+            call(&mut interpreter, "wrong(1)").unwrap_err().to_string(),
+            "Type error: wrong: expected String to be given back, got 1"
+        );
+
+        assert!(matches!(
+            interpreter.bind("to two(a: Int, b: Int)", |a: i64| a),
+            Err(BindingError::Arity { declared: 2, taken: 1, .. })
+        ));
+        assert!(matches!(interpreter.bind("to untyped(a)", |a: Value| a), Err(BindingError::Signature { .. })));
+        assert!(matches!(interpreter.bind("greet(", || ()), Err(BindingError::Signature { .. })));
+
+        // The type checker checks calls against the signature and points at the argument
+        let source = "to main() {\n    remember s = greet(\"ab\", \"three\");\n}";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let error = interpreter.type_checker().check_program(&program).unwrap_err();
+        assert_eq!(error.to_string(), "greet: count should be Int, got String");
+        assert_eq!(error.code(), "WOKE-E0208");
+        assert_eq!(&source[error.span().unwrap()], "\"three\"");
+
+        let source = "to main() {\n    remember s = greet(\"ab\", 2);\n    remember h = half(1.5);\n}";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        interpreter.type_checker().check_program(&program).unwrap();
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        stacks: Vec<Vec<StackFrame>>,
+        locals: Vec<Vec<(String, Value)>>,
+    }
+
+    impl Debugger for std::rc::Rc<std::cell::RefCell<Recorder>> {
+        fn before_statement(&mut self, span: &Span, view: &DebugView<'_>) -> DebugAction {
+            let mut recorder = self.borrow_mut();
+            recorder.stacks.push(view.stack());
+            recorder.locals.push(view.locals(0));
+            assert_eq!(view.stack()[0].span, *span);
+            if view.lookup("stop") == Some(&Value::Bool(true)) {
+                DebugAction::Stop
+            } else {
+                DebugAction::Continue
+            }
+        }
+    }
+
+    #[test]
+    fn test_debugger() {
+        let source = r#"
+            to square(n: Int) -> Int {
+                give back n * n;
+            }
+            to main() {
+                remember x = square(3);
+                remember stop = true;
+                print(x);
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let recorder = std::rc::Rc::new(std::cell::RefCell::new(Recorder::default()));
+        let output = OutputBuffer::new();
+        let result = Interpreter::new()
+            .with_output(output.clone())
+            .with_debugger(recorder.clone())
+            .run(&program);
+        assert!(matches!(result, Err(RuntimeError::Stopped)));
+        assert_eq!(output.contents(), "", "the debugger stops before print");
+
+        let recorder = recorder.borrow();
+        let names: Vec<Vec<&str>> = recorder
+            .stacks
+            .iter()
+            .map(|stack| stack.iter().map(|f| f.function.as_str()).collect())
+            .collect();
+        assert_eq!(names, [vec!["main"], vec!["square", "main"], vec!["main"], vec!["main"]]);
+        assert_eq!(recorder.locals[1], [("n".to_string(), Value::Int(3))]);
+        assert_eq!(
+            recorder.locals[3],
+            [("stop".to_string(), Value::Bool(true)), ("x".to_string(), Value::Int(9))]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let load = |interpreter: &mut Interpreter, source: &str| {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            interpreter.run(&Parser::new(tokens, source).parse().unwrap()).unwrap();
+        };
+        let mut interpreter = Interpreter::new().with_consent_handler(|_| true);
+        load(&mut interpreter, "to double(n: Int) -> Int { give back n * 2; } thanks to { \"Ada\" → \"help\"; }");
+        interpreter.define_global("x", Value::Int(1));
+        let snapshot = interpreter.snapshot();
+        assert_eq!((snapshot.functions(), snapshot.globals()), (1, 1));
+
+        load(&mut interpreter, "to triple(n: Int) -> Int { give back n * 3; } only if okay \"camera\" { }");
+        interpreter.define_global("x", Value::Int(5));
+        interpreter.define_global("y", Value::Int(2));
+        assert_eq!(interpreter.consents(), [("camera", true)]);
+        interpreter.restore(&snapshot);
+        assert_eq!(interpreter.globals(), [("x", &Value::Int(1))]);
+        let functions: Vec<&str> = interpreter.functions().iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(functions, ["double"]);
+        assert!(interpreter.consents().is_empty());
+        assert_eq!(interpreter.gratitude.len(), 1);
+
+        interpreter.restore(&snapshot.definitions());
+        assert!(interpreter.globals().is_empty());
+        assert!(interpreter.gratitude.is_empty());
+        assert_eq!(interpreter.functions().len(), 1);
+    }
 }
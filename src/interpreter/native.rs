@@ -0,0 +1,84 @@
+//! Host Functions for Embedding WokeLang
+//!
+//! An application running WokeLang programs can make its own Rust
+//! functions callable from them with [`super::Interpreter::register_native`]
+//! or [`crate::vm::VirtualMachine::register_native`]. A program calls one
+//! by name like any function it defines itself; its own functions and
+//! variables of the same name come first. A host function can be tagged
+//! with the superpowers it uses, which are asked for on every call just as
+//! the standard library's are.
+
+use super::{Signature, Value};
+use crate::security::{Capability, CapabilityRegistry};
+use crate::stdlib::StdlibError;
+
+/// The Rust side of a host function, given the arguments it was called with
+pub type NativeFn = Box<dyn FnMut(&[Value]) -> Result<Value, StdlibError>>;
+
+/// A host function registered with an interpreter or VM
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    capabilities: Vec<Capability>,
+    func: NativeFn,
+    /// Its WokeLang type, if it was bound with one
+    pub(crate) signature: Option<Signature>,
+}
+
+impl NativeFunction {
+    pub(crate) fn new(
+        name: &str,
+        arity: usize,
+        func: impl FnMut(&[Value]) -> Result<Value, StdlibError> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            capabilities: Vec::new(),
+            func: Box::new(func),
+            signature: None,
+        }
+    }
+
+    /// Ask for `capability` before every call, refusing the call if it is
+    /// not granted
+    pub fn requires(&mut self, capability: Capability) -> &mut Self {
+        self.capabilities.push(capability);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How many arguments it must be called with
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// The WokeLang signature it was bound with, if any
+    pub fn signature(&self) -> Option<&Signature> {
+        self.signature.as_ref()
+    }
+
+    /// The superpowers asked for before each call
+    pub fn capabilities(&self) -> &[Capability] {
+        &self.capabilities
+    }
+
+    /// Check the arguments and superpowers, then call the host
+    pub(crate) fn call(&mut self, args: &[Value], capabilities: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+        if args.len() != self.arity {
+            return Err(StdlibError::ArityError {
+                expected: self.arity,
+                got: args.len(),
+            });
+        }
+        for capability in &self.capabilities {
+            capabilities
+                .request_current(capability)
+                .map_err(|_| StdlibError::PermissionDenied(capability.to_string()))?;
+        }
+        (self.func)(args)
+    }
+}
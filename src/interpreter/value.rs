@@ -1,4 +1,7 @@
-use crate::ast::{LambdaBody, Parameter};
+use crate::ast::{LambdaBody, Parameter, Symbol};
+use crate::formatter::quote;
+use crate::security::{secrets, Footprint};
+use chrono::{FixedOffset, SecondsFormat};
 use std::collections::HashMap;
 use std::fmt;
 use std::rc::Rc;
@@ -7,10 +10,15 @@
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+/// Columns a value is kept on one line within by [`Value::pretty`]
+const PRETTY_WIDTH: usize = 72;
+
+const PRETTY_INDENT: &str = "    ";
+
 /// Captured environment for closures
 #[derive(Debug, Clone)]
 pub struct CapturedEnv {
-    pub bindings: HashMap<String, Value>,
+    pub bindings: HashMap<Symbol, Value>,
 }
 
 impl CapturedEnv {
@@ -20,7 +28,7 @@ pub fn new() -> Self {
         }
     }
 
-    pub fn from_map(bindings: HashMap<String, Value>) -> Self {
+    pub fn from_map(bindings: HashMap<Symbol, Value>) -> Self {
         Self { bindings }
     }
 }
@@ -46,6 +54,24 @@ fn eq(&self, _other: &Self) -> bool {
     }
 }
 
+/// A function the bytecode VM can call: a compiled function, or a host
+/// function past them, with the values a lambda captured when it was made
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmClosure {
+    pub function: usize,
+    pub captures: Rc<Vec<Value>>,
+}
+
+impl VmClosure {
+    /// A named function, which captures nothing
+    pub fn new(function: usize) -> Self {
+        Self {
+            function,
+            captures: Rc::new(Vec::new()),
+        }
+    }
+}
+
 /// Channel handle for Go-style channels
 /// Channels allow typed, thread-safe communication between concurrent tasks
 #[derive(Clone)]
@@ -79,6 +105,8 @@ fn eq(&self, _other: &Self) -> bool {
     }
 }
 
+// Values hold `Rc` closures, so channels are shared within a single thread.
+#[allow(clippy::arc_with_non_send_sync)]
 impl ChannelHandle {
     /// Create a new unbuffered channel
     pub fn new() -> Self {
@@ -175,23 +203,150 @@ fn default() -> Self {
     }
 }
 
-/// Runtime value in WokeLang
+/// The text of a string value, shared like an array's items, and whether
+/// it is secret: read from the environment, declared `remember secret`, or
+/// made from text that was. Secret text is shown as it is where a program
+/// asks for it, as `print` does, and masked in logs, traces, audit entries
+/// and error messages. Two texts are equal if they read the same, secret
+/// or not.
+#[derive(Clone, Default)]
+pub struct Text {
+    text: Rc<String>,
+    secret: bool,
+}
+
+impl Text {
+    /// Secret text
+    pub fn secret(text: impl Into<Text>) -> Self {
+        Self {
+            secret: true,
+            ..text.into()
+        }
+    }
+
+    pub fn is_secret(&self) -> bool {
+        self.secret
+    }
+
+    /// The text, copied first if it is shared, to change in place
+    pub fn make_mut(&mut self) -> &mut String {
+        Rc::make_mut(&mut self.text)
+    }
+
+    /// Whether both are the same shared text
+    pub fn ptr_eq(a: &Text, b: &Text) -> bool {
+        Rc::ptr_eq(&a.text, &b.text)
+    }
+
+    /// Where the shared text is, to tell copies of it apart from equal text
+    pub fn as_ptr(&self) -> *const String {
+        Rc::as_ptr(&self.text)
+    }
+
+    /// The text as a log or error message shows it: [`secrets::MASK`] if
+    /// it is secret
+    pub fn shown(&self) -> &str {
+        match self.secret {
+            true => secrets::MASK,
+            false => &self.text,
+        }
+    }
+}
+
+impl std::ops::Deref for Text {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.text
+    }
+}
+
+impl From<String> for Text {
+    fn from(text: String) -> Self {
+        Rc::new(text).into()
+    }
+}
+
+impl From<&str> for Text {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+impl From<Rc<String>> for Text {
+    fn from(text: Rc<String>) -> Self {
+        Self { text, secret: false }
+    }
+}
+
+impl PartialEq for Text {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl Eq for Text {}
+
+impl PartialOrd for Text {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Text {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.text.cmp(&other.text)
+    }
+}
+
+impl std::hash::Hash for Text {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.text.hash(state)
+    }
+}
+
+impl fmt::Debug for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.shown(), f)
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Runtime value in WokeLang. Strings and arrays are shared, so copying a
+/// value, as every variable read does, is cheap; changing one copies it
+/// first only if it is shared, through [`Rc::make_mut`].
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Int(i64),
     Float(f64),
-    String(String),
+    /// An exact fractional number, e.g. `1.50d`
+    Decimal(rust_decimal::Decimal),
+    String(Text),
+    /// Raw binary data
+    Bytes(Vec<u8>),
+    /// A point in time with the UTC offset it is shown in
+    DateTime(chrono::DateTime<FixedOffset>),
+    /// A signed span of time, e.g. `90 measured in minutes`
+    Duration(chrono::TimeDelta),
     Bool(bool),
-    Array(Vec<Value>),
+    Array(Rc<Vec<Value>>),
     /// Record/object/map with string keys
     Record(HashMap<String, Value>),
     Unit,
     /// Result success: `Okay(value)`
     Okay(Box<Value>),
-    /// Result error: `Oops(message)`
-    Oops(String),
+    /// Result error: `Oops(error)`, where the error is a message or any
+    /// other value, such as a map with a `code` and a `message`
+    Oops(Box<Value>),
     /// First-class function/closure
     Function(Closure),
+    /// A function or lambda compiled to bytecode
+    VmFunction(VmClosure),
     /// Go-style channel for concurrent communication
     Channel(ChannelHandle),
 }
@@ -203,13 +358,17 @@ pub fn is_truthy(&self) -> bool {
             Value::Bool(b) => *b,
             Value::Int(n) => *n != 0,
             Value::Float(f) => *f != 0.0,
+            Value::Decimal(d) => !d.is_zero(),
             Value::String(s) => !s.is_empty(),
+            Value::Bytes(b) => !b.is_empty(),
+            Value::DateTime(_) => true,
+            Value::Duration(d) => !d.is_zero(),
             Value::Array(a) => !a.is_empty(),
             Value::Record(m) => !m.is_empty(),
             Value::Unit => false,
             Value::Okay(_) => true,
             Value::Oops(_) => false,
-            Value::Function(_) => true,
+            Value::Function(_) | Value::VmFunction(_) => true,
             Value::Channel(ch) => !ch.is_closed(),
         }
     }
@@ -224,14 +383,205 @@ pub fn is_oops(&self) -> bool {
         matches!(self, Value::Oops(_))
     }
 
-    /// Unwrap an Okay value, or return the error
+    /// An Oops carrying a message
+    pub fn oops(message: impl Into<String>) -> Value {
+        Value::Oops(Box::new(Value::String(message.into().into())))
+    }
+
+    /// Unwrap an Okay value, or return the error's message
     pub fn unwrap(self) -> Result<Value, String> {
         match self {
             Value::Okay(v) => Ok(*v),
-            Value::Oops(e) => Err(e),
+            Value::Oops(e) => Err(e.error_message()),
             other => Ok(other), // Non-result values pass through
         }
     }
+
+    /// This value as the message of an error carrying it: a string as it
+    /// is, a map's `message` if it has one, or else the value as text
+    pub fn error_message(&self) -> String {
+        match self {
+            Value::String(s) => s.to_string(),
+            Value::Record(fields) => match fields.get("message") {
+                Some(Value::String(message)) => message.to_string(),
+                _ => self.to_string(),
+            },
+            other => other.to_string(),
+        }
+    }
+
+    /// Gratitude entries as `gratitudeEntries()` gives them: an array of
+    /// records, each with a `recipient` and a `reason`
+    pub fn gratitude<'a>(entries: impl IntoIterator<Item = (&'a str, &'a str)>) -> Value {
+        let records: Vec<Value> = entries
+            .into_iter()
+            .map(|(recipient, reason)| {
+                Value::Record(HashMap::from([
+                    ("recipient".to_string(), Value::String(recipient.to_string().into())),
+                    ("reason".to_string(), Value::String(reason.to_string().into())),
+                ]))
+            })
+            .collect();
+        Value::Array(records.into())
+    }
+
+    /// Whether this is or holds secret text
+    pub fn is_secret(&self) -> bool {
+        match self {
+            Value::String(s) => s.is_secret(),
+            Value::Array(items) => items.iter().any(Value::is_secret),
+            Value::Record(fields) => fields.values().any(Value::is_secret),
+            Value::Okay(inner) | Value::Oops(inner) => inner.is_secret(),
+            _ => false,
+        }
+    }
+
+    /// This value with all its text secret, as `remember secret` and
+    /// reading the environment make it
+    pub fn into_secret(self) -> Value {
+        match self {
+            Value::String(s) => Value::String(Text::secret(s)),
+            Value::Array(items) => {
+                Value::Array(items.iter().cloned().map(Value::into_secret).collect::<Vec<_>>().into())
+            }
+            Value::Record(fields) => {
+                Value::Record(fields.into_iter().map(|(k, v)| (k, v.into_secret())).collect())
+            }
+            Value::Okay(inner) => Value::Okay(Box::new(inner.into_secret())),
+            Value::Oops(inner) => Value::Oops(Box::new(inner.into_secret())),
+            other => other,
+        }
+    }
+
+    /// This value, with all its text secret if `secret`, as a value made
+    /// from secret text is
+    pub fn secret_if(self, secret: bool) -> Value {
+        match secret {
+            true => self.into_secret(),
+            false => self,
+        }
+    }
+
+    /// The secret text this value holds
+    pub fn secret_texts(&self) -> Vec<String> {
+        let mut texts = Vec::new();
+        self.collect_secret_texts(&mut texts);
+        texts
+    }
+
+    fn collect_secret_texts(&self, texts: &mut Vec<String>) {
+        match self {
+            Value::String(s) if s.is_secret() => texts.push(s.to_string()),
+            Value::Array(items) => items.iter().for_each(|item| item.collect_secret_texts(texts)),
+            Value::Record(fields) => fields.values().for_each(|value| value.collect_secret_texts(texts)),
+            Value::Okay(inner) | Value::Oops(inner) => inner.collect_secret_texts(texts),
+            _ => {}
+        }
+    }
+
+    /// A copy with its secret text masked, for writing to a log
+    pub fn redacted(&self) -> Value {
+        match self {
+            Value::String(s) if s.is_secret() => Value::String(secrets::MASK.into()),
+            Value::Oops(e) => Value::Oops(Box::new(e.redacted())),
+            Value::Array(items) => Value::Array(items.iter().map(Value::redacted).collect::<Vec<_>>().into()),
+            Value::Record(fields) => Value::Record(
+                fields.iter().map(|(k, v)| (k.clone(), v.redacted())).collect(),
+            ),
+            Value::Okay(inner) => Value::Okay(Box::new(inner.redacted())),
+            other => other.clone(),
+        }
+    }
+
+    /// The exit status of a program whose `main` gave back this value, or
+    /// called `exit` with it: an Int from 0 to 255 as it is, 1 for any
+    /// other Int or an Oops, and 0 for anything else
+    pub fn exit_status(&self) -> u8 {
+        match self {
+            Value::Int(code) => u8::try_from(*code).unwrap_or(1),
+            Value::Oops(_) => 1,
+            _ => 0,
+        }
+    }
+
+    /// Add the memory this value holds to `footprint`, for `#maxmemory`
+    pub fn measure(&self, footprint: &mut Footprint) {
+        let contents = match self {
+            Value::String(s) if footprint.first_sight(s.as_ptr() as usize) => s.capacity(),
+            Value::Bytes(bytes) => bytes.capacity(),
+            Value::Array(items) if footprint.first_sight(Rc::as_ptr(items) as usize) => {
+                items.iter().for_each(|item| item.measure(footprint));
+                0
+            }
+            Value::Record(fields) => fields
+                .iter()
+                .map(|(k, v)| {
+                    v.measure(footprint);
+                    k.capacity()
+                })
+                .sum(),
+            Value::Okay(inner) | Value::Oops(inner) => {
+                inner.measure(footprint);
+                0
+            }
+            Value::Function(closure) if footprint.first_sight(Rc::as_ptr(&closure.env) as usize) => {
+                if let Ok(env) = closure.env.try_borrow() {
+                    env.bindings.values().for_each(|v| v.measure(footprint));
+                }
+                0
+            }
+            _ => 0,
+        };
+        footprint.add((std::mem::size_of::<Value>() + contents) as u64);
+    }
+
+    /// The value over as many lines as it needs to stay readable: arrays
+    /// and records that do not fit on one line put each element on its
+    /// own, indented, with record keys in order and strings quoted. An
+    /// array met again inside itself is shown as `[...]`.
+    pub fn pretty(&self) -> String {
+        self.pretty_at(0, &mut Vec::new())
+    }
+
+    /// The value as [`Value::pretty`] shows it, starting `indent` levels
+    /// in, within the arrays at `open`
+    fn pretty_at(&self, indent: usize, open: &mut Vec<usize>) -> String {
+        let (elements, brackets): (Vec<String>, _) = match self {
+            Value::Array(items) => {
+                let address = Rc::as_ptr(items) as usize;
+                if open.contains(&address) {
+                    return "[...]".to_string();
+                }
+                open.push(address);
+                let elements = items.iter().map(|item| item.pretty_at(indent + 1, open)).collect();
+                open.pop();
+                (elements, ("[", "]"))
+            }
+            Value::Record(fields) => {
+                let mut keys: Vec<&String> = fields.keys().collect();
+                keys.sort();
+                let elements = keys
+                    .into_iter()
+                    .map(|key| format!("{}: {}", key, fields[key].pretty_at(indent + 1, open)))
+                    .collect();
+                (elements, ("{", "}"))
+            }
+            Value::Okay(inner) => return format!("Okay({})", inner.pretty_at(indent, open)),
+            Value::Oops(inner) => return format!("Oops({})", inner.pretty_at(indent, open)),
+            Value::String(s) => return quote(s),
+            Value::Decimal(d) => return format!("{}d", d),
+            other => return other.to_string(),
+        };
+
+        let line = format!("{}{}{}", brackets.0, elements.join(", "), brackets.1);
+        let fits = indent * PRETTY_INDENT.len() + line.chars().count() <= PRETTY_WIDTH;
+        if elements.is_empty() || (fits && !line.contains('\n')) {
+            return line;
+        }
+        let inner = PRETTY_INDENT.repeat(indent + 1);
+        let elements: Vec<String> = elements.iter().map(|e| format!("{}{}", inner, e)).collect();
+        format!("{}\n{}\n{}{}", brackets.0, elements.join(",\n"), PRETTY_INDENT.repeat(indent), brackets.1)
+    }
 }
 
 impl fmt::Display for Value {
@@ -239,7 +589,22 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Int(n) => write!(f, "{}", n),
             Value::Float(n) => write!(f, "{}", n),
+            Value::Decimal(n) => write!(f, "{}", n),
             Value::String(s) => write!(f, "{}", s),
+            // Printable ASCII as is, everything else escaped: b"GIF\x89"
+            Value::Bytes(bytes) => {
+                write!(f, "b\"")?;
+                for byte in bytes {
+                    match byte {
+                        b'"' | b'\\' => write!(f, "\\{}", *byte as char)?,
+                        0x20..=0x7e => write!(f, "{}", *byte as char)?,
+                        _ => write!(f, "\\x{:02x}", byte)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::DateTime(dt) => write!(f, "{}", dt.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            Value::Duration(d) => write!(f, "{}", crate::stdlib::duration::format_duration(d)),
             Value::Bool(b) => write!(f, "{}", b),
             Value::Array(elements) => {
                 write!(f, "[")?;
@@ -263,11 +628,15 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             }
             Value::Unit => write!(f, "()"),
             Value::Okay(v) => write!(f, "Okay({})", v),
-            Value::Oops(e) => write!(f, "Oops(\"{}\")", e),
+            Value::Oops(e) => match &**e {
+                Value::String(message) => write!(f, "Oops(\"{}\")", message),
+                other => write!(f, "Oops({})", other),
+            },
             Value::Function(closure) => {
                 let param_names: Vec<_> = closure.params.iter().map(|p| p.name.as_str()).collect();
                 write!(f, "|{}| -> <closure>", param_names.join(", "))
             }
+            Value::VmFunction(_) => write!(f, "<closure>"),
             Value::Channel(ch) => {
                 let status = if ch.is_closed() { "closed" } else { "open" };
                 match &ch.name {
@@ -1,5 +1,9 @@
+mod source;
+mod symbol;
 mod token;
 
+pub use source::SourceFile;
+pub use symbol::Symbol;
 pub use token::Token;
 
 use logos::Logos;
@@ -8,14 +12,21 @@
 
 #[derive(Error, Debug, Diagnostic)]
 #[error("Unexpected character")]
-#[diagnostic(code(wokelang::lexer::unexpected_char))]
+#[diagnostic(code("WOKE-E0001"), help("run `woke explain WOKE-E0001` for more about this error"))]
 pub struct LexerError {
     #[source_code]
-    pub src: String,
+    pub src: SourceFile,
     #[label("here")]
     pub span: SourceSpan,
 }
 
+impl LexerError {
+    /// The error's code in the `woke explain` catalog
+    pub fn code(&self) -> &'static str {
+        "WOKE-E0001"
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Spanned<T> {
     pub value: T,
@@ -30,33 +41,81 @@ pub fn new(value: T, span: std::ops::Range<usize>) -> Self {
 
 pub struct Lexer<'src> {
     source: &'src str,
+    file: Option<SourceFile>,
 }
 
 impl<'src> Lexer<'src> {
     pub fn new(source: &'src str) -> Self {
-        Self { source }
+        Self { source, file: None }
+    }
+
+    /// Lex a file, sharing it with the error if there is one
+    pub fn for_file(file: &'src SourceFile) -> Self {
+        Self {
+            source: file.text(),
+            file: Some(file.clone()),
+        }
     }
 
+    /// Split the source into tokens. A `#!` line at the very start, as in
+    /// `#!/usr/bin/env woke`, is skipped so scripts can be executable.
     pub fn tokenize(&self) -> Result<Vec<Spanned<Token>>, LexerError> {
-        let mut tokens = Vec::new();
+        self.tokens().collect()
+    }
+
+    /// The source's tokens, each lexed only when it is asked for, ending
+    /// with `Eof`, or with the error that stops them
+    pub fn tokens(&self) -> Tokens<'src> {
         let mut lexer = Token::lexer(self.source);
+        if self.source.starts_with("#!") {
+            lexer.bump(self.source.find('\n').unwrap_or(self.source.len()));
+        }
+        Tokens {
+            lexer,
+            file: self.file.clone(),
+            done: false,
+        }
+    }
+
+    pub(crate) fn source(&self) -> &'src str {
+        self.source
+    }
+
+    pub(crate) fn file(&self) -> Option<&SourceFile> {
+        self.file.as_ref()
+    }
+}
+
+/// The tokens of a source as [`Lexer::tokens`] gives them
+pub struct Tokens<'src> {
+    lexer: logos::Lexer<'src, Token>,
+    file: Option<SourceFile>,
+    done: bool,
+}
 
-        while let Some(result) = lexer.next() {
-            match result {
-                Ok(token) => {
-                    tokens.push(Spanned::new(token, lexer.span()));
-                }
-                Err(_) => {
-                    return Err(LexerError {
-                        src: self.source.to_string(),
-                        span: lexer.span().into(),
-                    });
-                }
+impl Iterator for Tokens<'_> {
+    type Item = Result<Spanned<Token>, LexerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.lexer.next() {
+            Some(Ok(token)) => Some(Ok(Spanned::new(token, self.lexer.span()))),
+            Some(Err(_)) => {
+                self.done = true;
+                let source = self.lexer.source();
+                Some(Err(LexerError {
+                    src: self.file.clone().unwrap_or_else(|| SourceFile::new(source)),
+                    span: self.lexer.span().into(),
+                }))
+            }
+            None => {
+                self.done = true;
+                let end = self.lexer.source().len();
+                Some(Ok(Spanned::new(Token::Eof, end..end)))
             }
         }
-
-        tokens.push(Spanned::new(Token::Eof, self.source.len()..self.source.len()));
-        Ok(tokens)
     }
 }
 
@@ -79,6 +138,19 @@ fn test_simple_function() {
         assert!(matches!(tokens[1].value, Token::Identifier(_)));
     }
 
+    #[test]
+    fn test_shebang() {
+        let source = "#!/usr/bin/env woke\nto main() { }";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        assert!(matches!(tokens[0].value, Token::To));
+        assert_eq!(tokens[0].span, 20..22);
+
+        let tokens = Lexer::new("#!/usr/bin/env woke").tokenize().unwrap();
+        assert!(matches!(tokens[0].value, Token::Eof));
+        // Only the first line can be one
+        assert!(Lexer::new("\n#!/usr/bin/env woke").tokenize().is_err());
+    }
+
     #[test]
     fn test_consent_block() {
         let source = r#"only if okay "access_camera" { }"#;
@@ -93,7 +165,7 @@ fn test_consent_block() {
 
     #[test]
     fn test_numbers() {
-        let source = "42 3.14 -17";
+        let source = "42 3.14 -17 1.50d 3d";
 
         let lexer = Lexer::new(source);
         let tokens = lexer.tokenize().unwrap();
@@ -102,6 +174,8 @@ fn test_numbers() {
         assert!(matches!(tokens[1].value, Token::Float(_)));
         assert!(matches!(tokens[2].value, Token::Minus));
         assert!(matches!(tokens[3].value, Token::Integer(17)));
+        assert_eq!(tokens[4].value.to_string(), "1.50d");
+        assert_eq!(tokens[5].value.to_string(), "3d");
     }
 
     #[test]
@@ -114,4 +188,52 @@ fn test_emote_tag() {
         assert!(matches!(tokens[0].value, Token::At));
         assert!(matches!(tokens[1].value, Token::Identifier(_)));
     }
+
+    #[test]
+    fn test_named_source() {
+        use miette::SourceCode;
+
+        let file = SourceFile::named("shapes/circle.woke", "to main() {\n    $\n}");
+        let error = Lexer::for_file(&file).tokenize().unwrap_err();
+        assert_eq!(error.src.name(), Some("shapes/circle.woke"));
+        assert!(std::ptr::eq(error.src.text(), file.text()));
+
+        let contents = error.src.read_span(&error.span, 0, 0).unwrap();
+        assert_eq!(contents.name(), Some("shapes/circle.woke"));
+        assert_eq!(contents.line(), 1);
+
+        let error = Lexer::new("$").tokenize().unwrap_err();
+        assert_eq!(error.src.read_span(&error.span, 0, 0).unwrap().name(), None);
+    }
+
+    #[test]
+    fn test_tokens_are_lexed_lazily() {
+        // Tokens before an error come out, and lexing stops at it
+        let mut tokens = Lexer::new("to main $ {").tokens();
+        assert!(matches!(tokens.next(), Some(Ok(Spanned { value: Token::To, .. }))));
+        assert!(matches!(tokens.next(), Some(Ok(Spanned { value: Token::Identifier(_), .. }))));
+        assert_eq!(tokens.next().unwrap().unwrap_err().span, (8, 1).into());
+        assert!(tokens.next().is_none());
+
+        let tokens: Vec<_> = Lexer::new("#!/usr/bin/env woke\nto").tokens().map(|t| t.unwrap().value).collect();
+        assert!(matches!(tokens[..], [Token::To, Token::Eof]));
+    }
+
+    #[test]
+    fn test_identifiers_are_interned() {
+        let tokens = Lexer::new("count = count + counter;").tokenize().unwrap();
+        let names: Vec<Symbol> = tokens
+            .iter()
+            .filter_map(|t| match t.value {
+                Token::Identifier(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names.len(), 3);
+        assert!(std::ptr::eq(names[0].as_str(), names[1].as_str()));
+        assert_ne!(names[0], names[2]);
+        assert_eq!(names[2], "counter");
+        assert_eq!(Symbol::intern("count"), names[0]);
+    }
+
 }
@@ -0,0 +1,58 @@
+use miette::{MietteError, MietteSpanContents, SourceCode, SourceSpan, SpanContents};
+use std::sync::Arc;
+
+/// A program's text and the file it came from, made once per compilation
+/// and shared by every diagnostic pointing into it; cloning is cheap
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    name: Option<Arc<str>>,
+    text: Arc<str>,
+}
+
+impl SourceFile {
+    /// Text that did not come from a file, such as a REPL line
+    pub fn new(text: &str) -> Self {
+        Self {
+            name: None,
+            text: Arc::from(text),
+        }
+    }
+
+    /// Text read from `name`, which diagnostics show before the line number
+    pub fn named(name: impl AsRef<str>, text: &str) -> Self {
+        Self {
+            name: Some(Arc::from(name.as_ref())),
+            text: Arc::from(text),
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl SourceCode for SourceFile {
+    fn read_span<'a>(
+        &'a self,
+        span: &SourceSpan,
+        context_lines_before: usize,
+        context_lines_after: usize,
+    ) -> Result<Box<dyn SpanContents<'a> + 'a>, MietteError> {
+        let contents = self.text.read_span(span, context_lines_before, context_lines_after)?;
+        let Some(name) = &self.name else {
+            return Ok(contents);
+        };
+        Ok(Box::new(MietteSpanContents::new_named(
+            name.to_string(),
+            contents.data(),
+            *contents.span(),
+            contents.line(),
+            contents.column(),
+            contents.line_count(),
+        )))
+    }
+}
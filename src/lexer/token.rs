@@ -1,4 +1,7 @@
+use super::Symbol;
 use logos::Logos;
+use rust_decimal::Decimal;
+use std::str::FromStr;
 
 fn parse_string(lex: &mut logos::Lexer<Token>) -> Option<String> {
     let slice = lex.slice();
@@ -26,6 +29,12 @@ fn parse_string(lex: &mut logos::Lexer<Token>) -> Option<String> {
     Some(result)
 }
 
+/// A Decimal literal keeps the digits as written, so `1.50d` has two places
+fn parse_decimal(lex: &mut logos::Lexer<Token>) -> Option<Decimal> {
+    let slice = lex.slice();
+    Decimal::from_str(&slice[..slice.len() - 1]).ok()
+}
+
 #[derive(Logos, Debug, Clone, PartialEq)]
 #[logos(skip r"[ \t\n\r\f]+")]
 #[logos(skip r"//[^\n]*")]
@@ -66,6 +75,9 @@ pub enum Token {
     #[token("okay")]
     Okay,
 
+    #[token("because")]
+    Because,
+
     #[token("attempt")]
     Attempt,
 
@@ -105,6 +117,10 @@ pub enum Token {
     #[token("spawn")]
     Spawn,
 
+    // === Keywords - Testing ===
+    #[token("expect")]
+    Expect,
+
     // === Keywords - Pattern Matching ===
     #[token("decide")]
     Decide,
@@ -280,12 +296,15 @@ pub enum Token {
     #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().parse::<f64>().ok())]
     Float(f64),
 
+    #[regex(r"[0-9]+(\.[0-9]+)?d", parse_decimal)]
+    Decimal(Decimal),
+
     #[regex(r#""([^"\\]|\\.)*""#, parse_string)]
     String(String),
 
     // === Identifiers ===
-    #[regex(r"[a-zA-Z][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
-    Identifier(String),
+    #[regex(r"[a-zA-Z][a-zA-Z0-9_]*", |lex| Symbol::intern(lex.slice()))]
+    Identifier(Symbol),
 
     // === Special ===
     Eof,
@@ -305,6 +324,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             Token::Only => write!(f, "only"),
             Token::If => write!(f, "if"),
             Token::Okay => write!(f, "okay"),
+            Token::Because => write!(f, "because"),
             Token::Attempt => write!(f, "attempt"),
             Token::Safely => write!(f, "safely"),
             Token::Reassure => write!(f, "reassure"),
@@ -317,6 +337,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             Token::Quest => write!(f, "quest"),
             Token::Superpower => write!(f, "superpower"),
             Token::Spawn => write!(f, "spawn"),
+            Token::Expect => write!(f, "expect"),
             Token::Decide => write!(f, "decide"),
             Token::Based => write!(f, "based"),
             Token::On => write!(f, "on"),
@@ -372,6 +393,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             Token::Underscore => write!(f, "_"),
             Token::Integer(n) => write!(f, "{}", n),
             Token::Float(n) => write!(f, "{}", n),
+            Token::Decimal(n) => write!(f, "{}d", n),
             Token::String(s) => write!(f, "\"{}\"", s),
             Token::Identifier(s) => write!(f, "{}", s),
             Token::Eof => write!(f, "EOF"),
@@ -1,16 +1,40 @@
 pub mod ast;
+pub mod bench;
+pub mod codegen;
+pub mod coverage;
+#[cfg(feature = "cli")]
+pub mod dap;
+pub mod explain;
+pub mod ffi;
+pub mod formatter;
 pub mod interpreter;
 pub mod lexer;
+pub mod lint;
+pub mod notebook;
 pub mod parser;
+pub mod pipeline;
+#[cfg(feature = "wasm")]
+pub mod playground;
+pub mod profile;
+pub mod project;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "cli")]
 pub mod repl;
 pub mod security;
 pub mod stdlib;
+pub mod testing;
 pub mod typechecker;
+pub mod vm;
+#[cfg(feature = "cli")]
+pub mod watch;
 
 pub use ast::Program;
 pub use interpreter::Interpreter;
 pub use lexer::Lexer;
 pub use parser::Parser;
+pub use pipeline::{Diagnostic, Diagnostics, Engine, Pipeline, Severity};
+#[cfg(feature = "cli")]
 pub use repl::Repl;
 pub use security::CapabilityRegistry;
 pub use stdlib::StdlibRegistry;
@@ -0,0 +1,304 @@
+//! Lints
+//!
+//! Warnings about programs that check and run, but probably do not do what
+//! was meant. Each lint has a name, which `@allow(...)` on a function or a
+//! statement takes to silence it there, and a code in the `woke explain`
+//! catalog:
+//!
+//! ```text
+//! @allow(unused)
+//! to main() {
+//!     remember spare = 1;
+//! }
+//! ```
+
+use crate::ast::*;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Something a program does that is allowed but likely a mistake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A variable that is remembered but never read
+    Unused,
+    /// A statement after `give back` or `complain`, which never runs
+    Unreachable,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 2] = [Lint::Unused, Lint::Unreachable];
+
+    /// The name `@allow(...)` takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::Unused => "unused",
+            Lint::Unreachable => "unreachable",
+        }
+    }
+
+    /// The lint's code in the `woke explain` catalog
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lint::Unused => "WOKE-W0001",
+            Lint::Unreachable => "WOKE-W0002",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+/// One lint found in a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Every lint in a program that is not allowed where it is found, in
+/// source order
+pub fn lint(program: &Program) -> Vec<Warning> {
+    let mut linter = Linter::default();
+    for item in &program.items {
+        match item {
+            TopLevelItem::Function(f) => linter.allowing(f.emote.as_ref(), |linter| {
+                linter.body(&f.name, &f.body);
+            }),
+            TopLevelItem::ConsentBlock(c) => linter.body("a consent block", &c.body),
+            TopLevelItem::WorkerDef(w) => linter.body(&format!("worker {}", w.name), &w.body),
+            TopLevelItem::SideQuestDef(s) => linter.body(&format!("side quest {}", s.name), &s.body),
+            TopLevelItem::SuperpowerDecl(s) => linter.body(&format!("superpower {}", s.name), &s.body),
+            TopLevelItem::Expect(e) => linter.body(&format!("expect \"{}\"", e.description), &e.body),
+            _ => {}
+        }
+    }
+    linter.warnings.sort_by_key(|w| w.span.start);
+    linter.warnings
+}
+
+/// Variables remembered in one body, and the names read in it
+#[derive(Default)]
+struct Uses {
+    declared: Vec<(Symbol, Span)>,
+    read: HashSet<Symbol>,
+}
+
+#[derive(Default)]
+struct Linter {
+    warnings: Vec<Warning>,
+    /// Lints allowed by the `@allow` tags around the current node
+    allowed: Vec<Lint>,
+}
+
+impl Linter {
+    fn warn(&mut self, lint: Lint, message: String, span: &Span) {
+        if !self.allowed.contains(&lint) {
+            self.warnings.push(Warning {
+                lint,
+                message,
+                span: span.clone(),
+            });
+        }
+    }
+
+    /// Run `f` with the lints an `@allow(...)` tag names allowed
+    fn allowing(&mut self, emote: Option<&EmoteTag>, f: impl FnOnce(&mut Self)) {
+        let before = self.allowed.len();
+        if let Some(emote) = emote.filter(|emote| emote.name == "allow") {
+            self.allowed
+                .extend(emote.params.iter().filter_map(|param| Lint::from_name(&param.name)));
+        }
+        f(self);
+        self.allowed.truncate(before);
+    }
+
+    /// Lint the body of a function or block-like item, named `owner` in
+    /// messages
+    fn body(&mut self, owner: &str, body: &[Statement]) {
+        let mut uses = Uses::default();
+        self.block(body, &mut uses);
+        for (name, span) in uses.declared {
+            if !uses.read.contains(&name) {
+                self.warnings.push(Warning {
+                    lint: Lint::Unused,
+                    message: format!("`{}` is remembered in {} but never used", name, owner),
+                    span,
+                });
+            }
+        }
+    }
+
+    fn block(&mut self, stmts: &[Statement], uses: &mut Uses) {
+        let mut ended = None;
+        for stmt in stmts {
+            if let Some(reason) = ended.take() {
+                let emote = match stmt {
+                    Statement::EmoteAnnotated(annotated) => Some(&annotated.emote),
+                    _ => None,
+                };
+                self.allowing(emote, |linter| {
+                    linter.warn(
+                        Lint::Unreachable,
+                        format!("This statement never runs, as it comes after `{}`", reason),
+                        stmt.span(),
+                    )
+                });
+            }
+            self.statement(stmt, uses);
+            ended = ends_block(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement, uses: &mut Uses) {
+        match stmt {
+            Statement::VarDecl(decl) => {
+                self.expr(&decl.value, uses);
+                if !self.allowed.contains(&Lint::Unused) {
+                    uses.declared.push((decl.name, decl.span.clone()));
+                }
+            }
+            Statement::Assignment(assign) => self.expr(&assign.value, uses),
+            Statement::Return(ret) => self.expr(&ret.value, uses),
+            Statement::Conditional(cond) => {
+                self.expr(&cond.condition, uses);
+                self.block(&cond.then_branch, uses);
+                if let Some(else_branch) = &cond.else_branch {
+                    self.block(else_branch, uses);
+                }
+            }
+            Statement::Loop(l) => {
+                self.expr(&l.count, uses);
+                self.block(&l.body, uses);
+            }
+            Statement::AttemptBlock(attempt) => self.block(&attempt.body, uses),
+            Statement::ConsentBlock(consent) => self.block(&consent.body, uses),
+            Statement::Expression(expr) => self.expr(expr, uses),
+            Statement::WorkerSpawn(_) | Statement::Complain(_) => {}
+            Statement::EmoteAnnotated(annotated) => self.allowing(Some(&annotated.emote), |linter| {
+                linter.statement(&annotated.statement, uses);
+            }),
+            Statement::Decide(decide) => {
+                self.expr(&decide.scrutinee, uses);
+                for arm in &decide.arms {
+                    self.block(&arm.body, uses);
+                }
+            }
+        }
+    }
+
+    /// Record the names an expression reads; lambdas share the enclosing
+    /// body's variables
+    fn expr(&mut self, expr: &Spanned<Expr>, uses: &mut Uses) {
+        match &expr.node {
+            Expr::Literal(_) | Expr::GratitudeLiteral(_) => {}
+            Expr::Identifier(name) => {
+                uses.read.insert(*name);
+            }
+            Expr::Call(name, args) => {
+                uses.read.insert(*name);
+                for arg in args {
+                    self.expr(arg, uses);
+                }
+            }
+            Expr::CallExpr(callee, args) => {
+                self.expr(callee, uses);
+                for arg in args {
+                    self.expr(arg, uses);
+                }
+            }
+            Expr::Binary(_, left, right) | Expr::Index(left, right) => {
+                self.expr(left, uses);
+                self.expr(right, uses);
+            }
+            Expr::Unary(_, inner)
+            | Expr::UnitMeasurement(inner, _)
+            | Expr::Okay(inner)
+            | Expr::Oops(inner)
+            | Expr::Unwrap(inner) => self.expr(inner, uses),
+            Expr::Array(items) => {
+                for item in items {
+                    self.expr(item, uses);
+                }
+            }
+            Expr::Lambda(lambda) => match &lambda.body {
+                LambdaBody::Expr(body) => self.expr(body, uses),
+                LambdaBody::Block(body) => self.block(body, uses),
+            },
+        }
+    }
+}
+
+/// The keyword of a statement that nothing after it in its block runs
+fn ends_block(stmt: &Statement) -> Option<&'static str> {
+    match stmt {
+        Statement::Return(_) => Some("give back"),
+        Statement::Complain(_) => Some("complain"),
+        Statement::EmoteAnnotated(annotated) => ends_block(&annotated.statement),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn lint_source(source: &str) -> Vec<Warning> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        lint(&Parser::new(tokens, source).parse().unwrap())
+    }
+
+    #[test]
+    fn test_unused() {
+        let source = r#"
+            to main() {
+                remember used = 1;
+                remember spare = 2;
+                remember f = |x| -> x + used;
+                print(f(1));
+            }
+        "#;
+        let warnings = lint_source(source);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::Unused);
+        assert_eq!(warnings[0].message, "`spare` is remembered in main but never used");
+        assert!(source[warnings[0].span.clone()].starts_with("remember spare"));
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let warnings = lint_source(
+            r#"to main() → Int {
+                when true { complain "no"; print("never"); }
+                give back 1;
+                print("never");
+            }"#,
+        );
+        let lints: Vec<Lint> = warnings.iter().map(|w| w.lint).collect();
+        assert_eq!(lints, [Lint::Unreachable, Lint::Unreachable]);
+    }
+
+    #[test]
+    fn test_allow() {
+        assert!(lint_source("@allow(unused) to main() { remember spare = 1; }").is_empty());
+        assert!(lint_source("to main() { @allow(unused) remember spare = 1; }").is_empty());
+        assert!(lint_source(
+            "to main() → Int { give back 1; @allow(unreachable) print(2); }"
+        )
+        .is_empty());
+
+        let warnings = lint_source("@allow(unreachable) to main() { remember spare = 1; }");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(Lint::from_name("unused"), Some(Lint::Unused));
+        assert_eq!(Lint::from_name("everything"), None);
+    }
+}
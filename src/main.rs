@@ -1,122 +1,1633 @@
-use miette::Result;
+use clap::{Args, Parser as ClapParser, Subcommand, ValueEnum};
+use std::alloc::{GlobalAlloc, Layout, System};
 use std::env;
+use std::ffi::OsString;
 use std::fs;
-use wokelang::{Interpreter, Lexer, Parser, Repl, TypeChecker};
-
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 2 {
-        println!("WokeLang v0.1.0 - A human-centered, consent-driven programming language");
-        println!();
-        println!("Usage: woke <file.woke>           Run a WokeLang program");
-        println!("       woke repl                  Start interactive REPL");
-        println!("       woke --tokenize <file>     Show lexer tokens");
-        println!("       woke --parse <file>        Show parsed AST");
-        println!("       woke --typecheck <file>    Type-check without running");
-        return Ok(());
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use wokelang::codegen::{WasmCompiler, WasmTarget};
+use wokelang::coverage::{self, Coverage, CoverageReport};
+use wokelang::dap::DebugAdapter;
+use wokelang::explain;
+use wokelang::interpreter::Value;
+use wokelang::ffi::Plugin;
+use wokelang::notebook::{self, Notebook};
+use wokelang::profile::Profiler;
+use wokelang::project::{self, BuildCache, GitReference, ModuleLoader, ProjectError, Source};
+use wokelang::lexer::SourceFile;
+use wokelang::lint::{self, Warning};
+use wokelang::security::consent::{self, CONSENT_FILE_ENV};
+use wokelang::security::manifest::{self, MANIFEST_SUFFIX};
+use wokelang::security::policy::POLICY_FILE;
+use wokelang::security::{ConsentError, ConsentMode, ConsentStore, Manifest, Policy};
+use wokelang::bench::{Baseline, BenchRunner};
+use wokelang::stdlib::docs;
+use wokelang::testing::{self, TestKind, TestRunner};
+use wokelang::watch::{self, Watcher};
+use wokelang::pipeline::Stage;
+use wokelang::testing::line_col;
+use wokelang::typechecker::CheckedProgram;
+use wokelang::{CapabilityRegistry, Diagnostic, Diagnostics, Engine, Severity, Interpreter, Lexer, Parser, Pipeline, Program, Repl, TypeChecker};
+
+/// WokeLang - A human-centered, consent-driven programming language
+#[derive(ClapParser)]
+#[command(name = "woke", version, arg_required_else_help = true)]
+#[command(after_help = "\
+Running `woke <file.woke> [args]` is the same as `woke run <file.woke> [args]`.
+A file of - reads the program from standard input.
+
+Environment:
+  WOKE_CONSENT=ask|allow|deny|policy
+                          How to answer requests; `policy` allows only what
+                          the policy file does, without asking
+  WOKE_CONSENT_FILE=<path>
+                          Policy file to use instead of woke.policy.toml
+  WOKE_CONSENT_TIMEOUT=<secs>
+                          Deny prompts not answered in time (default 60, 0
+                          waits forever)
+
+Exit codes:
+  0  success
+  1  bad command line, refused permissions or unwritable output
+  2  the program does not lex, parse, type-check or compile
+  3  the program failed while running, or a test or benchmark failed
+  4  the program file could not be read")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a WokeLang program
+    Run {
+        /// Run again whenever the program, its imports or its policy file
+        /// change
+        #[arg(long)]
+        watch: bool,
+        /// Load a native plugin adding a module to the standard library;
+        /// loading it is asked for as the custom:plugin:LIBRARY superpower
+        #[arg(long = "plugin", value_name = "LIBRARY")]
+        plugins: Vec<PathBuf>,
+        /// Time each function the program calls, and show how often it was
+        /// called, its total and own time, and its allocations
+        #[arg(long)]
+        profile: bool,
+        /// Write the calls timed to FILE as folded stacks, which flamegraph
+        /// tools read
+        #[arg(long, value_name = "FILE")]
+        folded: Option<PathBuf>,
+        /// Program to run, or - to read it from standard input
+        file: PathBuf,
+        /// Arguments for the program
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Create a new project: a manifest, a policy file, main.woke and tests
+    Init {
+        /// Where to create it (default: the current directory)
+        dir: Option<PathBuf>,
+        /// The project's name (default: made from the directory's name)
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Add a dependency to the project, fetch it and update woke.lock
+    ///
+    /// The package is then imported by name: `use colors;` loads its
+    /// lib.woke, `use colors.palette;` its palette.woke.
+    Add {
+        /// The package's name, as its woke.toml gives it
+        name: String,
+        #[command(flatten)]
+        source: DependencySource,
+    },
+    /// Start the interactive REPL
+    Repl,
+    /// Run the ```woke blocks of a Markdown notebook in one session
+    ///
+    /// The notebook is written out again with each block's output after
+    /// it: intro.woke.md to intro.md unless --output says otherwise.
+    /// --allow-* flags allow superpowers without asking.
+    Notebook {
+        /// The notebook to run, or - to read it from standard input
+        file: PathBuf,
+        /// Where to write the result, or - for standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Serve the Debug Adapter Protocol on stdin and stdout
+    ///
+    /// Editors such as VS Code start this to debug a program; the program
+    /// is named in the client's launch request. --allow-* flags allow
+    /// superpowers without asking.
+    Debug,
+    /// Parse and type-check programs without running them
+    ///
+    /// Given a directory or a project's woke.toml, every .woke file under
+    /// it is checked, and all the errors found are reported.
+    Check {
+        /// A program, or a directory or manifest to check the programs of
+        #[arg(default_value = ".")]
+        path: PathBuf,
+        /// Check again whenever the programs or their imports change
+        #[arg(long)]
+        watch: bool,
+        /// Check even the programs that passed and have not changed since
+        #[arg(long)]
+        no_cache: bool,
+    },
+    /// Run the `expect` blocks and `test_*` functions of programs
+    ///
+    /// Each test runs on its own on the interpreter, with every superpower
+    /// refused unless an --allow-* flag allows it, and inside --sandbox if
+    /// given.
+    Test {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Only run tests whose name contains this
+        #[arg(long, value_name = "TEXT")]
+        filter: Option<String>,
+        /// Show which lines of each file the tests ran
+        #[arg(long)]
+        coverage: bool,
+        /// Write line coverage to this file in the LCOV format
+        #[arg(long, value_name = "FILE")]
+        lcov: Option<PathBuf>,
+    },
+    /// Time the `bench_*` functions of programs on the interpreter and VM
+    ///
+    /// Each benchmark is warmed up and then timed over many runs, with every
+    /// superpower refused unless an --allow-* flag allows it. --engine
+    /// times it on one engine only.
+    Bench {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Only run benchmarks whose name contains this
+        #[arg(long, value_name = "TEXT")]
+        filter: Option<String>,
+        /// How many timed runs of each benchmark
+        #[arg(long, value_name = "N", default_value_t = 100)]
+        iterations: usize,
+        /// Compare the medians with a saved baseline
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<PathBuf>,
+        /// Save the medians as a baseline
+        #[arg(long, value_name = "FILE")]
+        save_baseline: Option<PathBuf>,
+    },
+    /// Show the lexer's tokens
+    Tokenize {
+        file: PathBuf,
+        /// Show only the first N tokens, lexing no further
+        #[arg(long, value_name = "N")]
+        head: Option<usize>,
+    },
+    /// Show the parsed AST
+    Parse {
+        file: PathBuf,
+        /// How to show it; `json` is for other tools to read
+        #[arg(long, value_enum, default_value = "debug")]
+        format: AstFormat,
+    },
+    /// Compile a program to WebAssembly
+    Compile {
+        file: PathBuf,
+        /// Environment the module runs in
+        #[arg(long, value_name = "host|wasi", default_value = "host", value_parser = WasmTarget::from_str)]
+        target: WasmTarget,
+        /// Also write a source map next to the module
+        #[arg(long)]
+        source_map: bool,
+        /// Where to write the module (default: <file>.wasm)
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Explain an error code, e.g. `woke explain WOKE-E0303`
+    ///
+    /// Without a code, list every code.
+    Explain { code: Option<String> },
+    /// Describe a builtin or standard library function, e.g.
+    /// `woke doc std.math.sqrt`, or the functions of a program, e.g.
+    /// `woke doc main.woke`, with the types checking found for them
+    ///
+    /// Without a name, print the reference for every function as Markdown.
+    Doc { name: Option<String> },
+    /// Show the credits of a program: who its `thanks to` blocks thank,
+    /// and what for
+    Thanks { file: PathBuf },
+    /// Show or forget consent decisions remembered between runs
+    Consents {
+        #[command(subcommand)]
+        command: Option<ConsentsCommand>,
+    },
+    /// Sign and check manifests of the superpowers a program needs
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommand,
+    },
+    /// `woke <file.woke> [args]`, the same as `woke run`
+    #[command(external_subcommand)]
+    File(Vec<OsString>),
+}
+
+#[derive(Subcommand)]
+enum ConsentsCommand {
+    /// Show remembered consent decisions
+    List,
+    /// Forget the decisions for a capability, e.g. `file:read:data`
+    Revoke { capability: String },
+    /// Forget every consent decision
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum ManifestCommand {
+    /// Make a key for signing manifests
+    Keygen { key_file: PathBuf },
+    /// Sign the manifest that goes with a program
+    Sign { file: PathBuf, key_file: PathBuf },
+    /// Check a program's manifest and show what it allows
+    Show { file: PathBuf },
+}
+
+/// Where `woke add` gets a package from: a git repository, at one branch,
+/// tag or commit, or a directory
+#[derive(Args)]
+struct DependencySource {
+    /// Clone the package from this git repository
+    #[arg(long, value_name = "URL", required_unless_present = "path", conflicts_with = "path")]
+    git: Option<String>,
+    /// Follow this branch of the repository
+    #[arg(long, requires = "git", group = "reference")]
+    branch: Option<String>,
+    /// Use this tag of the repository
+    #[arg(long, requires = "git", group = "reference")]
+    tag: Option<String>,
+    /// Use this commit of the repository
+    #[arg(long, requires = "git", group = "reference")]
+    rev: Option<String>,
+    /// Use the package in this directory
+    #[arg(long, value_name = "DIR")]
+    path: Option<PathBuf>,
+}
+
+/// Options every command accepts; the permissions only matter when a
+/// program runs
+#[derive(Args)]
+struct Options {
+    /// Trace execution and show debug log records
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Fail on warnings as if they were errors
+    #[arg(long, global = true)]
+    deny_warnings: bool,
+
+    /// Skip the program's side quests, as `#side quests off` does
+    #[arg(long, global = true)]
+    skip_side_quests: bool,
+
+    /// What runs the program [default: interpreter]
+    #[arg(long, global = true, value_enum)]
+    engine: Option<Engine>,
+
+    /// Allow reading files, or just these
+    #[arg(long, global = true, value_name = "PATH,...", num_args = 0..=1, require_equals = true, default_missing_value = "*")]
+    allow_read: Vec<String>,
+
+    /// Allow writing files, or just these
+    #[arg(long, global = true, value_name = "PATH,...", num_args = 0..=1, require_equals = true, default_missing_value = "*")]
+    allow_write: Vec<String>,
+
+    /// Allow network access, or just to these hosts
+    #[arg(long, global = true, value_name = "HOST,...", num_args = 0..=1, require_equals = true, default_missing_value = "*")]
+    allow_net: Vec<String>,
+
+    /// Allow environment variables, or just these
+    #[arg(long, global = true, value_name = "NAME,...", num_args = 0..=1, require_equals = true, default_missing_value = "*")]
+    allow_env: Vec<String>,
+
+    /// Allow running programs, or just these
+    #[arg(long, global = true, value_name = "PROGRAM,...", num_args = 0..=1, require_equals = true, default_missing_value = "*")]
+    allow_run: Vec<String>,
+
+    /// Refuse everything not allowed without asking
+    #[arg(long, global = true)]
+    deny_all: bool,
+
+    /// No network, programs or environment, and files only inside DIR
+    /// (default: the current directory)
+    #[arg(long, global = true, value_name = "DIR", num_args = 0..=1, require_equals = true, default_missing_value = ".")]
+    sandbox: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum AstFormat {
+    /// Rust's debug output
+    Debug,
+    /// JSON, with every node's source span as byte offsets
+    Json,
+}
+
+impl Options {
+    /// What the `--allow-*` flags allow
+    fn allowed(&self) -> Policy {
+        let mut policy = Policy::default();
+        for (name, values) in [
+            ("read", &self.allow_read),
+            ("write", &self.allow_write),
+            ("net", &self.allow_net),
+            ("env", &self.allow_env),
+            ("run", &self.allow_run),
+        ] {
+            for value in values {
+                policy
+                    .add_flag(&format!("--allow-{}={}", name, value))
+                    .expect("every --allow-* flag clap accepts is known");
+            }
+        }
+        policy
     }
+}
 
-    // Check for REPL mode first
-    if args.get(1).map(|s| s.as_str()) == Some("repl") {
-        let mut repl = Repl::new().expect("Failed to create REPL");
-        repl.run().expect("REPL error");
-        return Ok(());
+/// Why `woke` stopped early, and the exit code it stops with
+#[derive(Debug, Clone, Copy)]
+enum Failure {
+    /// A bad command line, permissions that were not granted, or output
+    /// that could not be written
+    General,
+    /// The program does not lex, parse, type-check or compile
+    Source,
+    /// The program failed while running
+    Runtime,
+    /// The program file could not be read
+    NotFound,
+    /// The program ended with a status of its own: its `main` gave back
+    /// an Int other than 0 or an Oops, or it called `exit`
+    Exit(u8),
+}
+
+impl Failure {
+    fn code(self) -> u8 {
+        match self {
+            Failure::General => 1,
+            Failure::Source => 2,
+            Failure::Runtime => 3,
+            Failure::NotFound => 4,
+            Failure::Exit(code) => code,
+        }
     }
+}
 
-    let (mode, file_path) = match args.get(1).map(|s| s.as_str()) {
-        Some("--tokenize") => ("tokenize", args.get(2)),
-        Some("--parse") => ("parse", args.get(2)),
-        Some("--typecheck") => ("typecheck", args.get(2)),
-        Some(_) => ("run", Some(&args[1])),
-        None => {
-            eprintln!("Expected file path");
-            return Ok(());
+type Outcome = Result<(), Failure>;
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse_from(legacy_args(env::args_os())) {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() {
+                ExitCode::from(Failure::General.code())
+            } else {
+                ExitCode::SUCCESS
+            };
         }
     };
 
-    let file_path = match file_path {
-        Some(p) => p,
+    let options = &cli.options;
+    let outcome = match cli.command {
+        Command::Run { file, watch: false, plugins, profile, folded, args } => {
+            run(options, &file, args, &plugins, profile, folded.as_deref(), &mut None)
+        }
+        Command::Run { file, watch: true, plugins, profile, folded, args } => {
+            // Consent decisions made in one run still hold in the next
+            let mut consents = None;
+            watch(&file, || {
+                run(options, &file, args.clone(), &plugins, profile, folded.as_deref(), &mut consents)
+            })
+        }
+        Command::File(mut args) => {
+            let file = PathBuf::from(args.remove(0));
+            // As with `woke run`, a `--` right after the file only separates
+            if args.first().is_some_and(|arg| arg == "--") {
+                args.remove(0);
+            }
+            let args = args.into_iter().map(|a| a.to_string_lossy().into_owned()).collect();
+            run(options, &file, args, &[], false, None, &mut None)
+        }
+        Command::Init { dir, name } => init(dir.as_deref(), name),
+        Command::Add { name, source } => add(&name, source),
+        Command::Repl => repl(),
+        Command::Notebook { file, output } => notebook(options, &file, output.as_deref()),
+        Command::Debug => debug(options),
+        Command::Check { path, watch: false, no_cache } => check(options, &path, !no_cache),
+        Command::Check { path, watch: true, no_cache } => watch(&path, || check(options, &path, !no_cache)),
+        Command::Test { files, filter, coverage, lcov } => {
+            test(options, &files, filter.as_deref(), coverage, lcov.as_deref())
+        }
+        Command::Bench { files, filter, iterations, baseline, save_baseline } => {
+            bench(options, &files, filter.as_deref(), iterations, baseline, save_baseline)
+        }
+        Command::Tokenize { file, head } => tokenize(&file, head),
+        Command::Parse { file, format } => parse(&file, format),
+        Command::Compile { file, target, source_map, output } => compile(&file, target, source_map, output),
+        Command::Explain { code } => explain_code(code.as_deref()),
+        Command::Doc { name } => function_doc(name.as_deref()),
+        Command::Thanks { file } => thanks(&file),
+        Command::Consents { command } => consents_command(command.unwrap_or(ConsentsCommand::List)),
+        Command::Manifest { command } => manifest_command(command),
+    };
+    match outcome {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => ExitCode::from(failure.code()),
+    }
+}
+
+/// Accept the flags older versions used in place of subcommands, such
+/// as `woke --typecheck file.woke`
+fn legacy_args(args: impl Iterator<Item = OsString>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = args.collect();
+    if let Some(first) = args.get_mut(1) {
+        let command = match first.to_str() {
+            Some("--tokenize") => "tokenize",
+            Some("--parse") => "parse",
+            Some("--typecheck") => "check",
+            Some("--wasm") => "compile",
+            _ => return args,
+        };
+        *first = command.into();
+    }
+    args
+}
+
+/// Whether a file argument means standard input, as `-` does
+fn is_stdin(file: &Path) -> bool {
+    file == Path::new("-")
+}
+
+fn read_source(file: &Path) -> Result<String, Failure> {
+    let read = if is_stdin(file) {
+        let mut source = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut source).map(|_| source)
+    } else {
+        fs::read_to_string(file)
+    };
+    read.map_err(|e| {
+        eprintln!("Could not read {}: {}", file.display(), e);
+        Failure::NotFound
+    })
+}
+
+/// Source read from `file`, named after it in diagnostics
+fn source_file(file: &Path, source: &str) -> SourceFile {
+    if is_stdin(file) {
+        SourceFile::named("<stdin>", source)
+    } else {
+        SourceFile::named(file.display().to_string(), source)
+    }
+}
+
+fn parse_source(source: &SourceFile) -> Result<Program, Failure> {
+    let (program, errors) = Parser::from_lexer(Lexer::for_file(source)).parse_all();
+    if errors.is_empty() {
+        return Ok(program);
+    }
+    for error in errors {
+        eprintln!("{:?}", miette::Report::new(error));
+    }
+    Err(Failure::Source)
+}
+
+/// Parse a program and merge in the modules it imports
+fn load_program(file: &Path, source: &str) -> Result<Program, Failure> {
+    load_modules(file, source, None).map(|(program, _, _)| program)
+}
+
+/// Parse a program and merge in the modules it imports, returning the
+/// loader that knows where they came from and the program's own lint
+/// warnings. With a cache, files parsed before are not parsed again.
+fn load_modules(
+    file: &Path,
+    source: &str,
+    cache: Option<&BuildCache>,
+) -> Result<(Program, ModuleLoader, Vec<Warning>), Failure> {
+    let program = match cache.and_then(|cache| cache.ast(source)) {
+        Some(program) => program,
         None => {
-            eprintln!("Expected file path after flag");
-            return Ok(());
+            let program = parse_source(&source_file(file, source))?;
+            if let Some(cache) = cache {
+                let _ = cache.store_ast(source, &program);
+            }
+            program
         }
     };
+    let warnings = lint::lint(&program);
+    let mut loader = ModuleLoader::for_program(file).map_err(|e| {
+        eprintln!("Could not load modules: {}", e);
+        Failure::Source
+    })?;
+    if let Some(cache) = cache {
+        loader = loader.with_cache(cache.clone());
+    }
+    let program = loader.load(file, program).map_err(|e| {
+        match e {
+            ProjectError::Syntax { error, .. } => eprintln!("{:?}", miette::Report::new_boxed(error)),
+            e => eprintln!("Could not load modules: {}", e),
+        }
+        Failure::Source
+    })?;
+    Ok((program, loader, warnings))
+}
 
-    let source = fs::read_to_string(file_path).expect("Failed to read file");
-    let lexer = Lexer::new(&source);
+fn type_check(program: &Program) -> Result<CheckedProgram<'_>, Failure> {
+    TypeChecker::new().check(program).map_err(|errors| {
+        for e in &errors {
+            report("Type error", e.code(), e, e.help());
+        }
+        Failure::Source
+    })
+}
 
-    let tokens = match lexer.tokenize() {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("{:?}", miette::Report::new(e));
-            return Ok(());
+/// Print what stopped a pipeline, each with where to read more about it
+fn report_diagnostics(diagnostics: Diagnostics) {
+    for diagnostic in diagnostics {
+        match diagnostic.code {
+            Some(code) => report(&diagnostic.kind(), code, &diagnostic.message, diagnostic.help),
+            None => eprintln!("{}", diagnostic),
+        }
+    }
+}
+
+/// Print a program's lint warnings with where they are, as errors with
+/// `--deny-warnings`, failing then if there are any
+fn warn(options: &Options, file: &Path, source: &str, warnings: &[Warning]) -> Outcome {
+    let name = source_file(file, source).name().unwrap_or_default().to_string();
+    for warning in warnings {
+        let mut diagnostic = Diagnostic::from(warning);
+        if options.deny_warnings {
+            diagnostic.severity = Severity::Error;
+        }
+        let (line, column) = line_col(source, warning.span.start);
+        eprintln!("{}", diagnostic);
+        eprintln!("  --> {}:{}:{}", name, line, column);
+        eprintln!("  help: {}", explain::hint(warning.lint.code()));
+    }
+    match options.deny_warnings && !warnings.is_empty() {
+        true => Err(Failure::Source),
+        false => Ok(()),
+    }
+}
+
+/// Print an error with its code, and where to read more about it
+fn report(kind: &str, code: &str, error: &dyn std::fmt::Display, help: Option<String>) {
+    eprintln!("{} [{}]: {}", kind, code, error);
+    if let Some(help) = help {
+        eprintln!("  help: {}", help);
+    }
+    eprintln!("  help: {}", explain::hint(code));
+}
+
+fn explain_code(code: Option<&str>) -> Outcome {
+    let Some(code) = code else {
+        for entry in explain::catalog() {
+            println!("{}  {}", entry.code, entry.title);
         }
+        return Ok(());
+    };
+    let entry = explain::lookup(code).ok_or_else(|| {
+        eprintln!("Unknown error code {}; `woke explain` lists them all", code);
+        Failure::General
+    })?;
+    println!("{}: {}\n\n{}", entry.code, entry.title, entry.text);
+    Ok(())
+}
+
+fn function_doc(name: Option<&str>) -> Outcome {
+    let Some(name) = name else {
+        print!("{}", docs::reference());
+        return Ok(());
     };
+    if name.ends_with(".woke") {
+        return program_doc(Path::new(name));
+    }
+    let doc = docs::lookup(name).ok_or_else(|| {
+        eprintln!("No function named {}; `woke doc` lists them all", name);
+        Failure::General
+    })?;
+    println!("{}", doc);
+    Ok(())
+}
 
-    match mode {
-        "tokenize" => {
-            for token in &tokens {
-                println!("{:?} @ {:?}", token.value, token.span);
-            }
-            println!("\nTokenized {} tokens successfully.", tokens.len());
-        }
-        "parse" => {
-            let mut parser = Parser::new(tokens, &source);
-            match parser.parse() {
-                Ok(program) => {
-                    println!("{:#?}", program);
-                    println!("\nParsed {} top-level items successfully.", program.items.len());
-                }
-                Err(e) => {
-                    eprintln!("{:?}", miette::Report::new(e));
+/// The signature of each function a program defines, as checked, then
+/// its credits
+fn program_doc(file: &Path) -> Outcome {
+    let source = read_source(file)?;
+    let program = load_program(file, &source)?;
+    let checked = type_check(&program)?;
+    for function in checked.functions() {
+        println!("{}", function);
+    }
+    if let Some(credits) = credits(&program) {
+        print!("\n{}", credits);
+    }
+    Ok(())
+}
+
+fn thanks(file: &Path) -> Outcome {
+    let source = read_source(file)?;
+    let program = load_program(file, &source)?;
+    match credits(&program) {
+        Some(credits) => print!("{}", credits),
+        None => println!("{} has no `thanks to` block", file.display()),
+    }
+    Ok(())
+}
+
+/// Who a program thanks and what for, one entry a line with the reasons
+/// lined up, or `None` if it thanks no one
+fn credits(program: &Program) -> Option<String> {
+    let entries: Vec<_> = program.gratitude().collect();
+    let width = entries.iter().map(|entry| entry.recipient.chars().count()).max()?;
+    let mut out = String::from("Thanks to:\n");
+    for entry in entries {
+        out.push_str(&format!("  {:width$}  {}\n", entry.recipient, entry.reason, width = width));
+    }
+    Some(out)
+}
+
+fn init(dir: Option<&Path>, name: Option<String>) -> Outcome {
+    let dir = dir.unwrap_or(Path::new("."));
+    let name = name.unwrap_or_else(|| project::name_for(dir));
+    let files = project::init(dir, &name).map_err(|e| {
+        eprintln!("Could not create the project: {}", e);
+        Failure::General
+    })?;
+    println!("Created project '{}':", name);
+    for file in &files {
+        println!("  {}", file.strip_prefix(".").unwrap_or(file).display());
+    }
+    let cd = if dir == Path::new(".") { String::new() } else { format!("cd {} && ", dir.display()) };
+    println!("\nRun it with `{}woke run {}`", cd, project::MAIN_FILE);
+    println!("and its tests with `{}woke test {}/*.woke`", cd, project::TESTS_DIR);
+    Ok(())
+}
+
+fn add(name: &str, from: DependencySource) -> Outcome {
+    let Some(root) = project::find_root(Path::new(".")) else {
+        let cwd = env::current_dir().unwrap_or_default();
+        eprintln!("{}", ProjectError::NoProject(cwd.display().to_string()));
+        return Err(Failure::General);
+    };
+    let source = match (from.git, from.path) {
+        (Some(url), _) => {
+            let reference = match (from.branch, from.tag, from.rev) {
+                (Some(branch), _, _) => GitReference::Branch(branch),
+                (_, Some(tag), _) => GitReference::Tag(tag),
+                (_, _, Some(rev)) => GitReference::Rev(rev),
+                _ => GitReference::DefaultBranch,
+            };
+            Source::Git { url, reference }
+        }
+        // Paths in the manifest are relative to the project, not to here
+        (None, Some(path)) => match fs::canonicalize(&path) {
+            Ok(dir) => Source::Path(project::relative_path(&dir, &root)),
+            Err(e) => {
+                eprintln!("Could not find {}: {}", path.display(), e);
+                return Err(Failure::General);
+            }
+        },
+        (None, None) => unreachable!("clap requires --git or --path"),
+    };
+
+    let resolution = project::add(&root, name, source).map_err(|e| {
+        eprintln!("Could not add {}: {}", name, e);
+        Failure::General
+    })?;
+    if let Some(package) = resolution.lockfile.get(name) {
+        let commit = package.commit.as_deref().map(|c| format!(" at {}", &c[..c.len().min(12)]));
+        println!("Added {} {}{}", package.name, package.version, commit.unwrap_or_default());
+    }
+    println!(
+        "Locked {} package(s) in {}",
+        resolution.lockfile.packages.len(),
+        root.join(project::LOCK_FILE).display()
+    );
+    Ok(())
+}
+
+fn repl() -> Outcome {
+    let mut repl = Repl::new().map_err(|e| {
+        eprintln!("Could not start the REPL: {}", e);
+        Failure::General
+    })?;
+    repl.run().map_err(|e| {
+        eprintln!("REPL error: {}", e);
+        Failure::General
+    })
+}
+
+fn debug(options: &Options) -> Outcome {
+    let stdin = std::io::BufReader::new(std::io::stdin());
+    DebugAdapter::new()
+        .with_policy(&options.allowed())
+        .serve(stdin, std::io::stdout())
+        .map_err(|e| {
+            eprintln!("Debug session failed: {}", e);
+            Failure::General
+        })
+}
+
+/// Print a program's tokens as they are lexed, stopping after `head` of
+/// them if given
+fn tokenize(file: &Path, head: Option<usize>) -> Outcome {
+    let source = source_file(file, &read_source(file)?);
+    let mut count = 0;
+    for token in Lexer::for_file(&source).tokens().take(head.unwrap_or(usize::MAX)) {
+        let token = token.map_err(|e| {
+            eprintln!("{:?}", miette::Report::new(e));
+            Failure::Source
+        })?;
+        println!("{:?} @ {:?}", token.value, token.span);
+        count += 1;
+    }
+    match head {
+        Some(head) if count == head => println!("\nShowed the first {} tokens.", count),
+        _ => println!("\nTokenized {} tokens successfully.", count),
+    }
+    Ok(())
+}
+
+fn parse(file: &Path, format: AstFormat) -> Outcome {
+    let program = parse_source(&source_file(file, &read_source(file)?))?;
+    match format {
+        AstFormat::Debug => {
+            println!("{:#?}", program);
+            println!("\nParsed {} top-level items successfully.", program.items.len());
+        }
+        AstFormat::Json => match serde_json::to_string_pretty(&program) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Could not write the AST as JSON: {}", e);
+                return Err(Failure::General);
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Go again whenever the program or a file it depends on changes, until
+/// interrupted
+fn watch(path: &Path, mut go: impl FnMut() -> Outcome) -> Outcome {
+    if is_stdin(path) {
+        eprintln!("Standard input cannot be watched; name the program's file");
+        return Err(Failure::General);
+    }
+    let mut changed: Vec<PathBuf> = Vec::new();
+    loop {
+        let programs = project::program_files(path).unwrap_or_default();
+        let mut files = Vec::new();
+        if programs != [path] {
+            // New programs show up as changes to the directories
+            files.push(path.to_path_buf());
+            files.extend(programs.iter().filter_map(|p| p.parent().map(Path::to_path_buf)));
+        }
+        for file in &programs {
+            // Modules are found from the program as it is now, as far as it loads
+            let pipeline = Pipeline::new();
+            let program = fs::read_to_string(file).ok().and_then(|source| pipeline.parse(&source).ok());
+            let mut loader = ModuleLoader::for_program(file).unwrap_or_default();
+            if let Some(program) = program {
+                let _ = pipeline.load_with(&mut loader, file, program);
+            }
+            files.extend(watch::watched_files(file, loader.files()));
+        }
+        let mut watcher = Watcher::new(files);
+
+        watch::clear_screen();
+        if !changed.is_empty() {
+            let names: Vec<String> = changed.iter().map(|f| f.display().to_string()).collect();
+            println!("[watch] Changed: {}\n", names.join(", "));
+        }
+        let status = match go() {
+            Ok(()) => "Finished".to_string(),
+            Err(failure) => format!("Failed with exit code {}", failure.code()),
+        };
+        println!(
+            "\n[watch] {}. Watching {} file(s) for changes; press Ctrl-C to stop.",
+            status,
+            watcher.files().filter(|f| f.exists()).count()
+        );
+        changed = watcher.wait(watch::POLL_INTERVAL);
+    }
+}
+
+fn check(options: &Options, path: &Path, use_cache: bool) -> Outcome {
+    let files = project::program_files(path).map_err(|e| {
+        eprintln!("{}", e);
+        Failure::NotFound
+    })?;
+    if files.is_empty() {
+        eprintln!("No .woke files in {}", path.display());
+        return Err(Failure::NotFound);
+    }
+
+    let (mut failed, mut unchanged) = (Vec::new(), 0);
+    for file in &files {
+        let cache = BuildCache::for_project(file).filter(|_| use_cache && !is_stdin(file));
+        match check_file(options, file, cache.as_ref()) {
+            Ok(skipped) => unchanged += skipped as usize,
+            Err(failure) => {
+                if files.len() > 1 {
+                    eprintln!();
                 }
+                failed.push(failure);
+            }
+        }
+    }
+
+    match failed.first() {
+        None if files.len() == 1 => println!("Type check passed!"),
+        None => println!("Type check passed! ({} files, {} unchanged)", files.len(), unchanged),
+        Some(&failure) => {
+            if files.len() > 1 {
+                eprintln!("Type check failed in {} of {} files", failed.len(), files.len());
+            }
+            return Err(failure);
+        }
+    }
+    Ok(())
+}
+
+/// Type-check and lint one program, reporting all its errors and
+/// warnings; whether it was skipped, having passed without warnings before
+/// and not changed since
+fn check_file(options: &Options, file: &Path, cache: Option<&BuildCache>) -> Result<bool, Failure> {
+    let source = read_source(file)?;
+    let (program, loader, warnings) = load_modules(file, &source, cache)?;
+    let key = loader.key(file, &source);
+    if cache.is_some_and(|cache| cache.passed(&key)) {
+        return Ok(true);
+    }
+
+    let errors = TypeChecker::new().check_program_all(&program);
+    let name = source_file(file, &source).name().unwrap_or_default().to_string();
+    for error in &errors {
+        eprintln!("Type error [{}]: {}", error.code(), error);
+        match error.span() {
+            Some(span) => {
+                let (line, column) = line_col(&source, span.start);
+                eprintln!("  --> {}:{}:{}", name, line, column);
+            }
+            None => eprintln!("  --> {}", name),
+        }
+        if let Some(help) = error.help() {
+            eprintln!("  help: {}", help);
+        }
+        eprintln!("  help: {}", explain::hint(error.code()));
+    }
+    if !errors.is_empty() {
+        return Err(Failure::Source);
+    }
+    warn(options, file, &source, &warnings)?;
+    if let Some(cache) = cache.filter(|_| warnings.is_empty()) {
+        // A check that cannot be remembered still passed
+        let _ = cache.record_pass(&key);
+    }
+    Ok(false)
+}
+
+fn compile(file: &Path, target: WasmTarget, source_map: bool, output: Option<PathBuf>) -> Outcome {
+    let source = read_source(file)?;
+    let program = load_program(file, &source)?;
+    let checked = type_check(&program)?;
+    let out_path = output.unwrap_or_else(|| match is_stdin(file) {
+        true => PathBuf::from("main.wasm"),
+        false => file.with_extension("wasm"),
+    });
+    let map_path = out_path.with_extension("wasm.map");
+
+    let mut compiler = WasmCompiler::new().with_target(target);
+    if source_map {
+        let file_name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        compiler = compiler.with_source_map(&file_name(file), &source, &file_name(&map_path));
+    }
+    let wasm = compiler.compile_checked(&checked).map_err(|e| {
+        eprintln!("WASM compile error: {}", e);
+        Failure::Source
+    })?;
+
+    let write = |path: &Path, bytes: &[u8]| {
+        fs::write(path, bytes).map_err(|e| {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+            Failure::General
+        })
+    };
+    write(&out_path, &wasm)?;
+    println!("Wrote {} ({} bytes)", out_path.display(), wasm.len());
+    if let Some(map) = compiler.source_map() {
+        write(&map_path, map.as_bytes())?;
+        println!("Wrote {}", map_path.display());
+    }
+    Ok(())
+}
+
+/// The module a program file is, named after the file
+fn module_name(file: &Path) -> String {
+    if is_stdin(file) {
+        return "main".to_string();
+    }
+    file.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "main".to_string())
+}
+
+/// Run the tests in `files` and report how they went, failing if any
+/// test did
+fn test(options: &Options, files: &[PathBuf], filter: Option<&str>, coverage: bool, lcov: Option<&Path>) -> Outcome {
+    if options.engine == Some(Engine::Vm) {
+        eprintln!("Tests run on the interpreter; --engine vm cannot run them. Not testing.");
+        return Err(Failure::General);
+    }
+    if let Some(root) = &options.sandbox {
+        if !root.is_dir() {
+            eprintln!("Sandbox directory {} does not exist", root.display());
+            return Err(Failure::General);
+        }
+    }
+    let started = std::time::Instant::now();
+    let (mut passed, mut filtered) = (0, 0);
+    let mut failures = Vec::new();
+    let mut report = (coverage || lcov.is_some()).then(CoverageReport::default);
+    for file in files {
+        let source = read_source(file)?;
+        let (program, loader, _) = load_modules(file, &source, None)?;
+        type_check(&program)?;
+
+        let recorder = Coverage::new();
+        let mut runner = TestRunner::new()
+            .with_module_name(&module_name(file))
+            .with_policy(&options.allowed());
+        if let Some(filter) = filter {
+            runner = runner.with_filter(filter);
+        }
+        if let Some(root) = &options.sandbox {
+            runner = runner.with_sandbox(root);
+        }
+        if report.is_some() {
+            runner = runner.with_coverage(&recorder);
+        }
+        let selected = runner.selected(&program);
+        filtered += testing::discover(&program).len() - selected.len();
+
+        println!("\nrunning {} test(s) from {}", selected.len(), file.display());
+        for test in &selected {
+            let result = runner.run_test(&program, test);
+            let name = match test.kind {
+                TestKind::Expect => format!("\"{}\"", test.name),
+                TestKind::Function => test.name.clone(),
+            };
+            let status = if result.passed() { "ok" } else { "FAILED" };
+            println!("test {} ... {} ({:.2?})", name, status, result.duration);
+            if result.passed() {
+                passed += 1;
+            } else {
+                let (line, column) = testing::line_col(&source, test.span.start);
+                failures.push((format!("{} at {}:{}:{}", name, file.display(), line, column), result));
             }
         }
-        "typecheck" => {
-            let mut parser = Parser::new(tokens, &source);
-            match parser.parse() {
-                Ok(program) => {
-                    let mut typechecker = TypeChecker::new();
-                    match typechecker.check_program(&program) {
-                        Ok(()) => {
-                            println!("Type check passed!");
-                        }
-                        Err(e) => {
-                            eprintln!("Type error: {}", e);
-                        }
-                    }
+        if let Some(report) = &mut report {
+            report.merge(recorder.report(&program, file, |f| loader.origin(f)));
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (place, result) in &failures {
+            println!("\n---- {} ----", place);
+            if let Some(error) = &result.error {
+                println!("[{}] {}", error.code(), error);
+            }
+            if !result.output.is_empty() {
+                println!("output:");
+                for line in result.output.lines() {
+                    println!("  {}", line);
                 }
-                Err(e) => {
-                    eprintln!("{:?}", miette::Report::new(e));
+            }
+        }
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} filtered out; finished in {:.2?}",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        passed,
+        failures.len(),
+        filtered,
+        started.elapsed()
+    );
+    if let Some(report) = &report {
+        if coverage {
+            print_coverage(report);
+        }
+        if let Some(path) = lcov {
+            fs::write(path, report.to_lcov()).map_err(|e| {
+                eprintln!("Failed to write {}: {}", path.display(), e);
+                Failure::General
+            })?;
+            println!("Wrote {}", path.display());
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(Failure::Runtime)
+    }
+}
+
+/// Time the benchmarks in `files`, comparing them with a saved baseline
+/// or saving one, failing if any benchmark did
+/// Show the lines covered in each file, and which were missed
+fn print_coverage(report: &CoverageReport) {
+    let cwd = env::current_dir().unwrap_or_default();
+    let names: Vec<String> = report
+        .files
+        .keys()
+        .map(|path| project::relative_path(path, &cwd).display().to_string())
+        .collect();
+    let width = names.iter().map(String::len).max().unwrap_or(0).max("total".len());
+    let lines = |hit: usize, found: usize| {
+        format!("{}/{} lines ({:.1}%)", hit, found, coverage::percent(hit, found))
+    };
+
+    println!("
+coverage:");
+    for (name, file) in names.iter().zip(report.files.values()) {
+        let missed: Vec<String> = file.missed().iter().map(usize::to_string).collect();
+        let missed = match missed.is_empty() {
+            true => String::new(),
+            false => format!(", missed {}", missed.join(", ")),
+        };
+        println!("  {:width$}  {}{}", name, lines(file.hit(), file.found()), missed, width = width);
+    }
+    let (found, hit) = report.totals();
+    println!("  {:width$}  {}", "total", lines(hit, found), width = width);
+}
+
+fn bench(
+    options: &Options,
+    files: &[PathBuf],
+    filter: Option<&str>,
+    iterations: usize,
+    baseline: Option<PathBuf>,
+    save_baseline: Option<PathBuf>,
+) -> Outcome {
+    let baseline = match &baseline {
+        Some(path) => Some(Baseline::parse(&read_source(path)?).map_err(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            Failure::General
+        })?),
+        None => None,
+    };
+    let engines = match options.engine {
+        Some(engine) => vec![engine],
+        None => vec![Engine::Interpreter, Engine::Vm],
+    };
+
+    let mut results = Vec::new();
+    for file in files {
+        let program = load_program(file, &read_source(file)?)?;
+        type_check(&program)?;
+        let mut runner = BenchRunner::new()
+            .with_iterations(iterations)
+            .with_engines(&engines)
+            .with_policy(&options.allowed());
+        if let Some(filter) = filter {
+            runner = runner.with_filter(filter);
+        }
+
+        println!("\nrunning {} benchmark(s) from {}", runner.selected(&program).len(), file.display());
+        for result in runner.run(&program) {
+            match &result.stats {
+                Ok(stats) => {
+                    let change = match baseline.as_ref().and_then(|b| b.change(&result.name, result.engine, stats)) {
+                        Some(change) => format!("  {:+.1}% vs baseline", change),
+                        None => String::new(),
+                    };
+                    println!(
+                        "{:<24} {:<12} mean {:>10.2?}  median {:>10.2?}  ± {:>10.2?}  ({} runs){}",
+                        result.name,
+                        result.engine.name(),
+                        stats.mean,
+                        stats.median,
+                        stats.stddev,
+                        stats.runs,
+                        change
+                    );
                 }
+                Err(e) => println!("{:<24} {:<12} FAILED: {}", result.name, result.engine.name(), e),
+            }
+            results.push(result);
+        }
+    }
+
+    if let Some(path) = &save_baseline {
+        fs::write(path, Baseline::from_results(&results).to_json()).map_err(|e| {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+            Failure::General
+        })?;
+        println!("\nSaved the baseline to {}", path.display());
+    }
+    if results.iter().all(|r| r.stats.is_ok()) {
+        Ok(())
+    } else {
+        Err(Failure::Runtime)
+    }
+}
+
+/// Type-check and run a program, with `args` as its arguments
+/// Run a program. With `consents`, decisions are remembered in the store
+/// it holds, or in the default one it is given back holding. With
+/// `profile` or `folded`, its calls are timed and reported when it ends.
+fn run(
+    options: &Options,
+    file: &Path,
+    args: Vec<String>,
+    plugins: &[PathBuf],
+    profile: bool,
+    folded: Option<&Path>,
+    consents: &mut Option<ConsentStore>,
+) -> Outcome {
+    let source = read_source(file)?;
+    // In a project, what the last run worked out is reused
+    let cache = BuildCache::for_project(file).filter(|_| !is_stdin(file));
+    let (program, loader, warnings) = load_modules(file, &source, cache.as_ref())?;
+    let key = loader.key(file, &source);
+
+    let mut pipeline = Pipeline::new()
+        .engine(options.engine.unwrap_or_default())
+        .module_name(&module_name(file))
+        .verbose(options.verbose)
+        .skip_side_quests(options.skip_side_quests);
+    let profiler = (profile || folded.is_some()).then(|| Profiler::new().with_allocation_counter(allocations));
+    if let Some(profiler) = &profiler {
+        pipeline = pipeline.profiler(profiler);
+    }
+
+    // Plugins are loaded first, as type checking needs their functions
+    let mut setup = None;
+    if !plugins.is_empty() {
+        if options.sandbox.is_some() {
+            eprintln!("Plugins cannot be loaded in the sandbox. Not running.");
+            return Err(Failure::General);
+        }
+        let (mut capabilities, consent_mode) = capabilities_for(options, file, &source, consents)?;
+        for path in plugins {
+            let plugin = Plugin::load(path, &mut capabilities).map_err(|e| {
+                eprintln!("{}", e);
+                eprintln!("\nNot running.");
+                Failure::General
+            })?;
+            pipeline = pipeline.plugin(&plugin);
+        }
+        setup = Some((capabilities, consent_mode));
+    }
+
+    // Plugins' functions are not in a check remembered without them
+    let cache = cache.filter(|_| plugins.is_empty());
+    let mut checked = None;
+    if !cache.as_ref().is_some_and(|cache| cache.passed(&key)) {
+        match pipeline.check(&program) {
+            Ok(passed) => checked = Some(passed),
+            Err(diagnostics) => {
+                report_diagnostics(diagnostics);
+                eprintln!("\nType checking failed. Not running.");
+                return Err(Failure::Source);
             }
         }
-        "run" => {
-            let mut parser = Parser::new(tokens, &source);
-            match parser.parse() {
-                Ok(program) => {
-                    // Type check first
-                    let mut typechecker = TypeChecker::new();
-                    if let Err(e) = typechecker.check_program(&program) {
-                        eprintln!("Type error: {}", e);
-                        eprintln!("\nType checking failed. Not running.");
-                        return Ok(());
-                    }
-
-                    // Run the program
-                    let mut interpreter = Interpreter::new();
-                    if let Err(e) = interpreter.run(&program) {
-                        eprintln!("Runtime error: {}", e);
-                    }
+        if let Some(cache) = cache.as_ref().filter(|_| warnings.is_empty()) {
+            let _ = cache.record_pass(&key);
+        }
+    }
+    if warn(options, file, &source, &warnings).is_err() {
+        eprintln!("\nWarnings are denied. Not running.");
+        return Err(Failure::Source);
+    }
+    if let Some(root) = &options.sandbox {
+        if !root.is_dir() {
+            eprintln!("Sandbox directory {} does not exist", root.display());
+            return Err(Failure::General);
+        }
+        pipeline = pipeline.sandbox(root);
+    }
+
+    let (capabilities, consent_mode) = match setup {
+        Some(setup) => setup,
+        None => capabilities_for(options, file, &source, consents)?,
+    };
+    wokelang::stdlib::env::set_script_args(args);
+
+    let mut pipeline = pipeline.typecheck(false).capabilities(capabilities).consent_mode(consent_mode);
+    let result = match &cache {
+        Some(cache) if options.engine == Some(Engine::Vm) => {
+            let compiled = match cache.bytecode(&key) {
+                Some(compiled) => Ok(compiled),
+                None => match &checked {
+                    Some(checked) => pipeline.compile_checked(checked),
+                    None => pipeline.compile(&program),
                 }
+                .inspect(|compiled| {
+                    let _ = cache.store_bytecode(&key, compiled);
+                }),
+            };
+            compiled.and_then(|compiled| pipeline.run_compiled(compiled))
+        }
+        _ => match &checked {
+            Some(checked) => pipeline.run_checked(checked),
+            None => pipeline.run_program(&program),
+        },
+    };
+    *consents = pipeline.capabilities_mut().take_consent_store();
+    report_side_quests(&pipeline);
+    if let Some(profiler) = &profiler {
+        if profile {
+            print_profile(profiler);
+        }
+        if let Some(path) = folded {
+            fs::write(path, profiler.folded()).map_err(|e| {
+                eprintln!("Failed to write {}: {}", path.display(), e);
+                Failure::General
+            })?;
+            eprintln!("Wrote {}", path.display());
+        }
+    }
+    let value = result.map_err(|diagnostics| {
+        let failure = match diagnostics.stage() {
+            Stage::Compile => Failure::Source,
+            _ => Failure::Runtime,
+        };
+        report_diagnostics(diagnostics);
+        failure
+    })?;
+    exit_status(&value)
+}
+
+/// The outcome of a program whose `main` gave back `value`: an Int is its
+/// exit status, and an Oops fails it
+fn exit_status(value: &Value) -> Outcome {
+    if let Value::Oops(error) = value {
+        eprintln!("Oops: {}", error);
+    }
+    match value.exit_status() {
+        0 => Ok(()),
+        code => Err(Failure::Exit(code)),
+    }
+}
+
+/// How the side quests went, apart from the program's own output; a side
+/// quest that failed does not fail the run
+fn report_side_quests(pipeline: &Pipeline) {
+    if pipeline.side_quests().is_empty() {
+        return;
+    }
+    eprintln!("\nSide quests:");
+    for outcome in pipeline.side_quests() {
+        eprintln!("  {}", outcome);
+    }
+}
+
+/// Show how often each function was called and the time and allocations
+/// spent in it, those with the most time of their own first
+fn print_profile(profiler: &Profiler) {
+    let functions = profiler.functions();
+    if functions.is_empty() {
+        return;
+    }
+    let width = functions.iter().map(|f| f.name.len()).max().unwrap_or(0).max("function".len());
+    eprintln!("\nProfile:");
+    eprintln!(
+        "  {:width$}  {:>8}  {:>12}  {:>12}  {:>11}",
+        "function", "calls", "total", "self", "allocations",
+        width = width
+    );
+    for function in &functions {
+        let allocations = function.allocations.map_or("-".to_string(), |count| count.to_string());
+        eprintln!(
+            "  {:width$}  {:>8}  {:>12}  {:>12}  {:>11}",
+            function.name,
+            function.calls,
+            format!("{:.2?}", function.total),
+            format!("{:.2?}", function.self_time),
+            allocations,
+            width = width
+        );
+    }
+}
+
+/// The system allocator, counting the allocations it makes so that
+/// `woke run --profile` can show each function's
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Heap allocations made so far
+fn allocations() -> u64 {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// Run a notebook's blocks and write it out with their output, failing
+/// if any block did
+fn notebook(options: &Options, file: &Path, output: Option<&Path>) -> Outcome {
+    let source = read_source(file)?;
+    if let Some(root) = &options.sandbox {
+        if !root.is_dir() {
+            eprintln!("Sandbox directory {} does not exist", root.display());
+            return Err(Failure::General);
+        }
+    }
+    let (capabilities, consent_mode) = capabilities_for(options, file, &source, &mut None)?;
+    let mut interpreter = Interpreter::new()
+        .with_module_name(&module_name(file))
+        .with_capabilities(capabilities)
+        .with_consent_mode(consent_mode)
+        .with_verbose(options.verbose);
+    if let Some(root) = &options.sandbox {
+        interpreter = interpreter.with_sandbox(root);
+    }
+
+    let mut notebook = Notebook::new(interpreter);
+    let document = notebook.run(&source);
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None if is_stdin(file) => PathBuf::from("-"),
+        None => notebook::output_path(file),
+    };
+    if is_stdin(&output) {
+        print!("{}", document);
+    } else {
+        fs::write(&output, document).map_err(|e| {
+            eprintln!("Could not write {}: {}", output.display(), e);
+            Failure::General
+        })?;
+        println!("Wrote {}", output.display());
+    }
+
+    for (line, error) in notebook.errors() {
+        eprintln!("{}:{}: {}", file.display(), line, error);
+    }
+    match notebook.errors().is_empty() {
+        true => Ok(()),
+        false => Err(Failure::Runtime),
+    }
+}
+
+/// The superpowers a run starts with and how it answers requests for
+/// more: the `--allow-*` flags, then `WOKE_CONSENT` and friends, then the
+/// program's signed manifest or else its policy file, then `--deny-all`.
+/// Consents are remembered in the store `consents` holds, if any.
+fn capabilities_for(
+    options: &Options,
+    file: &Path,
+    source: &str,
+    consents: &mut Option<ConsentStore>,
+) -> Result<(CapabilityRegistry, ConsentMode), Failure> {
+    let not_running = |message: String| {
+        eprintln!("{}", message);
+        eprintln!("\nNot running.");
+        Failure::General
+    };
+
+    let mut capabilities = CapabilityRegistry::new();
+    capabilities.apply_policy(&options.allowed());
+    // A WOKE_CONSENT_FILE policy is applied here and takes the place of
+    // woke.policy.toml
+    let mut mode = capabilities.configure_from_env().map_err(|e| not_running(e.to_string()))?;
+
+    // A signed manifest takes the place of the policy file next to the
+    // program, which nobody signed
+    let manifest = match Manifest::find_for(file) {
+        Some(path) => Some(
+            Manifest::load(&path)
+                .and_then(|m| m.verify(source).map(|()| m))
+                .map_err(|e| not_running(format!("{}: {}", path.display(), e)))?,
+        ),
+        None => None,
+    };
+    let has_consent_file = env::var_os(CONSENT_FILE_ENV).is_some_and(|p| !p.is_empty());
+    if let Some(manifest) = &manifest {
+        if !accept_manifest(file, manifest, mode, options.deny_all) {
+            eprintln!("The manifest's superpowers were not granted. Not running.");
+            return Err(Failure::General);
+        }
+        capabilities.apply_policy(&manifest.allowed());
+        mode = ConsentMode::Deny;
+    } else if !has_consent_file {
+        match Policy::find_for(file) {
+            Some(policy_path) => match Policy::load(&policy_path) {
+                Ok(policy) => capabilities.apply_policy(&policy),
                 Err(e) => {
-                    eprintln!("{:?}", miette::Report::new(e));
+                    eprintln!("{}: {}", policy_path.display(), e);
+                    eprintln!("\nFix the policy file or remove it. Not running.");
+                    return Err(Failure::General);
                 }
+            },
+            None if mode == ConsentMode::Policy => {
+                return Err(not_running(format!(
+                    "WOKE_CONSENT=policy needs a {} next to the program, or WOKE_CONSENT_FILE.",
+                    POLICY_FILE
+                )))
+            }
+            None => {}
+        }
+    }
+
+    if options.deny_all {
+        // Only what the flags and policy file allow
+        mode = ConsentMode::Deny;
+    }
+    capabilities.set_consent_mode(mode);
+    if mode == ConsentMode::Ask {
+        capabilities.set_program((!is_stdin(file)).then_some(file));
+        match consents.take().map_or_else(ConsentStore::open_default, Ok) {
+            Ok(store) => capabilities.set_consent_store(store),
+            Err(e) => eprintln!(
+                "Could not read {}: {}; consents will not be remembered",
+                ConsentStore::default_path().display(),
+                e
+            ),
+        }
+    }
+    Ok((capabilities, mode))
+}
+
+/// Show what a verified manifest asks for, and whether to grant all of it
+fn accept_manifest(file: &Path, manifest: &Manifest, mode: ConsentMode, deny_all: bool) -> bool {
+    match mode {
+        ConsentMode::Allow => return true,
+        ConsentMode::Ask if !deny_all => {}
+        _ => return false,
+    }
+    println!("📜 {} comes with a signed manifest", file.display());
+    match &manifest.author {
+        Some(author) => println!("   Signed by {} with key {}", author, manifest.key_fingerprint()),
+        None => println!("   Signed with key {}", manifest.key_fingerprint()),
+    }
+    println!("   It asks for:");
+    let wanted = manifest.describe();
+    if wanted.is_empty() {
+        println!("     nothing");
+    }
+    for line in wanted {
+        println!("     {}", line);
+    }
+    let timeout = consent::prompt_timeout_from_env().unwrap_or(Some(consent::DEFAULT_PROMPT_TIMEOUT));
+    consent::confirm("   Grant all of these, and nothing else?", timeout)
+}
+
+/// `woke manifest`: make keys, and sign and check manifests
+fn manifest_command(command: ManifestCommand) -> Outcome {
+    let failed = |message: String| {
+        eprintln!("{}", message);
+        Failure::General
+    };
+    match command {
+        ManifestCommand::Keygen { key_file: path } => {
+            if path.exists() {
+                return Err(failed(format!("{} already exists; not replacing it", path.display())));
+            }
+            let key = manifest::generate_key().map_err(|e| failed(format!("Could not make a key: {}", e)))?;
+            manifest::save_key(&key, &path).map_err(|e| failed(e.to_string()))?;
+            println!("Wrote a signing key to {}; keep it secret", path.display());
+            println!("Key fingerprint: {}", manifest::key_fingerprint(&key.verifying_key()));
+        }
+        ManifestCommand::Sign { file: program, key_file } => {
+            let path = Manifest::path_for(&program);
+            let Ok(draft) = fs::read_to_string(&path) else {
+                return Err(failed(format!(
+                    "Write {} with the [allow] and [quota] sections {} needs first",
+                    path.display(),
+                    program.display()
+                )));
+            };
+            let source = read_source(&program)?;
+            let key = manifest::load_key(&key_file).map_err(|e| failed(e.to_string()))?;
+            let text = Manifest::sign(&draft, &source, &key).map_err(|e| failed(e.to_string()))?;
+            fs::write(&path, text).map_err(|e| failed(format!("Could not write {}: {}", path.display(), e)))?;
+            println!(
+                "Signed {} with key {}",
+                path.display(),
+                manifest::key_fingerprint(&key.verifying_key())
+            );
+        }
+        ManifestCommand::Show { file: program } => {
+            let Some(path) = Manifest::find_for(&program) else {
+                println!(
+                    "{} has no manifest (looked for {}; a manifest for name.woke is name{})",
+                    program.display(),
+                    Manifest::path_for(&program).display(),
+                    MANIFEST_SUFFIX
+                );
+                return Ok(());
+            };
+            let manifest = Manifest::load(&path).map_err(|e| failed(format!("{}: {}", path.display(), e)))?;
+            let verified = manifest.verify(&read_source(&program)?);
+            match &verified {
+                Ok(()) => println!("{}: signature valid", path.display()),
+                Err(e) => println!("{}: {}", path.display(), e),
+            }
+            if let Some(author) = &manifest.author {
+                println!("Author: {}", author);
+            }
+            println!("Key fingerprint: {}", manifest.key_fingerprint());
+            for line in manifest.describe() {
+                println!("  {}", line);
             }
+            verified.map_err(|_| Failure::General)?;
         }
-        _ => unreachable!(),
     }
+    Ok(())
+}
 
+/// `woke consents`: show or forget the decisions remembered between runs
+fn consents_command(command: ConsentsCommand) -> Outcome {
+    let path = ConsentStore::default_path();
+    let failed = |verb: &str, e: ConsentError| {
+        eprintln!("Could not {} {}: {}", verb, path.display(), e);
+        Failure::General
+    };
+    let mut store = ConsentStore::open_default().map_err(|e| failed("read", e))?;
+
+    match command {
+        ConsentsCommand::List => {
+            let mut consents = store.list();
+            if consents.is_empty() {
+                println!("No consent decisions remembered in {}", path.display());
+                return Ok(());
+            }
+            consents.sort_by(|a, b| (&a.program, &a.scope, &a.capability).cmp(&(&b.program, &b.scope, &b.capability)));
+            for consent in consents {
+                let until = match consent
+                    .expires_at()
+                    .and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0))
+                {
+                    Some(expires) => format!("until {}", expires.format("%Y-%m-%d %H:%M UTC")),
+                    None => "always".to_string(),
+                };
+                println!(
+                    "{:<8} {} ({} in {}, {})",
+                    if consent.granted { "allowed" } else { "denied" },
+                    consent.capability,
+                    consent.scope,
+                    consent.program,
+                    until
+                );
+            }
+        }
+        ConsentsCommand::Revoke { capability } => {
+            let scopes: Vec<(String, String)> = store
+                .list()
+                .into_iter()
+                .filter(|c| c.capability == capability)
+                .map(|c| (c.program.clone(), c.scope.clone()))
+                .collect();
+            for (program, scope) in &scopes {
+                store.revoke(program, scope, &capability).map_err(|e| failed("save", e))?;
+            }
+            match scopes.len() {
+                0 => println!("No decision remembered for {}", capability),
+                n => println!("Forgot {} decision(s) for {}", n, capability),
+            }
+        }
+        ConsentsCommand::Clear => {
+            store.clear().map_err(|e| failed("save", e))?;
+            println!("Forgot every consent decision");
+        }
+    }
     Ok(())
 }
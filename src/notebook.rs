@@ -0,0 +1,268 @@
+//! Notebooks: Markdown With Runnable WokeLang
+//!
+//! A notebook is a Markdown file, by convention named `*.woke.md`, whose
+//! ```` ```woke ```` fenced blocks run in order in one session, as if each
+//! were typed into the REPL in turn. Running it gives the document back
+//! with every block's output in an ```` ```output ```` block right after
+//! it. Output blocks left by an earlier run are replaced, so a notebook can
+//! be run again as often as it changes. A block marked ```` ```woke skip ````
+//! is shown but not run.
+
+use crate::interpreter::{Interpreter, OutputBuffer, Value};
+use crate::pipeline::Pipeline;
+use crate::typechecker::TypeChecker;
+use std::path::{Path, PathBuf};
+
+/// Info string of the blocks that are run
+pub const CODE_INFO: &str = "woke";
+
+/// Info string of the blocks a run writes its output to
+pub const OUTPUT_INFO: &str = "output";
+
+/// One session running a notebook's blocks
+pub struct Notebook {
+    interpreter: Interpreter,
+    typechecker: TypeChecker,
+    output: OutputBuffer,
+    /// Blocks that failed, as the line their fence opens on and the error
+    errors: Vec<(usize, String)>,
+}
+
+/// A fence opening a code block
+struct Fence<'a> {
+    /// The fence character, `` ` `` or `~`
+    marker: char,
+    /// How many of it open the block; the closing fence needs as many
+    length: usize,
+    info: &'a str,
+}
+
+impl Notebook {
+    /// A session running blocks on `interpreter`, whose output becomes the
+    /// blocks' output
+    pub fn new(interpreter: Interpreter) -> Self {
+        let output = OutputBuffer::new();
+        Self {
+            interpreter: interpreter.with_output(output.clone()),
+            typechecker: TypeChecker::new(),
+            output,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Run the notebook `markdown` and give it back with each block's
+    /// output after it
+    pub fn run(&mut self, markdown: &str) -> String {
+        let lines: Vec<&str> = markdown.split_inclusive('\n').collect();
+        let mut out = String::new();
+        let mut i = 0;
+        while i < lines.len() {
+            let Some(fence) = opening_fence(lines[i]) else {
+                out.push_str(lines[i]);
+                i += 1;
+                continue;
+            };
+            let end = closing_fence(&lines, i, &fence);
+            for line in &lines[i..end.min(lines.len() - 1) + 1] {
+                out.push_str(line);
+            }
+            let mut words = fence.info.split_whitespace();
+            let runs = words.next() == Some(CODE_INFO) && !words.any(|word| word == "skip");
+            let opened = i;
+            i = end + 1;
+            if !runs {
+                continue;
+            }
+
+            let code: String = lines[opened + 1..end.min(lines.len())].concat();
+            let text = self.run_block(&code).unwrap_or_else(|message| {
+                self.errors.push((opened + 1, message.clone()));
+                self.output.take() + &message + "\n"
+            });
+
+            // Replace the output of an earlier run
+            let mut next = i;
+            while next < lines.len() && lines[next].trim().is_empty() {
+                next += 1;
+            }
+            if let Some(old) = lines.get(next).and_then(|line| opening_fence(line)) {
+                if old.info == OUTPUT_INFO {
+                    i = closing_fence(&lines, next, &old) + 1;
+                }
+            }
+
+            if !text.is_empty() {
+                if !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                let fence = "`".repeat(longest_run(&text, '`').max(2) + 1);
+                out.push_str(&format!("\n{}{}\n{}{}\n", fence, OUTPUT_INFO, text, fence));
+            }
+        }
+        out
+    }
+
+    /// Blocks that failed so far, as the line each one's fence opens on
+    /// and the error it gave
+    pub fn errors(&self) -> &[(usize, String)] {
+        &self.errors
+    }
+
+    /// Run one block, giving what it printed and the value of its final
+    /// expression, one per line
+    fn run_block(&mut self, code: &str) -> Result<String, String> {
+        let pipeline = Pipeline::new();
+        let snippet = pipeline.parse_snippet(code).map_err(|e| e.to_string())?;
+        pipeline.check_snippet(&mut self.typechecker, &snippet).map_err(|e| e.to_string())?;
+        let value = pipeline.run_snippet(&mut self.interpreter, &snippet).map_err(|e| e.to_string())?;
+
+        let mut text = self.output.take();
+        if !matches!(value, Value::Unit) {
+            text.push_str(&format!("{}\n", value));
+        }
+        Ok(text)
+    }
+}
+
+/// The fence a line opens a code block with, if it does
+fn opening_fence(line: &str) -> Option<Fence<'_>> {
+    let trimmed = line.trim_end_matches(['\n', '\r']);
+    let rest = trimmed.trim_start_matches(' ');
+    if trimmed.len() - rest.len() > 3 {
+        return None;
+    }
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = rest.chars().take_while(|c| *c == marker).count();
+    let info = rest[length..].trim();
+    if length < 3 || (marker == '`' && info.contains('`')) {
+        return None;
+    }
+    Some(Fence { marker, length, info })
+}
+
+/// The index of the line closing the block `fence` opens on line `start`,
+/// or the index past the last line if the block is never closed
+fn closing_fence(lines: &[&str], start: usize, fence: &Fence) -> usize {
+    (start + 1..lines.len())
+        .find(|&i| {
+            let line = lines[i].trim();
+            line.len() >= fence.length && line.chars().all(|c| c == fence.marker)
+        })
+        .unwrap_or(lines.len())
+}
+
+/// The longest run of `c` in `text`
+fn longest_run(text: &str, c: char) -> usize {
+    text.split(|ch| ch != c).map(str::len).max().unwrap_or(0)
+}
+
+/// Where the run of a notebook is written by default: `intro.md` for
+/// `intro.woke.md`, otherwise the name with `.out` before the extension
+pub fn output_path(notebook: &Path) -> PathBuf {
+    let name = notebook.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let output = match name.strip_suffix(".woke.md") {
+        Some(stem) if !stem.is_empty() => format!("{}.md", stem),
+        _ => match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => format!("{}.out.{}", stem, extension),
+            _ => format!("{}.out.md", name),
+        },
+    };
+    notebook.with_file_name(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notebook() -> Notebook {
+        Notebook::new(Interpreter::new().with_consent_handler(|_| false))
+    }
+
+    #[test]
+    fn test_run_notebook() {
+        let source = "# Doubling
+
+Define a function:
+
+```woke
+to double(n: Int) -> Int { give back n * 2; }
+remember x = 20;
+print(\"ready\");
+```
+
+Then use it:
+
+```woke
+double(x) + 2
+```
+
+```woke skip
+double(\"not run\")
+```
+
+```text
+not WokeLang
+```
+
+````woke
+x / 0
+````
+";
+        let mut notebook = notebook();
+        let output = notebook.run(source);
+        assert_eq!(
+            output,
+            "# Doubling
+
+Define a function:
+
+```woke
+to double(n: Int) -> Int { give back n * 2; }
+remember x = 20;
+print(\"ready\");
+```
+
+```output
+ready
+```
+
+Then use it:
+
+```woke
+double(x) + 2
+```
+
+```output
+42
+```
+
+```woke skip
+double(\"not run\")
+```
+
+```text
+not WokeLang
+```
+
+````woke
+x / 0
+````
+
+```output
+Runtime error [WOKE-E0303]: Division by zero
+```
+"
+        );
+        assert_eq!(notebook.errors(), &[(25, "Runtime error [WOKE-E0303]: Division by zero".to_string())]);
+
+        // A second run replaces the output blocks instead of adding more
+        assert_eq!(Notebook::new(Interpreter::new()).run(&output), output);
+    }
+
+    #[test]
+    fn test_output_path() {
+        assert_eq!(output_path(Path::new("docs/intro.woke.md")), Path::new("docs/intro.md"));
+        assert_eq!(output_path(Path::new("report.md")), Path::new("report.out.md"));
+        assert_eq!(output_path(Path::new("notes")), Path::new("notes.out.md"));
+    }
+}
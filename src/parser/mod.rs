@@ -1,57 +1,287 @@
 use crate::ast::*;
-use crate::lexer::{Spanned as LexSpanned, Token};
+use crate::lexer::{Lexer, LexerError, SourceFile, Spanned as LexSpanned, Token, Tokens};
+use crate::security::limits;
+use crate::security::policy::parse_capability;
+use crate::stdlib::duration;
 use miette::{Diagnostic, SourceSpan};
+use std::cell::OnceCell;
+use std::collections::VecDeque;
+use std::time::Duration;
 use thiserror::Error;
 
+/// How many tokens past the current one the parser looks at
+const LOOKAHEAD: usize = 2;
+
+/// How deeply expressions, blocks and types may nest by default, well
+/// within what the parser's stack holds
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
 #[derive(Error, Debug, Diagnostic)]
 pub enum ParseError {
     #[error("Unexpected token: expected {expected}, found {found}")]
-    #[diagnostic(code(wokelang::parser::unexpected_token))]
+    #[diagnostic(code("WOKE-E0100"), help("run `woke explain WOKE-E0100` for more about this error"))]
     UnexpectedToken {
         expected: String,
         found: String,
         #[source_code]
-        src: String,
+        src: SourceFile,
         #[label("here")]
         span: SourceSpan,
     },
 
     #[error("Unexpected end of input")]
-    #[diagnostic(code(wokelang::parser::unexpected_eof))]
+    #[diagnostic(code("WOKE-E0101"), help("run `woke explain WOKE-E0101` for more about this error"))]
     UnexpectedEof,
 
     #[error("{message}")]
-    #[diagnostic(code(wokelang::parser::general))]
+    #[diagnostic(code("WOKE-E0102"), help("run `woke explain WOKE-E0102` for more about this error"))]
     General {
         message: String,
         #[source_code]
-        src: String,
+        src: SourceFile,
+        #[label("here")]
+        span: SourceSpan,
+    },
+
+    #[error("Nested too deeply: more than {limit} levels")]
+    #[diagnostic(code("WOKE-E0103"), help("run `woke explain WOKE-E0103` for more about this error"))]
+    TooDeep {
+        limit: usize,
+        #[source_code]
+        src: SourceFile,
+        #[label("here")]
+        span: SourceSpan,
+    },
+
+    /// A token that does not lex, met by a parser pulling tokens from a
+    /// lexer as it goes; the lexer's own error, which it reports as is
+    #[error("Unexpected character")]
+    #[diagnostic(code("WOKE-E0001"), help("run `woke explain WOKE-E0001` for more about this error"))]
+    Lex {
+        #[source_code]
+        src: SourceFile,
         #[label("here")]
         span: SourceSpan,
     },
 }
 
+impl From<LexerError> for ParseError {
+    fn from(error: LexerError) -> Self {
+        ParseError::Lex {
+            src: error.src,
+            span: error.span,
+        }
+    }
+}
+
+impl ParseError {
+    /// The error's code in the `woke explain` catalog
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken { .. } => "WOKE-E0100",
+            ParseError::UnexpectedEof => "WOKE-E0101",
+            ParseError::General { .. } => "WOKE-E0102",
+            ParseError::TooDeep { .. } => "WOKE-E0103",
+            ParseError::Lex { .. } => "WOKE-E0001",
+        }
+    }
+}
+
+/// What is typed at a REPL, as [`Parser::parse_snippet`] reads it
+#[derive(Debug)]
+pub struct Snippet {
+    pub program: Program,
+    /// Statements to run at the top level, e.g. `remember x = 1;`
+    pub statements: Vec<Statement>,
+    /// The expression whose value is shown
+    pub expr: Option<Spanned<Expr>>,
+}
+
 pub struct Parser<'src> {
-    tokens: Vec<LexSpanned<Token>>,
+    /// The tokens from the one before the current item on, as far as
+    /// they have been lexed
+    tokens: VecDeque<LexSpanned<Token>>,
+    /// The position of the first of `tokens`
+    first: usize,
+    /// Where more tokens come from, when they are lexed as they are needed
+    stream: Option<Tokens<'src>>,
+    /// The error the stream stopped at, if it did
+    lex_error: Option<LexerError>,
     pos: usize,
     source: &'src str,
+    /// The source shared by every error, made at the first one
+    file: OnceCell<SourceFile>,
+    /// Errors recovered from so far, in the order they were found
+    errors: Vec<ParseError>,
+    /// How deeply nested the current expression, block or type is
+    depth: usize,
+    max_depth: usize,
 }
 
 impl<'src> Parser<'src> {
     pub fn new(tokens: Vec<LexSpanned<Token>>, source: &'src str) -> Self {
         Self {
-            tokens,
+            tokens: tokens.into(),
+            first: 0,
+            stream: None,
+            lex_error: None,
             pos: 0,
             source,
+            file: OnceCell::new(),
+            errors: Vec::new(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Parse the tokens of a file, whose name the errors show
+    pub fn for_file(tokens: Vec<LexSpanned<Token>>, file: &'src SourceFile) -> Self {
+        Self {
+            file: OnceCell::from(file.clone()),
+            ..Self::new(tokens, file.text())
         }
     }
 
+    /// Parse tokens as `lexer` makes them, pulling each only when it is
+    /// needed and letting go of those of each top-level item once it is
+    /// parsed, so the whole file's tokens are never held at once. A token
+    /// that does not lex ends the input, and is reported as
+    /// [`ParseError::Lex`] after the errors found before it.
+    pub fn from_lexer(lexer: Lexer<'src>) -> Self {
+        let mut parser = Self {
+            stream: Some(lexer.tokens()),
+            file: lexer.file().cloned().map(OnceCell::from).unwrap_or_default(),
+            ..Self::new(Vec::new(), lexer.source())
+        };
+        parser.fill();
+        parser
+    }
+
+    /// Fail with [`ParseError::TooDeep`] where expressions, blocks or
+    /// types nest more than `limit` deep, rather than [`DEFAULT_MAX_DEPTH`]
+    pub fn with_max_depth(mut self, limit: usize) -> Self {
+        self.max_depth = limit;
+        self
+    }
+
+    /// Parse a whole program, failing with the first error in it
     pub fn parse(&mut self) -> Result<Program, ParseError> {
+        let (program, errors) = self.parse_all();
+        match errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(program),
+        }
+    }
+
+    /// Parse a whole program, carrying on past errors: a statement in error
+    /// is skipped to its `;` or the end of its block, and a top-level item
+    /// in error to where the next one starts. Gives back what could be
+    /// parsed, and every error found in source order.
+    pub fn parse_all(&mut self) -> (Program, Vec<ParseError>) {
         let mut items = Vec::new();
         while !self.is_at_end() {
+            self.release();
+            let start = self.pos;
+            match self.parse_top_level_item() {
+                Ok(item) => items.push(item),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize_item(start);
+                }
+            }
+        }
+        (Program { items }, self.take_errors())
+    }
+
+    /// The errors found so far, ending with the one the lexer stopped at,
+    /// if it did; errors after where it stopped only come of the input
+    /// ending there, so they are left out
+    fn take_errors(&mut self) -> Vec<ParseError> {
+        let mut errors = std::mem::take(&mut self.errors);
+        if let Some(error) = self.lex_error.take() {
+            let at = error.span.offset();
+            errors.retain(|e| match e {
+                ParseError::UnexpectedToken { span, .. }
+                | ParseError::General { span, .. }
+                | ParseError::TooDeep { span, .. } => span.offset() < at,
+                _ => false,
+            });
+            errors.push(ParseError::Lex {
+                src: self.source_file(),
+                span: error.span,
+            });
+        }
+        errors
+    }
+
+    /// Parse what is typed at a REPL: definitions, then statements to run,
+    /// then optionally one expression to evaluate; an expression statement
+    /// at the end is taken as that expression
+    pub fn parse_snippet(&mut self) -> Result<Snippet, ParseError> {
+        let mut items = Vec::new();
+        while matches!(
+            self.peek(),
+            Some(
+                Token::To
+                    | Token::At
+                    | Token::Only
+                    | Token::Thanks
+                    | Token::Worker
+                    | Token::Side
+                    | Token::Superpower
+                    | Token::Use
+                    | Token::Hash
+                    | Token::Type
+                    | Token::Const
+                    | Token::Expect
+            )
+        ) {
             items.push(self.parse_top_level_item()?);
         }
-        Ok(Program { items })
+
+        let mut statements = Vec::new();
+        let mut expr = None;
+        while !self.is_at_end() {
+            let start = self.pos;
+            let recovered = self.errors.len();
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    // The expression may just have no `;` after it
+                    let error = self.errors.drain(recovered..).next().unwrap_or(error);
+                    self.pos = start;
+                    expr = Some(self.parse_single_expression().map_err(|_| error)?);
+                }
+            }
+        }
+        if let Some(error) = self.take_errors().into_iter().next() {
+            return Err(error);
+        }
+        if expr.is_none() && matches!(statements.last(), Some(Statement::Expression(_))) {
+            if let Some(Statement::Expression(last)) = statements.pop() {
+                expr = Some(last);
+            }
+        }
+        Ok(Snippet {
+            program: Program { items },
+            statements,
+            expr,
+        })
+    }
+
+    /// Parse source that is one expression, optionally ending in `;`
+    pub fn parse_single_expression(&mut self) -> Result<Spanned<Expr>, ParseError> {
+        let expr = self.parse_expression()?;
+        if !self.errors.is_empty() {
+            return Err(self.errors.remove(0));
+        }
+        if self.check(&Token::Semicolon) {
+            self.advance();
+        }
+        if !self.is_at_end() {
+            return Err(self.error("Expected the end of the expression"));
+        }
+        Ok(expr)
     }
 
     fn parse_top_level_item(&mut self) -> Result<TopLevelItem, ParseError> {
@@ -73,6 +303,8 @@ fn parse_top_level_item(&mut self) -> Result<TopLevelItem, ParseError> {
             Some(Token::Hash) => Ok(TopLevelItem::Pragma(self.parse_pragma()?)),
             Some(Token::Type) => Ok(TopLevelItem::TypeDef(self.parse_type_def()?)),
             Some(Token::Const) => Ok(TopLevelItem::ConstDef(self.parse_const_def()?)),
+            Some(Token::Expect) => Ok(TopLevelItem::Expect(self.parse_expect_block()?)),
+            Some(Token::Remember) => Ok(TopLevelItem::Global(self.parse_var_decl()?)),
             _ => Err(self.error("Expected top-level item")),
         }
     }
@@ -118,10 +350,7 @@ fn parse_function_def(&mut self, emote: Option<EmoteTag>) -> Result<FunctionDef,
             None
         };
 
-        let mut body = Vec::new();
-        while !self.check(&Token::Goodbye) && !self.check(&Token::RBrace) {
-            body.push(self.parse_statement()?);
-        }
+        let body = self.parse_statements_until(&[Token::Goodbye, Token::RBrace]);
 
         let goodbye = if self.check(&Token::Goodbye) {
             self.advance();
@@ -145,6 +374,7 @@ fn parse_function_def(&mut self, emote: Option<EmoteTag>) -> Result<FunctionDef,
             body,
             goodbye,
             span: start..end,
+            module: None,
         })
     }
 
@@ -165,7 +395,7 @@ fn parse_parameter_list(&mut self) -> Result<Vec<Parameter>, ParseError> {
 
     fn parse_parameter(&mut self) -> Result<Parameter, ParseError> {
         let start = self.current_span().start;
-        let name = self.expect_identifier()?;
+        let name = self.expect_symbol()?;
         let ty = if self.check(&Token::Colon) {
             self.advance();
             Some(self.parse_type()?)
@@ -227,6 +457,7 @@ fn parse_consent_block(&mut self) -> Result<ConsentBlock, ParseError> {
         self.expect(Token::If)?;
         self.expect(Token::Okay)?;
         let permission = self.expect_string()?;
+        let reason = self.parse_reason()?;
         self.expect(Token::LBrace)?;
         let body = self.parse_statement_list()?;
         let end = self.current_span().end;
@@ -234,6 +465,7 @@ fn parse_consent_block(&mut self) -> Result<ConsentBlock, ParseError> {
 
         Ok(ConsentBlock {
             permission,
+            reason,
             body,
             span: start..end,
         })
@@ -314,6 +546,22 @@ fn parse_side_quest_def(&mut self) -> Result<SideQuestDef, ParseError> {
         })
     }
 
+    fn parse_expect_block(&mut self) -> Result<ExpectBlock, ParseError> {
+        let start = self.current_span().start;
+        self.expect(Token::Expect)?;
+        let description = self.expect_string()?;
+        self.expect(Token::LBrace)?;
+        let body = self.parse_statement_list()?;
+        let end = self.current_span().end;
+        self.expect(Token::RBrace)?;
+
+        Ok(ExpectBlock {
+            description,
+            body,
+            span: start..end,
+        })
+    }
+
     fn parse_superpower_decl(&mut self) -> Result<SuperpowerDecl, ParseError> {
         let start = self.current_span().start;
         self.expect(Token::Superpower)?;
@@ -342,16 +590,61 @@ fn parse_module_import(&mut self) -> Result<ModuleImport, ParseError> {
         } else {
             None
         };
+        let (needs, reason) = if self.check(&Token::Must) {
+            self.advance();
+            self.expect(Token::Have)?;
+            (Some(self.parse_capability_needs()?), self.parse_reason()?)
+        } else {
+            (None, None)
+        };
         let end = self.current_span().end;
         self.expect(Token::Semicolon)?;
 
         Ok(ModuleImport {
             path,
             rename,
+            needs,
+            reason,
             span: start..end,
         })
     }
 
+    /// Parse an optional `because "..."`
+    fn parse_reason(&mut self) -> Result<Option<String>, ParseError> {
+        if self.check(&Token::Because) {
+            self.advance();
+            Ok(Some(self.expect_string()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parse `read "data", network, crypto` after `must have`
+    fn parse_capability_needs(&mut self) -> Result<Vec<CapabilityNeed>, ParseError> {
+        let mut needs = Vec::new();
+        loop {
+            let start = self.current_span().start;
+            let kind = self.expect_identifier()?;
+            let value = match self.peek() {
+                Some(Token::String(_)) => Some(self.expect_string()?),
+                _ => None,
+            };
+            let span = start..self.previous_span().end;
+            if let Err(e) = parse_capability(&kind, value.as_deref()) {
+                return Err(ParseError::General {
+                    message: e.to_string(),
+                    src: self.source_file(),
+                    span: span.into(),
+                });
+            }
+            needs.push(CapabilityNeed { kind, value, span });
+            if !self.check(&Token::Comma) {
+                return Ok(needs);
+            }
+            self.advance();
+        }
+    }
+
     fn parse_qualified_name(&mut self) -> Result<QualifiedName, ParseError> {
         let start = self.current_span().start;
         let mut parts = vec![self.expect_identifier()?];
@@ -385,23 +678,51 @@ fn parse_pragma(&mut self) -> Result<Pragma, ParseError> {
                 self.advance();
                 PragmaDirective::Verbose
             }
-            _ => return Err(self.error("Expected pragma directive (care, strict, verbose)")),
+            // `#side quests off;`
+            Some(Token::Side) => {
+                self.advance();
+                match self.peek() {
+                    Some(Token::Identifier(s)) if s == "quests" => {
+                        self.advance();
+                    }
+                    _ => return Err(self.error("Expected 'quests' after 'side'")),
+                }
+                PragmaDirective::SideQuests
+            }
+            // `#timeout 5s;` and `#maxmemory 64mb;` take an amount, not on/off
+            Some(Token::Identifier(s)) if s == "timeout" || s == "maxmemory" => {
+                let limit = s == "timeout";
+                self.advance();
+                let directive = match limit {
+                    true => PragmaDirective::Timeout(self.parse_timeout()?),
+                    false => PragmaDirective::MaxMemory(self.parse_memory_size()?),
+                };
+                let end = self.current_span().end;
+                self.expect(Token::Semicolon)?;
+                return Ok(Pragma {
+                    directive,
+                    enabled: true,
+                    span: start..end,
+                });
+            }
+            _ => {
+                return Err(self.error(
+                    "Expected pragma directive (care, strict, verbose, side quests, timeout, maxmemory)",
+                ))
+            }
         };
 
-        let enabled = if self.check(&Token::Identifier(String::new())) {
-            match self.peek() {
-                Some(Token::Identifier(s)) if s == "on" => {
-                    self.advance();
-                    true
-                }
-                Some(Token::Identifier(s)) if s == "off" => {
-                    self.advance();
-                    false
-                }
-                _ => return Err(self.error("Expected 'on' or 'off'")),
+        // `on` is a keyword (used by `on event`), `off` is an identifier
+        let enabled = match self.peek() {
+            Some(Token::On) => {
+                self.advance();
+                true
             }
-        } else {
-            return Err(self.error("Expected 'on' or 'off'"));
+            Some(Token::Identifier(s)) if s == "off" => {
+                self.advance();
+                false
+            }
+            _ => return Err(self.error("Expected 'on' or 'off'")),
         };
 
         let end = self.current_span().end;
@@ -414,6 +735,41 @@ fn parse_pragma(&mut self) -> Result<Pragma, ParseError> {
         })
     }
 
+    /// An amount of a time unit, as in `5s` or `1.5 min`
+    fn parse_timeout(&mut self) -> Result<Duration, ParseError> {
+        let amount = match self.peek() {
+            Some(Token::Integer(n)) if *n >= 0 => *n as f64,
+            Some(Token::Float(n)) if *n >= 0.0 => *n,
+            _ => return Err(self.error("Expected a timeout, such as 5s")),
+        };
+        self.advance();
+        let per_unit = match self.peek() {
+            Some(Token::Identifier(unit)) => duration::unit_millis(unit),
+            _ => None,
+        }
+        .ok_or_else(|| self.error("Expected a time unit (ms, s, min, h) after the timeout"))?;
+        self.advance();
+        Ok(Duration::from_millis((amount * per_unit as f64).round() as u64))
+    }
+
+    /// A whole number of a memory unit, as in `64mb`
+    fn parse_memory_size(&mut self) -> Result<u64, ParseError> {
+        let amount = match self.peek() {
+            Some(Token::Integer(n)) if *n >= 0 => *n as u64,
+            _ => return Err(self.error("Expected a memory size, such as 64mb")),
+        };
+        self.advance();
+        let per_unit = match self.peek() {
+            Some(Token::Identifier(unit)) => limits::memory_unit(unit),
+            _ => None,
+        }
+        .ok_or_else(|| self.error("Expected a memory unit (b, kb, mb, gb) after the size"))?;
+        self.advance();
+        amount
+            .checked_mul(per_unit)
+            .ok_or_else(|| self.error("Memory size is too large"))
+    }
+
     // === Type Definition ===
 
     fn parse_type_def(&mut self) -> Result<TypeDef, ParseError> {
@@ -524,6 +880,10 @@ fn parse_const_def(&mut self) -> Result<ConstDef, ParseError> {
     // === Type Parsing ===
 
     fn parse_type(&mut self) -> Result<Type, ParseError> {
+        self.nested(Self::parse_type_inner)
+    }
+
+    fn parse_type_inner(&mut self) -> Result<Type, ParseError> {
         if self.check(&Token::LBracket) {
             self.advance();
             let inner = self.parse_type()?;
@@ -543,6 +903,26 @@ fn parse_type(&mut self) -> Result<Type, ParseError> {
             return Ok(Type::Reference(Box::new(inner)));
         }
 
+        // Function type: (Int, String) -> Bool
+        if self.check(&Token::LParen) {
+            self.advance();
+            let mut params = Vec::new();
+            if !self.check(&Token::RParen) {
+                params.push(self.parse_type()?);
+                while self.check(&Token::Comma) {
+                    self.advance();
+                    params.push(self.parse_type()?);
+                }
+            }
+            self.expect(Token::RParen)?;
+            if !self.check(&Token::Arrow) && !self.check(&Token::AsciiArrow) {
+                return Err(self.error("Expected -> after function parameter types"));
+            }
+            self.advance();
+            let ret = self.parse_type()?;
+            return Ok(Type::Function(params, Box::new(ret)));
+        }
+
         match self.peek() {
             Some(Token::TypeString) => {
                 self.advance();
@@ -561,14 +941,14 @@ fn parse_type(&mut self) -> Result<Type, ParseError> {
                 Ok(Type::Basic("Bool".to_string()))
             }
             Some(Token::Identifier(name)) => {
-                let name = name.clone();
+                let name = *name;
                 self.advance();
                 // Check for generic type arguments: Result<T, E>
                 if self.check(&Token::Less) {
                     let args = self.parse_type_args()?;
-                    Ok(Type::Generic(name, args))
+                    Ok(Type::Generic(name.into(), args))
                 } else {
-                    Ok(Type::Basic(name))
+                    Ok(Type::Basic(name.into()))
                 }
             }
             _ => Err(self.error("Expected type")),
@@ -598,18 +978,119 @@ fn parse_type_args(&mut self) -> Result<Vec<Type>, ParseError> {
     // === Statement Parsing ===
 
     fn parse_statement_list(&mut self) -> Result<Vec<Statement>, ParseError> {
+        Ok(self.parse_statements_until(&[Token::RBrace]))
+    }
+
+    /// Parse statements up to one of `ends` or the end of input, recording
+    /// the errors in them and skipping past each; stops early at the start
+    /// of a top-level item, which means a `}` is missing
+    fn parse_statements_until(&mut self, ends: &[Token]) -> Vec<Statement> {
         let mut stmts = Vec::new();
-        while !self.check(&Token::RBrace) && !self.is_at_end() {
-            stmts.push(self.parse_statement()?);
+        while !ends.iter().any(|end| self.check(end)) && !self.is_at_end() && !self.at_item_start() {
+            let start = self.pos;
+            match self.nested(Self::parse_statement) {
+                Ok(stmt) => stmts.push(stmt),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize_statement(start);
+                }
+            }
+        }
+        stmts
+    }
+
+    /// Skip the rest of a statement in error, which began at `start`: past
+    /// its `;`, or past the block it opened (with any `otherwise`), or up
+    /// to the keyword of the next statement, the `}` closing the block it
+    /// is in or the start of a top-level item
+    fn synchronize_statement(&mut self, start: usize) {
+        let mut depth = 0usize;
+        loop {
+            match self.peek() {
+                None | Some(Token::Eof) => return,
+                Some(Token::Semicolon) if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                Some(Token::RBrace) if depth == 0 => return,
+                Some(Token::RBrace) => {
+                    depth -= 1;
+                    self.advance();
+                    if depth == 0 && !self.check(&Token::Otherwise) {
+                        if self.check(&Token::Semicolon) {
+                            self.advance();
+                        }
+                        return;
+                    }
+                    continue;
+                }
+                Some(Token::LBrace) => depth += 1,
+                Some(
+                    Token::Remember
+                    | Token::Give
+                    | Token::When
+                    | Token::Repeat
+                    | Token::Attempt
+                    | Token::Spawn
+                    | Token::Complain
+                    | Token::Decide,
+                ) if depth == 0 && self.pos > start => return,
+                _ if depth == 0 && self.at_item_start() => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Skip the rest of a top-level item in error, which began at `start`,
+    /// up to where the next one starts
+    fn synchronize_item(&mut self, start: usize) {
+        if self.pos == start {
+            self.advance();
+        }
+        let mut depth = 0usize;
+        while !self.is_at_end() {
+            match self.peek() {
+                Some(Token::LBrace) => depth += 1,
+                Some(Token::RBrace) => depth = depth.saturating_sub(1),
+                _ if depth == 0 && self.at_item_start() => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Whether the current token can only start a top-level item, so that
+    /// recovery can pick up there
+    fn at_item_start(&self) -> bool {
+        let next = self.token(self.pos + 1).map(|t| &t.value);
+        match self.peek() {
+            Some(
+                Token::Side
+                | Token::Superpower
+                | Token::Use
+                | Token::Hash
+                | Token::Type
+                | Token::Const
+                | Token::Expect,
+            ) => true,
+            // Not the `to` of `thanks to`
+            Some(Token::To) => !matches!(
+                self.pos.checked_sub(1).and_then(|i| self.token(i)).map(|t| &t.value),
+                Some(Token::Thanks)
+            ),
+            Some(Token::Thanks) => matches!(next, Some(Token::To)),
+            Some(Token::At) => matches!(next, Some(Token::Identifier(_)))
+                && matches!(self.token(self.pos + 2).map(|t| &t.value), Some(Token::To)),
+            _ => false,
         }
-        Ok(stmts)
     }
 
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
         // Check for emote-annotated statement
         if self.check(&Token::At) {
             let emote = self.parse_emote_tag()?;
-            let stmt = self.parse_statement()?;
+            let stmt = self.nested(Self::parse_statement)?;
             let span = emote.span.start..self.previous_span().end;
             return Ok(Statement::EmoteAnnotated(EmoteAnnotatedStmt {
                 emote,
@@ -619,7 +1100,7 @@ fn parse_statement(&mut self) -> Result<Statement, ParseError> {
         }
 
         match self.peek() {
-            Some(Token::Remember) => self.parse_var_decl(),
+            Some(Token::Remember) => Ok(Statement::VarDecl(self.parse_var_decl()?)),
             Some(Token::Give) => self.parse_return_stmt(),
             Some(Token::When) => self.parse_conditional(),
             Some(Token::Repeat) => self.parse_loop(),
@@ -636,7 +1117,7 @@ fn parse_statement(&mut self) -> Result<Statement, ParseError> {
                 // Check if this is an assignment
                 if self.check(&Token::Equal) {
                     if let Expr::Identifier(name) = &expr.node {
-                        let name = name.clone();
+                        let name = *name;
                         self.advance(); // consume '='
                         let value = self.parse_expression()?;
                         let end = self.current_span().end;
@@ -660,10 +1141,17 @@ fn parse_statement(&mut self) -> Result<Statement, ParseError> {
         }
     }
 
-    fn parse_var_decl(&mut self) -> Result<Statement, ParseError> {
+    fn parse_var_decl(&mut self) -> Result<VarDecl, ParseError> {
         let start = self.current_span().start;
         self.expect(Token::Remember)?;
-        let name = self.expect_identifier()?;
+        // `secret` only marks a secret when a name follows, so it can still
+        // be a variable name itself
+        let secret = matches!(self.peek(), Some(Token::Identifier(s)) if s == "secret")
+            && matches!(self.token(self.pos + 1).map(|t| &t.value), Some(Token::Identifier(_)));
+        if secret {
+            self.advance();
+        }
+        let name = self.expect_symbol()?;
         self.expect(Token::Equal)?;
         let value = self.parse_expression()?;
 
@@ -678,12 +1166,13 @@ fn parse_var_decl(&mut self) -> Result<Statement, ParseError> {
         let end = self.current_span().end;
         self.expect(Token::Semicolon)?;
 
-        Ok(Statement::VarDecl(VarDecl {
+        Ok(VarDecl {
             name,
             value,
             unit,
+            secret,
             span: start..end,
-        }))
+        })
     }
 
     fn parse_return_stmt(&mut self) -> Result<Statement, ParseError> {
@@ -852,6 +1341,11 @@ fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
                 self.advance();
                 Ok(Pattern::Literal(Literal::Float(n)))
             }
+            Some(Token::Decimal(n)) => {
+                let n = *n;
+                self.advance();
+                Ok(Pattern::Literal(Literal::Decimal(n)))
+            }
             Some(Token::String(s)) => {
                 let s = s.clone();
                 self.advance();
@@ -866,7 +1360,7 @@ fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
                 Ok(Pattern::Literal(Literal::Bool(false)))
             }
             Some(Token::Identifier(name)) => {
-                let name = name.clone();
+                let name = *name;
                 self.advance();
 
                 // Check for constructor pattern: Okay(inner) or Oops(inner)
@@ -878,7 +1372,7 @@ fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
                         Some(Box::new(self.parse_pattern()?))
                     };
                     self.expect(Token::RParen)?;
-                    Ok(Pattern::Constructor(name, inner_pattern))
+                    Ok(Pattern::Constructor(name.into(), inner_pattern))
                 } else {
                     Ok(Pattern::Identifier(name))
                 }
@@ -921,7 +1415,10 @@ fn parse_emote_tag(&mut self) -> Result<EmoteTag, ParseError> {
 
     fn parse_emote_param(&mut self) -> Result<EmoteParam, ParseError> {
         let name = self.expect_identifier()?;
-        self.expect(Token::Equal)?;
+        if !self.check(&Token::Equal) {
+            return Ok(EmoteParam { name, value: None });
+        }
+        self.advance();
 
         let value = match self.peek() {
             Some(Token::Integer(n)) => {
@@ -940,127 +1437,93 @@ fn parse_emote_param(&mut self) -> Result<EmoteParam, ParseError> {
                 EmoteValue::String(s)
             }
             Some(Token::Identifier(s)) => {
-                let s = s.clone();
+                let s = *s;
                 self.advance();
-                EmoteValue::Identifier(s)
+                EmoteValue::Identifier(s.into())
             }
             _ => return Err(self.error("Expected emote parameter value")),
         };
 
-        Ok(EmoteParam { name, value })
+        Ok(EmoteParam {
+            name,
+            value: Some(value),
+        })
     }
 
     // === Expression Parsing (Pratt parser style) ===
 
     fn parse_expression(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        self.parse_or()
+        self.nested(Self::parse_or)
     }
 
     fn parse_or(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_and()?;
-
-        while self.check(&Token::Or) {
-            self.advance();
-            let right = self.parse_and()?;
-            let span = left.span.start..right.span.end;
-            left = Spanned::new(
-                Expr::Binary(BinaryOp::Or, Box::new(left), Box::new(right)),
-                span,
-            );
-        }
-
-        Ok(left)
+        self.binary_chain(Self::parse_and, |token| match token {
+            Token::Or => Some(BinaryOp::Or),
+            _ => None,
+        })
     }
 
     fn parse_and(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_equality()?;
-
-        while self.check(&Token::And) {
-            self.advance();
-            let right = self.parse_equality()?;
-            let span = left.span.start..right.span.end;
-            left = Spanned::new(
-                Expr::Binary(BinaryOp::And, Box::new(left), Box::new(right)),
-                span,
-            );
-        }
-
-        Ok(left)
+        self.binary_chain(Self::parse_equality, |token| match token {
+            Token::And => Some(BinaryOp::And),
+            _ => None,
+        })
     }
 
     fn parse_equality(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_comparison()?;
-
-        loop {
-            let op = match self.peek() {
-                Some(Token::EqualEqual) => BinaryOp::Eq,
-                Some(Token::BangEqual) => BinaryOp::NotEq,
-                _ => break,
-            };
-            self.advance();
-            let right = self.parse_comparison()?;
-            let span = left.span.start..right.span.end;
-            left = Spanned::new(Expr::Binary(op, Box::new(left), Box::new(right)), span);
-        }
-
-        Ok(left)
+        self.binary_chain(Self::parse_comparison, |token| match token {
+            Token::EqualEqual => Some(BinaryOp::Eq),
+            Token::BangEqual => Some(BinaryOp::NotEq),
+            _ => None,
+        })
     }
 
     fn parse_comparison(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_additive()?;
-
-        loop {
-            let op = match self.peek() {
-                Some(Token::Less) => BinaryOp::Lt,
-                Some(Token::Greater) => BinaryOp::Gt,
-                Some(Token::LessEqual) => BinaryOp::LtEq,
-                Some(Token::GreaterEqual) => BinaryOp::GtEq,
-                _ => break,
-            };
-            self.advance();
-            let right = self.parse_additive()?;
-            let span = left.span.start..right.span.end;
-            left = Spanned::new(Expr::Binary(op, Box::new(left), Box::new(right)), span);
-        }
-
-        Ok(left)
+        self.binary_chain(Self::parse_additive, |token| match token {
+            Token::Less => Some(BinaryOp::Lt),
+            Token::Greater => Some(BinaryOp::Gt),
+            Token::LessEqual => Some(BinaryOp::LtEq),
+            Token::GreaterEqual => Some(BinaryOp::GtEq),
+            _ => None,
+        })
     }
 
     fn parse_additive(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_multiplicative()?;
-
-        loop {
-            let op = match self.peek() {
-                Some(Token::Plus) => BinaryOp::Add,
-                Some(Token::Minus) => BinaryOp::Sub,
-                _ => break,
-            };
-            self.advance();
-            let right = self.parse_multiplicative()?;
-            let span = left.span.start..right.span.end;
-            left = Spanned::new(Expr::Binary(op, Box::new(left), Box::new(right)), span);
-        }
-
-        Ok(left)
+        self.binary_chain(Self::parse_multiplicative, |token| match token {
+            Token::Plus => Some(BinaryOp::Add),
+            Token::Minus => Some(BinaryOp::Sub),
+            _ => None,
+        })
     }
 
     fn parse_multiplicative(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut left = self.parse_unary()?;
-
-        loop {
-            let op = match self.peek() {
-                Some(Token::Star) => BinaryOp::Mul,
-                Some(Token::Slash) => BinaryOp::Div,
-                Some(Token::Percent) => BinaryOp::Mod,
-                _ => break,
-            };
-            self.advance();
-            let right = self.parse_unary()?;
-            let span = left.span.start..right.span.end;
-            left = Spanned::new(Expr::Binary(op, Box::new(left), Box::new(right)), span);
-        }
+        self.binary_chain(Self::parse_unary, |token| match token {
+            Token::Star => Some(BinaryOp::Mul),
+            Token::Slash => Some(BinaryOp::Div),
+            Token::Percent => Some(BinaryOp::Mod),
+            _ => None,
+        })
+    }
 
-        Ok(left)
+    /// Parse operands joined by the left-associative operators `operator`
+    /// picks out. Each operator nests the tree one level deeper, so a long
+    /// chain counts towards the depth limit as much as nesting does.
+    fn binary_chain(
+        &mut self,
+        operand: fn(&mut Self) -> Result<Spanned<Expr>, ParseError>,
+        operator: fn(&Token) -> Option<BinaryOp>,
+    ) -> Result<Spanned<Expr>, ParseError> {
+        self.chain(|parser| {
+            let mut left = operand(parser)?;
+            while let Some(op) = parser.peek().and_then(operator) {
+                parser.deeper()?;
+                parser.advance();
+                let right = operand(parser)?;
+                let span = left.span.start..right.span.end;
+                left = Spanned::new(Expr::Binary(op, Box::new(left), Box::new(right)), span);
+            }
+            Ok(left)
+        })
     }
 
     fn parse_unary(&mut self) -> Result<Spanned<Expr>, ParseError> {
@@ -1068,7 +1531,7 @@ fn parse_unary(&mut self) -> Result<Spanned<Expr>, ParseError> {
             Some(Token::Not) => {
                 let start = self.current_span().start;
                 self.advance();
-                let expr = self.parse_unary()?;
+                let expr = self.nested(Self::parse_unary)?;
                 let end = expr.span.end;
                 Ok(Spanned::new(
                     Expr::Unary(UnaryOp::Not, Box::new(expr)),
@@ -1078,7 +1541,7 @@ fn parse_unary(&mut self) -> Result<Spanned<Expr>, ParseError> {
             Some(Token::Minus) => {
                 let start = self.current_span().start;
                 self.advance();
-                let expr = self.parse_unary()?;
+                let expr = self.nested(Self::parse_unary)?;
                 let end = expr.span.end;
                 Ok(Spanned::new(
                     Expr::Unary(UnaryOp::Neg, Box::new(expr)),
@@ -1089,48 +1552,55 @@ fn parse_unary(&mut self) -> Result<Spanned<Expr>, ParseError> {
         }
     }
 
+    /// Parse an expression and what follows it; each index, call or unit
+    /// nests the tree one level deeper, as operators do
     fn parse_postfix(&mut self) -> Result<Spanned<Expr>, ParseError> {
-        let mut expr = self.parse_primary()?;
-
-        loop {
-            if self.check(&Token::LBracket) {
-                // Array/string indexing: expr[index]
-                self.advance();
-                let index = self.parse_expression()?;
-                self.expect(Token::RBracket)?;
-                let span = expr.span.start..self.previous_span().end;
-                expr = Spanned::new(Expr::Index(Box::new(expr), Box::new(index)), span);
-            } else if self.check(&Token::LParen) {
-                // Call expression: expr(args) - for calling closures/lambdas
-                // Only if expr is not an identifier (those are handled in parse_primary)
-                if matches!(expr.node, Expr::Identifier(_)) {
-                    break; // Let parse_primary handle named function calls
-                }
-                self.advance();
-                let mut args = Vec::new();
-                if !self.check(&Token::RParen) {
-                    args.push(self.parse_expression()?);
-                    while self.check(&Token::Comma) {
-                        self.advance();
-                        args.push(self.parse_expression()?);
+        self.chain(|parser| {
+            let mut expr = parser.parse_primary()?;
+
+            loop {
+                if parser.check(&Token::LBracket) {
+                    // Array/string indexing: expr[index]
+                    parser.deeper()?;
+                    parser.advance();
+                    let index = parser.parse_expression()?;
+                    parser.expect(Token::RBracket)?;
+                    let span = expr.span.start..parser.previous_span().end;
+                    expr = Spanned::new(Expr::Index(Box::new(expr), Box::new(index)), span);
+                } else if parser.check(&Token::LParen) {
+                    // Call expression: expr(args) - for calling closures/lambdas
+                    // Only if expr is not an identifier (those are handled in parse_primary)
+                    if matches!(expr.node, Expr::Identifier(_)) {
+                        break; // Let parse_primary handle named function calls
+                    }
+                    parser.deeper()?;
+                    parser.advance();
+                    let mut args = Vec::new();
+                    if !parser.check(&Token::RParen) {
+                        args.push(parser.parse_expression()?);
+                        while parser.check(&Token::Comma) {
+                            parser.advance();
+                            args.push(parser.parse_expression()?);
+                        }
                     }
+                    parser.expect(Token::RParen)?;
+                    let span = expr.span.start..parser.previous_span().end;
+                    expr = Spanned::new(Expr::CallExpr(Box::new(expr), args), span);
+                } else if parser.check(&Token::Measured) {
+                    // Unit measurement: expr measured in unit
+                    parser.deeper()?;
+                    parser.advance();
+                    parser.expect(Token::In)?;
+                    let unit = parser.expect_identifier()?;
+                    let span = expr.span.start..parser.previous_span().end;
+                    expr = Spanned::new(Expr::UnitMeasurement(Box::new(expr), unit), span);
+                } else {
+                    break;
                 }
-                self.expect(Token::RParen)?;
-                let span = expr.span.start..self.previous_span().end;
-                expr = Spanned::new(Expr::CallExpr(Box::new(expr), args), span);
-            } else if self.check(&Token::Measured) {
-                // Unit measurement: expr measured in unit
-                self.advance();
-                self.expect(Token::In)?;
-                let unit = self.expect_identifier()?;
-                let span = expr.span.start..self.previous_span().end;
-                expr = Spanned::new(Expr::UnitMeasurement(Box::new(expr), unit), span);
-            } else {
-                break;
             }
-        }
 
-        Ok(expr)
+            Ok(expr)
+        })
     }
 
     fn parse_primary(&mut self) -> Result<Spanned<Expr>, ParseError> {
@@ -1147,6 +1617,11 @@ fn parse_primary(&mut self) -> Result<Spanned<Expr>, ParseError> {
                 let end = self.previous_span().end;
                 Ok(Spanned::new(Expr::Literal(Literal::Float(n)), start..end))
             }
+            Some(Token::Decimal(n)) => {
+                self.advance();
+                let end = self.previous_span().end;
+                Ok(Spanned::new(Expr::Literal(Literal::Decimal(n)), start..end))
+            }
             Some(Token::String(s)) => {
                 self.advance();
                 let end = self.previous_span().end;
@@ -1202,8 +1677,24 @@ fn parse_primary(&mut self) -> Result<Spanned<Expr>, ParseError> {
                 self.expect(Token::RParen)?;
                 Ok(expr)
             }
-            Some(Token::Identifier(name)) => {
+            Some(Token::Identifier(mut name)) => {
                 self.advance();
+
+                // Qualified call into a module: std.string.split(...)
+                let qualified = self.check(&Token::Dot);
+                if qualified {
+                    let mut path = name.to_string();
+                    while self.check(&Token::Dot) {
+                        self.advance();
+                        path.push('.');
+                        path.push_str(&self.expect_member_name()?);
+                    }
+                    name = Symbol::intern(&path);
+                }
+                if qualified && !self.check(&Token::LParen) {
+                    self.expect(Token::LParen)?;
+                }
+
                 if self.check(&Token::LParen) {
                     self.advance();
 
@@ -1298,8 +1789,42 @@ fn parse_lambda_params(&mut self) -> Result<Vec<Parameter>, ParseError> {
 
     // === Helper Methods ===
 
+    /// The token at `pos`, if it is still held
+    fn token(&self, pos: usize) -> Option<&LexSpanned<Token>> {
+        self.tokens.get(pos.checked_sub(self.first)?)
+    }
+
+    /// Lex tokens from the stream, if there is one, up to the furthest the
+    /// parser looks ahead
+    fn fill(&mut self) {
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+        while self.first + self.tokens.len() <= self.pos + LOOKAHEAD {
+            match stream.next() {
+                Some(Ok(token)) => self.tokens.push_back(token),
+                Some(Err(error)) => {
+                    // The input ends where the lexer stopped
+                    let at = error.span.offset();
+                    self.tokens.push_back(LexSpanned::new(Token::Eof, at..at));
+                    self.lex_error = Some(error);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Let go of the tokens before the previous one, which the parser will
+    /// not go back to
+    fn release(&mut self) {
+        while self.first + 1 < self.pos {
+            self.tokens.pop_front();
+            self.first += 1;
+        }
+    }
+
     fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.pos).map(|t| &t.value)
+        self.token(self.pos).map(|t| &t.value)
     }
 
     fn check(&self, token: &Token) -> bool {
@@ -1313,8 +1838,9 @@ fn check(&self, token: &Token) -> bool {
     fn advance(&mut self) -> Option<&Token> {
         if !self.is_at_end() {
             self.pos += 1;
+            self.fill();
         }
-        self.tokens.get(self.pos - 1).map(|t| &t.value)
+        self.pos.checked_sub(1).and_then(|i| self.token(i)).map(|t| &t.value)
     }
 
     fn expect(&mut self, token: Token) -> Result<(), ParseError> {
@@ -1329,13 +1855,34 @@ fn expect(&mut self, token: Token) -> Result<(), ParseError> {
             Err(ParseError::UnexpectedToken {
                 expected: token.to_string(),
                 found,
-                src: self.source.to_string(),
+                src: self.source_file(),
                 span: self.current_span().into(),
             })
         }
     }
 
+    /// Identifier after a `.`, where keywords such as `repeat` are plain names
+    fn expect_member_name(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(token)
+                if !matches!(token, Token::Identifier(_) | Token::Eof)
+                    && token.to_string().chars().all(|c| c.is_ascii_alphabetic()) =>
+            {
+                let name = token.to_string();
+                self.advance();
+                Ok(name)
+            }
+            _ => self.expect_identifier(),
+        }
+    }
+
     fn expect_identifier(&mut self) -> Result<String, ParseError> {
+        self.expect_symbol().map(String::from)
+    }
+
+    /// An identifier as the interned symbol the lexer made, for names
+    /// looked up while the program runs
+    fn expect_symbol(&mut self) -> Result<Symbol, ParseError> {
         match self.peek().cloned() {
             Some(Token::Identifier(name)) => {
                 self.advance();
@@ -1349,7 +1896,7 @@ fn expect_identifier(&mut self) -> Result<String, ParseError> {
                 Err(ParseError::UnexpectedToken {
                     expected: "identifier".to_string(),
                     found,
-                    src: self.source.to_string(),
+                    src: self.source_file(),
                     span: self.current_span().into(),
                 })
             }
@@ -1370,7 +1917,7 @@ fn expect_string(&mut self) -> Result<String, ParseError> {
                 Err(ParseError::UnexpectedToken {
                     expected: "string".to_string(),
                     found,
-                    src: self.source.to_string(),
+                    src: self.source_file(),
                     span: self.current_span().into(),
                 })
             }
@@ -1382,27 +1929,56 @@ fn is_at_end(&self) -> bool {
     }
 
     fn current_span(&self) -> std::ops::Range<usize> {
-        self.tokens
-            .get(self.pos)
+        self.token(self.pos).map(|t| t.span.clone()).unwrap_or(0..0)
+    }
+
+    fn previous_span(&self) -> std::ops::Range<usize> {
+        self.pos
+            .checked_sub(1)
+            .and_then(|i| self.token(i))
             .map(|t| t.span.clone())
             .unwrap_or(0..0)
     }
 
-    fn previous_span(&self) -> std::ops::Range<usize> {
-        if self.pos > 0 {
-            self.tokens
-                .get(self.pos - 1)
-                .map(|t| t.span.clone())
-                .unwrap_or(0..0)
-        } else {
-            0..0
+    /// Parse something nested one level deeper than where the parser is,
+    /// failing cleanly rather than overflowing the stack past the limit
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        self.chain(|parser| {
+            parser.deeper()?;
+            parse(parser)
+        })
+    }
+
+    /// Parse something that goes [`Parser::deeper`] as it goes on, back at
+    /// the depth it started from once it is done
+    fn chain<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        let outer = self.depth;
+        let result = parse(self);
+        self.depth = outer;
+        result
+    }
+
+    /// Go one level deeper, failing past the limit
+    fn deeper(&mut self) -> Result<(), ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::TooDeep {
+                limit: self.max_depth,
+                src: self.source_file(),
+                span: self.current_span().into(),
+            });
         }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn source_file(&self) -> SourceFile {
+        self.file.get_or_init(|| SourceFile::new(self.source)).clone()
     }
 
     fn error(&self, message: &str) -> ParseError {
         ParseError::General {
             message: message.to_string(),
-            src: self.source.to_string(),
+            src: self.source_file(),
             span: self.current_span().into(),
         }
     }
@@ -1451,6 +2027,67 @@ fn test_parse_gratitude() {
         assert!(matches!(program.items[0], TopLevelItem::GratitudeDecl(_)));
     }
 
+    #[test]
+    fn test_parse_pragmas() {
+        let program = parse("#verbose on;\n#care off;\n#side quests off;").unwrap();
+        let pragmas: Vec<(PragmaDirective, bool)> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TopLevelItem::Pragma(p) => Some((p.directive, p.enabled)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            pragmas,
+            vec![
+                (PragmaDirective::Verbose, true),
+                (PragmaDirective::Care, false),
+                (PragmaDirective::SideQuests, false)
+            ]
+        );
+        assert!(parse("#verbose maybe;").is_err());
+        assert!(parse("#side quest off;").is_err());
+    }
+
+    #[test]
+    fn test_parse_limit_pragmas() {
+        let program = parse("#timeout 1.5 min;\n#maxmemory 64mb;").unwrap();
+        let pragmas: Vec<PragmaDirective> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TopLevelItem::Pragma(p) => Some(p.directive),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            pragmas,
+            vec![
+                PragmaDirective::Timeout(Duration::from_secs(90)),
+                PragmaDirective::MaxMemory(64 << 20)
+            ]
+        );
+        assert!(parse("#timeout 5;").is_err());
+        assert!(parse("#timeout 5 parsecs;").is_err());
+        assert!(parse("#maxmemory 1.5gb;").is_err());
+        assert!(parse("#maxmemory 64mb on;").is_err());
+    }
+
+    #[test]
+    fn test_parse_expect_block() {
+        let source = r#"expect "addition works" {
+            std.test.assertEqual(1 + 1, 2);
+        }"#;
+        let program = parse(source).unwrap();
+        let TopLevelItem::Expect(block) = &program.items[0] else {
+            panic!("expected an expect block");
+        };
+        assert_eq!(block.description, "addition works");
+        assert_eq!(block.body.len(), 1);
+        assert!(parse("expect missing_description { }").is_err());
+    }
+
     #[test]
     fn test_parse_consent_block() {
         let source = r#"only if okay "camera" {
@@ -1458,6 +2095,13 @@ fn test_parse_consent_block() {
         }"#;
         let program = parse(source).unwrap();
         assert!(matches!(program.items[0], TopLevelItem::ConsentBlock(_)));
+
+        let program = parse(r#"only if okay "camera" because "to scan the QR code" { }"#).unwrap();
+        let TopLevelItem::ConsentBlock(block) = &program.items[0] else {
+            panic!("expected a consent block");
+        };
+        assert_eq!(block.reason.as_deref(), Some("to scan the QR code"));
+        assert!(parse(r#"only if okay "camera" because { }"#).is_err());
     }
 
     #[test]
@@ -1479,4 +2123,278 @@ fn test_parse_expressions() {
         let program = parse(source).unwrap();
         assert!(matches!(program.items[0], TopLevelItem::Function(_)));
     }
+
+    #[test]
+    fn test_parse_function_type() {
+        let source = r#"to twice(f: (Int) -> Int, x: Int) -> () -> Int {
+            give back || -> f(f(x));
+        }"#;
+        let program = parse(source).unwrap();
+        let TopLevelItem::Function(func) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        assert!(matches!(
+            &func.params[0].ty,
+            Some(Type::Function(params, _)) if params.len() == 1
+        ));
+        assert!(matches!(
+            &func.return_type,
+            Some(Type::Function(params, _)) if params.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_parse_qualified_call() {
+        let program = parse(r#"to main() { std.string.split("a b", " "); }"#).unwrap();
+        let TopLevelItem::Function(func) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        let Statement::Expression(expr) = &func.body[0] else {
+            panic!("expected an expression statement");
+        };
+        assert!(matches!(&expr.node, Expr::Call(name, args) if name == "std.string.split" && args.len() == 2));
+
+        assert!(parse("to main() { remember x = std.string; }").is_err());
+    }
+
+    #[test]
+    fn test_parse_import_needs() {
+        let source = r#"use std.io renamed files must have read "data", write, crypto because "to save notes";"#;
+        let program = parse(source).unwrap();
+        let TopLevelItem::ModuleImport(import) = &program.items[0] else {
+            panic!("expected an import");
+        };
+        assert_eq!(import.rename.as_deref(), Some("files"));
+        let needs = import.needs.as_ref().unwrap();
+        let kinds: Vec<_> = needs.iter().map(|n| (n.kind.as_str(), n.value.as_deref())).collect();
+        assert_eq!(kinds, [("read", Some("data")), ("write", None), ("crypto", None)]);
+        assert_eq!(import.reason.as_deref(), Some("to save notes"));
+
+        let program = parse("use std.io;").unwrap();
+        assert!(matches!(&program.items[0], TopLevelItem::ModuleImport(i) if i.needs.is_none()));
+        assert!(parse("use std.io must have superuser;").is_err());
+        assert!(parse("use std.io must have;").is_err());
+    }
+
+    #[test]
+    fn test_parse_secret_var_decl() {
+        let program = parse("to main() { remember secret token = \"abc\"; remember secret = 1; }").unwrap();
+        let TopLevelItem::Function(func) = &program.items[0] else {
+            panic!("expected a function");
+        };
+        let decls: Vec<_> = func
+            .body
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::VarDecl(decl) => (decl.name.as_str(), decl.secret),
+                other => panic!("expected a declaration, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(decls, [("token", true), ("secret", false)]);
+    }
+
+    #[test]
+    fn test_parse_snippet() {
+        let source = "to double(n: Int) -> Int { give back n * 2; }\ndouble(21);";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let snippet = Parser::new(tokens, source).parse_snippet().unwrap();
+        assert_eq!(snippet.program.items.len(), 1);
+        assert!(snippet.statements.is_empty());
+        let expr = snippet.expr.unwrap();
+        assert!(matches!(expr.node, Expr::Call(ref name, _) if name == "double"));
+        assert_eq!(&source[expr.span], "double(21)");
+
+        for (source, items, statements, has_expr) in [
+            ("to f() {}", 1, 0, false),
+            ("1 + 2", 0, 0, true),
+            ("", 0, 0, false),
+            ("remember x = 1;", 0, 1, false),
+            ("to f() {}\nremember x = f(); x = 2; x + 1", 1, 2, true),
+            ("when true { print(1); }\nprint(2);", 0, 1, true),
+        ] {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            let snippet = Parser::new(tokens, source).parse_snippet().unwrap();
+            assert_eq!(
+                (snippet.program.items.len(), snippet.statements.len(), snippet.expr.is_some()),
+                (items, statements, has_expr),
+                "{}",
+                source
+            );
+        }
+        for source in ["1 + 2 to f() {}", "double(21) 3", "to f( {", "remember x = 1; to f() {}", "remember x = ;"] {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            assert!(Parser::new(tokens, source).parse_snippet().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_program_as_json() {
+        let program = parse("to double(n: Int) -> Int {\n    give back n * 2;\n}").unwrap();
+        let json = serde_json::to_value(&program).unwrap();
+        let function = &json["items"][0]["Function"];
+        assert_eq!(function["name"], "double");
+        assert_eq!(function["params"][0]["ty"]["Basic"], "Int");
+        let value = &function["body"][0]["Return"]["value"];
+        assert_eq!(value["node"]["Binary"][0], "Mul");
+        assert_eq!(value["node"]["Binary"][2]["node"]["Literal"]["Integer"], 2);
+        assert_eq!(value["span"], serde_json::json!({ "start": 41, "end": 46 }));
+    }
+
+    fn parse_all(source: &str) -> (Program, Vec<ParseError>) {
+        Parser::new(Lexer::new(source).tokenize().unwrap(), source).parse_all()
+    }
+
+    #[test]
+    fn test_recover_from_errors() {
+        let source = r#"to first() {
+            remember x = ;
+            remember y = 2;
+            when y > { print(y); } otherwise { print(0); }
+            give back y;
+        }
+
+        to broken( {
+        }
+
+        to last() {
+            remember z = 1 +;
+            give back z;
+        }"#;
+        let (program, errors) = parse_all(source);
+        assert_eq!(errors.len(), 4);
+        let offsets: Vec<usize> = errors
+            .iter()
+            .map(|e| match e {
+                ParseError::UnexpectedToken { span, .. }
+                | ParseError::General { span, .. }
+                | ParseError::Lex { span, .. }
+                | ParseError::TooDeep { span, .. } => span.offset(),
+                ParseError::UnexpectedEof => usize::MAX,
+            })
+            .collect();
+        assert!(offsets.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(&source[offsets[0]..offsets[0] + 1], ";");
+
+        let names: Vec<&str> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TopLevelItem::Function(f) => Some(f.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["first", "last"]);
+        if let TopLevelItem::Function(f) = &program.items[0] {
+            assert_eq!(f.body.len(), 2);
+        }
+
+        // Every error shares one copy of the source
+        let sources: Vec<&SourceFile> = errors
+            .iter()
+            .filter_map(|e| match e {
+                ParseError::UnexpectedToken { src, .. }
+                | ParseError::General { src, .. }
+                | ParseError::Lex { src, .. }
+                | ParseError::TooDeep { src, .. } => Some(src),
+                ParseError::UnexpectedEof => None,
+            })
+            .collect();
+        assert!(sources.windows(2).all(|pair| std::ptr::eq(pair[0].text(), pair[1].text())));
+
+        // The first error is the one `parse` gives
+        let first = parse(source).unwrap_err();
+        assert_eq!(first.to_string(), errors[0].to_string());
+    }
+
+    #[test]
+    fn test_recover_from_missing_punctuation() {
+        let (program, errors) = parse_all("to a() {\n remember x = 1;\n\nto b() { give back 2; }");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], ParseError::UnexpectedToken { expected, .. } if expected == "}"));
+        assert!(matches!(&program.items[..], [TopLevelItem::Function(f)] if f.name == "b"));
+
+        let (program, errors) = parse_all("give back 1;\nto main() {}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(program.items.len(), 1);
+
+        // A top-level `remember` is a global, not a stray statement
+        let (program, errors) = parse_all("remember x = 1;\nto main() {}");
+        assert!(errors.is_empty());
+        assert!(matches!(&program.items[..], [TopLevelItem::Global(g), TopLevelItem::Function(_)] if g.name == "x"));
+
+        // A missing `;` costs only the statement it is missing from
+        let (program, errors) = parse_all("to main() {\n print(1)\n remember x = 2;\n give back x;\n}");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&program.items[..], [TopLevelItem::Function(f)] if f.body.len() == 2));
+    }
+
+    #[test]
+    fn test_nesting_depth_is_limited() {
+        // Unoptimized frames are large, and test threads get less stack
+        // than a program's main thread, so give the limit the room it has there
+        std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(nesting_depth_is_limited)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    fn nesting_depth_is_limited() {
+        let too_deep = |errors: &[ParseError]| matches!(errors, [ParseError::TooDeep { limit, .. }] if *limit == DEFAULT_MAX_DEPTH);
+
+        // Far deeper than the stack would hold, yet a clean error
+        let depth = 10_000;
+        let parens = format!("to main() {{ remember x = {}1{}; }}", "(".repeat(depth), ")".repeat(depth));
+        assert!(too_deep(&parse_all(&parens).1));
+        let negations = format!("to main() {{ remember x = {}1; }}", "-".repeat(depth));
+        assert!(too_deep(&parse_all(&negations).1));
+        let blocks = format!("to main() {{ {}{} }}", "when true { ".repeat(depth), "}".repeat(depth));
+        assert!(too_deep(&parse_all(&blocks).1));
+        let types = format!("to main(x: {}Int{}) {{ }}", "[".repeat(depth), "]".repeat(depth));
+        assert!(too_deep(&parse_all(&types).1));
+        // Chains nest the tree they build as deeply
+        let sums = format!("to main() {{ remember x = 1{}; }}", " + 1".repeat(depth));
+        assert!(too_deep(&parse_all(&sums).1));
+        let indexes = format!("to main() {{ remember x = y{}; }}", "[0]".repeat(depth));
+        assert!(too_deep(&parse_all(&indexes).1));
+        let chain = format!("to main() {{ remember x = 1{}; }}", " * 2".repeat(100));
+        assert!(parse(&chain).is_ok());
+
+        // Recovery carries on after it
+        let (program, errors) = parse_all(&format!("{}\nto other() {{ }}", parens));
+        assert!(too_deep(&errors));
+        assert_eq!(program.items.len(), 2);
+
+        // Nesting within the limit parses, and the limit can be changed
+        let source = format!("to main() {{ give back {}1{}; }}", "(".repeat(50), ")".repeat(50));
+        assert!(parse(&source).is_ok());
+        let tokens = Lexer::new(&source).tokenize().unwrap();
+        let error = Parser::new(tokens, &source).with_max_depth(20).parse().unwrap_err();
+        assert!(matches!(error, ParseError::TooDeep { limit: 20, .. }));
+        assert_eq!(error.code(), "WOKE-E0103");
+    }
+
+    #[test]
+    fn test_parse_from_lexer() {
+        let source: String = (0..200)
+            .map(|i| format!("to f{}(n: Int) -> Int {{\n    give back n * {};\n}}\n", i, i))
+            .collect();
+        let mut parser = Parser::from_lexer(Lexer::new(&source));
+        let (program, errors) = parser.parse_all();
+        assert!(errors.is_empty());
+        let batch = Parser::new(Lexer::new(&source).tokenize().unwrap(), &source).parse().unwrap();
+        assert_eq!(format!("{:?}", program), format!("{:?}", batch));
+        // Only the tokens around the last item were still held
+        assert!(parser.tokens.len() < 20, "{} tokens held", parser.tokens.len());
+
+        // A lexer error ends the input, after the errors found before it
+        let source = "to a() {\n    remember x = ;\n}\nto b() {\n    print($);\n}\n";
+        let (program, errors) = Parser::from_lexer(Lexer::new(source)).parse_all();
+        assert_eq!(program.items.len(), 1);
+        assert!(matches!(&errors[..], [ParseError::General { .. }, ParseError::Lex { .. }]), "{:?}", errors);
+        assert_eq!(errors[1].code(), "WOKE-E0001");
+        let error = Parser::from_lexer(Lexer::new("to main() { $ }")).parse().unwrap_err();
+        assert!(matches!(error, ParseError::Lex { span, .. } if span.offset() == 12));
+    }
 }
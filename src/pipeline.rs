@@ -0,0 +1,1171 @@
+//! Compilation Pipeline
+//!
+//! Lexing, parsing, loading modules, type checking and running a program
+//! in one place, so the CLI, the REPL, the debugger, the C API, the
+//! playground and embedders go through the same steps and report failures
+//! the same way:
+//!
+//! ```text
+//! Pipeline::new().engine(Engine::Vm).capabilities(registry).run(source)
+//! ```
+//!
+//! Every step that fails gives [`Diagnostics`]: what went wrong, at which
+//! stage, with its `woke explain` code and where in the source, when that
+//! is known. [`Pipeline::lint`] gives the same for things a program may not
+//! mean, as warnings, unless [`Pipeline::deny_warnings`] makes them errors.
+
+use crate::ast::{Expr, Program, Span, Spanned};
+use crate::interpreter::{
+    BindingError, HostFunction, Interpreter, NativeFunction, RuntimeError, SideQuestOutcome, Value,
+};
+use crate::lexer::{Lexer, SourceFile, Spanned as LexSpanned, Token};
+use crate::lint::Warning;
+use crate::parser::{ParseError, Parser, Snippet};
+use crate::profile::Profiler;
+use crate::project::{ModuleLoader, ProjectError};
+use crate::security::{CapabilityRegistry, ConsentMode, Limits};
+use crate::stdlib::StdlibError;
+use crate::typechecker::{CheckedProgram, TypeChecker, TypeError};
+use crate::vm::{BytecodeCompiler, CompileError, CompiledProgram, Optimizer, VirtualMachine};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// What runs a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Engine {
+    /// Walk the AST
+    #[default]
+    Interpreter,
+    /// Compile to bytecode and run it on the virtual machine
+    Vm,
+}
+
+impl Engine {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Engine::Interpreter => "interpreter",
+            Engine::Vm => "vm",
+        }
+    }
+}
+
+/// The step of the pipeline a diagnostic comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Lex,
+    Parse,
+    Load,
+    Typecheck,
+    Compile,
+    Run,
+    Lint,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Lex => "Lexer error",
+            Stage::Parse => "Parse error",
+            Stage::Load => "Module error",
+            Stage::Typecheck => "Type error",
+            Stage::Compile => "Compile error",
+            Stage::Run => "Runtime error",
+            Stage::Lint => "Lint error",
+        })
+    }
+}
+
+/// How much a diagnostic matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Stops the pipeline
+    Error,
+    /// Worth a look, but the program still runs
+    Warning,
+    /// More about another diagnostic
+    Note,
+}
+
+/// One thing that went wrong, shown as `Parse error [WOKE-E0100]: ...`,
+/// or one that may have, shown as `Warning [WOKE-W0001]: ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub stage: Stage,
+    /// The error's code in the `woke explain` catalog, if it has one
+    pub code: Option<&'static str>,
+    pub message: String,
+    /// Where in the source, as byte offsets, if known
+    pub span: Option<Span>,
+    /// What to try instead, such as the name a misspelled one may be
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    fn new(stage: Stage, code: Option<&'static str>, message: impl ToString, span: Option<Span>) -> Self {
+        Self {
+            severity: Severity::Error,
+            stage,
+            code,
+            message: message.to_string(),
+            span,
+            help: None,
+        }
+    }
+
+    fn type_error(e: &TypeError) -> Self {
+        Self {
+            help: e.help(),
+            ..Self::new(Stage::Typecheck, Some(e.code()), e, e.span())
+        }
+    }
+
+    fn runtime(e: RuntimeError) -> Self {
+        Self {
+            help: e.help(),
+            ..Self::new(Stage::Run, Some(e.code()), &e, None)
+        }
+    }
+
+    /// What the diagnostic is, as it is shown before its code: the failed
+    /// stage for an error
+    pub fn kind(&self) -> String {
+        match self.severity {
+            Severity::Error => self.stage.to_string(),
+            Severity::Warning => "Warning".to_string(),
+            Severity::Note => "Note".to_string(),
+        }
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::new(Stage::Lint, Some(warning.lint.code()), warning, Some(warning.span.clone()))
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{} [{}]: {}", self.kind(), code, self.message),
+            None => write!(f, "{}: {}", self.kind(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Everything a failed step reported, at least one diagnostic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    /// The first diagnostic, which stopped the pipeline
+    pub fn first(&self) -> &Diagnostic {
+        &self.0[0]
+    }
+
+    /// The stage that failed
+    pub fn stage(&self) -> Stage {
+        self.first().stage
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.0.iter()
+    }
+}
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self(vec![diagnostic])
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self.0.iter().map(|d| d.to_string()).collect();
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+pub type Result<T> = std::result::Result<T, Diagnostics>;
+
+fn module_error(e: ProjectError) -> Diagnostics {
+    let code = match &e {
+        ProjectError::Syntax { code, .. } => Some(*code),
+        _ => None,
+    };
+    Diagnostic::new(Stage::Load, code, e, None).into()
+}
+
+fn type_errors(errors: Vec<TypeError>) -> Diagnostics {
+    Diagnostics(errors.iter().map(Diagnostic::type_error).collect())
+}
+
+/// Where the first label of a lexer or parser error points
+fn labelled_span(error: &dyn miette::Diagnostic) -> Option<Span> {
+    let label = error.labels()?.next()?;
+    Some(label.offset()..label.offset() + label.len())
+}
+
+/// Program output shared by every run of a pipeline
+#[derive(Clone)]
+struct SharedOutput(Rc<RefCell<Box<dyn Write>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// The steps from source to a program's result, and how to take them
+pub struct Pipeline {
+    typecheck: bool,
+    deny_warnings: bool,
+    engine: Engine,
+    optimize: bool,
+    capabilities: Option<CapabilityRegistry>,
+    consent_mode: Option<ConsentMode>,
+    sandbox: Option<PathBuf>,
+    module_name: Option<String>,
+    file_name: Option<String>,
+    verbose: bool,
+    skip_side_quests: bool,
+    limits: Limits,
+    output: Option<SharedOutput>,
+    /// Where `ask` reads answers from, kept between runs
+    input: Option<Box<dyn BufRead>>,
+    natives: Vec<NativeFunction>,
+    profiler: Option<Profiler>,
+    /// How the side quests of the last run went
+    side_quests: Vec<SideQuestOutcome>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pipeline {
+    /// Type-check, then run on the interpreter
+    pub fn new() -> Self {
+        Self {
+            typecheck: true,
+            deny_warnings: false,
+            engine: Engine::Interpreter,
+            optimize: false,
+            capabilities: None,
+            consent_mode: None,
+            sandbox: None,
+            module_name: None,
+            file_name: None,
+            verbose: false,
+            skip_side_quests: false,
+            limits: Limits::default(),
+            output: None,
+            input: None,
+            natives: Vec::new(),
+            profiler: None,
+            side_quests: Vec::new(),
+        }
+    }
+
+    /// Whether to type-check before running, as `woke run` does
+    pub fn typecheck(mut self, typecheck: bool) -> Self {
+        self.typecheck = typecheck;
+        self
+    }
+
+    /// Whether lint warnings fail the program as errors would, stopping
+    /// it before it runs
+    pub fn deny_warnings(mut self, deny: bool) -> Self {
+        self.deny_warnings = deny;
+        self
+    }
+
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Whether to optimize bytecode before the VM runs it
+    pub fn optimize(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
+    }
+
+    /// Run with capabilities the host has set up, e.g. with policies and a
+    /// consent store applied; [`Pipeline::capabilities_mut`] gives them
+    /// back after a run
+    pub fn capabilities(mut self, capabilities: CapabilityRegistry) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    /// Answer consent blocks and superpower requests the way `mode` says
+    pub fn consent_mode(mut self, mode: ConsentMode) -> Self {
+        self.consent_mode = Some(mode);
+        self
+    }
+
+    /// Run confined to `root`, as `Interpreter::with_sandbox`
+    pub fn sandbox(mut self, root: &Path) -> Self {
+        self.sandbox = Some(root.to_path_buf());
+        self
+    }
+
+    /// Name the program's module, as `Interpreter::with_module_name`
+    pub fn module_name(mut self, name: &str) -> Self {
+        self.module_name = Some(name.to_string());
+        self
+    }
+
+    /// Name the file the source is read from, for parse errors to show
+    pub fn file_name(mut self, name: &str) -> Self {
+        self.file_name = Some(name.to_string());
+        self
+    }
+
+    /// Trace execution and show `debug` log records
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Skip the program's side quests, as `#side quests off` does
+    pub fn skip_side_quests(mut self, skip: bool) -> Self {
+        self.skip_side_quests = skip;
+        self
+    }
+
+    /// Stop a run that takes longer or holds more memory than `limits`, on
+    /// either engine; where the program's `#timeout` and `#maxmemory`
+    /// pragmas set lower limits, those hold
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Send program output to `output` instead of stdout, on either engine
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Some(SharedOutput(Rc::new(RefCell::new(Box::new(output)))));
+        self
+    }
+
+    /// Read the answers to `ask`, `askNumber` and `askYesNo` from `input`
+    /// instead of stdin, on either engine; answers a run does not read are
+    /// left for the next
+    pub fn input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Time each call to a WokeLang function with `profiler`, on either
+    /// engine
+    pub fn profiler(mut self, profiler: &Profiler) -> Self {
+        self.profiler = Some(profiler.clone());
+        self
+    }
+
+    /// Make a Rust function callable from programs, as
+    /// `Interpreter::register_native`
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl FnMut(&[Value]) -> std::result::Result<Value, StdlibError> + 'static,
+    ) -> &mut NativeFunction {
+        self.add_native(NativeFunction::new(name, arity, func))
+    }
+
+    /// Make a Rust closure callable from programs under a WokeLang
+    /// signature, as `Interpreter::bind`; calls to it are type-checked
+    pub fn bind<Args>(
+        &mut self,
+        signature: &str,
+        func: impl HostFunction<Args>,
+    ) -> std::result::Result<&mut NativeFunction, BindingError> {
+        let native = crate::interpreter::Signature::parse(signature)?.bind(func)?;
+        Ok(self.add_native(native))
+    }
+
+    /// Add the functions of a loaded plugin
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn plugin(mut self, plugin: &crate::ffi::Plugin) -> Self {
+        for native in plugin.natives() {
+            self.add_native(native);
+        }
+        self
+    }
+
+    fn add_native(&mut self, native: NativeFunction) -> &mut NativeFunction {
+        self.natives.retain(|n| n.name() != native.name());
+        self.natives.push(native);
+        self.natives.last_mut().expect("just added")
+    }
+
+    /// How each side quest of the last run went, on either engine
+    pub fn side_quests(&self) -> &[SideQuestOutcome] {
+        &self.side_quests
+    }
+
+    /// The capabilities runs are given, and the grants and consent store
+    /// they left behind
+    pub fn capabilities_mut(&mut self) -> &mut CapabilityRegistry {
+        self.capabilities.get_or_insert_with(CapabilityRegistry::new)
+    }
+
+    /// Lex a program into its tokens, each with where it is
+    pub fn tokenize(&self, source: &str) -> Result<Vec<LexSpanned<Token>>> {
+        let file = self.source_file(source);
+        Ok(Lexer::for_file(&file)
+            .tokenize()
+            .map_err(|e| Diagnostic::new(Stage::Lex, Some(e.code()), &e, labelled_span(&e)))?)
+    }
+
+    /// Lex and parse a whole program, reporting every parse error in it,
+    /// and the lexer error that ended it, if one did
+    pub fn parse(&self, source: &str) -> Result<Program> {
+        let file = self.source_file(source);
+        let (program, errors) = Parser::from_lexer(Lexer::for_file(&file)).parse_all();
+        if errors.is_empty() {
+            return Ok(program);
+        }
+        Err(Diagnostics(
+            errors
+                .iter()
+                .map(|e| {
+                    let stage = match e {
+                        ParseError::Lex { .. } => Stage::Lex,
+                        _ => Stage::Parse,
+                    };
+                    Diagnostic::new(stage, Some(e.code()), e, labelled_span(e))
+                })
+                .collect(),
+        ))
+    }
+
+    /// Lex and parse source typed at a REPL: definitions, statements, and
+    /// a final expression
+    pub fn parse_snippet(&self, source: &str) -> Result<Snippet> {
+        let file = self.source_file(source);
+        let tokens = self.tokenize(source)?;
+        Ok(Parser::for_file(tokens, &file)
+            .parse_snippet()
+            .map_err(|e| Diagnostic::new(Stage::Parse, Some(e.code()), &e, labelled_span(&e)))?)
+    }
+
+    /// Lex and parse one expression
+    pub fn parse_expression(&self, source: &str) -> Result<Spanned<Expr>> {
+        let file = self.source_file(source);
+        let tokens = self.tokenize(source)?;
+        Ok(Parser::for_file(tokens, &file)
+            .parse_single_expression()
+            .map_err(|e| Diagnostic::new(Stage::Parse, Some(e.code()), &e, labelled_span(&e)))?)
+    }
+
+    /// Merge into the program parsed from `file` the modules it imports,
+    /// from the project `file` is in, as `woke run` does
+    pub fn load(&self, file: &Path, program: Program) -> Result<Program> {
+        let mut loader = ModuleLoader::for_program(file).map_err(module_error)?;
+        self.load_with(&mut loader, file, program)
+    }
+
+    /// Merge in the modules a program imports with a loader the host
+    /// keeps, which then knows which files they came from
+    pub fn load_with(&self, loader: &mut ModuleLoader, file: &Path, program: Program) -> Result<Program> {
+        loader.load(file, program).map_err(module_error)
+    }
+
+    fn source_file(&self, source: &str) -> SourceFile {
+        match &self.file_name {
+            Some(name) => SourceFile::named(name, source),
+            None => SourceFile::new(source),
+        }
+    }
+
+    /// A type checker that knows the pipeline's host functions
+    pub fn type_checker(&self) -> TypeChecker {
+        TypeChecker::with_natives(&self.natives)
+    }
+
+    /// Type-check a program, reporting every error found, giving back
+    /// what the checker found out about it for the steps after
+    pub fn check<'p>(&self, program: &'p Program) -> Result<CheckedProgram<'p>> {
+        self.type_checker().check(program).map_err(type_errors)
+    }
+
+    /// Lint a program, giving back its warnings; with
+    /// [`Pipeline::deny_warnings`], any warning is an error
+    pub fn lint(&self, program: &Program) -> Result<Vec<Diagnostic>> {
+        let warnings: Vec<Diagnostic> = crate::lint::lint(program).iter().map(Diagnostic::from).collect();
+        if self.deny_warnings && !warnings.is_empty() {
+            return Err(Diagnostics(
+                warnings
+                    .into_iter()
+                    .map(|warning| Diagnostic {
+                        severity: Severity::Error,
+                        ..warning
+                    })
+                    .collect(),
+            ));
+        }
+        Ok(warnings)
+    }
+
+    /// Compile a program to bytecode for the VM
+    pub fn compile(&self, program: &Program) -> Result<CompiledProgram> {
+        self.optimized(BytecodeCompiler::new().compile(program))
+    }
+
+    /// Compile a program the type checker passed to bytecode for the VM
+    pub fn compile_checked(&self, checked: &CheckedProgram) -> Result<CompiledProgram> {
+        self.optimized(BytecodeCompiler::new().compile_checked(checked))
+    }
+
+    fn optimized(&self, compiled: std::result::Result<CompiledProgram, CompileError>) -> Result<CompiledProgram> {
+        let mut compiled = compiled.map_err(|e| Diagnostic::new(Stage::Compile, None, e, None))?;
+        if self.optimize {
+            Optimizer::new().optimize(&mut compiled);
+        }
+        Ok(compiled)
+    }
+
+    /// Parse, type-check if asked to, and run a program, giving back the
+    /// value its `main` gives back
+    pub fn run(&mut self, source: &str) -> Result<Value> {
+        let program = self.parse(source)?;
+        self.run_program(&program)
+    }
+
+    /// Type-check if asked to, and run a program already parsed, e.g. with
+    /// the modules it imports merged in
+    pub fn run_program(&mut self, program: &Program) -> Result<Value> {
+        if self.typecheck {
+            let checked = self.check(program)?;
+            return self.run_checked(&checked);
+        }
+        self.run_with(program, None)
+    }
+
+    /// Run a program the type checker passed, with what it found out about
+    /// it, giving back the value its `main` gives back
+    pub fn run_checked(&mut self, checked: &CheckedProgram) -> Result<Value> {
+        self.run_with(checked.program(), Some(checked))
+    }
+
+    fn run_with(&mut self, program: &Program, checked: Option<&CheckedProgram>) -> Result<Value> {
+        if self.deny_warnings {
+            self.lint(program)?;
+        }
+        if self.engine == Engine::Vm {
+            let compiled = match checked {
+                Some(checked) => self.compile_checked(checked)?,
+                None => self.compile(program)?,
+            };
+            return self.run_compiled(compiled);
+        }
+        let natives = std::mem::take(&mut self.natives);
+        let capabilities = self.capabilities.take().unwrap_or_default();
+        let mut interpreter = self.interpreter(capabilities, natives);
+        interpreter.swap_input(self.input.take());
+        let result = match checked {
+            Some(checked) => interpreter.run_checked(checked),
+            None => interpreter.run_main(program),
+        };
+        let result = result.map_err(Diagnostic::runtime);
+        if let Some(profiler) = &self.profiler {
+            profiler.unwind();
+        }
+        self.side_quests = interpreter.take_side_quests();
+        self.natives = interpreter.take_natives();
+        self.input = interpreter.swap_input(None);
+        self.capabilities = Some(std::mem::take(interpreter.capabilities_mut()));
+        Ok(result?)
+    }
+
+    /// Run a program compiled before, e.g. kept in a build cache, on the
+    /// VM whatever the engine, giving back the value its `main` gives back
+    pub fn run_compiled(&mut self, compiled: CompiledProgram) -> Result<Value> {
+        if self.verbose {
+            crate::stdlib::log::set_min_level(crate::stdlib::log::Level::Debug);
+        }
+        let capabilities = self.capabilities.take().unwrap_or_default();
+        let mut vm = VirtualMachine::new(compiled)
+            .with_capabilities(capabilities)
+            .with_limits(self.limits)
+            .with_skip_side_quests(self.skip_side_quests);
+        if let Some(name) = &self.module_name {
+            vm = vm.with_module_name(name);
+        }
+        if let Some(root) = &self.sandbox {
+            vm = vm.with_sandbox(root);
+        }
+        if let Some(profiler) = &self.profiler {
+            vm = vm.with_profiler(profiler);
+        }
+        if let Some(mode) = self.consent_mode {
+            vm.capabilities_mut().set_consent_mode(mode);
+        }
+        for native in std::mem::take(&mut self.natives) {
+            vm.add_native(native);
+        }
+        if let Some(output) = &self.output {
+            vm = vm.with_output(output.clone());
+        }
+        vm.swap_input(self.input.take());
+        let result = vm.run().map_err(|e| Diagnostic::new(Stage::Run, e.code, e.message, None));
+        if let Some(profiler) = &self.profiler {
+            profiler.unwind();
+        }
+        self.side_quests = vm.take_side_quests();
+        self.natives = vm.take_natives();
+        self.input = vm.swap_input(None);
+        self.capabilities = Some(std::mem::take(vm.capabilities_mut()));
+        Ok(result?)
+    }
+
+    /// Parse, type-check if asked to, and run a whole program on an
+    /// interpreter the host set up, with the host functions it knows
+    pub fn run_on(&self, interpreter: &mut Interpreter, source: &str) -> Result<Value> {
+        let program = self.parse(source)?;
+        if self.typecheck {
+            let checked = interpreter.type_checker().check(&program).map_err(type_errors)?;
+            return Ok(interpreter.run_checked(&checked).map_err(Diagnostic::runtime)?);
+        }
+        Ok(interpreter.run_main(&program).map_err(Diagnostic::runtime)?)
+    }
+
+    /// Run source on an interpreter the host keeps between calls, as the
+    /// REPL does: its definitions, then its statements, then the value of
+    /// its final expression, if any. It is not type-checked, as the
+    /// interpreter's earlier definitions are not known; see
+    /// [`Pipeline::check_snippet`].
+    pub fn evaluate(&self, interpreter: &mut Interpreter, source: &str) -> Result<Value> {
+        let snippet = self.parse_snippet(source)?;
+        self.run_snippet(interpreter, &snippet)
+    }
+
+    /// Type-check a snippet with a type checker kept between snippets,
+    /// which remembers the definitions and variables of the ones before
+    pub fn check_snippet(&self, checker: &mut TypeChecker, snippet: &Snippet) -> Result<()> {
+        let diagnostic = |e: TypeError| Diagnostic::type_error(&e);
+        checker.check_program(&snippet.program).map_err(diagnostic)?;
+        checker.check_statements(&snippet.statements).map_err(diagnostic)?;
+        if let Some(expr) = &snippet.expr {
+            checker.infer_expression(expr).map_err(diagnostic)?;
+        }
+        Ok(())
+    }
+
+    /// Run a snippet already parsed, as [`Pipeline::evaluate`] does
+    pub fn run_snippet(&self, interpreter: &mut Interpreter, snippet: &Snippet) -> Result<Value> {
+        if !snippet.program.items.is_empty() {
+            interpreter.run(&snippet.program).map_err(Diagnostic::runtime)?;
+        }
+        interpreter.exec_snippet(&snippet.statements).map_err(Diagnostic::runtime)?;
+        match &snippet.expr {
+            Some(expr) => Ok(interpreter.eval_snippet(expr).map_err(Diagnostic::runtime)?),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    /// A new interpreter set up as the pipeline says
+    fn interpreter(&self, capabilities: CapabilityRegistry, natives: Vec<NativeFunction>) -> Interpreter {
+        let mut interpreter = Interpreter::new()
+            .with_capabilities(capabilities)
+            .with_verbose(self.verbose)
+            .with_skip_side_quests(self.skip_side_quests)
+            .with_limits(self.limits);
+        if let Some(name) = &self.module_name {
+            interpreter = interpreter.with_module_name(name);
+        }
+        if let Some(mode) = self.consent_mode {
+            interpreter = interpreter.with_consent_mode(mode);
+        }
+        if let Some(root) = &self.sandbox {
+            interpreter = interpreter.with_sandbox(root);
+        }
+        if let Some(output) = &self.output {
+            interpreter = interpreter.with_output(output.clone());
+        }
+        if let Some(profiler) = &self.profiler {
+            interpreter = interpreter.with_debugger(profiler.clone());
+        }
+        for native in natives {
+            interpreter.add_native(native);
+        }
+        interpreter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{OutputBuffer, SideQuestStatus};
+    use crate::security::Capability;
+    use crate::stdlib::log;
+
+    #[test]
+    fn test_run_on_both_engines() {
+        let source = "to main() → Int { give back 6 * 7; }";
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let result = Pipeline::new().engine(engine).run(source);
+            assert_eq!(result.unwrap(), Value::Int(42), "{}", engine.name());
+        }
+    }
+
+    #[test]
+    fn test_print_on_both_engines() {
+        let source = r#"
+            to main() {
+                print("a", 1, true);
+                print();
+                remember nothing = print("x");
+                print(nothing);
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let output = OutputBuffer::new();
+            Pipeline::new().engine(engine).output(output.clone()).run(source).unwrap();
+            assert_eq!(output.contents(), "a 1 true\n\nx\n()\n", "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn test_lambdas_on_both_engines() {
+        let source = r#"
+            to main() -> String {
+                remember step = 10;
+                remember add = |n| -> n + step;
+                remember adder = |a| { give back |b| -> a + b; };
+                remember plusTwo = adder(2);
+                remember evens = std.array.filter([1, 2, 3, 4], |n| -> n % 2 == 0);
+                give back toString(std.array.map([1, 2], add)) + " " + toString(plusTwo(5)) + " " + toString(evens);
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let value = Pipeline::new().engine(engine).run(source).unwrap();
+            assert_eq!(value.to_string(), "[11, 12] 7 [2, 4]", "{:?}", engine);
+
+            let err = Pipeline::new()
+                .engine(engine)
+                .run("to main() { give back std.array.map([1, 2], 0); }")
+                .unwrap_err();
+            assert!(err.to_string().contains("expected (T) -> U, got Int"), "{:?}: {}", engine, err);
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut pipeline = Pipeline::new();
+        pipeline.bind("to double(n: Int) → Int", |n: i64| n * 2).unwrap();
+        let source = "to main() {\n    remember n = double(\"two\");\n}";
+        let diagnostics = pipeline.run(source).unwrap_err();
+        let first = diagnostics.first();
+        assert_eq!(first.stage, Stage::Typecheck);
+        assert_eq!(first.code, Some("WOKE-E0208"));
+        assert_eq!(first.span.clone().map(|span| &source[span]), Some("\"two\""));
+        assert_eq!(pipeline.run("to main() → Int { give back double(21); }").unwrap(), Value::Int(42));
+
+        let source = "to main() {\n    remember n = hasSuperpower(\"crypto\") + 1;\n}";
+        assert_eq!(Pipeline::new().run(source).unwrap_err().stage(), Stage::Typecheck);
+        assert_eq!(Pipeline::new().typecheck(false).run(source).unwrap_err().stage(), Stage::Run);
+
+        let diagnostics = Pipeline::new().run("to main( {").unwrap_err();
+        assert_eq!(diagnostics.stage(), Stage::Parse);
+        assert!(diagnostics.to_string().starts_with("Parse error"));
+
+        assert_eq!(pipeline.tokenize("1 + 2").unwrap().len(), 4, "with the end of input");
+        assert_eq!(pipeline.tokenize("\"open").unwrap_err().stage(), Stage::Lex);
+        assert!(pipeline.parse_expression("1 + 2").is_ok());
+        assert_eq!(pipeline.parse_expression("1 +").unwrap_err().stage(), Stage::Parse);
+    }
+
+    #[test]
+    fn test_module_diagnostics() {
+        let dir = std::env::temp_dir().join("wokelang_test_pipeline_modules");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("shapes")).unwrap();
+        std::fs::write(dir.join("shapes/square.woke"), "to area(n: Int) -> Int { give back n * n; }\n").unwrap();
+        std::fs::write(dir.join("shapes/broken.woke"), "to area( {\n").unwrap();
+        let file = dir.join("main.woke");
+        let pipeline = Pipeline::new();
+
+        let program = pipeline.parse("use shapes.square;\nto main() -> Int { give back square.area(3); }").unwrap();
+        let program = pipeline.load(&file, program).unwrap();
+        assert_eq!(pipeline.check(&program).map(|_| ()), Ok(()));
+
+        let program = pipeline.parse("use shapes.broken;\nto main() {}").unwrap();
+        let diagnostics = pipeline.load(&file, program).unwrap_err();
+        assert_eq!(diagnostics.stage(), Stage::Load);
+        assert_eq!(diagnostics.first().code, Some("WOKE-E0100"));
+    }
+
+    #[test]
+    fn test_side_quests_on_both_engines() {
+        let source = r#"
+            remember visits = 0;
+            side quest tidyUp { print("tidying up after", visits); }
+            side quest warmCache { remember n = 1 / 0; print("never"); }
+            to main() → Int { visits = 3; print("main done"); give back 1; }
+            side quest last { when true { give back 0; } print("not reached"); }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let output = OutputBuffer::new();
+            let mut pipeline = Pipeline::new().engine(engine).typecheck(false).output(output.clone());
+            assert_eq!(pipeline.run(source).unwrap(), Value::Int(1), "{}", engine.name());
+            assert_eq!(output.contents(), "main done\ntidying up after 3\n", "{}", engine.name());
+            let outcomes: Vec<String> = pipeline.side_quests().iter().map(|o| o.to_string()).collect();
+            assert_eq!(outcomes, ["tidyUp: done", "warmCache: failed: Division by zero", "last: done"]);
+            assert!(matches!(
+                pipeline.side_quests()[1].status,
+                SideQuestStatus::Failed { code: Some("WOKE-E0303"), .. }
+            ));
+
+            // Skipped by the host or the pragma
+            let mut pipeline = Pipeline::new().engine(engine).typecheck(false).skip_side_quests(true);
+            assert_eq!(pipeline.run(source).unwrap(), Value::Int(1));
+            assert_eq!(pipeline.side_quests()[0].to_string(), "tidyUp: skipped");
+            let output = OutputBuffer::new();
+            let mut pipeline = Pipeline::new().engine(engine).typecheck(false).output(output.clone());
+            pipeline.run(&format!("#side quests off;\n{}", source)).unwrap();
+            assert_eq!(output.contents(), "main done\n");
+            assert!(pipeline.side_quests().iter().all(|o| matches!(o.status, SideQuestStatus::Skipped)));
+        }
+    }
+
+    #[test]
+    fn test_input_on_both_engines() {
+        let source = "to main() → Bool { remember name = ask(\"Name? \"); give back askYesNo(name); }";
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let mut pipeline = Pipeline::new().engine(engine).output(Vec::new()).input(&b"Sure? \nyes\nOK? \nno\n"[..]);
+            assert_eq!(pipeline.run(source).unwrap(), Value::Bool(true));
+            // Answers the first run did not read are left for the next
+            assert_eq!(pipeline.run(source).unwrap(), Value::Bool(false));
+            assert!(pipeline.run(source).is_err());
+        }
+    }
+
+    #[test]
+    fn test_superpower_scopes_on_both_engines() {
+        let source = r#"
+            use std.env must have env "WOKE_SCOPE_TEST";
+
+            to readHome() {
+                remember home = std.env.get("HOME");
+            }
+
+            to main() {
+                remember declared = env.get("WOKE_SCOPE_TEST");
+                readHome();
+                remember path = std.env.get("PATH");
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            // An import is limited to what it declares
+            let err = Pipeline::new()
+                .engine(engine)
+                .capabilities(CapabilityRegistry::permissive())
+                .run(source)
+                .unwrap_err();
+            assert!(err.to_string().contains("HOME"), "{:?}: {}", engine, err);
+
+            // A grant to one function does not cover the others
+            let mut capabilities = CapabilityRegistry::new();
+            capabilities.set_consent_mode(ConsentMode::Deny);
+            capabilities.grant("tools:readHome", Capability::Environment(None), "test");
+            capabilities.grant("tools:main", Capability::Environment(Some("WOKE_SCOPE_TEST".to_string())), "test");
+            let source = source.replace(" must have env \"WOKE_SCOPE_TEST\"", "");
+            let err = Pipeline::new()
+                .engine(engine)
+                .module_name("tools")
+                .capabilities(capabilities)
+                .run(&source)
+                .unwrap_err();
+            assert!(err.to_string().contains("PATH"), "{:?}: {}", engine, err);
+        }
+    }
+
+    #[test]
+    fn test_decimals_on_both_engines() {
+        let source = r#"
+            to main() -> [Decimal] {
+                remember price = 19.99d;
+                remember tax = std.decimal.round(price * 0.0825d, 2);
+                give back [0.10d + 0.20d, price * 3 + tax, 10d / 4 - 7.5d % 2, -price];
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let value = Pipeline::new().engine(engine).run(source).unwrap();
+            assert_eq!(value.to_string(), "[0.30, 61.62, 1.00, -19.99]", "{:?}", engine);
+            let same = "to main() -> [Bool] { give back [0.3d == 0.30d, 1.5d != 1, 2 < 2.01d, 1.50d >= 1.5d]; }";
+            let value = Pipeline::new().engine(engine).run(same).unwrap();
+            assert_eq!(value.to_string(), "[true, true, true, true]", "{:?}", engine);
+            let err = Pipeline::new().engine(engine).typecheck(false).run("to main() { give back 1.5d + 0.5; }");
+            assert!(err.unwrap_err().to_string().contains("Cannot mix Decimal and Float"), "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn test_appending_on_both_engines() {
+        let source = r#"
+            to main() -> [String] {
+                remember text = "";
+                repeat 3 times {
+                    text = text + "ab";
+                }
+                remember before = text;
+                text = text + "!";
+                remember total = 1;
+                total = total + 2;
+                give back [text, before, toString(total)];
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let value = Pipeline::new().engine(engine).run(source).unwrap();
+            assert_eq!(value.to_string(), "[ababab!, ababab, 3]", "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn test_structured_errors_on_both_engines() {
+        let source = r#"
+            use std.map;
+            to lookup(id: Int) -> Result<Int, Int> {
+                when id == 7 {
+                    give back Okay(id * 6);
+                }
+                when id == 8 {
+                    give back Oops(404);
+                }
+                give back Oops(500);
+            }
+            to describe(id: Int) -> String {
+                decide based on lookup(id) {
+                    Okay(n) -> { give back "found " + toString(n); }
+                    Oops(404) -> { give back "missing"; }
+                    Oops(code) -> { give back "failed with " + toString(code); }
+                }
+                give back "unreachable";
+            }
+            to main() -> [String] {
+                give back [
+                    describe(7),
+                    describe(8),
+                    describe(9),
+                    toString(getError(lookup(9)) + 1),
+                    toString(Oops(map.set(map.new(), "code", 404))),
+                ];
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let value = Pipeline::new().engine(engine).run(source).unwrap();
+            assert_eq!(
+                value.to_string(),
+                "[found 42, missing, failed with 500, 501, Oops({code: 404})]",
+                "{:?}",
+                engine
+            );
+        }
+    }
+
+    #[test]
+    fn test_secrets_on_both_engines() {
+        let source = r#"
+            use std.encoding;
+            use std.log;
+            use std.string;
+            to main() {
+                remember secret token = "sk-live-0123456789";
+                remember secret pin = "42";
+                log.info(string.toUpper(token));
+                log.info(encoding.base64Encode(token));
+                log.info("pin " + pin + " and " + token[0]);
+                print(string.toUpper(pin));
+                string.format("{:" + string.toUpper(token) + "}", 1);
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let records = Rc::new(RefCell::new(Vec::new()));
+            let sink = Rc::clone(&records);
+            log::set_sink(move |record| sink.borrow_mut().push(record.message.clone()));
+            let output = OutputBuffer::new();
+            let err = Pipeline::new().engine(engine).output(output.clone()).run(source).unwrap_err();
+            log::reset_sink();
+
+            assert_eq!(*records.borrow(), ["***", "***", "***"], "{:?}", engine);
+            assert_eq!(output.contents(), "42\n", "{:?}", engine);
+            assert!(err.to_string().ends_with("invalid format spec ***"), "{:?}: {}", engine, err);
+        }
+    }
+
+    #[test]
+    fn test_characters_on_both_engines() {
+        let source = r#"
+            use std.string;
+            to main() -> [String] {
+                remember word = "café 🇳🇿";
+                give back [
+                    toString(len(word)),
+                    word[3],
+                    string.charAt(word, 6),
+                    toString(string.codePointAt(word, 0)),
+                    toString(len(string.graphemes(word))),
+                ];
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let value = Pipeline::new().engine(engine).run(source).unwrap();
+            assert_eq!(value.to_string(), "[8, e, 🇳, 99, 6]", "{:?}", engine);
+
+            for (index, error) in [("8", "Index out of bounds: 8"), ("-1", "Negative index not allowed: -1")] {
+                let source = format!("to main() -> String {{ give back \"café 🇳🇿\"[{}]; }}", index);
+                let err = Pipeline::new().engine(engine).run(&source).unwrap_err();
+                assert!(err.to_string().contains(error), "{:?}: {}", engine, err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_globals_on_both_engines() {
+        let source = r#"
+            remember count = 0;
+            remember step = count + 2;
+
+            to bump() {
+                count = count + step;
+            }
+
+            to main() -> Int {
+                bump();
+                bump();
+                give back count;
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let value = Pipeline::new().engine(engine).run(source).unwrap();
+            assert_eq!(value.to_string(), "4", "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn test_profiling_on_both_engines() {
+        let source = r#"
+            to fib(n: Int) -> Int {
+                when n < 2 {
+                    give back n;
+                }
+                give back fib(n - 1) + fib(n - 2);
+            }
+            to main() -> Int {
+                give back fib(5) + fib(2);
+            }
+        "#;
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let profiler = Profiler::new();
+            let value = Pipeline::new().engine(engine).profiler(&profiler).run(source).unwrap();
+            assert_eq!(value, Value::Int(6));
+            let mut calls: Vec<(String, u64)> = profiler.functions().into_iter().map(|f| (f.name, f.calls)).collect();
+            calls.sort();
+            assert_eq!(calls, [("fib".to_string(), 18), ("main".to_string(), 1)], "{:?}", engine);
+            assert!(profiler.folded().lines().any(|line| line.starts_with("main;fib;fib;fib;fib ")));
+
+            // Calls a failing program leaves open are still counted
+            let profiler = Profiler::new();
+            let failing = "to divide(n: Int) -> Int { give back 1 / n; } to main() { divide(0); }";
+            assert!(Pipeline::new().engine(engine).profiler(&profiler).run(failing).is_err());
+            assert_eq!(profiler.functions().len(), 2, "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn test_exit_on_both_engines() {
+        let source = "side quest tidyUp { print(1); } to stop() { exit(3); } to main() { stop(); print(2); }";
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let mut pipeline = Pipeline::new().engine(engine).output(Vec::new());
+            assert_eq!(pipeline.run(source).unwrap(), Value::Int(3));
+            assert!(pipeline.side_quests().is_empty());
+            let err = Pipeline::new().engine(engine).typecheck(false).run("to main() { exit(\"3\"); }").unwrap_err();
+            assert!(err.to_string().contains("exit() requires an Int exit code"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_error_codes_on_both_engines() {
+        let cases = [
+            ("to main() { remember x = 0; give back 1 / x; }", "WOKE-E0303"),
+            ("to main() { give back 1 - \"a\"; }", "WOKE-E0302"),
+            ("use std.math; to main() { give back math.sqrt(\"x\"); }", "WOKE-E0302"),
+            ("#maxmemory 1mb; to main() { remember s = \"x\"; repeat 30 times { s = s + s; } }", "WOKE-E0314"),
+        ];
+        for (source, code) in cases {
+            for engine in [Engine::Interpreter, Engine::Vm] {
+                let err = Pipeline::new().engine(engine).typecheck(false).run(source).unwrap_err();
+                assert_eq!(err.first().code, Some(code), "{} on {}: {}", source, engine.name(), err);
+                assert!(err.to_string().starts_with(&format!("Runtime error [{}]: ", code)), "{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_host_limits() {
+        let source = "to main() { remember n = 0; repeat 100000000 times { n = n + 1; } }";
+        let limits = Limits { timeout: Some(std::time::Duration::from_millis(10)), max_memory: None };
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let err = Pipeline::new().engine(engine).limits(limits).run(source).unwrap_err();
+            assert!(err.to_string().contains("Timed out after 10ms"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_timeout_cuts_blocking_calls_short() {
+        let source = "use std.time;\n#timeout 100ms;\nto main() { time.sleep(5000); }";
+        for engine in [Engine::Interpreter, Engine::Vm] {
+            let started = std::time::Instant::now();
+            let err = Pipeline::new().engine(engine).run(source).unwrap_err();
+            assert!(err.to_string().contains("Timed out after 100ms"), "{}: {}", engine.name(), err);
+            assert!(started.elapsed() < std::time::Duration::from_secs(2), "{}", engine.name());
+        }
+    }
+
+    #[test]
+    fn test_warnings() {
+        let source = "to main() → Int {\n    remember spare = 1;\n    give back 2;\n}";
+        let pipeline = Pipeline::new();
+        let warnings = pipeline.lint(&pipeline.parse(source).unwrap()).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+        assert_eq!(warnings[0].span.clone().map(|span| &source[span]), Some("remember spare = 1;"));
+        assert!(warnings[0].to_string().starts_with("Warning [WOKE-W0001]: `spare`"));
+        assert_eq!(Pipeline::new().run(source).unwrap(), Value::Int(2));
+
+        let diagnostics = Pipeline::new().deny_warnings(true).run(source).unwrap_err();
+        assert_eq!(diagnostics.stage(), Stage::Lint);
+        assert_eq!(diagnostics.first().severity, Severity::Error);
+        assert!(diagnostics.to_string().starts_with("Lint error [WOKE-W0001]"));
+    }
+}
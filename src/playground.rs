@@ -0,0 +1,175 @@
+//! Browser playground bindings
+//!
+//! Exposes the lexer, parser, type checker and interpreter to JavaScript via
+//! `wasm-bindgen`, so a web page can run WokeLang entirely client-side. Every
+//! export takes source text and returns a JSON string.
+//!
+//! Consent blocks are denied in the playground, since there is no terminal
+//! to prompt on.
+
+use crate::interpreter::OutputBuffer;
+use crate::lexer::Lexer;
+use crate::pipeline::Pipeline;
+use crate::security::ConsentMode;
+use miette::Diagnostic;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Run a program and return
+/// `{"output": "...", "error": null | "...", "code": null | "WOKE-E..."}`
+#[wasm_bindgen]
+pub fn run_source(source: &str) -> String {
+    let output = OutputBuffer::new();
+    let error = Pipeline::new()
+        .typecheck(false)
+        .output(output.clone())
+        .consent_mode(ConsentMode::Deny)
+        .run(source)
+        .err()
+        .map(|e| e.first().clone());
+
+    let (error, code) = match error {
+        Some(e) => (json_string(&e.message), e.code.map_or("null".to_string(), json_string)),
+        None => ("null".to_string(), "null".to_string()),
+    };
+    format!(
+        "{{\"output\":{},\"error\":{},\"code\":{}}}",
+        json_string(&output.contents()),
+        error,
+        code
+    )
+}
+
+/// Tokenize a program into `[{"kind", "text", "start", "end"}, ...]`, or
+/// `{"error": {...}}` if it contains an invalid character
+#[wasm_bindgen]
+pub fn tokenize_json(source: &str) -> String {
+    match Lexer::new(source).tokenize() {
+        Ok(tokens) => {
+            let entries: Vec<String> = tokens
+                .iter()
+                .map(|token| {
+                    // Variant name without any payload, e.g. `Identifier`
+                    let kind = format!("{:?}", token.value);
+                    let kind = kind.split('(').next().unwrap_or_default();
+                    format!(
+                        "{{\"kind\":{},\"text\":{},\"start\":{},\"end\":{}}}",
+                        json_string(kind),
+                        json_string(&source[token.span.clone()]),
+                        token.span.start,
+                        token.span.end
+                    )
+                })
+                .collect();
+            format!("[{}]", entries.join(","))
+        }
+        Err(e) => format!("{{\"error\":{}}}", diagnostic_json(&e)),
+    }
+}
+
+/// Parse and type-check a program, returning
+/// `{"ok": bool, "diagnostics": [{"message", "code", "start", "end"}, ...]}`
+///
+/// `start` and `end` are byte offsets, or null when the error has no location.
+#[wasm_bindgen]
+pub fn check_source(source: &str) -> String {
+    let pipeline = Pipeline::new();
+    match pipeline.parse(source).and_then(|program| pipeline.check(&program).map(|_| ())) {
+        Ok(()) => "{\"ok\":true,\"diagnostics\":[]}".to_string(),
+        Err(diagnostics) => {
+            let entries: Vec<String> = diagnostics
+                .iter()
+                .map(|d| located_json(&d.message, d.code, d.span.as_ref().map(|span| (span.start, span.end))))
+                .collect();
+            format!("{{\"ok\":false,\"diagnostics\":[{}]}}", entries.join(","))
+        }
+    }
+}
+
+/// JSON object for an error, located by its first label
+fn diagnostic_json(error: &dyn Diagnostic) -> String {
+    let span = error
+        .labels()
+        .and_then(|mut labels| labels.next())
+        .map(|label| (label.offset(), label.offset() + label.len()));
+    let code = error.code().map(|code| code.to_string());
+    located_json(&error.to_string(), code.as_deref(), span)
+}
+
+fn located_json(message: &str, code: Option<&str>, span: Option<(usize, usize)>) -> String {
+    let (start, end) = match span {
+        Some((start, end)) => (start.to_string(), end.to_string()),
+        None => ("null".to_string(), "null".to_string()),
+    };
+    format!(
+        "{{\"message\":{},\"code\":{},\"start\":{},\"end\":{}}}",
+        json_string(message),
+        code.map_or("null".to_string(), json_string),
+        start,
+        end
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_source() {
+        let result = run_source("to main() { print(\"hi\\tthere\"); print(1 + 2); }");
+        assert_eq!(result, r#"{"output":"hi\tthere\n3\n","error":null,"code":null}"#);
+
+        let result = run_source("to main() { print(missing); }");
+        assert_eq!(result, r#"{"output":"","error":"Undefined variable: missing","code":"WOKE-E0300"}"#);
+    }
+
+    #[test]
+    fn test_run_source_denies_consent() {
+        let result = run_source(r#"to main() { only if okay "camera" { print("on"); } print("off"); }"#);
+        assert_eq!(result, r#"{"output":"off\n","error":null,"code":null}"#);
+    }
+
+    #[test]
+    fn test_tokenize_json() {
+        assert_eq!(
+            tokenize_json("remember x = 1;"),
+            r#"[{"kind":"Remember","text":"remember","start":0,"end":8},{"kind":"Identifier","text":"x","start":9,"end":10},{"kind":"Equal","text":"=","start":11,"end":12},{"kind":"Integer","text":"1","start":13,"end":14},{"kind":"Semicolon","text":";","start":14,"end":15},{"kind":"Eof","text":"","start":15,"end":15}]"#
+        );
+        assert_eq!(
+            tokenize_json("remember ` = 1;"),
+            r#"{"error":{"message":"Unexpected character","code":"WOKE-E0001","start":9,"end":10}}"#
+        );
+    }
+
+    #[test]
+    fn test_check_source() {
+        assert_eq!(
+            check_source("to main() { remember x = 1; }"),
+            r#"{"ok":true,"diagnostics":[]}"#
+        );
+        assert!(check_source("to main( {").starts_with(r#"{"ok":false,"diagnostics":[{"message":"#));
+        let both = check_source("to main() { remember x = ; }\nto helper( {}");
+        assert_eq!(both.matches(r#""code":"WOKE-E01"#).count(), 2);
+        assert_eq!(
+            check_source("to main() { remember x = y; }"),
+            r#"{"ok":false,"diagnostics":[{"message":"Undefined variable: y","code":"WOKE-E0201","start":null,"end":null}]}"#
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Function Profiling for WokeLang
+//!
+//! A [`Profiler`] is told as each call to a WokeLang function starts and
+//! ends: by the interpreter through its [`Debugger`] hooks, and by the VM
+//! as it pushes and pops call frames. Afterwards it reports, for each
+//! function, how often it was called, the time spent in it with and
+//! without the functions it called, and, when the host counts them, the
+//! heap allocations made. `woke run --profile` shows the report, and
+//! `--folded` writes the calls as folded stacks, which flamegraph tools
+//! read.
+//!
+//! A recursive function's total time counts only its outermost call, so
+//! the time is not counted again for every call under it.
+
+use crate::ast::Span;
+use crate::interpreter::{DebugAction, DebugView, Debugger};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// How one function did over a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    /// Time from the start of each call to its end
+    pub total: Duration,
+    /// Time in the function's own statements, not in the functions it
+    /// called
+    pub self_time: Duration,
+    /// Heap allocations made by the function's own statements, if the
+    /// host counts them
+    pub allocations: Option<u64>,
+}
+
+/// A call that has started and not ended
+#[derive(Debug)]
+struct OpenCall {
+    name: String,
+    started: Instant,
+    /// Time spent in calls it made
+    in_callees: Duration,
+    allocations_at_start: u64,
+    /// Allocations made in calls it made
+    allocations_in_callees: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    open: Vec<OpenCall>,
+    functions: HashMap<String, FunctionProfile>,
+    /// Own time by call stack, outermost call first
+    stacks: BTreeMap<Vec<String>, Duration>,
+}
+
+/// Times calls to WokeLang functions; clones share what they record
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    state: Rc<RefCell<State>>,
+    /// Heap allocations made so far by the process
+    allocations: Option<fn() -> u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the heap allocations each function makes with `counter`,
+    /// which gives how many the process has made so far
+    pub fn with_allocation_counter(mut self, counter: fn() -> u64) -> Self {
+        self.allocations = Some(counter);
+        self
+    }
+
+    fn allocations_so_far(&self) -> u64 {
+        self.allocations.map_or(0, |counter| counter())
+    }
+
+    /// A call to `function` has started
+    pub fn enter(&self, function: &str) {
+        let mut state = self.state.borrow_mut();
+        state.open.push(OpenCall {
+            name: function.to_string(),
+            started: Instant::now(),
+            in_callees: Duration::ZERO,
+            allocations_at_start: 0,
+            allocations_in_callees: 0,
+        });
+        // Counted after the profiler's own allocations for the call
+        if let Some(call) = state.open.last_mut() {
+            call.allocations_at_start = self.allocations_so_far();
+        }
+    }
+
+    /// The innermost call has ended, however it ended
+    pub fn leave(&self) {
+        let allocations_now = self.allocations_so_far();
+        let mut state = self.state.borrow_mut();
+        let State { open, functions, stacks } = &mut *state;
+        let Some(call) = open.pop() else {
+            return;
+        };
+        let elapsed = call.started.elapsed();
+        let allocations = allocations_now.saturating_sub(call.allocations_at_start);
+        let self_time = elapsed.saturating_sub(call.in_callees);
+        if let Some(caller) = open.last_mut() {
+            caller.in_callees += elapsed;
+            caller.allocations_in_callees += allocations;
+        }
+
+        let recursive = open.iter().any(|outer| outer.name == call.name);
+        let profile = functions.entry(call.name.clone()).or_insert_with(|| FunctionProfile {
+            name: call.name.clone(),
+            calls: 0,
+            total: Duration::ZERO,
+            self_time: Duration::ZERO,
+            allocations: self.allocations.map(|_| 0),
+        });
+        profile.calls += 1;
+        if !recursive {
+            profile.total += elapsed;
+        }
+        profile.self_time += self_time;
+        if let Some(count) = &mut profile.allocations {
+            *count += allocations.saturating_sub(call.allocations_in_callees);
+        }
+
+        let mut stack: Vec<String> = open.iter().map(|outer| outer.name.clone()).collect();
+        stack.push(call.name);
+        *stacks.entry(stack).or_default() += self_time;
+    }
+
+    /// End every call still open, as when a program stops part way
+    pub fn unwind(&self) {
+        while !self.state.borrow().open.is_empty() {
+            self.leave();
+        }
+    }
+
+    /// Each function called, most time in its own statements first
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions: Vec<FunctionProfile> = self.state.borrow().functions.values().cloned().collect();
+        functions.sort_by(|a, b| b.self_time.cmp(&a.self_time).then_with(|| a.name.cmp(&b.name)));
+        functions
+    }
+
+    /// The calls as folded stacks, one line per call stack, e.g.
+    /// `main;fib;fib 1200`: the functions from the outermost in, then the
+    /// microseconds spent in the innermost one's own statements
+    pub fn folded(&self) -> String {
+        let mut folded = String::new();
+        for (stack, time) in &self.state.borrow().stacks {
+            let _ = writeln!(folded, "{} {}", stack.join(";"), time.as_micros());
+        }
+        folded
+    }
+}
+
+impl Debugger for Profiler {
+    fn before_statement(&mut self, _span: &Span, _view: &DebugView<'_>) -> DebugAction {
+        DebugAction::Continue
+    }
+
+    fn enter_function(&mut self, function: &str) {
+        self.enter(function);
+    }
+
+    fn leave_function(&mut self) {
+        self.leave();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let profiler = Profiler::new();
+        profiler.enter("main");
+        for _ in 0..2 {
+            profiler.enter("fib");
+            profiler.enter("fib");
+            profiler.leave();
+            profiler.leave();
+        }
+        profiler.enter("greet");
+        profiler.unwind();
+
+        let functions = profiler.functions();
+        let calls: HashMap<&str, u64> = functions.iter().map(|f| (f.name.as_str(), f.calls)).collect();
+        assert_eq!(calls, HashMap::from([("main", 1), ("fib", 4), ("greet", 1)]));
+        let main = functions.iter().find(|f| f.name == "main").unwrap();
+        assert!(main.total >= main.self_time);
+        assert!(functions.iter().all(|f| f.allocations.is_none()));
+
+        let folded = profiler.folded();
+        let stacks: Vec<&str> = folded.lines().map(|line| line.rsplit_once(' ').unwrap().0).collect();
+        assert_eq!(stacks, ["main", "main;fib", "main;fib;fib", "main;greet"]);
+    }
+
+    #[test]
+    fn test_allocations() {
+        thread_local! {
+            static COUNT: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+        }
+        fn allocate(n: u64) {
+            COUNT.with(|count| count.set(count.get() + n));
+        }
+        let profiler = Profiler::new().with_allocation_counter(|| COUNT.with(|count| count.get()));
+        profiler.enter("main");
+        allocate(2);
+        profiler.enter("helper");
+        allocate(5);
+        profiler.leave();
+        profiler.leave();
+
+        let allocations: HashMap<String, Option<u64>> =
+            profiler.functions().into_iter().map(|f| (f.name, f.allocations)).collect();
+        assert_eq!(allocations["main"], Some(2));
+        assert_eq!(allocations["helper"], Some(5));
+    }
+}
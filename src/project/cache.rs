@@ -0,0 +1,232 @@
+//! Incremental Compilation Cache
+//!
+//! What `woke check` and `woke run` work out about a project is kept under
+//! its `target/.woke-cache`, so that doing it again is skipped until the
+//! files it came from change:
+//!
+//! ```text
+//! target/.woke-cache/
+//! ├── ast/<hash>.json        parsed module, by the hash of its source
+//! ├── check/<key>            a program that passed its type check and lints
+//! └── bytecode/<key>.json    a program compiled for the VM
+//! ```
+//!
+//! A module's key is a hash of its source and of the keys of the modules it
+//! imports, which [`ModuleLoader::key`](super::ModuleLoader::key) works
+//! out, so changing a module changes the key of every module depending on
+//! it and nothing else. Every hash also covers the sources of what parses,
+//! checks and compiles a module, so a build that does any of those
+//! differently never reads an older build's entries, even within one
+//! version. Entries are only ever added; a stale one is never asked for
+//! again. A program outside any project is not cached.
+
+use super::{find_root, ProjectError, Result};
+use crate::ast::Program;
+use crate::vm::CompiledProgram;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Where in a project the cache is
+pub const CACHE_DIR: &str = "target/.woke-cache";
+
+/// A hex digest of `sha`
+pub(crate) fn hex(sha: Sha256) -> String {
+    sha.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The sources of every stage whose results are cached
+const FORMAT_SOURCES: &[&str] = &[
+    include_str!("../ast/mod.rs"),
+    include_str!("../lexer/mod.rs"),
+    include_str!("../lexer/source.rs"),
+    include_str!("../lexer/symbol.rs"),
+    include_str!("../lexer/token.rs"),
+    include_str!("../parser/mod.rs"),
+    include_str!("../typechecker/mod.rs"),
+    include_str!("../typechecker/checked.rs"),
+    include_str!("../lint.rs"),
+    include_str!("../vm/bytecode.rs"),
+    include_str!("../vm/compiler.rs"),
+    include_str!("../vm/optimizer.rs"),
+];
+
+/// A hash of this version of WokeLang and of [`FORMAT_SOURCES`], worked
+/// out once
+fn format_hash() -> &'static str {
+    static FORMAT: OnceLock<String> = OnceLock::new();
+    FORMAT.get_or_init(|| {
+        let mut sha = Sha256::new();
+        sha.update(env!("CARGO_PKG_VERSION"));
+        for source in FORMAT_SOURCES {
+            sha.update([0]);
+            sha.update(source);
+        }
+        hex(sha)
+    })
+}
+
+/// The directory a program file is in
+fn program_dir(file: &Path) -> &Path {
+    match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Parsed modules, checked programs and compiled bytecode, by hash
+#[derive(Debug, Clone)]
+pub struct BuildCache {
+    dir: PathBuf,
+}
+
+impl BuildCache {
+    /// The cache of the project `file` is in, if it is in one
+    pub fn for_project(file: &Path) -> Option<Self> {
+        find_root(program_dir(file)).map(|root| Self::with_dir(root.join(CACHE_DIR)))
+    }
+
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// A hash of a module's `source` and of this build's cache format
+    pub fn hash(source: &str) -> String {
+        let mut sha = Sha256::new();
+        sha.update(format_hash());
+        sha.update([0]);
+        sha.update(source);
+        hex(sha)
+    }
+
+    /// The module parsed from `source` before, if it was
+    pub fn ast(&self, source: &str) -> Option<Program> {
+        let json = fs::read(self.ast_path(source)).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    /// Remember the module parsed from `source`
+    pub fn store_ast(&self, source: &str, program: &Program) -> Result<()> {
+        let json = serde_json::to_vec(program).map_err(|e| self.invalid(e))?;
+        self.write(&self.ast_path(source), &json)
+    }
+
+    /// Whether the program with this key passed its check before
+    pub fn passed(&self, key: &str) -> bool {
+        self.dir.join("check").join(key).is_file()
+    }
+
+    /// Remember that the program with this key passed its check
+    pub fn record_pass(&self, key: &str) -> Result<()> {
+        self.write(&self.dir.join("check").join(key), b"")
+    }
+
+    /// The program with this key as it was compiled before, if it was
+    pub fn bytecode(&self, key: &str) -> Option<CompiledProgram> {
+        let json = fs::read(self.bytecode_path(key)).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    /// Remember the program with this key as compiled; a program holding
+    /// values bytecode cannot keep, such as a closure, is not remembered
+    pub fn store_bytecode(&self, key: &str, compiled: &CompiledProgram) -> Result<()> {
+        let json = serde_json::to_vec(compiled).map_err(|e| self.invalid(e))?;
+        self.write(&self.bytecode_path(key), &json)
+    }
+
+    fn ast_path(&self, source: &str) -> PathBuf {
+        self.dir.join("ast").join(Self::hash(source)).with_extension("json")
+    }
+
+    fn bytecode_path(&self, key: &str) -> PathBuf {
+        self.dir.join("bytecode").join(key).with_extension("json")
+    }
+
+    fn invalid(&self, e: serde_json::Error) -> ProjectError {
+        ProjectError::Invalid {
+            path: self.dir.display().to_string(),
+            message: e.to_string(),
+        }
+    }
+
+    /// Write an entry whole, through a temporary file, so that a reader
+    /// never sees half of one
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        let io = |path: &Path, e: std::io::Error| ProjectError::Io {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        };
+        let dir = path.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(dir).map_err(|e| io(dir, e))?;
+        let partial = path.with_extension(format!("{}.partial", std::process::id()));
+        fs::write(&partial, contents).map_err(|e| io(&partial, e))?;
+        fs::rename(&partial, path).map_err(|e| io(path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Value;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::project::{ModuleLoader, MANIFEST_FILE};
+    use crate::vm::{BytecodeCompiler, VirtualMachine};
+
+    #[test]
+    fn test_build_cache() {
+        let dir = std::env::temp_dir().join("wokelang_test_build_cache");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("shapes")).unwrap();
+        fs::write(dir.join(MANIFEST_FILE), "[package]\nname = \"shapes\"\nversion = \"0.1.0\"\n").unwrap();
+        fs::write(dir.join("shapes/square.woke"), "to area(n: Int) -> Int { give back n * n; }\n").unwrap();
+        fs::write(dir.join("shapes/label.woke"), "to label() -> String { give back \"square\"; }\n").unwrap();
+
+        let file = dir.join("main.woke");
+        let source = "use shapes.square;\nuse shapes.label;\nto main() -> Int { give back square.area(3) + len(label.label()); }\n";
+        let cache = BuildCache::for_project(&file).unwrap();
+        assert_eq!(cache.dir(), fs::canonicalize(&dir).unwrap().join(CACHE_DIR));
+        assert!(BuildCache::for_project(&std::env::temp_dir().join("loose.woke")).is_none());
+
+        let load = |source: &str| {
+            let mut loader = ModuleLoader::new().with_root(&dir).with_cache(cache.clone());
+            let program = Parser::new(Lexer::new(source).tokenize().unwrap(), source).parse().unwrap();
+            let program = loader.load(&file, program).unwrap();
+            (program, loader.key(&file, source))
+        };
+
+        // Modules parsed once are kept, and come back the same
+        let (program, key) = load(source);
+        let square = fs::read_to_string(dir.join("shapes/square.woke")).unwrap();
+        assert!(cache.ast(&square).is_some());
+        let (again, same) = load(source);
+        assert_eq!(same, key);
+        assert_eq!(format!("{:?}", again), format!("{:?}", program));
+
+        assert!(!cache.passed(&key));
+        cache.record_pass(&key).unwrap();
+        assert!(cache.passed(&key));
+
+        let compiled = BytecodeCompiler::new().compile(&program).unwrap();
+        cache.store_bytecode(&key, &compiled).unwrap();
+        let cached = cache.bytecode(&key).unwrap();
+        assert_eq!(cached.fingerprint(), compiled.fingerprint());
+        assert_eq!(VirtualMachine::new(cached).run().unwrap(), Value::Int(15));
+
+        // Changing a module changes the key of the program importing it,
+        // and changing it back gives the old key
+        fs::write(dir.join("shapes/label.woke"), "to label() -> String { give back \"box\"; }\n").unwrap();
+        let (_, changed) = load(source);
+        assert_ne!(changed, key);
+        assert!(!cache.passed(&changed));
+        fs::write(dir.join("shapes/label.woke"), "to label() -> String { give back \"square\"; }\n").unwrap();
+        assert_eq!(load(source).1, key);
+        assert_ne!(load(&format!("{}\n", source)).1, key);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,75 @@
+//! Checking Whole Projects
+//!
+//! `woke check` takes a directory, or a project's manifest, as well as a
+//! single program, and type-checks every `.woke` file under it with the
+//! modules each imports.
+//!
+//! A program that passed is remembered in the project's build cache (see
+//! [`cache`](super::cache)) by the key of its contents and those of its
+//! modules, so checking it again is skipped until one of them changes.
+
+use super::{ProjectError, Result, MANIFEST_FILE};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The programs `path` names: a `.woke` file itself, or every `.woke` file
+/// under a directory, or under the project a manifest is in, in path
+/// order. Hidden directories are skipped.
+pub fn program_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = if path.file_name().is_some_and(|name| name == MANIFEST_FILE) {
+        match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    } else if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        return Ok(vec![path.to_path_buf()]);
+    };
+
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let entries = fs::read_dir(dir).map_err(|e| ProjectError::Read {
+            path: dir.display().to_string(),
+            message: e.to_string(),
+        })?;
+        let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|e| e.path())).collect();
+        paths.sort();
+        for path in paths {
+            let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if path.is_dir() && !hidden {
+                walk(&path, files)?;
+            } else if path.is_file() && path.extension().is_some_and(|ext| ext == "woke") {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    walk(&dir, &mut files)?;
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_whole_project() {
+        let dir = std::env::temp_dir().join("wokelang_test_check");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        fs::create_dir_all(dir.join(".hidden")).unwrap();
+        for file in ["main.woke", "tests/main_test.woke", ".hidden/skipped.woke"] {
+            fs::write(dir.join(file), "to main() {}\n").unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join(MANIFEST_FILE), "").unwrap();
+
+        let expected = vec![dir.join("main.woke"), dir.join("tests/main_test.woke")];
+        assert_eq!(program_files(&dir).unwrap(), expected);
+        assert_eq!(program_files(&dir.join(MANIFEST_FILE)).unwrap(), expected);
+        assert_eq!(program_files(&expected[0]).unwrap(), &expected[..1]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
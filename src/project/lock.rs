@@ -0,0 +1,167 @@
+//! Lockfiles
+//!
+//! `woke.lock` pins every package a project depends on, directly or through
+//! other packages, to what was resolved: the commit of a git dependency and
+//! the version each package declared. Later runs use the same commits until
+//! the manifest asks for something else.
+//!
+//! ```toml
+//! [[package]]
+//! name = "colors"
+//! version = "1.0.0"
+//! git = "https://example.com/colors.git"
+//! tag = "v1.0"
+//! commit = "0f3c1d9a..."
+//! ```
+
+use super::manifest::Source;
+use super::{ProjectError, Result};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// Name of the lockfile, next to the manifest
+pub const LOCK_FILE: &str = "woke.lock";
+
+/// One resolved package
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// A path source is relative to the project
+    pub source: Source,
+    /// The commit checked out, for a git source
+    pub commit: Option<String>,
+}
+
+/// The contents of a `woke.lock`, one entry per package in name order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lockfile {
+    pub packages: Vec<LockedPackage>,
+}
+
+impl Lockfile {
+    /// Read the lockfile of the project in `dir`, if it has one
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(LOCK_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path).map_err(|e| ProjectError::Read {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })?;
+        Self::parse(&text).map(Some).map_err(|message| ProjectError::Invalid {
+            path: path.display().to_string(),
+            message,
+        })
+    }
+
+    /// Parse the text of a lockfile
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let mut table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+        let entries = match table.remove("package") {
+            None => Vec::new(),
+            Some(toml::Value::Array(entries)) => entries,
+            Some(_) => return Err("'package' must be a list of [[package]] tables".to_string()),
+        };
+        if let Some(key) = table.keys().next() {
+            return Err(format!("unknown key '{}' (use [[package]])", key));
+        }
+
+        let mut packages = Vec::new();
+        for entry in entries {
+            let toml::Value::Table(mut entry) = entry else {
+                return Err("each [[package]] must be a table".to_string());
+            };
+            let mut string = |key: &str| match entry.remove(key) {
+                Some(toml::Value::String(s)) => Ok(Some(s)),
+                Some(_) => Err(format!("'{}' of a [[package]] must be a string", key)),
+                None => Ok(None),
+            };
+            let name = string("name")?.ok_or("a [[package]] has no name")?;
+            let version = string("version")?.ok_or_else(|| format!("'{}' has no version", name))?;
+            let commit = string("commit")?;
+            let source = Source::from_table(&name, &entry)?;
+            if matches!(source, Source::Git { .. }) != commit.is_some() {
+                return Err(format!("'{}' must have a commit exactly when it is from git", name));
+            }
+            packages.push(LockedPackage { name, version, source, commit });
+        }
+        Ok(Self { packages })
+    }
+
+    /// The locked package named `name`
+    pub fn get(&self, name: &str) -> Option<&LockedPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// The lockfile as TOML
+    pub fn to_toml(&self) -> String {
+        let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+        let mut text = String::from("# Written by `woke`; do not edit it by hand.\n");
+        for package in &self.packages {
+            let _ = write!(
+                text,
+                "\n[[package]]\nname = {}\nversion = {}\n",
+                quote(&package.name),
+                quote(&package.version)
+            );
+            for (key, value) in package.source.fields() {
+                let _ = writeln!(text, "{} = {}", key, value);
+            }
+            if let Some(commit) = &package.commit {
+                let _ = writeln!(text, "commit = {}", quote(commit));
+            }
+        }
+        text
+    }
+
+    /// Write the lockfile into the project in `dir`
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(LOCK_FILE);
+        fs::write(&path, self.to_toml()).map_err(|e| ProjectError::Io {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::manifest::GitReference;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_lockfile() {
+        let lock = Lockfile {
+            packages: vec![
+                LockedPackage {
+                    name: "colors".to_string(),
+                    version: "1.0.0".to_string(),
+                    source: Source::Git {
+                        url: "https://example.com/colors.git".to_string(),
+                        reference: GitReference::Branch("main".to_string()),
+                    },
+                    commit: Some("0f3c1d9a".to_string()),
+                },
+                LockedPackage {
+                    name: "shapes".to_string(),
+                    version: "0.1.0".to_string(),
+                    source: Source::Path(PathBuf::from("../shapes")),
+                    commit: None,
+                },
+            ],
+        };
+        let text = lock.to_toml();
+        assert!(text.contains("branch = \"main\"\ncommit = \"0f3c1d9a\"\n"));
+        assert_eq!(Lockfile::parse(&text).unwrap(), lock);
+        assert_eq!(lock.get("shapes").unwrap().version, "0.1.0");
+        assert_eq!(Lockfile::parse("").unwrap(), Lockfile::default());
+
+        let no_commit = "[[package]]\nname = \"a\"\nversion = \"1\"\ngit = \"u\"\n";
+        assert!(Lockfile::parse(no_commit).unwrap_err().contains("commit"));
+        assert!(Lockfile::parse("[package]\nname = \"a\"\n").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Package Manifests
+//!
+//! `woke.toml` names a project and the packages it depends on, each from a
+//! git repository or a directory:
+//!
+//! ```toml
+//! [package]
+//! name = "hello"
+//! version = "0.1.0"
+//!
+//! [dependencies]
+//! colors = { git = "https://example.com/colors.git", tag = "v1.0" }
+//! shapes = { path = "../shapes" }
+//! ```
+//!
+//! A git dependency may name one `branch`, `tag` or `rev` (a commit); without
+//! one it follows the repository's default branch.
+
+use super::{ProjectError, Result, MANIFEST_FILE};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Which commit of a git repository a dependency wants
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitReference {
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+/// Where a package comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Git { url: String, reference: GitReference },
+    /// A directory, relative to the manifest that names it
+    Path(PathBuf),
+}
+
+impl Source {
+    /// Read a source from the keys of a dependency's table
+    pub(super) fn from_table(name: &str, table: &toml::Table) -> std::result::Result<Self, String> {
+        let string = |key: &str| match table.get(key) {
+            None => Ok(None),
+            Some(toml::Value::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(format!("'{}' of '{}' must be a string", key, name)),
+        };
+        let mut references = Vec::new();
+        for (key, make) in [
+            ("branch", GitReference::Branch as fn(String) -> GitReference),
+            ("tag", GitReference::Tag),
+            ("rev", GitReference::Rev),
+        ] {
+            if let Some(value) = string(key)? {
+                references.push(make(value));
+            }
+        }
+        let source = match (string("git")?, string("path")?) {
+            (Some(url), None) if references.len() <= 1 => Source::Git {
+                url,
+                reference: references.pop().unwrap_or(GitReference::DefaultBranch),
+            },
+            (Some(_), None) => return Err(format!("'{}' may name only one of branch, tag and rev", name)),
+            (None, Some(path)) if references.is_empty() => Source::Path(PathBuf::from(path)),
+            (None, Some(_)) => return Err(format!("'{}' is a path; branch, tag and rev need git", name)),
+            _ => return Err(format!("'{}' needs either git or path", name)),
+        };
+        Ok(source)
+    }
+
+    /// The keys and values that describe this source, as TOML
+    pub(super) fn fields(&self) -> Vec<(&'static str, String)> {
+        let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+        match self {
+            Source::Git { url, reference } => {
+                let mut fields = vec![("git", quote(url))];
+                match reference {
+                    GitReference::DefaultBranch => {}
+                    GitReference::Branch(b) => fields.push(("branch", quote(b))),
+                    GitReference::Tag(t) => fields.push(("tag", quote(t))),
+                    GitReference::Rev(r) => fields.push(("rev", quote(r))),
+                }
+                fields
+            }
+            Source::Path(path) => vec![("path", quote(&path.to_string_lossy()))],
+        }
+    }
+}
+
+/// The contents of a `woke.toml`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageManifest {
+    pub name: String,
+    pub version: String,
+    pub dependencies: BTreeMap<String, Source>,
+}
+
+impl PackageManifest {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            dependencies: BTreeMap::new(),
+        }
+    }
+
+    /// Read the manifest of the project in `dir`
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        let text = fs::read_to_string(&path).map_err(|e| ProjectError::Read {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })?;
+        Self::parse(&text).map_err(|message| ProjectError::Invalid {
+            path: path.display().to_string(),
+            message,
+        })
+    }
+
+    /// Parse the text of a manifest
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+        let mut name = None;
+        let mut version = None;
+        let mut dependencies = BTreeMap::new();
+        for (section, value) in &table {
+            let entries = value
+                .as_table()
+                .ok_or_else(|| format!("[{}] must be a table", section))?;
+            match section.as_str() {
+                "package" => {
+                    for (key, value) in entries {
+                        let value = value
+                            .as_str()
+                            .ok_or_else(|| format!("'{}' in [package] must be a string", key))?;
+                        match key.as_str() {
+                            "name" => name = Some(value.to_string()),
+                            "version" => version = Some(value.to_string()),
+                            _ => return Err(format!("unknown key '{}' in [package] (use name or version)", key)),
+                        }
+                    }
+                }
+                "dependencies" => {
+                    for (dependency, value) in entries {
+                        if !super::is_valid_name(dependency) {
+                            return Err(format!("'{}' is not a valid package name", dependency));
+                        }
+                        let value = value
+                            .as_table()
+                            .ok_or_else(|| format!("dependency '{}' must be a table", dependency))?;
+                        dependencies.insert(dependency.clone(), Source::from_table(dependency, value)?);
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown section [{}] (use [package] or [dependencies])",
+                        section
+                    ))
+                }
+            }
+        }
+        let name = name.ok_or("[package] needs a name")?;
+        if !super::is_valid_name(&name) {
+            return Err(format!("'{}' is not a valid package name", name));
+        }
+        Ok(Self {
+            name,
+            version: version.ok_or("[package] needs a version")?,
+            dependencies,
+        })
+    }
+
+    /// The manifest as TOML
+    pub fn to_toml(&self) -> String {
+        let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+        let mut text = format!("[package]\nname = {}\nversion = {}\n", quote(&self.name), quote(&self.version));
+        if !self.dependencies.is_empty() {
+            text.push_str("\n[dependencies]\n");
+        }
+        for (name, source) in &self.dependencies {
+            let fields: Vec<String> = source.fields().iter().map(|(k, v)| format!("{} = {}", k, v)).collect();
+            let _ = writeln!(text, "{} = {{ {} }}", name, fields.join(", "));
+        }
+        text
+    }
+
+    /// Write the manifest into the project in `dir`
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(MANIFEST_FILE);
+        fs::write(&path, self.to_toml()).map_err(|e| ProjectError::Io {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let text = r#"
+[package]
+name = "hello"
+version = "0.2.0"
+
+[dependencies]
+colors = { git = "https://example.com/colors.git", tag = "v1.0" }
+shapes = { path = "../shapes" }
+"#;
+        let manifest = PackageManifest::parse(text).unwrap();
+        assert_eq!(manifest.name, "hello");
+        assert_eq!(manifest.version, "0.2.0");
+        assert_eq!(
+            manifest.dependencies["colors"],
+            Source::Git {
+                url: "https://example.com/colors.git".to_string(),
+                reference: GitReference::Tag("v1.0".to_string()),
+            }
+        );
+        assert_eq!(manifest.dependencies["shapes"], Source::Path(PathBuf::from("../shapes")));
+        assert_eq!(PackageManifest::parse(&manifest.to_toml()).unwrap(), manifest);
+
+        for (text, error) in [
+            ("[package]\nversion = \"1\"\n", "needs a name"),
+            ("[package]\nname = \"my-app\"\nversion = \"1\"\n", "not a valid package name"),
+            ("[package]\nname = \"a\"\nversion = \"1\"\nedition = \"1\"\n", "unknown key"),
+            ("[package]\nname = \"a\"\nversion = \"1\"\n[dependencies]\nb = { tag = \"v1\" }\n", "either git or path"),
+            (
+                "[package]\nname = \"a\"\nversion = \"1\"\n[dependencies]\nb = { git = \"u\", tag = \"v\", rev = \"r\" }\n",
+                "only one of",
+            ),
+            ("[package]\nname = \"a\"\nversion = \"1\"\n[dependencies]\nb = { path = \"p\", tag = \"v\" }\n", "need git"),
+            ("[features]\n", "unknown section"),
+        ] {
+            let message = PackageManifest::parse(text).unwrap_err();
+            assert!(message.contains(error), "{}: {}", text, message);
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! WokeLang Projects
+//!
+//! A project is a directory with a `woke.toml` manifest naming it, a
+//! `main.woke` to run, a `woke.policy.toml` of the superpowers it allows up
+//! front, and a `tests/` folder for `woke test`. `woke init` creates one:
+//!
+//! ```text
+//! hello/
+//! ├── woke.toml
+//! ├── woke.policy.toml
+//! ├── main.woke
+//! └── tests/
+//!     └── main_test.woke
+//! ```
+//!
+//! The manifest lists the packages the project depends on, which `woke add`
+//! resolves into `woke.lock` and the package cache, and which the project's
+//! programs then import like their own modules. `woke check` in the project
+//! type-checks all of its programs, keeping what it can reuse next time in
+//! `target/.woke-cache`.
+
+pub mod cache;
+pub mod check;
+pub mod lock;
+pub mod manifest;
+pub mod modules;
+pub mod resolve;
+
+pub use cache::BuildCache;
+pub use check::program_files;
+pub use lock::{LockedPackage, Lockfile, LOCK_FILE};
+pub use manifest::{GitReference, PackageManifest, Source};
+pub use modules::ModuleLoader;
+pub use resolve::{Resolution, Resolver};
+
+use crate::security::policy::POLICY_FILE;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// Name of the project manifest
+pub const MANIFEST_FILE: &str = "woke.toml";
+
+/// The program a new project runs
+pub const MAIN_FILE: &str = "main.woke";
+
+/// Where a new project keeps its tests
+pub const TESTS_DIR: &str = "tests";
+
+#[derive(Error, Debug)]
+pub enum ProjectError {
+    #[error("Could not write {path}: {message}")]
+    Io { path: String, message: String },
+
+    #[error("Could not read {path}: {message}")]
+    Read { path: String, message: String },
+
+    #[error("Invalid {path}: {message}")]
+    Invalid { path: String, message: String },
+
+    #[error("{0} already exists; not overwriting it")]
+    Exists(String),
+
+    #[error("Invalid project name '{0}': use lowercase letters, digits and '_', starting with a letter")]
+    InvalidName(String),
+
+    #[error("No {} in {0} or any directory above it; create one with `woke init`", MANIFEST_FILE)]
+    NoProject(String),
+
+    #[error("Could not get dependency '{0}': {1}")]
+    Dependency(String, String),
+
+    #[error("Dependency '{name}' is required by '{first}' and '{second}' from different places")]
+    Conflict { name: String, first: String, second: String },
+
+    #[error("In module {path}: {message}")]
+    Module { path: String, message: String },
+
+    /// A module that does not lex or parse, kept whole so that it can be
+    /// shown in the module's own source
+    #[error("In module {path}: [{code}] {error}")]
+    Syntax {
+        path: String,
+        code: &'static str,
+        error: Box<dyn miette::Diagnostic + Send + Sync>,
+    },
+
+    #[error("Modules import each other: {0}")]
+    ImportCycle(String),
+}
+
+type Result<T> = std::result::Result<T, ProjectError>;
+
+/// Whether `name` can name a project: lowercase letters, digits and `_`,
+/// starting with a letter, so that `use name;` can import it
+pub fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// The root of the project `dir` is in: the nearest directory, from `dir`
+/// up, with a manifest
+pub fn find_root(dir: &Path) -> Option<PathBuf> {
+    let dir = fs::canonicalize(dir).ok()?;
+    dir.ancestors().find(|d| d.join(MANIFEST_FILE).is_file()).map(Path::to_path_buf)
+}
+
+/// `path` relative to `base`, going up with `..` where it must; both
+/// absolute, or both relative to the same directory
+pub fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let mut path_parts = path.components().peekable();
+    let mut base_parts = base.components().peekable();
+    while let (Some(p), Some(b)) = (path_parts.peek(), base_parts.peek()) {
+        if p != b {
+            break;
+        }
+        path_parts.next();
+        base_parts.next();
+    }
+    let relative: PathBuf = base_parts.map(|_| Component::ParentDir).chain(path_parts).collect();
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+/// A project name made from a directory's name, e.g. `my_app` for
+/// `My App`
+pub fn name_for(dir: &Path) -> String {
+    let dir_name = fs::canonicalize(dir)
+        .ok()
+        .and_then(|dir| dir.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .or_else(|| dir.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    let mut name = String::new();
+    for c in dir_name.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_matches('_').trim_start_matches(|c: char| !c.is_ascii_lowercase());
+    if name.is_empty() {
+        "woke_project".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Create a project named `name` in `dir`, which is made if it does not
+/// exist, returning the files written. Nothing is written if any of them
+/// already exists.
+pub fn init(dir: &Path, name: &str) -> Result<Vec<PathBuf>> {
+    if !is_valid_name(name) {
+        return Err(ProjectError::InvalidName(name.to_string()));
+    }
+    let files = [
+        (PathBuf::from(MANIFEST_FILE), PackageManifest::new(name).to_toml()),
+        (PathBuf::from(POLICY_FILE), POLICY_TEMPLATE.to_string()),
+        (PathBuf::from(MAIN_FILE), MAIN_TEMPLATE.to_string()),
+        (Path::new(TESTS_DIR).join("main_test.woke"), TEST_TEMPLATE.to_string()),
+    ];
+    if let Some((path, _)) = files.iter().find(|(path, _)| dir.join(path).exists()) {
+        return Err(ProjectError::Exists(dir.join(path).display().to_string()));
+    }
+
+    let io = |path: &Path, e: std::io::Error| ProjectError::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    };
+    let mut written = Vec::new();
+    for (path, contents) in files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| io(parent, e))?;
+        }
+        fs::write(&path, contents).map_err(|e| io(&path, e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Make the package `name` from `source` a dependency of the project in
+/// `root`, replacing any it had by that name, and resolve the project's
+/// dependencies again. The manifest and lockfile are only written once
+/// everything resolved.
+pub fn add(root: &Path, name: &str, source: Source) -> Result<Resolution> {
+    if !is_valid_name(name) {
+        return Err(ProjectError::InvalidName(name.to_string()));
+    }
+    let mut manifest = PackageManifest::load(root)?;
+    manifest.dependencies.insert(name.to_string(), source);
+    let resolution = Resolver::new().resolve(root, &manifest, Lockfile::load(root)?.as_ref())?;
+    manifest.save(root)?;
+    resolution.lockfile.save(root)?;
+    Ok(resolution)
+}
+
+const POLICY_TEMPLATE: &str = r#"# Superpowers this project allows without asking; anything else is asked
+# for when the program runs. For example:
+#
+# read = ["data/"]
+# write = ["out/"]
+# network = ["api.example.com"]
+# env = ["HOME"]
+[allow]
+"#;
+
+const MAIN_TEMPLATE: &str = r#"thanks to {
+    "You" → "For starting something new";
+}
+
+to greet(name: String) -> String {
+    give back "Hello, " + name + "!";
+}
+
+to main() {
+    print(greet("world"));
+}
+"#;
+
+const TEST_TEMPLATE: &str = r#"// Run with `woke test tests/*.woke`. Every `expect` block and every
+// `to test_*()` function is a test.
+use std.test;
+use main;
+
+to test_greet() {
+    test.assertEqual(main.greet("Ada"), "Hello, Ada!");
+}
+
+expect "greeting nobody" {
+    test.assertEqual(main.greet(""), "Hello, !");
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::security::Policy;
+    use crate::testing::TestRunner;
+    use crate::typechecker::TypeChecker;
+
+    #[test]
+    fn test_names() {
+        assert!(is_valid_name("hello_world2"));
+        assert!(!is_valid_name("hello-world"));
+        assert!(!is_valid_name("2fast"));
+        assert!(!is_valid_name("Hello"));
+        assert!(!is_valid_name(""));
+        assert_eq!(name_for(Path::new("/tmp/My Cool App!")), "my_cool_app");
+        assert_eq!(name_for(Path::new("/tmp/42")), "woke_project");
+
+        let relative = |path: &str, base: &str| relative_path(Path::new(path), Path::new(base));
+        assert_eq!(relative("/work/app/shapes", "/work/app"), Path::new("shapes"));
+        assert_eq!(relative("/work/shapes", "/work/app"), Path::new("../shapes"));
+        assert_eq!(relative("/work/app", "/work/app"), Path::new("."));
+    }
+
+    #[test]
+    fn test_init() {
+        let dir = std::env::temp_dir().join("wokelang_test_init");
+        let _ = fs::remove_dir_all(&dir);
+
+        let files = init(&dir, "hello").unwrap();
+        assert_eq!(files.len(), 4);
+        assert_eq!(PackageManifest::load(&dir).unwrap(), PackageManifest::new("hello"));
+        assert_eq!(find_root(&dir.join(TESTS_DIR)), Some(fs::canonicalize(&dir).unwrap()));
+        assert_eq!(Policy::load(&dir.join(POLICY_FILE)).unwrap(), Policy::default());
+
+        for path in [dir.join(MAIN_FILE), dir.join("tests/main_test.woke")] {
+            let source = fs::read_to_string(&path).unwrap();
+            let tokens = Lexer::new(&source).tokenize().unwrap();
+            let program = Parser::new(tokens, &source).parse().unwrap();
+            let program = ModuleLoader::for_program(&path).unwrap().load(&path, program).unwrap();
+            TypeChecker::new().check_program(&program).unwrap();
+            let results = TestRunner::new().run(&program);
+            assert!(results.iter().all(|r| r.passed()), "{}: {:?}", path.display(), results);
+        }
+
+        assert!(!dir.join(LOCK_FILE).exists(), "no dependencies, no lockfile");
+
+        // A second init would overwrite the project
+        assert!(matches!(init(&dir, "hello"), Err(ProjectError::Exists(_))));
+        assert!(matches!(init(&dir.join("other"), "Bad"), Err(ProjectError::InvalidName(_))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
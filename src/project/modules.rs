@@ -0,0 +1,447 @@
+//! Module Loading
+//!
+//! `use shapes.circle;` imports the functions of another WokeLang file,
+//! which are then called through the last part of its path, or its new
+//! name: `circle.area(2.0)`. The loader finds the file and merges its
+//! functions into the program, so the type checker and both engines see one
+//! program. An import names, in the order tried:
+//!
+//! 1. `shapes/circle.woke` next to the importing file
+//! 2. `shapes/circle.woke` in the project root, the directory of `woke.toml`
+//! 3. `circle.woke` in the package `shapes`, or its `lib.woke` for a bare
+//!    `use shapes;`
+//!
+//! `std` imports are the standard library's, and imports that name no file
+//! or package are left to the engines as before.
+//!
+//! Given a [`BuildCache`], the loader takes modules it parsed before from
+//! it rather than parsing them again, and works out the [key](ModuleLoader::key)
+//! a program is cached by from what each module imports.
+
+use super::cache::{hex, BuildCache};
+use super::lock::Lockfile;
+use super::manifest::PackageManifest;
+use super::resolve::Resolver;
+use super::{find_root, ProjectError, Result};
+use crate::ast::{Expr, LambdaBody, Program, Spanned, Statement, Symbol, TopLevelItem};
+use crate::lexer::{Lexer, SourceFile};
+use crate::parser::Parser;
+use crate::typechecker::ModuleScope;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Module a bare package import loads
+pub const PACKAGE_ENTRY: &str = "lib.woke";
+
+/// Finds imported modules and merges them into programs
+#[derive(Debug, Clone, Default)]
+pub struct ModuleLoader {
+    /// The project root, if the program is in a project
+    root: Option<PathBuf>,
+    /// Where each package's files are
+    packages: BTreeMap<String, PathBuf>,
+    /// Module files loaded so far
+    files: Vec<PathBuf>,
+    /// The file each function merged into a program is from
+    origins: HashMap<String, PathBuf>,
+    /// Where parsed modules are kept, if anywhere
+    cache: Option<BuildCache>,
+    /// The hash of each module file's source
+    hashes: HashMap<PathBuf, String>,
+    /// The module files each file loaded so far imports, in order
+    imports: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl ModuleLoader {
+    /// A loader for programs outside any project
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A loader for the program `file`, resolving the dependencies of the
+    /// project it is in. Git dependencies missing from the package cache are
+    /// fetched, and the lockfile is written if the resolution changed it
+    /// and the project has dependencies or a lockfile already.
+    pub fn for_program(file: &Path) -> Result<Self> {
+        let dir = match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let Some(root) = find_root(dir) else {
+            return Ok(Self::new());
+        };
+        let manifest = PackageManifest::load(&root)?;
+        let locked = Lockfile::load(&root)?;
+        let resolution = Resolver::new().resolve(&root, &manifest, locked.as_ref())?;
+        let unchanged = match &locked {
+            Some(locked) => *locked == resolution.lockfile,
+            None => resolution.lockfile.packages.is_empty(),
+        };
+        if !unchanged {
+            resolution.lockfile.save(&root)?;
+        }
+        Ok(Self {
+            root: Some(root),
+            packages: resolution.packages,
+            ..Self::default()
+        })
+    }
+
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Make the package `name`, whose files are in `dir`, importable
+    pub fn with_package(mut self, name: &str, dir: impl Into<PathBuf>) -> Self {
+        self.packages.insert(name.to_string(), dir.into());
+        self
+    }
+
+    /// Take modules parsed before from `cache`, and keep those parsed now
+    /// in it
+    pub fn with_cache(mut self, cache: BuildCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The module files loaded so far
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// The module file a function merged into a loaded program is from,
+    /// by its name in the program (`circle.area`)
+    pub fn origin(&self, function: &str) -> Option<&Path> {
+        self.origins.get(function).map(PathBuf::as_path)
+    }
+
+    /// The file a `use` of `parts` names from a file in `dir`, or `None` if
+    /// it names none
+    pub fn module_path(&self, dir: &Path, parts: &[String]) -> Result<Option<PathBuf>> {
+        let Some(first) = parts.first() else {
+            return Ok(None);
+        };
+        if first == "std" {
+            return Ok(None);
+        }
+        let relative = PathBuf::from(parts.join("/")).with_extension("woke");
+        let local = std::iter::once(dir).chain(self.root.as_deref());
+        if let Some(path) = local.map(|dir| dir.join(&relative)).find(|path| path.is_file()) {
+            return Ok(Some(path));
+        }
+        let Some(package) = self.packages.get(first) else {
+            return Ok(None);
+        };
+        let path = match &parts[1..] {
+            [] => package.join(PACKAGE_ENTRY),
+            rest => package.join(rest.join("/")).with_extension("woke"),
+        };
+        if !path.is_file() {
+            return Err(ProjectError::Module {
+                path: path.display().to_string(),
+                message: format!("package '{}' has no module {}", first, parts.join(".")),
+            });
+        }
+        Ok(Some(path))
+    }
+
+    /// The key a program loaded from `file`, with `source`, is cached by: a
+    /// hash of its source and of the keys of the modules it imports, so it
+    /// changes when any of them does
+    pub fn key(&self, file: &Path, source: &str) -> String {
+        let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        self.module_key(&file, &BuildCache::hash(source))
+    }
+
+    fn module_key(&self, file: &Path, hash: &str) -> String {
+        let mut sha = Sha256::new();
+        sha.update(hash);
+        for import in self.imports.get(file).into_iter().flatten() {
+            let hash = self.hashes.get(import).map(String::as_str).unwrap_or_default();
+            sha.update([0]);
+            sha.update(self.module_key(import, hash));
+        }
+        hex(sha)
+    }
+
+    /// Merge the modules `program`, read from `file`, imports into it
+    pub fn load(&mut self, file: &Path, program: Program) -> Result<Program> {
+        let dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+        let mut stack = vec![fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())];
+        let (program, origins) = self.load_imports(&dir, program, &mut stack)?;
+        self.origins.extend(origins);
+        Ok(program)
+    }
+
+    /// Merge the imports of a program, returning it with the files its
+    /// merged functions are from
+    fn load_imports(
+        &mut self,
+        dir: &Path,
+        program: Program,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(Program, HashMap<String, PathBuf>)> {
+        let mut items = Vec::new();
+        let mut merged = Vec::new();
+        let mut merged_names = HashSet::new();
+        let mut origins = HashMap::new();
+        let mut imported = Vec::new();
+        for item in program.items {
+            let TopLevelItem::ModuleImport(import) = &item else {
+                items.push(item);
+                continue;
+            };
+            let Some(path) = self.module_path(dir, &import.path.parts)? else {
+                items.push(item);
+                continue;
+            };
+            let alias = import.alias();
+
+            imported.push(fs::canonicalize(&path).unwrap_or_else(|_| path.clone()));
+            let (module, module_origins) = self.parse_module(&path, stack)?;
+            let names: HashSet<String> = ModuleScope::of(&module).functions().iter().cloned().collect();
+            for item in module.items {
+                match item {
+                    TopLevelItem::Function(mut f) => {
+                        let name = format!("{}.{}", alias, f.name);
+                        if let Some(origin) = module_origins.get(&f.name) {
+                            origins.insert(name.clone(), origin.clone());
+                        }
+                        f.name = name;
+                        f.module.get_or_insert_with(|| import.path.parts.join("."));
+                        qualify_calls(&mut f.body, &alias, &names);
+                        if merged_names.insert(f.name.clone()) {
+                            merged.push(TopLevelItem::Function(f));
+                        }
+                    }
+                    TopLevelItem::TypeDef(t) if merged_names.insert(t.name.clone()) => {
+                        merged.push(TopLevelItem::TypeDef(t))
+                    }
+                    // The module's own standard library imports
+                    TopLevelItem::ModuleImport(i) => items.push(TopLevelItem::ModuleImport(i)),
+                    _ => {}
+                }
+            }
+        }
+        items.extend(merged);
+        if let Some(file) = stack.last() {
+            self.imports.insert(file.clone(), imported);
+        }
+        Ok((Program { items }, origins))
+    }
+
+    /// Read, parse and load the imports of one module file, returning it
+    /// with the file each of its functions is from
+    fn parse_module(&mut self, path: &Path, stack: &mut Vec<PathBuf>) -> Result<(Program, HashMap<String, PathBuf>)> {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if let Some(start) = stack.iter().position(|p| *p == canonical) {
+            let mut cycle: Vec<String> = stack[start..].iter().map(|p| p.display().to_string()).collect();
+            cycle.push(canonical.display().to_string());
+            return Err(ProjectError::ImportCycle(cycle.join(" -> ")));
+        }
+
+        let error = |message: String| ProjectError::Module {
+            path: path.display().to_string(),
+            message,
+        };
+        if !self.files.contains(&canonical) {
+            self.files.push(canonical.clone());
+        }
+        let source = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        self.hashes.insert(canonical.clone(), BuildCache::hash(&source));
+        let program = match self.cache.as_ref().and_then(|cache| cache.ast(&source)) {
+            Some(program) => program,
+            None => {
+                let program = parse_module_source(path, &source)?;
+                if let Some(cache) = &self.cache {
+                    // A module that cannot be kept is parsed again next time
+                    let _ = cache.store_ast(&source, &program);
+                }
+                program
+            }
+        };
+
+        stack.push(canonical.clone());
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let loaded = self.load_imports(&dir, program, stack);
+        stack.pop();
+        let (program, mut origins) = loaded?;
+        for item in &program.items {
+            if let TopLevelItem::Function(f) = item {
+                origins.entry(f.name.clone()).or_insert_with(|| canonical.clone());
+            }
+        }
+        Ok((program, origins))
+    }
+}
+
+/// Lex and parse a module file's `source`
+fn parse_module_source(path: &Path, source: &str) -> Result<Program> {
+    let file = SourceFile::named(path.display().to_string(), source);
+    let syntax = |code, error: Box<dyn miette::Diagnostic + Send + Sync>| ProjectError::Syntax {
+        path: path.display().to_string(),
+        code,
+        error,
+    };
+    Parser::from_lexer(Lexer::for_file(&file))
+        .parse()
+        .map_err(|e| syntax(e.code(), Box::new(e)))
+}
+
+/// Prefix calls to the functions `names` with `alias.`
+fn qualify_calls(stmts: &mut [Statement], alias: &str, names: &HashSet<String>) {
+    fn visit_stmt(stmt: &mut Statement, alias: &str, names: &HashSet<String>) {
+        match stmt {
+            Statement::VarDecl(d) => visit_expr(&mut d.value, alias, names),
+            Statement::Assignment(a) => visit_expr(&mut a.value, alias, names),
+            Statement::Return(r) => visit_expr(&mut r.value, alias, names),
+            Statement::Conditional(c) => {
+                visit_expr(&mut c.condition, alias, names);
+                qualify_calls(&mut c.then_branch, alias, names);
+                if let Some(else_branch) = &mut c.else_branch {
+                    qualify_calls(else_branch, alias, names);
+                }
+            }
+            Statement::Loop(l) => {
+                visit_expr(&mut l.count, alias, names);
+                qualify_calls(&mut l.body, alias, names);
+            }
+            Statement::AttemptBlock(a) => qualify_calls(&mut a.body, alias, names),
+            Statement::ConsentBlock(c) => qualify_calls(&mut c.body, alias, names),
+            Statement::Expression(e) => visit_expr(e, alias, names),
+            Statement::EmoteAnnotated(e) => visit_stmt(&mut e.statement, alias, names),
+            Statement::Decide(d) => {
+                visit_expr(&mut d.scrutinee, alias, names);
+                for arm in &mut d.arms {
+                    qualify_calls(&mut arm.body, alias, names);
+                }
+            }
+            Statement::WorkerSpawn(_) | Statement::Complain(_) => {}
+        }
+    }
+
+    fn visit_expr(expr: &mut Spanned<Expr>, alias: &str, names: &HashSet<String>) {
+        match &mut expr.node {
+            Expr::Call(name, args) => {
+                if names.contains(name.as_str()) {
+                    *name = Symbol::intern(&format!("{}.{}", alias, name));
+                }
+                args.iter_mut().for_each(|a| visit_expr(a, alias, names));
+            }
+            Expr::CallExpr(callee, args) => {
+                visit_expr(callee, alias, names);
+                args.iter_mut().for_each(|a| visit_expr(a, alias, names));
+            }
+            Expr::Binary(_, left, right) | Expr::Index(left, right) => {
+                visit_expr(left, alias, names);
+                visit_expr(right, alias, names);
+            }
+            Expr::Unary(_, e) | Expr::UnitMeasurement(e, _) | Expr::Okay(e) | Expr::Oops(e) | Expr::Unwrap(e) => {
+                visit_expr(e, alias, names)
+            }
+            Expr::Array(items) => items.iter_mut().for_each(|i| visit_expr(i, alias, names)),
+            Expr::Lambda(lambda) => match &mut lambda.body {
+                LambdaBody::Expr(e) => visit_expr(e, alias, names),
+                LambdaBody::Block(stmts) => qualify_calls(stmts, alias, names),
+            },
+            Expr::Literal(_) | Expr::Identifier(_) | Expr::GratitudeLiteral(_) => {}
+        }
+    }
+
+    for stmt in stmts {
+        visit_stmt(stmt, alias, names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, OutputBuffer, Value};
+    use crate::security::{Capability, CapabilityRegistry, ConsentMode};
+    use crate::typechecker::TypeChecker;
+    use crate::vm::{BytecodeCompiler, VirtualMachine};
+
+    fn parse(source: &str) -> Program {
+        Parser::new(Lexer::new(source).tokenize().unwrap(), source).parse().unwrap()
+    }
+
+    #[test]
+    fn test_load_modules() {
+        let dir = std::env::temp_dir().join("wokelang_test_modules");
+        let _ = fs::remove_dir_all(&dir);
+        let write = |path: &str, source: &str| {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        };
+        write(
+            "app/shapes/square.woke",
+            "to edge() -> Int { give back 3; }\nto area() -> Int { give back edge() * edge(); }\n",
+        );
+        write(
+            "app/tests/area_test.woke",
+            "use shapes.square;\nto total() -> Int { give back square.area() + words.count(); }\n",
+        );
+        write("words/lib.woke", "use std.string;\nto count() -> Int { give back len(string.split(\"a b\", \" \")); }\n");
+
+        let loader = ModuleLoader::new().with_root(dir.join("app")).with_package("words", dir.join("words"));
+        // A module next to the file wins over one in the root
+        let tests = dir.join("app/tests");
+        let parts = |path: &str| path.split('.').map(String::from).collect::<Vec<_>>();
+        assert_eq!(loader.module_path(&tests, &parts("shapes.square")).unwrap(), Some(dir.join("app/shapes/square.woke")));
+        assert_eq!(loader.module_path(&tests, &parts("words")).unwrap(), Some(dir.join("words/lib.woke")));
+        assert_eq!(loader.module_path(&tests, &parts("std.math")).unwrap(), None);
+        assert_eq!(loader.module_path(&tests, &parts("net.fetch")).unwrap(), None);
+        assert!(loader.module_path(&tests, &parts("words.missing")).is_err());
+
+        let file = dir.join("app/main.woke");
+        let source = "use shapes.square renamed sq;\nuse words;\n\
+                      to total() -> Int { give back sq.area() + words.count(); }\n";
+        let mut loader = loader;
+        let program = loader.load(&file, parse(&format!("{}to main() {{ print(total()); }}", source))).unwrap();
+        assert_eq!(loader.files().len(), 2);
+        let square = fs::canonicalize(dir.join("app/shapes/square.woke")).unwrap();
+        assert_eq!(loader.origin("sq.edge"), Some(square.as_path()));
+        assert_eq!(loader.origin("total"), None);
+        TypeChecker::new().check_program(&program).unwrap();
+        let output = OutputBuffer::new();
+        Interpreter::new().with_output(output.clone()).run(&program).unwrap();
+        assert_eq!(output.contents(), "11\n");
+
+        let program = loader.load(&file, parse(&format!("{}to main() {{ give back total(); }}", source))).unwrap();
+        let compiled = BytecodeCompiler::new().compile(&program).unwrap();
+        assert_eq!(VirtualMachine::new(compiled).run().unwrap(), Value::Int(11));
+
+        // Imported functions ask in their own module's scope, which a grant
+        // to the program's module does not cover
+        write("app/settings.woke", "to home() { remember home = std.env.get(\"HOME\"); }\n");
+        let program = loader.load(&file, parse("use settings;\nto main() { settings.home(); }")).unwrap();
+        for (scope, allowed) in [("main", false), ("main:settings", false), ("settings", true)] {
+            let granted = || {
+                let mut capabilities = CapabilityRegistry::new();
+                capabilities.set_consent_mode(ConsentMode::Deny);
+                capabilities.grant(scope, Capability::Environment(None), "test");
+                capabilities
+            };
+            let mut interpreter = Interpreter::new().with_capabilities(granted());
+            assert_eq!(interpreter.run(&program).is_ok(), allowed, "{}", scope);
+            let compiled = BytecodeCompiler::new().compile(&program).unwrap();
+            let mut vm = VirtualMachine::new(compiled).with_capabilities(granted());
+            assert_eq!(vm.run().is_ok(), allowed, "{}", scope);
+        }
+
+        // Modules that import each other
+        write("app/a.woke", "use b;\nto f() -> Int { give back 1; }\n");
+        write("app/b.woke", "use a;\nto g() -> Int { give back 2; }\n");
+        let error = ModuleLoader::new().load(&file, parse("use a;")).unwrap_err();
+        assert!(matches!(error, ProjectError::ImportCycle(_)), "{}", error);
+
+        write("app/broken.woke", "to f( {");
+        let error = ModuleLoader::new().load(&file, parse("use broken;")).unwrap_err();
+        assert!(error.to_string().contains("broken.woke"), "{}", error);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
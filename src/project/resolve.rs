@@ -0,0 +1,329 @@
+//! Dependency Resolution
+//!
+//! The resolver walks a project's dependencies and theirs, and finds each
+//! package's files: a path dependency is used where it is, and a git
+//! dependency is cloned into the package cache (`~/.woke/packages`, or
+//! `$WOKE_PACKAGE_CACHE`) as one directory per commit, so projects share
+//! checkouts and a locked commit is never fetched twice. Cloning runs the
+//! `git` command.
+//!
+//! Packages share one namespace: two dependencies with the same name must
+//! come from the same place.
+
+use super::lock::{LockedPackage, Lockfile};
+use super::manifest::{GitReference, PackageManifest, Source};
+use super::{ProjectError, Result, MANIFEST_FILE};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Environment variable naming the package cache directory
+pub const PACKAGE_CACHE_ENV: &str = "WOKE_PACKAGE_CACHE";
+
+/// The package cache: `$WOKE_PACKAGE_CACHE`, or `~/.woke/packages`
+pub fn cache_dir() -> PathBuf {
+    match std::env::var_os(PACKAGE_CACHE_ENV).filter(|p| !p.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".woke")
+            .join("packages"),
+    }
+}
+
+/// The packages of a resolved project
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    pub lockfile: Lockfile,
+    /// Where each package's files are
+    pub packages: BTreeMap<String, PathBuf>,
+}
+
+/// Resolves dependencies into a package cache
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    cache: PathBuf,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    /// A resolver using the default package cache
+    pub fn new() -> Self {
+        Self { cache: cache_dir() }
+    }
+
+    pub fn with_cache(cache: impl Into<PathBuf>) -> Self {
+        Self { cache: cache.into() }
+    }
+
+    /// Resolve the dependencies of the project in `root`, keeping the
+    /// commits `locked` pins for git dependencies the manifest still asks
+    /// for in the same way
+    pub fn resolve(&self, root: &Path, manifest: &PackageManifest, locked: Option<&Lockfile>) -> Result<Resolution> {
+        let root = canonical(root)?;
+        let mut queue: VecDeque<(String, Source, PathBuf, String)> = manifest
+            .dependencies
+            .iter()
+            .map(|(name, source)| (name.clone(), source.clone(), root.clone(), manifest.name.clone()))
+            .collect();
+        // Each package's source, with paths made absolute, and who needs it
+        let mut seen: BTreeMap<String, (Source, String)> = BTreeMap::new();
+        let mut resolution = Resolution::default();
+
+        while let Some((name, source, base, required_by)) = queue.pop_front() {
+            if name == manifest.name {
+                return Err(ProjectError::Dependency(name, "a package cannot depend on itself".to_string()));
+            }
+            let absolute = match &source {
+                Source::Path(path) => Source::Path(canonical(&base.join(path))?),
+                git => git.clone(),
+            };
+            if let Some((first, first_required_by)) = seen.get(&name) {
+                if *first != absolute {
+                    return Err(ProjectError::Conflict {
+                        name,
+                        first: first_required_by.clone(),
+                        second: required_by,
+                    });
+                }
+                continue;
+            }
+            seen.insert(name.clone(), (absolute.clone(), required_by));
+
+            let (dir, source, commit) = match absolute {
+                Source::Path(dir) => {
+                    let relative = super::relative_path(&dir, &root);
+                    (dir, Source::Path(relative), None)
+                }
+                Source::Git { url, reference } => {
+                    let pinned = locked
+                        .and_then(|lock| lock.get(&name))
+                        .filter(|p| p.source == Source::Git { url: url.clone(), reference: reference.clone() })
+                        .and_then(|p| p.commit.clone());
+                    let (dir, commit) = self.fetch(&name, &url, &reference, pinned.as_deref())?;
+                    (dir, Source::Git { url, reference }, Some(commit))
+                }
+            };
+
+            if !dir.join(MANIFEST_FILE).is_file() {
+                return Err(ProjectError::Dependency(
+                    name,
+                    format!("{} has no {}", dir.display(), MANIFEST_FILE),
+                ));
+            }
+            let package = PackageManifest::load(&dir)?;
+            if package.name != name {
+                return Err(ProjectError::Dependency(
+                    name,
+                    format!("{} is the package '{}'", dir.display(), package.name),
+                ));
+            }
+            for (dependency, dependency_source) in &package.dependencies {
+                queue.push_back((dependency.clone(), dependency_source.clone(), dir.clone(), name.clone()));
+            }
+            resolution.lockfile.packages.push(LockedPackage {
+                name: name.clone(),
+                version: package.version,
+                source,
+                commit,
+            });
+            resolution.packages.insert(name, dir);
+        }
+
+        resolution.lockfile.packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(resolution)
+    }
+
+    /// The checkout of a git dependency at `commit`, or at what `reference`
+    /// names now, cloning it into the cache if it is not there yet
+    fn fetch(&self, name: &str, url: &str, reference: &GitReference, commit: Option<&str>) -> Result<(PathBuf, String)> {
+        // Nothing from a manifest or lockfile may reach git as an option
+        let refuse = |message: String| ProjectError::Dependency(name.to_string(), message);
+        let named = match reference {
+            GitReference::DefaultBranch => None,
+            GitReference::Branch(named) | GitReference::Tag(named) | GitReference::Rev(named) => Some(named),
+        };
+        if let Some(arg) = std::iter::once(url).chain(named.map(String::as_str)).find(|arg| arg.starts_with('-')) {
+            return Err(refuse(format!("'{}' cannot start with '-'", arg)));
+        }
+        if let Some(commit) = commit.filter(|commit| !is_commit(commit)) {
+            return Err(refuse(format!("'{}' is not a commit hash", commit)));
+        }
+
+        if let Some(commit) = commit {
+            let dir = self.checkout_dir(name, commit);
+            if dir.is_dir() {
+                return Ok((dir, commit.to_string()));
+            }
+        }
+
+        let io = |path: &Path, e: std::io::Error| ProjectError::Io {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        };
+        fs::create_dir_all(&self.cache).map_err(|e| io(&self.cache, e))?;
+        let clone = self.cache.join(format!(".{}-{}", name, std::process::id()));
+        if clone.exists() {
+            fs::remove_dir_all(&clone).map_err(|e| io(&clone, e))?;
+        }
+        let checked_out = (|| {
+            git(name, None, &["clone", "--quiet", "--", url, &clone.to_string_lossy()])?;
+            let target = match (commit, reference) {
+                (Some(commit), _) => commit.to_string(),
+                (None, GitReference::DefaultBranch) => "HEAD".to_string(),
+                (None, GitReference::Branch(branch)) => format!("origin/{}", branch),
+                (None, GitReference::Tag(tag)) => format!("tags/{}", tag),
+                (None, GitReference::Rev(rev)) => rev.clone(),
+            };
+            git(name, Some(&clone), &["checkout", "--quiet", "--detach", &target, "--"])?;
+            let commit = git(name, Some(&clone), &["rev-parse", "HEAD"])?;
+            if !is_commit(&commit) {
+                return Err(refuse(format!("git gave '{}' as the commit", commit)));
+            }
+            Ok(commit)
+        })();
+        let commit = match checked_out {
+            Ok(commit) => commit,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&clone);
+                return Err(e);
+            }
+        };
+
+        let dir = self.checkout_dir(name, &commit);
+        if dir.is_dir() {
+            let _ = fs::remove_dir_all(&clone);
+        } else {
+            fs::rename(&clone, &dir).map_err(|e| io(&dir, e))?;
+        }
+        Ok((dir, commit))
+    }
+
+    fn checkout_dir(&self, name: &str, commit: &str) -> PathBuf {
+        self.cache.join(format!("{}-{}", name, &commit[..commit.len().min(12)]))
+    }
+}
+
+/// Whether `commit` is a commit hash, as lockfiles pin them
+fn is_commit(commit: &str) -> bool {
+    (4..=64).contains(&commit.len()) && commit.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Run git, in `dir` if given, returning what it printed
+fn git(name: &str, dir: Option<&Path>, args: &[&str]) -> Result<String> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command
+        .args(args)
+        .output()
+        .map_err(|e| ProjectError::Dependency(name.to_string(), format!("could not run git: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ProjectError::Dependency(
+            name.to_string(),
+            format!("git {} failed: {}", args[0], stderr.trim()),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn canonical(path: &Path) -> Result<PathBuf> {
+    fs::canonicalize(path).map_err(|e| ProjectError::Read {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(dir: &Path, manifest: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(MANIFEST_FILE), manifest).unwrap();
+    }
+
+    fn commit_all(repo: &Path, message: &str) -> String {
+        let run = |args: &[&str]| git("test", Some(repo), args).unwrap();
+        run(&["add", "-A"]);
+        run(&["-c", "user.name=Test", "-c", "user.email=test@example.com", "commit", "--quiet", "-m", message]);
+        run(&["rev-parse", "HEAD"])
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = std::env::temp_dir().join("wokelang_test_resolve");
+        let _ = fs::remove_dir_all(&dir);
+        let cache = dir.join("cache");
+
+        // A git package with a path dependency of its own
+        let repo = dir.join("colors");
+        package(&repo, "[package]\nname = \"colors\"\nversion = \"1.0.0\"\n[dependencies]\nutil = { path = \"util\" }\n");
+        package(&repo.join("util"), "[package]\nname = \"util\"\nversion = \"0.3.0\"\n");
+        git("test", Some(&repo), &["init", "--quiet"]).unwrap();
+        let first = commit_all(&repo, "first");
+        git("test", Some(&repo), &["tag", "v1"]).unwrap();
+
+        let app = dir.join("app");
+        package(&app.join("shapes"), "[package]\nname = \"shapes\"\nversion = \"0.1.0\"\n");
+        let manifest = PackageManifest::parse(&format!(
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n[dependencies]\ncolors = {{ git = {:?}, tag = \"v1\" }}\nshapes = {{ path = \"shapes\" }}\n",
+            repo.display().to_string()
+        ))
+        .unwrap();
+
+        let resolver = Resolver::with_cache(&cache);
+        let resolution = resolver.resolve(&app, &manifest, None).unwrap();
+        let names: Vec<&str> = resolution.lockfile.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["colors", "shapes", "util"]);
+        let colors = resolution.lockfile.get("colors").unwrap();
+        assert_eq!(colors.commit.as_deref(), Some(first.as_str()));
+        assert_eq!(colors.version, "1.0.0");
+        assert_eq!(resolution.lockfile.get("shapes").unwrap().source, Source::Path(PathBuf::from("shapes")));
+        let checkout = &resolution.packages["colors"];
+        assert!(checkout.starts_with(&cache));
+        assert_eq!(resolution.packages["util"], checkout.join("util"));
+
+        // The lock keeps the pinned commit after the tag moves
+        fs::write(repo.join("lib.woke"), "").unwrap();
+        let second = commit_all(&repo, "second");
+        git("test", Some(&repo), &["tag", "-f", "v1"]).unwrap();
+        let relocked = resolver.resolve(&app, &manifest, Some(&resolution.lockfile)).unwrap();
+        assert_eq!(relocked.lockfile, resolution.lockfile);
+        let updated = resolver.resolve(&app, &manifest, None).unwrap();
+        assert_eq!(updated.lockfile.get("colors").unwrap().commit.as_deref(), Some(second.as_str()));
+        assert!(updated.packages["colors"].join("lib.woke").is_file());
+
+        // Two places asking for the same name from different sources
+        package(&app.join("other/util"), "[package]\nname = \"util\"\nversion = \"9.0.0\"\n");
+        let mut conflicting = manifest.clone();
+        conflicting.dependencies.insert("util".to_string(), Source::Path(PathBuf::from("other/util")));
+        let error = resolver.resolve(&app, &conflicting, None).unwrap_err();
+        assert!(matches!(error, ProjectError::Conflict { ref name, .. } if name == "util"), "{}", error);
+
+        let mut missing = PackageManifest::new("app");
+        missing.dependencies.insert("shapes".to_string(), Source::Path(PathBuf::from("other")));
+        assert!(resolver.resolve(&app, &missing, None).is_err());
+
+        // Nothing reaches git as an option, and a lockfile's commit must
+        // be a hash
+        let mut option = PackageManifest::new("app");
+        let reference = GitReference::Branch("--upload-pack=touch pwned".to_string());
+        option.dependencies.insert("colors".to_string(), Source::Git { url: repo.display().to_string(), reference });
+        let error = resolver.resolve(&app, &option, None).unwrap_err();
+        assert!(error.to_string().contains("cannot start with '-'"), "{}", error);
+        let mut forged = resolution.lockfile.clone();
+        forged.packages.iter_mut().for_each(|p| p.commit = p.commit.as_ref().map(|_| "é".repeat(12)));
+        let error = resolver.resolve(&app, &manifest, Some(&forged)).unwrap_err();
+        assert!(error.to_string().contains("is not a commit hash"), "{}", error);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
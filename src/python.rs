@@ -0,0 +1,457 @@
+//! Python bindings
+//!
+//! Built with the `python` feature, the library is also the Python module
+//! `wokelang`. `wokelang.run(source)` runs a whole program, and
+//! `wokelang.Interpreter` keeps a session whose `eval` gives back the value
+//! of the snippet it ran as a native Python object. Program output goes to
+//! Python's `sys.stdout`, so it shows up in notebooks.
+//!
+//! Nothing is granted unless the host says so: `consent` is `"allow"`,
+//! `"deny"` (the default) or `"ask"`, or a callable that is given the
+//! question and returns whether to grant it. It decides both consent
+//! blocks and superpower requests; an exception it raises refuses the
+//! request and is raised again once the program stops.
+
+use crate::interpreter::{Interpreter as Inner, Value};
+use crate::pipeline::{Diagnostics, Pipeline};
+use crate::security::ConsentMode;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
+use pyo3::types::{PyBool, PyByteArray, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+
+create_exception!(
+    wokelang,
+    WokeError,
+    PyException,
+    "A program failed to lex, parse or run; `code` is its WOKE error code"
+);
+
+/// An error raised by a consent callback, kept until the program stops
+type Pending = Rc<RefCell<Option<PyErr>>>;
+
+/// A successful result, `Okay(value)`
+#[pyclass(module = "wokelang", frozen)]
+pub struct Okay {
+    #[pyo3(get)]
+    value: PyObject,
+}
+
+#[pymethods]
+impl Okay {
+    #[new]
+    fn new(value: PyObject) -> Self {
+        Self { value }
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("Okay({})", self.value.bind(py).repr()?))
+    }
+
+    fn __eq__(&self, py: Python<'_>, other: &Bound<'_, PyAny>) -> PyResult<bool> {
+        match other.downcast::<Okay>() {
+            Ok(other) => self.value.bind(py).eq(&other.get().value),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+/// A failed result, `Oops(error)`
+#[pyclass(module = "wokelang", frozen)]
+pub struct Oops {
+    #[pyo3(get)]
+    error: PyObject,
+}
+
+#[pymethods]
+impl Oops {
+    #[new]
+    fn new(error: PyObject) -> Self {
+        Self { error }
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("Oops({})", self.error.bind(py).repr()?))
+    }
+
+    fn __eq__(&self, py: Python<'_>, other: &Bound<'_, PyAny>) -> PyResult<bool> {
+        match other.downcast::<Oops>() {
+            Ok(other) => self.error.bind(py).eq(&other.get().error),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+/// A WokeLang session: definitions and variables stay around between
+/// calls to `eval`
+#[pyclass(name = "Interpreter", module = "wokelang", unsendable)]
+pub struct PyInterpreter {
+    inner: Inner,
+    pending: Pending,
+}
+
+#[pymethods]
+impl PyInterpreter {
+    #[new]
+    #[pyo3(signature = (consent = None))]
+    fn new(consent: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        let pending = Pending::default();
+        let inner = configure(Inner::new().with_output(PyStdout), consent, &pending)?;
+        Ok(Self { inner, pending })
+    }
+
+    /// Run source as the REPL does, giving back the value of its final
+    /// expression, or None
+    fn eval(&mut self, py: Python<'_>, source: &str) -> PyResult<PyObject> {
+        let result = Pipeline::new().evaluate(&mut self.inner, source);
+        let value = finish(result, &self.pending)?;
+        to_python(py, &value)
+    }
+
+    /// Give a global variable a value the program can use
+    fn define(&mut self, name: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.inner.define_global(name, from_python(value)?);
+        Ok(())
+    }
+
+    /// The value of a global variable, or None if it is not defined
+    fn get(&self, py: Python<'_>, name: &str) -> PyResult<Option<PyObject>> {
+        self.inner
+            .globals()
+            .into_iter()
+            .find(|(global, _)| *global == name)
+            .map(|(_, value)| to_python(py, value))
+            .transpose()
+    }
+
+    /// Decide consent requests with `callback` from now on; gives it back,
+    /// so this can be used as a decorator
+    fn on_consent(&mut self, callback: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        if !callback.is_callable() {
+            return Err(PyTypeError::new_err("the consent callback must be callable"));
+        }
+        let inner = std::mem::take(&mut self.inner).with_output(PyStdout);
+        self.inner = configure(inner, Some(callback), &self.pending)?;
+        Ok(callback.clone().unbind())
+    }
+}
+
+/// Run a whole program, as `woke run` does
+#[pyfunction]
+#[pyo3(signature = (source, consent = None))]
+fn run(source: &str, consent: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+    let pending = Pending::default();
+    let mut interpreter = configure(Inner::new().with_output(PyStdout), consent, &pending)?;
+    let result = Pipeline::new().run_on(&mut interpreter, source);
+    finish(result, &pending).map(|_| ())
+}
+
+#[pymodule]
+#[pyo3(name = "wokelang")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    m.add_class::<PyInterpreter>()?;
+    m.add_class::<Okay>()?;
+    m.add_class::<Oops>()?;
+    m.add("WokeError", m.py().get_type::<WokeError>())?;
+    Ok(())
+}
+
+/// Program output, written to Python's `sys.stdout`
+struct PyStdout;
+
+impl io::Write for PyStdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            let stdout = py.import("sys")?.getattr("stdout")?;
+            stdout.call_method1("write", (String::from_utf8_lossy(buf),))?;
+            Ok::<_, PyErr>(())
+        })
+        .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Python::with_gil(|py| {
+            py.import("sys")?.getattr("stdout")?.call_method0("flush")?;
+            Ok::<_, PyErr>(())
+        })
+        .map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+/// Answer consent requests the way `consent` says
+fn configure(mut interpreter: Inner, consent: Option<&Bound<'_, PyAny>>, pending: &Pending) -> PyResult<Inner> {
+    let Some(consent) = consent else {
+        return Ok(interpreter.with_deny_all());
+    };
+    if !consent.is_callable() {
+        let name: String = consent.extract()?;
+        let mode = ConsentMode::parse(&name)
+            .ok_or_else(|| PyValueError::new_err(format!("unknown consent mode '{}' (use ask, allow or deny)", name)))?;
+        return Ok(interpreter.with_consent_mode(mode));
+    }
+
+    let ask = |callback: PyObject, pending: Pending| {
+        move |question: &str| {
+            Python::with_gil(|py| match callback.call1(py, (question,)).and_then(|answer| answer.bind(py).is_truthy()) {
+                Ok(granted) => granted,
+                Err(e) => {
+                    pending.borrow_mut().get_or_insert(e);
+                    false
+                }
+            })
+        }
+    };
+    interpreter
+        .capabilities_mut()
+        .set_prompt_handler(ask(consent.clone().unbind(), pending.clone()));
+    let consent_ask = ask(consent.clone().unbind(), pending.clone());
+    Ok(interpreter.with_consent_handler(move |permission| consent_ask(&format!("Allow '{}'?", permission))))
+}
+
+/// Raise what a consent callback raised, or the program's own error
+fn finish<T>(result: Result<T, Diagnostics>, pending: &Pending) -> PyResult<T> {
+    if let Some(e) = pending.borrow_mut().take() {
+        return Err(e);
+    }
+    result.map_err(|diagnostics| {
+        let err = WokeError::new_err(diagnostics.to_string());
+        let code = diagnostics.first().code;
+        Python::with_gil(|py| err.value(py).setattr("code", code).map(|_| err))
+            .unwrap_or_else(|e| e)
+    })
+}
+
+/// Convert a value to the Python type closest to it
+fn to_python(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Int(n) => n.into_py_any(py)?,
+        Value::Float(f) => f.into_py_any(py)?,
+        Value::Decimal(d) => py.import("decimal")?.getattr("Decimal")?.call1((d.to_string(),))?.unbind(),
+        Value::String(s) => s.as_str().into_py_any(py)?,
+        Value::Bool(b) => b.into_py_any(py)?,
+        Value::Unit => py.None(),
+        Value::Bytes(bytes) => PyBytes::new(py, bytes).into_any().unbind(),
+        Value::Array(items) => {
+            let items = items.iter().map(|item| to_python(py, item)).collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, items)?.into_any().unbind()
+        }
+        Value::Record(fields) => {
+            let mut keys: Vec<_> = fields.keys().collect();
+            keys.sort();
+            let dict = PyDict::new(py);
+            for key in keys {
+                dict.set_item(key, to_python(py, &fields[key])?)?;
+            }
+            dict.into_any().unbind()
+        }
+        Value::DateTime(dt) => {
+            use chrono::{Datelike, Timelike};
+            let datetime = py.import("datetime")?;
+            let offset = datetime
+                .getattr("timedelta")?
+                .call1((0, dt.offset().local_minus_utc()))?;
+            let tz = datetime.getattr("timezone")?.call1((offset,))?;
+            datetime
+                .getattr("datetime")?
+                .call1((
+                    dt.year(),
+                    dt.month(),
+                    dt.day(),
+                    dt.hour(),
+                    dt.minute(),
+                    dt.second(),
+                    dt.nanosecond().min(999_999_999) / 1000,
+                    tz,
+                ))?
+                .unbind()
+        }
+        Value::Duration(d) => py
+            .import("datetime")?
+            .getattr("timedelta")?
+            .call1((0, d.num_seconds(), d.subsec_nanos() / 1000))?
+            .unbind(),
+        Value::Okay(inner) => Py::new(py, Okay { value: to_python(py, inner)? })?.into_any(),
+        Value::Oops(error) => Py::new(py, Oops { error: to_python(py, error)? })?.into_any(),
+        Value::Function(_) | Value::VmFunction(_) => return Err(PyTypeError::new_err("a function cannot be converted to Python")),
+        Value::Channel(_) => return Err(PyTypeError::new_err("a channel cannot be converted to Python")),
+    })
+}
+
+/// Convert a Python object to the value closest to it
+fn from_python(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    let py = obj.py();
+    if obj.is_none() {
+        return Ok(Value::Unit);
+    }
+    // `bool` is a subclass of `int`, so it comes first
+    if let Ok(b) = obj.downcast::<PyBool>() {
+        return Ok(Value::Bool(b.is_true()));
+    }
+    if obj.is_instance_of::<PyInt>() {
+        return Ok(Value::Int(obj.extract()?));
+    }
+    if let Ok(f) = obj.downcast::<PyFloat>() {
+        return Ok(Value::Float(f.value()));
+    }
+    if let Ok(s) = obj.downcast::<PyString>() {
+        return Ok(Value::String(s.to_str()?.to_string().into()));
+    }
+    if let Ok(bytes) = obj.downcast::<PyBytes>() {
+        return Ok(Value::Bytes(bytes.as_bytes().to_vec()));
+    }
+    if let Ok(bytes) = obj.downcast::<PyByteArray>() {
+        return Ok(Value::Bytes(bytes.to_vec()));
+    }
+    if let Ok(list) = obj.downcast::<PyList>() {
+        return list.iter().map(|item| from_python(&item)).collect::<PyResult<Vec<_>>>().map(|items| Value::Array(items.into()));
+    }
+    if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        return tuple.iter().map(|item| from_python(&item)).collect::<PyResult<Vec<_>>>().map(|items| Value::Array(items.into()));
+    }
+    if let Ok(dict) = obj.downcast::<PyDict>() {
+        let mut fields = HashMap::with_capacity(dict.len());
+        for (key, value) in dict.iter() {
+            let Ok(key) = key.extract::<String>() else {
+                return Err(PyTypeError::new_err(format!("record keys must be strings, not {}", key.repr()?)));
+            };
+            fields.insert(key, from_python(&value)?);
+        }
+        return Ok(Value::Record(fields));
+    }
+    if let Ok(okay) = obj.downcast::<Okay>() {
+        return Ok(Value::Okay(Box::new(from_python(okay.get().value.bind(py))?)));
+    }
+    if let Ok(oops) = obj.downcast::<Oops>() {
+        return Ok(Value::Oops(Box::new(from_python(oops.get().error.bind(py))?)));
+    }
+
+    if obj.is_instance(&py.import("decimal")?.getattr("Decimal")?)? {
+        let text: String = obj.str()?.extract()?;
+        return text
+            .parse()
+            .map(Value::Decimal)
+            .map_err(|_| PyValueError::new_err(format!("Decimal {} is out of range", text)));
+    }
+    let datetime = py.import("datetime")?;
+    if obj.is_instance(&datetime.getattr("datetime")?)? {
+        let text: String = obj.call_method0("isoformat")?.extract()?;
+        return chrono::DateTime::parse_from_rfc3339(&text)
+            .map(Value::DateTime)
+            .map_err(|_| PyValueError::new_err(format!("{} has no timezone; give it a tzinfo", text)));
+    }
+    if obj.is_instance(&datetime.getattr("timedelta")?)? {
+        let days: i64 = obj.getattr("days")?.extract()?;
+        let seconds: i64 = obj.getattr("seconds")?.extract()?;
+        let micros: i64 = obj.getattr("microseconds")?.extract()?;
+        return Ok(Value::Duration(
+            chrono::TimeDelta::days(days) + chrono::TimeDelta::seconds(seconds) + chrono::TimeDelta::microseconds(micros),
+        ));
+    }
+    Err(PyTypeError::new_err(format!(
+        "{} objects cannot be converted to WokeLang values",
+        obj.get_type().name()?
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_module<R>(f: impl FnOnce(Python<'_>, &Bound<'_, PyModule>) -> R) -> R {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let m = pyo3::wrap_pymodule!(python_module)(py);
+            f(py, m.bind(py))
+        })
+    }
+
+    #[test]
+    fn test_eval_converts_values() {
+        with_module(|py, m| {
+            let locals = PyDict::new(py);
+            locals.set_item("wokelang", m).unwrap();
+            py.run(
+                cr#"
+import datetime
+import decimal
+woke = wokelang.Interpreter()
+woke.define("data", {"name": "Ada", "scores": [3, 4.5], "when": datetime.timedelta(seconds=90)})
+assert woke.eval('data["name"]') == "Ada"
+assert woke.eval('data["scores"]') == [3, 4.5]
+assert woke.eval('data["when"]') == datetime.timedelta(seconds=90)
+woke.define("price", decimal.Decimal("19.90"))
+assert str(woke.eval("price * 2")) == "39.80"
+woke.eval("to half(n: Int) -> Int { give back n / 2; }")
+assert woke.eval("half(10)") == 5
+assert woke.eval("Okay(1)") == wokelang.Okay(1)
+assert woke.eval("Oops(\"no\")") == wokelang.Oops("no")
+woke.define("reason", {"code": 404, "path": "/missing"})
+failed = woke.eval("Oops(reason)")
+assert failed == wokelang.Oops({"code": 404, "path": "/missing"})
+assert failed.error["code"] == 404
+woke.define("failed", wokelang.Oops([1, "two"]))
+assert woke.eval('failed == Oops([1, "two"])') is True
+assert woke.eval("remember x = 1;") is None
+assert woke.get("x") == 1 and woke.get("y") is None
+woke.define("blob", b"hi")
+assert woke.eval("[true, blob]") == [True, b"hi"]
+"#,
+                Some(&locals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_errors_and_consent() {
+        with_module(|py, m| {
+            let locals = PyDict::new(py);
+            locals.set_item("wokelang", m).unwrap();
+            py.run(
+                cr#"
+woke = wokelang.Interpreter()
+try:
+    woke.eval("1 / 0")
+    assert False
+except wokelang.WokeError as e:
+    assert e.code.startswith("WOKE-E"), e.code
+    assert str(e).startswith("Runtime error")
+
+asked = []
+@woke.on_consent
+def ask(question):
+    asked.append(question)
+    return question == "Allow 'camera'?"
+
+import io, sys
+sys.stdout = out = io.StringIO()
+woke.eval('only if okay "camera" { print("camera on"); }')
+woke.eval('only if okay "microphone" { print("microphone on"); }')
+sys.stdout = sys.__stdout__
+assert out.getvalue() == "camera on\n", out.getvalue()
+assert asked == ["Allow 'camera'?", "Allow 'microphone'?"], asked
+
+def fail(question):
+    raise KeyError(question)
+woke.on_consent(fail)
+try:
+    woke.eval('only if okay "location" { print("here"); }')
+    assert False
+except KeyError:
+    pass
+"#,
+                Some(&locals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
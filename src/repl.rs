@@ -5,22 +5,37 @@
 //! - Persistent history saved to ~/.woke_history
 //! - Tab completion for keywords and defined identifiers
 //! - Linting/type checking before evaluation
-//! - Environment inspection
+//! - The value of an expression typed after any definitions is printed
+//! - Statements run at the top level, and `:env` to see the variables,
+//!   functions, workers and consents they leave
+//! - Definitions saved to a file with `:save` and restored with `:load session`
+//! - Expressions watched with `:watch`, shown again after every evaluation
+//! - Checkpoints of the session with `:checkpoint`, gone back to with
+//!   `:rewind`, and `:reset --keep-functions` to start over with the
+//!   functions kept
 
-use crate::ast::TopLevelItem;
-use crate::interpreter::Interpreter;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
-use crate::typechecker::TypeChecker;
+use crate::ast::{Assignment, Expr, FunctionDef, Program, ReturnStmt, Span, Spanned, Statement, Symbol, TopLevelItem};
+use crate::formatter::{format_program, format_signature};
+use crate::interpreter::{Interpreter, InterpreterSnapshot, RuntimeError, Value};
+use crate::parser::Snippet;
+use crate::pipeline::{Diagnostics, Engine, Pipeline};
+use crate::stdlib::docs;
+use crate::testing::line_col;
+use crate::typechecker::{InferredType, TypeChecker, TypeError};
+use crate::vm::{self, BytecodeCompiler, Optimizer, VirtualMachine};
 use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
-use rustyline::highlight::Highlighter;
+use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::Hinter;
 use rustyline::history::DefaultHistory;
 use rustyline::validate::{ValidationContext, ValidationResult, Validator};
-use rustyline::{Editor, Helper};
+use rustyline::{
+    Cmd, ConditionalEventHandler, Editor, Event, EventContext, EventHandler, Helper, KeyEvent, Movement,
+    RepeatCount,
+};
 use std::borrow::Cow;
 use std::collections::HashSet;
+use std::time::Instant;
 
 const BANNER: &str = r#"
  __        __    _        _
@@ -37,15 +52,40 @@
   :quit, :q        Exit the REPL
   :clear, :c       Clear the screen
   :reset, :r       Reset interpreter state
+  :reset --keep-functions
+                   Forget variables, consents and gratitude, keeping the
+                   functions and other definitions
+  :checkpoint [name]
+                   Keep the session as it is now to go back to, or list the
+                   checkpoints kept
+  :rewind [name]   Go back to a checkpoint, the latest by default
   :load <file>     Load and run a file
-  :ast <expr>      Show AST for an expression
+  :load session <file>
+                   Replace the session with definitions saved by :save
+  :save <file>     Save the definitions entered so far as a .woke file
+  :ast [code]      Show the AST of a snippet, or of the session so far
+  :tokens [code]   Show the tokens of a snippet, or of the session so far
   :type <expr>     Show inferred type for an expression
-  :env             Show current environment variables
+  :time <expr>     Evaluate an expression and show how long it took
+  :disasm <code>   Show the VM bytecode of a snippet or a defined function
+                   (optimized; --raw for the compiler's own output)
+  :env             Show variables with their types and values, functions,
+                   workers and consents
+  :doc <name>      Show a function's signature, superpowers and summary,
+                   e.g. :doc std.math.sqrt; a module name lists its functions
   :lint            Toggle linting (type checking) before execution
+  :vm              Switch between the interpreter and the bytecode VM
+  :watch [expr]    Show an expression's value after every evaluation, or
+                   list the expressions watched
+  :unwatch [n|expr]
+                   Stop watching an expression, or all of them
   :history         Show command history
 
 Multiline Input:
   - Incomplete expressions automatically continue on the next line
+  - The prompt shows the innermost bracket left open, e.g. ...{>
+  - Continuation lines start indented; typing a closing bracket first
+    takes one level off
   - End multi-line input with a complete statement/expression
   - Press Ctrl+C to cancel multi-line input
 
@@ -56,26 +96,40 @@
   double(21)
 "#;
 
+/// What `:disasm` calls the function it compiles an expression into
+const EXPRESSION_FUNCTION: &str = "<expression>";
+
+/// One level of indentation in multiline input
+const INDENT: &str = "    ";
+
+/// How many elements of an array `:env` shows
+const PREVIEW_ELEMENTS: usize = 10;
+
 /// Keywords for tab completion
 const KEYWORDS: &[&str] = &[
     "to", "remember", "give", "back", "when", "otherwise", "repeat", "times",
     "while", "decide", "based", "on", "attempt", "safely", "or", "reassure",
     "only", "if", "okay", "thanks", "worker", "spawn", "hello", "goodbye",
-    "complain", "Int", "Float", "String", "Bool", "Unit", "Maybe", "Result",
-    "Okay", "Oops", "unwrap", "true", "false", "print", "len", "toString",
-    "toInt", "isOkay", "isOops", "unwrapOr", "getError",
+    "complain", "Int", "Float", "Decimal", "String", "Bool", "Unit", "Maybe", "Result",
+    "Okay", "Oops", "unwrap", "true", "false", "print", "prettyPrint", "len", "toString",
+    "toInt", "isOkay", "isOops", "unwrapOr", "getError", "ask", "askNumber", "askYesNo", "exit",
 ];
 
 /// REPL helper for rustyline (completion, validation, hints)
 #[derive(Helper)]
 struct WokeHelper {
     identifiers: HashSet<String>,
+    /// The lines of unfinished multiline input so far, each ending in `\n`
+    pending: String,
+    brackets: MatchingBracketHighlighter,
 }
 
 impl WokeHelper {
     fn new() -> Self {
         Self {
             identifiers: HashSet::new(),
+            pending: String::new(),
+            brackets: MatchingBracketHighlighter::new(),
         }
     }
 
@@ -136,63 +190,56 @@ fn complete(
 impl Hinter for WokeHelper {
     type Hint = String;
 
-    fn hint(&self, _line: &str, _pos: usize, _ctx: &rustyline::Context<'_>) -> Option<String> {
-        None // No hints for now
+    fn hint(&self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>) -> Option<String> {
+        match pos == line.len() {
+            true => closing_hint(&self.pending, line),
+            false => None,
+        }
     }
 }
 
 impl Highlighter for WokeHelper {
-    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
-        Cow::Borrowed(line) // No highlighting for now
+    /// Highlight the bracket matching the one at the cursor on the same
+    /// line; one on an earlier line is named in a hint instead
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        self.brackets.highlight(line, pos)
     }
 
-    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
-        false
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+
+    fn highlight_char(&self, line: &str, pos: usize, forced: bool) -> bool {
+        self.brackets.highlight_char(line, pos, forced)
     }
 }
 
 impl Validator for WokeHelper {
+    /// Reject a closing bracket with nothing to close; unfinished input is
+    /// accepted line by line and continued by the REPL itself
     fn validate(&self, ctx: &mut ValidationContext<'_>) -> rustyline::Result<ValidationResult> {
-        let input = ctx.input();
-
-        // Check for balanced braces/brackets/parens
-        let mut brace_count = 0i32;
-        let mut bracket_count = 0i32;
-        let mut paren_count = 0i32;
-        let mut in_string = false;
-        let mut prev_char = ' ';
-
-        for c in input.chars() {
-            if c == '"' && prev_char != '\\' {
-                in_string = !in_string;
-            }
-            if !in_string {
-                match c {
-                    '{' => brace_count += 1,
-                    '}' => brace_count -= 1,
-                    '[' => bracket_count += 1,
-                    ']' => bracket_count -= 1,
-                    '(' => paren_count += 1,
-                    ')' => paren_count -= 1,
-                    _ => {}
-                }
-            }
-            prev_char = c;
+        let input = format!("{}{}", self.pending, ctx.input());
+        match nesting(&input).unmatched {
+            true => Ok(ValidationResult::Invalid(Some(
+                "Unmatched closing bracket/brace/paren".to_string(),
+            ))),
+            false => Ok(ValidationResult::Valid(None)),
         }
+    }
+}
 
-        // If any count is positive, input is incomplete
-        if brace_count > 0 || bracket_count > 0 || paren_count > 0 || in_string {
-            return Ok(ValidationResult::Incomplete);
-        }
+/// Takes one level of indentation off a line as a closing bracket is typed
+/// at the start of it
+struct Dedent(char);
 
-        // If any count is negative, there's an error
-        if brace_count < 0 || bracket_count < 0 || paren_count < 0 {
-            return Ok(ValidationResult::Invalid(Some(
-                "Unmatched closing bracket/brace/paren".to_string(),
-            )));
+impl ConditionalEventHandler for Dedent {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        let before = &ctx.line()[..ctx.pos()];
+        if before.len() < INDENT.len() || !before.chars().all(|c| c == ' ') {
+            return None;
         }
-
-        Ok(ValidationResult::Valid(None))
+        let dedented = format!("{}{}", &before[INDENT.len()..], self.0);
+        Some(Cmd::Replace(Movement::BeginningOfLine, Some(dedented)))
     }
 }
 
@@ -202,7 +249,25 @@ pub struct Repl {
     typechecker: TypeChecker,
     editor: Editor<WokeHelper, DefaultHistory>,
     lint_enabled: bool,
+    /// What runs the code entered; `:vm` switches it
+    engine: Engine,
     history_path: Option<std::path::PathBuf>,
+    /// Definitions entered and loaded so far, a later one replacing any
+    /// earlier one of the same name
+    session: Program,
+    /// Expressions `:watch` shows after every evaluation, as typed and
+    /// as parsed
+    watches: Vec<(String, Spanned<Expr>)>,
+    /// Checkpoints kept with `:checkpoint`, oldest first
+    checkpoints: Vec<Checkpoint>,
+}
+
+/// The session as it was at a `:checkpoint`
+struct Checkpoint {
+    name: String,
+    interpreter: InterpreterSnapshot,
+    typechecker: TypeChecker,
+    session: Program,
 }
 
 impl Repl {
@@ -216,6 +281,9 @@ pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
         let helper = WokeHelper::new();
         let mut editor = Editor::with_config(config)?;
         editor.set_helper(Some(helper));
+        for bracket in ['}', ']', ')'] {
+            editor.bind_sequence(KeyEvent::from(bracket), EventHandler::Conditional(Box::new(Dedent(bracket))));
+        }
 
         // Try to load history
         let history_path = dirs::home_dir().map(|p| p.join(".woke_history"));
@@ -228,7 +296,11 @@ pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
             typechecker: TypeChecker::new(),
             editor,
             lint_enabled: true,
+            engine: Engine::Interpreter,
             history_path,
+            session: Program { items: Vec::new() },
+            watches: Vec::new(),
+            checkpoints: Vec::new(),
         })
     }
 
@@ -245,8 +317,17 @@ pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let mut in_multiline = false;
 
         loop {
-            let prompt = if in_multiline { "...> " } else { "woke> " };
-            let readline = self.editor.readline(prompt);
+            let (prompt, indent) = match in_multiline {
+                true => continuation(&multiline_buffer),
+                false => ("woke> ".to_string(), String::new()),
+            };
+            if let Some(helper) = self.editor.helper_mut() {
+                helper.pending = match in_multiline {
+                    true => format!("{}\n", multiline_buffer),
+                    false => String::new(),
+                };
+            }
+            let readline = self.editor.readline_with_initial(&prompt, (&indent, ""));
 
             match readline {
                 Ok(line) => {
@@ -312,31 +393,7 @@ pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
     }
 
     fn is_complete(&self, input: &str) -> bool {
-        let mut brace_count = 0i32;
-        let mut bracket_count = 0i32;
-        let mut paren_count = 0i32;
-        let mut in_string = false;
-        let mut prev_char = ' ';
-
-        for c in input.chars() {
-            if c == '"' && prev_char != '\\' {
-                in_string = !in_string;
-            }
-            if !in_string {
-                match c {
-                    '{' => brace_count += 1,
-                    '}' => brace_count -= 1,
-                    '[' => bracket_count += 1,
-                    ']' => bracket_count -= 1,
-                    '(' => paren_count += 1,
-                    ')' => paren_count -= 1,
-                    _ => {}
-                }
-            }
-            prev_char = c;
-        }
-
-        brace_count == 0 && bracket_count == 0 && paren_count == 0 && !in_string
+        nesting(input).complete()
     }
 
     fn handle_command(&mut self, line: &str) -> Result<bool, Box<dyn std::error::Error>> {
@@ -355,38 +412,147 @@ fn handle_command(&mut self, line: &str) -> Result<bool, Box<dyn std::error::Err
             ":clear" | ":c" => {
                 print!("\x1B[2J\x1B[1;1H");
             }
-            ":reset" | ":r" => {
-                self.interpreter = Interpreter::new();
-                self.typechecker = TypeChecker::new();
-                if let Some(helper) = self.editor.helper_mut() {
-                    helper.identifiers.clear();
+            ":reset" | ":r" => match arg {
+                None => {
+                    self.interpreter = Interpreter::new();
+                    self.typechecker = TypeChecker::new();
+                    self.session.items.clear();
+                    if let Some(helper) = self.editor.helper_mut() {
+                        helper.identifiers.clear();
+                    }
+                    println!("Interpreter and type checker state reset.");
                 }
-                println!("Interpreter and type checker state reset.");
-            }
-            ":load" | ":l" => {
-                if let Some(path) = arg {
-                    self.load_file(path);
+                Some("--keep-functions") => {
+                    let kept = self.reset_keeping_functions();
+                    println!(
+                        "Variables, consents and gratitude forgotten; kept {} function{}.",
+                        kept,
+                        if kept == 1 { "" } else { "s" }
+                    );
+                }
+                Some(_) => println!("Usage: :reset [--keep-functions]"),
+            },
+            ":checkpoint" => match arg {
+                Some(name) => {
+                    self.checkpoint(name);
+                    println!("Checkpoint {} kept.", name);
+                }
+                None if self.checkpoints.is_empty() => println!("No checkpoints kept."),
+                None => {
+                    for checkpoint in &self.checkpoints {
+                        println!(
+                            "  {}: {} definition(s), {} variable(s)",
+                            checkpoint.name,
+                            checkpoint.session.items.len(),
+                            checkpoint.interpreter.globals()
+                        );
+                    }
+                }
+            },
+            ":rewind" => match self.rewind(arg) {
+                Ok(name) => println!("Back at checkpoint {}.", name),
+                Err(message) => eprintln!("{}", message),
+            },
+            ":load" | ":l" => match arg {
+                Some("session") => println!("Usage: :load session <filename>"),
+                Some(arg) => match arg.strip_prefix("session ") {
+                    Some(path) => match self.restore(path.trim()) {
+                        Ok(count) => println!(
+                            "Restored {} definition{} from {}.",
+                            count,
+                            if count == 1 { "" } else { "s" },
+                            path.trim()
+                        ),
+                        Err(message) => eprintln!("{}", message),
+                    },
+                    None => self.load_file(arg),
+                },
+                None => println!("Usage: :load [session] <filename>"),
+            },
+            ":save" => match arg {
+                Some(path) => match self.save(path) {
+                    Ok(count) => println!(
+                        "Saved {} definition{} to {}.",
+                        count,
+                        if count == 1 { "" } else { "s" },
+                        path
+                    ),
+                    Err(message) => eprintln!("{}", message),
+                },
+                None => println!("Usage: :save <filename>"),
+            },
+            ":ast" => match self.ast_of(arg) {
+                Ok(ast) => println!("{}", ast),
+                Err(message) => eprintln!("{}", message),
+            },
+            ":tokens" => match self.tokens_of(arg) {
+                Ok(tokens) => println!("{}", tokens),
+                Err(message) => eprintln!("{}", message),
+            },
+            ":type" | ":t" => {
+                if let Some(code) = arg {
+                    self.show_type(code);
                 } else {
-                    println!("Usage: :load <filename>");
+                    println!("Usage: :type <expression>");
                 }
             }
-            ":ast" => {
+            ":time" => {
                 if let Some(code) = arg {
-                    self.show_ast(code);
+                    self.time(code);
                 } else {
-                    println!("Usage: :ast <expression>");
+                    println!("Usage: :time <expression>");
                 }
             }
-            ":type" | ":t" => {
-                if let Some(code) = arg {
-                    self.show_type(code);
+            ":disasm" => {
+                let (raw, code) = match arg {
+                    Some(arg) => match arg.strip_prefix("--raw") {
+                        Some(code) => (true, code.trim()),
+                        None => (false, arg),
+                    },
+                    None => (false, ""),
+                };
+                if code.is_empty() {
+                    println!("Usage: :disasm [--raw] <expression|function>");
                 } else {
-                    println!("Usage: :type <expression>");
+                    match self.disasm(code, raw) {
+                        Ok(bytecode) => println!("{}", bytecode),
+                        Err(message) => eprintln!("{}", message),
+                    }
                 }
             }
             ":env" => {
-                self.show_env();
+                println!("{}", self.env());
             }
+            ":doc" => match arg {
+                Some(name) => match self.doc(name) {
+                    Ok(doc) => println!("{}", doc),
+                    Err(message) => eprintln!("{}", message),
+                },
+                None => println!("Usage: :doc <function|module>"),
+            },
+            ":vm" => {
+                self.engine = match self.engine {
+                    Engine::Interpreter => Engine::Vm,
+                    Engine::Vm => Engine::Interpreter,
+                };
+                match self.engine {
+                    Engine::Vm => println!("Now running on the bytecode VM; defined functions are recompiled for it"),
+                    Engine::Interpreter => println!("Now running on the interpreter"),
+                }
+            }
+            ":watch" => match arg {
+                Some(code) => match self.watch(code) {
+                    Ok(line) => println!("{}", line),
+                    Err(message) => eprintln!("{}", message),
+                },
+                None if self.watches.is_empty() => println!("Nothing watched; use :watch <expression>"),
+                None => self.show_watches(),
+            },
+            ":unwatch" => match self.unwatch(arg) {
+                Ok(0) => println!("Nothing watched"),
+                Ok(count) => println!("Stopped watching {} expression{}", count, if count == 1 { "" } else { "s" }),
+                Err(message) => eprintln!("{}", message),
+            },
             ":lint" => {
                 self.lint_enabled = !self.lint_enabled;
                 println!(
@@ -411,170 +577,959 @@ fn handle_command(&mut self, line: &str) -> Result<bool, Box<dyn std::error::Err
     }
 
     fn process_input(&mut self, input: &str) {
-        // Try to parse as a program (statements/definitions)
-        let lexer = Lexer::new(input);
-        let tokens = match lexer.tokenize() {
-            Ok(t) => t,
-            Err(e) => {
-                eprintln!("Lexer error: {:?}", e);
-                return;
-            }
-        };
+        let result = self.eval(input);
+        self.show_result(result);
+        self.show_watches();
+    }
 
-        let mut parser = Parser::new(tokens.clone(), input);
+    /// Run definitions and evaluate the expression after them, if any
+    fn eval(&mut self, input: &str) -> Result<Option<Value>, String> {
+        let snippet = self.prepare(input)?;
+        self.execute(&snippet)
+    }
 
-        // First, try parsing as a full program
-        match parser.parse() {
-            Ok(program) => {
-                // Collect identifiers for completion
-                for item in &program.items {
-                    if let TopLevelItem::Function(f) = item {
-                        if let Some(helper) = self.editor.helper_mut() {
-                            helper.add_identifier(&f.name);
-                        }
-                    }
+    /// Parse a snippet and, if linting is enabled, type check it
+    fn prepare(&mut self, input: &str) -> Result<Snippet, String> {
+        let snippet = parse_snippet(input)?;
+        self.complete_names(&snippet.program);
+
+        if self.lint_enabled {
+            Pipeline::new()
+                .check_snippet(&mut self.typechecker, &snippet)
+                .map_err(diagnostics_error)?;
+        }
+        Ok(snippet)
+    }
+
+    /// Run a prepared snippet's definitions and statements, then evaluate
+    /// its expression
+    fn execute(&mut self, snippet: &Snippet) -> Result<Option<Value>, String> {
+        if !snippet.program.items.is_empty() {
+            self.run_program(&snippet.program)?;
+        }
+        match self.engine {
+            Engine::Interpreter => {
+                self.interpreter.exec_snippet(&snippet.statements).map_err(runtime_error)?;
+                snippet
+                    .expr
+                    .as_ref()
+                    .map(|expr| self.interpreter.eval_snippet(expr).map_err(runtime_error))
+                    .transpose()
+            }
+            Engine::Vm if snippet.statements.is_empty() && snippet.expr.is_none() => Ok(None),
+            Engine::Vm => {
+                let value = self.run_on_vm(snippet.statements.clone(), snippet.expr.clone())?;
+                Ok(snippet.expr.as_ref().map(|_| value))
+            }
+        }
+    }
+
+    /// Keep a program's definitions in the session and run it as `woke run`
+    /// would, on the current engine
+    fn run_program(&mut self, program: &Program) -> Result<(), String> {
+        self.accept(program);
+        match self.engine {
+            Engine::Interpreter => self.interpreter.run(program).map_err(runtime_error),
+            Engine::Vm => {
+                // The interpreter keeps the definitions either way, so that
+                // switching back finds them
+                self.interpreter.load(program);
+                if defines(program, "main") {
+                    let call = Spanned::new(Expr::Call(Symbol::intern("main"), Vec::new()), 0..0);
+                    self.run_on_vm(Vec::new(), Some(call))?;
                 }
+                Ok(())
+            }
+        }
+    }
 
-                // Type check if linting is enabled
-                if self.lint_enabled {
-                    if let Err(e) = self.typechecker.check_program(&program) {
-                        eprintln!("Type error: {}", e);
-                        return;
+    /// Run statements and then an expression on the VM, with the session's
+    /// functions recompiled alongside them
+    ///
+    /// The interpreter's global variables are the VM's globals for the run,
+    /// and take back whatever values the VM leaves them, so both engines
+    /// share one set of variables.
+    fn run_on_vm(&mut self, statements: Vec<Statement>, expr: Option<Spanned<Expr>>) -> Result<Value, String> {
+        // Variables declared at the top level outlive the snippet, so they
+        // are stored as globals rather than in the function's locals
+        let mut secrets = HashSet::new();
+        let body = statements
+            .into_iter()
+            .map(|stmt| match stmt {
+                Statement::VarDecl(decl) => {
+                    if decl.secret {
+                        secrets.insert(decl.name.as_str());
                     }
+                    Statement::Assignment(Assignment { target: decl.name, value: decl.value, span: decl.span })
                 }
+                other => other,
+            })
+            .collect();
+        let mut program = self.session.clone();
+        program.items.push(expression_function(body, expr, 0..0));
 
-                if let Err(e) = self.interpreter.run(&program) {
-                    eprintln!("Runtime error: {}", e);
+        let mut compiled = BytecodeCompiler::new()
+            .compile(&program)
+            .map_err(|e| format!("Compile error: {}", e))?;
+        compiled.entry = compiled.function_index(EXPRESSION_FUNCTION);
+        for (name, value) in self.interpreter.globals() {
+            compiled.globals.insert(name.to_string(), value.clone());
+        }
+
+        let capabilities = std::mem::take(self.interpreter.capabilities_mut());
+        let mut vm = VirtualMachine::new(compiled).with_capabilities(capabilities);
+        let result = vm.run().map_err(|e| match e.code {
+            Some(code) => format!("Runtime error [{}]: {}", code, e.message),
+            None => format!("Runtime error: {}", e),
+        });
+        for (name, value) in vm.globals() {
+            self.interpreter.define_global(name, value.clone().secret_if(secrets.contains(name)));
+        }
+        *self.interpreter.capabilities_mut() = std::mem::take(vm.capabilities_mut());
+        result
+    }
+
+    /// Keep a program's definitions in the session, in place of those of
+    /// the same name
+    fn accept(&mut self, program: &Program) {
+        for item in &program.items {
+            let earlier = definition_name(item).and_then(|name| {
+                self.session.items.iter().position(|kept| {
+                    std::mem::discriminant(kept) == std::mem::discriminant(item)
+                        && definition_name(kept) == Some(name)
+                })
+            });
+            match earlier {
+                Some(index) => self.session.items[index] = item.clone(),
+                None => self.session.items.push(item.clone()),
+            }
+        }
+    }
+
+    /// Add a program's functions to tab completion
+    fn complete_names(&mut self, program: &Program) {
+        if let Some(helper) = self.editor.helper_mut() {
+            for item in &program.items {
+                if let TopLevelItem::Function(f) = item {
+                    helper.add_identifier(&f.name);
                 }
             }
-            Err(_) => {
-                // Try wrapping as an expression in a function and evaluating
-                let wrapped = format!(
-                    "to __repl_expr__() {{ remember __result__ = {}; print(__result__); }}
-                     to main() {{ __repl_expr__(); }}",
-                    input.trim_end_matches(';')
-                );
+        }
+    }
 
-                let lexer = Lexer::new(&wrapped);
-                if let Ok(tokens) = lexer.tokenize() {
-                    let mut parser = Parser::new(tokens, &wrapped);
-                    if let Ok(program) = parser.parse() {
-                        // Type check if linting is enabled
-                        if self.lint_enabled {
-                            if let Err(e) = self.typechecker.check_program(&program) {
-                                eprintln!("Type error: {}", e);
-                                return;
-                            }
-                        }
+    /// Write the session's definitions to a file as source, returning how
+    /// many there were
+    fn save(&self, path: &str) -> Result<usize, String> {
+        if self.session.items.is_empty() {
+            return Err("Nothing to save; no definitions entered yet".to_string());
+        }
+        std::fs::write(path, format_program(&self.session))
+            .map_err(|e| format!("Could not write {}: {}", path, e))?;
+        Ok(self.session.items.len())
+    }
 
-                        if let Err(e) = self.interpreter.run(&program) {
-                            eprintln!("Error: {}", e);
-                        }
-                    } else {
-                        eprintln!("Parse error in input");
-                    }
-                }
+    /// Replace the session with the definitions in a saved file, without
+    /// running any of them, returning how many there were
+    fn restore(&mut self, path: &str) -> Result<usize, String> {
+        let program = read_program(path)?;
+        let mut typechecker = TypeChecker::new();
+        if self.lint_enabled {
+            typechecker
+                .check_program(&program)
+                .map_err(type_error)?;
+        }
+
+        self.typechecker = typechecker;
+        self.interpreter = Interpreter::new();
+        self.interpreter.load(&program);
+        self.interpreter
+            .define_globals(&program)
+            .map_err(|e| format!("Runtime error: {}", e))?;
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.identifiers.clear();
+        }
+        self.complete_names(&program);
+        self.session = Program { items: Vec::new() };
+        self.accept(&program);
+        Ok(self.session.items.len())
+    }
+
+    /// Forget the variables, consent answers and gratitude built up so far,
+    /// keeping the definitions, returning how many functions there are
+    fn reset_keeping_functions(&mut self) -> usize {
+        let definitions = self.interpreter.snapshot().definitions();
+        self.interpreter.restore(&definitions);
+        self.session
+            .items
+            .retain(|item| {
+                !matches!(
+                    item,
+                    TopLevelItem::GratitudeDecl(_) | TopLevelItem::ConsentBlock(_) | TopLevelItem::Global(_)
+                )
+            });
+        // The definitions kept were checked when they were entered; checking
+        // them again only tells a fresh checker their types
+        self.typechecker = TypeChecker::new();
+        let _ = self.typechecker.check_program(&self.session);
+        definitions.functions()
+    }
+
+    /// Keep the session as it is now under `name`, in place of any
+    /// checkpoint of that name
+    fn checkpoint(&mut self, name: &str) {
+        self.checkpoints.retain(|checkpoint| checkpoint.name != name);
+        self.checkpoints.push(Checkpoint {
+            name: name.to_string(),
+            interpreter: self.interpreter.snapshot(),
+            typechecker: self.typechecker.clone(),
+            session: self.session.clone(),
+        });
+    }
+
+    /// Go back to the checkpoint called `name`, or the latest, returning
+    /// its name; it is kept, to go back to again
+    fn rewind(&mut self, name: Option<&str>) -> Result<String, String> {
+        let checkpoint = match name {
+            Some(name) => self.checkpoints.iter().find(|checkpoint| checkpoint.name == name),
+            None => self.checkpoints.last(),
+        };
+        let Some(checkpoint) = checkpoint else {
+            return Err(match name {
+                Some(name) => format!("No checkpoint called {}; :checkpoint lists them", name),
+                None => "No checkpoints kept yet; keep one with :checkpoint <name>".to_string(),
+            });
+        };
+        self.interpreter.restore(&checkpoint.interpreter);
+        self.typechecker = checkpoint.typechecker.clone();
+        self.session = checkpoint.session.clone();
+        let name = checkpoint.name.clone();
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.identifiers.clear();
+        }
+        self.complete_names(&self.session.clone());
+        Ok(name)
+    }
+
+    /// Print the value of a snippet, unless it is `()`, or its error
+    fn show_result(&self, result: Result<Option<Value>, String>) {
+        match result {
+            Ok(Some(Value::Unit)) | Ok(None) => {}
+            Ok(Some(value)) => println!("{}", value.pretty()),
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+
+    /// The bytecode the VM runs for a snippet's functions and expression,
+    /// or for a function defined in the session, optimized unless `raw`
+    fn disasm(&self, code: &str, raw: bool) -> Result<String, String> {
+        let mut session = self.session.clone();
+        let named = defines(&session, code);
+        let Snippet { program: snippet, statements, expr } = match named {
+            true => Snippet {
+                program: Program { items: Vec::new() },
+                statements: Vec::new(),
+                expr: None,
+            },
+            false => parse_snippet(code)?,
+        };
+
+        // Show the snippet's functions, compiled with the session's
+        let mut shown = Vec::new();
+        for item in &snippet.items {
+            if let TopLevelItem::Function(f) = item {
+                shown.push(f.name.clone());
             }
         }
+        if named {
+            shown.push(code.to_string());
+        }
+        session
+            .items
+            .retain(|item| !matches!(item, TopLevelItem::Function(f) if defines(&snippet, &f.name)));
+        session.items.extend(snippet.items);
+        if !statements.is_empty() || expr.is_some() {
+            session.items.push(expression_function(statements, expr, 0..code.len()));
+            shown.push(EXPRESSION_FUNCTION.to_string());
+        }
+
+        let mut compiled = BytecodeCompiler::new()
+            .compile(&session)
+            .map_err(|e| format!("Compile error: {}", e))?;
+        if !raw {
+            Optimizer::new().optimize(&mut compiled);
+        }
+        let output: String = compiled
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| shown.contains(&f.name))
+            .map(|(i, f)| vm::disassemble_function(i, f))
+            .collect();
+        match output.is_empty() {
+            true => Err("No functions or expression to disassemble".to_string()),
+            false => Ok(output.trim_start().to_string()),
+        }
     }
 
-    fn load_file(&mut self, path: &str) {
-        match std::fs::read_to_string(path) {
-            Ok(source) => {
-                println!("Loading {}...", path);
-                let lexer = Lexer::new(&source);
-                match lexer.tokenize() {
-                    Ok(tokens) => {
-                        let mut parser = Parser::new(tokens, &source);
-                        match parser.parse() {
-                            Ok(program) => {
-                                // Type check
-                                if self.lint_enabled {
-                                    if let Err(e) = self.typechecker.check_program(&program) {
-                                        eprintln!("Type error: {}", e);
-                                        return;
-                                    }
-                                }
-
-                                // Collect identifiers for completion
-                                for item in &program.items {
-                                    if let TopLevelItem::Function(f) = item {
-                                        if let Some(helper) = self.editor.helper_mut() {
-                                            helper.add_identifier(&f.name);
-                                        }
-                                    }
-                                }
-
-                                if let Err(e) = self.interpreter.run(&program) {
-                                    eprintln!("Runtime error: {}", e);
-                                } else {
-                                    println!("Loaded successfully.");
-                                }
-                            }
-                            Err(e) => eprintln!("Parse error: {:?}", e),
-                        }
-                    }
-                    Err(e) => eprintln!("Lexer error: {:?}", e),
-                }
+    /// Evaluate a snippet, then show how long it ran and, on the
+    /// interpreter, how many steps it took
+    fn time(&mut self, code: &str) {
+        let snippet = match self.prepare(code) {
+            Ok(snippet) => snippet,
+            Err(message) => return eprintln!("{}", message),
+        };
+        let steps = self.interpreter.steps();
+        let started = Instant::now();
+        let result = self.execute(&snippet);
+        let elapsed = started.elapsed();
+        let steps = self.interpreter.steps() - steps;
+        self.show_result(result);
+        self.show_watches();
+        match self.engine {
+            Engine::Interpreter => {
+                println!("time: {:.2?}, {} step{}", elapsed, steps, if steps == 1 { "" } else { "s" })
             }
-            Err(e) => eprintln!("Could not read file: {}", e),
+            Engine::Vm => println!("time: {:.2?} on the VM", elapsed),
         }
     }
 
-    fn show_ast(&self, code: &str) {
-        let lexer = Lexer::new(code);
-        match lexer.tokenize() {
-            Ok(tokens) => {
-                let mut parser = Parser::new(tokens, code);
-                match parser.parse() {
-                    Ok(program) => {
-                        println!("{:#?}", program);
-                    }
-                    Err(e) => eprintln!("Parse error: {:?}", e),
-                }
+    fn load_file(&mut self, path: &str) {
+        let program = match read_program(path) {
+            Ok(program) => program,
+            Err(message) => return eprintln!("{}", message),
+        };
+        println!("Loading {}...", path);
+
+        // Type check
+        if self.lint_enabled {
+            if let Err(e) = self.typechecker.check_program(&program) {
+                eprintln!("{}", type_error(e));
+                return;
             }
-            Err(e) => eprintln!("Lexer error: {:?}", e),
         }
+
+        self.complete_names(&program);
+        match self.run_program(&program) {
+            Ok(()) => println!("Loaded successfully."),
+            Err(message) => eprintln!("{}", message),
+        }
+        self.show_watches();
     }
 
-    fn show_type(&self, code: &str) {
-        // Wrap as an expression to infer type
-        let wrapped = format!(
-            "to __type_check__() {{ remember __result__ = {}; give back __result__; }}",
-            code.trim_end_matches(';')
-        );
+    /// Start watching an expression, giving the line showing its value now
+    fn watch(&mut self, code: &str) -> Result<String, String> {
+        let expr = parse_expression(code)?;
+        self.watches.push((code.trim_end_matches(';').trim().to_string(), expr));
+        Ok(self.watched(self.watches.len() - 1))
+    }
 
-        let lexer = Lexer::new(&wrapped);
-        if let Ok(tokens) = lexer.tokenize() {
-            let mut parser = Parser::new(tokens, &wrapped);
-            if let Ok(program) = parser.parse() {
-                let mut tc = TypeChecker::new();
-                match tc.check_program(&program) {
-                    Ok(()) => {
-                        // TODO: Actually return the inferred type from type checker
-                        println!("Expression type checks successfully");
-                    }
-                    Err(e) => eprintln!("Type error: {}", e),
+    /// Stop watching the expression numbered or written as `arg`, or all of
+    /// them without one, giving how many are no longer watched
+    fn unwatch(&mut self, arg: Option<&str>) -> Result<usize, String> {
+        let Some(arg) = arg else {
+            return Ok(self.watches.drain(..).count());
+        };
+        let index = match arg.parse::<usize>() {
+            Ok(n) if (1..=self.watches.len()).contains(&n) => n - 1,
+            Ok(n) => return Err(format!("No watch numbered {}; :watch lists them", n)),
+            Err(_) => self
+                .watches
+                .iter()
+                .position(|(code, _)| code == arg.trim_end_matches(';').trim())
+                .ok_or_else(|| format!("Not watching {}", arg))?,
+        };
+        self.watches.remove(index);
+        Ok(1)
+    }
+
+    /// The watched expression at `index` with its current value, or the
+    /// error evaluating it gives
+    ///
+    /// Watches are evaluated by the interpreter whichever engine is in use;
+    /// it holds the VM's variables and functions too.
+    fn watched(&mut self, index: usize) -> String {
+        let (code, expr) = &self.watches[index];
+        let value = match self.interpreter.eval_snippet(expr) {
+            Ok(value) => preview(&value.redacted()),
+            Err(e) => format!("<Runtime error [{}]: {}>", e.code(), e),
+        };
+        format!("watch {}: {} = {}", index + 1, code, value)
+    }
+
+    /// Print every watched expression with its current value
+    fn show_watches(&mut self) {
+        for index in 0..self.watches.len() {
+            println!("{}", self.watched(index));
+        }
+    }
+
+    /// The snippet given to a command, or else the session's source
+    fn snippet(&self, code: Option<&str>) -> Result<String, String> {
+        match code {
+            Some(code) => Ok(code.to_string()),
+            None if self.session.items.is_empty() => Err("Nothing entered yet; give the command some code".to_string()),
+            None => Ok(format_program(&self.session)),
+        }
+    }
+
+    /// The AST of a snippet, as a program or else as an expression,
+    /// without running it
+    fn ast_of(&self, code: Option<&str>) -> Result<String, String> {
+        let code = &self.snippet(code)?;
+        match Pipeline::new().parse(code) {
+            Ok(program) => Ok(format!("{:#?}", program)),
+            Err(e) => match parse_expression(code) {
+                Ok(expr) => Ok(format!("{:#?}", expr.node)),
+                Err(_) => Err(e.to_string()),
+            },
+        }
+    }
+
+    /// The tokens of a snippet, one a line with where it is
+    fn tokens_of(&self, code: Option<&str>) -> Result<String, String> {
+        let code = &self.snippet(code)?;
+        let tokens = Pipeline::new().tokenize(code).map_err(|e| e.to_string())?;
+        let lines: Vec<String> = tokens
+            .iter()
+            .map(|token| {
+                let (line, column) = line_col(code, token.span.start);
+                format!("{:>4}:{:<3} {:?}", line, column, token.value)
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    fn show_type(&mut self, code: &str) {
+        match self.type_of(code) {
+            Ok(ty) => println!("{} : {}", code.trim_end_matches(';'), ty),
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+
+    /// The type of an expression, using the functions defined so far
+    fn type_of(&mut self, code: &str) -> Result<InferredType, String> {
+        let expr = parse_expression(code)?;
+        self.typechecker
+            .infer_expression(&expr)
+            .map_err(type_error)
+    }
+
+    /// The session's variables with their types and values, then its
+    /// functions, workers and the consents asked for
+    fn env(&self) -> String {
+        let variables = self
+            .interpreter
+            .globals()
+            .into_iter()
+            .map(|(name, value)| match self.typechecker.variable_type(name) {
+                Some(ty) => format!("{} : {} = {}", name, ty, preview(&value.redacted())),
+                None => format!("{} = {}", name, preview(&value.redacted())),
+            })
+            .collect();
+        let functions = self.interpreter.functions().into_iter().map(format_signature).collect();
+        let workers = self.interpreter.workers().into_iter().map(String::from).collect();
+        let consents = self
+            .interpreter
+            .consents()
+            .into_iter()
+            .map(|(permission, granted)| format!("{}: {}", permission, if granted { "granted" } else { "denied" }))
+            .collect();
+
+        let sections: Vec<String> = [
+            ("Variables", variables),
+            ("Functions", functions),
+            ("Workers", workers),
+            ("Consents", consents),
+        ]
+        .into_iter()
+        .filter(|(_, lines): &(_, Vec<String>)| !lines.is_empty())
+        .map(|(title, lines)| format!("{}:\n  {}", title, lines.join("\n  ")))
+        .collect();
+        match sections.is_empty() {
+            true => "Nothing defined yet.".to_string(),
+            false => sections.join("\n\n"),
+        }
+    }
+
+    /// The reference entry for a builtin or standard library function, the
+    /// signature of a function defined in the session, or the functions of
+    /// a module such as `std.math`
+    fn doc(&self, name: &str) -> Result<String, String> {
+        if let Some(doc) = docs::lookup(name) {
+            return Ok(doc.to_string());
+        }
+        if let Some(f) = self.interpreter.functions().into_iter().find(|f| f.name == name) {
+            return Ok(format!("{}\n    Defined in this session", format_signature(f)));
+        }
+        let module = if name.starts_with("std.") { name.to_string() } else { format!("std.{}", name) };
+        let functions: Vec<String> = docs::all()
+            .iter()
+            .filter(|doc| doc.module() == Some(module.as_str()))
+            .map(|doc| format!("  {}{}", doc.name, doc.signature))
+            .collect();
+        match functions.is_empty() {
+            true => Err(format!("No function or module named {}", name)),
+            false => Ok(format!("{}:\n{}", module, functions.join("\n"))),
+        }
+    }
+}
+
+/// How far brackets and strings are left open at the end of some input
+#[derive(Debug, Default)]
+struct Nesting {
+    /// Brackets not closed yet, innermost last, with their byte offsets
+    open: Vec<(char, usize)>,
+    in_string: bool,
+    /// Whether a closing bracket had nothing open to close
+    unmatched: bool,
+}
+
+impl Nesting {
+    fn complete(&self) -> bool {
+        self.open.is_empty() && !self.in_string
+    }
+}
+
+fn nesting(input: &str) -> Nesting {
+    let mut nesting = Nesting::default();
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if nesting.in_string {
+            match c {
+                '\\' => {
+                    chars.next();
                 }
-            } else {
-                eprintln!("Parse error");
+                '"' => nesting.in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => nesting.in_string = true,
+            // A comment runs to the end of the line
+            '/' if chars.peek().is_some_and(|&(_, next)| next == '/') => {
+                while chars.next_if(|&(_, next)| next != '\n').is_some() {}
             }
+            '{' | '[' | '(' => nesting.open.push((c, i)),
+            '}' | ']' | ')' => nesting.unmatched |= nesting.open.pop().is_none(),
+            _ => {}
         }
     }
+    nesting
+}
+
+/// The prompt and indentation for the next line of unfinished input: a
+/// level for each line with brackets left open, and the innermost of them,
+/// or `"` in a string, in the prompt
+fn continuation(pending: &str) -> (String, String) {
+    let nesting = nesting(pending);
+    if nesting.in_string {
+        return ("...\"> ".to_string(), String::new());
+    }
+    let mut lines: Vec<usize> = nesting
+        .open
+        .iter()
+        .map(|&(_, offset)| pending[..offset].matches('\n').count())
+        .collect();
+    lines.dedup();
+    let innermost = nesting.open.last().map_or('.', |&(bracket, _)| bracket);
+    (format!("...{}> ", innermost), INDENT.repeat(lines.len()))
+}
+
+/// For a line ending in a bracket that closes one opened on an earlier
+/// line, a hint showing that line
+fn closing_hint(pending: &str, line: &str) -> Option<String> {
+    let line = line.trim_end();
+    if !line.ends_with(['}', ']', ')']) {
+        return None;
+    }
+    let before = nesting(&format!("{}{}", pending, &line[..line.len() - 1]));
+    let &(_, offset) = before.open.last().filter(|_| !before.in_string)?;
+    if offset >= pending.len() {
+        return None;
+    }
+    let start = pending[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = pending[offset..].find('\n').map_or(pending.len(), |i| offset + i);
+    let number = pending[..offset].matches('\n').count() + 1;
+    Some(format!("  ← closes line {}: {}", number, pending[start..end].trim()))
+}
 
-    fn show_env(&self) {
-        println!("(Environment inspection not yet implemented)");
-        println!("Available identifiers for completion:");
-        if let Some(helper) = self.editor.helper() {
-            for ident in &helper.identifiers {
-                println!("  {}", ident);
+/// A value as `:env` shows it, with strings quoted and long arrays cut
+/// short
+fn preview(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        Value::Array(items) => {
+            let mut shown: Vec<String> = items.iter().take(PREVIEW_ELEMENTS).map(preview).collect();
+            if items.len() > PREVIEW_ELEMENTS {
+                shown.push(format!("… {} more", items.len() - PREVIEW_ELEMENTS));
             }
+            format!("[{}]", shown.join(", "))
         }
+        Value::Okay(inner) => format!("Okay({})", preview(inner)),
+        other => other.to_string(),
+    }
+}
+
+/// An interpreter error as the REPL shows it, with any suggestion
+fn runtime_error(e: RuntimeError) -> String {
+    with_help(format!("Runtime error [{}]: {}", e.code(), e), e.help())
+}
+
+/// A type error as the REPL shows it, with any suggestion
+fn type_error(e: TypeError) -> String {
+    with_help(format!("Type error [{}]: {}", e.code(), e), e.help())
+}
+
+/// Pipeline diagnostics as the REPL shows them, one per line
+fn diagnostics_error(diagnostics: Diagnostics) -> String {
+    let lines: Vec<String> = diagnostics
+        .into_iter()
+        .map(|d| with_help(d.to_string(), d.help.clone()))
+        .collect();
+    lines.join("\n")
+}
+
+fn with_help(message: String, help: Option<String>) -> String {
+    match help {
+        Some(help) => format!("{}\n  help: {}", message, help),
+        None => message,
+    }
+}
+
+/// The function `:disasm` and the VM compile a snippet into: one with no
+/// parameters that runs the statements and gives back the expression
+fn expression_function(mut body: Vec<Statement>, expr: Option<Spanned<Expr>>, span: Span) -> TopLevelItem {
+    if let Some(expr) = expr {
+        let span = expr.span.clone();
+        body.push(Statement::Return(ReturnStmt { value: expr, span }));
+    }
+    TopLevelItem::Function(FunctionDef {
+        emote: None,
+        name: EXPRESSION_FUNCTION.to_string(),
+        type_params: Vec::new(),
+        params: Vec::new(),
+        return_type: None,
+        hello: None,
+        body,
+        goodbye: None,
+        span,
+        module: None,
+    })
+}
+
+fn defines(program: &Program, name: &str) -> bool {
+    program.items.iter().any(|item| matches!(item, TopLevelItem::Function(f) if f.name == name))
+}
+
+/// The name a top-level item defines, if it defines one
+fn definition_name(item: &TopLevelItem) -> Option<&str> {
+    match item {
+        TopLevelItem::Function(f) => Some(&f.name),
+        TopLevelItem::WorkerDef(w) => Some(&w.name),
+        TopLevelItem::SideQuestDef(s) => Some(&s.name),
+        TopLevelItem::SuperpowerDecl(s) => Some(&s.name),
+        TopLevelItem::TypeDef(t) => Some(&t.name),
+        TopLevelItem::ConstDef(c) => Some(&c.name),
+        TopLevelItem::Global(g) => Some(g.name.as_str()),
+        _ => None,
     }
 }
 
+/// Read and parse a whole program file
+fn read_program(path: &str) -> Result<Program, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("Could not read file: {}", e))?;
+    Pipeline::new().parse(&source).map_err(|e| e.to_string())
+}
+
+/// Parse definitions, optionally followed by one expression
+fn parse_snippet(code: &str) -> Result<Snippet, String> {
+    Pipeline::new().parse_snippet(code).map_err(|e| e.to_string())
+}
+
+/// Parse one expression
+fn parse_expression(code: &str) -> Result<Spanned<Expr>, String> {
+    Pipeline::new().parse_expression(code).map_err(|e| e.to_string())
+}
+
 impl Default for Repl {
     fn default() -> Self {
         Self::new().expect("Failed to create REPL")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_type_command() {
+        let mut repl = Repl::new().unwrap();
+        repl.process_input("to double(n: Int) -> Int { give back n * 2; }");
+        assert_eq!(repl.type_of("double(21)"), Ok(InferredType::Int));
+        assert_eq!(repl.type_of("[1.5, 2.0];"), Ok(InferredType::Array(Box::new(InferredType::Float))));
+        // Errors are not always messages, so an Okay alone leaves its error type open
+        assert!(matches!(
+            repl.type_of("Okay(\"done\")"),
+            Ok(InferredType::Result { ok, .. }) if *ok == InferredType::String
+        ));
+        assert!(matches!(
+            repl.type_of("Oops(404)"),
+            Ok(InferredType::Result { err, .. }) if *err == InferredType::Int
+        ));
+        assert!(repl.type_of("double(\"x\")").unwrap_err().starts_with("Type error"));
+        assert!(repl.type_of("undefined_fn(1)").is_err());
+        assert!(repl.type_of("1 +").unwrap_err().starts_with("Parse error"));
+    }
+
+    #[test]
+    fn test_result_echo() {
+        let mut repl = Repl::new().unwrap();
+        assert!(matches!(repl.eval("to double(n: Int) -> Int { give back n * 2; }"), Ok(None)));
+        assert!(matches!(repl.eval("double(21)"), Ok(Some(Value::Int(42)))));
+        assert!(matches!(
+            repl.eval("to triple(n: Int) -> Int { give back n * 3; }\ntriple(double(1));"),
+            Ok(Some(Value::Int(6)))
+        ));
+        // Only the definitions are kept in the session
+        assert!(defines(&repl.session, "triple"));
+        assert_eq!(repl.session.items.len(), 2);
+
+        let steps = repl.interpreter.steps();
+        assert!(matches!(repl.eval("\"Hello\" + \" \" + \"World\""), Ok(Some(Value::String(ref s))) if **s == "Hello World"));
+        assert_eq!(repl.interpreter.steps() - steps, 5);
+
+        assert!(repl.eval("double(\"x\")").unwrap_err().starts_with("Type error"));
+        assert!(repl.eval("1 / 0").unwrap_err().starts_with("Runtime error"));
+        assert!(repl.eval("double(21) 3").unwrap_err().starts_with("Parse error"));
+    }
+
+    #[test]
+    fn test_vm_toggle() {
+        let mut repl = Repl::new().unwrap();
+        assert!(repl.eval("to double(n: Int) -> Int { give back n * 2; }").is_ok());
+        assert!(repl.eval("remember x = 20;").is_ok());
+
+        assert!(!repl.handle_command(":vm").unwrap());
+        assert_eq!(repl.engine, Engine::Vm);
+        // Functions defined on the interpreter are recompiled for the VM,
+        // and variables carry over both ways
+        assert!(matches!(repl.eval("double(x) + 2"), Ok(Some(Value::Int(42)))));
+        assert!(matches!(repl.eval("x = x * 2; remember y = x + 1;"), Ok(None)));
+        assert!(repl.eval("to triple(n: Int) -> Int { give back n * 3; }").is_ok());
+        assert!(matches!(repl.eval("triple(y)"), Ok(Some(Value::Int(123)))));
+        assert!(repl.eval("1 / 0").unwrap_err().starts_with("Runtime error"));
+        assert!(repl.env().contains("y : Int = 41"));
+
+        assert!(!repl.handle_command(":vm").unwrap());
+        assert_eq!(repl.engine, Engine::Interpreter);
+        assert!(matches!(repl.eval("triple(x) + y"), Ok(Some(Value::Int(161)))));
+    }
+
+    #[test]
+    fn test_watch_command() {
+        let mut repl = Repl::new().unwrap();
+        assert_eq!(repl.watch("x * 2").unwrap(), "watch 1: x * 2 = <Runtime error [WOKE-E0300]: Undefined variable: x>");
+        assert!(repl.eval("remember x = 20;").is_ok());
+        assert_eq!(repl.watched(0), "watch 1: x * 2 = 40");
+        assert!(repl.watch("x +").unwrap_err().starts_with("Parse error"));
+
+        assert!(repl.eval("remember name = \"Ada\"; remember secret key = \"hunter2\";").is_ok());
+        assert_eq!(repl.watch("name").unwrap(), "watch 2: name = \"Ada\"");
+        assert_eq!(repl.watch("key;").unwrap(), "watch 3: key = \"***\"");
+
+        assert!(repl.eval("x = x + 1;").is_ok());
+        assert_eq!(repl.watched(0), "watch 1: x * 2 = 42");
+
+        assert_eq!(repl.unwatch(Some("name")), Ok(1));
+        assert_eq!(repl.watched(1), "watch 2: key = \"***\"");
+        assert_eq!(repl.unwatch(Some("3")).unwrap_err(), "No watch numbered 3; :watch lists them");
+        assert_eq!(repl.unwatch(Some("y")).unwrap_err(), "Not watching y");
+        assert_eq!(repl.unwatch(Some("1")), Ok(1));
+        assert_eq!(repl.unwatch(None), Ok(1));
+        assert!(repl.watches.is_empty());
+    }
+
+    #[test]
+    fn test_disasm_command() {
+        let mut repl = Repl::new().unwrap();
+        assert!(matches!(repl.eval("to double(n: Int) -> Int { give back n * 2; }"), Ok(None)));
+
+        let double = repl.disasm("double", false).unwrap();
+        assert!(double.starts_with("=== Function 0: double (arity: 1, locals: 1) ==="), "{}", double);
+        assert!(double.contains("Mul"), "{}", double);
+
+        // Constant folding shows in the optimized output only
+        let expr = repl.disasm("double(2 + 3)", false).unwrap();
+        assert!(expr.contains("<expression>") && !expr.contains("double (arity"), "{}", expr);
+        assert!(expr.contains("Int(5)") && !expr.contains("Add"), "{}", expr);
+        let raw = repl.disasm("double(2 + 3)", true).unwrap();
+        assert!(raw.contains("Add"), "{}", raw);
+
+        let snippet = repl.disasm("to double(n: Int) -> Int { give back n + n; }", false).unwrap();
+        assert_eq!(snippet.matches("=== Function").count(), 1, "{}", snippet);
+        assert!(snippet.contains("Add"), "{}", snippet);
+        assert!(repl.disasm("#care on;", false).is_err());
+    }
+
+    #[test]
+    fn test_multiline_continuation() {
+        assert!(nesting("to f() { print(\"}\"); }").complete());
+        assert!(nesting("print(1) // (").complete());
+        assert!(!nesting("print(\"a\\\" (").complete());
+        assert!(nesting("print(1))").unmatched);
+
+        let pending = "to f(n: Int) {\n    when n > 1 {\n        print([1,\n";
+        assert_eq!(continuation(pending), ("...[> ".to_string(), " ".repeat(12)));
+        assert_eq!(continuation("print(\"a\n"), ("...\"> ".to_string(), String::new()));
+
+        let pending = "to f(n: Int) {\n    when n > 1 {\n        print(n);\n";
+        assert_eq!(continuation(pending).1, " ".repeat(8));
+        assert_eq!(closing_hint(pending, "    }").as_deref(), Some("  ← closes line 2: when n > 1 {"));
+        assert_eq!(closing_hint(pending, "    } otherwise { }"), None);
+        assert_eq!(closing_hint(pending, "    print(n)"), None);
+        assert_eq!(closing_hint("", "print(1)"), None);
+    }
+
+    #[test]
+    fn test_env_command() {
+        let mut repl = Repl::new().unwrap();
+        assert_eq!(repl.env(), "Nothing defined yet.");
+        repl.interpreter = Interpreter::new().with_consent_handler(|permission| permission == "camera");
+        for input in [
+            "remember x = 41;",
+            "x = x + 1;",
+            "remember xs = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];",
+            "remember secret token = \"abc123\"; remember name = \"Al\";",
+            "to double(n: Int) -> Int { give back n * 2; }",
+            "worker counter { print(1); }",
+            "only if okay \"camera\" { print(1); } only if okay \"network\" { print(2); }",
+        ] {
+            assert!(repl.eval(input).is_ok(), "{}", input);
+        }
+        assert!(repl.eval("remember bad = \"a\" - 1;").is_err());
+
+        assert_eq!(
+            repl.env(),
+            "Variables:
+  name : String = \"Al\"
+  token : String = \"***\"
+  x : Int = 42
+  xs : [Int] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, … 2 more]
+
+Functions:
+  double(n: Int) -> Int
+
+Workers:
+  counter
+
+Consents:
+  camera: granted
+  network: denied"
+        );
+    }
+
+    #[test]
+    fn test_checkpoints_and_reset() {
+        let mut repl = Repl::new().unwrap();
+        repl.interpreter = Interpreter::new().with_consent_handler(|_| true);
+        for input in ["remember x = 1;", "to double(n: Int) -> Int { give back n * 2; }"] {
+            assert!(repl.eval(input).is_ok(), "{}", input);
+        }
+        assert!(repl.rewind(None).is_err());
+        repl.handle_command(":checkpoint start").unwrap();
+        for input in [
+            "x = 5;",
+            "remember y = 2;",
+            "to triple(n: Int) -> Int { give back n * 3; }",
+            "only if okay \"camera\" { print(1); }",
+        ] {
+            assert!(repl.eval(input).is_ok(), "{}", input);
+        }
+
+        assert!(repl.rewind(Some("later")).is_err());
+        repl.handle_command(":rewind").unwrap();
+        assert_eq!(repl.eval("double(x)").unwrap(), Some(Value::Int(2)));
+        assert!(repl.eval("y").is_err());
+        assert!(repl.eval("triple(1)").is_err());
+        assert!(repl.interpreter.consents().is_empty());
+        assert_eq!(repl.session.items.len(), 1);
+
+        repl.handle_command(":reset --keep-functions").unwrap();
+        assert_eq!(repl.eval("double(4)").unwrap(), Some(Value::Int(8)));
+        assert!(repl.eval("x").is_err());
+
+        // Checkpoints outlive a reset
+        repl.handle_command(":reset").unwrap();
+        repl.handle_command(":rewind start").unwrap();
+        assert_eq!(repl.eval("double(x)").unwrap(), Some(Value::Int(2)));
+    }
+
+    #[test]
+    fn test_doc_command() {
+        let mut repl = Repl::new().unwrap();
+        assert_eq!(
+            repl.doc("std.math.sqrt").unwrap(),
+            "std.math.sqrt(n: Number) -> Float\n    Square root\n    Superpowers: none"
+        );
+        assert!(repl.doc("print").unwrap().starts_with("print(values...) -> Unit\n"));
+        assert!(repl.doc("io.readFile").unwrap().ends_with("Superpowers: file:read"));
+
+        let module = repl.doc("math").unwrap();
+        assert!(module.starts_with("std.math:\n  std.math.abs(n: Number) -> Number\n"));
+        assert_eq!(repl.doc("std.math").unwrap(), module);
+
+        assert!(repl.eval("to double(n: Int) -> Int { give back n * 2; }").is_ok());
+        assert_eq!(repl.doc("double").unwrap(), "double(n: Int) -> Int\n    Defined in this session");
+        assert_eq!(repl.doc("nope").unwrap_err(), "No function or module named nope");
+    }
+
+    #[test]
+    fn test_save_and_load_session() {
+        let path = std::env::temp_dir().join("wokelang_test_session.woke");
+        let path = path.to_str().unwrap();
+        let mut repl = Repl::new().unwrap();
+        assert!(repl.save(path).is_err());
+        assert!(matches!(repl.eval("to double(n: Int) -> Int { give back n * 2; }"), Ok(None)));
+        assert!(matches!(repl.eval("to greet() { print(\"hi\"); }\ngreet();"), Ok(Some(Value::Unit))));
+        // A redefinition takes the place of the first
+        assert!(matches!(repl.eval("to double(n: Int) -> Int { give back n + n; }"), Ok(None)));
+        assert_eq!(repl.save(path), Ok(2));
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "to double(n: Int) -> Int {\n    give back n + n;\n}\n\nto greet() {\n    print(\"hi\");\n}\n"
+        );
+
+        let mut restored = Repl::new().unwrap();
+        assert!(matches!(restored.eval("to triple(n: Int) -> Int { give back n * 3; }"), Ok(None)));
+        assert_eq!(restored.restore(path), Ok(2));
+        assert!(matches!(restored.eval("double(21)"), Ok(Some(Value::Int(42)))));
+        assert_eq!(restored.type_of("double(1)"), Ok(InferredType::Int));
+        assert!(restored.eval("triple(1)").is_err());
+        assert!(restored.restore("missing.woke").unwrap_err().starts_with("Could not read"));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_ast_and_tokens_commands() {
+        let mut repl = Repl::new().unwrap();
+        assert!(repl.ast_of(None).is_err());
+        repl.process_input("to double(n: Int) -> Int {\n    give back n * 2;\n}");
+
+        let session = repl.ast_of(None).unwrap();
+        assert!(session.contains("name: \"double\""), "{}", session);
+        let expr = repl.ast_of(Some("1 + 2 * 3")).unwrap();
+        assert!(expr.starts_with("Binary(\n    Add,"), "{}", expr);
+        assert!(expr.contains("span: 0..1,"), "{}", expr);
+        assert!(repl.ast_of(Some("1 +")).unwrap_err().starts_with("Parse error"));
+
+        let tokens = repl.tokens_of(Some("give back 1;")).unwrap();
+        assert_eq!(tokens.lines().next(), Some("   1:1   Give"));
+        assert_eq!(tokens.lines().last(), Some("   1:13  Eof"));
+        let session = repl.tokens_of(None).unwrap();
+        assert!(session.lines().any(|line| line.starts_with("   2:5   Give")), "{}", session);
+
+        repl.handle_command(":reset").unwrap();
+        assert!(repl.tokens_of(None).is_err());
+    }
+}
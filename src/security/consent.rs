@@ -2,13 +2,39 @@
 //!
 //! This module provides persistent storage for consent decisions,
 //! allowing users to remember their choices across sessions.
+//!
+//! Decisions are kept as JSON in `~/.woke/consents.json`, each for the
+//! program it was made for, by its canonical path, and the scope within it.
+//! Decisions made for the current session only are held in memory and
+//! never written.
+//!
+//! Where nobody can answer a prompt, such as in CI, `WOKE_CONSENT` says how
+//! requests are answered instead; see [`ConsentMode`]. Prompts that are
+//! not answered within a timeout are denied.
 
+use serde_json::{json, Value as Json};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
+/// Environment variable choosing how consent requests are answered
+pub const CONSENT_ENV: &str = "WOKE_CONSENT";
+
+/// Environment variable naming a policy file to answer requests from
+pub const CONSENT_FILE_ENV: &str = "WOKE_CONSENT_FILE";
+
+/// Environment variable with the prompt timeout in seconds; 0 waits forever
+pub const CONSENT_TIMEOUT_ENV: &str = "WOKE_CONSENT_TIMEOUT";
+
+/// How long a prompt waits for an answer before denying
+pub const DEFAULT_PROMPT_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[derive(Error, Debug)]
 pub enum ConsentError {
     #[error("IO error: {0}")]
@@ -19,13 +45,26 @@ pub enum ConsentError {
 
     #[error("Consent file corrupted")]
     CorruptedFile,
+
+    #[error("Invalid {0}: {1}")]
+    InvalidEnv(&'static str, String),
 }
 
 type Result<T> = std::result::Result<T, ConsentError>;
 
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 /// A stored consent decision
 #[derive(Debug, Clone)]
 pub struct StoredConsent {
+    /// Canonical path of the program the decision was made for
+    pub program: String,
     pub scope: String,
     pub capability: String,
     pub granted: bool,
@@ -33,6 +72,23 @@ pub struct StoredConsent {
     pub remember: ConsentDuration,
 }
 
+impl StoredConsent {
+    /// When the decision stops applying, in seconds since the Unix epoch;
+    /// `None` if it never does
+    pub fn expires_at(&self) -> Option<u64> {
+        match self.remember.to_seconds() {
+            None | Some(u64::MAX) => None,
+            Some(secs) => Some(self.timestamp.saturating_add(secs)),
+        }
+    }
+
+    /// Whether the decision still applies
+    pub fn is_current(&self) -> bool {
+        self.remember != ConsentDuration::Once
+            && self.expires_at().is_none_or(|expires| now() <= expires)
+    }
+}
+
 /// How long to remember a consent decision
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ConsentDuration {
@@ -58,6 +114,158 @@ pub fn to_seconds(&self) -> Option<u64> {
             ConsentDuration::Once => Some(0),
         }
     }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentDuration::Session => "session",
+            ConsentDuration::Day => "day",
+            ConsentDuration::Week => "week",
+            ConsentDuration::Forever => "forever",
+            ConsentDuration::Once => "once",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "session" => ConsentDuration::Session,
+            "day" => ConsentDuration::Day,
+            "week" => ConsentDuration::Week,
+            "forever" => ConsentDuration::Forever,
+            "once" => ConsentDuration::Once,
+            _ => return None,
+        })
+    }
+}
+
+/// Read an answer to a consent prompt: whether it was granted and for how
+/// long. Anything unrecognised is a denial for this session.
+pub fn parse_answer(answer: &str) -> (bool, ConsentDuration) {
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => (true, ConsentDuration::Session),
+        "d" | "day" => (true, ConsentDuration::Day),
+        "w" | "week" => (true, ConsentDuration::Week),
+        "a" | "always" => (true, ConsentDuration::Forever),
+        "never" => (false, ConsentDuration::Forever),
+        _ => (false, ConsentDuration::Session),
+    }
+}
+
+/// How consent requests are answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsentMode {
+    /// Prompt on stdin, using remembered decisions
+    #[default]
+    Ask,
+    /// Grant everything without asking
+    Allow,
+    /// Refuse everything not allowed already, without asking
+    Deny,
+    /// Like `Deny`, but a policy file must say what is allowed
+    Policy,
+}
+
+impl ConsentMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name.trim().to_lowercase().as_str() {
+            "ask" => ConsentMode::Ask,
+            "allow" => ConsentMode::Allow,
+            "deny" => ConsentMode::Deny,
+            "policy" => ConsentMode::Policy,
+            _ => return None,
+        })
+    }
+
+    /// The mode `WOKE_CONSENT` names, or `Ask` if it is not set
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(CONSENT_ENV) {
+            Ok(name) if !name.is_empty() => Self::parse(&name).ok_or_else(|| {
+                ConsentError::InvalidEnv(
+                    CONSENT_ENV,
+                    format!("'{}' (use ask, allow, deny or policy)", name),
+                )
+            }),
+            _ => Ok(ConsentMode::Ask),
+        }
+    }
+}
+
+/// The prompt timeout `WOKE_CONSENT_TIMEOUT` sets, in seconds with 0 for
+/// none, or the default
+pub fn prompt_timeout_from_env() -> Result<Option<Duration>> {
+    match std::env::var(CONSENT_TIMEOUT_ENV) {
+        Ok(secs) if !secs.is_empty() => match secs.trim().parse::<u64>() {
+            Ok(0) => Ok(None),
+            Ok(secs) => Ok(Some(Duration::from_secs(secs))),
+            Err(_) => Err(ConsentError::InvalidEnv(
+                CONSENT_TIMEOUT_ENV,
+                format!("'{}' (use a number of seconds)", secs),
+            )),
+        },
+        _ => Ok(Some(DEFAULT_PROMPT_TIMEOUT)),
+    }
+}
+
+/// A stdin read still waiting after its prompt timed out. The next prompt
+/// takes its answer, so a late reply is not lost.
+static PENDING_ANSWER: Mutex<Option<Receiver<Option<String>>>> = Mutex::new(None);
+
+/// Read one line from stdin, giving up after `timeout`
+fn read_answer(timeout: Option<Duration>) -> Option<String> {
+    let mut pending = PENDING_ANSWER.lock().unwrap_or_else(|e| e.into_inner());
+    let answer = pending.take().unwrap_or_else(|| {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut line = String::new();
+            let read = io::stdin().read_line(&mut line);
+            let _ = tx.send(matches!(read, Ok(n) if n > 0).then_some(line));
+        });
+        rx
+    });
+    match timeout {
+        None => answer.recv().ok().flatten(),
+        Some(timeout) => match answer.recv_timeout(timeout) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => {
+                *pending = Some(answer);
+                None
+            }
+            Err(RecvTimeoutError::Disconnected) => None,
+        },
+    }
+}
+
+/// Ask on stdin, after `question`, whether to grant a consent and for how
+/// long. No answer within `timeout`, or no stdin at all, is a denial.
+pub fn ask(question: &str, timeout: Option<Duration>) -> (bool, ConsentDuration) {
+    print!("{} [y]es, [n]o, [d]ay, [w]eek, [a]lways, never: ", question);
+    if io::stdout().flush().is_err() {
+        return (false, ConsentDuration::Session);
+    }
+    match read_answer(timeout) {
+        Some(answer) => parse_answer(&answer),
+        None => {
+            println!();
+            println!("No answer, so permission is denied");
+            (false, ConsentDuration::Session)
+        }
+    }
+}
+
+/// Ask a yes-or-no question on stdin, for decisions that are not
+/// remembered. No answer within `timeout` is a no.
+pub fn confirm(question: &str, timeout: Option<Duration>) -> bool {
+    print!("{} [y]es, [n]o: ", question);
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+    match read_answer(timeout) {
+        Some(answer) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        None => {
+            println!();
+            println!("No answer, so permission is denied");
+            false
+        }
+    }
 }
 
 /// Persistent consent storage
@@ -80,32 +288,46 @@ pub fn new(path: PathBuf) -> Self {
         }
     }
 
-    /// Create a consent store in the user's config directory
+    /// Where consents are kept: `~/.woke/consents.json`
     pub fn default_path() -> PathBuf {
-        // Use ~/.config/wokelang/consent.db on Unix
-        // or %APPDATA%/wokelang/consent.db on Windows
-        if let Some(config_dir) = dirs::config_dir() {
-            config_dir.join("wokelang").join("consent.db")
-        } else {
-            PathBuf::from(".wokelang-consent.db")
-        }
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".woke")
+            .join("consents.json")
     }
 
-    /// Load consents from file
+    /// Open the store at the default path, loading what it holds
+    pub fn open_default() -> Result<Self> {
+        let mut store = Self::default();
+        store.load()?;
+        Ok(store)
+    }
+
+    /// Path to the consent file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load consents from file, dropping any that have expired
     pub fn load(&mut self) -> Result<()> {
         if !self.path.exists() {
             return Ok(());
         }
 
         let content = fs::read_to_string(&self.path)?;
-
-        for line in content.lines() {
-            if line.trim().is_empty() || line.starts_with('#') {
+        let data: Json =
+            serde_json::from_str(&content).map_err(|e| ConsentError::ParseError(e.to_string()))?;
+        let entries = data["consents"].as_array().ok_or(ConsentError::CorruptedFile)?;
+
+        for entry in entries {
+            // Decisions from before they were kept per program could belong
+            // to any program with the same file name, so they are dropped
+            if entry.get("program").is_none() {
                 continue;
             }
-
-            if let Some(consent) = self.parse_line(line) {
-                let key = format!("{}:{}", consent.scope, consent.capability);
+            let consent = Self::parse_entry(entry).ok_or(ConsentError::CorruptedFile)?;
+            if consent.is_current() {
+                let key = Self::key(&consent.program, &consent.scope, &consent.capability);
                 self.consents.insert(key, consent);
             }
         }
@@ -120,114 +342,93 @@ pub fn save(&self) -> Result<()> {
             fs::create_dir_all(parent)?;
         }
 
-        let mut content = String::new();
-        content.push_str("# WokeLang Consent Storage\n");
-        content.push_str("# Format: scope|capability|granted|timestamp|duration\n\n");
-
-        for consent in self.consents.values() {
-            let line = format!(
-                "{}|{}|{}|{}|{}\n",
-                consent.scope,
-                consent.capability,
-                if consent.granted { "yes" } else { "no" },
-                consent.timestamp,
-                match consent.remember {
-                    ConsentDuration::Session => "session",
-                    ConsentDuration::Day => "day",
-                    ConsentDuration::Week => "week",
-                    ConsentDuration::Forever => "forever",
-                    ConsentDuration::Once => "once",
-                }
-            );
-            content.push_str(&line);
-        }
+        let mut kept: Vec<&StoredConsent> = self
+            .consents
+            .values()
+            .filter(|c| c.remember != ConsentDuration::Session && c.is_current())
+            .collect();
+        kept.sort_by(|a, b| (&a.program, &a.scope, &a.capability).cmp(&(&b.program, &b.scope, &b.capability)));
+
+        let entries: Vec<Json> = kept
+            .into_iter()
+            .map(|consent| {
+                json!({
+                    "program": consent.program,
+                    "scope": consent.scope,
+                    "capability": consent.capability,
+                    "granted": consent.granted,
+                    "timestamp": consent.timestamp,
+                    "remember": consent.remember.as_str(),
+                })
+            })
+            .collect();
+        let content = serde_json::to_string_pretty(&json!({ "consents": entries }))
+            .map_err(|e| ConsentError::ParseError(e.to_string()))?;
 
-        fs::write(&self.path, content)?;
+        fs::write(&self.path, content + "\n")?;
         Ok(())
     }
 
-    /// Parse a consent line from the file
-    fn parse_line(&self, line: &str) -> Option<StoredConsent> {
-        let parts: Vec<&str> = line.split('|').collect();
-        if parts.len() != 5 {
-            return None;
-        }
-
-        let scope = parts[0].to_string();
-        let capability = parts[1].to_string();
-        let granted = parts[2] == "yes";
-        let timestamp: u64 = parts[3].parse().ok()?;
-        let remember = match parts[4] {
-            "session" => ConsentDuration::Session,
-            "day" => ConsentDuration::Day,
-            "week" => ConsentDuration::Week,
-            "forever" => ConsentDuration::Forever,
-            "once" => ConsentDuration::Once,
-            _ => return None,
-        };
-
+    /// Read one consent from the file
+    fn parse_entry(entry: &Json) -> Option<StoredConsent> {
         Some(StoredConsent {
-            scope,
-            capability,
-            granted,
-            timestamp,
-            remember,
+            program: entry["program"].as_str()?.to_string(),
+            scope: entry["scope"].as_str()?.to_string(),
+            capability: entry["capability"].as_str()?.to_string(),
+            granted: entry["granted"].as_bool()?,
+            timestamp: entry["timestamp"].as_u64()?,
+            remember: ConsentDuration::parse(entry["remember"].as_str()?)?,
         })
     }
 
-    /// Store a consent decision
-    pub fn store(&mut self, scope: &str, capability: &str, granted: bool, duration: ConsentDuration) -> Result<()> {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
+    /// Where a decision is kept in `consents`
+    fn key(program: &str, scope: &str, capability: &str) -> String {
+        format!("{}\n{}:{}", program, scope, capability)
+    }
 
+    /// Store a consent decision made for `program` in `scope`
+    pub fn store(
+        &mut self,
+        program: &str,
+        scope: &str,
+        capability: &str,
+        granted: bool,
+        duration: ConsentDuration,
+    ) -> Result<()> {
         let consent = StoredConsent {
+            program: program.to_string(),
             scope: scope.to_string(),
             capability: capability.to_string(),
             granted,
-            timestamp: now,
+            timestamp: now(),
             remember: duration,
         };
 
-        let key = format!("{}:{}", scope, capability);
+        let key = Self::key(program, scope, capability);
         self.consents.insert(key, consent);
 
-        if self.auto_save {
+        if self.auto_save && duration != ConsentDuration::Session {
             self.save()?;
         }
 
         Ok(())
     }
 
-    /// Check if consent was previously granted
-    pub fn check(&self, scope: &str, capability: &str) -> Option<bool> {
-        let key = format!("{}:{}", scope, capability);
-        let consent = self.consents.get(&key)?;
+    /// Check if consent was previously granted to `program` in `scope`
+    pub fn check(&self, program: &str, scope: &str, capability: &str) -> Option<bool> {
+        let consent = self.consents.get(&Self::key(program, scope, capability))?;
 
         // Check if consent has expired
-        if consent.remember == ConsentDuration::Once {
+        if !consent.is_current() {
             return None;
         }
 
-        if let Some(duration_secs) = consent.remember.to_seconds() {
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0);
-
-            if now - consent.timestamp > duration_secs {
-                return None;
-            }
-        }
-
         Some(consent.granted)
     }
 
     /// Revoke a stored consent
-    pub fn revoke(&mut self, scope: &str, capability: &str) -> Result<()> {
-        let key = format!("{}:{}", scope, capability);
-        self.consents.remove(&key);
+    pub fn revoke(&mut self, program: &str, scope: &str, capability: &str) -> Result<()> {
+        self.consents.remove(&Self::key(program, scope, capability));
 
         if self.auto_save {
             self.save()?;
@@ -236,18 +437,9 @@ pub fn revoke(&mut self, scope: &str, capability: &str) -> Result<()> {
         Ok(())
     }
 
-    /// Revoke all consents for a scope
+    /// Revoke all consents for a scope, in every program
     pub fn revoke_all(&mut self, scope: &str) -> Result<()> {
-        let keys: Vec<String> = self
-            .consents
-            .keys()
-            .filter(|k| k.starts_with(&format!("{}:", scope)))
-            .cloned()
-            .collect();
-
-        for key in keys {
-            self.consents.remove(&key);
-        }
+        self.consents.retain(|_, consent| consent.scope != scope);
 
         if self.auto_save {
             self.save()?;
@@ -284,46 +476,15 @@ fn default() -> Self {
     }
 }
 
-// Optional: Use 'dirs' crate for cross-platform config directories
-// If not available, provide a fallback
-mod dirs {
-    use std::path::PathBuf;
-
-    pub fn config_dir() -> Option<PathBuf> {
-        #[cfg(target_os = "linux")]
-        {
-            std::env::var("XDG_CONFIG_HOME")
-                .map(PathBuf::from)
-                .ok()
-                .or_else(|| std::env::var("HOME").map(|h| PathBuf::from(h).join(".config")).ok())
-        }
-
-        #[cfg(target_os = "macos")]
-        {
-            std::env::var("HOME")
-                .map(|h| PathBuf::from(h).join("Library/Application Support"))
-                .ok()
-        }
-
-        #[cfg(target_os = "windows")]
-        {
-            std::env::var("APPDATA").map(PathBuf::from).ok()
-        }
-
-        #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
-        {
-            None
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::env;
 
+    const PROGRAM: &str = "/home/ada/tools/main.woke";
+
     fn temp_path(name: &str) -> PathBuf {
-        env::temp_dir().join(format!("wokelang_test_{}.db", name))
+        env::temp_dir().join(format!("wokelang_test_{}.json", name))
     }
 
     #[test]
@@ -332,10 +493,22 @@ fn test_store_and_check() {
         let mut store = ConsentStore::new(path);
         store.set_auto_save(false);
 
-        store.store("main", "file:read", true, ConsentDuration::Forever).unwrap();
+        store.store(PROGRAM, "main", "file:read", true, ConsentDuration::Forever).unwrap();
 
-        assert_eq!(store.check("main", "file:read"), Some(true));
-        assert_eq!(store.check("main", "file:write"), None);
+        assert_eq!(store.check(PROGRAM, "main", "file:read"), Some(true));
+        assert_eq!(store.check(PROGRAM, "main", "file:write"), None);
+    }
+
+    #[test]
+    fn test_programs_with_the_same_name_do_not_share_consent() {
+        let path = temp_path("same_name");
+        let mut store = ConsentStore::new(path);
+        store.set_auto_save(false);
+
+        store.store("/tmp/pt/p1/main.woke", "main.main", "network", true, ConsentDuration::Forever).unwrap();
+
+        assert_eq!(store.check("/tmp/pt/p1/main.woke", "main.main", "network"), Some(true));
+        assert_eq!(store.check("/tmp/pt/p2/main.woke", "main.main", "network"), None);
     }
 
     #[test]
@@ -344,11 +517,11 @@ fn test_revoke() {
         let mut store = ConsentStore::new(path);
         store.set_auto_save(false);
 
-        store.store("main", "network", true, ConsentDuration::Forever).unwrap();
-        assert_eq!(store.check("main", "network"), Some(true));
+        store.store(PROGRAM, "main", "network", true, ConsentDuration::Forever).unwrap();
+        assert_eq!(store.check(PROGRAM, "main", "network"), Some(true));
 
-        store.revoke("main", "network").unwrap();
-        assert_eq!(store.check("main", "network"), None);
+        store.revoke(PROGRAM, "main", "network").unwrap();
+        assert_eq!(store.check(PROGRAM, "main", "network"), None);
     }
 
     #[test]
@@ -360,8 +533,8 @@ fn test_save_and_load() {
         // Store some consents
         {
             let mut store = ConsentStore::new(path.clone());
-            store.store("main", "file:read", true, ConsentDuration::Forever).unwrap();
-            store.store("main", "network", false, ConsentDuration::Day).unwrap();
+            store.store(PROGRAM, "main", "file:read", true, ConsentDuration::Forever).unwrap();
+            store.store(PROGRAM, "main", "network", false, ConsentDuration::Day).unwrap();
         }
 
         // Load in a new store
@@ -369,8 +542,8 @@ fn test_save_and_load() {
             let mut store = ConsentStore::new(path.clone());
             store.load().unwrap();
 
-            assert_eq!(store.check("main", "file:read"), Some(true));
-            assert_eq!(store.check("main", "network"), Some(false));
+            assert_eq!(store.check(PROGRAM, "main", "file:read"), Some(true));
+            assert_eq!(store.check(PROGRAM, "main", "network"), Some(false));
         }
 
         // Clean up
@@ -383,9 +556,77 @@ fn test_once_duration() {
         let mut store = ConsentStore::new(path);
         store.set_auto_save(false);
 
-        store.store("main", "temp", true, ConsentDuration::Once).unwrap();
+        store.store(PROGRAM, "main", "temp", true, ConsentDuration::Once).unwrap();
 
         // Once consents should never be returned from check
-        assert_eq!(store.check("main", "temp"), None);
+        assert_eq!(store.check(PROGRAM, "main", "temp"), None);
+    }
+
+    #[test]
+    fn test_session_consents_are_not_saved() {
+        let path = temp_path("session");
+        let _ = fs::remove_file(&path);
+
+        let mut store = ConsentStore::new(path.clone());
+        store.store(PROGRAM, "stdlib", "network:*", true, ConsentDuration::Session).unwrap();
+        store.store(PROGRAM, "consent", "camera", false, ConsentDuration::Week).unwrap();
+        assert_eq!(store.check(PROGRAM, "stdlib", "network:*"), Some(true));
+
+        let mut reloaded = ConsentStore::new(path.clone());
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.check(PROGRAM, "stdlib", "network:*"), None);
+        assert_eq!(reloaded.check(PROGRAM, "consent", "camera"), Some(false));
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_expired_and_corrupted_files() {
+        let path = temp_path("expired");
+        fs::write(
+            &path,
+            r#"{"consents": [
+                {"program": "/home/ada/tools/main.woke", "scope": "main", "capability": "old", "granted": true, "timestamp": 0, "remember": "day"},
+                {"program": "/home/ada/tools/main.woke", "scope": "main", "capability": "kept", "granted": true, "timestamp": 0, "remember": "forever"},
+                {"scope": "main", "capability": "unowned", "granted": true, "timestamp": 0, "remember": "forever"}
+            ]}"#,
+        )
+        .unwrap();
+        let mut store = ConsentStore::new(path.clone());
+        store.load().unwrap();
+        assert_eq!(store.check(PROGRAM, "main", "old"), None);
+        assert_eq!(store.check(PROGRAM, "main", "kept"), Some(true));
+        assert_eq!(store.list().len(), 1);
+
+        fs::write(&path, r#"{"consents": [{"program": "/home/ada/tools/main.woke", "scope": "main"}]}"#).unwrap();
+        assert!(matches!(
+            ConsentStore::new(path.clone()).load(),
+            Err(ConsentError::CorruptedFile)
+        ));
+        fs::write(&path, "main|network|yes|0|forever").unwrap();
+        assert!(matches!(
+            ConsentStore::new(path.clone()).load(),
+            Err(ConsentError::ParseError(_))
+        ));
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_parse_answer() {
+        assert_eq!(parse_answer("y\n"), (true, ConsentDuration::Session));
+        assert_eq!(parse_answer("Week"), (true, ConsentDuration::Week));
+        assert_eq!(parse_answer("a"), (true, ConsentDuration::Forever));
+        assert_eq!(parse_answer("never"), (false, ConsentDuration::Forever));
+        assert_eq!(parse_answer(""), (false, ConsentDuration::Session));
+        assert_eq!(parse_answer("maybe"), (false, ConsentDuration::Session));
+    }
+
+    #[test]
+    fn test_parse_consent_mode() {
+        assert_eq!(ConsentMode::parse("deny"), Some(ConsentMode::Deny));
+        assert_eq!(ConsentMode::parse(" Allow "), Some(ConsentMode::Allow));
+        assert_eq!(ConsentMode::parse("policy"), Some(ConsentMode::Policy));
+        assert_eq!(ConsentMode::parse("yes"), None);
     }
 }
@@ -0,0 +1,320 @@
+//! Resource Limits
+//!
+//! How long a run may take and how much memory its values may hold. A
+//! program declares its own with pragmas, and a host can set tighter ones;
+//! where both set a limit, the lower one holds:
+//!
+//! ```text
+//! #timeout 5s;        the run stops with WOKE-E0313 after 5 seconds
+//! #maxmemory 64mb;    and with WOKE-E0314 once its values hold over 64 MiB
+//! ```
+//!
+//! Memory is what the values a run can still reach hold, measured between
+//! statements rather than on each allocation, so a run may go a little
+//! over before it is stopped. The more values there are, the less often
+//! they are measured, so measuring costs a run a few percent at most.
+//!
+//! The clock is read every so often, after each standard library call and
+//! when the run ends. Calls that block, such as `sleep`, running a process,
+//! waiting on a socket or serving HTTP, wait no longer than the time left
+//! (see [`time_left`]). Timeouts are not kept in the browser playground,
+//! which has no clock to keep them with.
+
+use crate::ast::{PragmaDirective, Program, TopLevelItem};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
+use thiserror::Error;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{cell::Cell, time::Instant};
+
+/// Instructions or steps run between checks of the clock
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Values measured for each step until the next measure
+const VALUES_PER_STEP: u64 = 8;
+
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+    /// When the run making the current standard library call times out
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Keeps the deadline of a run for the standard library calls it makes
+/// while held, restoring the one before when dropped
+pub struct CallDeadline {
+    #[cfg(not(target_arch = "wasm32"))]
+    outer: Option<Instant>,
+}
+
+/// Let what blocks in the standard library calls made while the result is
+/// held wait only for the time left to the run with `budget`
+pub fn enter_call(budget: Option<&Budget>) -> CallDeadline {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let deadline = budget.and_then(|b| Some(b.started + b.limits.timeout?));
+        CallDeadline {
+            outer: DEADLINE.with(|d| d.replace(deadline.or(d.get()))),
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = budget;
+        CallDeadline {}
+    }
+}
+
+impl Drop for CallDeadline {
+    fn drop(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        DEADLINE.with(|d| d.set(self.outer));
+    }
+}
+
+/// How much longer a blocking standard library call may wait before the
+/// run making it times out; `None` when it has no timeout
+pub fn time_left() -> Option<Duration> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return DEADLINE.with(Cell::get).map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    #[cfg(target_arch = "wasm32")]
+    None
+}
+
+/// The limits of one run; `None` is no limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    pub timeout: Option<Duration>,
+    /// Bytes
+    pub max_memory: Option<u64>,
+}
+
+impl Limits {
+    /// The limits a program declares with `#timeout` and `#maxmemory`
+    pub fn of(program: &Program) -> Self {
+        let mut limits = Self::default();
+        for item in &program.items {
+            if let TopLevelItem::Pragma(pragma) = item {
+                limits.apply(pragma.directive);
+            }
+        }
+        limits
+    }
+
+    /// Take on the limit a pragma sets, if it sets one
+    pub fn apply(&mut self, directive: PragmaDirective) {
+        match directive {
+            PragmaDirective::Timeout(timeout) => self.tighten(Limits { timeout: Some(timeout), max_memory: None }),
+            PragmaDirective::MaxMemory(bytes) => self.tighten(Limits { timeout: None, max_memory: Some(bytes) }),
+            _ => {}
+        }
+    }
+
+    /// Take on the limits of `other` too, keeping the lower of two
+    pub fn tighten(&mut self, other: Limits) {
+        fn lower<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+        self.timeout = lower(self.timeout, other.timeout);
+        self.max_memory = lower(self.max_memory, other.max_memory);
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.timeout.is_none() && self.max_memory.is_none()
+    }
+}
+
+/// A limit a run went over
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum LimitExceeded {
+    #[error("Timed out after {}", Timeout(*.0))]
+    Timeout(Duration),
+
+    #[error("Memory limit of {} exceeded", Bytes(*.0))]
+    Memory(u64),
+}
+
+impl LimitExceeded {
+    /// The error's code in the `woke explain` catalog
+    pub fn code(&self) -> &'static str {
+        match self {
+            LimitExceeded::Timeout(_) => "WOKE-E0313",
+            LimitExceeded::Memory(_) => "WOKE-E0314",
+        }
+    }
+}
+
+/// A timeout as a pragma writes it: `5s`, `1500ms`
+pub struct Timeout(pub Duration);
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.0.as_millis();
+        match millis % 1000 {
+            0 => write!(f, "{}s", millis / 1000),
+            _ => write!(f, "{}ms", millis),
+        }
+    }
+}
+
+/// A memory size as a pragma writes it, in the largest unit it is a whole
+/// number of: `64mb`, `1500b`
+pub struct Bytes(pub u64);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (unit, size) in [("gb", 1 << 30), ("mb", 1 << 20), ("kb", 1 << 10)] {
+            if self.0 >= size && self.0.is_multiple_of(size) {
+                return write!(f, "{}{}", self.0 / size, unit);
+            }
+        }
+        write!(f, "{}b", self.0)
+    }
+}
+
+/// Bytes in one of a memory size unit, `b`, `kb`, `mb` or `gb`, in any case
+pub fn memory_unit(unit: &str) -> Option<u64> {
+    match unit.to_ascii_lowercase().as_str() {
+        "b" => Some(1),
+        "kb" => Some(1 << 10),
+        "mb" => Some(1 << 20),
+        "gb" => Some(1 << 30),
+        _ => None,
+    }
+}
+
+/// A run's limits, and how far it has got towards them
+#[derive(Debug)]
+pub struct Budget {
+    limits: Limits,
+    #[cfg(not(target_arch = "wasm32"))]
+    started: Instant,
+    steps: u64,
+    /// The step at which memory is next measured
+    next_measure: u64,
+}
+
+impl Budget {
+    /// Start the clock on a run
+    pub fn start(limits: Limits) -> Self {
+        Self {
+            limits,
+            #[cfg(not(target_arch = "wasm32"))]
+            started: Instant::now(),
+            steps: 0,
+            next_measure: 0,
+        }
+    }
+
+    /// Count one step of the run, checking the clock every so often
+    pub fn step(&mut self) -> Result<(), LimitExceeded> {
+        self.steps += 1;
+        if !self.steps.is_multiple_of(CHECK_INTERVAL) {
+            return Ok(());
+        }
+        self.check_clock()
+    }
+
+    /// Check the run has not gone over its timeout, as it may have in a
+    /// standard library call or since the last step checked
+    pub fn check_clock(&self) -> Result<(), LimitExceeded> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(timeout) = self.limits.timeout {
+            if self.started.elapsed() > timeout {
+                return Err(LimitExceeded::Timeout(timeout));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether it is time to measure memory again, with
+    /// [`Budget::check_memory`]
+    pub fn should_measure(&self) -> bool {
+        self.limits.max_memory.is_some() && self.steps >= self.next_measure
+    }
+
+    /// Check how much memory the run's values hold. The more values were
+    /// measured, the longer until the next measure.
+    pub fn check_memory(&mut self, footprint: &Footprint) -> Result<(), LimitExceeded> {
+        self.next_measure = self.steps + (footprint.values / VALUES_PER_STEP).max(1);
+        match self.limits.max_memory {
+            Some(limit) if footprint.bytes > limit => Err(LimitExceeded::Memory(limit)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A measure of the memory a run's values hold
+#[derive(Debug, Default)]
+pub struct Footprint {
+    /// Bytes held
+    pub bytes: u64,
+    /// Values measured
+    pub values: u64,
+    /// Addresses of shared strings, arrays and closures measured so far
+    seen: HashSet<usize>,
+}
+
+impl Footprint {
+    /// Count a value of `bytes`
+    pub fn add(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        self.values += 1;
+    }
+
+    /// Whether the contents shared at `address` are yet to be counted; a
+    /// value shared between variables holds its memory once
+    pub fn first_sight(&mut self, address: usize) -> bool {
+        self.seen.insert(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let mut limits = Limits::default();
+        assert!(limits.is_none());
+        limits.apply(PragmaDirective::Timeout(Duration::from_secs(5)));
+        limits.apply(PragmaDirective::MaxMemory(64 << 20));
+        limits.apply(PragmaDirective::Care);
+        limits.tighten(Limits { timeout: Some(Duration::from_secs(2)), max_memory: None });
+        assert_eq!(limits, Limits { timeout: Some(Duration::from_secs(2)), max_memory: Some(64 << 20) });
+
+        assert_eq!(Timeout(Duration::from_secs(5)).to_string(), "5s");
+        assert_eq!(Timeout(Duration::from_millis(1500)).to_string(), "1500ms");
+        assert_eq!(Bytes(64 << 20).to_string(), "64mb");
+        assert_eq!(Bytes(1536).to_string(), "1536b");
+        assert_eq!(Bytes(2048).to_string(), "2kb");
+        assert_eq!(memory_unit("MB"), Some(1 << 20));
+        assert_eq!(memory_unit("mib"), None);
+        assert_eq!(LimitExceeded::Memory(1 << 20).to_string(), "Memory limit of 1mb exceeded");
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut budget = Budget::start(Limits { timeout: Some(Duration::ZERO), max_memory: Some(100) });
+        let stopped = (0..CHECK_INTERVAL).map(|_| budget.step()).find(Result::is_err);
+        assert_eq!(stopped, Some(Err(LimitExceeded::Timeout(Duration::ZERO))));
+
+        let mut footprint = Footprint::default();
+        (0..16).for_each(|_| footprint.add(6));
+        assert!(budget.should_measure());
+        assert_eq!(budget.check_memory(&footprint), Ok(()));
+        assert!(!budget.should_measure());
+        budget.step().unwrap();
+        assert!(!budget.should_measure());
+        budget.step().unwrap();
+        assert!(budget.should_measure());
+        footprint.add(5);
+        assert_eq!(budget.check_memory(&footprint), Err(LimitExceeded::Memory(100)));
+        assert!(footprint.first_sight(1));
+        assert!(!footprint.first_sight(1));
+    }
+}
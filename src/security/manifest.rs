@@ -0,0 +1,429 @@
+//! Signed Capability Manifests for WokeLang
+//!
+//! A script handed to other people can come with a manifest listing the
+//! superpowers it needs, signed by its author with an ed25519 key. For
+//! `backup.woke` the manifest is `backup.manifest.toml` next to it:
+//!
+//! ```toml
+//! author = "Ada"
+//! program_sha256 = "9f86d081884c7d65..."
+//! public_key = "0v4aUi0i7cR3HAXdbyXsmSoO0ZeOC6AvBoVA5P+rRWQ="
+//! signature = "kJ3rKwwj6x0M..."
+//!
+//! [allow]
+//! read = ["data"]
+//! network = ["api.example.com"]
+//!
+//! [quota]
+//! network_requests = 10
+//! ```
+//!
+//! `[allow]` and `[quota]` are written as in a policy file (see
+//! [`super::policy`]). The signature covers the program's SHA-256 hash, the
+//! author and everything the manifest allows, so none of them can be
+//! changed without the signature breaking. Write the `[allow]` and
+//! `[quota]` sections, then fill in the rest with [`Manifest::sign`]
+//! (`woke manifest sign`).
+//!
+//! A valid signature shows the manifest has not been changed since it was
+//! signed with the key it names; whether to trust that key is up to the
+//! person running the script, so [`Manifest::key_fingerprint`] is shown
+//! when asking them.
+
+use super::{Policy, PolicyError};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// What a manifest's name adds to the program's file stem
+pub const MANIFEST_SUFFIX: &str = ".manifest.toml";
+
+/// Top-level keys the signature is kept in, rather than covering
+const SIGNATURE_KEYS: [&str; 3] = ["program_sha256", "public_key", "signature"];
+
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("Could not read {path}: {message}")]
+    Io { path: String, message: String },
+
+    #[error("Invalid manifest: {0}")]
+    Invalid(String),
+
+    #[error(transparent)]
+    Policy(#[from] PolicyError),
+
+    #[error("The program has changed since the manifest was signed")]
+    ProgramChanged,
+
+    #[error("The manifest's signature does not match what it allows")]
+    BadSignature,
+}
+
+type Result<T> = std::result::Result<T, ManifestError>;
+
+/// A signed list of the superpowers a program needs
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// Who signed it, as they describe themselves
+    pub author: Option<String>,
+    /// Hex SHA-256 of the program the manifest was signed for
+    pub program_sha256: String,
+    /// What the program is allowed, as signed; see [`Manifest::allowed`]
+    pub policy: Policy,
+    public_key: VerifyingKey,
+    signature: Signature,
+    /// Directory the manifest was loaded from, which relative paths are in
+    dir: Option<PathBuf>,
+}
+
+impl Manifest {
+    /// Where the manifest for `program` would be: `dir/name.manifest.toml`
+    /// for `dir/name.woke`
+    pub fn path_for(program: &Path) -> PathBuf {
+        let stem = program.file_stem().unwrap_or_default().to_string_lossy();
+        program.with_file_name(format!("{}{}", stem, MANIFEST_SUFFIX))
+    }
+
+    /// The manifest that came with `program`, if there is one
+    pub fn find_for(program: &Path) -> Option<PathBuf> {
+        let path = Self::path_for(program);
+        path.is_file().then_some(path)
+    }
+
+    /// Read a manifest file, without checking its signature
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut manifest = Self::parse(&read(path)?)?;
+        manifest.dir = Some(path.parent().unwrap_or(Path::new("")).to_path_buf());
+        Ok(manifest)
+    }
+
+    /// Parse the text of a manifest, without checking its signature
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut table = parse_table(text)?;
+        let author = match table.remove("author") {
+            Some(toml::Value::String(author)) => Some(author),
+            Some(_) => return Err(ManifestError::Invalid("'author' must be a string".to_string())),
+            None => None,
+        };
+        let mut take = |key: &str| match table.remove(key) {
+            Some(toml::Value::String(value)) => Ok(value),
+            _ => Err(ManifestError::Invalid(format!(
+                "missing '{}'; sign the manifest with `woke manifest sign`",
+                key
+            ))),
+        };
+        let program_sha256 = take("program_sha256")?;
+        let public_key = take("public_key")?;
+        let signature = take("signature")?;
+
+        let public_key = decode_key(&public_key)
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| ManifestError::Invalid("'public_key' is not an ed25519 key".to_string()))?;
+        let signature = STANDARD
+            .decode(signature.trim())
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| ManifestError::Invalid("'signature' is not an ed25519 signature".to_string()))?;
+
+        Ok(Manifest {
+            author,
+            program_sha256,
+            policy: Policy::from_table(&table)?,
+            public_key,
+            signature,
+            dir: None,
+        })
+    }
+
+    /// Check that the manifest was signed for `source` and has not been
+    /// changed since
+    pub fn verify(&self, source: &str) -> Result<()> {
+        if sha256_hex(source) != self.program_sha256 {
+            return Err(ManifestError::ProgramChanged);
+        }
+        let message = signed_message(&self.program_sha256, self.author.as_deref(), &self.policy);
+        self.public_key
+            .verify(&message, &self.signature)
+            .map_err(|_| ManifestError::BadSignature)
+    }
+
+    /// Sign a manifest for `source`, returning its text
+    ///
+    /// `draft` is the manifest so far: its `[allow]` and `[quota]`
+    /// sections, and an `author` if wanted. The hash, key and signature of
+    /// an earlier signing are replaced.
+    pub fn sign(draft: &str, source: &str, key: &SigningKey) -> Result<String> {
+        let mut table = parse_table(draft)?;
+        for name in SIGNATURE_KEYS {
+            table.remove(name);
+        }
+        let mut sections = table.clone();
+        let author = match sections.remove("author") {
+            Some(toml::Value::String(author)) => Some(author),
+            Some(_) => return Err(ManifestError::Invalid("'author' must be a string".to_string())),
+            None => None,
+        };
+        let policy = Policy::from_table(&sections)?;
+
+        let program_sha256 = sha256_hex(source);
+        let signature = key.sign(&signed_message(&program_sha256, author.as_deref(), &policy));
+        let encoded = [
+            program_sha256,
+            STANDARD.encode(key.verifying_key().as_bytes()),
+            STANDARD.encode(signature.to_bytes()),
+        ];
+        for (name, value) in SIGNATURE_KEYS.into_iter().zip(encoded) {
+            table.insert(name.to_string(), toml::Value::String(value));
+        }
+        Ok(table.to_string())
+    }
+
+    /// A short fingerprint of the signing key, for comparing with the one
+    /// the author publishes
+    pub fn key_fingerprint(&self) -> String {
+        key_fingerprint(&self.public_key)
+    }
+
+    /// What the manifest allows, with relative paths taken from the
+    /// directory it was loaded from
+    pub fn allowed(&self) -> Policy {
+        match &self.dir {
+            Some(dir) => self.policy.clone().relative_to(dir),
+            None => self.policy.clone(),
+        }
+    }
+
+    /// What the manifest allows, one line each, as shown before granting it
+    pub fn describe(&self) -> Vec<String> {
+        let policy = self.allowed();
+        let mut lines: Vec<String> = policy.capabilities.iter().map(|c| c.to_string()).collect();
+        lines.extend(policy.consents.iter().map(|c| format!("consent: {}", c)));
+        lines.extend(
+            policy
+                .quotas
+                .iter()
+                .map(|(quota, limit)| format!("at most {} {} per run", limit, quota)),
+        );
+        lines
+    }
+}
+
+/// Make a new signing key
+pub fn generate_key() -> std::result::Result<SigningKey, String> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| e.to_string())?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Save a signing key, readable only by its owner where that can be set
+pub fn save_key(key: &SigningKey, path: &Path) -> Result<()> {
+    let io_error = |e: std::io::Error| ManifestError::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    };
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(io_error)?;
+    // The mode only applies to a new file, so narrow one saved over too
+    // before the key goes in
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600)).map_err(io_error)?;
+    }
+    file.write_all(format!("{}\n", STANDARD.encode(key.to_bytes())).as_bytes()).map_err(io_error)
+}
+
+/// Read a signing key saved by [`save_key`]
+pub fn load_key(path: &Path) -> Result<SigningKey> {
+    let bytes = decode_key(&read(path)?)
+        .ok_or_else(|| ManifestError::Invalid(format!("{} is not a signing key", path.display())))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// A short fingerprint of a public key: the start of its SHA-256 hash
+pub fn key_fingerprint(key: &VerifyingKey) -> String {
+    let hash = Sha256::digest(key.as_bytes());
+    hash[..8]
+        .chunks(2)
+        .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// What the signature covers: the program's hash, the author if there is
+/// one, and everything the manifest allows, one item per line in a fixed
+/// order
+fn signed_message(program_sha256: &str, author: Option<&str>, policy: &Policy) -> Vec<u8> {
+    let mut items: Vec<String> = policy
+        .capabilities
+        .iter()
+        // Paths are signed the same way whichever platform signs them
+        .map(|c| format!("allow {}", c.to_string().replace('\\', "/")))
+        .collect();
+    items.extend(policy.consents.iter().map(|c| format!("consent {}", c)));
+    items.extend(policy.quotas.iter().map(|(q, n)| format!("quota {} {}", q, n)));
+    items.sort();
+    items.dedup();
+
+    let mut message = format!("wokelang manifest v1\nprogram {}\n", program_sha256);
+    // Quoted, so that an author cannot read as lines of the policy
+    if let Some(author) = author {
+        message.push_str(&format!("author {:?}\n", author));
+    }
+    for item in items {
+        message.push_str(&item);
+        message.push('\n');
+    }
+    message.into_bytes()
+}
+
+fn sha256_hex(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn decode_key(text: &str) -> Option<[u8; 32]> {
+    STANDARD.decode(text.trim()).ok()?.try_into().ok()
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|e| ManifestError::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })
+}
+
+fn parse_table(text: &str) -> Result<toml::Table> {
+    text.parse()
+        .map_err(|e: toml::de::Error| ManifestError::Invalid(e.message().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::{Capability, Quota};
+
+    const SOURCE: &str = "to main() { print(\"hi\"); }\n";
+    const DRAFT: &str = r#"
+        author = "Ada"
+
+        [allow]
+        read = ["data"]
+        network = ["api.example.com"]
+        consents = ["send_analytics"]
+
+        [quota]
+        network_requests = 10
+    "#;
+
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let text = Manifest::sign(DRAFT, SOURCE, &key()).unwrap();
+        let manifest = Manifest::parse(&text).unwrap();
+        manifest.verify(SOURCE).unwrap();
+
+        assert_eq!(manifest.author.as_deref(), Some("Ada"));
+        assert_eq!(manifest.key_fingerprint(), key_fingerprint(&key().verifying_key()));
+        assert_eq!(
+            manifest.policy.capabilities,
+            [
+                Capability::Network(Some("api.example.com".to_string())),
+                Capability::FileRead(Some(PathBuf::from("data"))),
+            ]
+        );
+        assert_eq!(manifest.policy.quotas, [(Quota::NetworkRequests, 10)]);
+        assert_eq!(
+            manifest.describe(),
+            [
+                "network:api.example.com",
+                "file:read:data",
+                "consent: send_analytics",
+                "at most 10 network requests per run",
+            ]
+        );
+
+        // Signing again replaces the old signature
+        let again = Manifest::sign(&text, SOURCE, &key()).unwrap();
+        Manifest::parse(&again).unwrap().verify(SOURCE).unwrap();
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let text = Manifest::sign(DRAFT, SOURCE, &key()).unwrap();
+        let manifest = Manifest::parse(&text).unwrap();
+        assert!(matches!(
+            manifest.verify("to main() { print(\"bye\"); }\n"),
+            Err(ManifestError::ProgramChanged)
+        ));
+
+        let widened = text.replace("\"api.example.com\"", "\"*\"");
+        assert_ne!(widened, text);
+        assert!(matches!(
+            Manifest::parse(&widened).unwrap().verify(SOURCE),
+            Err(ManifestError::BadSignature)
+        ));
+
+        // The author is signed too, so it cannot be changed or removed
+        for forged in [text.replace("\"Ada\"", "\"Grace\""), text.replace("author = \"Ada\"", "")] {
+            assert_ne!(forged, text);
+            assert!(matches!(
+                Manifest::parse(&forged).unwrap().verify(SOURCE),
+                Err(ManifestError::BadSignature)
+            ));
+        }
+
+        // A manifest without an author is signed without one
+        let unnamed = Manifest::sign(&DRAFT.replace("author = \"Ada\"", ""), SOURCE, &key()).unwrap();
+        let manifest = Manifest::parse(&unnamed).unwrap();
+        assert_eq!(manifest.author, None);
+        manifest.verify(SOURCE).unwrap();
+
+        assert!(matches!(Manifest::parse(DRAFT), Err(ManifestError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_key_files_and_paths() {
+        let dir = std::env::temp_dir().join("wokelang_test_manifest");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("author.key");
+        fs::write(&path, "old").unwrap();
+        save_key(&key(), &path).unwrap();
+        assert_eq!(load_key(&path).unwrap().to_bytes(), key().to_bytes());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let program = dir.join("backup.woke");
+        assert_eq!(Manifest::path_for(&program), dir.join("backup.manifest.toml"));
+        assert_eq!(Manifest::find_for(&program), None);
+
+        // Relative paths are in the manifest's directory once loaded, but
+        // signed as written
+        fs::write(Manifest::path_for(&program), Manifest::sign(DRAFT, SOURCE, &key()).unwrap()).unwrap();
+        let manifest = Manifest::load(&Manifest::find_for(&program).unwrap()).unwrap();
+        manifest.verify(SOURCE).unwrap();
+        assert!(manifest.policy.capabilities.contains(&Capability::FileRead(Some(PathBuf::from("data")))));
+        assert!(manifest.allowed().capabilities.contains(&Capability::FileRead(Some(dir.join("data")))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
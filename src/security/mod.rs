@@ -4,11 +4,22 @@
 //! that requires explicit consent for sensitive operations.
 
 pub mod consent;
+pub mod limits;
+pub mod manifest;
+pub mod policy;
+pub mod secrets;
 
-pub use consent::{ConsentDuration, ConsentStore, StoredConsent};
+pub use consent::{ConsentDuration, ConsentError, ConsentMode, ConsentStore, StoredConsent};
+pub use limits::{Budget, Footprint, LimitExceeded, Limits};
+pub use manifest::{Manifest, ManifestError};
+pub use policy::{Policy, PolicyError};
 
+use crate::stdlib::glob::matches_part;
+use crate::stdlib::path::normalize_path;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
@@ -28,10 +39,44 @@ pub enum SecurityError {
 
     #[error("Invalid capability: {0}")]
     InvalidCapability(String),
+
+    #[error("{capability} was not declared when importing {module}")]
+    NotDeclared { module: String, capability: String },
+
+    #[error("Quota exceeded: at most {limit} {quota} per run")]
+    QuotaExceeded { quota: Quota, limit: u64 },
+
+    #[error("Not available in the sandbox: {0}")]
+    Sandboxed(String),
 }
 
 type Result<T> = std::result::Result<T, SecurityError>;
 
+/// Scope that `only if okay` consent block decisions are kept under
+pub const CONSENT_SCOPE: &str = "consent";
+
+/// Scope of capability checks made outside any WokeLang function, such as
+/// a host calling the standard library directly
+pub const HOST_SCOPE: &str = "stdlib";
+
+/// Scope of checks made in `function` of `module`: `backup:copyFiles`.
+/// The colon keeps a function's scope from being taken for a module's,
+/// so a grant to `backup:util` never covers the module `backup.util`.
+/// An imported function (`circle.area`) is named by its last part.
+pub fn function_scope(module: &str, function: &str) -> String {
+    let function = function.rsplit('.').next().unwrap_or(function);
+    format!("{}:{}", module, function)
+}
+
+/// The scope a grant to which also covers `scope`: a function's module,
+/// then each module it is inside
+fn parent_scope(scope: &str) -> Option<&str> {
+    scope
+        .rsplit_once(':')
+        .or_else(|| scope.rsplit_once('.'))
+        .map(|(parent, _)| parent)
+}
+
 /// Types of capabilities (superpowers) in WokeLang
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Capability {
@@ -59,6 +104,24 @@ pub enum Capability {
     Custom(String),
 }
 
+impl Capability {
+    /// A copy with any secret in its path, program, host, variable or name
+    /// masked, for the audit log
+    pub fn redacted(&self) -> Capability {
+        let text = |s: &String| secrets::redact(s).into_owned();
+        let path = |p: &PathBuf| PathBuf::from(secrets::redact(&p.to_string_lossy()).into_owned());
+        match self {
+            Capability::FileRead(p) => Capability::FileRead(p.as_ref().map(path)),
+            Capability::FileWrite(p) => Capability::FileWrite(p.as_ref().map(path)),
+            Capability::Execute(c) => Capability::Execute(c.as_ref().map(text)),
+            Capability::Network(h) => Capability::Network(h.as_ref().map(text)),
+            Capability::Environment(v) => Capability::Environment(v.as_ref().map(text)),
+            Capability::Custom(name) => Capability::Custom(text(name)),
+            other => other.clone(),
+        }
+    }
+}
+
 impl std::fmt::Display for Capability {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -107,6 +170,82 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     }
 }
 
+impl std::str::FromStr for Capability {
+    type Err = SecurityError;
+
+    /// Parse a capability as it is displayed, e.g. `network:*`,
+    /// `file:read:data` or `crypto`; leaving the value off means any value
+    fn from_str(s: &str) -> Result<Self> {
+        let kinds = [
+            ("file:read", "read"),
+            ("file:write", "write"),
+            ("execute", "execute"),
+            ("network", "network"),
+            ("env", "env"),
+        ];
+        for (prefix, kind) in kinds {
+            let Some(rest) = s.strip_prefix(prefix) else {
+                continue;
+            };
+            let value = match rest.strip_prefix(':') {
+                Some(value) => Some(value),
+                None if rest.is_empty() => None,
+                None => continue,
+            };
+            if let Some(capability) = policy::capability_for(kind, value) {
+                return Ok(capability);
+            }
+        }
+        policy::named_capability(s).map_err(|_| SecurityError::InvalidCapability(s.to_string()))
+    }
+}
+
+/// Written as it is displayed, e.g. for compiled programs in the build cache
+impl Serialize for Capability {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Capability {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// How much of a superpower a program may use in one run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Quota {
+    /// HTTP requests made
+    NetworkRequests,
+    /// Bytes written to files
+    WriteBytes,
+    /// Commands run or spawned
+    Processes,
+}
+
+impl Quota {
+    /// The quota named as in a policy file's `[quota]` section
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "network_requests" => Some(Quota::NetworkRequests),
+            "write_bytes" => Some(Quota::WriteBytes),
+            "processes" => Some(Quota::Processes),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Quota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Quota::NetworkRequests => write!(f, "network requests"),
+            Quota::WriteBytes => write!(f, "bytes written"),
+            Quota::Processes => write!(f, "processes"),
+        }
+    }
+}
+
 /// A granted capability with metadata
 #[derive(Debug, Clone)]
 pub struct GrantedCapability {
@@ -161,6 +300,8 @@ pub struct AuditEntry {
     pub action: AuditAction,
     pub context: String,
     pub success: bool,
+    /// Why the program said it needs the capability, if it said
+    pub reason: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -173,6 +314,9 @@ pub enum AuditAction {
     Expired,
 }
 
+/// Answers a consent prompt's question with yes or no
+pub type PromptHandler = Box<dyn FnMut(&str) -> bool>;
+
 /// The capability registry that manages all superpowers
 pub struct CapabilityRegistry {
     /// Granted capabilities
@@ -186,6 +330,28 @@ pub struct CapabilityRegistry {
     interactive: bool,
     /// Default consent decision (for non-interactive mode)
     default_consent: bool,
+    /// Decisions remembered from earlier runs
+    consent_store: Option<ConsentStore>,
+    /// Canonical path of the program decisions are remembered for; a
+    /// program without one, e.g. read from stdin, has none remembered
+    program: Option<String>,
+    /// Scope that standard library checks are made in: the running
+    /// function, as [`function_scope`] names it
+    scope: String,
+    /// Standard library module whose function is running, if any
+    module: Option<String>,
+    /// What imports declared their modules need, by module
+    declared: HashMap<String, SuperpowerDeclaration>,
+    /// Quota limits, and how much of each has been used
+    quotas: HashMap<Quota, (u64, u64)>,
+    /// Capabilities given up for the rest of the run
+    renounced: Vec<Capability>,
+    /// How long a prompt waits for an answer before denying
+    prompt_timeout: Option<Duration>,
+    /// The only directory files may be used in, when sandboxed
+    sandbox_root: Option<PathBuf>,
+    /// Answers prompts in place of stdin, e.g. through a debugger
+    prompt_handler: Option<PromptHandler>,
 }
 
 impl CapabilityRegistry {
@@ -196,6 +362,16 @@ pub fn new() -> Self {
             audit_log: Vec::new(),
             interactive: true,
             default_consent: false,
+            consent_store: None,
+            program: None,
+            scope: HOST_SCOPE.to_string(),
+            module: None,
+            declared: HashMap::new(),
+            quotas: HashMap::new(),
+            renounced: Vec::new(),
+            prompt_timeout: Some(consent::DEFAULT_PROMPT_TIMEOUT),
+            sandbox_root: None,
+            prompt_handler: None,
         }
     }
 
@@ -207,6 +383,16 @@ pub fn permissive() -> Self {
             audit_log: Vec::new(),
             interactive: false,
             default_consent: true,
+            consent_store: None,
+            program: None,
+            scope: HOST_SCOPE.to_string(),
+            module: None,
+            declared: HashMap::new(),
+            quotas: HashMap::new(),
+            renounced: Vec::new(),
+            prompt_timeout: Some(consent::DEFAULT_PROMPT_TIMEOUT),
+            sandbox_root: None,
+            prompt_handler: None,
         }
     }
 
@@ -216,7 +402,7 @@ pub fn grant(&mut self, scope: &str, capability: Capability, granted_by: &str) {
 
         self.capabilities
             .entry(scope.to_string())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(entry);
 
         self.audit(capability, AuditAction::Granted, scope, true);
@@ -229,7 +415,7 @@ pub fn grant_temporary(&mut self, scope: &str, capability: Capability, duration:
 
         self.capabilities
             .entry(scope.to_string())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(entry);
 
         self.audit(capability, AuditAction::Granted, scope, true);
@@ -248,33 +434,106 @@ pub fn revoke(&mut self, scope: &str, capability: &Capability) {
     }
 
     /// Check if a capability is granted for a scope
+    ///
+    /// Scopes name a function in its module, such as `app:main`, and a
+    /// grant to `app` covers every function in that module and the
+    /// modules inside it (`app.tools`).
     pub fn has_capability(&self, scope: &str, capability: &Capability) -> bool {
-        // Check exact scope
-        if let Some(caps) = self.capabilities.get(scope) {
-            for cap in caps {
-                if &cap.capability == capability && cap.is_valid() {
-                    return true;
-                }
-                // Check wildcard capabilities
-                if self.capability_matches(&cap.capability, capability) && cap.is_valid() {
-                    return true;
-                }
+        if self.is_renounced(capability) || self.is_sandboxed(capability) {
+            return false;
+        }
+        let mut current = Some(scope);
+        while let Some(scope) = current {
+            if self.granted_in(scope, capability) {
+                return true;
             }
+            current = parent_scope(scope);
         }
 
         // Check global scope
-        if let Some(caps) = self.capabilities.get("*") {
-            for cap in caps {
-                if self.capability_matches(&cap.capability, capability) && cap.is_valid() {
-                    return true;
-                }
+        self.granted_in("*", capability)
+    }
+
+    /// Valid capabilities granted to a scope, its parents or every scope
+    pub fn effective_capabilities(&self, scope: &str) -> Vec<&Capability> {
+        let mut scopes = vec!["*", scope];
+        let mut current = scope;
+        while let Some(parent) = parent_scope(current) {
+            scopes.push(parent);
+            current = parent;
+        }
+        scopes
+            .into_iter()
+            .filter_map(|scope| self.capabilities.get(scope))
+            .flatten()
+            .filter(|cap| {
+                cap.is_valid() && !self.is_renounced(&cap.capability) && !self.is_sandboxed(&cap.capability)
+            })
+            .map(|cap| &cap.capability)
+            .collect()
+    }
+
+    /// Give up a capability, and everything it covers, for the rest of the
+    /// run: existing grants are revoked, and later requests are refused
+    /// without asking
+    pub fn renounce(&mut self, capability: Capability) {
+        let mut granted = std::mem::take(&mut self.capabilities);
+        for cap in granted.values_mut().flatten() {
+            if self.capability_matches(&capability, &cap.capability) {
+                cap.revoked = true;
             }
         }
+        self.capabilities = granted;
+        let scope = self.scope.clone();
+        self.audit(capability.clone(), AuditAction::Revoked, &scope, true);
+        self.renounced.push(capability);
+    }
+
+    fn is_renounced(&self, capability: &Capability) -> bool {
+        self.renounced
+            .iter()
+            .any(|renounced| self.capability_matches(renounced, capability))
+    }
 
-        false
+    /// Confine the run: network access, programs, processes and
+    /// environment variables can no longer be used at all, and files only
+    /// inside `root`. Nothing granted before or after changes that.
+    pub fn sandbox(&mut self, root: &Path) {
+        self.sandbox_root = Some(resolve_path(root));
     }
 
-    /// Check if a wildcard capability matches a specific one
+    pub fn sandbox_root(&self) -> Option<&Path> {
+        self.sandbox_root.as_deref()
+    }
+
+    /// Whether the sandbox rules a capability out, whatever is granted
+    fn is_sandboxed(&self, capability: &Capability) -> bool {
+        let Some(root) = &self.sandbox_root else {
+            return false;
+        };
+        match capability {
+            Capability::FileRead(path) | Capability::FileWrite(path) => {
+                !path.as_ref().is_some_and(|path| resolve_path(path).starts_with(root))
+            }
+            Capability::Network(_)
+            | Capability::Execute(_)
+            | Capability::Process
+            | Capability::Environment(_) => true,
+            _ => false,
+        }
+    }
+
+    fn granted_in(&self, scope: &str, capability: &Capability) -> bool {
+        self.capabilities.get(scope).is_some_and(|caps| {
+            caps.iter()
+                .any(|cap| cap.is_valid() && self.capability_matches(&cap.capability, capability))
+        })
+    }
+
+    /// Check if a granted capability covers a requested one
+    ///
+    /// A granted path covers everything inside it, and a granted host may
+    /// be a pattern such as `*.example.com`.
     fn capability_matches(&self, granted: &Capability, requested: &Capability) -> bool {
         match (granted, requested) {
             (Capability::FileRead(None), Capability::FileRead(_)) => true,
@@ -282,6 +541,13 @@ fn capability_matches(&self, granted: &Capability, requested: &Capability) -> bo
             (Capability::Execute(None), Capability::Execute(_)) => true,
             (Capability::Network(None), Capability::Network(_)) => true,
             (Capability::Environment(None), Capability::Environment(_)) => true,
+            (Capability::FileRead(Some(dir)), Capability::FileRead(Some(path)))
+            | (Capability::FileWrite(Some(dir)), Capability::FileWrite(Some(path))) => {
+                path == dir || resolve_path(path).starts_with(resolve_path(dir))
+            }
+            (Capability::Network(Some(pattern)), Capability::Network(Some(host))) => {
+                host_matches(pattern, host)
+            }
             _ => granted == requested,
         }
     }
@@ -296,6 +562,30 @@ pub fn request(&mut self, scope: &str, capability: &Capability) -> Result<()> {
 
         self.audit(capability.clone(), AuditAction::Requested, scope, true);
 
+        if self.is_sandboxed(capability) {
+            self.audit(capability.clone(), AuditAction::Denied, scope, false);
+            return Err(SecurityError::Sandboxed(capability.to_string()));
+        }
+
+        // A renounced capability stays given up, whatever was decided before
+        if self.is_renounced(capability) {
+            self.audit(capability.clone(), AuditAction::Denied, scope, false);
+            return Err(SecurityError::CapabilityRevoked(capability.to_string()));
+        }
+
+        // A remembered decision is used without asking again
+        match self.stored_consent(scope, &capability.to_string()) {
+            Some(true) => {
+                self.grant(scope, capability.clone(), "stored");
+                return Ok(());
+            }
+            Some(false) => {
+                self.audit(capability.clone(), AuditAction::Denied, scope, false);
+                return Err(SecurityError::CapabilityNotGranted(capability.to_string()));
+            }
+            None => {}
+        }
+
         // If non-interactive, use default consent
         if !self.interactive {
             if self.default_consent {
@@ -308,34 +598,191 @@ pub fn request(&mut self, scope: &str, capability: &Capability) -> Result<()> {
         }
 
         // Interactive consent prompt
-        println!("🔐 Capability request: {}", capability);
-        println!("   Scope: {}", scope);
-        print!("   Grant this capability? (y/n): ");
-
-        use std::io::{self, Write};
-        io::stdout().flush().ok();
-
-        let mut input = String::new();
-        if io::stdin().read_line(&mut input).is_ok() {
-            let input = input.trim().to_lowercase();
-            if input == "y" || input == "yes" {
-                self.grant(scope, capability.clone(), "user");
-                return Ok(());
+        let reason = self.reason_for(capability);
+        let (granted, duration) = match &mut self.prompt_handler {
+            Some(handler) => {
+                let question = match &reason {
+                    Some(reason) => format!("Grant {} to {} ({})?", capability, scope, reason),
+                    None => format!("Grant {} to {}?", capability, scope),
+                };
+                (handler(&question), ConsentDuration::Session)
+            }
+            None => {
+                println!("🔐 Capability request: {}", capability);
+                println!("   Scope: {}", scope);
+                if let Some(reason) = &reason {
+                    println!("   Reason: {}", reason);
+                }
+                consent::ask("   Grant this capability?", self.prompt_timeout)
             }
+        };
+        self.remember_consent(scope, &capability.to_string(), granted, duration);
+
+        if granted {
+            self.grant(scope, capability.clone(), "user");
+            return Ok(());
         }
 
         self.audit(capability.clone(), AuditAction::Denied, scope, false);
         Err(SecurityError::CapabilityNotGranted(capability.to_string()))
     }
 
+    /// Request a capability for the running function, within what the
+    /// import of the running standard library module declared
+    pub fn request_current(&mut self, capability: &Capability) -> Result<()> {
+        let scope = self.scope.clone();
+        if let Some(module) = &self.module {
+            if let Some(declared) = self.declared.get(module) {
+                if !declared
+                    .capabilities
+                    .iter()
+                    .any(|d| self.capability_matches(d, capability))
+                {
+                    let module = module.clone();
+                    self.audit(capability.clone(), AuditAction::Denied, &scope, false);
+                    return Err(SecurityError::NotDeclared {
+                        module,
+                        capability: capability.to_string(),
+                    });
+                }
+            }
+        }
+        self.request(&scope, capability)
+    }
+
+    /// Make checks in `scope` from now on, returning the previous scope
+    pub fn set_scope(&mut self, scope: String) -> String {
+        std::mem::replace(&mut self.scope, scope)
+    }
+
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    /// Note which standard library module is running, returning the
+    /// previous one
+    pub fn set_module(&mut self, module: Option<String>) -> Option<String> {
+        std::mem::replace(&mut self.module, module)
+    }
+
+    /// Limit the module named by a declaration to the capabilities it
+    /// lists, and show its description when they are asked for. Modules
+    /// without a declaration are not limited.
+    pub fn declare(&mut self, declaration: SuperpowerDeclaration) {
+        self.declared.insert(declaration.name.clone(), declaration);
+    }
+
+    /// Why the running module said it needs `capability`, if it said
+    fn reason_for(&self, capability: &Capability) -> Option<String> {
+        let declaration = self.declared.get(self.module.as_ref()?)?;
+        declaration
+            .capabilities
+            .iter()
+            .any(|d| self.capability_matches(d, capability))
+            .then(|| declaration.description.clone())
+            .flatten()
+    }
+
+    /// Limit how much of `quota` the program may use; usage so far counts
+    /// toward the new limit
+    pub fn set_quota(&mut self, quota: Quota, limit: u64) {
+        self.quotas.entry(quota).or_insert((0, 0)).0 = limit;
+    }
+
+    /// Use `amount` of a quota, failing without using any if that would go
+    /// over its limit. Quotas without a limit are unlimited.
+    pub fn consume(&mut self, quota: Quota, amount: u64) -> Result<()> {
+        let Some((limit, used)) = self.quotas.get_mut(&quota) else {
+            return Ok(());
+        };
+        if used.saturating_add(amount) > *limit {
+            return Err(SecurityError::QuotaExceeded { quota, limit: *limit });
+        }
+        *used += amount;
+        Ok(())
+    }
+
+    /// How much of a quota is left, or `None` if it is unlimited
+    pub fn quota_remaining(&self, quota: Quota) -> Option<u64> {
+        self.quotas.get(&quota).map(|(limit, used)| limit.saturating_sub(*used))
+    }
+
+    /// Record a decision about an `only if okay` block in the audit log
+    pub fn audit_consent(&mut self, consent: &str, granted: bool, reason: Option<&str>) {
+        let action = if granted { AuditAction::Granted } else { AuditAction::Denied };
+        self.audit_log.push(AuditEntry {
+            timestamp: SystemTime::now(),
+            capability: Capability::Custom(consent.to_string()).redacted(),
+            action,
+            context: CONSENT_SCOPE.to_string(),
+            success: granted,
+            reason: reason.map(|r| secrets::redact(r).into_owned()),
+        });
+    }
+
+    /// Grant everything a project policy allows, to every scope
+    pub fn apply_policy(&mut self, policy: &Policy) {
+        for capability in &policy.capabilities {
+            self.grant("*", capability.clone(), "policy");
+        }
+        for consent in &policy.consents {
+            self.grant(CONSENT_SCOPE, Capability::Custom(consent.clone()), "policy");
+        }
+        for &(quota, limit) in &policy.quotas {
+            self.set_quota(quota, limit);
+        }
+    }
+
+    /// Remember consent decisions in `store`, and use the ones it holds
+    pub fn set_consent_store(&mut self, store: ConsentStore) {
+        self.consent_store = Some(store);
+    }
+
+    pub fn consent_store(&self) -> Option<&ConsentStore> {
+        self.consent_store.as_ref()
+    }
+
+    /// Stop using the consent store, handing it back with the decisions
+    /// made this session, e.g. for another run
+    pub fn take_consent_store(&mut self) -> Option<ConsentStore> {
+        self.consent_store.take()
+    }
+
+    /// Remember decisions for the program at `path`, and use the ones
+    /// made for it before. A program without a file, such as one read from
+    /// stdin, has none remembered.
+    pub fn set_program(&mut self, path: Option<&Path>) {
+        self.program = path
+            .and_then(|path| path.canonicalize().ok())
+            .map(|path| path.display().to_string());
+    }
+
+    /// A remembered decision about `consent` in `scope`, if one still applies
+    pub fn stored_consent(&self, scope: &str, consent: &str) -> Option<bool> {
+        self.consent_store.as_ref()?.check(self.program.as_ref()?, scope, consent)
+    }
+
+    /// Remember a decision in the consent store, if there is one. A store
+    /// that cannot be saved only costs the user a prompt next time, so the
+    /// failure is reported rather than stopping the program.
+    pub fn remember_consent(&mut self, scope: &str, consent: &str, granted: bool, duration: ConsentDuration) {
+        if let (Some(store), Some(program)) = (&mut self.consent_store, &self.program) {
+            if let Err(e) = store.store(program, scope, consent, granted, duration) {
+                eprintln!("Could not save consent to {}: {}", store.path().display(), e);
+            }
+        }
+    }
+
     /// Add an audit log entry
     fn audit(&mut self, capability: Capability, action: AuditAction, context: &str, success: bool) {
+        let reason = self.reason_for(&capability).map(|r| secrets::redact(&r).into_owned());
         self.audit_log.push(AuditEntry {
             timestamp: SystemTime::now(),
-            capability,
+            capability: capability.redacted(),
             action,
-            context: context.to_string(),
+            context: secrets::redact(context).into_owned(),
             success,
+            reason,
         });
     }
 
@@ -380,6 +827,43 @@ pub fn set_interactive(&mut self, interactive: bool) {
     pub fn set_default_consent(&mut self, consent: bool) {
         self.default_consent = consent;
     }
+
+    /// Answer requests the way `mode` says
+    pub fn set_consent_mode(&mut self, mode: ConsentMode) {
+        self.interactive = mode == ConsentMode::Ask;
+        self.default_consent = mode == ConsentMode::Allow;
+    }
+
+    /// Ask `handler` whether to grant a capability instead of prompting on
+    /// stdin; its answers last for the rest of the run
+    pub fn set_prompt_handler(&mut self, handler: impl FnMut(&str) -> bool + 'static) {
+        self.prompt_handler = Some(Box::new(handler));
+    }
+
+    /// Wait at most `timeout` for an answer to a prompt, or forever with
+    /// `None`; a prompt that times out is denied
+    pub fn set_prompt_timeout(&mut self, timeout: Option<Duration>) {
+        self.prompt_timeout = timeout;
+    }
+
+    pub fn prompt_timeout(&self) -> Option<Duration> {
+        self.prompt_timeout
+    }
+
+    /// Answer requests as `WOKE_CONSENT` says, with the prompt timeout
+    /// from `WOKE_CONSENT_TIMEOUT`, granting what the policy file named by
+    /// `WOKE_CONSENT_FILE` allows. Returns the mode.
+    pub fn configure_from_env(&mut self) -> std::result::Result<ConsentMode, ConsentError> {
+        let mode = ConsentMode::from_env()?;
+        self.set_consent_mode(mode);
+        self.set_prompt_timeout(consent::prompt_timeout_from_env()?);
+        if let Some(path) = std::env::var_os(consent::CONSENT_FILE_ENV).filter(|p| !p.is_empty()) {
+            let policy = Policy::load(Path::new(&path))
+                .map_err(|e| ConsentError::InvalidEnv(consent::CONSENT_FILE_ENV, e.to_string()))?;
+            self.apply_policy(&policy);
+        }
+        Ok(mode)
+    }
 }
 
 impl Default for CapabilityRegistry {
@@ -388,8 +872,62 @@ fn default() -> Self {
     }
 }
 
-/// A superpower declaration that can be stored in WokeLang code
-#[derive(Debug, Clone)]
+/// The absolute path a capability refers to, with links resolved
+///
+/// Paths that do not exist yet, such as a file about to be written, are
+/// resolved through their closest existing parent.
+fn resolve_path(path: &Path) -> PathBuf {
+    let absolute = match std::env::current_dir() {
+        Ok(cwd) if path.is_relative() => normalize_path(&cwd.join(path)),
+        _ => normalize_path(path),
+    };
+    let mut existing = absolute.as_path();
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(real) = fs::canonicalize(existing) {
+            return missing.iter().rev().fold(real, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_owned());
+                existing = parent;
+            }
+            _ => return absolute,
+        }
+    }
+}
+
+/// Split a trailing `:port` off a host, allowing for `[::1]:80`
+fn split_port(host: &str) -> (&str, Option<&str>) {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if !port.is_empty()
+                && port.bytes().all(|b| b.is_ascii_digit())
+                && (!name.contains(':') || name.ends_with(']')) =>
+        {
+            (name, Some(port))
+        }
+        _ => (host, None),
+    }
+}
+
+/// Does a granted host pattern cover a requested host?
+///
+/// Patterns use `*`, `?` and `[...]` as in `std.io.glob`, so
+/// `*.example.com` covers every subdomain. A pattern without a port
+/// covers every port.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let (pattern_name, pattern_port) = split_port(pattern);
+    let (host_name, host_port) = split_port(host);
+    if pattern_port.is_some() && pattern_port != host_port {
+        return false;
+    }
+    matches_part(&pattern_name.to_ascii_lowercase(), &host_name.to_ascii_lowercase())
+}
+
+/// The capabilities a module is declared to need, and why: what
+/// `use std.io must have read "data" because "..."` declares
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SuperpowerDeclaration {
     pub name: String,
     pub capabilities: Vec<Capability>,
@@ -431,6 +969,188 @@ fn test_wildcard_capability() {
         assert!(registry.has_capability("main", &specific));
     }
 
+    #[test]
+    fn test_scoped_capability() {
+        let mut registry = CapabilityRegistry::new();
+        let cap = Capability::Crypto;
+
+        registry.grant("tools", cap.clone(), "test");
+        registry.grant("main", Capability::Clipboard, "test");
+        assert!(registry.has_capability("tools:main", &cap));
+        assert!(registry.has_capability("tools.sub:helper", &cap));
+        assert!(!registry.has_capability("toolsmith:main", &cap));
+        assert!(!registry.has_capability("other:main", &Capability::Clipboard));
+        assert!(registry.has_capability("main:helper", &Capability::Clipboard));
+
+        // A function's scope is not the parent of a module's
+        registry.grant("app:util", cap.clone(), "test");
+        assert!(registry.has_capability("app:util", &cap));
+        assert!(!registry.has_capability("app.util:helper", &cap));
+        assert_eq!(function_scope("app.util", "util.helper"), "app.util:helper");
+    }
+
+    #[test]
+    fn test_request_current() {
+        let mut registry = CapabilityRegistry::permissive();
+        let home = Capability::Environment(Some("HOME".to_string()));
+        assert_eq!(registry.scope(), HOST_SCOPE);
+
+        let caller = registry.set_scope("app:main".to_string());
+        registry.request_current(&Capability::Crypto).unwrap();
+        assert!(registry.has_capability("app:main", &Capability::Crypto));
+        assert!(!registry.has_capability("app:other", &Capability::Crypto));
+        registry.set_scope(caller);
+
+        // A declared module may only use what its import listed
+        registry.declare(SuperpowerDeclaration {
+            name: "std.env".to_string(),
+            capabilities: vec![Capability::Environment(Some("PATH".to_string()))],
+            description: Some("to find programs".to_string()),
+        });
+        registry.set_module(Some("std.env".to_string()));
+        assert!(registry
+            .request_current(&Capability::Environment(Some("PATH".to_string())))
+            .is_ok());
+        let last = registry.get_audit_log().last().unwrap();
+        assert_eq!(last.reason.as_deref(), Some("to find programs"));
+        assert!(matches!(
+            registry.request_current(&home),
+            Err(SecurityError::NotDeclared { .. })
+        ));
+        registry.set_module(Some("std.io".to_string()));
+        assert!(registry.request_current(&home).is_ok());
+    }
+
+    #[test]
+    fn test_consent_mode() {
+        let network = Capability::Network(None);
+        let mut registry = CapabilityRegistry::new();
+        registry.set_consent_mode(ConsentMode::Allow);
+        assert!(registry.request("main", &network).is_ok());
+
+        let mut registry = CapabilityRegistry::new();
+        registry.grant("*", Capability::Crypto, "policy");
+        registry.set_consent_mode(ConsentMode::Deny);
+        assert!(registry.request("main", &Capability::Crypto).is_ok());
+        assert!(matches!(
+            registry.request("main", &network),
+            Err(SecurityError::CapabilityNotGranted(_))
+        ));
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let root = std::env::temp_dir().join("wokelang_test_sandbox");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("data")).unwrap();
+
+        let mut registry = CapabilityRegistry::permissive();
+        registry.grant("*", Capability::Network(None), "policy");
+        registry.grant("*", Capability::FileRead(None), "policy");
+        registry.sandbox(&root);
+
+        let read = |p: PathBuf| Capability::FileRead(Some(p));
+        assert!(registry.request("main", &read(root.join("data/input.csv"))).is_ok());
+        assert!(registry.request("main", &Capability::Crypto).is_ok());
+        for capability in [
+            read(root.join("data/../../secret")),
+            Capability::FileWrite(None),
+            Capability::Network(Some("example.com".to_string())),
+            Capability::Execute(Some("sh".to_string())),
+            Capability::Environment(Some("HOME".to_string())),
+            Capability::Process,
+        ] {
+            assert!(!registry.has_capability("main", &capability), "{}", capability);
+            assert!(matches!(
+                registry.request("main", &capability),
+                Err(SecurityError::Sandboxed(_))
+            ));
+        }
+        // The network and any-file grants no longer count
+        assert_eq!(registry.effective_capabilities("main"), [&Capability::Crypto]);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_audit_masks_secrets() {
+        let handling = secrets::handling(["tok-abcdef".to_string()]);
+        let mut registry = CapabilityRegistry::permissive();
+        let host = Capability::Network(Some("tok-abcdef.example.com".to_string()));
+        registry.request("main", &host).unwrap();
+        let entry = registry.get_audit_log().last().unwrap();
+        assert_eq!(entry.capability, Capability::Network(Some("***.example.com".to_string())));
+
+        drop(handling);
+        registry.request("main", &host).unwrap();
+        assert_eq!(registry.get_audit_log().last().unwrap().capability, host);
+    }
+
+    #[test]
+    fn test_quota() {
+        let mut registry = CapabilityRegistry::permissive();
+        assert!(registry.consume(Quota::NetworkRequests, 1000).is_ok());
+        assert_eq!(registry.quota_remaining(Quota::NetworkRequests), None);
+
+        registry.set_quota(Quota::NetworkRequests, 2);
+        registry.consume(Quota::NetworkRequests, 2).unwrap();
+        let err = registry.consume(Quota::NetworkRequests, 1).unwrap_err();
+        assert_eq!(err.to_string(), "Quota exceeded: at most 2 network requests per run");
+        assert_eq!(registry.quota_remaining(Quota::NetworkRequests), Some(0));
+
+        // Other quotas are not affected
+        assert!(registry.consume(Quota::Processes, 5).is_ok());
+    }
+
+    #[test]
+    fn test_path_prefix_capability() {
+        let root = std::env::temp_dir().join("wokelang_test_path_prefix");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("project/src")).unwrap();
+        fs::create_dir_all(root.join("project2")).unwrap();
+
+        let mut registry = CapabilityRegistry::new();
+        registry.grant("main", Capability::FileRead(Some(root.join("project"))), "test");
+        let read = |p: PathBuf| Capability::FileRead(Some(p));
+
+        assert!(registry.has_capability("main", &read(root.join("project/src"))));
+        assert!(registry.has_capability("main", &read(root.join("project/new/file.txt"))));
+        assert!(registry.has_capability("main", &read(root.join("project/src/./../README"))));
+        assert!(!registry.has_capability("main", &read(root.join("project2/file.txt"))));
+        assert!(!registry.has_capability("main", &read(root.clone())));
+        // Reading does not cover writing
+        assert!(!registry.has_capability("main", &Capability::FileWrite(Some(root.join("project/a")))));
+
+        // Links are followed, so they cannot lead outside the grant
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("project2"), root.join("project/escape")).unwrap();
+            assert!(!registry.has_capability("main", &read(root.join("project/escape/secret"))));
+            registry.grant("main", read(root.join("project2")), "test");
+            assert!(registry.has_capability("main", &read(root.join("project/escape/secret"))));
+        }
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_host_pattern_capability() {
+        let mut registry = CapabilityRegistry::new();
+        registry.grant("main", Capability::Network(Some("*.example.com".to_string())), "test");
+        registry.grant("main", Capability::Network(Some("localhost:8080".to_string())), "test");
+        let net = |host: &str| Capability::Network(Some(host.to_string()));
+
+        assert!(registry.has_capability("main", &net("api.example.com")));
+        assert!(registry.has_capability("main", &net("a.b.EXAMPLE.com:443")));
+        assert!(!registry.has_capability("main", &net("example.com")));
+        assert!(!registry.has_capability("main", &net("example.com.evil.org")));
+        assert!(registry.has_capability("main", &net("localhost:8080")));
+        assert!(!registry.has_capability("main", &net("localhost:9090")));
+        assert!(!registry.has_capability("main", &net("localhost")));
+
+        assert_eq!(split_port("[::1]:80"), ("[::1]", Some("80")));
+        assert_eq!(split_port("::1"), ("::1", None));
+    }
+
     #[test]
     fn test_capability_expiry() {
         let mut registry = CapabilityRegistry::permissive();
@@ -464,4 +1184,91 @@ fn test_audit_log() {
         assert!(!log.is_empty());
         assert!(matches!(log.last().unwrap().action, AuditAction::Granted));
     }
+
+    #[test]
+    fn test_prompt_handler() {
+        let questions = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let asked = questions.clone();
+        let mut registry = CapabilityRegistry::new();
+        registry.set_prompt_handler(move |question| {
+            asked.borrow_mut().push(question.to_string());
+            question.contains("notify")
+        });
+
+        // Answers last for the session, so each capability is asked once
+        assert!(registry.request("main", &Capability::Notify).is_ok());
+        assert!(registry.request("main", &Capability::Notify).is_ok());
+        assert!(registry.request("main", &Capability::Clipboard).is_err());
+        assert_eq!(*questions.borrow(), ["Grant notify to main?", "Grant clipboard to main?"]);
+    }
+
+    #[test]
+    fn test_stored_consents() {
+        let dir = std::env::temp_dir().join("wokelang_test_registry_consents");
+        fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("main.woke");
+        fs::write(&program, "").unwrap();
+        let program_name = program.canonicalize().unwrap().display().to_string();
+
+        let mut store = ConsentStore::new(dir.join("consents.json"));
+        store.set_auto_save(false);
+        store.store(&program_name, "stdlib", "network:*", true, ConsentDuration::Forever).unwrap();
+        store.store(&program_name, "stdlib", "clipboard", false, ConsentDuration::Week).unwrap();
+
+        // Stored decisions win over the non-interactive default
+        let mut registry = CapabilityRegistry::permissive();
+        registry.set_consent_store(store);
+        registry.set_program(Some(&program));
+        assert!(registry.request("stdlib", &Capability::Network(None)).is_ok());
+        assert!(registry.request("stdlib", &Capability::Clipboard).is_err());
+        assert!(registry.request("stdlib", &Capability::Notify).is_ok());
+
+        registry.remember_consent("stdlib", "notify", false, ConsentDuration::Day);
+        assert_eq!(registry.stored_consent("stdlib", "notify"), Some(false));
+
+        // A program read from stdin has nothing remembered
+        registry.set_program(None);
+        registry.remember_consent("stdlib", "camera", true, ConsentDuration::Forever);
+        assert_eq!(registry.stored_consent("stdlib", "camera"), None);
+        assert_eq!(registry.stored_consent("stdlib", "notify"), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_programs_with_the_same_name_do_not_share_consent() {
+        let dir = std::env::temp_dir().join("wokelang_test_same_name_consents");
+        let (first, second) = (dir.join("p1").join("main.woke"), dir.join("p2").join("main.woke"));
+        for program in [&first, &second] {
+            fs::create_dir_all(program.parent().unwrap()).unwrap();
+            fs::write(program, "").unwrap();
+        }
+        let mut store = ConsentStore::new(dir.join("consents.json"));
+        store.set_auto_save(false);
+
+        let mut registry = CapabilityRegistry::new();
+        registry.set_consent_store(store);
+        registry.set_program(Some(&first));
+        registry.remember_consent("main:main", "network:*", true, ConsentDuration::Forever);
+        assert_eq!(registry.stored_consent("main:main", "network:*"), Some(true));
+
+        registry.set_program(Some(&second));
+        assert_eq!(registry.stored_consent("main:main", "network:*"), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_apply_policy() {
+        let policy = Policy::parse(
+            "[allow]\nexecute = [\"git\"]\ncapabilities = [\"crypto\"]\nconsents = [\"camera\"]\n",
+        )
+        .unwrap();
+        let mut registry = CapabilityRegistry::new();
+        registry.set_interactive(false);
+        registry.apply_policy(&policy);
+
+        assert!(registry.request("stdlib", &Capability::Execute(Some("git".to_string()))).is_ok());
+        assert!(registry.request("stdlib", &Capability::Execute(Some("rm".to_string()))).is_err());
+        assert!(registry.request("stdlib", &Capability::Crypto).is_ok());
+        assert!(registry.has_capability(CONSENT_SCOPE, &Capability::Custom("camera".to_string())));
+    }
 }
@@ -0,0 +1,411 @@
+//! Project Policy Files for WokeLang
+//!
+//! A `woke.policy.toml` next to a program lists the superpowers the
+//! project allows up front, so the program can run unattended without
+//! granting everything with `CapabilityRegistry::permissive()`:
+//!
+//! ```toml
+//! [allow]
+//! read = ["data/input.csv", "config.toml"]
+//! write = ["out/report.txt"]
+//! execute = ["git"]
+//! network = ["api.example.com"]
+//! env = ["HOME"]
+//! capabilities = ["process", "clipboard"]
+//! consents = ["send_analytics"]
+//!
+//! [quota]
+//! network_requests = 10
+//! write_bytes = 5_000_000
+//! processes = 3
+//! ```
+//!
+//! `"*"` in `read`, `write`, `execute`, `network` or `env` allows any
+//! value. Relative `read` and `write` paths are taken from the directory
+//! the policy file is in, wherever the program is run from. `consents`
+//! pre-approves `only if okay "..."` blocks. Anything not listed is still
+//! asked for as usual. `[quota]` limits how much of a superpower one run
+//! may use, however it was allowed.
+//!
+//! The same allowances can be given on the command line with
+//! `--allow-read[=path,...]`, `--allow-write`, `--allow-net`, `--allow-env`
+//! and `--allow-run`; see [`Policy::add_flag`].
+
+use super::{Capability, Quota};
+use crate::stdlib::path::normalize_path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Name of the policy file looked for next to a program
+pub const POLICY_FILE: &str = "woke.policy.toml";
+
+#[derive(Error, Debug)]
+pub enum PolicyError {
+    #[error("Could not read {path}: {message}")]
+    Io { path: String, message: String },
+
+    #[error("Invalid policy: {0}")]
+    Invalid(String),
+
+    #[error("Unknown flag: {0} (use --allow-read, --allow-write, --allow-net, --allow-env or --allow-run)")]
+    UnknownFlag(String),
+}
+
+type Result<T> = std::result::Result<T, PolicyError>;
+
+/// Capabilities and consents a project allows without asking
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Policy {
+    /// Superpowers granted to every scope
+    pub capabilities: Vec<Capability>,
+    /// Consent block permissions granted without a prompt
+    pub consents: Vec<String>,
+    /// Limits on how much of a superpower one run may use
+    pub quotas: Vec<(Quota, u64)>,
+}
+
+impl Policy {
+    /// The policy file that applies to `program`, if there is one
+    pub fn find_for(program: &Path) -> Option<PathBuf> {
+        let dir = program.parent().unwrap_or(Path::new(""));
+        let path = dir.join(POLICY_FILE);
+        path.is_file().then_some(path)
+    }
+
+    /// Read a policy file, taking relative paths from its directory
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| PolicyError::Io {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })?;
+        Ok(Self::parse(&text)?.relative_to(path.parent().unwrap_or(Path::new(""))))
+    }
+
+    /// The policy with its relative `read` and `write` paths taken from
+    /// `dir` rather than from wherever the program runs
+    pub fn relative_to(mut self, dir: &Path) -> Self {
+        let dir = match std::env::current_dir() {
+            Ok(cwd) if dir.is_relative() => cwd.join(dir),
+            _ => dir.to_path_buf(),
+        };
+        for capability in &mut self.capabilities {
+            if let Capability::FileRead(Some(path)) | Capability::FileWrite(Some(path)) = capability {
+                if path.is_relative() {
+                    *path = normalize_path(&dir.join(&*path));
+                }
+            }
+        }
+        self
+    }
+
+    /// Parse the text of a policy file
+    pub fn parse(text: &str) -> Result<Self> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| PolicyError::Invalid(e.message().to_string()))?;
+        Self::from_table(&table)
+    }
+
+    /// Read the `[allow]` and `[quota]` sections of a parsed policy
+    pub(super) fn from_table(table: &toml::Table) -> Result<Self> {
+        let mut policy = Policy::default();
+        for (section, value) in table {
+            let entries = value
+                .as_table()
+                .ok_or_else(|| PolicyError::Invalid(format!("[{}] must be a table", section)))?;
+            match section.as_str() {
+                "allow" => {
+                    for (key, entries) in entries {
+                        for entry in string_list(key, entries)? {
+                            policy.add(key, entry)?;
+                        }
+                    }
+                }
+                "quota" => {
+                    for (key, limit) in entries {
+                        let quota = Quota::from_name(key).ok_or_else(|| {
+                            PolicyError::Invalid(format!(
+                                "unknown quota '{}' (use network_requests, write_bytes or processes)",
+                                key
+                            ))
+                        })?;
+                        let limit = limit
+                            .as_integer()
+                            .and_then(|n| u64::try_from(n).ok())
+                            .ok_or_else(|| {
+                                PolicyError::Invalid(format!("quota '{}' must be a whole number", key))
+                            })?;
+                        policy.quotas.push((quota, limit));
+                    }
+                }
+                _ => {
+                    return Err(PolicyError::Invalid(format!(
+                        "unknown section [{}] (use [allow] or [quota])",
+                        section
+                    )))
+                }
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Add what an `--allow-*` command-line flag allows, returning false
+    /// for anything that is not one
+    ///
+    /// Without a value the flag allows everything of its kind, as `"*"` does
+    /// in a policy file; `--allow-net=a.com,b.com` allows just those.
+    pub fn add_flag(&mut self, flag: &str) -> Result<bool> {
+        let Some(rest) = flag.strip_prefix("--allow-") else {
+            return Ok(false);
+        };
+        let (name, values) = match rest.split_once('=') {
+            Some((name, values)) => (name, Some(values)),
+            None => (rest, None),
+        };
+        let key = match name {
+            "read" => "read",
+            "write" => "write",
+            "net" => "network",
+            "env" => "env",
+            "run" => "execute",
+            _ => return Err(PolicyError::UnknownFlag(flag.to_string())),
+        };
+        match values {
+            None => self.add(key, "*")?,
+            Some(values) => {
+                for value in values.split(',').filter(|v| !v.is_empty()) {
+                    self.add(key, value)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn add(&mut self, key: &str, entry: &str) -> Result<()> {
+        let capability = match key {
+            "capabilities" => named_capability(entry)?,
+            "consents" => {
+                self.consents.push(entry.to_string());
+                return Ok(());
+            }
+            _ => match capability_for(key, Some(entry)) {
+                Some(capability) => capability,
+                None => {
+                    return Err(PolicyError::Invalid(format!(
+                        "unknown key '{}' in [allow] (use read, write, execute, network, env, \
+                         capabilities or consents)",
+                        key
+                    )))
+                }
+            },
+        };
+        self.capabilities.push(capability);
+        Ok(())
+    }
+}
+
+/// The capability a `read`, `write`, `execute`, `network` or `env` entry
+/// stands for; `"*"` or no value at all allows anything of its kind
+pub fn capability_for(kind: &str, value: Option<&str>) -> Option<Capability> {
+    let value = value.filter(|v| *v != "*").map(str::to_string);
+    Some(match kind {
+        "read" => Capability::FileRead(value.map(|p| normalize_path(Path::new(&p)))),
+        "write" => Capability::FileWrite(value.map(|p| normalize_path(Path::new(&p)))),
+        "execute" => Capability::Execute(value),
+        "network" => Capability::Network(value),
+        "env" => Capability::Environment(value),
+        _ => return None,
+    })
+}
+
+/// Parse a capability as imports declare it: a kind with an optional
+/// value (`read "data"`, `network`), or a capability without a value by
+/// name (`crypto`, `custom "camera"`)
+pub fn parse_capability(kind: &str, value: Option<&str>) -> Result<Capability> {
+    if let Some(capability) = capability_for(kind, value) {
+        return Ok(capability);
+    }
+    match (kind, value) {
+        ("custom", Some(name)) => Ok(Capability::Custom(name.to_string())),
+        (_, None) => named_capability(kind).map_err(|_| {
+            PolicyError::Invalid(format!(
+                "unknown capability '{}' (use read, write, execute, network, env, process, \
+                 system_info, crypto, clipboard, notify or custom)",
+                kind
+            ))
+        }),
+        (_, Some(_)) => Err(PolicyError::Invalid(format!(
+            "'{}' does not take a value (only read, write, execute, network, env and custom do)",
+            kind
+        ))),
+    }
+}
+
+fn string_list<'a>(key: &str, value: &'a toml::Value) -> Result<Vec<&'a str>> {
+    let invalid = || PolicyError::Invalid(format!("'{}' must be a list of strings", key));
+    value
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|item| item.as_str().ok_or_else(invalid))
+        .collect()
+}
+
+/// A capability without a value, by the name it is shown with
+pub(super) fn named_capability(name: &str) -> Result<Capability> {
+    Ok(match name {
+        "process" => Capability::Process,
+        "system_info" => Capability::SystemInfo,
+        "crypto" => Capability::Crypto,
+        "clipboard" => Capability::Clipboard,
+        "notify" => Capability::Notify,
+        _ => match name.strip_prefix("custom:") {
+            Some(custom) => Capability::Custom(custom.to_string()),
+            None => {
+                return Err(PolicyError::Invalid(format!(
+                    "unknown capability '{}' (use process, system_info, crypto, clipboard, \
+                     notify or custom:<name>)",
+                    name
+                )))
+            }
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::CapabilityRegistry;
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = Policy::parse(
+            r#"
+            [allow]
+            read = ["./data/input.csv", "*"]
+            execute = ["git"]
+            network = ["api.example.com"]
+            capabilities = ["clipboard", "custom:camera"]
+            consents = ["send_analytics"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            policy.capabilities,
+            [
+                Capability::Clipboard,
+                Capability::Custom("camera".to_string()),
+                Capability::Execute(Some("git".to_string())),
+                Capability::Network(Some("api.example.com".to_string())),
+                Capability::FileRead(Some(PathBuf::from("data/input.csv"))),
+                Capability::FileRead(None),
+            ]
+        );
+        assert_eq!(policy.consents, ["send_analytics"]);
+        assert_eq!(Policy::parse("").unwrap(), Policy::default());
+
+        let policy = Policy::parse("[quota]\nnetwork_requests = 10\nwrite_bytes = 5_000_000\n").unwrap();
+        assert_eq!(
+            policy.quotas,
+            [(Quota::NetworkRequests, 10), (Quota::WriteBytes, 5_000_000)]
+        );
+    }
+
+    #[test]
+    fn test_invalid_policies() {
+        for text in [
+            "[allow]\nreed = [\"a\"]",
+            "[allow]\nread = \"a\"",
+            "[allow]\ncapabilities = [\"superuser\"]",
+            "[deny]\nread = [\"a\"]",
+            "[quota]\nrequests = 10",
+            "[quota]\nprocesses = -1",
+            "[allow\n",
+        ] {
+            assert!(matches!(Policy::parse(text), Err(PolicyError::Invalid(_))), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_allow_flags() {
+        let mut policy = Policy::default();
+        for flag in ["--allow-read", "--allow-net=api.example.com,cdn.example.com", "--allow-run=git"] {
+            assert!(policy.add_flag(flag).unwrap());
+        }
+        assert!(!policy.add_flag("main.woke").unwrap());
+        assert!(matches!(policy.add_flag("--allow-everything"), Err(PolicyError::UnknownFlag(_))));
+
+        assert_eq!(
+            policy.capabilities,
+            [
+                Capability::FileRead(None),
+                Capability::Network(Some("api.example.com".to_string())),
+                Capability::Network(Some("cdn.example.com".to_string())),
+                Capability::Execute(Some("git".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_capability() {
+        assert_eq!(
+            parse_capability("read", Some("./data")).unwrap(),
+            Capability::FileRead(Some(PathBuf::from("data")))
+        );
+        assert_eq!(parse_capability("network", None).unwrap(), Capability::Network(None));
+        assert_eq!(parse_capability("crypto", None).unwrap(), Capability::Crypto);
+        assert_eq!(
+            parse_capability("custom", Some("camera")).unwrap(),
+            Capability::Custom("camera".to_string())
+        );
+        assert!(parse_capability("crypto", Some("sha256")).is_err());
+        assert!(parse_capability("superuser", None).is_err());
+    }
+
+    #[test]
+    fn test_find_for() {
+        let dir = std::env::temp_dir().join("wokelang_test_policy");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("main.woke");
+        assert_eq!(Policy::find_for(&program), None);
+
+        fs::write(dir.join(POLICY_FILE), "[allow]\nenv = [\"HOME\"]\n").unwrap();
+        let found = Policy::find_for(&program).unwrap();
+        assert_eq!(
+            Policy::load(&found).unwrap().capabilities,
+            [Capability::Environment(Some("HOME".to_string()))]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_relative_paths_are_in_the_policy_directory() {
+        // Tests run from the crate root, not the policy's directory
+        let dir = std::env::temp_dir().join("wokelang_test_policy_paths");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join(POLICY_FILE), "[allow]\nread = [\"data\", \"/etc/hosts\"]\nwrite = [\"../out\"]\n").unwrap();
+        assert_ne!(std::env::current_dir().unwrap(), dir);
+
+        let policy = Policy::load(&dir.join(POLICY_FILE)).unwrap();
+        assert_eq!(
+            policy.capabilities,
+            [
+                Capability::FileRead(Some(dir.join("data"))),
+                Capability::FileRead(Some(PathBuf::from("/etc/hosts"))),
+                Capability::FileWrite(Some(normalize_path(&dir.join("../out")))),
+            ]
+        );
+
+        let mut registry = CapabilityRegistry::new();
+        registry.set_interactive(false);
+        registry.apply_policy(&policy);
+        let read = |path: PathBuf| Capability::FileRead(Some(path));
+        assert!(registry.request("main", &read(dir.join("data").join("input.csv"))).is_ok());
+        assert!(registry.request("main", &read(PathBuf::from("data/input.csv"))).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
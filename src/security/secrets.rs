@@ -0,0 +1,91 @@
+//! Secret Redaction for WokeLang
+//!
+//! Text a program should not leak, such as environment variable values
+//! and `remember secret` variables, is marked secret where it enters the
+//! program (see [`Text`](crate::interpreter::Text)), and so is anything a
+//! builtin, a standard library function or `+` makes from it: changing its
+//! case, encoding it or putting it in a longer string leaves it secret.
+//! Log records and verbose traces show secret text as [`MASK`], whatever
+//! its length. What a program prints on purpose is left alone.
+//!
+//! A function given secret text may still put it into an audit entry or an
+//! error message, say as a path it was refused or text it could not parse.
+//! While it runs, the secret text of its arguments is [`handling`], and
+//! [`redact`] masks it wherever it turns up in what is recorded or raised.
+//!
+//! Secrets in progress are kept per thread, like the interpreter that
+//! calls with them.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+/// What secret text is replaced with
+pub const MASK: &str = "***";
+
+thread_local! {
+    /// The secret text of the calls in progress, innermost last
+    static SECRETS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Secret text [`redact`] masks until this is dropped
+#[must_use]
+pub struct Handling {
+    outer: usize,
+}
+
+/// Mask each of `texts` until the guard given back is dropped, as for the
+/// secret arguments of a call while it runs
+pub fn handling(texts: impl IntoIterator<Item = String>) -> Handling {
+    SECRETS.with(|secrets| {
+        let mut secrets = secrets.borrow_mut();
+        let outer = secrets.len();
+        secrets.extend(texts.into_iter().filter(|text| !text.is_empty()));
+        Handling { outer }
+    })
+}
+
+impl Drop for Handling {
+    fn drop(&mut self) {
+        SECRETS.with(|secrets| secrets.borrow_mut().truncate(self.outer));
+    }
+}
+
+/// `text` with the secret text being handled on this thread masked, a
+/// secret containing another masked whole
+pub fn redact(text: &str) -> Cow<'_, str> {
+    SECRETS.with(|secrets| {
+        let secrets = secrets.borrow();
+        let mut longest_first: Vec<&String> = secrets.iter().collect();
+        longest_first.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        let mut text = Cow::Borrowed(text);
+        for secret in longest_first {
+            if text.contains(secret.as_str()) {
+                text = Cow::Owned(text.replace(secret.as_str(), MASK));
+            }
+        }
+        text
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let outer = handling(["sk-test-0123456789".to_string()]);
+        {
+            let _inner = handling(["sk-test-0123456789-extra".to_string(), "on".to_string(), String::new()]);
+            assert_eq!(
+                redact("token sk-test-0123456789-extra and sk-test-0123456789"),
+                "token *** and ***"
+            );
+            assert_eq!(redact("turned on"), "turned ***");
+        }
+        assert_eq!(redact("turned on"), "turned on");
+        assert!(matches!(redact("nothing secret"), Cow::Borrowed(_)));
+
+        drop(outer);
+        assert_eq!(redact("sk-test-0123456789"), "sk-test-0123456789");
+    }
+}
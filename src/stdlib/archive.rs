@@ -0,0 +1,496 @@
+//! WokeLang Standard Library - Archive Module
+//!
+//! Packing and unpacking `.zip` and `.tar.gz` archives, for build and
+//! backup scripts. Packing needs `FileRead` for the source and every
+//! directory below it (allowing the source covers them), and `FileWrite`
+//! for the archive; a declined directory is an error rather than a gap in
+//! the backup. Unpacking needs `FileRead` for the archive and `FileWrite`
+//! for the destination.
+//!
+//! Only regular files and directories are packed or unpacked; symlinks
+//! and special files are skipped. Entries that would land outside the
+//! destination (`../`, absolute paths) stop the extraction, and at most
+//! 1 GB is unpacked from one archive.
+//!
+//! The archive written by packing, and each file unpacked, count towards
+//! the run's `WriteBytes` quota. A pack that would go over it is stopped
+//! and its partial archive removed.
+
+use crate::interpreter::Value;
+use crate::security::{CapabilityRegistry, Quota};
+use super::glob::entry_names;
+use super::io::{require_read, require_write, validate_path};
+use super::path::normalize_path;
+use super::{check_arity, expect_string, quota_exceeded, StdlibError};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+
+/// Maximum number of bytes unpacked from one archive (1 GB)
+const MAX_EXTRACT_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// One file or directory to pack, with its name inside the archive
+struct Entry {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+}
+
+/// An archive being written, counting the bytes that make it longer
+/// towards the `WriteBytes` quota; bytes written again after seeking back,
+/// as zip does to fill in sizes, are not counted twice
+struct QuotaWriter<'a> {
+    file: File,
+    caps: &'a mut CapabilityRegistry,
+    position: u64,
+    end: u64,
+    /// The Oops to give back, once the quota has run out
+    exceeded: &'a RefCell<Option<Value>>,
+}
+
+impl<'a> QuotaWriter<'a> {
+    fn new(file: File, caps: &'a mut CapabilityRegistry, exceeded: &'a RefCell<Option<Value>>) -> Self {
+        Self {
+            file,
+            caps,
+            position: 0,
+            end: 0,
+            exceeded,
+        }
+    }
+}
+
+impl Write for QuotaWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let reach = self.position + buf.len() as u64;
+        if let Some(oops) = quota_exceeded(self.caps, Quota::WriteBytes, reach.saturating_sub(self.end)) {
+            *self.exceeded.borrow_mut() = Some(oops);
+            return Err(io::Error::other("write quota exceeded"));
+        }
+        self.file.write_all(buf)?;
+        self.position = reach;
+        self.end = self.end.max(reach);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for QuotaWriter<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.file.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+/// The number of files a pack wrote, or the quota's Oops, with the
+/// partial archive removed, if it ran out
+fn finish_pack(
+    written: Result<i64, StdlibError>,
+    exceeded: RefCell<Option<Value>>,
+    archive: &Path,
+) -> Result<Value, StdlibError> {
+    match exceeded.into_inner() {
+        Some(oops) => {
+            let _ = fs::remove_file(archive);
+            Ok(oops)
+        }
+        None => written.map(Value::Int),
+    }
+}
+
+fn io_error(action: &str, path: &Path, e: impl std::fmt::Display) -> StdlibError {
+    StdlibError::IoError(format!("Could not {} {}: {}", action, path.display(), e))
+}
+
+/// Collect what to pack below `source`, asking to read each directory.
+/// The archive being written is left out in case it sits inside `source`.
+fn collect_entries(
+    source: &Path,
+    archive: &Path,
+    caps: &mut CapabilityRegistry,
+) -> Result<Vec<Entry>, StdlibError> {
+    let meta = fs::symlink_metadata(source).map_err(|e| io_error("read", source, e))?;
+    // Entries are named from the source's own name, like `tar czf out.tgz build`
+    let root = source
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut entries = Vec::new();
+    if meta.is_file() {
+        entries.push(Entry {
+            path: source.to_path_buf(),
+            name: root,
+            is_dir: false,
+        });
+    } else if meta.is_dir() {
+        collect_dir(source, &root, &normalize_path(archive), &mut entries, caps)?;
+    }
+    Ok(entries)
+}
+
+fn collect_dir(
+    dir: &Path,
+    name: &str,
+    archive: &Path,
+    entries: &mut Vec<Entry>,
+    caps: &mut CapabilityRegistry,
+) -> Result<(), StdlibError> {
+    require_read(&dir.to_string_lossy(), caps)?;
+    if !name.is_empty() {
+        entries.push(Entry {
+            path: dir.to_path_buf(),
+            name: format!("{}/", name),
+            is_dir: true,
+        });
+    }
+    for child in entry_names(dir) {
+        let path = dir.join(&child);
+        let child_name = if name.is_empty() {
+            child
+        } else {
+            format!("{}/{}", name, child)
+        };
+        let Ok(meta) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if meta.is_dir() {
+            collect_dir(&path, &child_name, archive, entries, caps)?;
+        } else if meta.is_file() && normalize_path(&path) != archive {
+            entries.push(Entry {
+                path,
+                name: child_name,
+                is_dir: false,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check and resolve the (source, destination) arguments of a pack or
+/// unpack call
+fn paths(args: &[Value], caps: &mut CapabilityRegistry) -> Result<(PathBuf, PathBuf), StdlibError> {
+    check_arity(args, 2)?;
+    let source = expect_string(&args[0], "source")?;
+    let dest = expect_string(&args[1], "destination")?;
+    let source_path = validate_path(&source)?;
+    let dest_path = validate_path(&dest)?;
+    require_read(&source, caps)?;
+    require_write(&dest, caps)?;
+    Ok((source_path, dest_path))
+}
+
+/// Fail once an archive unpacks to more than [`MAX_EXTRACT_SIZE`]
+fn check_extract_size(total: &mut u64, size: u64) -> Result<(), StdlibError> {
+    *total = total.saturating_add(size);
+    if *total > MAX_EXTRACT_SIZE {
+        Err(StdlibError::IoError(format!(
+            "Archive too large to extract (max {} bytes)",
+            MAX_EXTRACT_SIZE
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Pack a file or directory into a zip archive: zip(source, archive) -> Int
+///
+/// Returns the number of files packed.
+pub fn zip(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    let (source, dest) = paths(args, caps)?;
+    let entries = collect_entries(&source, &dest, caps)?;
+
+    let file = File::create(&dest).map_err(|e| io_error("create", &dest, e))?;
+    let exceeded = RefCell::new(None);
+    let written = write_zip(entries, QuotaWriter::new(file, caps, &exceeded), &dest);
+    finish_pack(written, exceeded, &dest)
+}
+
+fn write_zip(entries: Vec<Entry>, file: QuotaWriter, dest: &Path) -> Result<i64, StdlibError> {
+    let mut writer = zip::ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut files = 0;
+    for entry in entries {
+        let zip_error = |e| io_error("write", dest, e);
+        if entry.is_dir {
+            writer.add_directory(entry.name, options).map_err(zip_error)?;
+            continue;
+        }
+        // Keep permissions, so packed scripts stay executable
+        let options = match fs::metadata(&entry.path) {
+            #[cfg(unix)]
+            Ok(meta) => {
+                use std::os::unix::fs::PermissionsExt;
+                options.unix_permissions(meta.permissions().mode())
+            }
+            _ => options,
+        };
+        let mut input = File::open(&entry.path).map_err(|e| io_error("read", &entry.path, e))?;
+        writer.start_file(entry.name, options).map_err(zip_error)?;
+        io::copy(&mut input, &mut writer).map_err(|e| io_error("write", dest, e))?;
+        files += 1;
+    }
+    writer.finish().map_err(|e| io_error("write", dest, e))?;
+    Ok(files)
+}
+
+/// Unpack a zip archive into a directory: unzip(archive, directory) -> Int
+///
+/// Returns the number of files unpacked.
+pub fn unzip(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    let (source, dest) = paths(args, caps)?;
+    let file = File::open(&source).map_err(|e| io_error("open", &source, e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| io_error("read", &source, e))?;
+
+    let mut files = 0;
+    let mut total = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| io_error("read", &source, e))?;
+        let Some(relative) = entry.enclosed_name() else {
+            return Err(StdlibError::PermissionDenied(format!(
+                "Archive entry outside the destination: {}",
+                entry.name()
+            )));
+        };
+        let out = dest.join(relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&out).map_err(|e| io_error("create", &out, e))?;
+            continue;
+        }
+        if entry.is_symlink() {
+            continue;
+        }
+        check_extract_size(&mut total, entry.size())?;
+        if let Some(oops) = quota_exceeded(caps, Quota::WriteBytes, entry.size()) {
+            return Ok(oops);
+        }
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent).map_err(|e| io_error("create", parent, e))?;
+        }
+        let mut output = File::create(&out).map_err(|e| io_error("create", &out, e))?;
+        io::copy(&mut entry, &mut output).map_err(|e| io_error("unpack", &out, e))?;
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(&out, fs::Permissions::from_mode(mode & 0o777));
+        }
+        files += 1;
+    }
+    Ok(Value::Int(files))
+}
+
+/// Pack a file or directory into a gzipped tar archive:
+/// tarCreate(source, archive) -> Int
+///
+/// Returns the number of files packed.
+pub fn tar_create(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    let (source, dest) = paths(args, caps)?;
+    let entries = collect_entries(&source, &dest, caps)?;
+
+    let file = File::create(&dest).map_err(|e| io_error("create", &dest, e))?;
+    let exceeded = RefCell::new(None);
+    let written = write_tar(entries, QuotaWriter::new(file, caps, &exceeded), &dest);
+    finish_pack(written, exceeded, &dest)
+}
+
+fn write_tar(entries: Vec<Entry>, file: QuotaWriter, dest: &Path) -> Result<i64, StdlibError> {
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    let mut files = 0;
+    for entry in entries {
+        if entry.is_dir {
+            builder.append_dir(&entry.name, &entry.path)
+        } else {
+            files += 1;
+            builder.append_path_with_name(&entry.path, &entry.name)
+        }
+        .map_err(|e| io_error("pack", &entry.path, e))?;
+    }
+    builder
+        .into_inner()
+        .and_then(|gz| gz.finish())
+        .map_err(|e| io_error("write", dest, e))?;
+    Ok(files)
+}
+
+/// Unpack a gzipped tar archive into a directory:
+/// tarExtract(archive, directory) -> Int
+///
+/// Returns the number of files unpacked.
+pub fn tar_extract(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    let (source, dest) = paths(args, caps)?;
+    let file = File::open(&source).map_err(|e| io_error("open", &source, e))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    fs::create_dir_all(&dest).map_err(|e| io_error("create", &dest, e))?;
+
+    let mut files = 0;
+    let mut total = 0;
+    let entries = archive.entries().map_err(|e| io_error("read", &source, e))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| io_error("read", &source, e))?;
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            continue;
+        }
+        if kind.is_file() {
+            check_extract_size(&mut total, entry.size())?;
+            if let Some(oops) = quota_exceeded(caps, Quota::WriteBytes, entry.size()) {
+                return Ok(oops);
+            }
+        }
+        let name = entry.path().map(|p| p.display().to_string()).unwrap_or_default();
+        // `unpack_in` refuses paths that leave the destination
+        let unpacked = entry.unpack_in(&dest).map_err(|e| io_error("unpack", &dest, e))?;
+        if !unpacked {
+            return Err(StdlibError::PermissionDenied(format!(
+                "Archive entry outside the destination: {}",
+                name
+            )));
+        }
+        if kind.is_file() {
+            files += 1;
+        }
+    }
+    Ok(Value::Int(files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+    use std::env;
+
+    /// A scratch directory holding `site/index.html` and `site/css/main.css`
+    fn make_site(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("wokelang_test_archive_{}", name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("site/css")).unwrap();
+        fs::write(root.join("site/index.html"), "<h1>thanks</h1>").unwrap();
+        fs::write(root.join("site/css/main.css"), "h1 { color: teal }").unwrap();
+        root
+    }
+
+    fn round_trip(
+        pack: crate::stdlib::StdlibFn,
+        unpack: crate::stdlib::StdlibFn,
+        name: &str,
+        file: &str,
+    ) {
+        let root = make_site(name);
+        let at = |p: &str| s(&root.join(p).to_string_lossy());
+        let mut caps = CapabilityRegistry::permissive();
+
+        assert_eq!(pack(&[at("site"), at(file)], &mut caps).unwrap(), Value::Int(2));
+        assert_eq!(unpack(&[at(file), at("out")], &mut caps).unwrap(), Value::Int(2));
+        assert_eq!(
+            fs::read_to_string(root.join("out/site/css/main.css")).unwrap(),
+            "h1 { color: teal }"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("out/site/index.html")).unwrap(),
+            "<h1>thanks</h1>"
+        );
+
+        // The archive is not packed into itself
+        let inside = format!("site/{}", file);
+        assert_eq!(pack(&[at("site"), at(&inside)], &mut caps).unwrap(), Value::Int(2));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_zip_round_trip() {
+        round_trip(zip, unzip, "zip", "site.zip");
+    }
+
+    #[test]
+    fn test_tar_round_trip() {
+        round_trip(tar_create, tar_extract, "tar", "site.tar.gz");
+    }
+
+    fn quota(pack: crate::stdlib::StdlibFn, unpack: crate::stdlib::StdlibFn, name: &str, file: &str) {
+        let root = make_site(name);
+        let at = |p: &str| s(&root.join(p).to_string_lossy());
+        let over = |value: Value| matches!(value, Value::Oops(ref error) if error.to_string().contains("Quota exceeded"));
+
+        // The archive's own size is counted, once
+        let mut caps = CapabilityRegistry::permissive();
+        caps.set_quota(Quota::WriteBytes, 1_000_000);
+        assert_eq!(pack(&[at("site"), at(file)], &mut caps).unwrap(), Value::Int(2));
+        let size = fs::metadata(root.join(file)).unwrap().len();
+        assert_eq!(caps.quota_remaining(Quota::WriteBytes), Some(1_000_000 - size));
+
+        // A pack that runs out leaves no partial archive behind
+        let mut caps = CapabilityRegistry::permissive();
+        caps.set_quota(Quota::WriteBytes, size - 1);
+        assert!(over(pack(&[at("site"), at("again")], &mut caps).unwrap()));
+        assert!(!root.join("again").exists());
+
+        // Unpacking counts the files written: 15 and 18 bytes here
+        let mut caps = CapabilityRegistry::permissive();
+        caps.set_quota(Quota::WriteBytes, 20);
+        assert!(over(unpack(&[at(file), at("out")], &mut caps).unwrap()));
+        caps.set_quota(Quota::WriteBytes, 100);
+        assert_eq!(unpack(&[at(file), at("out")], &mut caps).unwrap(), Value::Int(2));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_zip_quota() {
+        quota(zip, unzip, "zip_quota", "site.zip");
+    }
+
+    #[test]
+    fn test_tar_quota() {
+        quota(tar_create, tar_extract, "tar_quota", "site.tar.gz");
+    }
+
+    #[test]
+    fn test_archive_requires_consent() {
+        let root = make_site("consent");
+        let site = root.join("site");
+        let archive = root.join("site.zip");
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+        let args = [s(&site.to_string_lossy()), s(&archive.to_string_lossy())];
+
+        assert!(matches!(zip(&args, &mut caps), Err(StdlibError::PermissionDenied(_))));
+
+        // Reading the source is not enough without writing the archive
+        let read = |p: &Path| crate::security::Capability::FileRead(Some(normalize_path(p)));
+        caps.grant("stdlib", read(&site), "test");
+        assert!(matches!(zip(&args, &mut caps), Err(StdlibError::PermissionDenied(_))));
+
+        // Allowing the source covers the directories inside it
+        let write = crate::security::Capability::FileWrite(Some(normalize_path(&archive)));
+        caps.grant("stdlib", write, "test");
+        assert_eq!(zip(&args, &mut caps).unwrap(), Value::Int(2));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_unzip_rejects_escaping_entries() {
+        let root = make_site("escape");
+        let archive = root.join("evil.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive).unwrap());
+        writer.start_file("../escaped.txt", SimpleFileOptions::default()).unwrap();
+        io::Write::write_all(&mut writer, b"gotcha").unwrap();
+        writer.finish().unwrap();
+
+        let mut caps = CapabilityRegistry::permissive();
+        let args = [s(&archive.to_string_lossy()), s(&root.join("out").to_string_lossy())];
+        assert!(matches!(unzip(&args, &mut caps), Err(StdlibError::PermissionDenied(_))));
+        assert!(!root.join("escaped.txt").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
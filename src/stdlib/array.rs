@@ -4,7 +4,8 @@
 
 use crate::interpreter::Value;
 use crate::security::CapabilityRegistry;
-use super::{check_arity, check_arity_range, expect_int, StdlibError};
+use super::{check_arity, check_arity_range, expect_bool, expect_int, Invoke, StdlibError};
+use std::cmp::Ordering;
 
 /// Get the length of an array
 pub fn length(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
@@ -36,7 +37,7 @@ pub fn first(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, St
     match &args[0] {
         Value::Array(a) => match a.first() {
             Some(v) => Ok(Value::Okay(Box::new(v.clone()))),
-            None => Ok(Value::Oops("array is empty".to_string())),
+            None => Ok(Value::oops("array is empty")),
         },
         other => Err(StdlibError::TypeError {
             expected: "Array".to_string(),
@@ -51,7 +52,7 @@ pub fn last(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, Std
     match &args[0] {
         Value::Array(a) => match a.last() {
             Some(v) => Ok(Value::Okay(Box::new(v.clone()))),
-            None => Ok(Value::Oops("array is empty".to_string())),
+            None => Ok(Value::oops("array is empty")),
         },
         other => Err(StdlibError::TypeError {
             expected: "Array".to_string(),
@@ -65,9 +66,9 @@ pub fn push(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, Std
     check_arity(args, 2)?;
     match &args[0] {
         Value::Array(a) => {
-            let mut new_arr = a.clone();
+            let mut new_arr = a.to_vec();
             new_arr.push(args[1].clone());
-            Ok(Value::Array(new_arr))
+            Ok(Value::Array(new_arr.into()))
         }
         other => Err(StdlibError::TypeError {
             expected: "Array".to_string(),
@@ -82,11 +83,11 @@ pub fn pop(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, Stdl
     match &args[0] {
         Value::Array(a) => {
             if a.is_empty() {
-                Ok(Value::Oops("array is empty".to_string()))
+                Ok(Value::oops("array is empty"))
             } else {
-                let mut new_arr = a.clone();
+                let mut new_arr = a.to_vec();
                 let popped = new_arr.pop().unwrap();
-                Ok(Value::Array(vec![Value::Array(new_arr), popped]))
+                Ok(Value::Array(vec![Value::Array(new_arr.into()), popped].into()))
             }
         }
         other => Err(StdlibError::TypeError {
@@ -120,9 +121,9 @@ pub fn concat(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, S
         }
     };
 
-    let mut result = arr1.clone();
-    result.extend(arr2.clone());
-    Ok(Value::Array(result))
+    let mut result = arr1.to_vec();
+    result.extend(arr2.iter().cloned());
+    Ok(Value::Array(result.into()))
 }
 
 /// Reverse an array
@@ -130,9 +131,9 @@ pub fn reverse(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value,
     check_arity(args, 1)?;
     match &args[0] {
         Value::Array(a) => {
-            let mut new_arr = a.clone();
+            let mut new_arr = a.to_vec();
             new_arr.reverse();
-            Ok(Value::Array(new_arr))
+            Ok(Value::Array(new_arr.into()))
         }
         other => Err(StdlibError::TypeError {
             expected: "Array".to_string(),
@@ -177,10 +178,10 @@ pub fn slice(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, St
     };
 
     if start_idx >= end_idx {
-        return Ok(Value::Array(vec![]));
+        return Ok(Value::Array(vec![].into()));
     }
 
-    Ok(Value::Array(arr[start_idx..end_idx].to_vec()))
+    Ok(Value::Array(arr[start_idx..end_idx].to_vec().into()))
 }
 
 /// Check if array contains a value
@@ -233,8 +234,8 @@ pub fn repeat(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, S
         return Err(StdlibError::RuntimeError("repeat count too large (max 10000)".to_string()));
     }
 
-    let arr: Vec<Value> = std::iter::repeat(args[0].clone()).take(count as usize).collect();
-    Ok(Value::Array(arr))
+    let arr: Vec<Value> = std::iter::repeat_n(args[0].clone(), count as usize).collect();
+    Ok(Value::Array(arr.into()))
 }
 
 /// Create a range array from start to end (exclusive)
@@ -280,7 +281,7 @@ pub fn range(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, St
         result
     };
 
-    Ok(Value::Array(arr))
+    Ok(Value::Array(arr.into()))
 }
 
 /// Flatten nested arrays one level
@@ -298,14 +299,14 @@ pub fn flatten(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value,
     };
 
     let mut result = Vec::new();
-    for item in arr {
+    for item in arr.iter() {
         match item {
-            Value::Array(inner) => result.extend(inner.clone()),
+            Value::Array(inner) => result.extend(inner.iter().cloned()),
             other => result.push(other.clone()),
         }
     }
 
-    Ok(Value::Array(result))
+    Ok(Value::Array(result.into()))
 }
 
 /// Remove duplicates from array (preserves first occurrence)
@@ -323,13 +324,13 @@ pub fn unique(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, S
     };
 
     let mut result = Vec::new();
-    for item in arr {
+    for item in arr.iter() {
         if !result.contains(item) {
             result.push(item.clone());
         }
     }
 
-    Ok(Value::Array(result))
+    Ok(Value::Array(result.into()))
 }
 
 /// Zip two arrays together
@@ -359,10 +360,101 @@ pub fn zip(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, Stdl
     let result: Vec<Value> = arr1
         .iter()
         .zip(arr2.iter())
-        .map(|(a, b)| Value::Array(vec![a.clone(), b.clone()]))
+        .map(|(a, b)| Value::Array(vec![a.clone(), b.clone()].into()))
         .collect();
 
-    Ok(Value::Array(result))
+    Ok(Value::Array(result.into()))
+}
+
+/// Apply a function to every element
+pub fn map(args: &[Value], invoke: &mut Invoke) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let arr = expect_array(&args[0])?;
+    let mapped = arr
+        .iter()
+        .map(|v| invoke(&args[1], vec![v.clone()]))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::Array(mapped.into()))
+}
+
+/// Keep the elements for which a predicate returns true
+pub fn filter(args: &[Value], invoke: &mut Invoke) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let arr = expect_array(&args[0])?;
+    let mut kept = Vec::new();
+    for v in arr {
+        if expect_bool(&invoke(&args[1], vec![v.clone()])?, "predicate result")? {
+            kept.push(v.clone());
+        }
+    }
+    Ok(Value::Array(kept.into()))
+}
+
+/// Fold an array into one value: reduce(array, fn(acc, x), initial)
+pub fn reduce(args: &[Value], invoke: &mut Invoke) -> Result<Value, StdlibError> {
+    check_arity(args, 3)?;
+    let arr = expect_array(&args[0])?;
+    let mut acc = args[2].clone();
+    for v in arr {
+        acc = invoke(&args[1], vec![acc, v.clone()])?;
+    }
+    Ok(acc)
+}
+
+/// Sort an array of numbers or strings, or with a comparator returning
+/// a negative, zero or positive Int
+pub fn sort(args: &[Value], invoke: &mut Invoke) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let mut sorted = expect_array(&args[0])?.clone();
+
+    // sort_by cannot fail, so remember the first error and report it after
+    let mut error = None;
+    sorted.sort_by(|a, b| {
+        if error.is_some() {
+            return Ordering::Equal;
+        }
+        let ordering = match args.get(1) {
+            Some(compare) => invoke(compare, vec![a.clone(), b.clone()])
+                .and_then(|n| expect_int(&n, "comparator result"))
+                .map(|n| n.cmp(&0)),
+            None => compare_values(a, b),
+        };
+        ordering.unwrap_or_else(|e| {
+            error = Some(e);
+            Ordering::Equal
+        })
+    });
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(Value::Array(sorted.into())),
+    }
+}
+
+/// Natural ordering used by `sort` without a comparator
+fn compare_values(a: &Value, b: &Value) -> Result<Ordering, StdlibError> {
+    let ordering = match (a, b) {
+        (Value::Int(x), Value::Int(y)) => Some(x.cmp(y)),
+        (Value::Float(x), Value::Float(y)) => x.partial_cmp(y),
+        (Value::Int(x), Value::Float(y)) => (*x as f64).partial_cmp(y),
+        (Value::Float(x), Value::Int(y)) => x.partial_cmp(&(*y as f64)),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    };
+    ordering.ok_or_else(|| StdlibError::TypeError {
+        expected: "comparable values".to_string(),
+        got: format!("{:?} and {:?}", a, b),
+    })
+}
+
+pub(super) fn expect_array(value: &Value) -> Result<&Vec<Value>, StdlibError> {
+    match value {
+        Value::Array(a) => Ok(a),
+        other => Err(StdlibError::TypeError {
+            expected: "Array".to_string(),
+            got: format!("{:?}", other),
+        }),
+    }
 }
 
 #[cfg(test)]
@@ -376,16 +468,16 @@ fn test_caps() -> CapabilityRegistry {
     #[test]
     fn test_length() {
         let mut caps = test_caps();
-        let arr = Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)]);
+        let arr = Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)].into());
         assert_eq!(length(&[arr], &mut caps).unwrap(), Value::Int(3));
     }
 
     #[test]
     fn test_first_last() {
         let mut caps = test_caps();
-        let arr = Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)]);
+        let arr = Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)].into());
 
-        if let Value::Okay(v) = first(&[arr.clone()], &mut caps).unwrap() {
+        if let Value::Okay(v) = first(std::slice::from_ref(&arr), &mut caps).unwrap() {
             assert_eq!(*v, Value::Int(1));
         }
 
@@ -397,20 +489,20 @@ fn test_first_last() {
     #[test]
     fn test_push_pop() {
         let mut caps = test_caps();
-        let arr = Value::Array(vec![Value::Int(1), Value::Int(2)]);
+        let arr = Value::Array(vec![Value::Int(1), Value::Int(2)].into());
 
         let pushed = push(&[arr, Value::Int(3)], &mut caps).unwrap();
         assert_eq!(
             pushed,
-            Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)])
+            Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)].into())
         );
     }
 
     #[test]
     fn test_concat() {
         let mut caps = test_caps();
-        let arr1 = Value::Array(vec![Value::Int(1), Value::Int(2)]);
-        let arr2 = Value::Array(vec![Value::Int(3), Value::Int(4)]);
+        let arr1 = Value::Array(vec![Value::Int(1), Value::Int(2)].into());
+        let arr2 = Value::Array(vec![Value::Int(3), Value::Int(4)].into());
 
         assert_eq!(
             concat(&[arr1, arr2], &mut caps).unwrap(),
@@ -419,7 +511,7 @@ fn test_concat() {
                 Value::Int(2),
                 Value::Int(3),
                 Value::Int(4)
-            ])
+            ].into())
         );
     }
 
@@ -431,11 +523,11 @@ fn test_slice() {
             Value::Int(2),
             Value::Int(3),
             Value::Int(4),
-        ]);
+        ].into());
 
         assert_eq!(
             slice(&[arr, Value::Int(1), Value::Int(3)], &mut caps).unwrap(),
-            Value::Array(vec![Value::Int(2), Value::Int(3)])
+            Value::Array(vec![Value::Int(2), Value::Int(3)].into())
         );
     }
 
@@ -451,12 +543,12 @@ fn test_range() {
                 Value::Int(2),
                 Value::Int(3),
                 Value::Int(4)
-            ])
+            ].into())
         );
 
         assert_eq!(
             range(&[Value::Int(2), Value::Int(5)], &mut caps).unwrap(),
-            Value::Array(vec![Value::Int(2), Value::Int(3), Value::Int(4)])
+            Value::Array(vec![Value::Int(2), Value::Int(3), Value::Int(4)].into())
         );
     }
 
@@ -469,29 +561,84 @@ fn test_unique() {
             Value::Int(1),
             Value::Int(3),
             Value::Int(2),
-        ]);
+        ].into());
 
         assert_eq!(
             unique(&[arr], &mut caps).unwrap(),
-            Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)])
+            Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)].into())
         );
     }
 
     #[test]
     fn test_zip() {
         let mut caps = test_caps();
-        let arr1 = Value::Array(vec![Value::Int(1), Value::Int(2)]);
+        let arr1 = Value::Array(vec![Value::Int(1), Value::Int(2)].into());
         let arr2 = Value::Array(vec![
-            Value::String("a".to_string()),
-            Value::String("b".to_string()),
-        ]);
+            Value::String("a".to_string().into()),
+            Value::String("b".to_string().into()),
+        ].into());
 
         assert_eq!(
             zip(&[arr1, arr2], &mut caps).unwrap(),
             Value::Array(vec![
-                Value::Array(vec![Value::Int(1), Value::String("a".to_string())]),
-                Value::Array(vec![Value::Int(2), Value::String("b".to_string())]),
-            ])
+                Value::Array(vec![Value::Int(1), Value::String("a".to_string().into())].into()),
+                Value::Array(vec![Value::Int(2), Value::String("b".to_string().into())].into()),
+            ].into())
+        );
+    }
+
+    /// Calls `double`, `isEven`, `add` or `desc` by name
+    fn test_invoke(f: &Value, args: Vec<Value>) -> Result<Value, StdlibError> {
+        let int = |i: usize| expect_int(&args[i], "arg");
+        match f {
+            Value::String(name) if name.as_str() == "double" => Ok(Value::Int(int(0)? * 2)),
+            Value::String(name) if name.as_str() == "isEven" => Ok(Value::Bool(int(0)? % 2 == 0)),
+            Value::String(name) if name.as_str() == "add" => Ok(Value::Int(int(0)? + int(1)?)),
+            Value::String(name) if name.as_str() == "desc" => Ok(Value::Int(int(1)? - int(0)?)),
+            other => Err(StdlibError::RuntimeError(format!("not callable: {}", other))),
+        }
+    }
+
+    fn ints(values: &[i64]) -> Value {
+        Value::Array(values.iter().map(|&n| Value::Int(n)).collect::<Vec<_>>().into())
+    }
+
+    fn name(s: &str) -> Value {
+        Value::String(s.to_string().into())
+    }
+
+    #[test]
+    fn test_map_filter_reduce() {
+        let arr = ints(&[1, 2, 3, 4]);
+        assert_eq!(
+            map(&[arr.clone(), name("double")], &mut test_invoke).unwrap(),
+            ints(&[2, 4, 6, 8])
+        );
+        assert_eq!(
+            filter(&[arr.clone(), name("isEven")], &mut test_invoke).unwrap(),
+            ints(&[2, 4])
+        );
+        assert_eq!(
+            reduce(&[arr.clone(), name("add"), Value::Int(10)], &mut test_invoke).unwrap(),
+            Value::Int(20)
+        );
+        // A predicate must return a Bool
+        assert!(filter(&[arr, name("double")], &mut test_invoke).is_err());
+    }
+
+    #[test]
+    fn test_sort() {
+        let arr = ints(&[3, 1, 2]);
+        assert_eq!(sort(std::slice::from_ref(&arr), &mut test_invoke).unwrap(), ints(&[1, 2, 3]));
+        assert_eq!(sort(&[arr, name("desc")], &mut test_invoke).unwrap(), ints(&[3, 2, 1]));
+
+        let words = Value::Array(vec![name("pear"), name("apple")].into());
+        assert_eq!(
+            sort(&[words], &mut test_invoke).unwrap(),
+            Value::Array(vec![name("apple"), name("pear")].into())
         );
+        let mixed = Value::Array(vec![Value::Int(1), name("a")].into());
+        assert!(sort(&[mixed], &mut test_invoke).is_err());
+        assert!(sort(&[ints(&[2, 1]), name("missing")], &mut test_invoke).is_err());
     }
 }
@@ -0,0 +1,160 @@
+//! WokeLang Standard Library - Bytes Module
+//!
+//! Byte arrays for binary data: conversions to and from strings and
+//! arrays of integers, plus slicing and concatenation. Index bytes with
+//! `b[i]` and join them with `+` as for strings.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, check_arity_range, expect_bytes, expect_int, expect_string, StdlibError};
+
+/// Encode a string as UTF-8 bytes
+pub fn from_string(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let s = expect_string(&args[0], "string")?;
+    Ok(Value::Bytes(s.into_bytes()))
+}
+
+/// Decode UTF-8 bytes, returning Okay(text) or Oops if they are not UTF-8
+pub fn to_string(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let bytes = expect_bytes(&args[0], "bytes")?;
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => Value::Okay(Box::new(Value::String(text.into()))),
+        Err(e) => Value::oops(format!(
+            "not valid UTF-8 at byte {}",
+            e.utf8_error().valid_up_to()
+        )),
+    })
+}
+
+/// Build bytes from an array of integers in 0..=255
+pub fn from_array(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let items = match &args[0] {
+        Value::Array(items) => items,
+        other => {
+            return Err(StdlibError::TypeError {
+                expected: "Array".to_string(),
+                got: format!("{:?}", other),
+            })
+        }
+    };
+
+    let mut bytes = Vec::with_capacity(items.len());
+    for item in items.iter() {
+        let n = expect_int(item, "byte")?;
+        let byte = u8::try_from(n).map_err(|_| {
+            StdlibError::RuntimeError(format!("Byte value out of range 0..255: {}", n))
+        })?;
+        bytes.push(byte);
+    }
+    Ok(Value::Bytes(bytes))
+}
+
+/// Convert bytes to an array of integers
+pub fn to_array(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let bytes = expect_bytes(&args[0], "bytes")?;
+    Ok(Value::Array(
+        bytes.into_iter().map(|b| Value::Int(b as i64)).collect::<Vec<_>>().into(),
+    ))
+}
+
+/// Get the number of bytes
+pub fn length(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let bytes = expect_bytes(&args[0], "bytes")?;
+    Ok(Value::Int(bytes.len() as i64))
+}
+
+/// Get bytes by start and optional end index
+pub fn slice(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 2, 3)?;
+    let bytes = expect_bytes(&args[0], "bytes")?;
+    let len = bytes.len() as i64;
+    let start = expect_int(&args[1], "start")?;
+
+    // Handle negative indices
+    let start_idx = if start < 0 {
+        ((len + start).max(0)) as usize
+    } else {
+        start.min(len) as usize
+    };
+
+    let end_idx = if args.len() > 2 {
+        let end = expect_int(&args[2], "end")?;
+        if end < 0 {
+            ((len + end).max(0)) as usize
+        } else {
+            end.min(len) as usize
+        }
+    } else {
+        len as usize
+    };
+
+    if start_idx >= end_idx {
+        return Ok(Value::Bytes(Vec::new()));
+    }
+
+    Ok(Value::Bytes(bytes[start_idx..end_idx].to_vec()))
+}
+
+/// Concatenate two byte arrays
+pub fn concat(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let mut bytes = expect_bytes(&args[0], "first")?;
+    bytes.extend(expect_bytes(&args[1], "second")?);
+    Ok(Value::Bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn b(bytes: &[u8]) -> Value {
+        Value::Bytes(bytes.to_vec())
+    }
+
+    #[test]
+    fn test_string_conversions() {
+        let mut caps = test_caps();
+        let bytes = from_string(&[Value::String("hé".to_string().into())], &mut caps).unwrap();
+        assert_eq!(bytes, b(&[b'h', 0xc3, 0xa9]));
+        assert_eq!(
+            to_string(&[bytes], &mut caps).unwrap(),
+            Value::Okay(Box::new(Value::String("hé".to_string().into())))
+        );
+        assert!(to_string(&[b(&[b'h', 0xff])], &mut caps).unwrap().is_oops());
+        assert!(to_string(&[Value::String("h".to_string().into())], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_array_conversions() {
+        let mut caps = test_caps();
+        let ints = Value::Array(vec![Value::Int(0), Value::Int(127), Value::Int(255)].into());
+        let bytes = from_array(std::slice::from_ref(&ints), &mut caps).unwrap();
+        assert_eq!(bytes, b(&[0, 127, 255]));
+        assert_eq!(to_array(&[bytes], &mut caps).unwrap(), ints);
+        assert!(from_array(&[Value::Array(vec![Value::Int(256)].into())], &mut caps).is_err());
+        assert!(from_array(&[Value::Array(vec![Value::Int(-1)].into())], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_slice_and_concat() {
+        let mut caps = test_caps();
+        let bytes = b(b"wokelang");
+        assert_eq!(length(std::slice::from_ref(&bytes), &mut caps).unwrap(), Value::Int(8));
+        assert_eq!(
+            slice(&[bytes.clone(), Value::Int(0), Value::Int(4)], &mut caps).unwrap(),
+            b(b"woke")
+        );
+        assert_eq!(slice(&[bytes.clone(), Value::Int(-4)], &mut caps).unwrap(), b(b"lang"));
+        assert_eq!(slice(&[bytes, Value::Int(6), Value::Int(2)], &mut caps).unwrap(), b(b""));
+        assert_eq!(concat(&[b(b"wo"), b(b"ke")], &mut caps).unwrap(), b(b"woke"));
+    }
+}
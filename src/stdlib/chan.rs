@@ -5,7 +5,7 @@
 
 use crate::interpreter::{ChannelHandle, Value};
 use crate::security::CapabilityRegistry;
-use super::{check_arity, check_arity_range, expect_int, StdlibError};
+use super::{check_arity, check_arity_range, duration, expect_int, StdlibError};
 
 /// Maximum channel buffer size
 const MAX_BUFFER_SIZE: usize = 10000;
@@ -60,7 +60,7 @@ pub fn send(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, Std
 
     match channel.send(args[1].clone()) {
         Ok(()) => Ok(Value::Bool(true)),
-        Err(e) => Ok(Value::Oops(e)),
+        Err(e) => Ok(Value::oops(e)),
     }
 }
 
@@ -81,7 +81,7 @@ pub fn recv(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, Std
 
     match channel.recv() {
         Ok(value) => Ok(Value::Okay(Box::new(value))),
-        Err(e) => Ok(Value::Oops(e)),
+        Err(e) => Ok(Value::oops(e)),
     }
 }
 
@@ -102,13 +102,14 @@ pub fn try_recv(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value,
 
     match channel.try_recv() {
         Ok(Some(value)) => Ok(Value::Okay(Box::new(value))),
-        Ok(None) => Ok(Value::Oops("channel empty".to_string())),
-        Err(e) => Ok(Value::Oops(e)),
+        Ok(None) => Ok(Value::oops("channel empty")),
+        Err(e) => Ok(Value::oops(e)),
     }
 }
 
 /// Receive with timeout
-/// recv_timeout(channel, timeout_ms) -> Result
+/// recv_timeout(channel, timeout) -> Result, with the timeout a Duration or
+/// Int milliseconds
 pub fn recv_timeout(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 2)?;
 
@@ -122,7 +123,7 @@ pub fn recv_timeout(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Va
         }
     };
 
-    let timeout_ms = expect_int(&args[1], "timeout_ms")?;
+    let timeout_ms = duration::expect_millis(&args[1], "timeout")?;
     if timeout_ms < 0 {
         return Err(StdlibError::RuntimeError(
             "timeout cannot be negative".to_string(),
@@ -131,8 +132,8 @@ pub fn recv_timeout(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Va
 
     match channel.recv_timeout(timeout_ms as u64) {
         Ok(Some(value)) => Ok(Value::Okay(Box::new(value))),
-        Ok(None) => Ok(Value::Oops("timeout".to_string())),
-        Err(e) => Ok(Value::Oops(e)),
+        Ok(None) => Ok(Value::oops("timeout")),
+        Err(e) => Ok(Value::oops(e)),
     }
 }
 
@@ -237,6 +238,19 @@ fn test_try_recv_empty() {
         assert!(matches!(result, Value::Oops(_)));
     }
 
+    #[test]
+    fn test_recv_timeout_takes_duration() {
+        let mut caps = test_caps();
+
+        let channel = make_chan(&[], &mut caps).unwrap();
+        let timeout = Value::Duration(chrono::TimeDelta::milliseconds(10));
+        let result = recv_timeout(&[channel.clone(), timeout], &mut caps).unwrap();
+        assert_eq!(result, Value::oops("timeout"));
+
+        let negative = Value::Duration(chrono::TimeDelta::milliseconds(-10));
+        assert!(recv_timeout(&[channel, negative], &mut caps).is_err());
+    }
+
     #[test]
     fn test_close_channel() {
         let mut caps = test_caps();
@@ -244,7 +258,7 @@ fn test_close_channel() {
         let channel = make_chan(&[], &mut caps).unwrap();
 
         // Close
-        let result = close(&[channel.clone()], &mut caps).unwrap();
+        let result = close(std::slice::from_ref(&channel), &mut caps).unwrap();
         assert_eq!(result, Value::Bool(true));
 
         // Check closed
@@ -0,0 +1,205 @@
+//! WokeLang Standard Library - Config Module
+//!
+//! Parsing of TOML and YAML configuration text into ordinary WokeLang
+//! values: tables and mappings become maps, sequences become arrays.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, expect_string, StdlibError};
+use std::collections::HashMap;
+
+/// Maximum configuration input size (1 MB)
+const MAX_CONFIG_SIZE: usize = 1024 * 1024;
+
+/// Read the text argument, enforcing the size limit
+fn config_text(args: &[Value], format: &str) -> Result<String, StdlibError> {
+    check_arity(args, 1)?;
+    let text = expect_string(&args[0], format)?;
+    if text.len() > MAX_CONFIG_SIZE {
+        return Err(StdlibError::ParseError(format!(
+            "{} input too large: {} bytes (max {} bytes)",
+            format,
+            text.len(),
+            MAX_CONFIG_SIZE
+        )));
+    }
+    Ok(text)
+}
+
+/// Convert a TOML value into a WokeLang value
+///
+/// Dates and times have no WokeLang equivalent and become strings in their
+/// TOML form, e.g. `"1979-05-27T07:32:00Z"`.
+pub fn from_toml(toml: toml::Value) -> Value {
+    match toml {
+        toml::Value::String(s) => Value::String(s.into()),
+        toml::Value::Integer(n) => Value::Int(n),
+        toml::Value::Float(n) => Value::Float(n),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string().into()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(from_toml).collect::<Vec<_>>().into()),
+        toml::Value::Table(table) => Value::Record(
+            table
+                .into_iter()
+                .map(|(k, v)| (k, from_toml(v)))
+                .collect::<HashMap<_, _>>(),
+        ),
+    }
+}
+
+/// Convert a YAML value into a WokeLang value
+///
+/// Scalar mapping keys are converted to strings; tags are dropped.
+pub fn from_yaml(yaml: serde_yaml::Value) -> Result<Value, StdlibError> {
+    use serde_yaml::Value as Yaml;
+    Ok(match yaml {
+        Yaml::Null => Value::Unit,
+        Yaml::Bool(b) => Value::Bool(b),
+        Yaml::Number(n) => match n.as_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Yaml::String(s) => Value::String(s.into()),
+        Yaml::Sequence(items) => {
+            Value::Array(items.into_iter().map(from_yaml).collect::<Result<Vec<_>, _>>()?.into())
+        }
+        Yaml::Mapping(mapping) => {
+            let mut map = HashMap::new();
+            for (k, v) in mapping {
+                let key = match k {
+                    Yaml::String(s) => s,
+                    Yaml::Bool(b) => b.to_string(),
+                    Yaml::Number(n) => n.to_string(),
+                    Yaml::Null => "null".to_string(),
+                    other => {
+                        return Err(StdlibError::ParseError(format!(
+                            "Invalid YAML: unsupported mapping key {:?}",
+                            other
+                        )))
+                    }
+                };
+                map.insert(key, from_yaml(v)?);
+            }
+            Value::Record(map)
+        }
+        Yaml::Tagged(tagged) => from_yaml(tagged.value)?,
+    })
+}
+
+/// Parse a TOML document into a map
+pub fn parse_toml(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    let text = config_text(args, "toml")?;
+    let table: toml::Table = text
+        .parse()
+        .map_err(|e: toml::de::Error| StdlibError::ParseError(format!("Invalid TOML: {}", e.message())))?;
+    Ok(from_toml(toml::Value::Table(table)))
+}
+
+/// Parse a YAML document into a WokeLang value
+pub fn parse_yaml(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    let text = config_text(args, "yaml")?;
+    let yaml: serde_yaml::Value = serde_yaml::from_str(&text)
+        .map_err(|e| StdlibError::ParseError(format!("Invalid YAML: {}", e)))?;
+    from_yaml(yaml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn record(value: &Value) -> &HashMap<String, Value> {
+        match value {
+            Value::Record(map) => map,
+            other => panic!("expected a map, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let mut caps = test_caps();
+        let text = r#"
+            title = "WokeLang"
+            released = 2025-01-01
+
+            [server]
+            port = 8080
+            ratio = 0.5
+            hosts = ["a", "b"]
+            tls = false
+        "#;
+        let config = parse_toml(&[s(text)], &mut caps).unwrap();
+        let config = record(&config);
+        assert_eq!(config.get("title"), Some(&s("WokeLang")));
+        assert_eq!(config.get("released"), Some(&s("2025-01-01")));
+
+        let server = record(&config["server"]);
+        assert_eq!(server.get("port"), Some(&Value::Int(8080)));
+        assert_eq!(server.get("ratio"), Some(&Value::Float(0.5)));
+        assert_eq!(server.get("hosts"), Some(&Value::Array(vec![s("a"), s("b")].into())));
+        assert_eq!(server.get("tls"), Some(&Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_parse_yaml() {
+        let mut caps = test_caps();
+        let text = "
+name: woke
+version: 1.5
+debug: ~
+ports:
+  - 80
+  - 443
+limits:
+  200: ok
+  true: yes
+nested: !custom
+  inner: [x]
+";
+        let config = parse_yaml(&[s(text)], &mut caps).unwrap();
+        let config = record(&config);
+        assert_eq!(config.get("name"), Some(&s("woke")));
+        assert_eq!(config.get("version"), Some(&Value::Float(1.5)));
+        assert_eq!(config.get("debug"), Some(&Value::Unit));
+        assert_eq!(
+            config.get("ports"),
+            Some(&Value::Array(vec![Value::Int(80), Value::Int(443)].into()))
+        );
+
+        let limits = record(&config["limits"]);
+        assert_eq!(limits.get("200"), Some(&s("ok")));
+        assert_eq!(limits.get("true"), Some(&s("yes")));
+        assert_eq!(
+            record(&config["nested"]).get("inner"),
+            Some(&Value::Array(vec![s("x")].into()))
+        );
+
+        // Documents need not be mappings
+        assert_eq!(
+            parse_yaml(&[s("- 1\n- two\n")], &mut caps).unwrap(),
+            Value::Array(vec![Value::Int(1), s("two")].into())
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut caps = test_caps();
+        assert!(matches!(
+            parse_toml(&[s("key = ")], &mut caps),
+            Err(StdlibError::ParseError(msg)) if msg.starts_with("Invalid TOML")
+        ));
+        assert!(matches!(
+            parse_yaml(&[s("a: [1, 2")], &mut caps),
+            Err(StdlibError::ParseError(msg)) if msg.starts_with("Invalid YAML")
+        ));
+        assert!(matches!(
+            parse_yaml(&[s("? [1, 2]\n: x\n")], &mut caps),
+            Err(StdlibError::ParseError(_))
+        ));
+        assert!(parse_toml(&[Value::Int(1)], &mut caps).is_err());
+    }
+}
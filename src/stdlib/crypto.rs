@@ -0,0 +1,188 @@
+//! WokeLang Standard Library - Crypto Module
+//!
+//! Hashing, message authentication and secure random data, for verifying
+//! downloads and signing webhooks. Data may be a string (hashed as UTF-8)
+//! or bytes. Digests and random bytes are returned as lowercase hex strings.
+//! Every function needs the `Crypto` capability.
+
+use crate::interpreter::Value;
+use crate::security::{Capability, CapabilityRegistry};
+use super::{check_arity, check_arity_range, expect_data, expect_int, expect_string, StdlibError};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+use subtle::ConstantTimeEq;
+
+/// Largest number of random bytes returned by one call
+const MAX_RANDOM_BYTES: i64 = 64 * 1024;
+
+/// Helper to require crypto capability
+fn require_crypto(caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    if caps.request_current(&Capability::Crypto).is_err() {
+        Err(StdlibError::PermissionDenied(
+            "Crypto access denied".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> Value {
+    Value::String(bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>().into())
+}
+
+/// SHA-256 digest of a string or bytes, as hex
+pub fn sha256(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let data = expect_data(&args[0], "data")?;
+    require_crypto(caps)?;
+    Ok(to_hex(&Sha256::digest(&data)))
+}
+
+/// SHA-512 digest of a string or bytes, as hex
+pub fn sha512(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let data = expect_data(&args[0], "data")?;
+    require_crypto(caps)?;
+    Ok(to_hex(&Sha512::digest(&data)))
+}
+
+/// HMAC of a message: hmac(key, message[, "sha256" | "sha512"]), as hex
+pub fn hmac(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 2, 3)?;
+    let key = expect_data(&args[0], "key")?;
+    let message = expect_data(&args[1], "message")?;
+    let algorithm = match args.get(2) {
+        Some(algorithm) => expect_string(algorithm, "algorithm")?,
+        None => "sha256".to_string(),
+    };
+    require_crypto(caps)?;
+
+    // HMAC accepts keys of any length, so new_from_slice cannot fail
+    let tag = match algorithm.as_str() {
+        "sha256" => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("any key length");
+            mac.update(&message);
+            mac.finalize().into_bytes().to_vec()
+        }
+        "sha512" => {
+            let mut mac = Hmac::<Sha512>::new_from_slice(&key).expect("any key length");
+            mac.update(&message);
+            mac.finalize().into_bytes().to_vec()
+        }
+        other => {
+            return Err(StdlibError::RuntimeError(format!(
+                "Unsupported HMAC algorithm: {} (use sha256 or sha512)",
+                other
+            )))
+        }
+    };
+    Ok(to_hex(&tag))
+}
+
+/// Cryptographically secure random bytes from the OS, as hex
+pub fn random_bytes(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let count = expect_int(&args[0], "count")?;
+    if !(0..=MAX_RANDOM_BYTES).contains(&count) {
+        return Err(StdlibError::RuntimeError(format!(
+            "randomBytes count must be between 0 and {}, got {}",
+            MAX_RANDOM_BYTES, count
+        )));
+    }
+    require_crypto(caps)?;
+
+    let mut bytes = vec![0u8; count as usize];
+    getrandom::getrandom(&mut bytes).map_err(|e| StdlibError::RuntimeError(e.to_string()))?;
+    Ok(to_hex(&bytes))
+}
+
+/// Compare two strings in time independent of where they differ
+///
+/// Use this to check signatures and tokens. Strings of different lengths
+/// compare unequal straight away; only the length is revealed.
+pub fn constant_time_equals(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let a = expect_string(&args[0], "a")?;
+    let b = expect_string(&args[1], "b")?;
+    require_crypto(caps)?;
+    Ok(Value::Bool(a.as_bytes().ct_eq(b.as_bytes()).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    #[test]
+    fn test_digests() {
+        let mut caps = test_caps();
+        assert_eq!(
+            sha256(&[s("abc")], &mut caps).unwrap(),
+            s("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert_eq!(
+            sha256(&[Value::Bytes(b"abc".to_vec())], &mut caps).unwrap(),
+            sha256(&[s("abc")], &mut caps).unwrap()
+        );
+        assert_eq!(
+            sha512(&[s("")], &mut caps).unwrap(),
+            s("cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce\
+               47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e")
+        );
+    }
+
+    #[test]
+    fn test_hmac() {
+        let mut caps = test_caps();
+        // RFC 4231 test case 2
+        let (key, message) = (s("Jefe"), s("what do ya want for nothing?"));
+        assert_eq!(
+            hmac(&[key.clone(), message.clone()], &mut caps).unwrap(),
+            s("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+        assert_eq!(
+            hmac(&[key.clone(), message.clone(), s("sha512")], &mut caps).unwrap(),
+            s("164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+               9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737")
+        );
+        assert!(hmac(&[key, message, s("md5")], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_random_bytes_and_compare() {
+        let mut caps = test_caps();
+        let Value::String(a) = random_bytes(&[Value::Int(16)], &mut caps).unwrap() else {
+            panic!("expected a string");
+        };
+        let b = random_bytes(&[Value::Int(16)], &mut caps).unwrap();
+        assert_eq!(a.len(), 32);
+        assert_ne!(s(&a), b);
+        assert_eq!(random_bytes(&[Value::Int(0)], &mut caps).unwrap(), s(""));
+        assert!(random_bytes(&[Value::Int(-1)], &mut caps).is_err());
+
+        assert_eq!(
+            constant_time_equals(&[s("token"), s("token")], &mut caps).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            constant_time_equals(&[s("token"), s("tokens")], &mut caps).unwrap(),
+            Value::Bool(false)
+        );
+    }
+
+    #[test]
+    fn test_requires_crypto_capability() {
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+        assert!(matches!(
+            sha256(&[s("abc")], &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+        assert!(random_bytes(&[Value::Int(4)], &mut caps).is_err());
+    }
+}
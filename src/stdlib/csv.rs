@@ -0,0 +1,301 @@
+//! WokeLang Standard Library - CSV Module
+//!
+//! Reading and writing comma-separated values. With a header row, each row
+//! becomes a map from column name to field; without one, each row is an
+//! array of fields. Fields are always read as strings.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::io::{require_read, validate_path};
+use super::{check_arity_range, expect_bool, expect_string, json, Invoke, StdlibError};
+use ::csv::{ReaderBuilder, StringRecord, Writer};
+use std::collections::{BTreeSet, HashMap};
+
+/// Convert a CSV record into a row value
+fn row_value(record: &StringRecord, headers: Option<&StringRecord>) -> Value {
+    match headers {
+        Some(headers) => Value::Record(
+            headers
+                .iter()
+                .zip(record.iter())
+                .map(|(h, field)| (h.to_string(), Value::String(field.to_string().into())))
+                .collect(),
+        ),
+        None => Value::Array(
+            record
+                .iter()
+                .map(|field| Value::String(field.to_string().into()))
+                .collect::<Vec<_>>().into(),
+        ),
+    }
+}
+
+fn csv_error(e: ::csv::Error) -> StdlibError {
+    match e.kind() {
+        ::csv::ErrorKind::Io(_) => StdlibError::IoError(e.to_string()),
+        _ => StdlibError::ParseError(format!("Invalid CSV: {}", e)),
+    }
+}
+
+/// Whether the optional argument at `index` asks for a header row (default true)
+fn has_headers(args: &[Value], index: usize) -> Result<bool, StdlibError> {
+    args.get(index)
+        .map_or(Ok(true), |v| expect_bool(v, "headers"))
+}
+
+/// Parse CSV text into an array of rows
+///
+/// `parse(text)` uses the first line as headers and returns an array of
+/// maps; `parse(text, false)` returns an array of arrays.
+pub fn parse(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let text = expect_string(&args[0], "csv")?;
+    let headers = has_headers(args, 1)?;
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(headers)
+        .from_reader(text.as_bytes());
+    let header_row = if headers {
+        Some(reader.headers().map_err(csv_error)?.clone())
+    } else {
+        None
+    };
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        rows.push(row_value(&record.map_err(csv_error)?, header_row.as_ref()));
+    }
+    Ok(Value::Array(rows.into()))
+}
+
+/// Text form of a single field
+fn field_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_string(),
+        Value::Unit => String::new(),
+        Value::Array(_) | Value::Record(_) => json::to_json(value).to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Write an array of rows as CSV text
+///
+/// Rows may be maps or arrays. For maps the header row lists the columns
+/// given in the optional second argument, or every key in sorted order;
+/// missing fields are left empty. For arrays the columns, if given, are
+/// written as the header row.
+pub fn stringify(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let rows = match &args[0] {
+        Value::Array(rows) => rows,
+        other => {
+            return Err(StdlibError::TypeError {
+                expected: "Array of rows".to_string(),
+                got: format!("{:?}", other),
+            })
+        }
+    };
+    let columns = args.get(1).map(expect_columns).transpose()?;
+
+    let maps: Option<Vec<&HashMap<String, Value>>> = rows
+        .iter()
+        .map(|row| match row {
+            Value::Record(map) => Some(map),
+            _ => None,
+        })
+        .collect();
+
+    let mut writer = Writer::from_writer(Vec::new());
+    match maps {
+        Some(maps) if !maps.is_empty() => {
+            let columns = columns.unwrap_or_else(|| {
+                let keys: BTreeSet<&String> = maps.iter().flat_map(|m| m.keys()).collect();
+                keys.into_iter().cloned().collect()
+            });
+            writer.write_record(&columns).map_err(csv_error)?;
+            for map in maps {
+                writer
+                    .write_record(columns.iter().map(|c| map.get(c).map(field_text).unwrap_or_default()))
+                    .map_err(csv_error)?;
+            }
+        }
+        _ => {
+            if let Some(columns) = &columns {
+                writer.write_record(columns).map_err(csv_error)?;
+            }
+            for row in rows.iter() {
+                match row {
+                    Value::Array(fields) => writer
+                        .write_record(fields.iter().map(field_text))
+                        .map_err(csv_error)?,
+                    other => {
+                        return Err(StdlibError::TypeError {
+                            expected: "rows that are all maps or all arrays".to_string(),
+                            got: format!("{:?}", other),
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| StdlibError::IoError(e.to_string()))?;
+    String::from_utf8(bytes)
+        .map(|s| Value::String(s.into()))
+        .map_err(|e| StdlibError::RuntimeError(e.to_string()))
+}
+
+fn expect_columns(value: &Value) -> Result<Vec<String>, StdlibError> {
+    match value {
+        Value::Array(items) => items.iter().map(|c| expect_string(c, "column")).collect(),
+        other => Err(StdlibError::TypeError {
+            expected: "Array of column names".to_string(),
+            got: format!("{:?}", other),
+        }),
+    }
+}
+
+/// Consent gate for `forEachRow`: reading the file needs `FileRead`
+pub fn require_rows(args: &[Value], caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    check_arity_range(args, 2, 3)?;
+    let path = expect_string(&args[0], "path")?;
+    validate_path(&path)?;
+    require_read(&path, caps)
+}
+
+/// Call a function with each row of a CSV file, returning the row count
+///
+/// Rows are read one at a time, so files of any size can be processed.
+/// Like `parse`, an optional third argument of `false` reads rows as arrays.
+/// The caller must hold the `FileRead` capability (see [`require_rows`]).
+pub fn for_each_row(args: &[Value], invoke: &mut Invoke) -> Result<Value, StdlibError> {
+    check_arity_range(args, 2, 3)?;
+    let path = validate_path(&expect_string(&args[0], "path")?)?;
+    let headers = has_headers(args, 2)?;
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(headers)
+        .from_path(&path)
+        .map_err(csv_error)?;
+    let header_row = if headers {
+        Some(reader.headers().map_err(csv_error)?.clone())
+    } else {
+        None
+    };
+
+    let mut count = 0;
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record).map_err(csv_error)? {
+        invoke(&args[1], vec![row_value(&record, header_row.as_ref())])?;
+        count += 1;
+    }
+    Ok(Value::Int(count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+    use std::env;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn row(fields: &[(&str, &str)]) -> Value {
+        Value::Record(fields.iter().map(|(k, v)| (k.to_string(), s(v))).collect())
+    }
+
+    #[test]
+    fn test_parse_with_headers() {
+        let mut caps = test_caps();
+        let text = "name,lang\nAda,\"Woke, obviously\"\nGrace,\"say \"\"hi\"\"\"\n";
+        assert_eq!(
+            parse(&[s(text)], &mut caps).unwrap(),
+            Value::Array(vec![
+                row(&[("name", "Ada"), ("lang", "Woke, obviously")]),
+                row(&[("name", "Grace"), ("lang", "say \"hi\"")]),
+            ].into())
+        );
+
+        assert_eq!(
+            parse(&[s("a,b\n1,2\n"), Value::Bool(false)], &mut caps).unwrap(),
+            Value::Array(vec![
+                Value::Array(vec![s("a"), s("b")].into()),
+                Value::Array(vec![s("1"), s("2")].into()),
+            ].into())
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut caps = test_caps();
+        assert!(matches!(
+            parse(&[s("a,b\n1,2,3\n")], &mut caps),
+            Err(StdlibError::ParseError(_))
+        ));
+        assert!(parse(&[Value::Int(1)], &mut caps).is_err());
+        assert!(parse(&[s("a"), s("yes")], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_stringify() {
+        let mut caps = test_caps();
+        let rows = Value::Array(vec![
+            row(&[("name", "Ada"), ("note", "a,b")]),
+            Value::Record(HashMap::from([
+                ("name".to_string(), s("Grace")),
+                ("age".to_string(), Value::Int(85)),
+            ])),
+        ].into());
+        assert_eq!(
+            stringify(std::slice::from_ref(&rows), &mut caps).unwrap(),
+            s("age,name,note\n,Ada,\"a,b\"\n85,Grace,\n")
+        );
+        assert_eq!(
+            stringify(&[rows, Value::Array(vec![s("name")].into())], &mut caps).unwrap(),
+            s("name\nAda\nGrace\n")
+        );
+
+        let arrays = Value::Array(vec![Value::Array(vec![Value::Int(1), Value::Bool(true), Value::Unit].into())].into());
+        assert_eq!(stringify(&[arrays], &mut caps).unwrap(), s("1,true,\n"));
+        assert!(stringify(&[Value::Array(vec![Value::Int(1)].into())], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut caps = test_caps();
+        let text = "id,text\n1,\"line one\nline two\"\n2,plain\n";
+        let rows = parse(&[s(text)], &mut caps).unwrap();
+        assert_eq!(stringify(&[rows], &mut caps).unwrap(), s(text));
+    }
+
+    #[test]
+    fn test_for_each_row() {
+        let path = env::temp_dir().join("wokelang_test_rows.csv");
+        std::fs::write(&path, "n,sq\n2,4\n3,9\n").unwrap();
+        let path = s(&path.to_string_lossy());
+
+        let mut caps = test_caps();
+        require_rows(&[path.clone(), s("f")], &mut caps).unwrap();
+
+        let mut seen = Vec::new();
+        let mut record = |_: &Value, args: Vec<Value>| {
+            seen.push(args[0].clone());
+            Ok(Value::Unit)
+        };
+        let count = for_each_row(&[path.clone(), s("f")], &mut record).unwrap();
+        assert_eq!(count, Value::Int(2));
+        assert_eq!(seen[1], row(&[("n", "3"), ("sq", "9")]));
+
+        let mut denied = CapabilityRegistry::new();
+        denied.set_interactive(false);
+        denied.set_default_consent(false);
+        assert!(matches!(
+            require_rows(&[path, s("f")], &mut denied),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+    }
+}
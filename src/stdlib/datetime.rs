@@ -0,0 +1,348 @@
+//! WokeLang Standard Library - DateTime Functions
+//!
+//! Calendar dates and times for `std.time`. A DateTime is an instant plus
+//! the UTC offset it is shown in. Zones are given as `"UTC"`, `"local"` or
+//! a fixed offset such as `"+05:30"`; `"local"` follows the system zone,
+//! including daylight saving, at each instant.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, check_arity_range, expect_int, expect_string, StdlibError};
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Local, LocalResult, Months, NaiveDate,
+    Offset, TimeZone, Timelike, Utc,
+};
+use std::fmt::Write;
+
+/// Where a DateTime is shown
+enum Zone {
+    Fixed(FixedOffset),
+    Local,
+}
+
+impl Zone {
+    fn parse(name: &str) -> Result<Zone, StdlibError> {
+        match name {
+            "UTC" | "utc" | "Z" => return Ok(Zone::Fixed(Utc.fix())),
+            "local" => return Ok(Zone::Local),
+            _ => {}
+        }
+        parse_offset(name).map(Zone::Fixed).ok_or_else(|| {
+            StdlibError::RuntimeError(format!(
+                "Unknown time zone: {} (use UTC, local or an offset like +02:00)",
+                name
+            ))
+        })
+    }
+
+    /// Show an instant in this zone
+    fn at(&self, instant: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Zone::Fixed(offset) => instant.with_timezone(offset),
+            Zone::Local => instant.with_timezone(&Local).fixed_offset(),
+        }
+    }
+}
+
+/// Parse `+HH:MM`, `+HHMM` or `+HH` (or `-`)
+fn parse_offset(text: &str) -> Option<FixedOffset> {
+    let (sign, rest) = match text.as_bytes().first()? {
+        b'+' => (1, &text[1..]),
+        b'-' => (-1, &text[1..]),
+        _ => return None,
+    };
+    let digits: String = rest.chars().filter(|c| *c != ':').collect();
+    if !digits.bytes().all(|b| b.is_ascii_digit()) || !matches!(digits.len(), 2 | 4) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits.get(2..).map_or(Some(0), |m| m.parse().ok())?;
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+fn zone_arg(args: &[Value], index: usize) -> Result<Zone, StdlibError> {
+    match args.get(index) {
+        Some(zone) => Zone::parse(&expect_string(zone, "zone")?),
+        None => Ok(Zone::Local),
+    }
+}
+
+/// Helper to extract a DateTime
+pub fn expect_datetime(value: &Value, _arg_name: &str) -> Result<DateTime<FixedOffset>, StdlibError> {
+    match value {
+        Value::DateTime(dt) => Ok(*dt),
+        other => Err(StdlibError::TypeError {
+            expected: "DateTime".to_string(),
+            got: format!("{:?}", other),
+        }),
+    }
+}
+
+fn out_of_range(what: &str) -> StdlibError {
+    StdlibError::RuntimeError(format!("{} is out of the supported date range", what))
+}
+
+/// Build a DateTime from its parts:
+/// dateTime(year, month, day[, hour, minute, second[, zone]])
+///
+/// The zone defaults to local time. A local time skipped by a daylight
+/// saving change is an error; a repeated one uses the earlier instant.
+pub fn date_time(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 3, 7)?;
+    let part = |i: usize, name: &str| -> Result<i64, StdlibError> {
+        args.get(i).map_or(Ok(0), |v| expect_int(v, name))
+    };
+    let (year, month, day) = (part(0, "year")?, part(1, "month")?, part(2, "day")?);
+    let (hour, minute, second) = (part(3, "hour")?, part(4, "minute")?, part(5, "second")?);
+
+    let invalid = || {
+        StdlibError::RuntimeError(format!(
+            "Invalid date or time: {}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year, month, day, hour, minute, second
+        ))
+    };
+    let narrow = |n: i64| u32::try_from(n).map_err(|_| invalid());
+    let naive = i32::try_from(year)
+        .ok()
+        .and_then(|y| NaiveDate::from_ymd_opt(y, narrow(month).ok()?, narrow(day).ok()?))
+        .and_then(|date| {
+            date.and_hms_opt(narrow(hour).ok()?, narrow(minute).ok()?, narrow(second).ok()?)
+        })
+        .ok_or_else(invalid)?;
+
+    let dt = match zone_arg(args, 6)? {
+        Zone::Fixed(offset) => offset.from_local_datetime(&naive),
+        Zone::Local => Local.from_local_datetime(&naive).map(|dt| dt.fixed_offset()),
+    };
+    match dt {
+        LocalResult::Single(dt) | LocalResult::Ambiguous(dt, _) => Ok(Value::DateTime(dt)),
+        LocalResult::None => Err(StdlibError::RuntimeError(format!(
+            "{} does not exist in local time",
+            naive
+        ))),
+    }
+}
+
+/// The current date and time: current([zone]), local time by default
+pub fn current(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 0, 1)?;
+    Ok(Value::DateTime(zone_arg(args, 0)?.at(Utc::now())))
+}
+
+/// Parse an RFC 3339 timestamp such as `2024-05-01T09:30:00+02:00`,
+/// returning Okay(DateTime) or Oops
+pub fn parse_rfc3339(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let text = expect_string(&args[0], "text")?;
+    Ok(match DateTime::parse_from_rfc3339(text.trim()) {
+        Ok(dt) => Value::Okay(Box::new(Value::DateTime(dt))),
+        Err(e) => Value::oops(format!("invalid RFC 3339 datetime {:?}: {}", text, e)),
+    })
+}
+
+/// Format a DateTime as RFC 3339
+pub fn to_rfc3339(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let dt = expect_datetime(&args[0], "datetime")?;
+    Ok(Value::String(Value::DateTime(dt).to_string().into()))
+}
+
+/// A DateTime from milliseconds since the epoch: fromTimestamp(ms[, zone])
+pub fn from_timestamp(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let ms = expect_int(&args[0], "timestamp")?;
+    let instant = DateTime::<Utc>::from_timestamp_millis(ms).ok_or_else(|| out_of_range("Timestamp"))?;
+    Ok(Value::DateTime(zone_arg(args, 1)?.at(instant)))
+}
+
+/// Milliseconds since the epoch, as returned by `std.time.now`
+pub fn to_timestamp(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let dt = expect_datetime(&args[0], "datetime")?;
+    Ok(Value::Int(dt.timestamp_millis()))
+}
+
+/// The same instant shown in another zone: inZone(datetime, zone)
+pub fn in_zone(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let dt = expect_datetime(&args[0], "datetime")?;
+    let zone = Zone::parse(&expect_string(&args[1], "zone")?)?;
+    Ok(Value::DateTime(zone.at(dt.to_utc())))
+}
+
+fn component(args: &[Value], get: fn(&DateTime<FixedOffset>) -> i64) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let dt = expect_datetime(&args[0], "datetime")?;
+    Ok(Value::Int(get(&dt)))
+}
+
+/// The year, in the DateTime's own zone
+pub fn year(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    component(args, |dt| dt.year() as i64)
+}
+
+/// The month, 1 to 12
+pub fn month(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    component(args, |dt| dt.month() as i64)
+}
+
+/// The day of the month, 1 to 31
+pub fn day(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    component(args, |dt| dt.day() as i64)
+}
+
+/// The hour, 0 to 23
+pub fn hour(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    component(args, |dt| dt.hour() as i64)
+}
+
+/// The minute, 0 to 59
+pub fn minute(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    component(args, |dt| dt.minute() as i64)
+}
+
+/// The second, 0 to 59
+pub fn second(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    component(args, |dt| dt.second() as i64)
+}
+
+/// The day of the week, 1 (Monday) to 7 (Sunday)
+pub fn weekday(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    component(args, |dt| dt.weekday().number_from_monday() as i64)
+}
+
+/// The UTC offset, such as `+02:00`
+pub fn offset(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let dt = expect_datetime(&args[0], "datetime")?;
+    Ok(Value::String(dt.offset().to_string().into()))
+}
+
+/// Add whole days, keeping the time of day: addDays(datetime, days)
+pub fn add_days(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let dt = expect_datetime(&args[0], "datetime")?;
+    let days = expect_int(&args[1], "days")?;
+    Duration::try_days(days)
+        .and_then(|d| dt.checked_add_signed(d))
+        .map(Value::DateTime)
+        .ok_or_else(|| out_of_range("Result"))
+}
+
+/// Add calendar months: addMonths(datetime, months)
+///
+/// The day is clamped to the end of a shorter month, so January 31 plus
+/// one month is the last day of February.
+pub fn add_months(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let dt = expect_datetime(&args[0], "datetime")?;
+    let months = expect_int(&args[1], "months")?;
+    let count = u32::try_from(months.unsigned_abs()).map_err(|_| out_of_range("Result"))?;
+    let shifted = if months >= 0 {
+        dt.checked_add_months(Months::new(count))
+    } else {
+        dt.checked_sub_months(Months::new(count))
+    };
+    shifted.map(Value::DateTime).ok_or_else(|| out_of_range("Result"))
+}
+
+/// Format a DateTime with strftime-style tokens (`%Y-%m-%d %H:%M`)
+pub(super) fn format_datetime(dt: &DateTime<FixedOffset>, pattern: &str) -> Result<String, StdlibError> {
+    let mut out = String::new();
+    write!(out, "{}", dt.format(pattern))
+        .map_err(|_| StdlibError::RuntimeError(format!("Invalid format string: {}", pattern)))?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn int(n: i64) -> Value {
+        Value::Int(n)
+    }
+
+    fn parse(text: &str) -> Value {
+        match parse_rfc3339(&[s(text)], &mut test_caps()).unwrap() {
+            Value::Okay(dt) => *dt,
+            other => panic!("expected Okay, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_construct_and_components() {
+        let mut caps = test_caps();
+        let dt = date_time(
+            &[int(2024), int(2), int(29), int(18), int(5), int(9), s("+05:30")],
+            &mut caps,
+        )
+        .unwrap();
+        assert_eq!(dt.to_string(), "2024-02-29T18:05:09+05:30");
+        let get = |f: fn(&[Value], &mut CapabilityRegistry) -> Result<Value, StdlibError>| {
+            f(std::slice::from_ref(&dt), &mut test_caps()).unwrap()
+        };
+        assert_eq!(get(year), int(2024));
+        assert_eq!(get(month), int(2));
+        assert_eq!(get(day), int(29));
+        assert_eq!(get(hour), int(18));
+        assert_eq!(get(minute), int(5));
+        assert_eq!(get(second), int(9));
+        assert_eq!(get(weekday), int(4));
+        assert_eq!(get(offset), s("+05:30"));
+
+        assert!(date_time(&[int(2023), int(2), int(29)], &mut caps).is_err());
+        assert!(date_time(&[int(2024), int(1), int(1), int(0), int(0), int(0), s("Mars/Olympus")], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_rfc3339_and_zones() {
+        let mut caps = test_caps();
+        let dt = parse("2024-05-01T09:30:00+02:00");
+        assert_eq!(
+            to_timestamp(std::slice::from_ref(&dt), &mut caps).unwrap(),
+            int(1_714_548_600_000)
+        );
+        let utc = in_zone(&[dt.clone(), s("UTC")], &mut caps).unwrap();
+        assert_eq!(to_rfc3339(std::slice::from_ref(&utc), &mut caps).unwrap(), s("2024-05-01T07:30:00Z"));
+        // Same instant, so equal
+        assert_eq!(utc, dt);
+
+        assert_eq!(
+            from_timestamp(&[int(1_714_548_600_000), s("-0400")], &mut caps).unwrap().to_string(),
+            "2024-05-01T03:30:00-04:00"
+        );
+        assert!(parse_rfc3339(&[s("yesterday")], &mut caps).unwrap().is_oops());
+        assert!(matches!(current(&[s("local")], &mut caps).unwrap(), Value::DateTime(_)));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut caps = test_caps();
+        let dt = parse("2024-01-31T12:00:00Z");
+        assert_eq!(
+            add_days(&[dt.clone(), int(30)], &mut caps).unwrap().to_string(),
+            "2024-03-01T12:00:00Z"
+        );
+        assert_eq!(
+            add_days(&[dt.clone(), int(-31)], &mut caps).unwrap().to_string(),
+            "2023-12-31T12:00:00Z"
+        );
+        assert_eq!(
+            add_months(&[dt.clone(), int(1)], &mut caps).unwrap().to_string(),
+            "2024-02-29T12:00:00Z"
+        );
+        assert_eq!(
+            add_months(&[dt.clone(), int(-2)], &mut caps).unwrap().to_string(),
+            "2023-11-30T12:00:00Z"
+        );
+        assert!(add_days(&[dt, int(i64::MAX)], &mut caps).is_err());
+    }
+}
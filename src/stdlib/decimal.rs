@@ -0,0 +1,234 @@
+//! WokeLang Standard Library - Decimal Functions
+//!
+//! Exact fractional numbers for money and the like. A Decimal comes from a
+//! literal such as `1.50d`, keeps the places it is written with, and does
+//! arithmetic without the rounding errors of Floats: `0.10d + 0.20d` is
+//! `0.30d`. Ints mix with Decimals freely; Floats do not, and have to be
+//! converted with `fromFloat` or `toFloat` first.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, expect_float, expect_int, expect_string, StdlibError};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+fn out_of_range() -> String {
+    "Decimal out of range".to_string()
+}
+
+/// Whether arithmetic or a comparison of `left` and `right` is Decimal
+/// arithmetic: one side is a Decimal and the other a number
+pub fn involves(left: &Value, right: &Value) -> bool {
+    let number = |value: &Value| matches!(value, Value::Int(_) | Value::Float(_) | Value::Decimal(_));
+    (matches!(left, Value::Decimal(_)) && number(right)) || (matches!(right, Value::Decimal(_)) && number(left))
+}
+
+/// Both sides as Decimals; an Int side is taken exactly
+fn operands(left: &Value, right: &Value) -> Result<(Decimal, Decimal), String> {
+    fn operand(value: &Value) -> Result<Decimal, String> {
+        match value {
+            Value::Decimal(d) => Ok(*d),
+            Value::Int(n) => Ok(Decimal::from(*n)),
+            Value::Float(_) => Err(
+                "Cannot mix Decimal and Float; convert with std.decimal.fromFloat or std.decimal.toFloat"
+                    .to_string(),
+            ),
+            other => Err(format!("Expected a Decimal or Int, got {:?}", other)),
+        }
+    }
+    Ok((operand(left)?, operand(right)?))
+}
+
+fn arithmetic(
+    left: &Value,
+    right: &Value,
+    op: fn(Decimal, Decimal) -> Option<Decimal>,
+) -> Result<Value, String> {
+    let (a, b) = operands(left, right)?;
+    op(a, b).map(Value::Decimal).ok_or_else(out_of_range)
+}
+
+/// `left + right` with a Decimal on at least one side
+pub fn add(left: &Value, right: &Value) -> Result<Value, String> {
+    arithmetic(left, right, Decimal::checked_add)
+}
+
+/// `left - right` with a Decimal on at least one side
+pub fn subtract(left: &Value, right: &Value) -> Result<Value, String> {
+    arithmetic(left, right, Decimal::checked_sub)
+}
+
+/// `left * right` with a Decimal on at least one side
+pub fn multiply(left: &Value, right: &Value) -> Result<Value, String> {
+    arithmetic(left, right, Decimal::checked_mul)
+}
+
+/// `left / right` with a Decimal on at least one side, to as many places as
+/// a Decimal holds
+pub fn divide(left: &Value, right: &Value) -> Result<Value, String> {
+    if operands(left, right)?.1.is_zero() {
+        return Err("Division by zero".to_string());
+    }
+    arithmetic(left, right, Decimal::checked_div)
+}
+
+/// `left % right` with a Decimal on at least one side
+pub fn remainder(left: &Value, right: &Value) -> Result<Value, String> {
+    if operands(left, right)?.1.is_zero() {
+        return Err("Division by zero".to_string());
+    }
+    arithmetic(left, right, Decimal::checked_rem)
+}
+
+/// How `left` compares with `right`, with a Decimal on at least one side;
+/// `1.5d` and `1.50d` are equal
+pub fn compare(left: &Value, right: &Value) -> Result<Ordering, String> {
+    let (a, b) = operands(left, right)?;
+    Ok(a.cmp(&b))
+}
+
+/// Helper to extract a Decimal; an Int is taken as one
+pub fn expect_decimal(value: &Value, _arg_name: &str) -> Result<Decimal, StdlibError> {
+    match value {
+        Value::Decimal(d) => Ok(*d),
+        Value::Int(n) => Ok(Decimal::from(*n)),
+        other => Err(StdlibError::TypeError {
+            expected: "Decimal".to_string(),
+            got: format!("{:?}", other),
+        }),
+    }
+}
+
+/// Places to round to, from 0 to 28
+fn expect_places(value: &Value) -> Result<u32, StdlibError> {
+    let places = expect_int(value, "places")?;
+    u32::try_from(places)
+        .ok()
+        .filter(|places| *places <= Decimal::MAX_SCALE)
+        .ok_or_else(|| StdlibError::RuntimeError(format!("places must be from 0 to 28, got {}", places)))
+}
+
+fn rounded(args: &[Value], strategy: RoundingStrategy) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let d = expect_decimal(&args[0], "value")?;
+    let places = expect_places(&args[1])?;
+    Ok(Value::Decimal(d.round_dp_with_strategy(places, strategy)))
+}
+
+/// Round to a number of places, halves away from zero:
+/// round(value, places)
+pub fn round(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    rounded(args, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// Round to a number of places, halves to the even neighbour, as banks
+/// do: roundHalfEven(value, places)
+pub fn round_half_even(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    rounded(args, RoundingStrategy::MidpointNearestEven)
+}
+
+/// Cut to a number of places, toward zero: truncate(value, places)
+pub fn truncate(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    rounded(args, RoundingStrategy::ToZero)
+}
+
+/// The whole number at or below a Decimal
+pub fn floor(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    Ok(Value::Decimal(expect_decimal(&args[0], "value")?.floor()))
+}
+
+/// The whole number at or above a Decimal
+pub fn ceil(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    Ok(Value::Decimal(expect_decimal(&args[0], "value")?.ceil()))
+}
+
+/// Places after the point a Decimal keeps: 2 for `1.50d`
+pub fn places(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    Ok(Value::Int(expect_decimal(&args[0], "value")?.scale().into()))
+}
+
+/// Read a Decimal from text such as "12.50", keeping its places
+pub fn parse(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let text = expect_string(&args[0], "text")?;
+    Ok(match Decimal::from_str(text.trim()) {
+        Ok(d) => Value::Okay(Box::new(Value::Decimal(d))),
+        Err(e) => Value::oops(format!("invalid Decimal {:?}: {}", text, e)),
+    })
+}
+
+/// The Decimal a Float shows as: fromFloat(0.1) is `0.1d`
+pub fn from_float(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let f = expect_float(&args[0], "value")?;
+    Decimal::from_str(&f.to_string())
+        .map(Value::Decimal)
+        .map_err(|_| StdlibError::RuntimeError(out_of_range()))
+}
+
+/// The Float nearest a Decimal
+pub fn to_float(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let d = expect_decimal(&args[0], "value")?;
+    d.to_f64()
+        .map(Value::Float)
+        .ok_or_else(|| StdlibError::RuntimeError(out_of_range()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(text: &str) -> Value {
+        Value::Decimal(Decimal::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(add(&d("0.10"), &d("0.20")).unwrap().to_string(), "0.30");
+        assert_eq!(subtract(&d("5.00"), &Value::Int(2)).unwrap().to_string(), "3.00");
+        assert_eq!(multiply(&Value::Int(3), &d("1.15")).unwrap().to_string(), "3.45");
+        assert_eq!(divide(&d("10"), &d("4")).unwrap(), d("2.5"));
+        assert_eq!(divide(&d("1"), &d("3")).unwrap().to_string(), "0.3333333333333333333333333333");
+        assert_eq!(remainder(&d("7.5"), &Value::Int(2)).unwrap().to_string(), "1.5");
+        assert_eq!(divide(&d("1"), &Value::Int(0)).unwrap_err(), "Division by zero");
+        assert!(add(&d("1.5"), &Value::Float(1.5)).unwrap_err().contains("Cannot mix Decimal and Float"));
+        assert_eq!(multiply(&Value::Decimal(Decimal::MAX), &d("2")).unwrap_err(), "Decimal out of range");
+
+        assert_eq!(compare(&d("1.5"), &d("1.50")).unwrap(), Ordering::Equal);
+        assert_eq!(compare(&Value::Int(2), &d("1.99")).unwrap(), Ordering::Greater);
+        assert!(involves(&d("1"), &Value::Float(1.0)));
+        assert!(!involves(&d("1"), &Value::String("1".to_string().into())));
+        assert!(!involves(&Value::Int(1), &Value::Float(1.0)));
+    }
+
+    #[test]
+    fn test_rounding() {
+        let mut caps = CapabilityRegistry::permissive();
+        assert_eq!(round(&[d("2.345"), Value::Int(2)], &mut caps).unwrap(), d("2.35"));
+        assert_eq!(round(&[d("-2.5"), Value::Int(0)], &mut caps).unwrap(), d("-3"));
+        assert_eq!(round_half_even(&[d("2.345"), Value::Int(2)], &mut caps).unwrap(), d("2.34"));
+        assert_eq!(truncate(&[d("2.349"), Value::Int(2)], &mut caps).unwrap(), d("2.34"));
+        assert_eq!(floor(&[d("-1.2")], &mut caps).unwrap(), d("-2"));
+        assert_eq!(ceil(&[d("1.2")], &mut caps).unwrap(), d("2"));
+        assert_eq!(places(&[d("1.50")], &mut caps).unwrap(), Value::Int(2));
+        assert!(round(&[d("1"), Value::Int(-1)], &mut caps).is_err());
+        assert!(round(&[Value::Float(1.0), Value::Int(1)], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let mut caps = CapabilityRegistry::permissive();
+        let text = |s: &str| Value::String(s.to_string().into());
+        assert_eq!(parse(&[text(" 12.50 ")], &mut caps).unwrap().to_string(), "Okay(12.50)");
+        assert!(parse(&[text("twelve")], &mut caps).unwrap().is_oops());
+        assert_eq!(from_float(&[Value::Float(0.1)], &mut caps).unwrap().to_string(), "0.1");
+        assert!(from_float(&[Value::Float(f64::NAN)], &mut caps).is_err());
+        assert_eq!(to_float(&[d("0.25")], &mut caps).unwrap(), Value::Float(0.25));
+    }
+}
@@ -0,0 +1,407 @@
+//! Reference for the Builtin and Standard Library Functions
+//!
+//! One table gives every builtin and `std` function's signature, the
+//! superpowers it asks for and a one-line summary. The REPL's `:doc`
+//! command shows single entries, and `woke doc` prints the whole table as
+//! the Markdown reference kept in `docs/wiki/Reference/Standard-Library.md`.
+
+use std::fmt;
+
+/// The reference entry for one function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionDoc {
+    /// The name it is called by: `print`, or `std.math.sqrt`
+    pub name: &'static str,
+    /// Parameters and result, e.g. `(n: Number) -> Float`; optional
+    /// parameters are in brackets
+    pub signature: &'static str,
+    /// Superpowers it asks for, named as in `--allow-*` flags
+    pub capabilities: &'static [&'static str],
+    /// What it does, in a line
+    pub summary: &'static str,
+}
+
+impl FunctionDoc {
+    /// Whether it is a builtin rather than part of a `std` module
+    pub fn is_builtin(&self) -> bool {
+        !self.name.contains('.')
+    }
+
+    /// The module it belongs to, e.g. `std.math`, or `None` for a builtin
+    pub fn module(&self) -> Option<&'static str> {
+        self.name.rsplit_once('.').map(|(module, _)| module)
+    }
+}
+
+impl fmt::Display for FunctionDoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}{}", self.name, self.signature)?;
+        writeln!(f, "    {}", self.summary)?;
+        if self.capabilities.is_empty() {
+            write!(f, "    Superpowers: none")
+        } else {
+            write!(f, "    Superpowers: {}", self.capabilities.join(", "))
+        }
+    }
+}
+
+const fn entry(
+    name: &'static str,
+    signature: &'static str,
+    capabilities: &'static [&'static str],
+    summary: &'static str,
+) -> FunctionDoc {
+    FunctionDoc { name, signature, capabilities, summary }
+}
+
+/// Every documented function: the builtins, then the `std` modules
+static FUNCTIONS: &[FunctionDoc] = &[
+    entry("print", "(values...) -> Unit", &[], "Print values separated by spaces, then a newline"),
+    entry("len", "(value: String | Bytes | [T] | Map) -> Int", &[], "Characters in a string, bytes in bytes, or elements in an array or map"),
+    entry("prettyPrint", "(value: T) -> Unit", &[], "Print a value over indented lines when it does not fit on one, with map keys in order"),
+    entry("toString", "(value: T) -> String", &[], "The value as text, the way `print` shows it"),
+    entry("toInt", "(value: String | Float | Decimal | Int) -> Int", &[], "Parse a string or truncate a number to an Int"),
+    entry("isOkay", "(result: Result) -> Bool", &[], "Whether a Result is an Okay"),
+    entry("isOops", "(result: Result) -> Bool", &[], "Whether a Result is an Oops"),
+    entry("unwrapOr", "(result: Result[T, E], fallback: T) -> T", &[], "The value inside an Okay, or the fallback for an Oops"),
+    entry("getError", "(result: Result[T, E]) -> E", &[], "The error inside an Oops, such as its message, or Unit for an Okay"),
+    entry("format", "(template: String, values...) -> String", &[], "Fill each `{}` in a template with the next value; the same as `std.string.format`"),
+    entry("args", "() -> [String]", &[], "Arguments given to the script; the same as `std.env.args`"),
+    entry("listSuperpowers", "() -> [String]", &[], "Superpowers the running function holds without asking; the same as `std.superpowers.list`"),
+    entry("hasSuperpower", "(name: String) -> Bool", &[], "Whether the running function holds a superpower, e.g. `\"network:*\"`, without asking"),
+    entry("renounce", "(name: String) -> Unit", &[], "Give up a superpower, e.g. `\"file:write\"`, for the rest of the run"),
+    entry("ask", "(prompt: String) -> String", &[], "Show the prompt and give back the line the user types; needs no superpower"),
+    entry("askNumber", "(prompt: String) -> Float", &[], "Ask as `ask` does until the answer is a number"),
+    entry("askYesNo", "(prompt: String) -> Bool", &[], "Ask as `ask` does until the answer is yes, y, no or n"),
+    entry("exit", "(code: Int) -> Unit", &[], "End the program now, with `code` as its exit status; side quests do not run"),
+    entry("gratitudeEntries", "() -> [Map]", &[], "The program's `thanks to` entries, each a map with a `recipient` and a `reason`"),
+    entry("std.math.abs", "(n: Number) -> Number", &[], "Absolute value"),
+    entry("std.math.sqrt", "(n: Number) -> Float", &[], "Square root"),
+    entry("std.math.pow", "(base: Number, exponent: Number) -> Float", &[], "Raise a base to a power"),
+    entry("std.math.sin", "(n: Number) -> Float", &[], "Sine of an angle in radians"),
+    entry("std.math.cos", "(n: Number) -> Float", &[], "Cosine of an angle in radians"),
+    entry("std.math.tan", "(n: Number) -> Float", &[], "Tangent of an angle in radians"),
+    entry("std.math.floor", "(n: Number) -> Int", &[], "Round down to an Int"),
+    entry("std.math.ceil", "(n: Number) -> Int", &[], "Round up to an Int"),
+    entry("std.math.round", "(n: Number) -> Int", &[], "Round to the nearest Int"),
+    entry("std.math.min", "(a: Number, b: Number) -> Number", &[], "The smaller of two values"),
+    entry("std.math.max", "(a: Number, b: Number) -> Number", &[], "The larger of two values"),
+    entry("std.math.random", "([min: Number, max: Number]) -> Float", &[], "Random number between 0 and 1, or between min and max if given"),
+    entry("std.math.randomSeed", "(seed: Int) -> Unit", &[], "Seed the random functions, so that a run can be repeated"),
+    entry("std.math.randomInt", "(lo: Int, hi: Int) -> Int", &[], "Random integer between lo and hi, both included"),
+    entry("std.math.randomChoice", "(array: [T]) -> Result[T, String]", &[], "Pick one element at random, or Oops for an empty array"),
+    entry("std.math.shuffle", "(array: [T]) -> [T]", &[], "Copy of an array in random order"),
+    entry("std.math.pi", "() -> Float", &[], "Pi constant"),
+    entry("std.math.e", "() -> Float", &[], "E constant (Euler's number)"),
+    entry("std.decimal.round", "(value: Decimal, places: Int) -> Decimal", &[], "Round to a number of places, halves away from zero"),
+    entry("std.decimal.roundHalfEven", "(value: Decimal, places: Int) -> Decimal", &[], "Round to a number of places, halves to the even neighbour"),
+    entry("std.decimal.truncate", "(value: Decimal, places: Int) -> Decimal", &[], "Cut to a number of places, toward zero"),
+    entry("std.decimal.floor", "(value: Decimal) -> Decimal", &[], "The whole number at or below a Decimal"),
+    entry("std.decimal.ceil", "(value: Decimal) -> Decimal", &[], "The whole number at or above a Decimal"),
+    entry("std.decimal.places", "(value: Decimal) -> Int", &[], "Places after the point a Decimal keeps"),
+    entry("std.decimal.parse", "(text: String) -> Result[Decimal, String]", &[], "Read a Decimal from text such as \"12.50\""),
+    entry("std.decimal.fromFloat", "(value: Float) -> Decimal", &[], "The Decimal a Float shows as"),
+    entry("std.decimal.toFloat", "(value: Decimal) -> Float", &[], "The Float nearest a Decimal"),
+    entry("std.io.readFile", "(path: String) -> String", &["file:read"], "Read entire file contents as a string"),
+    entry("std.io.writeFile", "(path: String, contents: String) -> Bool", &["file:write"], "Write string contents to a file"),
+    entry("std.io.readBytes", "(path: String) -> Bytes", &["file:read"], "Read entire file contents as bytes"),
+    entry("std.io.writeBytes", "(path: String, contents: Bytes) -> Bool", &["file:write"], "Write bytes to a file"),
+    entry("std.io.appendFile", "(path: String, contents: String) -> Bool", &["file:write"], "Append string contents to a file"),
+    entry("std.io.readLines", "(path: String) -> Int", &["file:read"], "Open a file for reading line by line, returning a cursor for `nextLine`"),
+    entry("std.io.nextLine", "(cursor: Int) -> Result[String, String]", &[], "Take the next line from a cursor, or Oops(\"end of file\") once they run out"),
+    entry("std.io.closeLines", "(cursor: Int) -> Bool", &[], "Close a cursor before its end; false if it was already closed"),
+    entry("std.io.forEachLine", "(path: String, f: (String) -> T) -> Int", &["file:read"], "Call a function with each line of a file, returning the line count"),
+    entry("std.io.streamWrite", "(path: String, contents: String) -> Bool", &["file:write"], "Append text to a file that stays open between calls"),
+    entry("std.io.streamClose", "(path: String) -> Bool", &[], "Close a file opened by `streamWrite`; false if none was open"),
+    entry("std.io.glob", "(pattern: String) -> [String]", &["file:read"], "Sorted paths matching a pattern such as `\"src/**/*.woke\"`"),
+    entry("std.io.walk", "(path: String) -> [Map]", &["file:read"], "Everything below a directory, depth first, as maps of `path`, `name`, `isDir`, `size` and more"),
+    entry("std.io.exists", "(path: String) -> Bool", &["file:read"], "Check if a file or directory exists"),
+    entry("std.io.delete", "(path: String) -> Bool", &["file:write"], "Delete a file"),
+    entry("std.io.listDir", "(path: String) -> [String]", &["file:read"], "List directory contents"),
+    entry("std.io.createDir", "(path: String) -> Bool", &["file:write"], "Create a directory (and parents if needed)"),
+    entry("std.io.readLine", "([prompt: String]) -> String", &[], "Read a line from standard input, after showing the prompt if given"),
+    entry("std.archive.zip", "(source: String, archive: String) -> Int", &["file:read", "file:write"], "Pack a file or directory into a zip archive, returning the number of files packed"),
+    entry("std.archive.unzip", "(archive: String, directory: String) -> Int", &["file:read", "file:write"], "Unpack a zip archive into a directory, returning the number of files unpacked"),
+    entry("std.archive.tarCreate", "(source: String, archive: String) -> Int", &["file:read", "file:write"], "Pack a file or directory into a gzipped tar archive, returning the number of files packed"),
+    entry("std.archive.tarExtract", "(archive: String, directory: String) -> Int", &["file:read", "file:write"], "Unpack a gzipped tar archive into a directory, returning the number of files unpacked"),
+    entry("std.log.debug", "(message: String[, fields: Map]) -> Unit", &[], "Log a debug message"),
+    entry("std.log.info", "(message: String[, fields: Map]) -> Unit", &[], "Log an informational message"),
+    entry("std.log.warn", "(message: String[, fields: Map]) -> Unit", &[], "Log a warning"),
+    entry("std.log.error", "(message: String[, fields: Map]) -> Unit", &[], "Log an error"),
+    entry("std.log.setLevel", "(level: String) -> Unit", &[], "Set the minimum level by name: \"debug\", \"info\", \"warn\" or \"error\""),
+    entry("std.log.setFormat", "(format: String) -> Unit", &[], "Choose the output format: \"text\" or \"json\""),
+    entry("std.test.assertEqual", "(actual: T, expected: T[, message: String]) -> Unit", &[], "Check that two values are equal"),
+    entry("std.test.assertTrue", "(condition: Bool[, message: String]) -> Unit", &[], "Check that a condition holds"),
+    entry("std.test.assertOops", "(result: Result[, message: String]) -> Unit", &[], "Check that a Result is an Oops"),
+    entry("std.path.join", "(base: String, parts...) -> String", &[], "Join path parts; an absolute part replaces everything before it"),
+    entry("std.path.dirname", "(path: String) -> String", &[], "The directory part of a path: `.` for a bare file name"),
+    entry("std.path.basename", "(path: String) -> String", &[], "The final component of a path, or \"\" if there is none"),
+    entry("std.path.extension", "(path: String) -> String", &[], "The extension of the final component without the dot, or \"\""),
+    entry("std.path.normalize", "(path: String) -> String", &[], "Normalize a path without touching the file system"),
+    entry("std.path.absolute", "(path: String) -> String", &[], "Make a path absolute against the current directory, then normalize it"),
+    entry("std.path.exists", "(path: String) -> Bool", &["file:read"], "Check whether a path exists"),
+    entry("std.json.parse", "(json: String) -> Json", &[], "Parse JSON text into maps, arrays and plain values"),
+    entry("std.json.stringify", "(value: T[, indent: Bool | Int]) -> String", &[], "Convert any value to JSON text, pretty printed with `true` or an indent width"),
+    entry("std.json.get", "(json: Map, path: String) -> T", &[], "Deprecated: use `std.map.get` or indexing (`value[\"key\"]`) instead"),
+    entry("std.json.set", "(json: Map, key: String, value: T) -> Map", &[], "Deprecated: use `std.map.set` instead"),
+    entry("std.config.parseToml", "(text: String) -> Map", &[], "Parse a TOML document into a map"),
+    entry("std.config.parseYaml", "(text: String) -> Yaml", &[], "Parse a YAML document into maps, arrays and plain values"),
+    entry("std.env.get", "(name: String) -> Result[String, String]", &["env"], "Read a variable, returning Okay(value) or Oops if it is not set"),
+    entry("std.env.set", "(name: String, value: String) -> Unit", &["env"], "Set a variable for this process and the processes it starts"),
+    entry("std.env.all", "() -> Map", &["env"], "All variables with Unicode names and values, as a map"),
+    entry("std.env.args", "() -> [String]", &[], "Arguments given to the script; these need no consent"),
+    entry("std.process.run", "(program: String, args: [String][, options: Map]) -> Result[Map, String]", &["execute"], "Run a command to completion, returning its `code`, `stdout` and `stderr`"),
+    entry("std.process.spawn", "(program: String, args: [String][, options: Map]) -> Int", &["execute"], "Start a long-running command, returning a handle for `wait`"),
+    entry("std.process.wait", "(process: Int) -> Result[Map, String]", &[], "Wait for a spawned child to finish, returning the same result as `run`"),
+    entry("std.process.isRunning", "(process: Int) -> Bool", &[], "Check whether a spawned child is still running"),
+    entry("std.process.kill", "(process: Int) -> Bool", &[], "Stop a spawned child; `wait` still returns its output afterwards"),
+    entry("std.term.color", "(text: String, color: String) -> String", &[], "Color text"),
+    entry("std.term.background", "(text: String, color: String) -> String", &[], "Color the background behind text"),
+    entry("std.term.style", "(text: String, style: String) -> String", &[], "Style text: \"bold\", \"dim\", \"italic\", \"underline\", \"inverse\" or \"strikethrough\""),
+    entry("std.term.strip", "(text: String) -> String", &[], "Remove escape codes from text, e.g. before measuring or saving it"),
+    entry("std.term.isTty", "() -> Bool", &[], "Whether stdout is a terminal"),
+    entry("std.term.setColor", "(enabled: Bool) -> Unit", &[], "Turn escape codes on or off, whatever the terminal"),
+    entry("std.term.width", "() -> Int", &[], "Terminal width in columns, from `COLUMNS`, or 80"),
+    entry("std.term.cursorUp", "([lines: Int]) -> String", &[], "Move the cursor up"),
+    entry("std.term.cursorDown", "([lines: Int]) -> String", &[], "Move the cursor down"),
+    entry("std.term.cursorColumn", "(column: Int) -> String", &[], "Move the cursor to a column of the current line, counting from 1"),
+    entry("std.term.clearLine", "() -> String", &[], "Clear the current line and return to its start"),
+    entry("std.term.hideCursor", "() -> String", &[], "Hide the cursor, e.g. while drawing progress"),
+    entry("std.term.showCursor", "() -> String", &[], "Show the cursor again"),
+    entry("std.term.progressBar", "(done: Int, total: Int[, width: Int]) -> String", &[], "Draw a progress bar such as `[#####.....]  50%`"),
+    entry("std.ui.clipboardRead", "() -> Result[String, String]", &["clipboard"], "Read the clipboard text"),
+    entry("std.ui.clipboardWrite", "(text: String) -> Result[Unit, String]", &["clipboard"], "Replace the clipboard text"),
+    entry("std.ui.notify", "(title: String, body: String) -> Result[Unit, String]", &["notify"], "Show a desktop notification"),
+    entry("std.superpowers.list", "() -> [String]", &[], "Superpowers the running function holds without asking"),
+    entry("std.superpowers.has", "(name: String) -> Bool", &[], "Whether the running function holds a superpower, e.g. `\"network:*\"`, without asking"),
+    entry("std.superpowers.renounce", "(name: String) -> Unit", &[], "Give up a superpower, and everything it covers, for the rest of the run"),
+    entry("std.crypto.sha256", "(data: String | Bytes) -> String", &["crypto"], "SHA-256 digest of a string or bytes, as hex"),
+    entry("std.crypto.sha512", "(data: String | Bytes) -> String", &["crypto"], "SHA-512 digest of a string or bytes, as hex"),
+    entry("std.crypto.hmac", "(key: String | Bytes, message: String | Bytes[, algorithm: String]) -> String", &["crypto"], "HMAC of a message with \"sha256\" (the default) or \"sha512\", as hex"),
+    entry("std.crypto.randomBytes", "(count: Int) -> String", &["crypto"], "Cryptographically secure random bytes from the OS, as hex"),
+    entry("std.crypto.constantTimeEquals", "(a: String, b: String) -> Bool", &["crypto"], "Compare two strings in time independent of where they differ"),
+    entry("std.encoding.base64Encode", "(data: String | Bytes[, urlSafe: Bool]) -> String", &[], "Base64-encode a string or bytes; pass `true` to use the URL-safe alphabet"),
+    entry("std.encoding.base64Decode", "(text: String[, urlSafe: Bool]) -> Result[String, String]", &[], "Decode base64 text; pass `true` to use the URL-safe alphabet"),
+    entry("std.encoding.base64DecodeBytes", "(text: String[, urlSafe: Bool]) -> Result[Bytes, String]", &[], "Decode base64 text to bytes; pass `true` to use the URL-safe alphabet"),
+    entry("std.encoding.hexEncode", "(data: String | Bytes) -> String", &[], "Hex-encode a string or bytes as lowercase digits"),
+    entry("std.encoding.hexDecode", "(text: String) -> Result[String, String]", &[], "Decode hex digits (either case)"),
+    entry("std.encoding.hexDecodeBytes", "(text: String) -> Result[Bytes, String]", &[], "Decode hex digits (either case) to bytes"),
+    entry("std.encoding.urlEncode", "(text: String) -> String", &[], "Percent-encode everything except unreserved characters (RFC 3986)"),
+    entry("std.encoding.urlDecode", "(text: String) -> Result[String, String]", &[], "Decode `%XX` escapes; `+` is left as is"),
+    entry("std.time.now", "() -> Int", &[], "Current timestamp as milliseconds since the epoch"),
+    entry("std.time.timestamp", "() -> Int", &[], "Current timestamp as seconds since the epoch"),
+    entry("std.time.format", "(timestamp: Int | DateTime, format: String) -> String", &[], "Format a timestamp or DateTime with tokens such as `%Y-%m-%d %H:%M:%S`"),
+    entry("std.time.parse", "(date: String, format: String) -> Int", &[], "Parse a date string to a timestamp"),
+    entry("std.time.sleep", "(delay: Duration | Int) -> Unit", &[], "Sleep for a Duration or a given number of milliseconds"),
+    entry("std.time.elapsed", "(action: String, name: String) -> Int", &[], "Start a named timer with \"start\", or get its elapsed milliseconds with \"stop\""),
+    entry("std.time.dateTime", "(year: Int, month: Int, day: Int[, hour: Int, minute: Int, second: Int, zone: String]) -> DateTime", &[], "Build a DateTime from its parts, in local time unless a zone is given"),
+    entry("std.time.current", "([zone: String]) -> DateTime", &[], "The current date and time, local time by default"),
+    entry("std.time.parseRfc3339", "(text: String) -> Result[DateTime, String]", &[], "Parse an RFC 3339 timestamp such as `2024-05-01T09:30:00+02:00`"),
+    entry("std.time.toRfc3339", "(datetime: DateTime) -> String", &[], "Format a DateTime as RFC 3339"),
+    entry("std.time.fromTimestamp", "(timestamp: Int[, zone: String]) -> DateTime", &[], "A DateTime from milliseconds since the epoch"),
+    entry("std.time.toTimestamp", "(datetime: DateTime) -> Int", &[], "Milliseconds since the epoch, as returned by `std.time.now`"),
+    entry("std.time.inZone", "(datetime: DateTime, zone: String) -> DateTime", &[], "The same instant shown in another zone"),
+    entry("std.time.year", "(datetime: DateTime) -> Int", &[], "The year, in the DateTime's own zone"),
+    entry("std.time.month", "(datetime: DateTime) -> Int", &[], "The month, 1 to 12"),
+    entry("std.time.day", "(datetime: DateTime) -> Int", &[], "The day of the month, 1 to 31"),
+    entry("std.time.hour", "(datetime: DateTime) -> Int", &[], "The hour, 0 to 23"),
+    entry("std.time.minute", "(datetime: DateTime) -> Int", &[], "The minute, 0 to 59"),
+    entry("std.time.second", "(datetime: DateTime) -> Int", &[], "The second, 0 to 59"),
+    entry("std.time.weekday", "(datetime: DateTime) -> Int", &[], "The day of the week, 1 (Monday) to 7 (Sunday)"),
+    entry("std.time.offset", "(datetime: DateTime) -> String", &[], "The UTC offset, such as `+02:00`"),
+    entry("std.time.addDays", "(datetime: DateTime, days: Int) -> DateTime", &[], "Add whole days, keeping the time of day"),
+    entry("std.time.addMonths", "(datetime: DateTime, months: Int) -> DateTime", &[], "Add calendar months, clamping the day to the end of a shorter month"),
+    entry("std.time.duration", "(amount: Number, unit: String) -> Duration", &[], "Build a Duration from an amount of a unit such as \"seconds\""),
+    entry("std.time.fromMillis", "(milliseconds: Int) -> Duration", &[], "Build a Duration from milliseconds"),
+    entry("std.time.toMillis", "(duration: Duration) -> Int", &[], "Whole milliseconds in a Duration, rounded toward zero"),
+    entry("std.time.toSeconds", "(duration: Duration) -> Float", &[], "Seconds in a Duration, with the fraction"),
+    entry("std.net.httpGet", "(url: String) -> String", &["network"], "Make an HTTP GET request, returning the response body"),
+    entry("std.net.httpPost", "(url: String, body: String[, contentType: String]) -> String", &["network"], "Make an HTTP POST request, returning the response body"),
+    entry("std.net.download", "(url: String, path: String) -> Bool", &["network", "file:write"], "Download a file from a URL"),
+    entry("std.net.serve", "(port: Int, handler: (Request) -> Response[, options: Map]) -> Int", &["network"], "Run an HTTP server, calling the handler with each request"),
+    entry("std.net.tcpConnect", "(host: String, port: Int) -> Int", &["network"], "Open a TCP connection, returning a socket"),
+    entry("std.net.tcpListen", "(port: Int[, host: String]) -> Int", &["network"], "Listen for TCP connections, returning a listener"),
+    entry("std.net.tcpAccept", "(listener: Int) -> Int", &[], "Wait for a connection on a listener, returning a socket"),
+    entry("std.net.tcpSend", "(socket: Int, data: String) -> Int", &[], "Send a string on a TCP stream, returning the number of bytes sent"),
+    entry("std.net.tcpReceive", "(socket: Int[, maxBytes: Int]) -> String", &[], "Receive up to maxBytes (default 64 KB); \"\" once the peer has closed"),
+    entry("std.net.udpBind", "(port: Int[, host: String]) -> Int", &["network"], "Bind a UDP socket"),
+    entry("std.net.udpSend", "(socket: Int, host: String, port: Int, data: String) -> Int", &["network"], "Send a datagram, returning the number of bytes sent"),
+    entry("std.net.udpReceive", "(socket: Int[, maxBytes: Int]) -> Map", &[], "Receive a datagram as a map of `data`, `host` and `port`"),
+    entry("std.net.localPort", "(socket: Int) -> Int", &[], "Port a socket is bound to, useful after binding port 0"),
+    entry("std.net.close", "(socket: Int) -> Bool", &[], "Close any socket; returns false if it was already closed"),
+    entry("std.chan.make", "([capacity: Int]) -> Channel", &[], "Create a channel, unbuffered or holding up to capacity values"),
+    entry("std.chan.send", "(channel: Channel, value: T) -> Bool", &[], "Send a value on a channel"),
+    entry("std.chan.recv", "(channel: Channel) -> Result[T, String]", &[], "Receive a value from a channel, waiting for one"),
+    entry("std.chan.tryRecv", "(channel: Channel) -> Result[T, String]", &[], "Receive a value without waiting, or Oops(\"empty\")"),
+    entry("std.chan.recvTimeout", "(channel: Channel, timeout: Duration | Int) -> Result[T, String]", &[], "Receive a value, waiting at most a Duration or a number of milliseconds"),
+    entry("std.chan.close", "(channel: Channel) -> Bool", &[], "Close a channel"),
+    entry("std.chan.isClosed", "(channel: Channel) -> Bool", &[], "Check if a channel is closed"),
+    entry("std.string.length", "(string: String) -> Int", &[], "Get the length of a string (in characters, not bytes)"),
+    entry("std.string.upper", "(string: String) -> String", &[], "Convert string to uppercase"),
+    entry("std.string.lower", "(string: String) -> String", &[], "Convert string to lowercase"),
+    entry("std.string.toUpper", "(string: String) -> String", &[], "Convert string to uppercase"),
+    entry("std.string.toLower", "(string: String) -> String", &[], "Convert string to lowercase"),
+    entry("std.string.trim", "(string: String) -> String", &[], "Trim whitespace from both ends"),
+    entry("std.string.trimStart", "(string: String) -> String", &[], "Trim whitespace from start"),
+    entry("std.string.trimEnd", "(string: String) -> String", &[], "Trim whitespace from end"),
+    entry("std.string.contains", "(string: String, substring: String) -> Bool", &[], "Check if string contains a substring"),
+    entry("std.string.startsWith", "(string: String, prefix: String) -> Bool", &[], "Check if string starts with a prefix"),
+    entry("std.string.endsWith", "(string: String, suffix: String) -> Bool", &[], "Check if string ends with a suffix"),
+    entry("std.string.replace", "(string: String, from: String, to: String) -> String", &[], "Replace all occurrences of a pattern"),
+    entry("std.string.split", "(string: String, delimiter: String) -> [String]", &[], "Split string by delimiter"),
+    entry("std.string.join", "(strings: [String], delimiter: String) -> String", &[], "Join array of strings with delimiter"),
+    entry("std.string.substring", "(string: String, start: Int[, end: Int]) -> String", &[], "Get substring by start and optional end index"),
+    entry("std.string.indexOf", "(string: String, needle: String) -> Int", &[], "Find index of first occurrence of substring"),
+    entry("std.string.repeat", "(string: String, count: Int) -> String", &[], "Repeat string n times"),
+    entry("std.string.reverse", "(string: String) -> String", &[], "Reverse a string"),
+    entry("std.string.padStart", "(string: String, length: Int[, pad: String]) -> String", &[], "Pad string on the left to reach target length"),
+    entry("std.string.padEnd", "(string: String, length: Int[, pad: String]) -> String", &[], "Pad string on the right to reach target length"),
+    entry("std.string.chars", "(string: String) -> [String]", &[], "Split string into array of characters"),
+    entry("std.string.charAt", "(string: String, index: Int) -> String", &[], "Get the character at an index, as a string"),
+    entry("std.string.codePointAt", "(string: String, index: Int) -> Int", &[], "Get the Unicode code point of the character at an index"),
+    entry("std.string.graphemes", "(string: String) -> [String]", &[], "Split string into the characters a reader sees (grapheme clusters)"),
+    entry("std.string.isEmpty", "(string: String) -> Bool", &[], "Check if string is empty"),
+    entry("std.string.format", "(template: String, values...) -> String", &[], "Fill each `{}` in a template with the next value"),
+    entry("std.bytes.fromString", "(string: String) -> Bytes", &[], "Encode a string as UTF-8 bytes"),
+    entry("std.bytes.toString", "(bytes: Bytes) -> Result[String, String]", &[], "Decode UTF-8 bytes, returning Okay(text) or Oops if they are not UTF-8"),
+    entry("std.bytes.fromArray", "(array: [Int]) -> Bytes", &[], "Build bytes from an array of integers in 0..=255"),
+    entry("std.bytes.toArray", "(bytes: Bytes) -> [Int]", &[], "Convert bytes to an array of integers"),
+    entry("std.bytes.length", "(bytes: Bytes) -> Int", &[], "Get the number of bytes"),
+    entry("std.bytes.slice", "(bytes: Bytes, start: Int[, end: Int]) -> Bytes", &[], "Get bytes by start and optional end index"),
+    entry("std.bytes.concat", "(first: Bytes, second: Bytes) -> Bytes", &[], "Concatenate two byte arrays"),
+    entry("std.array.length", "(array: [T]) -> Int", &[], "Get the length of an array"),
+    entry("std.array.isEmpty", "(array: [T]) -> Bool", &[], "Check if array is empty"),
+    entry("std.array.first", "(array: [T]) -> Result[T, String]", &[], "Get first element of array"),
+    entry("std.array.last", "(array: [T]) -> Result[T, String]", &[], "Get last element of array"),
+    entry("std.array.push", "(array: [T], value: T) -> [T]", &[], "Push element to array (returns new array)"),
+    entry("std.array.pop", "(array: [T]) -> [T]", &[], "Pop last element from array (returns [new_array, popped_element])"),
+    entry("std.array.concat", "(first: [T], second: [T]) -> [T]", &[], "Concatenate two arrays"),
+    entry("std.array.reverse", "(array: [T]) -> [T]", &[], "Reverse an array"),
+    entry("std.array.slice", "(array: [T], start: Int[, end: Int]) -> [T]", &[], "Get slice of array"),
+    entry("std.array.contains", "(array: [T], value: T) -> Bool", &[], "Check if array contains a value"),
+    entry("std.array.indexOf", "(array: [T], value: T) -> Int", &[], "Find index of first occurrence of value"),
+    entry("std.array.repeat", "(value: T, count: Int) -> [T]", &[], "Create array of repeated value"),
+    entry("std.array.range", "(start: Int, end: Int[, step: Int]) -> [Int]", &[], "Create a range array from start to end (exclusive); `range(end)` starts at 0"),
+    entry("std.array.flatten", "(array: [[T]]) -> [T]", &[], "Flatten nested arrays one level"),
+    entry("std.array.unique", "(array: [T]) -> [T]", &[], "Remove duplicates from array (preserves first occurrence)"),
+    entry("std.array.zip", "(first: [T], second: [U]) -> [[T]]", &[], "Zip two arrays together"),
+    entry("std.array.map", "(array: [T], f: (T) -> U) -> [U]", &[], "Apply a function to every element"),
+    entry("std.array.filter", "(array: [T], predicate: (T) -> Bool) -> [T]", &[], "Keep the elements for which a predicate returns true"),
+    entry("std.array.reduce", "(array: [T], f: (U, T) -> U, initial: U) -> U", &[], "Fold an array into one value, starting from initial"),
+    entry("std.array.sort", "(array: [T][, compare: (T, T) -> Int]) -> [T]", &[], "Sort an array of numbers or strings, or with a comparator returning a negative, zero or positive Int"),
+    entry("std.map.new", "([pairs: [[V]]]) -> Map", &[], "Create a map, optionally from an array of [key, value] pairs"),
+    entry("std.map.get", "(map: Map, key: String) -> Result[V, String]", &[], "Look up a key, returning Okay(value) or Oops if it is missing"),
+    entry("std.map.set", "(map: Map, key: String, value: V) -> Map", &[], "Set a key (returns new map)"),
+    entry("std.map.remove", "(map: Map, key: String) -> Map", &[], "Remove a key if present (returns new map)"),
+    entry("std.map.has", "(map: Map, key: String) -> Bool", &[], "Check if a key is present"),
+    entry("std.map.size", "(map: Map) -> Int", &[], "Number of entries"),
+    entry("std.map.keys", "(map: Map) -> [String]", &[], "Sorted array of keys"),
+    entry("std.map.values", "(map: Map) -> [V]", &[], "Array of values, in key order"),
+    entry("std.map.entries", "(map: Map) -> [[V]]", &[], "Array of [key, value] pairs, in key order"),
+    entry("std.map.merge", "(first: Map, second: Map) -> Map", &[], "Combine two maps; keys in the second map win"),
+    entry("std.map.forEach", "(map: Map, f: (String, V) -> T) -> Unit", &[], "Call a function with each key and value, in key order"),
+    entry("std.regex.match", "(pattern: String, text: String) -> Result[Bool, String]", &[], "Check whether the pattern matches anywhere in the text"),
+    entry("std.regex.find", "(pattern: String, text: String) -> Result[String, String]", &[], "First match in the text, or Oops if there is none"),
+    entry("std.regex.findAll", "(pattern: String, text: String) -> Result[[String], String]", &[], "All non-overlapping matches in the text"),
+    entry("std.regex.replace", "(pattern: String, text: String, replacement: String) -> Result[String, String]", &[], "Replace every match; the replacement may refer to groups as `$1` or `${name}`"),
+    entry("std.regex.split", "(pattern: String, text: String) -> Result[[String], String]", &[], "Split the text on every match"),
+    entry("std.regex.captures", "(pattern: String, text: String) -> Result[Map, String]", &[], "Capture groups of the first match, keyed by number and by name"),
+    entry("std.csv.parse", "(csv: String[, headers: Bool]) -> [Row]", &[], "Parse CSV text into maps keyed by the header line, or arrays with `false`"),
+    entry("std.csv.stringify", "(rows: [Row][, columns: [String]]) -> String", &[], "Write an array of maps or arrays as CSV text"),
+    entry("std.csv.forEachRow", "(path: String, f: (Row) -> T[, headers: Bool]) -> Int", &["file:read"], "Call a function with each row of a CSV file, returning the row count"),
+];
+
+/// Every documented function: the builtins, then the `std` modules
+pub fn all() -> &'static [FunctionDoc] {
+    FUNCTIONS
+}
+
+/// The entry for a function, named in full (`std.math.sqrt`), without the
+/// `std.` prefix (`math.sqrt`), or as a builtin (`print`)
+pub fn lookup(name: &str) -> Option<&'static FunctionDoc> {
+    let name = name.trim();
+    FUNCTIONS
+        .iter()
+        .find(|doc| doc.name == name || doc.name.strip_prefix("std.") == Some(name))
+}
+
+/// The whole table as the Markdown reference, one section per module
+pub fn reference() -> String {
+    let mut out = String::from("# Standard Library Reference\n\n");
+    out.push_str(
+        "Every builtin and `std` function, with the superpowers it asks for. \
+         This page is generated by `woke doc`; the REPL's `:doc` command shows \
+         the same entries.\n",
+    );
+    let mut module = None;
+    for doc in FUNCTIONS {
+        if module != Some(doc.module()) {
+            module = Some(doc.module());
+            out.push_str(&format!("\n## {}\n", doc.module().unwrap_or("Builtins")));
+        }
+        out.push_str(&format!("\n### `{}`\n\n", doc.name));
+        out.push_str(&format!("```\n{}{}\n```\n\n", doc.name, doc.signature));
+        out.push_str(doc.summary);
+        out.push('\n');
+        if !doc.capabilities.is_empty() {
+            let names: Vec<String> = doc.capabilities.iter().map(|c| format!("`{}`", c)).collect();
+            out.push_str(&format!("\nSuperpowers: {}\n", names.join(", ")));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::StdlibRegistry;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_every_function_documented() {
+        let registry = StdlibRegistry::new();
+        let registered: HashSet<&str> = registry.list().into_iter().collect();
+        let documented: HashSet<&str> =
+            all().iter().filter(|doc| !doc.is_builtin()).map(|doc| doc.name).collect();
+        let mut missing: Vec<_> = registered.difference(&documented).collect();
+        missing.sort();
+        assert!(missing.is_empty(), "undocumented functions: {:?}", missing);
+        let mut unknown: Vec<_> = documented.difference(&registered).collect();
+        unknown.sort();
+        assert!(unknown.is_empty(), "documented but not registered: {:?}", unknown);
+
+        let names: Vec<&str> = all().iter().map(|doc| doc.name).collect();
+        let unique: HashSet<&str> = names.iter().copied().collect();
+        assert_eq!(names.len(), unique.len(), "names are unique");
+        for doc in all() {
+            assert!(doc.signature.starts_with('(') && doc.signature.contains(") -> "), "{}", doc.name);
+            for capability in doc.capabilities {
+                assert!(capability.parse::<crate::security::Capability>().is_ok(), "{}", capability);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let sqrt = lookup("std.math.sqrt").unwrap();
+        assert_eq!(sqrt.signature, "(n: Number) -> Float");
+        assert_eq!(lookup("math.sqrt"), Some(sqrt));
+        assert_eq!(lookup("print").map(|doc| doc.is_builtin()), Some(true));
+        assert_eq!(lookup("sqrt"), None);
+        assert_eq!(
+            lookup("std.io.readFile").unwrap().to_string(),
+            "std.io.readFile(path: String) -> String\n    \
+             Read entire file contents as a string\n    \
+             Superpowers: file:read"
+        );
+    }
+
+    #[test]
+    fn test_reference_is_up_to_date() {
+        let page = include_str!("../../docs/wiki/Reference/Standard-Library.md");
+        assert!(
+            page == reference(),
+            "docs/wiki/Reference/Standard-Library.md is stale; regenerate it with `woke doc`"
+        );
+    }
+}
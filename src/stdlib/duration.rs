@@ -0,0 +1,254 @@
+//! WokeLang Standard Library - Duration Functions
+//!
+//! Spans of time for `std.time`. A Duration comes from a measured literal
+//! such as `5 measured in seconds` or from `duration(5, "seconds")`. It can
+//! be added to or subtracted from a DateTime, and subtracting two DateTimes
+//! gives the Duration between them.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, expect_int, expect_string, StdlibError};
+use chrono::TimeDelta;
+
+/// Milliseconds in one of a time unit, or `None` if `unit` is not a time unit
+pub fn unit_millis(unit: &str) -> Option<i64> {
+    match unit {
+        "ms" | "millisecond" | "milliseconds" => Some(1),
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1_000),
+        "min" | "mins" | "minute" | "minutes" => Some(60_000),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(3_600_000),
+        "day" | "days" => Some(86_400_000),
+        "week" | "weeks" => Some(604_800_000),
+        _ => None,
+    }
+}
+
+fn out_of_range() -> String {
+    "Duration out of range".to_string()
+}
+
+/// Build a Duration from an amount of a time unit; Floats round to the
+/// nearest millisecond
+pub fn from_amount(amount: &Value, unit: &str) -> Result<TimeDelta, StdlibError> {
+    let per_unit = unit_millis(unit).ok_or_else(|| {
+        StdlibError::RuntimeError(format!(
+            "Unknown time unit: {} (use ms, seconds, minutes, hours, days or weeks)",
+            unit
+        ))
+    })?;
+    let millis = match amount {
+        Value::Int(n) => n.checked_mul(per_unit),
+        Value::Float(f) => {
+            let millis = (f * per_unit as f64).round();
+            (millis.is_finite() && millis.abs() < i64::MAX as f64).then_some(millis as i64)
+        }
+        other => {
+            return Err(StdlibError::TypeError {
+                expected: "Int or Float".to_string(),
+                got: format!("{:?}", other),
+            })
+        }
+    };
+    millis
+        .and_then(TimeDelta::try_milliseconds)
+        .ok_or_else(|| StdlibError::RuntimeError(out_of_range()))
+}
+
+/// Helper to extract a Duration
+pub fn expect_duration(value: &Value, _arg_name: &str) -> Result<TimeDelta, StdlibError> {
+    match value {
+        Value::Duration(d) => Ok(*d),
+        other => Err(StdlibError::TypeError {
+            expected: "Duration".to_string(),
+            got: format!("{:?}", other),
+        }),
+    }
+}
+
+/// Helper for timeouts and delays, which take a Duration or an Int of
+/// milliseconds
+pub fn expect_millis(value: &Value, arg_name: &str) -> Result<i64, StdlibError> {
+    match value {
+        Value::Duration(d) => Ok(d.num_milliseconds()),
+        Value::Int(ms) => Ok(*ms),
+        other => Err(StdlibError::TypeError {
+            expected: format!("Duration or Int milliseconds for {}", arg_name),
+            got: format!("{:?}", other),
+        }),
+    }
+}
+
+/// Show a Duration compactly, largest unit first: `1h 30m`, `-250ms`
+pub fn format_duration(d: &TimeDelta) -> String {
+    if d.is_zero() {
+        return "0s".to_string();
+    }
+    let sign = if *d < TimeDelta::zero() { "-" } else { "" };
+    let d = d.abs();
+    let subsec = d.subsec_nanos() as i64;
+    let parts = [
+        (d.num_days(), "d"),
+        (d.num_hours() % 24, "h"),
+        (d.num_minutes() % 60, "m"),
+        (d.num_seconds() % 60, "s"),
+        (subsec / 1_000_000, "ms"),
+        (subsec / 1_000 % 1_000, "us"),
+        (subsec % 1_000, "ns"),
+    ];
+    let text: Vec<String> = parts
+        .iter()
+        .filter(|(amount, _)| *amount != 0)
+        .map(|(amount, unit)| format!("{}{}", amount, unit))
+        .collect();
+    format!("{}{}", sign, text.join(" "))
+}
+
+/// `left + right` for Durations and DateTimes
+pub fn add(left: &Value, right: &Value) -> Result<Value, String> {
+    match (left, right) {
+        (Value::Duration(a), Value::Duration(b)) => a.checked_add(b).map(Value::Duration),
+        (Value::DateTime(t), Value::Duration(d)) | (Value::Duration(d), Value::DateTime(t)) => {
+            t.checked_add_signed(*d).map(Value::DateTime)
+        }
+        _ => return Err("Can only add a Duration to a Duration or a DateTime".to_string()),
+    }
+    .ok_or_else(out_of_range)
+}
+
+/// `left - right` for Durations and DateTimes; two DateTimes give the
+/// Duration between them
+pub fn subtract(left: &Value, right: &Value) -> Result<Value, String> {
+    match (left, right) {
+        (Value::Duration(a), Value::Duration(b)) => a.checked_sub(b).map(Value::Duration),
+        (Value::DateTime(t), Value::Duration(d)) => t.checked_sub_signed(*d).map(Value::DateTime),
+        (Value::DateTime(a), Value::DateTime(b)) => Some(Value::Duration(a.signed_duration_since(*b))),
+        _ => {
+            return Err(
+                "Can only subtract a Duration or DateTime from a DateTime, or a Duration from a Duration"
+                    .to_string(),
+            )
+        }
+    }
+    .ok_or_else(out_of_range)
+}
+
+/// `left * right` for a Duration scaled by an Int
+pub fn multiply(left: &Value, right: &Value) -> Result<Value, String> {
+    match (left, right) {
+        (Value::Duration(d), Value::Int(n)) | (Value::Int(n), Value::Duration(d)) => i32::try_from(*n)
+            .ok()
+            .and_then(|n| d.checked_mul(n))
+            .map(Value::Duration)
+            .ok_or_else(out_of_range),
+        _ => Err("Can only multiply a Duration by an Int".to_string()),
+    }
+}
+
+/// Build a Duration: duration(amount, unit)
+pub fn duration(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let unit = expect_string(&args[1], "unit")?;
+    from_amount(&args[0], &unit).map(Value::Duration)
+}
+
+/// Whole milliseconds in a Duration, rounded toward zero
+pub fn to_millis(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    Ok(Value::Int(expect_duration(&args[0], "duration")?.num_milliseconds()))
+}
+
+/// Seconds in a Duration, with the fraction
+pub fn to_seconds(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let d = expect_duration(&args[0], "duration")?;
+    Ok(Value::Float(d.num_seconds() as f64 + d.subsec_nanos() as f64 / 1e9))
+}
+
+/// Build a Duration from milliseconds: fromMillis(ms)
+pub fn from_millis(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    TimeDelta::try_milliseconds(expect_int(&args[0], "milliseconds")?)
+        .map(Value::Duration)
+        .ok_or_else(|| StdlibError::RuntimeError(out_of_range()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+    use chrono::DateTime;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    #[test]
+    fn test_duration_from_units() {
+        let mut caps = test_caps();
+        assert_eq!(
+            duration(&[Value::Int(90), s("minutes")], &mut caps).unwrap(),
+            Value::Duration(TimeDelta::minutes(90))
+        );
+        assert_eq!(
+            duration(&[Value::Float(1.5), s("s")], &mut caps).unwrap(),
+            Value::Duration(TimeDelta::milliseconds(1500))
+        );
+        assert!(duration(&[Value::Int(1), s("fortnights")], &mut caps).is_err());
+        assert!(duration(&[s("1"), s("days")], &mut caps).is_err());
+        assert!(duration(&[Value::Int(i64::MAX), s("weeks")], &mut caps).is_err());
+
+        let d = Value::Duration(TimeDelta::milliseconds(2250));
+        assert_eq!(to_millis(std::slice::from_ref(&d), &mut caps).unwrap(), Value::Int(2250));
+        assert_eq!(to_seconds(&[d], &mut caps).unwrap(), Value::Float(2.25));
+        assert_eq!(
+            from_millis(&[Value::Int(-5)], &mut caps).unwrap(),
+            Value::Duration(TimeDelta::milliseconds(-5))
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(&TimeDelta::zero()), "0s");
+        assert_eq!(format_duration(&TimeDelta::minutes(90)), "1h 30m");
+        assert_eq!(format_duration(&(TimeDelta::days(2) + TimeDelta::seconds(5))), "2d 5s");
+        assert_eq!(format_duration(&TimeDelta::milliseconds(-250)), "-250ms");
+        assert_eq!(format_duration(&TimeDelta::microseconds(1500)), "1ms 500us");
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let start = Value::DateTime(DateTime::parse_from_rfc3339("2024-03-01T12:00:00+01:00").unwrap());
+        let hour = Value::Duration(TimeDelta::hours(1));
+
+        let later = add(&start, &hour).unwrap();
+        assert_eq!(later.to_string(), "2024-03-01T13:00:00+01:00");
+        assert_eq!(add(&hour, &start).unwrap(), later);
+        assert_eq!(subtract(&later, &start).unwrap(), hour);
+        assert_eq!(subtract(&later, &hour).unwrap(), start);
+        assert_eq!(
+            subtract(&start, &later).unwrap(),
+            Value::Duration(TimeDelta::hours(-1))
+        );
+        assert_eq!(
+            add(&hour, &hour).unwrap(),
+            Value::Duration(TimeDelta::hours(2))
+        );
+        assert_eq!(
+            multiply(&Value::Int(3), &hour).unwrap(),
+            Value::Duration(TimeDelta::hours(3))
+        );
+        assert!(add(&start, &start).is_err());
+        assert!(subtract(&hour, &start).is_err());
+        assert!(add(&Value::Duration(TimeDelta::MAX), &hour).is_err());
+    }
+
+    #[test]
+    fn test_expect_millis() {
+        assert_eq!(expect_millis(&Value::Int(20), "delay").unwrap(), 20);
+        assert_eq!(
+            expect_millis(&Value::Duration(TimeDelta::seconds(2)), "delay").unwrap(),
+            2000
+        );
+        assert!(expect_millis(&Value::Float(1.0), "delay").is_err());
+    }
+}
@@ -0,0 +1,229 @@
+//! WokeLang Standard Library - Encoding Module
+//!
+//! Base64, hex and URL (percent) encoding of strings and bytes. Encoding
+//! always succeeds; decoding returns a Result, with malformed input or
+//! decoded data that is not valid UTF-8 reported as `Oops`. The `...Bytes`
+//! decoders return the raw bytes instead.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, check_arity_range, expect_bool, expect_data, expect_string, StdlibError};
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+
+/// Encoders write padding; decoders accept input with or without it
+const BASE64_CONFIG: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, BASE64_CONFIG);
+const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, BASE64_CONFIG);
+
+/// Wrap decoded bytes as Okay(text), or Oops if they are not UTF-8
+fn decoded(bytes: Vec<u8>) -> Value {
+    match String::from_utf8(bytes) {
+        Ok(text) => Value::Okay(Box::new(Value::String(text.into()))),
+        Err(_) => Value::oops("decoded data is not valid UTF-8"),
+    }
+}
+
+/// Pick the base64 alphabet from the optional `urlSafe` argument
+fn base64_engine(args: &[Value]) -> Result<&'static GeneralPurpose, StdlibError> {
+    match args.get(1) {
+        Some(url_safe) if expect_bool(url_safe, "urlSafe")? => Ok(&URL_SAFE),
+        _ => Ok(&STANDARD),
+    }
+}
+
+/// Base64-encode a string or bytes; pass `true` to use the URL-safe alphabet
+pub fn base64_encode(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let data = expect_data(&args[0], "data")?;
+    Ok(Value::String(base64_engine(args)?.encode(data).into()))
+}
+
+fn base64_bytes(args: &[Value]) -> Result<Result<Vec<u8>, String>, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let text = expect_string(&args[0], "text")?;
+    Ok(base64_engine(args)?
+        .decode(text.trim())
+        .map_err(|e| format!("invalid base64: {}", e)))
+}
+
+/// Decode base64 text; pass `true` to use the URL-safe alphabet
+pub fn base64_decode(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    Ok(match base64_bytes(args)? {
+        Ok(bytes) => decoded(bytes),
+        Err(e) => Value::oops(e),
+    })
+}
+
+/// Decode base64 text to bytes; pass `true` to use the URL-safe alphabet
+pub fn base64_decode_bytes(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    Ok(match base64_bytes(args)? {
+        Ok(bytes) => Value::Okay(Box::new(Value::Bytes(bytes))),
+        Err(e) => Value::oops(e),
+    })
+}
+
+/// Hex-encode a string or bytes as lowercase digits
+pub fn hex_encode(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let data = expect_data(&args[0], "data")?;
+    Ok(Value::String(
+        data.iter().map(|b| format!("{:02x}", b)).collect::<String>().into(),
+    ))
+}
+
+fn hex_bytes(args: &[Value]) -> Result<Result<Vec<u8>, String>, StdlibError> {
+    check_arity(args, 1)?;
+    let text = expect_string(&args[0], "text")?;
+    let digits = text.trim().as_bytes();
+    if digits.len() % 2 != 0 {
+        return Ok(Err("invalid hex: odd number of digits".to_string()));
+    }
+
+    let mut bytes = Vec::with_capacity(digits.len() / 2);
+    for (i, pair) in digits.chunks(2).enumerate() {
+        match (hex_value(pair[0]), hex_value(pair[1])) {
+            (Some(high), Some(low)) => bytes.push(high << 4 | low),
+            _ => return Ok(Err(format!("invalid hex: bad digit at position {}", i * 2))),
+        }
+    }
+    Ok(Ok(bytes))
+}
+
+/// Decode hex digits (either case)
+pub fn hex_decode(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    Ok(match hex_bytes(args)? {
+        Ok(bytes) => decoded(bytes),
+        Err(e) => Value::oops(e),
+    })
+}
+
+/// Decode hex digits (either case) to bytes
+pub fn hex_decode_bytes(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    Ok(match hex_bytes(args)? {
+        Ok(bytes) => Value::Okay(Box::new(Value::Bytes(bytes))),
+        Err(e) => Value::oops(e),
+    })
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Percent-encode everything except unreserved characters (RFC 3986)
+pub fn url_encode(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let text = expect_string(&args[0], "text")?;
+    let mut encoded = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    Ok(Value::String(encoded.into()))
+}
+
+/// Decode `%XX` escapes; `+` is left as is
+pub fn url_decode(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let text = expect_string(&args[0], "text")?;
+    let input = text.as_bytes();
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' {
+            let escape = input.get(i + 1..i + 3);
+            match escape.map(|e| (hex_value(e[0]), hex_value(e[1]))) {
+                Some((Some(high), Some(low))) => bytes.push(high << 4 | low),
+                _ => {
+                    return Ok(Value::oops(format!(
+                        "invalid URL encoding: bad escape at position {}",
+                        i
+                    )))
+                }
+            }
+            i += 3;
+        } else {
+            bytes.push(input[i]);
+            i += 1;
+        }
+    }
+    Ok(decoded(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn okay(value: &str) -> Value {
+        Value::Okay(Box::new(s(value)))
+    }
+
+    #[test]
+    fn test_base64() {
+        let mut caps = test_caps();
+        assert_eq!(base64_encode(&[s("woke?>")], &mut caps).unwrap(), s("d29rZT8+"));
+        assert_eq!(
+            base64_encode(&[s("woke?>"), Value::Bool(true)], &mut caps).unwrap(),
+            s("d29rZT8-")
+        );
+        assert_eq!(base64_encode(&[s("hi")], &mut caps).unwrap(), s("aGk="));
+
+        assert_eq!(base64_decode(&[s("aGk=")], &mut caps).unwrap(), okay("hi"));
+        assert_eq!(base64_decode(&[s("aGk")], &mut caps).unwrap(), okay("hi"));
+        assert!(base64_decode(&[s("not base64!")], &mut caps).unwrap().is_oops());
+        // Valid base64 of bytes that are not UTF-8
+        assert!(base64_decode(&[s("/w==")], &mut caps).unwrap().is_oops());
+    }
+
+    #[test]
+    fn test_hex() {
+        let mut caps = test_caps();
+        assert_eq!(hex_encode(&[s("Hi!")], &mut caps).unwrap(), s("486921"));
+        assert_eq!(hex_decode(&[s("486921")], &mut caps).unwrap(), okay("Hi!"));
+        assert_eq!(hex_decode(&[s("C3A9")], &mut caps).unwrap(), okay("é"));
+        assert!(hex_decode(&[s("486")], &mut caps).unwrap().is_oops());
+        assert!(hex_decode(&[s("zz")], &mut caps).unwrap().is_oops());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut caps = test_caps();
+        let raw = Value::Bytes(vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(hex_encode(std::slice::from_ref(&raw), &mut caps).unwrap(), s("deadbeef"));
+        assert_eq!(base64_encode(std::slice::from_ref(&raw), &mut caps).unwrap(), s("3q2+7w=="));
+
+        let okay_raw = Value::Okay(Box::new(raw));
+        assert_eq!(hex_decode_bytes(&[s("DEADbeef")], &mut caps).unwrap(), okay_raw);
+        assert_eq!(base64_decode_bytes(&[s("3q2+7w")], &mut caps).unwrap(), okay_raw);
+        // Bytes that are not UTF-8 only fail the text decoders
+        assert!(hex_decode(&[s("ff")], &mut caps).unwrap().is_oops());
+        assert!(hex_decode_bytes(&[s("f")], &mut caps).unwrap().is_oops());
+    }
+
+    #[test]
+    fn test_url() {
+        let mut caps = test_caps();
+        assert_eq!(
+            url_encode(&[s("a b&c=d/é~")], &mut caps).unwrap(),
+            s("a%20b%26c%3Dd%2F%C3%A9~")
+        );
+        assert_eq!(
+            url_decode(&[s("a%20b%26c%3dd%2F%C3%A9~+")], &mut caps).unwrap(),
+            okay("a b&c=d/é~+")
+        );
+        assert!(url_decode(&[s("100%")], &mut caps).unwrap().is_oops());
+        assert!(url_decode(&[s("%zz")], &mut caps).unwrap().is_oops());
+        assert!(url_encode(&[Value::Int(1)], &mut caps).is_err());
+    }
+}
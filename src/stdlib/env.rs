@@ -0,0 +1,164 @@
+//! WokeLang Standard Library - Environment Module
+//!
+//! Environment variables and script arguments. Reading or changing a
+//! variable needs the `Environment` capability for that variable; listing
+//! every variable needs it for all of them.
+//!
+//! Values read here are treated as secrets: logs, traces and error messages
+//! mask them (see [`crate::security::secrets`]).
+
+use crate::interpreter::{Text, Value};
+use crate::security::{Capability, CapabilityRegistry};
+use super::{check_arity, expect_string, StdlibError};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Arguments given to the running script, after the script path
+static SCRIPT_ARGS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Set the arguments returned by `std.env.args`
+pub fn set_script_args(args: Vec<String>) {
+    *SCRIPT_ARGS.write().unwrap_or_else(|e| e.into_inner()) = args;
+}
+
+/// Helper to require environment capability, for one variable or all (`None`)
+fn require_env(var: Option<&str>, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    let cap = Capability::Environment(var.map(str::to_string));
+    if caps.request_current(&cap).is_err() {
+        Err(StdlibError::PermissionDenied(format!(
+            "Environment access denied: {}",
+            var.unwrap_or("*")
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Read a variable name, rejecting names the OS cannot store
+fn expect_var_name(value: &Value) -> Result<String, StdlibError> {
+    let name = expect_string(value, "name")?;
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return Err(StdlibError::RuntimeError(format!(
+            "Invalid environment variable name: {:?}",
+            name
+        )));
+    }
+    Ok(name)
+}
+
+/// Read a variable, returning Okay(value) or Oops if it is not set
+pub fn get(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let name = expect_var_name(&args[0])?;
+    require_env(Some(&name), caps)?;
+
+    match std::env::var(&name) {
+        Ok(value) => Ok(Value::Okay(Box::new(Value::String(Text::secret(value))))),
+        Err(std::env::VarError::NotPresent) => Ok(Value::oops(format!("not set: {}", name))),
+        Err(std::env::VarError::NotUnicode(_)) => {
+            Ok(Value::oops(format!("not valid Unicode: {}", name)))
+        }
+    }
+}
+
+/// Set a variable for this process and the processes it starts
+pub fn set(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let name = expect_var_name(&args[0])?;
+    let value = expect_string(&args[1], "value")?;
+    if value.contains('\0') {
+        return Err(StdlibError::RuntimeError(
+            "Environment variable value contains a null byte".to_string(),
+        ));
+    }
+    require_env(Some(&name), caps)?;
+
+    std::env::set_var(&name, value);
+    Ok(Value::Unit)
+}
+
+/// All variables with Unicode names and values, as a map
+pub fn all(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 0)?;
+    require_env(None, caps)?;
+
+    let vars: HashMap<String, Value> = std::env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, Value::String(v.into_string().ok()?.into()))))
+        .collect();
+    Ok(Value::Record(vars).into_secret())
+}
+
+/// Arguments given to the script; these need no consent
+pub fn args(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 0)?;
+    let script_args = SCRIPT_ARGS.read().unwrap_or_else(|e| e.into_inner());
+    Ok(Value::Array(
+        script_args.iter().cloned().map(|s| Value::String(s.into())).collect::<Vec<_>>().into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn denying_caps() -> CapabilityRegistry {
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+        caps
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let mut caps = test_caps();
+        set(&[s("WOKELANG_TEST_ENV"), s("kind")], &mut caps).unwrap();
+        assert_eq!(
+            get(&[s("WOKELANG_TEST_ENV")], &mut caps).unwrap(),
+            Value::Okay(Box::new(s("kind")))
+        );
+        assert!(get(&[s("WOKELANG_TEST_UNSET")], &mut caps).unwrap().is_oops());
+
+        let Value::Record(vars) = all(&[], &mut caps).unwrap() else {
+            panic!("expected a map");
+        };
+        assert_eq!(vars.get("WOKELANG_TEST_ENV"), Some(&s("kind")));
+        // What was read is secret
+        assert!(vars.values().all(Value::is_secret));
+        assert!(get(&[s("WOKELANG_TEST_ENV")], &mut caps).unwrap().is_secret());
+
+        assert!(set(&[s("BAD=NAME"), s("x")], &mut caps).is_err());
+        assert!(get(&[s("")], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_consent_is_per_variable() {
+        let mut caps = denying_caps();
+        assert!(matches!(
+            get(&[s("HOME")], &mut caps),
+            Err(StdlibError::PermissionDenied(msg)) if msg.contains("HOME")
+        ));
+        assert!(set(&[s("WOKELANG_TEST_DENIED"), s("x")], &mut caps).is_err());
+        assert!(std::env::var("WOKELANG_TEST_DENIED").is_err());
+
+        // A grant for one variable does not cover the others
+        caps.grant("stdlib", Capability::Environment(Some("HOME".to_string())), "test");
+        assert!(get(&[s("HOME")], &mut caps).is_ok());
+        assert!(get(&[s("PATH")], &mut caps).is_err());
+        assert!(all(&[], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_args() {
+        let mut caps = denying_caps();
+        set_script_args(vec!["--name".to_string(), "Ada".to_string()]);
+        assert_eq!(
+            args(&[], &mut caps).unwrap(),
+            Value::Array(vec![s("--name"), s("Ada")].into())
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//! WokeLang Standard Library - Finding Files
+//!
+//! `std.io.glob` and `std.io.walk`. Listing a directory needs `FileRead`
+//! for that directory, so each directory is checked as the search reaches
+//! it. The starting directory must be allowed, which covers everything
+//! below it; a subdirectory that is not allowed is skipped, along with
+//! everything below it.
+//!
+//! Patterns use `/` between parts. `*` matches any run of characters
+//! within one part, `?` one character, `[abc]` or `[a-z]` one character
+//! from a set (`[!abc]` for the rest), and a part that is exactly `**`
+//! matches any number of directories. Wildcards do not match a leading
+//! `.`, so hidden files only match patterns that spell out the dot.
+
+use crate::interpreter::Value;
+use crate::security::{Capability, CapabilityRegistry};
+use super::io::validate_path;
+use super::path::normalize_path;
+use super::{check_arity, expect_string, StdlibError};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Ask to list a directory
+fn may_list(dir: &Path, caps: &mut CapabilityRegistry) -> bool {
+    let cap = Capability::FileRead(Some(normalize_path(dir)));
+    caps.request_current(&cap).is_ok()
+}
+
+fn require_list(dir: &Path, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    if may_list(dir, caps) {
+        Ok(())
+    } else {
+        Err(StdlibError::PermissionDenied(format!(
+            "File read access denied: {}",
+            dir.display()
+        )))
+    }
+}
+
+/// A path below `dir`, without a `./` in front when `dir` is the current
+/// directory
+fn child(dir: &Path, name: &str) -> PathBuf {
+    if dir == Path::new(".") {
+        PathBuf::from(name)
+    } else {
+        dir.join(name)
+    }
+}
+
+/// Names in a directory, sorted so results come out in a stable order
+pub(super) fn entry_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// A real directory, not a link to one, so searches cannot loop
+fn is_real_dir(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.is_dir())
+}
+
+fn has_wildcard(part: &str) -> bool {
+    part.contains(['*', '?', '['])
+}
+
+/// Match one character against a `[...]` set starting after the `[`;
+/// returns whether it matched and the pattern left after the `]`, or None
+/// if the set is not closed
+fn match_set(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match pattern.first() {
+        Some('!') | Some('^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match rest {
+            [] => return None,
+            [']', tail @ ..] if !first => return Some((matched != negated, tail)),
+            [lo, '-', hi, tail @ ..] if *hi != ']' => {
+                matched |= (*lo..=*hi).contains(&c);
+                rest = tail;
+            }
+            [single, tail @ ..] => {
+                matched |= *single == c;
+                rest = tail;
+            }
+        }
+        first = false;
+    }
+}
+
+fn match_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| match_chars(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && match_chars(&pattern[1..], &name[1..]),
+        Some('[') => match (name.first(), match_set(&pattern[1..], name.first().copied().unwrap_or('\0'))) {
+            (Some(_), Some((true, rest))) => match_chars(rest, &name[1..]),
+            (Some(_), Some((false, _))) => false,
+            // An unclosed `[` is an ordinary character
+            (Some('['), None) => match_chars(&pattern[1..], &name[1..]),
+            _ => false,
+        },
+        Some(c) => name.first() == Some(c) && match_chars(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Does one part of a pattern match a file name?
+pub fn matches_part(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_chars(&pattern, &name)
+}
+
+/// Collect the paths under `dir` matching the remaining pattern parts
+fn search(
+    dir: &Path,
+    parts: &[&str],
+    found: &mut BTreeSet<PathBuf>,
+    caps: &mut CapabilityRegistry,
+) {
+    let Some((part, rest)) = parts.split_first() else {
+        found.insert(dir.to_path_buf());
+        return;
+    };
+    if *part == "**" {
+        // Zero directories, then one more level with `**` still in place
+        search(dir, rest, found, caps);
+        if !may_list(dir, caps) {
+            return;
+        }
+        for name in entry_names(dir) {
+            let path = child(dir, &name);
+            if !name.starts_with('.') && is_real_dir(&path) {
+                search(&path, parts, found, caps);
+            }
+        }
+    } else if !has_wildcard(part) {
+        let path = child(dir, part);
+        if rest.is_empty() {
+            if path.exists() {
+                found.insert(path);
+            }
+        } else if path.is_dir() {
+            search(&path, rest, found, caps);
+        }
+    } else if may_list(dir, caps) {
+        for name in entry_names(dir) {
+            if matches_part(part, &name) {
+                let path = child(dir, &name);
+                if rest.is_empty() || path.is_dir() {
+                    search(&path, rest, found, caps);
+                }
+            }
+        }
+    }
+}
+
+/// Find paths matching a pattern: glob("src/**/*.woke") -> [String]
+///
+/// The parts before the first wildcard name the directory the search
+/// starts from, which must be readable. Paths come back sorted.
+pub fn glob(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let pattern = expect_string(&args[0], "pattern")?;
+    validate_path(&pattern)?;
+
+    let (mut base, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (PathBuf::from("/"), rest),
+        None => (PathBuf::from("."), pattern.as_str()),
+    };
+    let parts: Vec<&str> = rest.split('/').filter(|p| !p.is_empty() && *p != ".").collect();
+    let literal = parts.iter().take_while(|p| !has_wildcard(p) && **p != "**").count();
+    // Keep the last part for matching, so a plain path still checks the
+    // directory it is in
+    let literal = literal.min(parts.len().saturating_sub(1));
+    for part in &parts[..literal] {
+        base = child(&base, part);
+    }
+
+    require_list(&base, caps)?;
+    let mut found = BTreeSet::new();
+    search(&base, &parts[literal..], &mut found, caps);
+    Ok(Value::Array(
+        found
+            .into_iter()
+            .map(|p| Value::String(p.to_string_lossy().into_owned().into()))
+            .collect::<Vec<_>>().into(),
+    ))
+}
+
+/// Metadata map for one entry found by `walk`
+fn entry_info(path: &Path, name: &str, depth: usize) -> Option<Value> {
+    let meta = fs::symlink_metadata(path).ok()?;
+    let modified = meta
+        .modified()
+        .ok()
+        .map(|time| Value::DateTime(DateTime::<Utc>::from(time).fixed_offset()))
+        .unwrap_or(Value::Unit);
+    Some(Value::Record(HashMap::from([
+        ("path".to_string(), Value::String(path.to_string_lossy().into_owned().into())),
+        ("name".to_string(), Value::String(name.to_string().into())),
+        ("isDir".to_string(), Value::Bool(meta.is_dir())),
+        ("isSymlink".to_string(), Value::Bool(meta.file_type().is_symlink())),
+        ("size".to_string(), Value::Int(meta.len() as i64)),
+        ("depth".to_string(), Value::Int(depth as i64)),
+        ("modified".to_string(), modified),
+    ])))
+}
+
+fn walk_dir(dir: &Path, depth: usize, entries: &mut Vec<Value>, caps: &mut CapabilityRegistry) {
+    for name in entry_names(dir) {
+        let path = child(dir, &name);
+        let Some(info) = entry_info(&path, &name, depth) else {
+            continue;
+        };
+        entries.push(info);
+        if is_real_dir(&path) && may_list(&path, caps) {
+            walk_dir(&path, depth + 1, entries, caps);
+        }
+    }
+}
+
+/// Everything below a directory, depth first: walk(path) -> [Map]
+///
+/// Each map has `path`, `name`, `isDir`, `isSymlink`, `size`, `depth`
+/// (1 for the directory's own entries) and `modified` (a DateTime).
+/// Links to directories are listed but not followed.
+pub fn walk(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = expect_string(&args[0], "path")?;
+    let root = validate_path(&path)?;
+    require_list(&root, caps)?;
+    if !root.is_dir() {
+        return Err(StdlibError::IoError(format!("Not a directory: {}", path)));
+    }
+
+    let mut entries = Vec::new();
+    walk_dir(&root, 1, &mut entries, caps);
+    Ok(Value::Array(entries.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+    use std::env;
+
+    /// A small tree: a.woke, notes.txt, .hidden.woke, src/b.woke,
+    /// src/deep/c.woke, src/deep/d.txt
+    fn make_tree(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("wokelang_test_glob_{}", name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src/deep")).unwrap();
+        for file in ["a.woke", "notes.txt", ".hidden.woke", "src/b.woke", "src/deep/c.woke", "src/deep/d.txt"] {
+            fs::write(root.join(file), "x").unwrap();
+        }
+        root
+    }
+
+    fn strings(value: Value) -> Vec<String> {
+        let Value::Array(items) = value else {
+            panic!("expected an array");
+        };
+        items.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_matches_part() {
+        assert!(matches_part("*.woke", "main.woke"));
+        assert!(!matches_part("*.woke", "main.wokex"));
+        assert!(matches_part("ma?n.*", "main.rs"));
+        assert!(matches_part("[a-c]*", "beta"));
+        assert!(!matches_part("[!a-c]*", "beta"));
+        assert!(matches_part("[", "["));
+        assert!(!matches_part("*", ".hidden"));
+        assert!(matches_part(".*", ".hidden"));
+    }
+
+    #[test]
+    fn test_glob() {
+        let root = make_tree("patterns");
+        let base = root.to_string_lossy().to_string();
+        let mut caps = CapabilityRegistry::permissive();
+        let find = |pattern: &str, caps: &mut CapabilityRegistry| {
+            let found = strings(glob(&[s(&format!("{}/{}", base, pattern))], caps).unwrap());
+            found
+                .iter()
+                .map(|p| p.strip_prefix(&format!("{}/", base)).unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(find("*.woke", &mut caps), ["a.woke"]);
+        assert_eq!(find("**/*.woke", &mut caps), ["a.woke", "src/b.woke", "src/deep/c.woke"]);
+        assert_eq!(find("src/**/*.txt", &mut caps), ["src/deep/d.txt"]);
+        assert_eq!(find("*/deep", &mut caps), ["src/deep"]);
+        assert_eq!(find("notes.txt", &mut caps), ["notes.txt"]);
+        assert!(find("missing/*.woke", &mut caps).is_empty());
+        assert!(glob(&[s("../*.woke")], &mut caps).is_err());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_glob_checks_each_directory() {
+        let root = make_tree("consent");
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+        let pattern = s(&format!("{}/**/*.woke", root.display()));
+
+        // The starting directory must be allowed
+        assert!(matches!(
+            glob(std::slice::from_ref(&pattern), &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+
+        // Allowing the top directory covers everything below it
+        caps.grant("stdlib", Capability::FileRead(Some(normalize_path(&root))), "test");
+        let found = strings(glob(&[pattern], &mut caps).unwrap());
+        assert_eq!(found.len(), 3);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_walk() {
+        let root = make_tree("walk");
+        let mut caps = CapabilityRegistry::permissive();
+        let Value::Array(entries) = walk(&[s(&root.to_string_lossy())], &mut caps).unwrap() else {
+            panic!("expected an array");
+        };
+
+        let names: Vec<String> = entries
+            .iter()
+            .map(|e| match e {
+                Value::Record(info) => format!("{}:{}", info["depth"], info["name"]),
+                _ => panic!("expected a map"),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "1:.hidden.woke", "1:a.woke", "1:notes.txt", "1:src", "2:b.woke", "2:deep",
+                "3:c.woke", "3:d.txt"
+            ]
+        );
+        let Value::Record(src) = &entries[3] else { unreachable!() };
+        assert_eq!(src["isDir"], Value::Bool(true));
+        assert!(matches!(src["modified"], Value::DateTime(_)));
+        let Value::Record(file) = &entries[1] else { unreachable!() };
+        assert_eq!(file["size"], Value::Int(1));
+
+        assert!(walk(&[s(&root.join("a.woke").to_string_lossy())], &mut caps).is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+}
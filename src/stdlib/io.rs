@@ -1,20 +1,42 @@
 //! WokeLang Standard Library - I/O Module
 //!
 //! File I/O operations that require explicit consent through capabilities.
+//!
+//! `readFile` loads a whole file, up to 10 MB. Larger files are read a line
+//! at a time with `readLines` cursors or `forEachLine`, and written a piece
+//! at a time with `streamWrite`.
+//!
+//! The `ask` builtins put a question to whoever is running the program.
+//! They need no superpower: the user sees the question and chooses what to
+//! answer.
 
 use crate::interpreter::Value;
-use crate::security::{Capability, CapabilityRegistry};
-use super::{check_arity, check_arity_range, expect_string, StdlibError};
-use std::fs;
-use std::io::{self, BufRead, Write};
+use crate::security::{Capability, CapabilityRegistry, Quota};
+use serde::{Deserialize, Serialize};
+use super::path::normalize_path;
+use super::{
+    check_arity, check_arity_range, expect_bytes, expect_int, expect_string, quota_exceeded, Invoke,
+    StdlibError,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 
+thread_local! {
+    /// Open `readLines` cursors by handle, with the last handle given out
+    static CURSORS: RefCell<(i64, HashMap<i64, BufReader<File>>)> = RefCell::new((0, HashMap::new()));
+    /// Files kept open by `streamWrite`, by normalized path
+    static STREAMS: RefCell<HashMap<PathBuf, File>> = RefCell::new(HashMap::new());
+}
+
 /// Maximum file size for read operations (10 MB)
 const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
 
 /// Validate a path to prevent path traversal attacks
 /// Rejects paths containing `..` components
-fn validate_path(path: &str) -> Result<PathBuf, StdlibError> {
+pub(super) fn validate_path(path: &str) -> Result<PathBuf, StdlibError> {
     let path_buf = PathBuf::from(path);
 
     // Check for path traversal attempts
@@ -54,10 +76,10 @@ fn check_file_size(path: &Path) -> Result<(), StdlibError> {
     }
 }
 
-/// Helper to require file read capability
-fn require_read(path: &str, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
-    let cap = Capability::FileRead(Some(PathBuf::from(path)));
-    if caps.request("stdlib", &cap).is_err() {
+/// Helper to require file read capability, for the normalized path
+pub(super) fn require_read(path: &str, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    let cap = Capability::FileRead(Some(normalize_path(Path::new(path))));
+    if caps.request_current(&cap).is_err() {
         Err(StdlibError::PermissionDenied(format!(
             "File read access denied: {}",
             path
@@ -67,10 +89,10 @@ fn require_read(path: &str, caps: &mut CapabilityRegistry) -> Result<(), StdlibE
     }
 }
 
-/// Helper to require file write capability
-fn require_write(path: &str, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
-    let cap = Capability::FileWrite(Some(PathBuf::from(path)));
-    if caps.request("stdlib", &cap).is_err() {
+/// Helper to require file write capability, for the normalized path
+pub(super) fn require_write(path: &str, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    let cap = Capability::FileWrite(Some(normalize_path(Path::new(path))));
+    if caps.request_current(&cap).is_err() {
         Err(StdlibError::PermissionDenied(format!(
             "File write access denied: {}",
             path
@@ -94,7 +116,7 @@ pub fn read_file(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value,
     check_file_size(&validated_path)?;
 
     match fs::read_to_string(&validated_path) {
-        Ok(contents) => Ok(Value::String(contents)),
+        Ok(contents) => Ok(Value::String(contents.into())),
         Err(e) => Err(StdlibError::IoError(e.to_string())),
     }
 }
@@ -109,6 +131,48 @@ pub fn write_file(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value
     let validated_path = validate_path(&path)?;
 
     require_write(&path, caps)?;
+    if let Some(oops) = quota_exceeded(caps, Quota::WriteBytes, contents.len() as u64) {
+        return Ok(oops);
+    }
+
+    match fs::write(&validated_path, &contents) {
+        Ok(()) => Ok(Value::Bool(true)),
+        Err(e) => Err(StdlibError::IoError(e.to_string())),
+    }
+}
+
+/// Read entire file contents as bytes
+pub fn read_bytes(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = expect_string(&args[0], "path")?;
+
+    // Validate path for security
+    let validated_path = validate_path(&path)?;
+
+    require_read(&path, caps)?;
+
+    // Check file size to prevent memory exhaustion
+    check_file_size(&validated_path)?;
+
+    match fs::read(&validated_path) {
+        Ok(contents) => Ok(Value::Bytes(contents)),
+        Err(e) => Err(StdlibError::IoError(e.to_string())),
+    }
+}
+
+/// Write bytes to a file
+pub fn write_bytes(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let path = expect_string(&args[0], "path")?;
+    let contents = expect_bytes(&args[1], "contents")?;
+
+    // Validate path for security
+    let validated_path = validate_path(&path)?;
+
+    require_write(&path, caps)?;
+    if let Some(oops) = quota_exceeded(caps, Quota::WriteBytes, contents.len() as u64) {
+        return Ok(oops);
+    }
 
     match fs::write(&validated_path, &contents) {
         Ok(()) => Ok(Value::Bool(true)),
@@ -126,8 +190,10 @@ pub fn append_file(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Valu
     let validated_path = validate_path(&path)?;
 
     require_write(&path, caps)?;
+    if let Some(oops) = quota_exceeded(caps, Quota::WriteBytes, contents.len() as u64) {
+        return Ok(oops);
+    }
 
-    use std::fs::OpenOptions;
     match OpenOptions::new().create(true).append(true).open(&validated_path) {
         Ok(mut file) => match file.write_all(contents.as_bytes()) {
             Ok(()) => Ok(Value::Bool(true)),
@@ -137,6 +203,151 @@ pub fn append_file(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Valu
     }
 }
 
+/// Read the next line from a reader, without its line ending
+fn take_line(reader: &mut (impl BufRead + ?Sized)) -> Result<Option<String>, StdlibError> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) => Ok(None),
+        Ok(_) => {
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            Ok(Some(line))
+        }
+        Err(e) => Err(StdlibError::IoError(e.to_string())),
+    }
+}
+
+/// Open a file for reading line by line: readLines(path) -> cursor
+///
+/// The cursor is an Int handle for `nextLine`. Only one line is held in
+/// memory at a time, so there is no size limit.
+pub fn read_lines(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = expect_string(&args[0], "path")?;
+    let validated_path = validate_path(&path)?;
+    require_read(&path, caps)?;
+
+    let file = File::open(&validated_path).map_err(|e| StdlibError::IoError(e.to_string()))?;
+    Ok(CURSORS.with(|cursors| {
+        let (next, table) = &mut *cursors.borrow_mut();
+        *next += 1;
+        table.insert(*next, BufReader::new(file));
+        Value::Int(*next)
+    }))
+}
+
+/// Take the next line from a cursor: nextLine(cursor) -> Result
+///
+/// Gives `Oops("end of file")` once the lines run out, and closes the
+/// cursor.
+pub fn next_line(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let handle = expect_int(&args[0], "cursor")?;
+    CURSORS.with(|cursors| {
+        let table = &mut cursors.borrow_mut().1;
+        let reader = table.get_mut(&handle).ok_or_else(|| {
+            StdlibError::RuntimeError(format!("Unknown or closed line cursor: {}", handle))
+        })?;
+        match take_line(reader)? {
+            Some(line) => Ok(Value::Okay(Box::new(Value::String(line.into())))),
+            None => {
+                table.remove(&handle);
+                Ok(Value::oops("end of file"))
+            }
+        }
+    })
+}
+
+/// Close a cursor before its end: closeLines(cursor) -> Bool
+///
+/// Returns false if the cursor was already closed.
+pub fn close_lines(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let handle = expect_int(&args[0], "cursor")?;
+    let removed = CURSORS.with(|cursors| cursors.borrow_mut().1.remove(&handle));
+    Ok(Value::Bool(removed.is_some()))
+}
+
+/// Consent gate for `forEachLine`: reading the file needs `FileRead`
+pub fn require_lines(args: &[Value], caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    check_arity(args, 2)?;
+    let path = expect_string(&args[0], "path")?;
+    validate_path(&path)?;
+    require_read(&path, caps)
+}
+
+/// Call a function with each line of a file, returning the line count
+///
+/// The caller must hold the `FileRead` capability (see [`require_lines`]).
+pub fn for_each_line(args: &[Value], invoke: &mut Invoke) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let path = validate_path(&expect_string(&args[0], "path")?)?;
+    let file = File::open(&path).map_err(|e| StdlibError::IoError(e.to_string()))?;
+    let mut reader = BufReader::new(file);
+
+    let mut count = 0;
+    while let Some(line) = take_line(&mut reader)? {
+        invoke(&args[1], vec![Value::String(line.into())])?;
+        count += 1;
+    }
+    Ok(Value::Int(count))
+}
+
+/// Append text to a file that stays open between calls:
+/// streamWrite(path, text)
+///
+/// Each call writes straight through to the file, so nothing is lost if
+/// the program stops without calling `streamClose`.
+pub fn stream_write(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let path = expect_string(&args[0], "path")?;
+    let contents = expect_string(&args[1], "contents")?;
+    let validated_path = validate_path(&path)?;
+    require_write(&path, caps)?;
+    if let Some(oops) = quota_exceeded(caps, Quota::WriteBytes, contents.len() as u64) {
+        return Ok(oops);
+    }
+
+    let key = normalize_path(&validated_path);
+    STREAMS.with(|streams| {
+        let mut streams = streams.borrow_mut();
+        let file = match streams.entry(key) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => entry.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&validated_path)
+                    .map_err(|e| StdlibError::IoError(e.to_string()))?,
+            ),
+        };
+        file.write_all(contents.as_bytes())
+            .map(|()| Value::Bool(true))
+            .map_err(|e| StdlibError::IoError(e.to_string()))
+    })
+}
+
+/// Close a file opened by `streamWrite`: streamClose(path) -> Bool
+///
+/// Returns false if no stream was open for the path.
+pub fn stream_close(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = expect_string(&args[0], "path")?;
+    let key = normalize_path(&validate_path(&path)?);
+    let file = STREAMS.with(|streams| streams.borrow_mut().remove(&key));
+    match file {
+        Some(file) => file
+            .sync_all()
+            .map(|()| Value::Bool(true))
+            .map_err(|e| StdlibError::IoError(e.to_string())),
+        None => Ok(Value::Bool(false)),
+    }
+}
+
 /// Check if a file or directory exists
 pub fn exists(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
@@ -181,9 +392,9 @@ pub fn list_dir(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value,
         Ok(entries) => {
             let files: Vec<Value> = entries
                 .filter_map(|e| e.ok())
-                .map(|e| Value::String(e.file_name().to_string_lossy().to_string()))
+                .map(|e| Value::String(e.file_name().to_string_lossy().to_string().into()))
                 .collect();
-            Ok(Value::Array(files))
+            Ok(Value::Array(files.into()))
         }
         Err(e) => Err(StdlibError::IoError(e.to_string())),
     }
@@ -219,11 +430,63 @@ pub fn read_line(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value
     let stdin = io::stdin();
     let mut line = String::new();
     match stdin.lock().read_line(&mut line) {
-        Ok(_) => Ok(Value::String(line.trim_end_matches('\n').to_string())),
+        Ok(_) => Ok(Value::String(line.trim_end_matches('\n').to_string().into())),
         Err(e) => Err(StdlibError::IoError(e.to_string())),
     }
 }
 
+/// What an `ask` builtin waits for
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Answer {
+    /// `ask(prompt)`: the line as typed
+    Text,
+    /// `askNumber(prompt)`: a Float
+    Number,
+    /// `askYesNo(prompt)`: `y`, `yes`, `n` or `no`, in any case, as a Bool
+    YesNo,
+}
+
+impl Answer {
+    pub const ALL: [Answer; 3] = [Answer::Text, Answer::Number, Answer::YesNo];
+
+    /// The builtin that asks for this kind of answer
+    pub fn builtin(self) -> &'static str {
+        match self {
+            Answer::Text => "ask",
+            Answer::Number => "askNumber",
+            Answer::YesNo => "askYesNo",
+        }
+    }
+
+    pub fn of_builtin(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|answer| answer.builtin() == name)
+    }
+
+    /// Write `prompt` to `output` and read answers from `input` until one
+    /// is of this kind, saying what is wanted after each that is not
+    pub fn ask(self, prompt: &str, input: &mut dyn BufRead, output: &mut dyn Write) -> Result<Value, StdlibError> {
+        let io_error = |e: io::Error| StdlibError::IoError(e.to_string());
+        loop {
+            write!(output, "{}", prompt).and_then(|()| output.flush()).map_err(io_error)?;
+            let answer = take_line(input)?
+                .ok_or_else(|| StdlibError::IoError("input ended before an answer was given".to_string()))?;
+            let hint = match self {
+                Answer::Text => return Ok(Value::String(answer.into())),
+                Answer::Number => match answer.trim().parse::<f64>() {
+                    Ok(n) if n.is_finite() => return Ok(Value::Float(n)),
+                    _ => "Please answer with a number.",
+                },
+                Answer::YesNo => match answer.trim().to_lowercase().as_str() {
+                    "y" | "yes" => return Ok(Value::Bool(true)),
+                    "n" | "no" => return Ok(Value::Bool(false)),
+                    _ => "Please answer yes or no.",
+                },
+            };
+            writeln!(output, "{}", hint).map_err(io_error)?;
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -240,6 +503,20 @@ fn temp_file(name: &str) -> String {
             .to_string()
     }
 
+    #[test]
+    fn test_write_and_read_bytes() {
+        let mut caps = test_caps();
+        let path = temp_file("io_bytes.bin");
+        let data = Value::Bytes(vec![0x89, b'P', b'N', b'G', 0, 0xff]);
+
+        write_bytes(&[Value::String(path.clone().into()), data.clone()], &mut caps).unwrap();
+        assert_eq!(read_bytes(&[Value::String(path.clone().into())], &mut caps).unwrap(), data);
+
+        // Text functions still take strings only
+        assert!(write_bytes(&[Value::String(path.clone().into()), Value::String("text".to_string().into())], &mut caps).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
     #[test]
     fn test_write_and_read() {
         let mut caps = test_caps();
@@ -247,36 +524,51 @@ fn test_write_and_read() {
 
         // Write
         let write_result = write_file(
-            &[Value::String(path.clone()), Value::String("Hello, WokeLang!".to_string())],
+            &[Value::String(path.clone().into()), Value::String("Hello, WokeLang!".to_string().into())],
             &mut caps,
         );
         assert!(write_result.is_ok());
 
         // Read
-        let read_result = read_file(&[Value::String(path.clone())], &mut caps);
+        let read_result = read_file(&[Value::String(path.clone().into())], &mut caps);
         assert_eq!(
             read_result.unwrap(),
-            Value::String("Hello, WokeLang!".to_string())
+            Value::String("Hello, WokeLang!".to_string().into())
         );
 
         // Cleanup
         let _ = fs::remove_file(&path);
     }
 
+    #[test]
+    fn test_write_quota() {
+        let mut caps = test_caps();
+        caps.set_quota(Quota::WriteBytes, 8);
+        let path = temp_file("io_quota.txt");
+        let args = |text: &str| [Value::String(path.clone().into()), Value::String(text.to_string().into())];
+
+        assert_eq!(write_file(&args("hello"), &mut caps).unwrap(), Value::Bool(true));
+        let over = append_file(&args("world"), &mut caps).unwrap();
+        assert!(matches!(over, Value::Oops(ref error) if error.to_string().contains("Quota exceeded")));
+        assert_eq!(read_file(&[Value::String(path.clone().into())], &mut caps).unwrap(), Value::String("hello".to_string().into()));
+        assert_eq!(append_file(&args("!!!"), &mut caps).unwrap(), Value::Bool(true));
+        let _ = fs::remove_file(&path);
+    }
+
     #[test]
     fn test_exists() {
         let mut caps = test_caps();
         let path = temp_file("exists_test.txt");
 
         // Should not exist yet
-        let result = exists(&[Value::String(path.clone())], &mut caps);
+        let result = exists(&[Value::String(path.clone().into())], &mut caps);
         assert_eq!(result.unwrap(), Value::Bool(false));
 
         // Create file
         fs::write(&path, "test").unwrap();
 
         // Should exist now
-        let result = exists(&[Value::String(path.clone())], &mut caps);
+        let result = exists(&[Value::String(path.clone().into())], &mut caps);
         assert_eq!(result.unwrap(), Value::Bool(true));
 
         // Cleanup
@@ -290,29 +582,85 @@ fn test_append() {
 
         // Write initial content
         write_file(
-            &[Value::String(path.clone()), Value::String("Hello".to_string())],
+            &[Value::String(path.clone().into()), Value::String("Hello".to_string().into())],
             &mut caps,
         )
         .unwrap();
 
         // Append
         append_file(
-            &[Value::String(path.clone()), Value::String(", World!".to_string())],
+            &[Value::String(path.clone().into()), Value::String(", World!".to_string().into())],
             &mut caps,
         )
         .unwrap();
 
         // Read and verify
-        let result = read_file(&[Value::String(path.clone())], &mut caps);
+        let result = read_file(&[Value::String(path.clone().into())], &mut caps);
         assert_eq!(
             result.unwrap(),
-            Value::String("Hello, World!".to_string())
+            Value::String("Hello, World!".to_string().into())
         );
 
         // Cleanup
         let _ = fs::remove_file(&path);
     }
 
+    #[test]
+    fn test_stream_write_and_read_lines() {
+        let mut caps = test_caps();
+        let path = temp_file("stream_lines.txt");
+        let _ = fs::remove_file(&path);
+        let p = Value::String(path.clone().into());
+
+        for line in ["first\n", "second\r\n", "", "third"] {
+            stream_write(&[p.clone(), Value::String(line.to_string().into())], &mut caps).unwrap();
+        }
+        assert_eq!(stream_close(std::slice::from_ref(&p), &mut caps).unwrap(), Value::Bool(true));
+        assert_eq!(stream_close(std::slice::from_ref(&p), &mut caps).unwrap(), Value::Bool(false));
+
+        let cursor = read_lines(std::slice::from_ref(&p), &mut caps).unwrap();
+        let mut lines = Vec::new();
+        while let Value::Okay(line) = next_line(std::slice::from_ref(&cursor), &mut caps).unwrap() {
+            lines.push(line.to_string());
+        }
+        assert_eq!(lines, ["first", "second", "third"]);
+        // The cursor closed itself at the end
+        assert!(next_line(std::slice::from_ref(&cursor), &mut caps).is_err());
+        assert_eq!(close_lines(&[cursor], &mut caps).unwrap(), Value::Bool(false));
+
+        let mut seen = Vec::new();
+        let count = for_each_line(&[p.clone(), Value::Unit], &mut |_, args| {
+            seen.push(args[0].clone());
+            Ok(Value::Unit)
+        })
+        .unwrap();
+        assert_eq!(count, Value::Int(3));
+        assert_eq!(seen[1], Value::String("second".to_string().into()));
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_streaming_requires_consent() {
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+        let p = Value::String(temp_file("stream_denied.txt").into());
+
+        assert!(matches!(
+            read_lines(std::slice::from_ref(&p), &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            require_lines(&[p.clone(), Value::Unit], &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            stream_write(&[p, Value::String("x".to_string().into())], &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+    }
+
     #[test]
     fn test_delete() {
         let mut caps = test_caps();
@@ -323,7 +671,7 @@ fn test_delete() {
         assert!(std::path::Path::new(&path).exists());
 
         // Delete
-        let result = delete(&[Value::String(path.clone())], &mut caps);
+        let result = delete(&[Value::String(path.clone().into())], &mut caps);
         assert!(result.is_ok());
         assert!(!std::path::Path::new(&path).exists());
     }
@@ -334,7 +682,7 @@ fn test_create_dir_and_list() {
         let dir_path = temp_file("test_dir");
 
         // Create directory
-        create_dir(&[Value::String(dir_path.clone())], &mut caps).unwrap();
+        create_dir(&[Value::String(dir_path.clone().into())], &mut caps).unwrap();
         assert!(std::path::Path::new(&dir_path).is_dir());
 
         // Create a file in the directory
@@ -342,10 +690,10 @@ fn test_create_dir_and_list() {
         fs::write(&file_path, "test").unwrap();
 
         // List directory
-        let result = list_dir(&[Value::String(dir_path.clone())], &mut caps);
+        let result = list_dir(&[Value::String(dir_path.clone().into())], &mut caps);
         match result.unwrap() {
             Value::Array(files) => {
-                assert!(files.contains(&Value::String("test.txt".to_string())));
+                assert!(files.contains(&Value::String("test.txt".to_string().into())));
             }
             _ => panic!("Expected array"),
         }
@@ -359,14 +707,14 @@ fn test_path_traversal_prevention() {
         let mut caps = test_caps();
 
         // Should reject paths with ..
-        let result = read_file(&[Value::String("../etc/passwd".to_string())], &mut caps);
+        let result = read_file(&[Value::String("../etc/passwd".to_string().into())], &mut caps);
         assert!(result.is_err());
 
-        let result = read_file(&[Value::String("/tmp/../etc/passwd".to_string())], &mut caps);
+        let result = read_file(&[Value::String("/tmp/../etc/passwd".to_string().into())], &mut caps);
         assert!(result.is_err());
 
         // Should reject paths with null bytes
-        let result = read_file(&[Value::String("test\0.txt".to_string())], &mut caps);
+        let result = read_file(&[Value::String("test\0.txt".to_string().into())], &mut caps);
         assert!(result.is_err());
     }
 
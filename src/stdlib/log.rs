@@ -0,0 +1,335 @@
+//! WokeLang Standard Library - Log Module
+//!
+//! Levelled logging with timestamps. Records below the minimum level are
+//! dropped; `#verbose on` lowers the minimum to `debug`. By default records
+//! go to stderr as text or JSON lines. Embedders can route them elsewhere
+//! with [`set_sink`]. Secrets are masked in messages and fields before a
+//! record is made (see [`crate::security::secrets`]).
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::json::to_json;
+use super::time::timestamp_to_components;
+use super::{check_arity, check_arity_range, expect_string, StdlibError};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Severity of a log record, from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    /// Parse a level name, ignoring case
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name.to_ascii_lowercase().as_str() {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" | "warning" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// One log record, as passed to the sink
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub level: Level,
+    pub message: String,
+    /// Extra structured fields, in key order
+    pub fields: BTreeMap<String, Value>,
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: i64,
+}
+
+impl LogRecord {
+    /// The timestamp in RFC 3339 form, in UTC with milliseconds
+    pub fn timestamp(&self) -> String {
+        let (year, month, day, hour, minute, second) =
+            timestamp_to_components(self.timestamp_ms.div_euclid(1000));
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            self.timestamp_ms.rem_euclid(1000)
+        )
+    }
+
+    /// A human-readable line: timestamp, level, message, then `key=value`s
+    pub fn to_text(&self) -> String {
+        let mut line = format!(
+            "{} {:<5} {}",
+            self.timestamp(),
+            self.level.name().to_uppercase(),
+            self.message
+        );
+        for (key, value) in &self.fields {
+            line.push_str(&format!(" {}={}", key, value));
+        }
+        line
+    }
+
+    /// A single-line JSON object with `timestamp`, `level`, `message` and,
+    /// if there are any, `fields`; keys are in sorted order
+    pub fn to_json(&self) -> String {
+        let mut object = serde_json::Map::new();
+        object.insert("timestamp".to_string(), self.timestamp().into());
+        object.insert("level".to_string(), self.level.name().into());
+        object.insert("message".to_string(), self.message.clone().into());
+        if !self.fields.is_empty() {
+            let fields = self
+                .fields
+                .iter()
+                .map(|(key, value)| (key.clone(), to_json(value)))
+                .collect();
+            object.insert("fields".to_string(), serde_json::Value::Object(fields));
+        }
+        serde_json::Value::Object(object).to_string()
+    }
+}
+
+/// Receives every record at or above the minimum level
+pub type LogSink = Box<dyn FnMut(&LogRecord)>;
+
+struct Logger {
+    min_level: Level,
+    json: bool,
+    sink: Option<LogSink>,
+}
+
+thread_local! {
+    static LOGGER: RefCell<Logger> = RefCell::new(Logger {
+        min_level: Level::Info,
+        json: false,
+        sink: None,
+    });
+}
+
+/// Set the minimum level; records below it are dropped
+pub fn set_min_level(level: Level) {
+    LOGGER.with(|logger| logger.borrow_mut().min_level = level);
+}
+
+/// Write JSON lines instead of text to the default stderr sink
+pub fn set_json(json: bool) {
+    LOGGER.with(|logger| logger.borrow_mut().json = json);
+}
+
+/// Send records to `sink` instead of stderr
+pub fn set_sink(sink: impl FnMut(&LogRecord) + 'static) {
+    LOGGER.with(|logger| logger.borrow_mut().sink = Some(Box::new(sink)));
+}
+
+/// Send records to stderr again
+pub fn reset_sink() {
+    LOGGER.with(|logger| logger.borrow_mut().sink = None);
+}
+
+fn emit(level: Level, args: &[Value]) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    // Secret text is masked whole, whatever it was made from
+    let message = match &args[0] {
+        Value::String(s) => s.shown().to_string(),
+        other => expect_string(other, "message")?,
+    };
+    let fields = match args.get(1) {
+        None => BTreeMap::new(),
+        Some(Value::Record(fields)) => fields.iter().map(|(k, v)| (k.clone(), v.redacted())).collect(),
+        Some(other) => {
+            return Err(StdlibError::TypeError {
+                expected: "Map of log fields".to_string(),
+                got: format!("{:?}", other),
+            })
+        }
+    };
+
+    let (min_level, json, sink) = LOGGER.with(|logger| {
+        let mut logger = logger.borrow_mut();
+        (logger.min_level, logger.json, logger.sink.take())
+    });
+    let record = LogRecord {
+        level,
+        message,
+        fields,
+        timestamp_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0),
+    };
+
+    // The sink is taken out while it runs, so it may log itself
+    match sink {
+        Some(mut sink) => {
+            if level >= min_level {
+                sink(&record);
+            }
+            LOGGER.with(|logger| {
+                logger.borrow_mut().sink.get_or_insert(sink);
+            });
+        }
+        None if level < min_level => {}
+        None if json => eprintln!("{}", record.to_json()),
+        None => eprintln!("{}", record.to_text()),
+    }
+    Ok(Value::Unit)
+}
+
+/// Log a debug message: debug(message[, fields])
+pub fn debug(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    emit(Level::Debug, args)
+}
+
+/// Log an informational message: info(message[, fields])
+pub fn info(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    emit(Level::Info, args)
+}
+
+/// Log a warning: warn(message[, fields])
+pub fn warn(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    emit(Level::Warn, args)
+}
+
+/// Log an error: error(message[, fields])
+pub fn error(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    emit(Level::Error, args)
+}
+
+/// Set the minimum level by name: "debug", "info", "warn" or "error"
+pub fn set_level(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let name = expect_string(&args[0], "level")?;
+    let level = Level::from_name(&name).ok_or_else(|| {
+        StdlibError::RuntimeError(format!(
+            "Unknown log level: {} (use debug, info, warn or error)",
+            name
+        ))
+    })?;
+    set_min_level(level);
+    Ok(Value::Unit)
+}
+
+/// Choose the output format: "text" or "json"
+pub fn set_format(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let format = expect_string(&args[0], "format")?;
+    match format.as_str() {
+        "text" => set_json(false),
+        "json" => set_json(true),
+        other => {
+            return Err(StdlibError::RuntimeError(format!(
+                "Unknown log format: {} (use text or json)",
+                other
+            )))
+        }
+    }
+    Ok(Value::Unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn capture() -> Rc<RefCell<Vec<LogRecord>>> {
+        let records = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&records);
+        set_sink(move |record| sink.borrow_mut().push(record.clone()));
+        records
+    }
+
+    #[test]
+    fn test_levels_and_sink() {
+        let mut caps = test_caps();
+        let records = capture();
+
+        debug(&[s("hidden")], &mut caps).unwrap();
+        info(&[s("starting")], &mut caps).unwrap();
+        set_level(&[s("WARN")], &mut caps).unwrap();
+        info(&[s("hidden too")], &mut caps).unwrap();
+        error(&[s("failed")], &mut caps).unwrap();
+        set_level(&[s("debug")], &mut caps).unwrap();
+        debug(&[s("details")], &mut caps).unwrap();
+
+        let logged: Vec<(Level, String)> = records
+            .borrow()
+            .iter()
+            .map(|r| (r.level, r.message.clone()))
+            .collect();
+        assert_eq!(
+            logged,
+            vec![
+                (Level::Info, "starting".to_string()),
+                (Level::Error, "failed".to_string()),
+                (Level::Debug, "details".to_string()),
+            ]
+        );
+        assert!(set_level(&[s("loud")], &mut caps).is_err());
+        reset_sink();
+        set_min_level(Level::Info);
+    }
+
+    #[test]
+    fn test_record_formats() {
+        let record = LogRecord {
+            level: Level::Warn,
+            message: "disk low".to_string(),
+            fields: BTreeMap::from([
+                ("free".to_string(), Value::Int(12)),
+                ("disk".to_string(), s("/dev/sda")),
+            ]),
+            timestamp_ms: 1_700_000_000_123,
+        };
+        assert_eq!(
+            record.to_text(),
+            "2023-11-14T22:13:20.123Z WARN  disk low disk=/dev/sda free=12"
+        );
+        assert_eq!(
+            record.to_json(),
+            r#"{"fields":{"disk":"/dev/sda","free":12},"level":"warn","message":"disk low","timestamp":"2023-11-14T22:13:20.123Z"}"#
+        );
+    }
+
+    #[test]
+    fn test_fields_argument() {
+        let mut caps = test_caps();
+        let records = capture();
+        let fields = Value::Record(HashMap::from([("user".to_string(), s("ada"))]));
+        warn(&[s("slow request"), fields], &mut caps).unwrap();
+        assert_eq!(records.borrow()[0].fields["user"], s("ada"));
+        assert!(info(&[s("bad"), s("not a map")], &mut caps).is_err());
+        assert!(set_format(&[s("xml")], &mut caps).is_err());
+        reset_sink();
+    }
+}
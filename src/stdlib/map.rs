@@ -0,0 +1,230 @@
+//! WokeLang Standard Library - Map Module
+//!
+//! String-keyed maps, backed by record values. Every operation returns a
+//! new map rather than modifying its argument. Keys, values and entries are
+//! listed in sorted key order so output is deterministic.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, check_arity_range, expect_string, Invoke, StdlibError};
+use std::collections::HashMap;
+
+/// Create a map, optionally from an array of [key, value] pairs
+pub fn new(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 0, 1)?;
+    let mut map = HashMap::new();
+    if let Some(entries) = args.first() {
+        let entries = match entries {
+            Value::Array(a) => a,
+            other => {
+                return Err(StdlibError::TypeError {
+                    expected: "Array of [key, value] pairs".to_string(),
+                    got: format!("{:?}", other),
+                })
+            }
+        };
+        for entry in entries.iter() {
+            match entry {
+                Value::Array(pair) if pair.len() == 2 => {
+                    map.insert(expect_string(&pair[0], "key")?, pair[1].clone());
+                }
+                other => {
+                    return Err(StdlibError::TypeError {
+                        expected: "[key, value] pair".to_string(),
+                        got: format!("{:?}", other),
+                    })
+                }
+            }
+        }
+    }
+    Ok(Value::Record(map))
+}
+
+/// Look up a key, returning Okay(value) or Oops if it is missing
+pub fn get(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let map = expect_map(&args[0])?;
+    let key = expect_string(&args[1], "key")?;
+    match map.get(&key) {
+        Some(v) => Ok(Value::Okay(Box::new(v.clone()))),
+        None => Ok(Value::oops(format!("key not found: {}", key))),
+    }
+}
+
+/// Set a key (returns new map)
+pub fn set(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 3)?;
+    let mut map = expect_map(&args[0])?.clone();
+    map.insert(expect_string(&args[1], "key")?, args[2].clone());
+    Ok(Value::Record(map))
+}
+
+/// Remove a key if present (returns new map)
+pub fn remove(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let mut map = expect_map(&args[0])?.clone();
+    map.remove(&expect_string(&args[1], "key")?);
+    Ok(Value::Record(map))
+}
+
+/// Check if a key is present
+pub fn has(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let map = expect_map(&args[0])?;
+    Ok(Value::Bool(map.contains_key(&expect_string(&args[1], "key")?)))
+}
+
+/// Number of entries
+pub fn size(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    Ok(Value::Int(expect_map(&args[0])?.len() as i64))
+}
+
+/// Sorted array of keys
+pub fn keys(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let entries = sorted_entries(expect_map(&args[0])?);
+    Ok(Value::Array(
+        entries.into_iter().map(|(k, _)| Value::String(k.clone().into())).collect::<Vec<_>>().into(),
+    ))
+}
+
+/// Array of values, in key order
+pub fn values(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let entries = sorted_entries(expect_map(&args[0])?);
+    Ok(Value::Array(entries.into_iter().map(|(_, v)| v.clone()).collect::<Vec<_>>().into()))
+}
+
+/// Array of [key, value] pairs, in key order
+pub fn entries(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let entries = sorted_entries(expect_map(&args[0])?);
+    Ok(Value::Array(
+        entries
+            .into_iter()
+            .map(|(k, v)| Value::Array(vec![Value::String(k.clone().into()), v.clone()].into()))
+            .collect::<Vec<_>>().into(),
+    ))
+}
+
+/// Combine two maps; keys in the second map win
+pub fn merge(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let mut map = expect_map(&args[0])?.clone();
+    map.extend(expect_map(&args[1])?.clone());
+    Ok(Value::Record(map))
+}
+
+/// Call a function with each key and value, in key order
+pub fn for_each(args: &[Value], invoke: &mut Invoke) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    for (k, v) in sorted_entries(expect_map(&args[0])?) {
+        invoke(&args[1], vec![Value::String(k.clone().into()), v.clone()])?;
+    }
+    Ok(Value::Unit)
+}
+
+fn sorted_entries(map: &HashMap<String, Value>) -> Vec<(&String, &Value)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+fn expect_map(value: &Value) -> Result<&HashMap<String, Value>, StdlibError> {
+    match value {
+        Value::Record(map) => Ok(map),
+        other => Err(StdlibError::TypeError {
+            expected: "Map".to_string(),
+            got: format!("{:?}", other),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn sample(caps: &mut CapabilityRegistry) -> Value {
+        let pairs = Value::Array(vec![
+            Value::Array(vec![s("b"), Value::Int(2)].into()),
+            Value::Array(vec![s("a"), Value::Int(1)].into()),
+        ].into());
+        new(&[pairs], caps).unwrap()
+    }
+
+    #[test]
+    fn test_new_get_set_remove() {
+        let mut caps = test_caps();
+        assert_eq!(new(&[], &mut caps).unwrap(), Value::Record(HashMap::new()));
+
+        let map = sample(&mut caps);
+        assert_eq!(
+            get(&[map.clone(), s("a")], &mut caps).unwrap(),
+            Value::Okay(Box::new(Value::Int(1)))
+        );
+        assert!(get(&[map.clone(), s("z")], &mut caps).unwrap().is_oops());
+
+        let updated = set(&[map.clone(), s("c"), Value::Int(3)], &mut caps).unwrap();
+        assert_eq!(size(std::slice::from_ref(&updated), &mut caps).unwrap(), Value::Int(3));
+        // The original map is unchanged
+        assert_eq!(has(&[map, s("c")], &mut caps).unwrap(), Value::Bool(false));
+
+        let removed = remove(&[updated, s("a")], &mut caps).unwrap();
+        assert_eq!(keys(&[removed], &mut caps).unwrap(), Value::Array(vec![s("b"), s("c")].into()));
+    }
+
+    #[test]
+    fn test_keys_values_entries_sorted() {
+        let mut caps = test_caps();
+        let map = sample(&mut caps);
+        assert_eq!(
+            keys(std::slice::from_ref(&map), &mut caps).unwrap(),
+            Value::Array(vec![s("a"), s("b")].into())
+        );
+        assert_eq!(
+            values(std::slice::from_ref(&map), &mut caps).unwrap(),
+            Value::Array(vec![Value::Int(1), Value::Int(2)].into())
+        );
+        assert_eq!(
+            entries(&[map], &mut caps).unwrap(),
+            Value::Array(vec![
+                Value::Array(vec![s("a"), Value::Int(1)].into()),
+                Value::Array(vec![s("b"), Value::Int(2)].into()),
+            ].into())
+        );
+    }
+
+    #[test]
+    fn test_merge_and_for_each() {
+        let mut caps = test_caps();
+        let map = sample(&mut caps);
+        let other = new(&[Value::Array(vec![Value::Array(vec![s("a"), Value::Int(10)].into())].into())], &mut caps).unwrap();
+        let merged = merge(&[map, other], &mut caps).unwrap();
+        assert_eq!(
+            values(std::slice::from_ref(&merged), &mut caps).unwrap(),
+            Value::Array(vec![Value::Int(10), Value::Int(2)].into())
+        );
+
+        let mut seen = Vec::new();
+        let mut record = |_: &Value, args: Vec<Value>| {
+            seen.push(format!("{}={}", args[0], args[1]));
+            Ok(Value::Unit)
+        };
+        for_each(&[merged, s("f")], &mut record).unwrap();
+        assert_eq!(seen, ["a=10", "b=2"]);
+    }
+
+    #[test]
+    fn test_type_errors() {
+        let mut caps = test_caps();
+        assert!(keys(&[Value::Int(1)], &mut caps).is_err());
+        assert!(new(&[Value::Array(vec![Value::Int(1)].into())], &mut caps).is_err());
+        assert!(get(&[Value::Record(HashMap::new()), Value::Int(1)], &mut caps).is_err());
+    }
+}
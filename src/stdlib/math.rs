@@ -1,12 +1,71 @@
 //! WokeLang Standard Library - Math Module
 //!
 //! Mathematical functions that don't require any special capabilities.
+//!
+//! The random functions share one generator per thread. It is seeded from
+//! the operating system on first use, or with `randomSeed(n)` to make a run
+//! reproducible.
 
 use crate::interpreter::Value;
 use crate::security::CapabilityRegistry;
-use super::{check_arity, check_arity_range, expect_float, StdlibError};
+use super::array::expect_array;
+use super::{check_arity, check_arity_range, expect_float, expect_int, StdlibError};
+use std::cell::Cell;
 use std::f64::consts::{E, PI};
 
+thread_local! {
+    static RNG_STATE: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Seed the random functions so the values they produce repeat exactly
+pub fn set_seed(seed: u64) {
+    RNG_STATE.with(|state| state.set(Some(seed)));
+}
+
+fn os_seed() -> u64 {
+    let mut bytes = [0u8; 8];
+    if getrandom::getrandom(&mut bytes).is_err() {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        return nanos as u64;
+    }
+    u64::from_le_bytes(bytes)
+}
+
+/// Next value from the generator (SplitMix64)
+fn next_u64() -> u64 {
+    RNG_STATE.with(|state| {
+        let next = state
+            .get()
+            .unwrap_or_else(os_seed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        state.set(Some(next));
+        let mut z = next;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    })
+}
+
+/// Uniform Float in [0, 1)
+fn next_unit() -> f64 {
+    (next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Uniform value in [0, bound), without modulo bias
+fn next_below(bound: u64) -> u64 {
+    let zone = u64::MAX - u64::MAX % bound;
+    loop {
+        let candidate = next_u64();
+        if candidate < zone {
+            return candidate % bound;
+        }
+    }
+}
+
 /// Absolute value
 pub fn abs(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
@@ -116,16 +175,7 @@ pub fn max(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, Stdl
 /// Random number between 0 and 1 (or between min and max if provided)
 pub fn random(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity_range(args, 0, 2)?;
-
-    // Simple pseudo-random using system time
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let seed = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_nanos())
-        .unwrap_or(0);
-
-    // Simple LCG
-    let random_val = ((seed.wrapping_mul(1103515245).wrapping_add(12345)) % (1 << 31)) as f64 / (1u64 << 31) as f64;
+    let random_val = next_unit();
 
     match args.len() {
         0 => Ok(Value::Float(random_val)),
@@ -138,6 +188,56 @@ pub fn random(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, S
     }
 }
 
+/// Seed the random functions: randomSeed(n)
+pub fn random_seed(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    set_seed(expect_int(&args[0], "seed")? as u64);
+    Ok(Value::Unit)
+}
+
+/// Random integer between lo and hi, both included: randomInt(lo, hi)
+pub fn random_int(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let lo = expect_int(&args[0], "lo")?;
+    let hi = expect_int(&args[1], "hi")?;
+    if lo > hi {
+        return Err(StdlibError::RuntimeError(format!(
+            "randomInt: lo ({}) is greater than hi ({})",
+            lo, hi
+        )));
+    }
+    let span = (hi as i128 - lo as i128 + 1) as u128;
+    let offset = if span > u64::MAX as u128 {
+        next_u64()
+    } else {
+        next_below(span as u64)
+    };
+    Ok(Value::Int((lo as i128 + offset as i128) as i64))
+}
+
+/// Pick one element at random: randomChoice(array) -> Result
+pub fn random_choice(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let items = expect_array(&args[0])?;
+    if items.is_empty() {
+        return Ok(Value::oops("array is empty"));
+    }
+    let index = next_below(items.len() as u64) as usize;
+    Ok(Value::Okay(Box::new(items[index].clone())))
+}
+
+/// Copy of an array in random order: shuffle(array)
+pub fn shuffle(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let mut items = expect_array(&args[0])?.clone();
+    // Fisher-Yates
+    for i in (1..items.len()).rev() {
+        let j = next_below(i as u64 + 1) as usize;
+        items.swap(i, j);
+    }
+    Ok(Value::Array(items.into()))
+}
+
 /// Pi constant
 pub fn pi(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 0)?;
@@ -166,8 +266,8 @@ fn test_abs() {
             Value::Int(5)
         );
         assert_eq!(
-            abs(&[Value::Float(-3.14)], &mut caps).unwrap(),
-            Value::Float(3.14)
+            abs(&[Value::Float(-2.75)], &mut caps).unwrap(),
+            Value::Float(2.75)
         );
     }
 
@@ -220,6 +320,54 @@ fn test_min_max() {
         );
     }
 
+    #[test]
+    fn test_seeded_random_repeats() {
+        let mut caps = test_caps();
+        let deck = Value::Array((1..=10).map(Value::Int).collect::<Vec<_>>().into());
+        let draw = |caps: &mut CapabilityRegistry| {
+            random_seed(&[Value::Int(42)], caps).unwrap();
+            vec![
+                random(&[], caps).unwrap(),
+                random_int(&[Value::Int(1), Value::Int(6)], caps).unwrap(),
+                random_choice(std::slice::from_ref(&deck), caps).unwrap(),
+                shuffle(std::slice::from_ref(&deck), caps).unwrap(),
+            ]
+        };
+        let first = draw(&mut caps);
+        assert_eq!(first, draw(&mut caps));
+
+        let Value::Array(shuffled) = &first[3] else {
+            panic!("expected an array");
+        };
+        let mut sorted = shuffled.to_vec();
+        sorted.sort_by_key(|v| match v {
+            Value::Int(n) => *n,
+            _ => 0,
+        });
+        assert_eq!(Value::Array(sorted.into()), deck);
+    }
+
+    #[test]
+    fn test_random_int_bounds() {
+        let mut caps = test_caps();
+        for _ in 0..200 {
+            let Value::Int(n) = random_int(&[Value::Int(-2), Value::Int(2)], &mut caps).unwrap() else {
+                panic!("expected an Int");
+            };
+            assert!((-2..=2).contains(&n));
+        }
+        assert_eq!(
+            random_int(&[Value::Int(7), Value::Int(7)], &mut caps).unwrap(),
+            Value::Int(7)
+        );
+        assert!(random_int(&[Value::Int(i64::MIN), Value::Int(i64::MAX)], &mut caps).is_ok());
+        assert!(random_int(&[Value::Int(3), Value::Int(1)], &mut caps).is_err());
+        assert_eq!(
+            random_choice(&[Value::Array(vec![].into())], &mut caps).unwrap(),
+            Value::oops("array is empty")
+        );
+    }
+
     #[test]
     fn test_constants() {
         let mut caps = test_caps();
@@ -3,22 +3,59 @@
 //! This module provides the standard library for WokeLang, offering
 //! common functionality with consent-aware operations.
 
+pub mod archive;
 pub mod array;
+pub mod bytes;
 pub mod chan;
+pub mod config;
+pub mod crypto;
+pub mod csv;
+pub mod datetime;
+pub mod decimal;
+pub mod docs;
+pub mod duration;
+pub mod encoding;
+pub mod env;
+pub mod glob;
 pub mod io;
 pub mod json;
+pub mod log;
+pub mod map;
 pub mod math;
 pub mod net;
+pub mod path;
+pub mod process;
+pub mod regex;
+pub mod socket;
 pub mod string;
+pub mod superpowers;
+pub mod term;
+pub mod test;
 pub mod time;
+pub mod ui;
 
 use crate::interpreter::Value;
-use crate::security::CapabilityRegistry;
+use crate::security::{CapabilityRegistry, Quota};
 use std::collections::HashMap;
 
 /// Standard library function signature
 pub type StdlibFn = fn(&[Value], &mut CapabilityRegistry) -> Result<Value, StdlibError>;
 
+/// Calls a function value from the running program with the given arguments
+///
+/// Supplied by the interpreter or VM so library functions like
+/// `std.array.map` can call back into user code.
+pub type Invoke<'a> = dyn FnMut(&Value, Vec<Value>) -> Result<Value, StdlibError> + 'a;
+
+/// Standard library function that takes function values as arguments
+pub type HigherOrderFn = fn(&[Value], &mut Invoke) -> Result<Value, StdlibError>;
+
+/// Capability check run before a higher-order function is called
+///
+/// Higher-order functions have no access to the capability registry, so
+/// those that touch the outside world declare a gate instead.
+pub type ConsentGate = fn(&[Value], &mut CapabilityRegistry) -> Result<(), StdlibError>;
+
 /// Error type for standard library operations
 #[derive(Debug, Clone)]
 pub enum StdlibError {
@@ -63,17 +100,39 @@ impl std::error::Error for StdlibError {}
 /// The standard library registry
 pub struct StdlibRegistry {
     functions: HashMap<String, StdlibFn>,
+    higher_order: HashMap<String, HigherOrderFn>,
+    gates: HashMap<String, ConsentGate>,
 }
 
 impl StdlibRegistry {
     pub fn new() -> Self {
         let mut registry = Self {
             functions: HashMap::new(),
+            higher_order: HashMap::new(),
+            gates: HashMap::new(),
         };
         registry.register_all();
         registry
     }
 
+    /// The standard library without the functions a sandbox rules out:
+    /// network access, running programs (`std.process`, and `std.ui`,
+    /// which runs the platform's tools) and environment variables
+    pub fn sandboxed() -> Self {
+        let mut registry = Self::new();
+        let unavailable = |name: &str| {
+            ["std.net.", "std.process.", "std.ui.", "std.env."]
+                .iter()
+                .any(|module| name.starts_with(module))
+                // The program's own arguments are not the environment
+                && name != "std.env.args"
+        };
+        registry.functions.retain(|name, _| !unavailable(name));
+        registry.higher_order.retain(|name, _| !unavailable(name));
+        registry.gates.retain(|name, _| !unavailable(name));
+        registry
+    }
+
     /// Register all standard library functions
     fn register_all(&mut self) {
         // Math functions
@@ -89,24 +148,140 @@ fn register_all(&mut self) {
         self.register("std.math.min", math::min);
         self.register("std.math.max", math::max);
         self.register("std.math.random", math::random);
+        self.register("std.math.randomSeed", math::random_seed);
+        self.register("std.math.randomInt", math::random_int);
+        self.register("std.math.randomChoice", math::random_choice);
+        self.register("std.math.shuffle", math::shuffle);
         self.register("std.math.pi", math::pi);
         self.register("std.math.e", math::e);
 
+        // Decimal functions
+        self.register("std.decimal.round", decimal::round);
+        self.register("std.decimal.roundHalfEven", decimal::round_half_even);
+        self.register("std.decimal.truncate", decimal::truncate);
+        self.register("std.decimal.floor", decimal::floor);
+        self.register("std.decimal.ceil", decimal::ceil);
+        self.register("std.decimal.places", decimal::places);
+        self.register("std.decimal.parse", decimal::parse);
+        self.register("std.decimal.fromFloat", decimal::from_float);
+        self.register("std.decimal.toFloat", decimal::to_float);
+
         // I/O functions (require consent)
         self.register("std.io.readFile", io::read_file);
         self.register("std.io.writeFile", io::write_file);
+        self.register("std.io.readBytes", io::read_bytes);
+        self.register("std.io.writeBytes", io::write_bytes);
         self.register("std.io.appendFile", io::append_file);
+        self.register("std.io.readLines", io::read_lines);
+        self.register("std.io.nextLine", io::next_line);
+        self.register("std.io.closeLines", io::close_lines);
+        self.register("std.io.streamWrite", io::stream_write);
+        self.register("std.io.streamClose", io::stream_close);
+        self.register("std.io.glob", glob::glob);
+        self.register("std.io.walk", glob::walk);
         self.register("std.io.exists", io::exists);
         self.register("std.io.delete", io::delete);
         self.register("std.io.listDir", io::list_dir);
         self.register("std.io.createDir", io::create_dir);
         self.register("std.io.readLine", io::read_line);
 
+        // Archive functions (require consent)
+        self.register("std.archive.zip", archive::zip);
+        self.register("std.archive.unzip", archive::unzip);
+        self.register("std.archive.tarCreate", archive::tar_create);
+        self.register("std.archive.tarExtract", archive::tar_extract);
+
+        // Logging functions
+        self.register("std.log.debug", log::debug);
+        self.register("std.log.info", log::info);
+        self.register("std.log.warn", log::warn);
+        self.register("std.log.error", log::error);
+        self.register("std.log.setLevel", log::set_level);
+        self.register("std.log.setFormat", log::set_format);
+
+        // Test assertions
+        self.register("std.test.assertEqual", test::assert_equal);
+        self.register("std.test.assertTrue", test::assert_true);
+        self.register("std.test.assertOops", test::assert_oops);
+
+        // Path functions (exists requires consent)
+        self.register("std.path.join", path::join);
+        self.register("std.path.dirname", path::dirname);
+        self.register("std.path.basename", path::basename);
+        self.register("std.path.extension", path::extension);
+        self.register("std.path.normalize", path::normalize);
+        self.register("std.path.absolute", path::absolute);
+        self.register("std.path.exists", path::exists);
+
         // JSON functions
         self.register("std.json.parse", json::parse);
         self.register("std.json.stringify", json::stringify);
-        self.register("std.json.get", json::get);
-        self.register("std.json.set", json::set);
+        #[allow(deprecated)]
+        {
+            self.register("std.json.get", json::get);
+            self.register("std.json.set", json::set);
+        }
+
+        // Config functions
+        self.register("std.config.parseToml", config::parse_toml);
+        self.register("std.config.parseYaml", config::parse_yaml);
+
+        // Environment functions (require consent, except args)
+        self.register("std.env.get", env::get);
+        self.register("std.env.set", env::set);
+        self.register("std.env.all", env::all);
+        self.register("std.env.args", env::args);
+
+        // Process functions (require consent per program)
+        self.register("std.process.run", process::run);
+        self.register("std.process.spawn", process::spawn);
+        self.register("std.process.wait", process::wait);
+        self.register("std.process.isRunning", process::is_running);
+        self.register("std.process.kill", process::kill);
+
+        // Terminal functions
+        self.register("std.term.color", term::color);
+        self.register("std.term.background", term::background);
+        self.register("std.term.style", term::style);
+        self.register("std.term.strip", term::strip);
+        self.register("std.term.isTty", term::is_tty);
+        self.register("std.term.setColor", term::set_color);
+        self.register("std.term.width", term::width);
+        self.register("std.term.cursorUp", term::cursor_up);
+        self.register("std.term.cursorDown", term::cursor_down);
+        self.register("std.term.cursorColumn", term::cursor_column);
+        self.register("std.term.clearLine", term::clear_line);
+        self.register("std.term.hideCursor", term::hide_cursor);
+        self.register("std.term.showCursor", term::show_cursor);
+        self.register("std.term.progressBar", term::progress_bar);
+
+        // UI functions (require clipboard or notify consent)
+        self.register("std.ui.clipboardRead", ui::clipboard_read);
+        self.register("std.ui.clipboardWrite", ui::clipboard_write);
+        self.register("std.ui.notify", ui::notify);
+
+        // Superpower introspection, behind listSuperpowers, hasSuperpower
+        // and renounce
+        self.register("std.superpowers.list", superpowers::list);
+        self.register("std.superpowers.has", superpowers::has);
+        self.register("std.superpowers.renounce", superpowers::renounce);
+
+        // Crypto functions (require consent)
+        self.register("std.crypto.sha256", crypto::sha256);
+        self.register("std.crypto.sha512", crypto::sha512);
+        self.register("std.crypto.hmac", crypto::hmac);
+        self.register("std.crypto.randomBytes", crypto::random_bytes);
+        self.register("std.crypto.constantTimeEquals", crypto::constant_time_equals);
+
+        // Encoding functions
+        self.register("std.encoding.base64Encode", encoding::base64_encode);
+        self.register("std.encoding.base64Decode", encoding::base64_decode);
+        self.register("std.encoding.hexEncode", encoding::hex_encode);
+        self.register("std.encoding.hexDecode", encoding::hex_decode);
+        self.register("std.encoding.base64DecodeBytes", encoding::base64_decode_bytes);
+        self.register("std.encoding.hexDecodeBytes", encoding::hex_decode_bytes);
+        self.register("std.encoding.urlEncode", encoding::url_encode);
+        self.register("std.encoding.urlDecode", encoding::url_decode);
 
         // Time functions
         self.register("std.time.now", time::now);
@@ -115,11 +290,43 @@ fn register_all(&mut self) {
         self.register("std.time.sleep", time::sleep);
         self.register("std.time.timestamp", time::timestamp);
         self.register("std.time.elapsed", time::elapsed);
+        self.register("std.time.dateTime", datetime::date_time);
+        self.register("std.time.current", datetime::current);
+        self.register("std.time.parseRfc3339", datetime::parse_rfc3339);
+        self.register("std.time.toRfc3339", datetime::to_rfc3339);
+        self.register("std.time.fromTimestamp", datetime::from_timestamp);
+        self.register("std.time.toTimestamp", datetime::to_timestamp);
+        self.register("std.time.inZone", datetime::in_zone);
+        self.register("std.time.year", datetime::year);
+        self.register("std.time.month", datetime::month);
+        self.register("std.time.day", datetime::day);
+        self.register("std.time.hour", datetime::hour);
+        self.register("std.time.minute", datetime::minute);
+        self.register("std.time.second", datetime::second);
+        self.register("std.time.weekday", datetime::weekday);
+        self.register("std.time.offset", datetime::offset);
+        self.register("std.time.addDays", datetime::add_days);
+        self.register("std.time.addMonths", datetime::add_months);
+        self.register("std.time.duration", duration::duration);
+        self.register("std.time.fromMillis", duration::from_millis);
+        self.register("std.time.toMillis", duration::to_millis);
+        self.register("std.time.toSeconds", duration::to_seconds);
 
         // Network functions (require consent)
         self.register("std.net.httpGet", net::http_get);
         self.register("std.net.httpPost", net::http_post);
         self.register("std.net.download", net::download);
+        self.register_gated("std.net.serve", net::serve, net::require_serve);
+        self.register("std.net.tcpConnect", socket::tcp_connect);
+        self.register("std.net.tcpListen", socket::tcp_listen);
+        self.register("std.net.tcpAccept", socket::tcp_accept);
+        self.register("std.net.tcpSend", socket::tcp_send);
+        self.register("std.net.tcpReceive", socket::tcp_receive);
+        self.register("std.net.udpBind", socket::udp_bind);
+        self.register("std.net.udpSend", socket::udp_send);
+        self.register("std.net.udpReceive", socket::udp_receive);
+        self.register("std.net.localPort", socket::local_port);
+        self.register("std.net.close", socket::close);
 
         // Channel functions (Go-style concurrency)
         self.register("std.chan.make", chan::make_chan);
@@ -134,6 +341,8 @@ fn register_all(&mut self) {
         self.register("std.string.length", string::length);
         self.register("std.string.upper", string::upper);
         self.register("std.string.lower", string::lower);
+        self.register("std.string.toUpper", string::upper);
+        self.register("std.string.toLower", string::lower);
         self.register("std.string.trim", string::trim);
         self.register("std.string.trimStart", string::trim_start);
         self.register("std.string.trimEnd", string::trim_end);
@@ -150,7 +359,20 @@ fn register_all(&mut self) {
         self.register("std.string.padStart", string::pad_start);
         self.register("std.string.padEnd", string::pad_end);
         self.register("std.string.chars", string::chars);
+        self.register("std.string.charAt", string::char_at);
+        self.register("std.string.codePointAt", string::code_point_at);
+        self.register("std.string.graphemes", string::graphemes);
         self.register("std.string.isEmpty", string::is_empty);
+        self.register("std.string.format", string::format);
+
+        // Bytes functions
+        self.register("std.bytes.fromString", bytes::from_string);
+        self.register("std.bytes.toString", bytes::to_string);
+        self.register("std.bytes.fromArray", bytes::from_array);
+        self.register("std.bytes.toArray", bytes::to_array);
+        self.register("std.bytes.length", bytes::length);
+        self.register("std.bytes.slice", bytes::slice);
+        self.register("std.bytes.concat", bytes::concat);
 
         // Array functions
         self.register("std.array.length", array::length);
@@ -169,6 +391,37 @@ fn register_all(&mut self) {
         self.register("std.array.flatten", array::flatten);
         self.register("std.array.unique", array::unique);
         self.register("std.array.zip", array::zip);
+        self.register_higher_order("std.array.map", array::map);
+        self.register_higher_order("std.array.filter", array::filter);
+        self.register_higher_order("std.array.reduce", array::reduce);
+        self.register_higher_order("std.array.sort", array::sort);
+
+        // Map functions
+        self.register("std.map.new", map::new);
+        self.register("std.map.get", map::get);
+        self.register("std.map.set", map::set);
+        self.register("std.map.remove", map::remove);
+        self.register("std.map.has", map::has);
+        self.register("std.map.size", map::size);
+        self.register("std.map.keys", map::keys);
+        self.register("std.map.values", map::values);
+        self.register("std.map.entries", map::entries);
+        self.register("std.map.merge", map::merge);
+        self.register_higher_order("std.map.forEach", map::for_each);
+
+        // Regex functions
+        self.register("std.regex.match", regex::is_match);
+        self.register("std.regex.find", regex::find);
+        self.register("std.regex.findAll", regex::find_all);
+        self.register("std.regex.replace", regex::replace);
+        self.register("std.regex.split", regex::split);
+        self.register("std.regex.captures", regex::captures);
+
+        // CSV functions
+        self.register("std.csv.parse", csv::parse);
+        self.register("std.csv.stringify", csv::stringify);
+        self.register_gated("std.csv.forEachRow", csv::for_each_row, csv::require_rows);
+        self.register_gated("std.io.forEachLine", io::for_each_line, io::require_lines);
     }
 
     /// Register a function
@@ -176,19 +429,58 @@ fn register(&mut self, name: &str, func: StdlibFn) {
         self.functions.insert(name.to_string(), func);
     }
 
+    /// Register a function that calls back into the program
+    fn register_higher_order(&mut self, name: &str, func: HigherOrderFn) {
+        self.higher_order.insert(name.to_string(), func);
+    }
+
+    /// Register a higher-order function guarded by a consent gate
+    fn register_gated(&mut self, name: &str, func: HigherOrderFn, gate: ConsentGate) {
+        self.register_higher_order(name, func);
+        self.gates.insert(name.to_string(), gate);
+    }
+
     /// Get a function by name
     pub fn get(&self, name: &str) -> Option<&StdlibFn> {
         self.functions.get(name)
     }
 
+    /// Get a higher-order function by name
+    pub fn get_higher_order(&self, name: &str) -> Option<HigherOrderFn> {
+        self.higher_order.get(name).copied()
+    }
+
+    /// Run the consent gate for a higher-order function, if it has one
+    pub fn authorize(
+        &self,
+        name: &str,
+        args: &[Value],
+        capabilities: &mut CapabilityRegistry,
+    ) -> Result<(), StdlibError> {
+        match self.gates.get(name) {
+            Some(gate) => gate(args, capabilities),
+            None => Ok(()),
+        }
+    }
+
     /// Check if a function exists
     pub fn has(&self, name: &str) -> bool {
-        self.functions.contains_key(name)
+        self.functions.contains_key(name) || self.higher_order.contains_key(name)
+    }
+
+    /// Check if any function belongs to `module`, e.g. `std.io`
+    pub fn has_module(&self, module: &str) -> bool {
+        let prefix = format!("{}.", module);
+        self.list().iter().any(|name| name.starts_with(&prefix))
     }
 
     /// List all available functions
     pub fn list(&self) -> Vec<&str> {
-        self.functions.keys().map(|s| s.as_str()).collect()
+        self.functions
+            .keys()
+            .chain(self.higher_order.keys())
+            .map(|s| s.as_str())
+            .collect()
     }
 
     /// Call a standard library function
@@ -200,6 +492,11 @@ pub fn call(
     ) -> Result<Value, StdlibError> {
         if let Some(func) = self.functions.get(name) {
             func(args, capabilities)
+        } else if self.higher_order.contains_key(name) {
+            Err(StdlibError::RuntimeError(format!(
+                "{} takes a function and must be called from a running program",
+                name
+            )))
         } else {
             Err(StdlibError::RuntimeError(format!(
                 "Unknown function: {}",
@@ -215,6 +512,12 @@ fn default() -> Self {
     }
 }
 
+/// Use `amount` of a quota, giving back the `Oops` to return if it has
+/// run out
+pub(crate) fn quota_exceeded(caps: &mut CapabilityRegistry, quota: Quota, amount: u64) -> Option<Value> {
+    caps.consume(quota, amount).err().map(|e| Value::oops(e.to_string()))
+}
+
 /// Helper to check argument count
 pub fn check_arity(args: &[Value], expected: usize) -> Result<(), StdlibError> {
     if args.len() != expected {
@@ -242,7 +545,7 @@ pub fn check_arity_range(args: &[Value], min: usize, max: usize) -> Result<(), S
 /// Helper to extract a string argument
 pub fn expect_string(value: &Value, _arg_name: &str) -> Result<String, StdlibError> {
     match value {
-        Value::String(s) => Ok(s.clone()),
+        Value::String(s) => Ok(s.to_string()),
         other => Err(StdlibError::TypeError {
             expected: "String".to_string(),
             got: format!("{:?}", other),
@@ -284,6 +587,35 @@ pub fn expect_bool(value: &Value, _arg_name: &str) -> Result<bool, StdlibError>
     }
 }
 
+/// Helper to extract bytes
+pub fn expect_bytes(value: &Value, _arg_name: &str) -> Result<Vec<u8>, StdlibError> {
+    match value {
+        Value::Bytes(b) => Ok(b.clone()),
+        other => Err(StdlibError::TypeError {
+            expected: "Bytes".to_string(),
+            got: format!("{:?}", other),
+        }),
+    }
+}
+
+/// Helper to extract binary data from bytes, or from a string as UTF-8
+pub fn expect_data(value: &Value, _arg_name: &str) -> Result<Vec<u8>, StdlibError> {
+    match value {
+        Value::Bytes(b) => Ok(b.clone()),
+        Value::String(s) => Ok(s.as_bytes().to_vec()),
+        other => Err(StdlibError::TypeError {
+            expected: "String or Bytes".to_string(),
+            got: format!("{:?}", other),
+        }),
+    }
+}
+
+/// A string value, for the modules' tests
+#[cfg(test)]
+pub(crate) fn s(value: &str) -> Value {
+    Value::String(value.to_string().into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -292,12 +624,74 @@ mod tests {
     fn test_registry_creation() {
         let registry = StdlibRegistry::new();
         assert!(registry.has("std.math.abs"));
+        assert!(registry.has("std.math.shuffle"));
         assert!(registry.has("std.io.readFile"));
         assert!(registry.has("std.json.parse"));
         assert!(registry.has("std.time.now"));
+        assert!(registry.has("std.string.toUpper"));
+        assert!(registry.has("std.string.toLower"));
+        assert!(registry.has("std.array.map"));
+        assert!(registry.get_higher_order("std.array.sort").is_some());
+        assert!(registry.has("std.map.merge"));
+        assert!(registry.has("std.regex.captures"));
+        assert!(registry.get_higher_order("std.map.forEach").is_some());
+        assert!(registry.has("std.csv.stringify"));
+        assert!(registry.has("std.config.parseYaml"));
+        assert!(registry.get_higher_order("std.net.serve").is_some());
+        assert!(registry.has("std.net.tcpReceive"));
+        assert!(registry.has("std.net.udpSend"));
+        assert!(registry.has("std.env.args"));
+        assert!(registry.has("std.process.spawn"));
+        assert!(registry.has("std.ui.notify"));
+        assert!(registry.has("std.superpowers.renounce"));
+        assert!(registry.has("std.term.progressBar"));
+        assert!(registry.has("std.crypto.hmac"));
+        assert!(registry.has("std.encoding.urlDecode"));
+        assert!(registry.has("std.encoding.hexDecodeBytes"));
+        assert!(registry.has("std.io.readBytes"));
+        assert!(registry.has("std.io.streamWrite"));
+        assert!(registry.has("std.io.walk"));
+        assert!(registry.has("std.archive.tarExtract"));
+        assert!(registry.get_higher_order("std.io.forEachLine").is_some());
+        assert!(registry.has("std.bytes.slice"));
+        assert!(registry.has("std.path.normalize"));
+        assert!(registry.has("std.log.warn"));
+        assert!(registry.has("std.test.assertOops"));
+        assert!(registry.has("std.time.addDays"));
+        assert!(registry.has("std.time.duration"));
+        assert!(registry.get_higher_order("std.csv.forEachRow").is_some());
         assert!(!registry.has("nonexistent"));
     }
 
+    #[test]
+    fn test_sandboxed_registry() {
+        let registry = StdlibRegistry::sandboxed();
+        assert!(!registry.has("std.net.httpGet"));
+        assert!(!registry.has("std.net.serve"));
+        assert!(!registry.has("std.process.run"));
+        assert!(!registry.has("std.env.get"));
+        assert!(!registry.has("std.ui.clipboardRead"));
+        assert!(registry.has("std.env.args"));
+        assert!(registry.has("std.io.readFile"));
+        assert!(registry.get_higher_order("std.io.forEachLine").is_some());
+    }
+
+    #[test]
+    fn test_authorize_runs_gates() {
+        let registry = StdlibRegistry::new();
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+
+        let args = [Value::String("data.csv".to_string().into()), Value::String("f".to_string().into())];
+        assert!(matches!(
+            registry.authorize("std.csv.forEachRow", &args, &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+        // Functions without a gate need no capabilities
+        assert!(registry.authorize("std.array.map", &args, &mut caps).is_ok());
+    }
+
     #[test]
     fn test_check_arity() {
         let args = vec![Value::Int(1), Value::Int(2)];
@@ -3,10 +3,17 @@
 //! HTTP and network operations that require explicit consent.
 
 use crate::interpreter::Value;
-use crate::security::{Capability, CapabilityRegistry};
-use super::{check_arity, check_arity_range, expect_string, StdlibError};
+use crate::security::{limits, Capability, CapabilityRegistry, Quota};
+use super::{
+    check_arity, check_arity_range, expect_int, expect_string, json, quota_exceeded, Invoke,
+    StdlibError,
+};
+use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Read, Write};
-use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::net::{IpAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
 use std::time::Duration;
 
 /// Maximum response size (10 MB) - reserved for future streaming implementation
@@ -98,9 +105,9 @@ fn is_private_ip(ip: &IpAddr) -> bool {
 }
 
 /// Helper to require network capability
-fn require_network(host: &str, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+pub(super) fn require_network(host: &str, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
     let cap = Capability::Network(Some(host.to_string()));
-    if caps.request("stdlib", &cap).is_err() {
+    if caps.request_current(&cap).is_err() {
         Err(StdlibError::PermissionDenied(format!(
             "Network access denied: {}",
             host
@@ -115,10 +122,10 @@ fn parse_url(url: &str) -> Result<(String, String, u16, String), StdlibError> {
     let url = url.trim();
 
     // Remove protocol
-    let (is_https, rest) = if url.starts_with("https://") {
-        (true, &url[8..])
-    } else if url.starts_with("http://") {
-        (false, &url[7..])
+    let (is_https, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
     } else {
         (false, url)
     };
@@ -171,9 +178,13 @@ pub fn http_get(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value,
         ));
     }
 
+    if let Some(oops) = quota_exceeded(caps, Quota::NetworkRequests, 1) {
+        return Ok(oops);
+    }
+
     // Make HTTP request
     let response = http_request(&host, port, "GET", &path, None, None)?;
-    Ok(Value::String(response))
+    Ok(Value::String(response.into()))
 }
 
 /// Make an HTTP POST request
@@ -203,9 +214,13 @@ pub fn http_post(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value,
         ));
     }
 
+    if let Some(oops) = quota_exceeded(caps, Quota::NetworkRequests, 1) {
+        return Ok(oops);
+    }
+
     // Make HTTP request
     let response = http_request(&host, port, "POST", &path, Some(&body), Some(&content_type))?;
-    Ok(Value::String(response))
+    Ok(Value::String(response.into()))
 }
 
 /// Download a file from a URL
@@ -224,7 +239,7 @@ pub fn download(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value,
 
     // Check file write capability
     let file_cap = Capability::FileWrite(Some(std::path::PathBuf::from(&dest_path)));
-    if caps.request("stdlib", &file_cap).is_err() {
+    if caps.request_current(&file_cap).is_err() {
         return Err(StdlibError::PermissionDenied(format!(
             "File write access denied: {}",
             dest_path
@@ -238,8 +253,15 @@ pub fn download(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value,
         ));
     }
 
+    if let Some(oops) = quota_exceeded(caps, Quota::NetworkRequests, 1) {
+        return Ok(oops);
+    }
+
     // Make HTTP request
     let response = http_request_binary(&host, port, "GET", &path)?;
+    if let Some(oops) = quota_exceeded(caps, Quota::WriteBytes, response.len() as u64) {
+        return Ok(oops);
+    }
 
     // Write to file
     std::fs::write(&dest_path, response)
@@ -415,6 +437,400 @@ fn read_chunked_body<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, StdlibError>
     Ok(body)
 }
 
+// === HTTP server ===
+
+/// Default number of connection worker threads for `serve`
+const DEFAULT_SERVER_WORKERS: i64 = 4;
+
+/// Maximum number of connection worker threads for `serve`
+const MAX_SERVER_WORKERS: i64 = 64;
+
+/// Maximum request body accepted by `serve` (1 MB)
+const MAX_REQUEST_BODY: usize = 1024 * 1024;
+
+/// Maximum number of request headers accepted by `serve`
+const MAX_REQUEST_HEADERS: usize = 100;
+
+/// Maximum length of the request line or a header accepted by `serve`
+const MAX_LINE: u64 = 8 * 1024;
+
+/// Settings for `serve`, taken from its arguments
+struct ServeOptions {
+    host: String,
+    port: u16,
+    max_requests: Option<usize>,
+    workers: usize,
+}
+
+/// A request read by a connection worker, handed to the program thread
+struct HttpRequest {
+    method: String,
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+/// A response produced by the handler, handed back to the connection worker
+struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl HttpRequest {
+    /// The request map passed to the handler
+    fn to_value(&self) -> Value {
+        let text = |s: &str| Value::String(s.to_string().into());
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), text(value)))
+            .collect();
+        Value::Record(HashMap::from([
+            ("method".to_string(), text(&self.method)),
+            ("path".to_string(), text(&self.path)),
+            ("query".to_string(), text(&self.query)),
+            ("headers".to_string(), Value::Record(headers)),
+            ("body".to_string(), text(&self.body)),
+        ]))
+    }
+}
+
+impl HttpResponse {
+    fn plain(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    /// Build a response from the handler's return value
+    ///
+    /// A map may hold `status` (default 200), `headers` and `body`; a
+    /// non-string body is sent as JSON. Any other value is the body itself.
+    fn from_value(value: &Value) -> Result<Self, StdlibError> {
+        let map = match value {
+            Value::Record(map) => map,
+            Value::String(body) => return Ok(Self::plain(200, body)),
+            other => return Ok(Self::json(200, other)),
+        };
+
+        let status = match map.get("status") {
+            None => 200,
+            Some(Value::Int(code)) if (100..=599).contains(code) => *code as u16,
+            Some(other) => {
+                return Err(StdlibError::TypeError {
+                    expected: "status code between 100 and 599".to_string(),
+                    got: format!("{:?}", other),
+                })
+            }
+        };
+
+        let mut response = match map.get("body") {
+            None => Self::plain(status, ""),
+            Some(Value::String(body)) => Self::plain(status, body),
+            Some(other) => Self::json(status, other),
+        };
+
+        match map.get("headers") {
+            None => {}
+            Some(Value::Record(headers)) => {
+                let mut headers: Vec<_> = headers.iter().collect();
+                headers.sort_by(|a, b| a.0.cmp(b.0));
+                for (name, value) in headers {
+                    let value = expect_string(value, "header")?;
+                    if [name.as_str(), value.as_str()]
+                        .iter()
+                        .any(|s| s.contains(['\r', '\n']))
+                    {
+                        return Err(StdlibError::NetworkError(format!(
+                            "Invalid response header: {}",
+                            name
+                        )));
+                    }
+                    response.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+                    response.headers.push((name.clone(), value));
+                }
+            }
+            Some(other) => {
+                return Err(StdlibError::TypeError {
+                    expected: "Map of headers".to_string(),
+                    got: format!("{:?}", other),
+                })
+            }
+        }
+        Ok(response)
+    }
+
+    fn json(status: u16, value: &Value) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: json::to_json(value).to_string(),
+        }
+    }
+
+    fn write_to(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason_phrase(self.status));
+        if !self.headers.iter().any(|(n, _)| n.eq_ignore_ascii_case("content-type")) {
+            head.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+        }
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        head.push_str("Connection: close\r\n\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(self.body.as_bytes())?;
+        stream.flush()
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+fn serve_options(args: &[Value]) -> Result<ServeOptions, StdlibError> {
+    check_arity_range(args, 2, 3)?;
+    let port = expect_int(&args[0], "port")?;
+    let port = u16::try_from(port)
+        .ok()
+        .filter(|p| *p != 0)
+        .ok_or_else(|| StdlibError::NetworkError(format!("Invalid port: {}", port)))?;
+
+    let mut options = ServeOptions {
+        host: "127.0.0.1".to_string(),
+        port,
+        max_requests: None,
+        workers: DEFAULT_SERVER_WORKERS as usize,
+    };
+    let settings = match args.get(2) {
+        None => return Ok(options),
+        Some(Value::Record(settings)) => settings,
+        Some(other) => {
+            return Err(StdlibError::TypeError {
+                expected: "Map of server options".to_string(),
+                got: format!("{:?}", other),
+            })
+        }
+    };
+    if let Some(host) = settings.get("host") {
+        options.host = expect_string(host, "host")?;
+    }
+    if let Some(max) = settings.get("maxRequests") {
+        match expect_int(max, "maxRequests")? {
+            n if n > 0 => options.max_requests = Some(n as usize),
+            n => {
+                return Err(StdlibError::RuntimeError(format!(
+                    "maxRequests must be positive, got {}",
+                    n
+                )))
+            }
+        }
+    }
+    if let Some(workers) = settings.get("workers") {
+        match expect_int(workers, "workers")? {
+            n if (1..=MAX_SERVER_WORKERS).contains(&n) => options.workers = n as usize,
+            n => {
+                return Err(StdlibError::RuntimeError(format!(
+                    "workers must be between 1 and {}, got {}",
+                    MAX_SERVER_WORKERS, n
+                )))
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Consent gate for `serve`: listening needs `Network` for the bound address
+pub fn require_serve(args: &[Value], caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    let options = serve_options(args)?;
+    require_network(&format!("{}:{}", options.host, options.port), caps)
+}
+
+/// Read one line of a request, refusing one longer than [`MAX_LINE`]
+/// rather than holding however much the client sends
+fn read_line(reader: &mut impl BufRead) -> Result<String, String> {
+    let mut line = String::new();
+    reader.take(MAX_LINE).read_line(&mut line).map_err(|e| e.to_string())?;
+    if line.len() as u64 >= MAX_LINE && !line.ends_with('\n') {
+        return Err("Request line or header too long".to_string());
+    }
+    Ok(line)
+}
+
+/// Read one HTTP request from a connection
+fn read_request(stream: &TcpStream) -> Result<HttpRequest, String> {
+    let mut reader = BufReader::new(stream);
+    let line = read_line(&mut reader)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err("Malformed request line".to_string());
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = HttpRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        headers: Vec::new(),
+        body: String::new(),
+    };
+
+    let mut content_length = 0;
+    loop {
+        let header = read_line(&mut reader)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if request.headers.len() >= MAX_REQUEST_HEADERS {
+            return Err("Too many headers".to_string());
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| format!("Malformed header: {}", header))?;
+        let (name, value) = (name.trim().to_lowercase(), value.trim().to_string());
+        if name == "content-length" {
+            content_length = value
+                .parse()
+                .map_err(|_| "Invalid Content-Length".to_string())?;
+        }
+        request.headers.push((name, value));
+    }
+
+    if content_length > MAX_REQUEST_BODY {
+        return Err("Request body too large".to_string());
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    request.body = String::from_utf8_lossy(&body).into_owned();
+    Ok(request)
+}
+
+type PendingRequest = (HttpRequest, mpsc::Sender<HttpResponse>);
+
+/// Serve one connection: read the request, wait for the program's response
+fn handle_connection(mut stream: TcpStream, requests: &mpsc::Sender<PendingRequest>) {
+    stream.set_read_timeout(Some(Duration::from_secs(30))).ok();
+    stream.set_write_timeout(Some(Duration::from_secs(30))).ok();
+
+    let response = match read_request(&stream) {
+        Ok(request) => {
+            let (reply, response) = mpsc::channel();
+            match requests.send((request, reply)) {
+                Ok(()) => response
+                    .recv()
+                    .unwrap_or_else(|_| HttpResponse::plain(503, "Server stopped")),
+                Err(_) => HttpResponse::plain(503, "Server stopped"),
+            }
+        }
+        Err(message) => HttpResponse::plain(400, &message),
+    };
+    response.write_to(&mut stream).ok();
+}
+
+/// Run an HTTP server, calling the handler with each request
+///
+/// `serve(port, handler)` listens on 127.0.0.1 and calls
+/// `handler(request)` with a map of `method`, `path`, `query`, `headers`
+/// (lower-cased names) and `body`. The handler returns a response map of
+/// `status`, `headers` and `body`, or just a body. An optional third argument
+/// is a map of options: `host`, `workers` (connection threads, default 4)
+/// and `maxRequests`, after which the server stops and returns the number
+/// of requests handled. It also stops when the run's timeout comes.
+///
+/// Connections are accepted and parsed by worker threads; the handler runs
+/// on the program's thread, one request at a time. The caller must hold the
+/// `Network` capability for the bound address (see [`require_serve`]).
+pub fn serve(args: &[Value], invoke: &mut Invoke) -> Result<Value, StdlibError> {
+    let options = serve_options(args)?;
+    let listener = TcpListener::bind((options.host.as_str(), options.port))
+        .map_err(|e| StdlibError::NetworkError(format!("Cannot listen on port {}: {}", options.port, e)))?;
+    let address = listener
+        .local_addr()
+        .map_err(|e| StdlibError::NetworkError(e.to_string()))?;
+    let listener = Arc::new(listener);
+    let stopping = Arc::new(AtomicBool::new(false));
+
+    let (requests, pending) = mpsc::channel::<PendingRequest>();
+    let workers: Vec<_> = (0..options.workers)
+        .map(|_| {
+            let listener = Arc::clone(&listener);
+            let stopping = Arc::clone(&stopping);
+            let requests = requests.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopping.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        handle_connection(stream, &requests);
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(requests);
+
+    let mut handled = 0;
+    let mut outcome = Ok(());
+    loop {
+        let next = match limits::time_left() {
+            Some(left) => pending.recv_timeout(left).ok(),
+            None => pending.recv().ok(),
+        };
+        let Some((request, reply)) = next else {
+            break;
+        };
+        let response = invoke(&args[1], vec![request.to_value()])
+            .and_then(|value| HttpResponse::from_value(&value));
+        handled += 1;
+        match response {
+            Ok(response) => {
+                reply.send(response).ok();
+            }
+            Err(e) => {
+                reply.send(HttpResponse::plain(500, "Internal Server Error")).ok();
+                outcome = Err(e);
+                break;
+            }
+        }
+        if options.max_requests.is_some_and(|max| handled >= max) {
+            break;
+        }
+    }
+
+    // Wake every worker blocked in accept so it sees the stop flag
+    stopping.store(true, Ordering::SeqCst);
+    drop(pending);
+    for _ in &workers {
+        TcpStream::connect(address).ok();
+    }
+    for worker in workers {
+        worker.join().ok();
+    }
+
+    outcome.map(|()| Value::Int(handled as i64))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -498,4 +914,127 @@ fn test_is_private_ip() {
         assert!(!is_private_ip(&IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))));
         assert!(!is_private_ip(&IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))));
     }
+
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    /// Send a raw request once the server is up, returning the raw response
+    fn send_request(port: u16, request: &'static str) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            for _ in 0..100 {
+                if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", port)) {
+                    stream.write_all(request.as_bytes()).unwrap();
+                    let mut response = String::new();
+                    stream.read_to_string(&mut response).unwrap();
+                    return response;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            panic!("server did not start");
+        })
+    }
+
+    fn options(max_requests: i64) -> Value {
+        Value::Record(HashMap::from([
+            ("maxRequests".to_string(), Value::Int(max_requests)),
+            ("workers".to_string(), Value::Int(2)),
+        ]))
+    }
+
+    #[test]
+    fn test_serve_round_trip() {
+        let port = free_port();
+        let client = send_request(
+            port,
+            "POST /greet?lang=woke HTTP/1.1\r\nHost: test\r\nContent-Length: 3\r\n\r\nAda",
+        );
+
+        let mut handler = |_: &Value, args: Vec<Value>| {
+            let Value::Record(request) = &args[0] else {
+                panic!("expected request map");
+            };
+            assert_eq!(request["method"], Value::String("POST".to_string().into()));
+            assert_eq!(request["query"], Value::String("lang=woke".to_string().into()));
+            let Value::Record(headers) = &request["headers"] else {
+                panic!("expected header map");
+            };
+            assert_eq!(headers["host"], Value::String("test".to_string().into()));
+            let body = format!("{} says hi from {}", request["body"], request["path"]);
+            Ok(Value::Record(HashMap::from([
+                ("status".to_string(), Value::Int(201)),
+                ("body".to_string(), Value::String(body.into())),
+                (
+                    "headers".to_string(),
+                    Value::Record(HashMap::from([(
+                        "X-Woke".to_string(),
+                        Value::String("yes".to_string().into()),
+                    )])),
+                ),
+            ])))
+        };
+        let handled = serve(
+            &[Value::Int(port as i64), Value::String("handler".to_string().into()), options(1)],
+            &mut handler,
+        )
+        .unwrap();
+        assert_eq!(handled, Value::Int(1));
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 201 Created\r\n"), "{}", response);
+        assert!(response.contains("X-Woke: yes\r\n"));
+        assert!(response.ends_with("\r\n\r\nAda says hi from /greet"));
+    }
+
+    #[test]
+    fn test_request_lines_are_limited() {
+        let mut reader = std::io::Cursor::new(format!("GET / HTTP/1.1\r\n{}", "a".repeat(100_000)));
+        assert_eq!(read_line(&mut reader).unwrap(), "GET / HTTP/1.1\r\n");
+        assert!(read_line(&mut reader).is_err());
+        // Only as much as a line may hold was read
+        assert_eq!(reader.position(), 16 + MAX_LINE);
+        assert_eq!(read_line(&mut std::io::Cursor::new("end")).unwrap(), "end");
+    }
+
+    #[test]
+    fn test_serve_json_body_and_handler_errors() {
+        let port = free_port();
+        let client = send_request(port, "GET / HTTP/1.1\r\n\r\n");
+        let mut handler = |_: &Value, _: Vec<Value>| Ok(Value::Array(vec![Value::Int(1)].into()));
+        serve(&[Value::Int(port as i64), Value::Unit, options(1)], &mut handler).unwrap();
+        let response = client.join().unwrap();
+        assert!(response.contains("Content-Type: application/json\r\n"));
+        assert!(response.ends_with("\r\n\r\n[1]"));
+
+        let port = free_port();
+        let client = send_request(port, "GET / HTTP/1.1\r\n\r\n");
+        let mut handler = |_: &Value, _: Vec<Value>| {
+            Ok(Value::Record(HashMap::from([(
+                "status".to_string(),
+                Value::Int(42),
+            )])))
+        };
+        let result = serve(&[Value::Int(port as i64), Value::Unit, options(5)], &mut handler);
+        assert!(result.is_err());
+        assert!(client.join().unwrap().starts_with("HTTP/1.1 500"));
+    }
+
+    #[test]
+    fn test_require_serve() {
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+        let args = [Value::Int(8080), Value::Unit];
+        assert!(matches!(
+            require_serve(&args, &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+        assert!(require_serve(&args, &mut CapabilityRegistry::permissive()).is_ok());
+        assert!(require_serve(&[Value::Int(70000), Value::Unit], &mut caps).is_err());
+        assert!(serve_options(&[Value::Int(80), Value::Unit, options(0)]).is_err());
+    }
 }
@@ -0,0 +1,199 @@
+//! WokeLang Standard Library - Path Module
+//!
+//! Path manipulation without string concatenation. Everything except
+//! `exists` works on the path text alone and needs no consent; `absolute`
+//! only reads the current directory. File capabilities are requested for
+//! the normalized path, so `./notes.txt` and `notes.txt` ask for the same
+//! permission.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, expect_string, io, StdlibError};
+use std::path::{Component, Path, PathBuf};
+
+/// Lexically normalize a path: drop `.` components and fold `..` into the
+/// component before it where there is one
+///
+/// The file system is not consulted, so symlinks are not resolved. Leading
+/// `..` components of a relative path are kept, and `..` above the root is
+/// dropped. An empty result is `.`.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut parts: Vec<Component> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match parts.last() {
+                Some(Component::Normal(_)) => {
+                    parts.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => parts.push(component),
+            },
+            other => parts.push(other),
+        }
+    }
+
+    if parts.is_empty() {
+        PathBuf::from(".")
+    } else {
+        parts.iter().collect()
+    }
+}
+
+fn path_value(path: &Path) -> Value {
+    Value::String(path.to_string_lossy().into_owned().into())
+}
+
+/// Join path parts: join(base, part, ...)
+///
+/// An absolute part replaces everything before it, as with most path APIs.
+pub fn join(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    if args.is_empty() {
+        return Err(StdlibError::ArityError {
+            expected: 1,
+            got: 0,
+        });
+    }
+    let mut path = PathBuf::new();
+    for arg in args {
+        path.push(expect_string(arg, "part")?);
+    }
+    Ok(path_value(&path))
+}
+
+/// The directory part of a path: `.` for a bare file name
+pub fn dirname(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = PathBuf::from(expect_string(&args[0], "path")?);
+    Ok(match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => Value::String(".".to_string().into()),
+        Some(parent) => path_value(parent),
+        // The root, or an empty path
+        None if path.has_root() => path_value(&path),
+        None => Value::String(".".to_string().into()),
+    })
+}
+
+/// The final component of a path, or "" if there is none
+pub fn basename(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = PathBuf::from(expect_string(&args[0], "path")?);
+    Ok(Value::String(
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default().into(),
+    ))
+}
+
+/// The extension of the final component without the dot, or ""
+pub fn extension(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = PathBuf::from(expect_string(&args[0], "path")?);
+    Ok(Value::String(
+        path.extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_default().into(),
+    ))
+}
+
+/// Normalize a path without touching the file system
+pub fn normalize(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = expect_string(&args[0], "path")?;
+    Ok(path_value(&normalize_path(Path::new(&path))))
+}
+
+/// Make a path absolute against the current directory, then normalize it
+pub fn absolute(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = PathBuf::from(expect_string(&args[0], "path")?);
+    let full = if path.is_absolute() {
+        path
+    } else {
+        std::env::current_dir()
+            .map_err(|e| StdlibError::IoError(e.to_string()))?
+            .join(path)
+    };
+    Ok(path_value(&normalize_path(&full)))
+}
+
+/// Check whether a path exists; needs read consent for the normalized path
+pub fn exists(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let path = expect_string(&args[0], "path")?;
+    io::exists(&[path_value(&normalize_path(Path::new(&path)))], caps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn call(
+        func: fn(&[Value], &mut CapabilityRegistry) -> Result<Value, StdlibError>,
+        path: &str,
+    ) -> Value {
+        func(&[s(path)], &mut test_caps()).unwrap()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_join() {
+        let mut caps = test_caps();
+        assert_eq!(join(&[s("data"), s("2024"), s("notes.txt")], &mut caps).unwrap(), s("data/2024/notes.txt"));
+        assert_eq!(join(&[s("data/"), s("notes.txt")], &mut caps).unwrap(), s("data/notes.txt"));
+        assert_eq!(join(&[s("data"), s("/etc")], &mut caps).unwrap(), s("/etc"));
+        assert!(join(&[], &mut caps).is_err());
+        assert!(join(&[s("data"), Value::Int(1)], &mut caps).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_components() {
+        assert_eq!(call(dirname, "data/notes.txt"), s("data"));
+        assert_eq!(call(dirname, "notes.txt"), s("."));
+        assert_eq!(call(dirname, "/"), s("/"));
+        assert_eq!(call(basename, "data/notes.txt"), s("notes.txt"));
+        assert_eq!(call(basename, "/"), s(""));
+        assert_eq!(call(extension, "archive.tar.gz"), s("gz"));
+        assert_eq!(call(extension, ".bashrc"), s(""));
+        assert_eq!(call(extension, "README"), s(""));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_normalize() {
+        assert_eq!(call(normalize, "./data//2024/../notes.txt"), s("data/notes.txt"));
+        assert_eq!(call(normalize, "../../x/./y"), s("../../x/y"));
+        assert_eq!(call(normalize, "/../etc"), s("/etc"));
+        assert_eq!(call(normalize, "a/.."), s("."));
+        assert_eq!(call(normalize, ""), s("."));
+
+        let Value::String(abs) = call(absolute, "data/../notes.txt") else {
+            panic!("expected a string");
+        };
+        assert!(Path::new(abs.as_str()).is_absolute());
+        assert!(abs.ends_with("/notes.txt") && !abs.contains(".."));
+    }
+
+    #[test]
+    fn test_exists_uses_normalized_path() {
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+        caps.grant(
+            "stdlib",
+            crate::security::Capability::FileRead(Some(PathBuf::from("Cargo.toml"))),
+            "test",
+        );
+
+        assert_eq!(exists(&[s("./src/../Cargo.toml")], &mut caps).unwrap(), Value::Bool(true));
+        assert!(exists(&[s("src/main.rs")], &mut caps).is_err());
+        // `..` that escapes the start is still refused
+        assert!(exists(&[s("../Cargo.toml")], &mut test_caps()).is_err());
+    }
+}
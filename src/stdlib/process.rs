@@ -0,0 +1,316 @@
+//! WokeLang Standard Library - Process Module
+//!
+//! Running external commands. Each program needs its own
+//! `Execute(program)` capability, so the user is asked about every command
+//! separately and each use appears in the capability audit log.
+
+use crate::interpreter::Value;
+use crate::security::{limits, Capability, CapabilityRegistry, Quota};
+use super::{check_arity, check_arity_range, expect_int, expect_string, quota_exceeded, StdlibError};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A spawned child and the threads collecting its output
+struct Running {
+    child: Child,
+    stdout: JoinHandle<Vec<u8>>,
+    stderr: JoinHandle<Vec<u8>>,
+}
+
+thread_local! {
+    static CHILDREN: RefCell<(i64, HashMap<i64, Running>)> = RefCell::new((0, HashMap::new()));
+}
+
+/// Helper to require execute capability for one program
+fn require_execute(program: &str, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    let cap = Capability::Execute(Some(program.to_string()));
+    if caps.request_current(&cap).is_err() {
+        Err(StdlibError::PermissionDenied(format!(
+            "Execute access denied: {}",
+            program
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Build a command from `(program, args[, options])`
+///
+/// Options are a map that may hold `cwd` (working directory) and, for
+/// `run`, `stdin` (text written to the command's input).
+fn command(args: &[Value]) -> Result<(String, Command, Option<String>), StdlibError> {
+    let program = expect_string(&args[0], "program")?;
+    if program.is_empty() || program.contains('\0') {
+        return Err(StdlibError::RuntimeError(format!(
+            "Invalid program name: {:?}",
+            program
+        )));
+    }
+    let arguments = match &args[1] {
+        Value::Array(items) => items
+            .iter()
+            .map(|a| expect_string(a, "argument"))
+            .collect::<Result<Vec<_>, _>>()?,
+        other => {
+            return Err(StdlibError::TypeError {
+                expected: "Array of argument strings".to_string(),
+                got: format!("{:?}", other),
+            })
+        }
+    };
+
+    let mut cmd = Command::new(&program);
+    cmd.args(&arguments);
+    let mut input = None;
+    match args.get(2) {
+        None => {}
+        Some(Value::Record(options)) => {
+            if let Some(cwd) = options.get("cwd") {
+                cmd.current_dir(expect_string(cwd, "cwd")?);
+            }
+            if let Some(stdin) = options.get("stdin") {
+                input = Some(expect_string(stdin, "stdin")?);
+            }
+        }
+        Some(other) => {
+            return Err(StdlibError::TypeError {
+                expected: "Map of process options".to_string(),
+                got: format!("{:?}", other),
+            })
+        }
+    }
+    Ok((program, cmd, input))
+}
+
+/// The result map: `code`, `stdout` and `stderr`
+///
+/// `code` is -1 if the process was ended by a signal.
+fn outcome(status: ExitStatus, stdout: &[u8], stderr: &[u8]) -> Value {
+    let text = |bytes: &[u8]| Value::String(String::from_utf8_lossy(bytes).into_owned().into());
+    Value::Okay(Box::new(Value::Record(HashMap::from([
+        ("code".to_string(), Value::Int(status.code().unwrap_or(-1) as i64)),
+        ("stdout".to_string(), text(stdout)),
+        ("stderr".to_string(), text(stderr)),
+    ]))))
+}
+
+fn collect(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        pipe.read_to_end(&mut buf).ok();
+        buf
+    })
+}
+
+/// Wait for `child` to finish, stopping it when the run making the call
+/// has no time left
+fn finish(child: &mut Child) -> io::Result<ExitStatus> {
+    let Some(left) = limits::time_left() else {
+        return child.wait();
+    };
+    let polls = left.as_millis() / 10;
+    for _ in 0..polls {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    child.kill().ok();
+    child.wait()
+}
+
+/// Run a command to completion: run(program, args[, options])
+///
+/// Returns Okay with a map of `code`, `stdout` and `stderr` once the
+/// command finishes, whatever its exit code, or Oops if it could not be
+/// started. The program is not run through a shell.
+pub fn run(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 2, 3)?;
+    let (program, mut cmd, input) = command(args)?;
+    require_execute(&program, caps)?;
+    if let Some(oops) = quota_exceeded(caps, Quota::Processes, 1) {
+        return Ok(oops);
+    }
+
+    cmd.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => return Ok(Value::oops(format!("{}: {}", program, e))),
+    };
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        // Write from another thread so a chatty child cannot deadlock us
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+
+    let stdout = collect(child.stdout.take().expect("stdout is piped"));
+    let stderr = collect(child.stderr.take().expect("stderr is piped"));
+    let status = finish(&mut child).map_err(|e| StdlibError::IoError(e.to_string()))?;
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    Ok(outcome(status, &stdout, &stderr))
+}
+
+/// Start a long-running command: spawn(program, args[, options]) -> handle
+///
+/// The child's output is collected in the background and returned by
+/// `wait`. Its input is empty.
+pub fn spawn(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 2, 3)?;
+    let (program, mut cmd, input) = command(args)?;
+    if input.is_some() {
+        return Err(StdlibError::RuntimeError(
+            "spawn does not support the stdin option".to_string(),
+        ));
+    }
+    require_execute(&program, caps)?;
+    if let Some(oops) = quota_exceeded(caps, Quota::Processes, 1) {
+        return Ok(oops);
+    }
+
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| StdlibError::IoError(format!("{}: {}", program, e)))?;
+    let running = Running {
+        stdout: collect(child.stdout.take().expect("stdout is piped")),
+        stderr: collect(child.stderr.take().expect("stderr is piped")),
+        child,
+    };
+
+    Ok(CHILDREN.with(|children| {
+        let (next, table) = &mut *children.borrow_mut();
+        *next += 1;
+        table.insert(*next, running);
+        Value::Int(*next)
+    }))
+}
+
+fn unknown_child(handle: i64) -> StdlibError {
+    StdlibError::RuntimeError(format!("Unknown or finished process: {}", handle))
+}
+
+/// Wait for a spawned child to finish, returning the same result as `run`
+pub fn wait(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let handle = expect_int(&args[0], "process")?;
+    let mut running = CHILDREN
+        .with(|children| children.borrow_mut().1.remove(&handle))
+        .ok_or_else(|| unknown_child(handle))?;
+
+    let status = finish(&mut running.child).map_err(|e| StdlibError::IoError(e.to_string()))?;
+    let stdout = running.stdout.join().unwrap_or_default();
+    let stderr = running.stderr.join().unwrap_or_default();
+    Ok(outcome(status, &stdout, &stderr))
+}
+
+/// Check whether a spawned child is still running
+pub fn is_running(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let handle = expect_int(&args[0], "process")?;
+    CHILDREN.with(|children| match children.borrow_mut().1.get_mut(&handle) {
+        Some(running) => running
+            .child
+            .try_wait()
+            .map(|status| Value::Bool(status.is_none()))
+            .map_err(|e| StdlibError::IoError(e.to_string())),
+        None => Err(unknown_child(handle)),
+    })
+}
+
+/// Stop a spawned child; `wait` still returns its output afterwards
+pub fn kill(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let handle = expect_int(&args[0], "process")?;
+    CHILDREN.with(|children| match children.borrow_mut().1.get_mut(&handle) {
+        Some(running) => Ok(Value::Bool(running.child.kill().is_ok())),
+        None => Err(unknown_child(handle)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::AuditAction;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn strings(items: &[&str]) -> Value {
+        Value::Array(items.iter().map(|item| s(item)).collect::<Vec<_>>().into())
+    }
+
+    fn result_map(value: Value) -> HashMap<String, Value> {
+        match value {
+            Value::Okay(inner) => match *inner {
+                Value::Record(map) => map,
+                other => panic!("expected a map, got {:?}", other),
+            },
+            other => panic!("expected Okay, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run() {
+        let mut caps = test_caps();
+        let script = strings(&["-c", "read name; echo hi $name; echo oops >&2; exit 3"]);
+        let options = Value::Record(HashMap::from([("stdin".to_string(), s("Ada\n"))]));
+
+        let result = result_map(run(&[s("sh"), script, options], &mut caps).unwrap());
+        assert_eq!(result["code"], Value::Int(3));
+        assert_eq!(result["stdout"], s("hi Ada\n"));
+        assert_eq!(result["stderr"], s("oops\n"));
+
+        let missing = run(&[s("wokelang-no-such-program"), strings(&[])], &mut caps).unwrap();
+        assert!(missing.is_oops());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_spawn_wait_and_kill() {
+        let mut caps = test_caps();
+        let child = spawn(&[s("sh"), strings(&["-c", "echo started"])], &mut caps).unwrap();
+        let result = result_map(wait(std::slice::from_ref(&child), &mut caps).unwrap());
+        assert_eq!(result["stdout"], s("started\n"));
+        assert!(wait(&[child], &mut caps).is_err());
+
+        let sleeper = spawn(&[s("sleep"), strings(&["30"])], &mut caps).unwrap();
+        assert_eq!(is_running(std::slice::from_ref(&sleeper), &mut caps).unwrap(), Value::Bool(true));
+        assert_eq!(kill(std::slice::from_ref(&sleeper), &mut caps).unwrap(), Value::Bool(true));
+        let result = result_map(wait(&[sleeper], &mut caps).unwrap());
+        assert_eq!(result["code"], Value::Int(-1));
+    }
+
+    #[test]
+    fn test_consent_is_per_command() {
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+        caps.grant("stdlib", Capability::Execute(Some("true".to_string())), "test");
+
+        assert!(matches!(
+            run(&[s("rm"), strings(&["-rf", "nothing"])], &mut caps),
+            Err(StdlibError::PermissionDenied(msg)) if msg.contains("rm")
+        ));
+        assert!(spawn(&[s("rm"), strings(&[])], &mut caps).is_err());
+
+        let denied = caps.get_audit_log().iter().any(|entry| {
+            matches!(entry.action, AuditAction::Denied)
+                && entry.capability == Capability::Execute(Some("rm".to_string()))
+        });
+        assert!(denied);
+
+        assert!(run(&[s("true"), s("not an array")], &mut caps).is_err());
+        assert!(run(&[s(""), strings(&[])], &mut caps).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! WokeLang Standard Library - Regex Module
+//!
+//! Regular expressions using the `regex` crate syntax. Every function returns
+//! a Result, so an invalid pattern is an `Oops` the program can inspect
+//! rather than a runtime error. Compiled patterns are cached per thread.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, expect_string, StdlibError};
+use ::regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Compiled patterns kept before the cache is cleared
+const CACHE_CAPACITY: usize = 64;
+
+thread_local! {
+    static CACHE: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+/// Compile a pattern, reusing a cached copy when possible
+fn compile(pattern: &str) -> Result<Regex, String> {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some(regex) = cache.get(pattern) {
+            return Ok(regex.clone());
+        }
+        let regex = Regex::new(pattern)
+            .map_err(|e| format!("invalid regex {:?}: {}", pattern, e))?;
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    })
+}
+
+/// Extract the pattern and text arguments and compile the pattern
+fn pattern_and_text(args: &[Value]) -> Result<Result<(Regex, String), Value>, StdlibError> {
+    let pattern = expect_string(&args[0], "pattern")?;
+    let text = expect_string(&args[1], "text")?;
+    Ok(match compile(&pattern) {
+        Ok(regex) => Ok((regex, text)),
+        Err(message) => Err(Value::oops(message)),
+    })
+}
+
+fn okay(value: Value) -> Value {
+    Value::Okay(Box::new(value))
+}
+
+fn strings<'a>(items: impl Iterator<Item = &'a str>) -> Value {
+    Value::Array(items.map(|s| Value::String(s.to_string().into())).collect::<Vec<_>>().into())
+}
+
+/// Check whether the pattern matches anywhere in the text
+pub fn is_match(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    Ok(match pattern_and_text(args)? {
+        Ok((regex, text)) => okay(Value::Bool(regex.is_match(&text))),
+        Err(oops) => oops,
+    })
+}
+
+/// First match in the text, or Oops if there is none
+pub fn find(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    Ok(match pattern_and_text(args)? {
+        Ok((regex, text)) => match regex.find(&text) {
+            Some(m) => okay(Value::String(m.as_str().to_string().into())),
+            None => Value::oops("no match"),
+        },
+        Err(oops) => oops,
+    })
+}
+
+/// All non-overlapping matches in the text
+pub fn find_all(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    Ok(match pattern_and_text(args)? {
+        Ok((regex, text)) => okay(strings(regex.find_iter(&text).map(|m| m.as_str()))),
+        Err(oops) => oops,
+    })
+}
+
+/// Replace every match; the replacement may refer to groups as `$1` or `${name}`
+pub fn replace(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 3)?;
+    let replacement = expect_string(&args[2], "replacement")?;
+    Ok(match pattern_and_text(args)? {
+        Ok((regex, text)) => okay(Value::String(
+            regex.replace_all(&text, replacement.as_str()).into_owned().into(),
+        )),
+        Err(oops) => oops,
+    })
+}
+
+/// Split the text on every match
+pub fn split(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    Ok(match pattern_and_text(args)? {
+        Ok((regex, text)) => okay(strings(regex.split(&text))),
+        Err(oops) => oops,
+    })
+}
+
+/// Capture groups of the first match, as a map
+///
+/// Groups are keyed by number (`"0"` is the whole match) and also by name
+/// for named groups. Groups that did not take part in the match are left out.
+pub fn captures(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let (regex, text) = match pattern_and_text(args)? {
+        Ok(compiled) => compiled,
+        Err(oops) => return Ok(oops),
+    };
+    let Some(caps) = regex.captures(&text) else {
+        return Ok(Value::oops("no match"));
+    };
+
+    let mut groups = HashMap::new();
+    for (i, name) in regex.capture_names().enumerate() {
+        if let Some(m) = caps.get(i) {
+            let value = Value::String(m.as_str().to_string().into());
+            if let Some(name) = name {
+                groups.insert(name.to_string(), value.clone());
+            }
+            groups.insert(i.to_string(), value);
+        }
+    }
+    Ok(okay(Value::Record(groups)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    fn okay_of(value: Value) -> Value {
+        Value::Okay(Box::new(value))
+    }
+
+    #[test]
+    fn test_match_and_find() {
+        let mut caps = test_caps();
+        assert_eq!(
+            is_match(&[s(r"\d+"), s("abc 123")], &mut caps).unwrap(),
+            okay_of(Value::Bool(true))
+        );
+        assert_eq!(
+            find(&[s(r"\d+"), s("abc 123 45")], &mut caps).unwrap(),
+            okay_of(s("123"))
+        );
+        assert!(find(&[s(r"\d+"), s("abc")], &mut caps).unwrap().is_oops());
+        assert_eq!(
+            find_all(&[s(r"\d+"), s("1 22 333")], &mut caps).unwrap(),
+            okay_of(Value::Array(vec![s("1"), s("22"), s("333")].into()))
+        );
+    }
+
+    #[test]
+    fn test_replace_and_split() {
+        let mut caps = test_caps();
+        assert_eq!(
+            replace(&[s(r"(\w+)@(\w+)"), s("me@home you@work"), s("$2:$1")], &mut caps).unwrap(),
+            okay_of(s("home:me work:you"))
+        );
+        assert_eq!(
+            split(&[s(r"\s*,\s*"), s("a , b,c")], &mut caps).unwrap(),
+            okay_of(Value::Array(vec![s("a"), s("b"), s("c")].into()))
+        );
+    }
+
+    #[test]
+    fn test_captures() {
+        let mut caps = test_caps();
+        let result = captures(&[s(r"(?P<key>\w+)=(\d+)?"), s("name=")], &mut caps).unwrap();
+        let Value::Okay(groups) = result else {
+            panic!("expected Okay, got {:?}", result);
+        };
+        let Value::Record(groups) = *groups else {
+            panic!("expected a map");
+        };
+        assert_eq!(groups.get("0"), Some(&s("name=")));
+        assert_eq!(groups.get("1"), Some(&s("name")));
+        assert_eq!(groups.get("key"), Some(&s("name")));
+        // The optional group did not participate
+        assert_eq!(groups.get("2"), None);
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_oops() {
+        let mut caps = test_caps();
+        let result = is_match(&[s("(unclosed"), s("text")], &mut caps).unwrap();
+        match result {
+            Value::Oops(error) => assert!(error.to_string().starts_with("invalid regex \"(unclosed\"")),
+            other => panic!("expected Oops, got {:?}", other),
+        }
+        // Non-string arguments are still type errors
+        assert!(find(&[Value::Int(1), s("text")], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_cache_reuses_patterns() {
+        compile("cached[a-z]").unwrap();
+        assert!(CACHE.with(|cache| cache.borrow().contains_key("cached[a-z]")));
+        for i in 0..CACHE_CAPACITY + 1 {
+            compile(&format!("p{}", i)).unwrap();
+        }
+        assert!(CACHE.with(|cache| cache.borrow().len() <= CACHE_CAPACITY));
+    }
+}
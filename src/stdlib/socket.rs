@@ -0,0 +1,392 @@
+//! WokeLang Standard Library - Socket Module
+//!
+//! Raw TCP and UDP sockets for systems scripting, exposed as `std.net.tcp*`
+//! and `std.net.udp*`. Sockets are referred to by Int handles. Opening a
+//! socket or sending a datagram needs the `Network` capability for the
+//! exact `host:port` involved. Each connection and each send counts as a
+//! network request towards the run's quota. Accepting and receiving wait
+//! no longer than the run's timeout allows.
+
+use crate::interpreter::Value;
+use crate::security::{limits, CapabilityRegistry, Quota};
+use super::net::require_network;
+use super::{check_arity, check_arity_range, expect_int, expect_string, quota_exceeded, StdlibError};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default and maximum number of bytes read by one receive call
+const MAX_RECEIVE: i64 = 64 * 1024;
+
+/// Address used when a listening function is given no host
+const DEFAULT_HOST: &str = "127.0.0.1";
+
+enum Socket {
+    Stream(TcpStream),
+    Listener(TcpListener),
+    Datagram(UdpSocket),
+}
+
+impl Socket {
+    fn kind(&self) -> &'static str {
+        match self {
+            Socket::Stream(_) => "TCP stream",
+            Socket::Listener(_) => "TCP listener",
+            Socket::Datagram(_) => "UDP socket",
+        }
+    }
+}
+
+thread_local! {
+    static SOCKETS: RefCell<(i64, HashMap<i64, Socket>)> = RefCell::new((0, HashMap::new()));
+}
+
+fn net_error(e: std::io::Error) -> StdlibError {
+    StdlibError::NetworkError(e.to_string())
+}
+
+/// Store a socket and return its handle
+fn open(socket: Socket) -> Value {
+    SOCKETS.with(|sockets| {
+        let (next, table) = &mut *sockets.borrow_mut();
+        *next += 1;
+        table.insert(*next, socket);
+        Value::Int(*next)
+    })
+}
+
+/// Run `f` with the socket behind a handle argument
+fn with_socket<T>(
+    handle: &Value,
+    f: impl FnOnce(&mut Socket) -> Result<T, StdlibError>,
+) -> Result<T, StdlibError> {
+    let handle = expect_int(handle, "socket")?;
+    SOCKETS.with(|sockets| match sockets.borrow_mut().1.get_mut(&handle) {
+        Some(socket) => f(socket),
+        None => Err(StdlibError::NetworkError(format!(
+            "Invalid or closed socket: {}",
+            handle
+        ))),
+    })
+}
+
+fn wrong_kind(expected: &str, socket: &Socket) -> StdlibError {
+    StdlibError::TypeError {
+        expected: expected.to_string(),
+        got: socket.kind().to_string(),
+    }
+}
+
+fn expect_port(value: &Value) -> Result<u16, StdlibError> {
+    let port = expect_int(value, "port")?;
+    u16::try_from(port)
+        .map_err(|_| StdlibError::NetworkError(format!("Invalid port: {}", port)))
+}
+
+/// Host and port for the listening functions: `(port[, host])`
+fn local_address(args: &[Value]) -> Result<(String, u16), StdlibError> {
+    let port = expect_port(&args[0])?;
+    let host = match args.get(1) {
+        Some(host) => expect_string(host, "host")?,
+        None => DEFAULT_HOST.to_string(),
+    };
+    Ok((host, port))
+}
+
+fn receive_limit(args: &[Value], index: usize) -> Result<usize, StdlibError> {
+    match args.get(index) {
+        None => Ok(MAX_RECEIVE as usize),
+        Some(value) => match expect_int(value, "maxBytes")? {
+            n if (1..=MAX_RECEIVE).contains(&n) => Ok(n as usize),
+            n => Err(StdlibError::RuntimeError(format!(
+                "maxBytes must be between 1 and {}, got {}",
+                MAX_RECEIVE, n
+            ))),
+        },
+    }
+}
+
+/// The longest a receive may wait: the run's time left, if it has a timeout
+fn read_timeout() -> Option<Duration> {
+    limits::time_left().map(|left| left.max(Duration::from_millis(1)))
+}
+
+/// Accept a connection, giving up once `left` has passed
+fn accept_within(listener: &TcpListener, left: Duration) -> io::Result<TcpStream> {
+    let deadline = Instant::now() + left;
+    listener.set_nonblocking(true)?;
+    let accepted = loop {
+        match listener.accept() {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            result => break result,
+        }
+    };
+    listener.set_nonblocking(false)?;
+    let (stream, _) = accepted?;
+    stream.set_nonblocking(false)?;
+    Ok(stream)
+}
+
+/// Open a TCP connection: tcpConnect(host, port) -> socket
+pub fn tcp_connect(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let host = expect_string(&args[0], "host")?;
+    let port = expect_port(&args[1])?;
+    require_network(&format!("{}:{}", host, port), caps)?;
+    if let Some(oops) = quota_exceeded(caps, Quota::NetworkRequests, 1) {
+        return Ok(oops);
+    }
+
+    let stream = TcpStream::connect((host.as_str(), port)).map_err(net_error)?;
+    Ok(open(Socket::Stream(stream)))
+}
+
+/// Listen for TCP connections: tcpListen(port[, host]) -> listener
+pub fn tcp_listen(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let (host, port) = local_address(args)?;
+    require_network(&format!("{}:{}", host, port), caps)?;
+
+    let listener = TcpListener::bind((host.as_str(), port)).map_err(net_error)?;
+    Ok(open(Socket::Listener(listener)))
+}
+
+/// Wait for a connection on a listener: tcpAccept(listener) -> socket
+pub fn tcp_accept(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let stream = with_socket(&args[0], |socket| match socket {
+        Socket::Listener(listener) => match limits::time_left() {
+            Some(left) => accept_within(listener, left).map_err(net_error),
+            None => listener.accept().map(|(s, _)| s).map_err(net_error),
+        },
+        other => Err(wrong_kind("TCP listener", other)),
+    })?;
+    Ok(open(Socket::Stream(stream)))
+}
+
+/// Send a string on a TCP stream, returning the number of bytes sent
+pub fn tcp_send(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let data = expect_string(&args[1], "data")?;
+    if let Some(oops) = quota_exceeded(caps, Quota::NetworkRequests, 1) {
+        return Ok(oops);
+    }
+    with_socket(&args[0], |socket| match socket {
+        Socket::Stream(stream) => stream.write_all(data.as_bytes()).map_err(net_error),
+        other => Err(wrong_kind("TCP stream", other)),
+    })?;
+    Ok(Value::Int(data.len() as i64))
+}
+
+/// Receive up to maxBytes (default 64 KB) from a TCP stream
+///
+/// Returns an empty string once the peer has closed the connection.
+pub fn tcp_receive(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let mut buf = vec![0u8; receive_limit(args, 1)?];
+    let read = with_socket(&args[0], |socket| match socket {
+        Socket::Stream(stream) => stream
+            .set_read_timeout(read_timeout())
+            .and_then(|()| stream.read(&mut buf))
+            .map_err(net_error),
+        other => Err(wrong_kind("TCP stream", other)),
+    })?;
+    Ok(Value::String(String::from_utf8_lossy(&buf[..read]).into_owned().into()))
+}
+
+/// Bind a UDP socket: udpBind(port[, host]) -> socket
+pub fn udp_bind(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let (host, port) = local_address(args)?;
+    require_network(&format!("{}:{}", host, port), caps)?;
+
+    let socket = UdpSocket::bind((host.as_str(), port)).map_err(net_error)?;
+    Ok(open(Socket::Datagram(socket)))
+}
+
+/// Send a datagram: udpSend(socket, host, port, data) -> bytes sent
+pub fn udp_send(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 4)?;
+    let host = expect_string(&args[1], "host")?;
+    let port = expect_port(&args[2])?;
+    let data = expect_string(&args[3], "data")?;
+    require_network(&format!("{}:{}", host, port), caps)?;
+    if let Some(oops) = quota_exceeded(caps, Quota::NetworkRequests, 1) {
+        return Ok(oops);
+    }
+
+    let sent = with_socket(&args[0], |socket| match socket {
+        Socket::Datagram(udp) => udp
+            .send_to(data.as_bytes(), (host.as_str(), port))
+            .map_err(net_error),
+        other => Err(wrong_kind("UDP socket", other)),
+    })?;
+    Ok(Value::Int(sent as i64))
+}
+
+/// Receive a datagram as a map of `data`, `host` and `port`
+pub fn udp_receive(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    let mut buf = vec![0u8; receive_limit(args, 1)?];
+    let (read, from) = with_socket(&args[0], |socket| match socket {
+        Socket::Datagram(udp) => udp
+            .set_read_timeout(read_timeout())
+            .and_then(|()| udp.recv_from(&mut buf))
+            .map_err(net_error),
+        other => Err(wrong_kind("UDP socket", other)),
+    })?;
+    Ok(Value::Record(HashMap::from([
+        (
+            "data".to_string(),
+            Value::String(String::from_utf8_lossy(&buf[..read]).into_owned().into()),
+        ),
+        ("host".to_string(), Value::String(from.ip().to_string().into())),
+        ("port".to_string(), Value::Int(from.port() as i64)),
+    ])))
+}
+
+/// Port a socket is bound to, useful after binding port 0
+pub fn local_port(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let address = with_socket(&args[0], |socket| {
+        match socket {
+            Socket::Stream(stream) => stream.local_addr(),
+            Socket::Listener(listener) => listener.local_addr(),
+            Socket::Datagram(udp) => udp.local_addr(),
+        }
+        .map_err(net_error)
+    })?;
+    Ok(Value::Int(address.port() as i64))
+}
+
+/// Close any socket; returns false if it was already closed
+pub fn close(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let handle = expect_int(&args[0], "socket")?;
+    let removed = SOCKETS.with(|sockets| sockets.borrow_mut().1.remove(&handle));
+    Ok(Value::Bool(removed.is_some()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    #[test]
+    fn test_tcp_round_trip() {
+        let mut caps = test_caps();
+        let listener = tcp_listen(&[Value::Int(0)], &mut caps).unwrap();
+        let port = local_port(std::slice::from_ref(&listener), &mut caps).unwrap();
+
+        let client = tcp_connect(&[s("127.0.0.1"), port], &mut caps).unwrap();
+        let server = tcp_accept(std::slice::from_ref(&listener), &mut caps).unwrap();
+
+        assert_eq!(tcp_send(&[client.clone(), s("ping")], &mut caps).unwrap(), Value::Int(4));
+        assert_eq!(tcp_receive(std::slice::from_ref(&server), &mut caps).unwrap(), s("ping"));
+
+        tcp_send(&[server.clone(), s("pong")], &mut caps).unwrap();
+        assert_eq!(tcp_receive(&[client.clone(), Value::Int(2)], &mut caps).unwrap(), s("po"));
+
+        assert_eq!(close(std::slice::from_ref(&server), &mut caps).unwrap(), Value::Bool(true));
+        assert_eq!(tcp_receive(std::slice::from_ref(&client), &mut caps).unwrap(), s("ng"));
+        assert_eq!(tcp_receive(std::slice::from_ref(&client), &mut caps).unwrap(), s(""));
+        assert_eq!(close(std::slice::from_ref(&server), &mut caps).unwrap(), Value::Bool(false));
+        assert!(tcp_send(&[server, s("x")], &mut caps).is_err());
+    }
+
+    #[test]
+    fn test_waits_end_with_the_run() {
+        let mut caps = test_caps();
+        let listener = tcp_listen(&[Value::Int(0)], &mut caps).unwrap();
+        let port = local_port(std::slice::from_ref(&listener), &mut caps).unwrap();
+        let client = tcp_connect(&[s("127.0.0.1"), port], &mut caps).unwrap();
+        let udp = udp_bind(&[Value::Int(0)], &mut caps).unwrap();
+
+        let budget = limits::Budget::start(limits::Limits {
+            timeout: Some(Duration::from_millis(50)),
+            max_memory: None,
+        });
+        let _deadline = limits::enter_call(Some(&budget));
+        let started = Instant::now();
+        assert!(tcp_receive(std::slice::from_ref(&client), &mut caps).is_err());
+        assert!(udp_receive(std::slice::from_ref(&udp), &mut caps).is_err());
+        let server = tcp_accept(std::slice::from_ref(&listener), &mut caps).unwrap();
+        assert!(tcp_accept(std::slice::from_ref(&listener), &mut caps).is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        close(&[server], &mut caps).unwrap();
+    }
+
+    #[test]
+    fn test_udp_round_trip() {
+        let mut caps = test_caps();
+        let a = udp_bind(&[Value::Int(0)], &mut caps).unwrap();
+        let b = udp_bind(&[Value::Int(0)], &mut caps).unwrap();
+        let b_port = local_port(std::slice::from_ref(&b), &mut caps).unwrap();
+
+        assert_eq!(
+            udp_send(&[a.clone(), s("127.0.0.1"), b_port, s("hello")], &mut caps).unwrap(),
+            Value::Int(5)
+        );
+        let Value::Record(datagram) = udp_receive(&[b], &mut caps).unwrap() else {
+            panic!("expected a datagram map");
+        };
+        assert_eq!(datagram["data"], s("hello"));
+        assert_eq!(datagram["host"], s("127.0.0.1"));
+        assert_eq!(datagram["port"], local_port(std::slice::from_ref(&a), &mut caps).unwrap());
+
+        // A UDP socket is not a TCP stream
+        assert!(matches!(
+            tcp_send(&[a, s("x")], &mut caps),
+            Err(StdlibError::TypeError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_network_quota() {
+        let mut caps = test_caps();
+        caps.set_quota(Quota::NetworkRequests, 2);
+        let over = |value: Value| matches!(value, Value::Oops(ref error) if error.to_string().contains("Quota exceeded"));
+
+        // Listening and binding are not requests
+        let listener = tcp_listen(&[Value::Int(0)], &mut caps).unwrap();
+        let port = local_port(std::slice::from_ref(&listener), &mut caps).unwrap();
+        let udp = udp_bind(&[Value::Int(0)], &mut caps).unwrap();
+
+        let client = tcp_connect(&[s("127.0.0.1"), port.clone()], &mut caps).unwrap();
+        assert_eq!(tcp_send(&[client.clone(), s("ping")], &mut caps).unwrap(), Value::Int(4));
+        assert!(over(tcp_send(&[client, s("ping")], &mut caps).unwrap()));
+        assert!(over(tcp_connect(&[s("127.0.0.1"), port.clone()], &mut caps).unwrap()));
+        assert!(over(udp_send(&[udp, s("127.0.0.1"), port, s("x")], &mut caps).unwrap()));
+    }
+
+    #[test]
+    fn test_sockets_need_network_consent() {
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+
+        assert!(matches!(
+            tcp_connect(&[s("127.0.0.1"), Value::Int(9)], &mut caps),
+            Err(StdlibError::PermissionDenied(msg)) if msg.contains("127.0.0.1:9")
+        ));
+        assert!(tcp_listen(&[Value::Int(0)], &mut caps).is_err());
+        assert!(udp_bind(&[Value::Int(0), s("0.0.0.0")], &mut caps).is_err());
+
+        // Sending to a new destination needs its own grant
+        let udp = udp_bind(&[Value::Int(0)], &mut test_caps()).unwrap();
+        assert!(matches!(
+            udp_send(&[udp, s("127.0.0.1"), Value::Int(9), s("x")], &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+        assert!(tcp_connect(&[s("host"), Value::Int(70000)], &mut caps).is_err());
+    }
+}
@@ -1,9 +1,18 @@
 //! WokeLang Standard Library - String Module
 //!
 //! String manipulation functions.
+//!
+//! A string is a sequence of Unicode characters (code points): `len`,
+//! `string[i]` and every index here count characters, not bytes. Finding
+//! the character at an index walks the string up to it. What a reader sees
+//! as one character may be several code points, such as a flag or an
+//! accented letter written with a combining mark; `graphemes` splits a
+//! string into those.
 
 use crate::interpreter::Value;
 use crate::security::CapabilityRegistry;
+use rust_decimal::{Decimal, RoundingStrategy};
+use unicode_segmentation::UnicodeSegmentation;
 use super::{check_arity, check_arity_range, expect_int, expect_string, StdlibError};
 
 /// Get the length of a string (in characters, not bytes)
@@ -17,35 +26,35 @@ pub fn length(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, S
 pub fn upper(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
     let s = expect_string(&args[0], "string")?;
-    Ok(Value::String(s.to_uppercase()))
+    Ok(Value::String(s.to_uppercase().into()))
 }
 
 /// Convert string to lowercase
 pub fn lower(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
     let s = expect_string(&args[0], "string")?;
-    Ok(Value::String(s.to_lowercase()))
+    Ok(Value::String(s.to_lowercase().into()))
 }
 
 /// Trim whitespace from both ends
 pub fn trim(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
     let s = expect_string(&args[0], "string")?;
-    Ok(Value::String(s.trim().to_string()))
+    Ok(Value::String(s.trim().to_string().into()))
 }
 
 /// Trim whitespace from start
 pub fn trim_start(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
     let s = expect_string(&args[0], "string")?;
-    Ok(Value::String(s.trim_start().to_string()))
+    Ok(Value::String(s.trim_start().to_string().into()))
 }
 
 /// Trim whitespace from end
 pub fn trim_end(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
     let s = expect_string(&args[0], "string")?;
-    Ok(Value::String(s.trim_end().to_string()))
+    Ok(Value::String(s.trim_end().to_string().into()))
 }
 
 /// Check if string contains a substring
@@ -78,7 +87,7 @@ pub fn replace(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value,
     let s = expect_string(&args[0], "string")?;
     let from = expect_string(&args[1], "from")?;
     let to = expect_string(&args[2], "to")?;
-    Ok(Value::String(s.replace(&from, &to)))
+    Ok(Value::String(s.replace(&from, &to).into()))
 }
 
 /// Split string by delimiter
@@ -86,8 +95,8 @@ pub fn split(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, St
     check_arity(args, 2)?;
     let s = expect_string(&args[0], "string")?;
     let delimiter = expect_string(&args[1], "delimiter")?;
-    let parts: Vec<Value> = s.split(&delimiter).map(|p| Value::String(p.to_string())).collect();
-    Ok(Value::Array(parts))
+    let parts: Vec<Value> = s.split(&delimiter).map(|p| Value::String(p.to_string().into())).collect();
+    Ok(Value::Array(parts.into()))
 }
 
 /// Join array of strings with delimiter
@@ -109,7 +118,7 @@ pub fn join(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, Std
     let strings: Result<Vec<String>, _> = arr
         .iter()
         .map(|v| match v {
-            Value::String(s) => Ok(s.clone()),
+            Value::String(s) => Ok(s.to_string()),
             other => Err(StdlibError::TypeError {
                 expected: "String".to_string(),
                 got: format!("{:?}", other),
@@ -117,7 +126,7 @@ pub fn join(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, Std
         })
         .collect();
 
-    Ok(Value::String(strings?.join(&delimiter)))
+    Ok(Value::String(strings?.join(&delimiter).into()))
 }
 
 /// Get substring by start and optional end index
@@ -148,10 +157,10 @@ pub fn substring(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value
     };
 
     if start_idx >= end_idx {
-        return Ok(Value::String(String::new()));
+        return Ok(Value::String(String::new().into()));
     }
 
-    Ok(Value::String(chars[start_idx..end_idx].iter().collect()))
+    Ok(Value::String(chars[start_idx..end_idx].iter().collect::<String>().into()))
 }
 
 /// Find index of first occurrence of substring
@@ -170,6 +179,10 @@ pub fn index_of(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value,
     }
 }
 
+/// The most a string may be repeated, or a format placeholder padded or
+/// given places
+const MAX_REPEAT: usize = 10000;
+
 /// Repeat string n times
 pub fn repeat(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 2)?;
@@ -180,18 +193,18 @@ pub fn repeat(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, S
         return Err(StdlibError::RuntimeError("repeat count cannot be negative".to_string()));
     }
 
-    if n > 10000 {
-        return Err(StdlibError::RuntimeError("repeat count too large (max 10000)".to_string()));
+    if n > MAX_REPEAT as i64 {
+        return Err(StdlibError::RuntimeError(format!("repeat count too large (max {})", MAX_REPEAT)));
     }
 
-    Ok(Value::String(s.repeat(n as usize)))
+    Ok(Value::String(s.repeat(n as usize).into()))
 }
 
 /// Reverse a string
 pub fn reverse(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
     let s = expect_string(&args[0], "string")?;
-    Ok(Value::String(s.chars().rev().collect()))
+    Ok(Value::String(s.chars().rev().collect::<String>().into()))
 }
 
 /// Pad string on the left to reach target length
@@ -208,11 +221,11 @@ pub fn pad_start(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value
 
     let current_len = s.chars().count();
     if current_len >= target_len {
-        return Ok(Value::String(s));
+        return Ok(Value::String(s.into()));
     }
 
-    let padding: String = std::iter::repeat(pad_char).take(target_len - current_len).collect();
-    Ok(Value::String(format!("{}{}", padding, s)))
+    let padding: String = std::iter::repeat_n(pad_char, target_len - current_len).collect();
+    Ok(Value::String(format!("{}{}", padding, s).into()))
 }
 
 /// Pad string on the right to reach target length
@@ -229,19 +242,64 @@ pub fn pad_end(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value,
 
     let current_len = s.chars().count();
     if current_len >= target_len {
-        return Ok(Value::String(s));
+        return Ok(Value::String(s.into()));
     }
 
-    let padding: String = std::iter::repeat(pad_char).take(target_len - current_len).collect();
-    Ok(Value::String(format!("{}{}", s, padding)))
+    let padding: String = std::iter::repeat_n(pad_char, target_len - current_len).collect();
+    Ok(Value::String(format!("{}{}", s, padding).into()))
 }
 
 /// Split string into array of characters
 pub fn chars(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
     let s = expect_string(&args[0], "string")?;
-    let char_array: Vec<Value> = s.chars().map(|c| Value::String(c.to_string())).collect();
-    Ok(Value::Array(char_array))
+    let char_array: Vec<Value> = s.chars().map(|c| Value::String(c.to_string().into())).collect();
+    Ok(Value::Array(char_array.into()))
+}
+
+/// The character at `index`, counting from 0, as `string[index]` gives
+/// it on both engines
+pub fn nth_char(s: &str, index: usize) -> Option<char> {
+    s.chars().nth(index)
+}
+
+/// The character at a WokeLang index, or an error naming the string's
+/// length if there is none
+fn char_at_index(s: &str, index: i64) -> Result<char, StdlibError> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| nth_char(s, i))
+        .ok_or_else(|| {
+            StdlibError::RuntimeError(format!(
+                "index {} is out of range for a string of {} characters",
+                index,
+                s.chars().count()
+            ))
+        })
+}
+
+/// Get the character at an index, as a string
+pub fn char_at(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let s = expect_string(&args[0], "string")?;
+    let index = expect_int(&args[1], "index")?;
+    Ok(Value::String(char_at_index(&s, index)?.to_string().into()))
+}
+
+/// Get the Unicode code point of the character at an index
+pub fn code_point_at(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let s = expect_string(&args[0], "string")?;
+    let index = expect_int(&args[1], "index")?;
+    Ok(Value::Int(char_at_index(&s, index)? as i64))
+}
+
+/// Split string into the characters a reader sees (grapheme clusters)
+pub fn graphemes(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let s = expect_string(&args[0], "string")?;
+    let clusters: Vec<Value> = s.graphemes(true).map(|g| Value::String(g.to_string().into())).collect();
+    Ok(Value::Array(clusters.into()))
 }
 
 /// Check if string is empty
@@ -251,6 +309,184 @@ pub fn is_empty(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value,
     Ok(Value::Bool(s.is_empty()))
 }
 
+/// How a `{...}` placeholder shows its value: `[[fill]align][+][0][width][.precision]`
+struct FormatSpec {
+    fill: char,
+    align: Option<char>,
+    sign: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+fn parse_format_spec(spec: &str) -> Result<FormatSpec, String> {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut parsed = FormatSpec {
+        fill: ' ',
+        align: None,
+        sign: false,
+        zero: false,
+        width: 0,
+        precision: None,
+    };
+    let is_align = |c: Option<&char>| matches!(c, Some('<' | '>' | '^'));
+    let mut i = 0;
+    if is_align(chars.get(1)) {
+        parsed.fill = chars[0];
+        parsed.align = Some(chars[1]);
+        i = 2;
+    } else if is_align(chars.first()) {
+        parsed.align = Some(chars[0]);
+        i = 1;
+    }
+    if chars.get(i) == Some(&'+') {
+        parsed.sign = true;
+        i += 1;
+    }
+    if chars.get(i) == Some(&'0') {
+        parsed.zero = true;
+        i += 1;
+    }
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>()
+    };
+    let limited = |digits: String, what: &str| {
+        digits
+            .parse()
+            .ok()
+            .filter(|n| *n <= MAX_REPEAT)
+            .ok_or_else(|| format!("{} too large in {{:{}}} (max {})", what, spec, MAX_REPEAT))
+    };
+    let width = digits(&mut i);
+    if !width.is_empty() {
+        parsed.width = limited(width, "width")?;
+    }
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        let precision = digits(&mut i);
+        if precision.is_empty() {
+            return Err(format!("missing precision in {{:{}}}", spec));
+        }
+        parsed.precision = Some(limited(precision, "precision")?);
+    }
+    if i != chars.len() {
+        return Err(format!("invalid format spec {{:{}}}", spec));
+    }
+    Ok(parsed)
+}
+
+/// Show one value as a placeholder asks
+fn format_value(value: &Value, spec: &FormatSpec) -> String {
+    let numeric = matches!(value, Value::Int(_) | Value::Float(_) | Value::Decimal(_));
+    let mut text = match (value, spec.precision) {
+        (Value::Float(f), Some(p)) => format!("{:.*}", p, f),
+        // Rounded as std.decimal.round rounds, then padded to the places
+        (Value::Decimal(d), Some(p)) => {
+            let places = p.min(Decimal::MAX_SCALE as usize);
+            let rounded = d.round_dp_with_strategy(places as u32, RoundingStrategy::MidpointAwayFromZero);
+            format!("{:.*}", p, rounded)
+        }
+        // All its digits, which a float would not keep past 2^53
+        (Value::Int(n), Some(0)) => n.to_string(),
+        (Value::Int(n), Some(p)) => format!("{}.{}", n, "0".repeat(p)),
+        (Value::String(s), Some(p)) => s.chars().take(p).collect(),
+        (other, _) => other.to_string(),
+    };
+    if spec.sign && numeric && !text.starts_with('-') {
+        text.insert(0, '+');
+    }
+
+    let len = text.chars().count();
+    if len >= spec.width {
+        return text;
+    }
+    let extra = spec.width - len;
+    if spec.zero && numeric && spec.align.is_none() {
+        // Zeros go between the sign and the digits
+        let digits_at = if text.starts_with(['+', '-']) { 1 } else { 0 };
+        text.insert_str(digits_at, &"0".repeat(extra));
+        return text;
+    }
+    let align = spec.align.unwrap_or(if numeric { '>' } else { '<' });
+    let (before, after) = match align {
+        '<' => (0, extra),
+        '^' => (extra / 2, extra - extra / 2),
+        _ => (extra, 0),
+    };
+    let pad = |n| std::iter::repeat_n(spec.fill, n).collect::<String>();
+    format!("{}{}{}", pad(before), text, pad(after))
+}
+
+/// Fill a template's placeholders with values
+///
+/// `{}` takes the next value and `{1}` a value by position; either may
+/// add a spec after a colon, e.g. `{:>8}`, `{0:.2}`, `{:*^10}` or
+/// `{:+05}`. `{{` and `}}` are literal braces. This is the `format`
+/// builtin, shared by the interpreter and the VM.
+pub fn format_template(template: &str, values: &[Value]) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    let mut next_auto = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '}' => return Err("unmatched '}' in template (use '}}' for a brace)".to_string()),
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err("unclosed '{' in template (use '{{' for a brace)".to_string()),
+                    }
+                }
+                let (position, spec) = placeholder.split_once(':').unwrap_or((&placeholder, ""));
+                let index = if position.is_empty() {
+                    next_auto += 1;
+                    next_auto - 1
+                } else {
+                    position
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid placeholder {{{}}}", placeholder))?
+                };
+                let value = values.get(index).ok_or_else(|| {
+                    format!(
+                        "placeholder {{{}}} has no value ({} given)",
+                        placeholder,
+                        values.len()
+                    )
+                })?;
+                out.push_str(&format_value(value, &parse_format_spec(spec)?));
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// Format values into a template: format(template, values...)
+pub fn format(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    let Some((template, values)) = args.split_first() else {
+        return Err(StdlibError::ArityError { expected: 1, got: 0 });
+    };
+    let template = expect_string(template, "template")?;
+    format_template(&template, values)
+        .map(|s| Value::String(s.into()))
+        .map_err(StdlibError::RuntimeError)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,26 +499,45 @@ fn test_caps() -> CapabilityRegistry {
     fn test_length() {
         let mut caps = test_caps();
         assert_eq!(
-            length(&[Value::String("hello".to_string())], &mut caps).unwrap(),
+            length(&[Value::String("hello".to_string().into())], &mut caps).unwrap(),
             Value::Int(5)
         );
         // UTF-8 characters
         assert_eq!(
-            length(&[Value::String("你好".to_string())], &mut caps).unwrap(),
+            length(&[Value::String("你好".to_string().into())], &mut caps).unwrap(),
             Value::Int(2)
         );
     }
 
+    #[test]
+    fn test_characters() {
+        let mut caps = test_caps();
+        let text = || Value::String("e\u{301}🇳🇿!".to_string().into());
+        assert_eq!(
+            char_at(&[text(), Value::Int(1)], &mut caps).unwrap(),
+            Value::String("\u{301}".to_string().into())
+        );
+        assert_eq!(code_point_at(&[text(), Value::Int(4)], &mut caps).unwrap(), Value::Int('!' as i64));
+        assert!(char_at(&[text(), Value::Int(5)], &mut caps).is_err());
+        assert!(code_point_at(&[text(), Value::Int(-1)], &mut caps).is_err());
+
+        let Value::Array(clusters) = graphemes(&[text()], &mut caps).unwrap() else {
+            panic!("graphemes gives back an array");
+        };
+        let clusters: Vec<String> = clusters.iter().map(|c| c.to_string()).collect();
+        assert_eq!(clusters, ["e\u{301}", "🇳🇿", "!"]);
+    }
+
     #[test]
     fn test_upper_lower() {
         let mut caps = test_caps();
         assert_eq!(
-            upper(&[Value::String("hello".to_string())], &mut caps).unwrap(),
-            Value::String("HELLO".to_string())
+            upper(&[Value::String("hello".to_string().into())], &mut caps).unwrap(),
+            Value::String("HELLO".to_string().into())
         );
         assert_eq!(
-            lower(&[Value::String("HELLO".to_string())], &mut caps).unwrap(),
-            Value::String("hello".to_string())
+            lower(&[Value::String("HELLO".to_string().into())], &mut caps).unwrap(),
+            Value::String("hello".to_string().into())
         );
     }
 
@@ -290,8 +545,8 @@ fn test_upper_lower() {
     fn test_trim() {
         let mut caps = test_caps();
         assert_eq!(
-            trim(&[Value::String("  hello  ".to_string())], &mut caps).unwrap(),
-            Value::String("hello".to_string())
+            trim(&[Value::String("  hello  ".to_string().into())], &mut caps).unwrap(),
+            Value::String("hello".to_string().into())
         );
     }
 
@@ -300,7 +555,7 @@ fn test_contains() {
         let mut caps = test_caps();
         assert_eq!(
             contains(
-                &[Value::String("hello world".to_string()), Value::String("world".to_string())],
+                &[Value::String("hello world".to_string().into()), Value::String("world".to_string().into())],
                 &mut caps
             )
             .unwrap(),
@@ -312,7 +567,7 @@ fn test_contains() {
     fn test_split_join() {
         let mut caps = test_caps();
         let result = split(
-            &[Value::String("a,b,c".to_string()), Value::String(",".to_string())],
+            &[Value::String("a,b,c".to_string().into()), Value::String(",".to_string().into())],
             &mut caps,
         )
         .unwrap();
@@ -320,19 +575,19 @@ fn test_split_join() {
         assert_eq!(
             result,
             Value::Array(vec![
-                Value::String("a".to_string()),
-                Value::String("b".to_string()),
-                Value::String("c".to_string()),
-            ])
+                Value::String("a".to_string().into()),
+                Value::String("b".to_string().into()),
+                Value::String("c".to_string().into()),
+            ].into())
         );
 
         let joined = join(
-            &[result, Value::String("-".to_string())],
+            &[result, Value::String("-".to_string().into())],
             &mut caps,
         )
         .unwrap();
 
-        assert_eq!(joined, Value::String("a-b-c".to_string()));
+        assert_eq!(joined, Value::String("a-b-c".to_string().into()));
     }
 
     #[test]
@@ -340,11 +595,11 @@ fn test_substring() {
         let mut caps = test_caps();
         assert_eq!(
             substring(
-                &[Value::String("hello".to_string()), Value::Int(1), Value::Int(4)],
+                &[Value::String("hello".to_string().into()), Value::Int(1), Value::Int(4)],
                 &mut caps
             )
             .unwrap(),
-            Value::String("ell".to_string())
+            Value::String("ell".to_string().into())
         );
     }
 
@@ -354,14 +609,58 @@ fn test_replace() {
         assert_eq!(
             replace(
                 &[
-                    Value::String("hello world".to_string()),
-                    Value::String("world".to_string()),
-                    Value::String("rust".to_string())
+                    Value::String("hello world".to_string().into()),
+                    Value::String("world".to_string().into()),
+                    Value::String("rust".to_string().into())
                 ],
                 &mut caps
             )
             .unwrap(),
-            Value::String("hello rust".to_string())
+            Value::String("hello rust".to_string().into())
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let ada = [Value::String("Ada".to_string().into())];
+        let fill = |template: &str, values: &[Value]| format_template(template, values).unwrap();
+
+        assert_eq!(
+            fill("Hello {0}, you have {1} items", &[ada[0].clone(), Value::Int(3)]),
+            "Hello Ada, you have 3 items"
+        );
+        assert_eq!(fill("{} and {}", &[Value::Int(1), Value::Int(2)]), "1 and 2");
+        assert_eq!(fill("{1}{0}{1}", &[Value::Int(1), Value::Int(2)]), "212");
+        assert_eq!(fill("{{literal}}", &[]), "{literal}");
+        assert_eq!(fill("[{:>6}]", &ada), "[   Ada]");
+        assert_eq!(fill("[{:<6}]", &[Value::Int(42)]), "[42    ]");
+        assert_eq!(fill("[{:*^7}]", &ada), "[**Ada**]");
+        assert_eq!(fill("[{:5}]", &[Value::Int(42)]), "[   42]");
+        assert_eq!(fill("{:.2}", &[Value::Float(1.23456)]), "1.23");
+        assert_eq!(fill("{:.1}", &[Value::Int(2)]), "2.0");
+        assert_eq!(fill("{:+06.1}", &[Value::Float(-2.25)]), "-002.2");
+        assert_eq!(fill("{:+}", &[Value::Int(5)]), "+5");
+        assert_eq!(fill("{:.2}", &ada), "Ad");
+        let price = Value::Decimal("2.345".parse().unwrap());
+        assert_eq!(fill("{:.2} {:.4} {:>7}", &[price.clone(), price.clone(), price]), "2.35 2.3450   2.345");
+
+        assert!(format_template("{2}", &ada).is_err());
+        assert!(format_template("{name}", &ada).is_err());
+        assert!(format_template("{:>x}", &ada).is_err());
+        assert_eq!(fill("{:.2}", &[Value::Int(i64::MAX)]), "9223372036854775807.00");
+        assert_eq!(fill("{:.0}", &[Value::Int(-7)]), "-7");
+        assert_eq!(fill("{:10000}", &ada).len(), 10000);
+        assert!(format_template("{:10001}", &ada).is_err());
+        assert!(format_template("{:99999999999}", &ada).is_err());
+        assert!(format_template("{:.99999999999999999999}", &ada).is_err());
+        assert!(format_template("oops {", &[]).is_err());
+        assert!(format_template("oops }", &[]).is_err());
+
+        let mut caps = test_caps();
+        assert_eq!(
+            format(&[Value::String("{}!".to_string().into()), ada[0].clone()], &mut caps).unwrap(),
+            Value::String("Ada!".to_string().into())
         );
+        assert!(format(&[], &mut caps).is_err());
     }
 }
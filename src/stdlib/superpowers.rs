@@ -0,0 +1,87 @@
+//! WokeLang Standard Library - Superpowers Module
+//!
+//! Lets a program see which superpowers it holds and give some up, e.g.
+//! before handing control to code it trusts less. The builtins
+//! `listSuperpowers`, `hasSuperpower` and `renounce` call these.
+//!
+//! Superpowers are written as they are shown in consent prompts:
+//! `network:*`, `file:read:data`, `env:HOME`, `crypto`, `custom:camera`.
+//! Leaving off the value, as in `network`, means any value.
+
+use crate::interpreter::Value;
+use crate::security::{Capability, CapabilityRegistry};
+use super::{check_arity, expect_string, StdlibError};
+
+fn capability(value: &Value) -> Result<Capability, StdlibError> {
+    let text = expect_string(value, "superpower")?;
+    text.parse()
+        .map_err(|_| StdlibError::ParseError(format!("Unknown superpower: {}", text)))
+}
+
+/// Superpowers the running function holds without asking:
+/// listSuperpowers() -> [String]
+pub fn list(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 0)?;
+    let mut names: Vec<String> = caps
+        .effective_capabilities(caps.scope())
+        .into_iter()
+        .map(|cap| cap.to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(Value::Array(names.into_iter().map(|s| Value::String(s.into())).collect::<Vec<_>>().into()))
+}
+
+/// Whether the running function holds a superpower without asking:
+/// hasSuperpower("network:*") -> Bool
+pub fn has(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let cap = capability(&args[0])?;
+    Ok(Value::Bool(caps.has_capability(caps.scope(), &cap)))
+}
+
+/// Give up a superpower, and everything it covers, for the rest of the
+/// run; it is refused from then on without asking: renounce("file:write")
+pub fn renounce(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let cap = capability(&args[0])?;
+    caps.renounce(cap);
+    Ok(Value::Unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_list_and_renounce() {
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.grant("*", Capability::Network(None), "test");
+        caps.grant("stdlib", Capability::FileWrite(Some(PathBuf::from("out"))), "test");
+        caps.grant("other", Capability::Crypto, "test");
+
+        assert_eq!(
+            list(&[], &mut caps).unwrap(),
+            Value::Array(vec![s("file:write:out"), s("network:*")].into())
+        );
+        assert_eq!(has(&[s("network:example.com")], &mut caps).unwrap(), Value::Bool(true));
+        assert_eq!(has(&[s("crypto")], &mut caps).unwrap(), Value::Bool(false));
+
+        renounce(&[s("file:write")], &mut caps).unwrap();
+        renounce(&[s("network:*.internal")], &mut caps).unwrap();
+        assert_eq!(list(&[], &mut caps).unwrap(), Value::Array(vec![s("network:*")].into()));
+        assert_eq!(has(&[s("network:db.internal")], &mut caps).unwrap(), Value::Bool(false));
+        assert_eq!(has(&[s("network:example.com")], &mut caps).unwrap(), Value::Bool(true));
+
+        // Granting again does not bring a renounced superpower back
+        caps.set_default_consent(true);
+        let out = Capability::FileWrite(Some(PathBuf::from("out/report.txt")));
+        caps.grant("stdlib", out.clone(), "test");
+        assert!(caps.request_current(&out).is_err());
+
+        assert!(matches!(has(&[s("superuser")], &mut caps), Err(StdlibError::ParseError(_))));
+    }
+}
@@ -0,0 +1,284 @@
+//! WokeLang Standard Library - Terminal Module
+//!
+//! Colors, styles, cursor movement and progress bars for command-line
+//! scripts. The functions return text for `print` rather than writing it,
+//! so they work wherever program output goes.
+//!
+//! Escape codes are only produced when stdout is a terminal and `NO_COLOR`
+//! is not set; otherwise text comes back unchanged and cursor functions
+//! return an empty string. `setColor` overrides the detection.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity, check_arity_range, expect_bool, expect_int, expect_string, StdlibError};
+use std::cell::Cell;
+use std::io::IsTerminal;
+
+thread_local! {
+    static COLOR_OVERRIDE: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+/// Force escape codes on or off, or go back to detecting with `None`
+pub fn set_color_enabled(enabled: Option<bool>) {
+    COLOR_OVERRIDE.with(|c| c.set(enabled));
+}
+
+/// Whether escape codes should be written
+pub fn color_enabled() -> bool {
+    COLOR_OVERRIDE.with(|c| c.get()).unwrap_or_else(|| {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        !no_color && std::io::stdout().is_terminal()
+    })
+}
+
+/// SGR code for a color name, as a foreground color
+fn color_code(name: &str) -> Option<u8> {
+    Some(match name {
+        "black" => 30,
+        "red" => 31,
+        "green" => 32,
+        "yellow" => 33,
+        "blue" => 34,
+        "magenta" => 35,
+        "cyan" => 36,
+        "white" => 37,
+        "gray" | "grey" => 90,
+        _ => return None,
+    })
+}
+
+fn style_code(name: &str) -> Option<u8> {
+    Some(match name {
+        "bold" => 1,
+        "dim" => 2,
+        "italic" => 3,
+        "underline" => 4,
+        "inverse" => 7,
+        "strikethrough" => 9,
+        _ => return None,
+    })
+}
+
+fn unknown(kind: &str, name: &str, known: &str) -> StdlibError {
+    StdlibError::RuntimeError(format!("Unknown {}: {} (use {})", kind, name, known))
+}
+
+const COLORS: &str = "black, red, green, yellow, blue, magenta, cyan, white or gray";
+
+/// Wrap text in an SGR code and a reset
+fn paint(text: String, code: u8) -> Value {
+    if color_enabled() {
+        Value::String(format!("\x1b[{}m{}\x1b[0m", code, text).into())
+    } else {
+        Value::String(text.into())
+    }
+}
+
+/// An escape sequence, or nothing when escape codes are off
+fn control(sequence: String) -> Value {
+    Value::String(if color_enabled() { sequence.into() } else { String::new().into() })
+}
+
+/// Color text: color(text, name)
+pub fn color(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let text = expect_string(&args[0], "text")?;
+    let name = expect_string(&args[1], "color")?;
+    let code = color_code(&name).ok_or_else(|| unknown("color", &name, COLORS))?;
+    Ok(paint(text, code))
+}
+
+/// Color the background behind text: background(text, name)
+pub fn background(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let text = expect_string(&args[0], "text")?;
+    let name = expect_string(&args[1], "color")?;
+    let code = color_code(&name).ok_or_else(|| unknown("color", &name, COLORS))?;
+    Ok(paint(text, code + 10))
+}
+
+/// Style text: style(text, "bold" | "dim" | "italic" | "underline" |
+/// "inverse" | "strikethrough")
+pub fn style(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let text = expect_string(&args[0], "text")?;
+    let name = expect_string(&args[1], "style")?;
+    let code = style_code(&name).ok_or_else(|| {
+        unknown("style", &name, "bold, dim, italic, underline, inverse or strikethrough")
+    })?;
+    Ok(paint(text, code))
+}
+
+/// Remove escape codes from text, e.g. before measuring or saving it
+pub fn strip(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let text = expect_string(&args[0], "text")?;
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            // Parameters and intermediates, up to the final letter
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    Ok(Value::String(plain.into()))
+}
+
+/// Whether stdout is a terminal
+pub fn is_tty(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 0)?;
+    Ok(Value::Bool(std::io::stdout().is_terminal()))
+}
+
+/// Turn escape codes on or off, whatever the terminal: setColor(enabled)
+pub fn set_color(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    set_color_enabled(Some(expect_bool(&args[0], "enabled")?));
+    Ok(Value::Unit)
+}
+
+/// Terminal width in columns, from `COLUMNS`, or 80
+pub fn width(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 0)?;
+    let columns = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.trim().parse::<i64>().ok())
+        .filter(|c| *c > 0)
+        .unwrap_or(80);
+    Ok(Value::Int(columns))
+}
+
+fn cursor_move(args: &[Value], letter: char) -> Result<Value, StdlibError> {
+    check_arity_range(args, 0, 1)?;
+    let lines = match args.first() {
+        Some(n) => expect_int(n, "lines")?,
+        None => 1,
+    };
+    if lines <= 0 {
+        return Ok(Value::String(String::new().into()));
+    }
+    Ok(control(format!("\x1b[{}{}", lines, letter)))
+}
+
+/// Move the cursor up: cursorUp([lines])
+pub fn cursor_up(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    cursor_move(args, 'A')
+}
+
+/// Move the cursor down: cursorDown([lines])
+pub fn cursor_down(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    cursor_move(args, 'B')
+}
+
+/// Move the cursor to a column of the current line, counting from 1:
+/// cursorColumn(column)
+pub fn cursor_column(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let column = expect_int(&args[0], "column")?.max(1);
+    Ok(control(format!("\x1b[{}G", column)))
+}
+
+/// Clear the current line and return to its start: clearLine()
+pub fn clear_line(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 0)?;
+    Ok(control("\r\x1b[2K".to_string()))
+}
+
+/// Hide the cursor, e.g. while drawing progress: hideCursor()
+pub fn hide_cursor(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 0)?;
+    Ok(control("\x1b[?25l".to_string()))
+}
+
+/// Show the cursor again: showCursor()
+pub fn show_cursor(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 0)?;
+    Ok(control("\x1b[?25h".to_string()))
+}
+
+/// Draw a progress bar: progressBar(done, total[, width]) -> "[#####.....]  50%"
+///
+/// `width` is the number of cells inside the brackets (default 20). To
+/// redraw a bar in place, print `clearLine()` in front of it.
+pub fn progress_bar(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 2, 3)?;
+    let done = expect_int(&args[0], "done")?;
+    let total = expect_int(&args[1], "total")?;
+    let cells = match args.get(2) {
+        Some(w) => expect_int(w, "width")?,
+        None => 20,
+    };
+    if total <= 0 || !(1..=1000).contains(&cells) {
+        return Err(StdlibError::RuntimeError(
+            "progressBar needs a positive total and a width from 1 to 1000".to_string(),
+        ));
+    }
+
+    let fraction = done.clamp(0, total) as f64 / total as f64;
+    let filled = (fraction * cells as f64).floor() as usize;
+    let cells = cells as usize;
+    let bar = format!("{}{}", "#".repeat(filled), ".".repeat(cells - filled));
+    let bar = if color_enabled() {
+        format!("\x1b[32m{}\x1b[0m", bar)
+    } else {
+        bar
+    };
+    Ok(Value::String(format!("[{}] {:>3}%", bar, (fraction * 100.0).floor() as i64).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    #[test]
+    fn test_colors_and_styles() {
+        let mut caps = caps();
+        set_color_enabled(Some(true));
+        assert_eq!(
+            color(&[s("ok"), s("green")], &mut caps).unwrap(),
+            s("\x1b[32mok\x1b[0m")
+        );
+        assert_eq!(
+            background(&[s("!"), s("red")], &mut caps).unwrap(),
+            s("\x1b[41m!\x1b[0m")
+        );
+        let bold = style(&[s("title"), s("bold")], &mut caps).unwrap();
+        assert_eq!(bold, s("\x1b[1mtitle\x1b[0m"));
+        assert_eq!(strip(&[bold], &mut caps).unwrap(), s("title"));
+        assert!(color(&[s("x"), s("chartreuse")], &mut caps).is_err());
+        assert_eq!(cursor_up(&[Value::Int(2)], &mut caps).unwrap(), s("\x1b[2A"));
+
+        set_color_enabled(Some(false));
+        assert_eq!(color(&[s("ok"), s("green")], &mut caps).unwrap(), s("ok"));
+        assert_eq!(clear_line(&[], &mut caps).unwrap(), s(""));
+        set_color_enabled(None);
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        let mut caps = caps();
+        set_color_enabled(Some(false));
+        assert_eq!(
+            progress_bar(&[Value::Int(5), Value::Int(10), Value::Int(10)], &mut caps).unwrap(),
+            s("[#####.....]  50%")
+        );
+        assert_eq!(
+            progress_bar(&[Value::Int(12), Value::Int(10), Value::Int(4)], &mut caps).unwrap(),
+            s("[####] 100%")
+        );
+        assert!(progress_bar(&[Value::Int(1), Value::Int(0)], &mut caps).is_err());
+        set_color_enabled(None);
+    }
+}
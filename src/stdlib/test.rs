@@ -0,0 +1,95 @@
+//! WokeLang Standard Library - Test Module
+//!
+//! Assertions for `expect` blocks and other checks. A failed assertion is a
+//! runtime error that names what went wrong, which fails the surrounding
+//! `expect` block. Each function takes an optional message to add context.
+
+use crate::interpreter::Value;
+use crate::security::CapabilityRegistry;
+use super::{check_arity_range, expect_string, StdlibError};
+
+/// Build the failure error, with the caller's message first if there is one
+fn failure(args: &[Value], message_at: usize, detail: String) -> Result<Value, StdlibError> {
+    let detail = match args.get(message_at) {
+        Some(message) => format!("{}: {}", expect_string(message, "message")?, detail),
+        None => detail,
+    };
+    Err(StdlibError::RuntimeError(detail))
+}
+
+/// Check that two values are equal: assertEqual(actual, expected[, message])
+pub fn assert_equal(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 2, 3)?;
+    if args[0] == args[1] {
+        Ok(Value::Unit)
+    } else {
+        failure(
+            args,
+            2,
+            format!("expected {}, got {}", args[1].pretty(), args[0].pretty()),
+        )
+    }
+}
+
+/// Check that a condition holds: assertTrue(condition[, message])
+pub fn assert_true(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    match &args[0] {
+        Value::Bool(true) => Ok(Value::Unit),
+        Value::Bool(false) => failure(args, 1, "expected true, got false".to_string()),
+        other => Err(StdlibError::TypeError {
+            expected: "Bool".to_string(),
+            got: format!("{:?}", other),
+        }),
+    }
+}
+
+/// Check that a Result is an Oops: assertOops(result[, message])
+pub fn assert_oops(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity_range(args, 1, 2)?;
+    if args[0].is_oops() {
+        Ok(Value::Unit)
+    } else {
+        failure(args, 1, format!("expected Oops, got {}", args[0].pretty()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+
+    fn test_caps() -> CapabilityRegistry {
+        CapabilityRegistry::permissive()
+    }
+
+    #[test]
+    fn test_assert_equal() {
+        let mut caps = test_caps();
+        assert!(assert_equal(&[Value::Int(2), Value::Int(2)], &mut caps).is_ok());
+        let Err(StdlibError::RuntimeError(message)) =
+            assert_equal(&[Value::Int(3), Value::Int(2), s("sum")], &mut caps)
+        else {
+            panic!("expected a failure");
+        };
+        assert_eq!(message, "sum: expected 2, got 3");
+        let Err(StdlibError::RuntimeError(message)) = assert_equal(&[s("a"), s("b")], &mut caps) else {
+            panic!("expected a failure");
+        };
+        assert_eq!(message, r#"expected "b", got "a""#);
+    }
+
+    #[test]
+    fn test_assert_true_and_oops() {
+        let mut caps = test_caps();
+        assert!(assert_true(&[Value::Bool(true)], &mut caps).is_ok());
+        assert!(assert_true(&[Value::Bool(false)], &mut caps).is_err());
+        assert!(matches!(
+            assert_true(&[Value::Int(1)], &mut caps),
+            Err(StdlibError::TypeError { .. })
+        ));
+
+        assert!(assert_oops(&[Value::oops("bad")], &mut caps).is_ok());
+        assert!(assert_oops(&[Value::Okay(Box::new(Value::Int(1)))], &mut caps).is_err());
+    }
+}
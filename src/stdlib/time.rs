@@ -3,7 +3,9 @@
 //! Date and time handling functions.
 
 use crate::interpreter::Value;
-use crate::security::CapabilityRegistry;
+use crate::security::{limits, CapabilityRegistry};
+use super::datetime::format_datetime;
+use super::duration;
 use super::{check_arity, expect_int, expect_string, StdlibError};
 use std::collections::HashMap;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
@@ -37,13 +39,17 @@ pub fn timestamp(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value
     Ok(Value::Int(timestamp))
 }
 
-/// Format a timestamp to a string
+/// Format a timestamp or DateTime to a string
 /// format(timestamp, format_string)
-/// Format tokens: %Y=year, %m=month, %d=day, %H=hour, %M=minute, %S=second
+/// Format tokens: %Y=year, %m=month, %d=day, %H=hour, %M=minute, %S=second;
+/// a DateTime also accepts the other strftime tokens, such as %z for its offset
 pub fn format(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 2)?;
-    let timestamp_ms = expect_int(&args[0], "timestamp")?;
     let format_str = expect_string(&args[1], "format")?;
+    if let Value::DateTime(dt) = &args[0] {
+        return format_datetime(dt, &format_str).map(|s| Value::String(s.into()));
+    }
+    let timestamp_ms = expect_int(&args[0], "timestamp")?;
 
     // Convert milliseconds to components
     let total_secs = timestamp_ms / 1000;
@@ -58,7 +64,7 @@ pub fn format(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, S
         .replace("%M", &format!("{:02}", minute))
         .replace("%S", &format!("{:02}", second));
 
-    Ok(Value::String(result))
+    Ok(Value::String(result.into()))
 }
 
 /// Parse a date string to timestamp
@@ -73,13 +79,15 @@ pub fn parse(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, St
     Ok(Value::Int(result))
 }
 
-/// Sleep for a given number of milliseconds
+/// Sleep for a Duration or a given number of milliseconds, or until the
+/// run's timeout if that comes first
 pub fn sleep(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
     check_arity(args, 1)?;
-    let ms = expect_int(&args[0], "milliseconds")?;
+    let ms = duration::expect_millis(&args[0], "milliseconds")?;
 
     if ms > 0 {
-        std::thread::sleep(Duration::from_millis(ms as u64));
+        let delay = Duration::from_millis(ms as u64);
+        std::thread::sleep(limits::time_left().map_or(delay, |left| delay.min(left)));
     }
 
     Ok(Value::Unit)
@@ -130,7 +138,7 @@ pub fn elapsed(args: &[Value], _caps: &mut CapabilityRegistry) -> Result<Value,
 }
 
 /// Convert timestamp (seconds since epoch) to date components
-fn timestamp_to_components(total_secs: i64) -> (i32, u32, u32, u32, u32, u32) {
+pub(super) fn timestamp_to_components(total_secs: i64) -> (i32, u32, u32, u32, u32, u32) {
     // Simple conversion (ignores leap seconds)
     let days_since_epoch = total_secs / 86400;
     let time_of_day = total_secs % 86400;
@@ -194,7 +202,7 @@ fn parse_date_string(date_str: &str, format_str: &str) -> Result<i64, StdlibErro
 
     // Try ISO 8601 format: YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS
     if format_str.contains("%Y") && format_str.contains("%m") && format_str.contains("%d") {
-        let parts: Vec<&str> = date_str.split(|c| c == '-' || c == 'T' || c == ':' || c == ' ')
+        let parts: Vec<&str> = date_str.split(['-', 'T', ':', ' '])
             .collect();
 
         if parts.len() >= 3 {
@@ -245,8 +253,8 @@ fn ymd_to_timestamp(year: i32, month: u32, day: u32, hour: u32, minute: u32, sec
         [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
     };
 
-    for m in 0..(month - 1) as usize {
-        days += days_in_months[m] as i64;
+    for days_in_month in days_in_months.iter().take((month - 1) as usize) {
+        days += *days_in_month as i64;
     }
     days += (day - 1) as i64;
 
@@ -289,12 +297,12 @@ fn test_format() {
         let ts = 1705322445000i64; // milliseconds
 
         let result = format(
-            &[Value::Int(ts), Value::String("%Y-%m-%d".to_string())],
+            &[Value::Int(ts), Value::String("%Y-%m-%d".to_string().into())],
             &mut caps,
         )
         .unwrap();
 
-        assert_eq!(result, Value::String("2024-01-15".to_string()));
+        assert_eq!(result, Value::String("2024-01-15".to_string().into()));
     }
 
     #[test]
@@ -303,8 +311,8 @@ fn test_parse() {
 
         let result = parse(
             &[
-                Value::String("2024-01-15".to_string()),
-                Value::String("%Y-%m-%d".to_string()),
+                Value::String("2024-01-15".to_string().into()),
+                Value::String("%Y-%m-%d".to_string().into()),
             ],
             &mut caps,
         )
@@ -325,9 +333,11 @@ fn test_sleep() {
         let start = Instant::now();
 
         sleep(&[Value::Int(50)], &mut caps).unwrap();
+        sleep(&[Value::Duration(chrono::TimeDelta::milliseconds(30))], &mut caps).unwrap();
 
         let elapsed = start.elapsed().as_millis();
-        assert!(elapsed >= 50);
+        assert!(elapsed >= 80);
+        assert!(sleep(&[Value::Float(1.0)], &mut caps).is_err());
     }
 
     #[test]
@@ -337,8 +347,8 @@ fn test_elapsed() {
         // Start timer
         elapsed(
             &[
-                Value::String("start".to_string()),
-                Value::String("test_timer".to_string()),
+                Value::String("start".to_string().into()),
+                Value::String("test_timer".to_string().into()),
             ],
             &mut caps,
         )
@@ -350,8 +360,8 @@ fn test_elapsed() {
         // Get elapsed
         let result = elapsed(
             &[
-                Value::String("stop".to_string()),
-                Value::String("test_timer".to_string()),
+                Value::String("stop".to_string().into()),
+                Value::String("test_timer".to_string().into()),
             ],
             &mut caps,
         )
@@ -365,8 +375,8 @@ fn test_elapsed() {
         // Reset
         elapsed(
             &[
-                Value::String("reset".to_string()),
-                Value::String("test_timer".to_string()),
+                Value::String("reset".to_string().into()),
+                Value::String("test_timer".to_string().into()),
             ],
             &mut caps,
         )
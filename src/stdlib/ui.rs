@@ -0,0 +1,251 @@
+//! WokeLang Standard Library - UI Module
+//!
+//! Desktop interactions: the clipboard and notifications. Reading or
+//! writing the clipboard needs the `Clipboard` capability and showing a
+//! notification needs `Notify`, so the user is asked before a program
+//! touches either.
+//!
+//! By default requests go to the platform's own tools (`pbcopy`,
+//! `wl-copy`, `xclip`, `notify-send`, ...). Hosts with their own UI, such
+//! as an editor or the browser playground, install a [`UiBackend`] with
+//! [`set_backend`] instead.
+
+use crate::interpreter::Value;
+use crate::security::{Capability, CapabilityRegistry};
+use super::{check_arity, expect_string, StdlibError};
+use std::cell::RefCell;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Where clipboard and notification requests are carried out
+pub trait UiBackend {
+    fn clipboard_read(&mut self) -> Result<String, String>;
+    fn clipboard_write(&mut self, text: &str) -> Result<(), String>;
+    fn notify(&mut self, title: &str, body: &str) -> Result<(), String>;
+}
+
+/// Backend that runs the platform's clipboard and notification tools
+pub struct SystemUi;
+
+/// Run a tool, feeding it `input`, and return what it printed
+fn run_tool(program: &str, args: &[&str], input: Option<&str>) -> Result<String, String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run {}: {}", program, e))?;
+    if let (Some(text), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("Could not write to {}: {}", program, e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("{} failed: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(target_os = "macos")]
+impl UiBackend for SystemUi {
+    fn clipboard_read(&mut self) -> Result<String, String> {
+        run_tool("pbpaste", &[], None)
+    }
+
+    fn clipboard_write(&mut self, text: &str) -> Result<(), String> {
+        run_tool("pbcopy", &[], Some(text)).map(|_| ())
+    }
+
+    fn notify(&mut self, title: &str, body: &str) -> Result<(), String> {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let script = format!("display notification {} with title {}", quote(body), quote(title));
+        run_tool("osascript", &["-e", &script], None).map(|_| ())
+    }
+}
+
+#[cfg(windows)]
+impl UiBackend for SystemUi {
+    fn clipboard_read(&mut self) -> Result<String, String> {
+        run_tool("powershell", &["-NoProfile", "-Command", "Get-Clipboard -Raw"], None)
+    }
+
+    fn clipboard_write(&mut self, text: &str) -> Result<(), String> {
+        run_tool("clip", &[], Some(text)).map(|_| ())
+    }
+
+    fn notify(&mut self, _title: &str, _body: &str) -> Result<(), String> {
+        Err("Notifications are not supported on this platform".to_string())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+impl UiBackend for SystemUi {
+    fn clipboard_read(&mut self) -> Result<String, String> {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            run_tool("wl-paste", &["--no-newline"], None)
+        } else {
+            run_tool("xclip", &["-selection", "clipboard", "-o"], None)
+        }
+    }
+
+    fn clipboard_write(&mut self, text: &str) -> Result<(), String> {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            run_tool("wl-copy", &[], Some(text)).map(|_| ())
+        } else {
+            run_tool("xclip", &["-selection", "clipboard", "-i"], Some(text)).map(|_| ())
+        }
+    }
+
+    fn notify(&mut self, title: &str, body: &str) -> Result<(), String> {
+        run_tool("notify-send", &["--", title, body], None).map(|_| ())
+    }
+}
+
+thread_local! {
+    static BACKEND: RefCell<Box<dyn UiBackend>> = RefCell::new(Box::new(SystemUi));
+}
+
+/// Send clipboard and notification requests on this thread to `backend`
+pub fn set_backend(backend: impl UiBackend + 'static) {
+    BACKEND.with(|b| *b.borrow_mut() = Box::new(backend));
+}
+
+/// Go back to the platform's tools
+pub fn reset_backend() {
+    set_backend(SystemUi);
+}
+
+fn require(cap: Capability, what: &str, caps: &mut CapabilityRegistry) -> Result<(), StdlibError> {
+    if caps.request_current(&cap).is_err() {
+        Err(StdlibError::PermissionDenied(format!("{} access denied", what)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Turn a backend outcome into `Okay(value)` or `Oops(message)`
+fn outcome(result: Result<Value, String>) -> Value {
+    match result {
+        Ok(value) => Value::Okay(Box::new(value)),
+        Err(message) => Value::oops(message),
+    }
+}
+
+/// Read the clipboard text: clipboardRead() -> Result
+pub fn clipboard_read(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 0)?;
+    require(Capability::Clipboard, "Clipboard", caps)?;
+    let result = BACKEND.with(|b| b.borrow_mut().clipboard_read());
+    Ok(outcome(result.map(|s| Value::String(s.into()))))
+}
+
+/// Replace the clipboard text: clipboardWrite(text) -> Result
+pub fn clipboard_write(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 1)?;
+    let text = expect_string(&args[0], "text")?;
+    require(Capability::Clipboard, "Clipboard", caps)?;
+    let result = BACKEND.with(|b| b.borrow_mut().clipboard_write(&text));
+    Ok(outcome(result.map(|()| Value::Unit)))
+}
+
+/// Show a desktop notification: notify(title, body) -> Result
+pub fn notify(args: &[Value], caps: &mut CapabilityRegistry) -> Result<Value, StdlibError> {
+    check_arity(args, 2)?;
+    let title = expect_string(&args[0], "title")?;
+    let body = expect_string(&args[1], "body")?;
+    require(Capability::Notify, "Notification", caps)?;
+    let result = BACKEND.with(|b| b.borrow_mut().notify(&title, &body));
+    Ok(outcome(result.map(|()| Value::Unit)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::s;
+    use std::rc::Rc;
+
+    /// Backend that keeps everything in memory
+    #[derive(Default)]
+    struct FakeUi {
+        clipboard: Option<String>,
+        shown: Rc<RefCell<Vec<(String, String)>>>,
+    }
+
+    impl UiBackend for FakeUi {
+        fn clipboard_read(&mut self) -> Result<String, String> {
+            self.clipboard.clone().ok_or_else(|| "clipboard is empty".to_string())
+        }
+
+        fn clipboard_write(&mut self, text: &str) -> Result<(), String> {
+            self.clipboard = Some(text.to_string());
+            Ok(())
+        }
+
+        fn notify(&mut self, title: &str, body: &str) -> Result<(), String> {
+            self.shown.borrow_mut().push((title.to_string(), body.to_string()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_clipboard_and_notify() {
+        let shown = Rc::new(RefCell::new(Vec::new()));
+        set_backend(FakeUi {
+            clipboard: None,
+            shown: shown.clone(),
+        });
+        let mut caps = CapabilityRegistry::permissive();
+
+        assert!(clipboard_read(&[], &mut caps).unwrap().is_oops());
+        assert_eq!(
+            clipboard_write(&[s("thanks!")], &mut caps).unwrap(),
+            Value::Okay(Box::new(Value::Unit))
+        );
+        assert_eq!(
+            clipboard_read(&[], &mut caps).unwrap(),
+            Value::Okay(Box::new(s("thanks!")))
+        );
+
+        notify(&[s("Build"), s("All tests passed")], &mut caps).unwrap();
+        assert_eq!(
+            shown.borrow().as_slice(),
+            &[("Build".to_string(), "All tests passed".to_string())]
+        );
+        reset_backend();
+    }
+
+    #[test]
+    fn test_ui_requires_consent() {
+        set_backend(FakeUi::default());
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+
+        assert!(matches!(
+            clipboard_read(&[], &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            clipboard_write(&[s("secret")], &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            notify(&[s("Hi"), s("there")], &mut caps),
+            Err(StdlibError::PermissionDenied(_))
+        ));
+
+        // Granting one capability does not grant the other
+        caps.grant("stdlib", Capability::Notify, "test");
+        assert!(notify(&[s("Hi"), s("there")], &mut caps).unwrap().is_okay());
+        assert!(clipboard_read(&[], &mut caps).is_err());
+        reset_backend();
+    }
+}
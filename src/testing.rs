@@ -0,0 +1,267 @@
+//! Test Runner for WokeLang
+//!
+//! Finds a program's tests, its `expect "..." { ... }` blocks and its
+//! `to test_*()` functions without parameters, and runs each one on its
+//! own. Every test gets a fresh interpreter that refuses every superpower
+//! without asking, so tests cannot touch files, the network or the
+//! environment unless the runner's policy allows it, and cannot see what
+//! an earlier test left behind. `woke test` reports the results.
+
+use crate::ast::{Program, Span, TopLevelItem};
+use crate::coverage::Coverage;
+use crate::interpreter::{Interpreter, OutputBuffer, RuntimeError};
+use crate::security::Policy;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Prefix of the functions that are tests
+pub const TEST_PREFIX: &str = "test_";
+
+/// What a test is written as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestKind {
+    /// `expect "description" { ... }`
+    Expect,
+    /// `to test_name() { ... }`
+    Function,
+}
+
+/// One test in a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    /// The `expect` block's description or the function's name
+    pub name: String,
+    pub kind: TestKind,
+    /// Position of the test among the program's top-level items
+    pub item: usize,
+    pub span: Span,
+}
+
+/// How one test went
+#[derive(Debug)]
+pub struct TestResult {
+    pub test: TestCase,
+    /// Why the test failed, or `None` if it passed
+    pub error: Option<RuntimeError>,
+    /// What the test printed
+    pub output: String,
+    pub duration: Duration,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Every test in a program, in source order
+pub fn discover(program: &Program) -> Vec<TestCase> {
+    program
+        .items
+        .iter()
+        .enumerate()
+        .filter_map(|(item, top)| match top {
+            TopLevelItem::Expect(block) => Some(TestCase {
+                name: block.description.clone(),
+                kind: TestKind::Expect,
+                item,
+                span: block.span.clone(),
+            }),
+            TopLevelItem::Function(f) if f.name.starts_with(TEST_PREFIX) && f.params.is_empty() => {
+                Some(TestCase {
+                    name: f.name.clone(),
+                    kind: TestKind::Function,
+                    item,
+                    span: f.span.clone(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Runs a program's tests, each in its own deny-all interpreter
+#[derive(Debug, Clone)]
+pub struct TestRunner {
+    module_name: String,
+    filter: Option<String>,
+    policy: Policy,
+    sandbox: Option<PathBuf>,
+    coverage: Option<Coverage>,
+}
+
+impl TestRunner {
+    pub fn new() -> Self {
+        Self {
+            module_name: "main".to_string(),
+            filter: None,
+            policy: Policy::default(),
+            sandbox: None,
+            coverage: None,
+        }
+    }
+
+    /// Name the program's module, as `Interpreter::with_module_name` does
+    pub fn with_module_name(mut self, name: &str) -> Self {
+        self.module_name = name.to_string();
+        self
+    }
+
+    /// Only run tests whose name contains `filter`
+    pub fn with_filter(mut self, filter: &str) -> Self {
+        self.filter = Some(filter.to_string());
+        self
+    }
+
+    /// Let every test use what `policy` allows; everything else is refused
+    pub fn with_policy(mut self, policy: &Policy) -> Self {
+        self.policy = policy.clone();
+        self
+    }
+
+    /// Run every test confined to `root`, as `Interpreter::with_sandbox` does
+    pub fn with_sandbox(mut self, root: &Path) -> Self {
+        self.sandbox = Some(root.to_path_buf());
+        self
+    }
+
+    /// Record the statements tests run in `coverage`
+    pub fn with_coverage(mut self, coverage: &Coverage) -> Self {
+        self.coverage = Some(coverage.clone());
+        self
+    }
+
+    /// The program's tests that pass the filter
+    pub fn selected(&self, program: &Program) -> Vec<TestCase> {
+        discover(program)
+            .into_iter()
+            .filter(|test| self.filter.as_ref().is_none_or(|f| test.name.contains(f.as_str())))
+            .collect()
+    }
+
+    /// Run one test
+    pub fn run_test(&self, program: &Program, test: &TestCase) -> TestResult {
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::new()
+            .with_output(output.clone())
+            .with_module_name(&self.module_name)
+            .with_policy(&self.policy)
+            .with_deny_all();
+        if let Some(root) = &self.sandbox {
+            interpreter = interpreter.with_sandbox(root);
+        }
+        if let Some(coverage) = &self.coverage {
+            interpreter = interpreter.with_debugger(coverage.clone());
+        }
+        let started = Instant::now();
+        let result = interpreter.run_test(program, test);
+        TestResult {
+            test: test.clone(),
+            error: result.err(),
+            output: output.contents(),
+            duration: started.elapsed(),
+        }
+    }
+
+    /// Run every selected test, in source order
+    pub fn run(&self, program: &Program) -> Vec<TestResult> {
+        self.selected(program)
+            .iter()
+            .map(|test| self.run_test(program, test))
+            .collect()
+    }
+}
+
+impl Default for TestRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The 1-based line and column of a byte offset in `source`
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Pipeline;
+
+    fn parse(source: &str) -> Program {
+        Pipeline::new().parse(source).unwrap()
+    }
+
+    #[test]
+    fn test_run_tests() {
+        let program = parse(
+            r#"
+            use std.test;
+
+            to double(n: Int) -> Int {
+                give back n * 2;
+            }
+
+            to test_double() {
+                print("checking");
+                test.assertEqual(double(2), 4);
+            }
+
+            to test_needs_args(n: Int) {
+                test.assertTrue(false);
+            }
+
+            expect "reads the manifest" {
+                test.assertTrue(std.io.readFile("Cargo.toml") != "");
+            }
+
+            expect "division fails" {
+                remember x = 1 / 0;
+            }
+            "#,
+        );
+
+        let results = TestRunner::new().run(&program);
+        let summary: Vec<(&str, TestKind, bool)> = results
+            .iter()
+            .map(|r| (r.test.name.as_str(), r.test.kind, r.passed()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("test_double", TestKind::Function, true),
+                ("reads the manifest", TestKind::Expect, false),
+                ("division fails", TestKind::Expect, false),
+            ]
+        );
+        assert_eq!(results[0].output, "checking\n");
+        assert!(matches!(results[1].error, Some(RuntimeError::ConsentDenied(_))));
+        assert!(matches!(results[2].error, Some(RuntimeError::DivisionByZero)));
+
+        let mut policy = Policy::default();
+        policy.add_flag("--allow-read=Cargo.toml").unwrap();
+        let results = TestRunner::new().with_policy(&policy).with_filter("manifest").run(&program);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].passed(), "{:?}", results[0].error);
+
+        let sandbox = std::env::temp_dir().join("wokelang_test_runner_sandbox");
+        std::fs::create_dir_all(&sandbox).unwrap();
+        let results = TestRunner::new()
+            .with_policy(&policy)
+            .with_sandbox(&sandbox)
+            .with_filter("manifest")
+            .run(&program);
+        assert!(!results[0].passed(), "the sandbox keeps tests out of Cargo.toml");
+    }
+
+    #[test]
+    fn test_line_col() {
+        let source = "to main() {\n    print(1);\n}\n";
+        assert_eq!(line_col(source, 0), (1, 1));
+        assert_eq!(line_col(source, 16), (2, 5));
+        assert_eq!(line_col(source, 1000), (4, 1));
+    }
+}
@@ -0,0 +1,249 @@
+//! Checked Programs
+//!
+//! What the type checker works out about a program, kept for the steps
+//! after it rather than worked out again by each of them:
+//!
+//! ```text
+//! TypeChecker::check(&program) -> CheckedProgram
+//!     .program()    the AST
+//!     .type_of(e)   the type found for an expression
+//!     .functions()  the function table, with each function's signature
+//!     .scope()      the modules imported, by alias, and the functions defined
+//! ```
+//!
+//! The interpreter, the bytecode compiler, the WASM compiler and `woke doc`
+//! take a checked program as it is; the interpreter and the bytecode
+//! compiler can still be given a program that was not checked, for which
+//! they build its [`ModuleScope`] themselves.
+
+use super::InferredType;
+use crate::ast::{Expr, FunctionDef, ModuleImport, Program, Spanned, TopLevelItem};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The names a module brings into scope at its top level: the modules it
+/// imports, under the names it gives them, and the functions it defines
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModuleScope {
+    /// Local names for imported modules, e.g. `string` -> `std.string`
+    aliases: HashMap<String, String>,
+    /// The functions defined, in the order they are
+    functions: Vec<String>,
+}
+
+impl ModuleScope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The scope of a program's top level
+    pub fn of(program: &Program) -> Self {
+        let mut scope = Self::new();
+        for item in &program.items {
+            match item {
+                TopLevelItem::Function(f) => scope.functions.push(f.name.clone()),
+                TopLevelItem::ModuleImport(import) => scope.import(import),
+                _ => {}
+            }
+        }
+        scope
+    }
+
+    /// Bring an imported module into scope, under its alias
+    pub fn import(&mut self, import: &ModuleImport) {
+        self.aliases.insert(import.alias(), import.path.parts.join("."));
+    }
+
+    /// Add the names of another scope, e.g. of the next snippet typed at a
+    /// REPL, to this one
+    pub fn extend(&mut self, other: &ModuleScope) {
+        self.aliases
+            .extend(other.aliases.iter().map(|(alias, path)| (alias.clone(), path.clone())));
+        for name in &other.functions {
+            if !self.functions.contains(name) {
+                self.functions.push(name.clone());
+            }
+        }
+    }
+
+    /// The full path of the module imported as `alias`
+    pub fn alias(&self, alias: &str) -> Option<&str> {
+        self.aliases.get(alias).map(String::as_str)
+    }
+
+    /// The functions defined, in the order they are
+    pub fn functions(&self) -> &[String] {
+        &self.functions
+    }
+
+    /// A name with any imported module alias expanded, so that
+    /// `string.trim` is `std.string.trim`
+    pub fn resolve(&self, name: &str) -> String {
+        match name.split_once('.') {
+            Some((module, rest)) => match self.alias(module) {
+                Some(path) => format!("{}.{}", path, rest),
+                None => name.to_string(),
+            },
+            None => name.to_string(),
+        }
+    }
+
+    /// `found`, a full name, written through the same alias as `written`,
+    /// e.g. to suggest `string.trim` for `string.trm` rather than
+    /// `std.string.trim`
+    pub fn written_as(&self, written: &str, found: &str) -> String {
+        let aliased = written.split_once('.').and_then(|(alias, _)| {
+            let path = self.alias(alias)?;
+            let rest = found.strip_prefix(path)?.strip_prefix('.')?;
+            Some(format!("{}.{}", alias, rest))
+        });
+        aliased.unwrap_or_else(|| found.to_string())
+    }
+}
+
+/// A function of a checked program, with the types its parameters and
+/// result were found to have
+#[derive(Debug, Clone)]
+pub struct CheckedFunction<'p> {
+    pub def: &'p FunctionDef,
+    pub params: Vec<InferredType>,
+    pub ret: InferredType,
+}
+
+impl CheckedFunction<'_> {
+    pub fn name(&self) -> &str {
+        &self.def.name
+    }
+}
+
+/// Shown as a signature, e.g. `area(width: Int, height: Int) -> Int`;
+/// parameters whose type is still unknown are shown by name alone
+impl fmt::Display for CheckedFunction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self
+            .def
+            .params
+            .iter()
+            .zip(&self.params)
+            .map(|(param, ty)| match ty {
+                InferredType::Unknown(_) => param.name.to_string(),
+                ty => format!("{}: {}", param.name, ty),
+            })
+            .collect();
+        write!(f, "{}({}) -> {}", self.def.name, params.join(", "), self.ret)
+    }
+}
+
+/// Where an expression node is, which is what its type is kept by
+pub(super) fn node(expr: &Spanned<Expr>) -> usize {
+    expr as *const Spanned<Expr> as usize
+}
+
+/// A program that passed the type checker, and what the checker found
+/// out about it
+#[derive(Debug, Clone)]
+pub struct CheckedProgram<'p> {
+    program: &'p Program,
+    scope: ModuleScope,
+    functions: Vec<CheckedFunction<'p>>,
+    /// The type of each expression checked, by [`node`]
+    types: HashMap<usize, InferredType>,
+}
+
+impl<'p> CheckedProgram<'p> {
+    pub(super) fn new(
+        program: &'p Program,
+        scope: ModuleScope,
+        functions: Vec<CheckedFunction<'p>>,
+        types: HashMap<usize, InferredType>,
+    ) -> Self {
+        Self { program, scope, functions, types }
+    }
+
+    pub fn program(&self) -> &'p Program {
+        self.program
+    }
+
+    pub fn scope(&self) -> &ModuleScope {
+        &self.scope
+    }
+
+    /// The program's functions, in the order they are defined
+    pub fn functions(&self) -> &[CheckedFunction<'p>] {
+        &self.functions
+    }
+
+    pub fn function(&self, name: &str) -> Option<&CheckedFunction<'p>> {
+        self.functions.iter().find(|f| f.name() == name)
+    }
+
+    /// The type found for an expression of the program, if it was checked;
+    /// the arguments of builtins such as `print` are not
+    pub fn type_of(&self, expr: &Spanned<Expr>) -> Option<&InferredType> {
+        self.types.get(&node(expr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Statement, TopLevelItem};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::typechecker::{TypeChecker, TypeError};
+
+    fn parse(source: &str) -> Program {
+        Parser::new(Lexer::new(source).tokenize().unwrap(), source).parse().unwrap()
+    }
+
+    #[test]
+    fn test_checked_program() {
+        let program = parse(
+            r#"
+            use std.string renamed s;
+            to area(width: Int, height: Int) -> Int {
+                give back width * height;
+            }
+            to half(n) -> Float {
+                give back n / 2.0;
+            }
+            to main() {
+                remember label = s.upper("big");
+                remember size = half(toFloat(area(2, 3)));
+            }
+        "#,
+        );
+        let checked = TypeChecker::new().check(&program).unwrap();
+        assert!(std::ptr::eq(checked.program(), &program));
+
+        // The function table, with the types found for what is not declared
+        let signatures: Vec<String> = checked.functions().iter().map(|f| f.to_string()).collect();
+        assert_eq!(signatures, ["area(width: Int, height: Int) -> Int", "half(n: Float) -> Float", "main() -> Unit"]);
+        assert_eq!(checked.function("half").unwrap().params, [InferredType::Float]);
+        assert!(checked.function("missing").is_none());
+
+        // The type of each expression
+        let TopLevelItem::Function(main) = &program.items[3] else { panic!("main is not a function") };
+        let values: Vec<_> = main
+            .body
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::VarDecl(decl) => checked.type_of(&decl.value),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values, [Some(&InferredType::String), Some(&InferredType::Float)]);
+
+        // The module scope
+        let scope = checked.scope();
+        assert_eq!(scope.functions(), ["area", "half", "main"]);
+        assert_eq!(scope.alias("s"), Some("std.string"));
+        assert_eq!(scope.resolve("s.upper"), "std.string.upper");
+        assert_eq!(scope.resolve("area"), "area");
+        assert_eq!(scope.written_as("s.uper", "std.string.upper"), "s.upper");
+
+        let program = parse("to main() -> Int { give back \"one\"; }");
+        let errors = TypeChecker::new().check(&program).unwrap_err();
+        assert!(matches!(&errors[..], [TypeError::TypeMismatch { .. }]));
+    }
+}
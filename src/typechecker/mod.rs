@@ -3,7 +3,14 @@
 //! This module implements Hindley-Milner style type inference with
 //! support for WokeLang's types including Result types.
 
+mod checked;
+
+pub use checked::{CheckedFunction, CheckedProgram, ModuleScope};
+
 use crate::ast::*;
+use crate::explain;
+use crate::interpreter::{NativeFunction, Signature};
+use crate::stdlib::duration;
 use std::collections::HashMap;
 use thiserror::Error;
 
@@ -12,11 +19,11 @@ pub enum TypeError {
     #[error("Type mismatch: expected {expected}, got {actual}")]
     TypeMismatch { expected: String, actual: String },
 
-    #[error("Undefined variable: {0}")]
-    UndefinedVariable(String),
+    #[error("Undefined variable: {name}")]
+    UndefinedVariable { name: String, suggestion: Option<String> },
 
-    #[error("Undefined function: {0}")]
-    UndefinedFunction(String),
+    #[error("Undefined function: {name}")]
+    UndefinedFunction { name: String, suggestion: Option<String> },
 
     #[error("Cannot infer type: {0}")]
     InferenceError(String),
@@ -32,16 +39,70 @@ pub enum TypeError {
 
     #[error("Cannot call non-function: {0}")]
     NotCallable(String),
+
+    #[error("{function}: {parameter} should be {expected}, got {actual}")]
+    HostArgument {
+        function: String,
+        parameter: String,
+        expected: String,
+        actual: String,
+        span: Span,
+    },
+}
+
+impl TypeError {
+    /// The error's code in the `woke explain` catalog
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::TypeMismatch { .. } => "WOKE-E0200",
+            TypeError::UndefinedVariable { .. } => "WOKE-E0201",
+            TypeError::UndefinedFunction { .. } => "WOKE-E0202",
+            TypeError::InferenceError(_) => "WOKE-E0203",
+            TypeError::ArityMismatch { .. } => "WOKE-E0204",
+            TypeError::AnnotationRequired(_) => "WOKE-E0205",
+            TypeError::CannotIndex(_) => "WOKE-E0206",
+            TypeError::NotCallable(_) => "WOKE-E0207",
+            TypeError::HostArgument { .. } => "WOKE-E0208",
+        }
+    }
+
+    /// Where in the source the error is, for the errors that know
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            TypeError::HostArgument { span, .. } => Some(span.clone()),
+            _ => None,
+        }
+    }
+
+    /// What to try instead, for a name that looks like a typo of another
+    pub fn help(&self) -> Option<String> {
+        match self {
+            TypeError::UndefinedVariable { suggestion: Some(name), .. }
+            | TypeError::UndefinedFunction { suggestion: Some(name), .. } => {
+                Some(format!("did you mean `{}`?", name))
+            }
+            _ => None,
+        }
+    }
 }
 
 type Result<T> = std::result::Result<T, TypeError>;
 
+/// Functions checked by name in calls rather than from the function table
+const BUILTINS: &[&str] = &[
+    "print", "prettyPrint", "toString", "format", "len", "isOkay", "isOops", "unwrapOr", "getError", "toInt", "toFloat",
+];
+
 /// Internal representation of inferred types
 #[derive(Debug, Clone, PartialEq)]
 pub enum InferredType {
     Int,
     Float,
+    Decimal,
     String,
+    Bytes,
+    DateTime,
+    Duration,
     Bool,
     Unit,
     Array(Box<InferredType>),
@@ -59,7 +120,11 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             InferredType::Int => write!(f, "Int"),
             InferredType::Float => write!(f, "Float"),
+            InferredType::Decimal => write!(f, "Decimal"),
             InferredType::String => write!(f, "String"),
+            InferredType::Bytes => write!(f, "Bytes"),
+            InferredType::DateTime => write!(f, "DateTime"),
+            InferredType::Duration => write!(f, "Duration"),
             InferredType::Bool => write!(f, "Bool"),
             InferredType::Unit => write!(f, "Unit"),
             InferredType::Array(inner) => write!(f, "[{}]", inner),
@@ -75,6 +140,62 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     }
 }
 
+impl From<&Type> for InferredType {
+    fn from(ty: &Type) -> Self {
+        match ty {
+            Type::Basic(name) => match name.as_str() {
+                "Int" => InferredType::Int,
+                "Float" => InferredType::Float,
+                "Decimal" => InferredType::Decimal,
+                "String" => InferredType::String,
+                "Bytes" => InferredType::Bytes,
+                "DateTime" => InferredType::DateTime,
+                "Duration" => InferredType::Duration,
+                "Bool" => InferredType::Bool,
+                "Unit" => InferredType::Unit,
+                "Result" => InferredType::Result {
+                    ok: Box::new(InferredType::Unknown(0)),
+                    err: Box::new(InferredType::String),
+                },
+                _ => InferredType::TypeVar(name.clone()),
+            },
+            Type::Array(inner) => InferredType::Array(Box::new(InferredType::from(&**inner))),
+            Type::Optional(inner) => InferredType::Maybe(Box::new(InferredType::from(&**inner))),
+            Type::Reference(inner) => InferredType::from(&**inner),
+            Type::Function(params, ret) => InferredType::Function {
+                params: params.iter().map(InferredType::from).collect(),
+                ret: Box::new(InferredType::from(&**ret)),
+            },
+            Type::Generic(name, args) => {
+                let inferred_args: Vec<_> = args.iter().map(InferredType::from).collect();
+                match name.as_str() {
+                    "Result" if args.len() == 2 => InferredType::Result {
+                        ok: Box::new(inferred_args[0].clone()),
+                        err: Box::new(inferred_args[1].clone()),
+                    },
+                    "Result" if args.len() == 1 => InferredType::Result {
+                        ok: Box::new(inferred_args[0].clone()),
+                        err: Box::new(InferredType::String),
+                    },
+                    "Maybe" | "Option" if args.len() == 1 => {
+                        InferredType::Maybe(Box::new(inferred_args[0].clone()))
+                    }
+                    "Array" if args.len() == 1 => {
+                        InferredType::Array(Box::new(inferred_args[0].clone()))
+                    }
+                    _ => {
+                        // For now, treat unknown generics as type variables
+                        // In the future, we'd look up the generic type definition
+                        InferredType::TypeVar(format!("{}<{}>", name,
+                            args.iter().map(|a| format!("{:?}", a)).collect::<Vec<_>>().join(", ")))
+                    }
+                }
+            },
+            Type::TypeVar(name) => InferredType::TypeVar(name.clone()),
+        }
+    }
+}
+
 /// Type environment tracking variable and function types
 #[derive(Clone)]
 struct TypeEnv {
@@ -114,6 +235,10 @@ fn get(&self, name: &str) -> Option<&InferredType> {
         self.functions.get(name)
     }
 
+    fn get_variable(&self, name: &str) -> Option<&InferredType> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
     fn get_function(&self, name: &str) -> Option<&InferredType> {
         self.functions.get(name)
     }
@@ -124,12 +249,22 @@ fn define_function(&mut self, name: String, ty: InferredType) {
 }
 
 /// The type checker
+#[derive(Clone)]
 pub struct TypeChecker {
     env: TypeEnv,
     /// Counter for generating fresh type variables
     next_type_var: u32,
     /// Substitution map for type unification
     substitutions: HashMap<u32, InferredType>,
+    /// Number of trailing optional parameters for builtins like `std.string.substring`
+    optional_params: HashMap<String, usize>,
+    /// The modules imported and functions defined by the programs checked
+    scope: ModuleScope,
+    /// Parameter names of the host functions declared with a signature
+    host_params: HashMap<String, Vec<String>>,
+    /// The type of each expression checked, while a [`CheckedProgram`] is
+    /// being put together
+    types: Option<HashMap<usize, InferredType>>,
 }
 
 impl Default for TypeChecker {
@@ -144,6 +279,10 @@ pub fn new() -> Self {
             env: TypeEnv::new(),
             next_type_var: 0,
             substitutions: HashMap::new(),
+            optional_params: HashMap::new(),
+            scope: ModuleScope::new(),
+            host_params: HashMap::new(),
+            types: None,
         };
         tc.register_builtins();
         tc
@@ -161,6 +300,24 @@ fn register_builtins(&mut self) {
             },
         );
 
+        // prettyPrint(any) -> Unit
+        self.env.define_function(
+            "prettyPrint".to_string(),
+            InferredType::Function {
+                params: vec![InferredType::Unknown(991)], // Any type
+                ret: Box::new(InferredType::Unit),
+            },
+        );
+
+        // format(String, ...) -> String - variadic, like print
+        self.env.define_function(
+            "format".to_string(),
+            InferredType::Function {
+                params: vec![],
+                ret: Box::new(InferredType::String),
+            },
+        );
+
         // len(String) -> Int  OR  len(Array<T>) -> Int
         // For now, use a fresh type var since we lack proper generics
         self.env.define_function(
@@ -216,15 +373,969 @@ fn register_builtins(&mut self) {
             },
         );
 
-        // getError(Result<T, E>) -> String
+        // getError(Result<T, E>) -> E
         self.env.define_function(
             "getError".to_string(),
             InferredType::Function {
                 params: vec![InferredType::Unknown(992)],
+                ret: Box::new(InferredType::Unknown(990)),
+            },
+        );
+
+        // gratitudeEntries() -> [Map], each with a recipient and a reason
+        self.env.define_function(
+            "gratitudeEntries".to_string(),
+            InferredType::Function {
+                params: vec![],
+                ret: Box::new(InferredType::Array(Box::new(InferredType::TypeVar("Map".to_string())))),
+            },
+        );
+
+        // ask(String) -> String, askNumber(String) -> Float, askYesNo(String) -> Bool
+        for (name, answer) in [
+            ("ask", InferredType::String),
+            ("askNumber", InferredType::Float),
+            ("askYesNo", InferredType::Bool),
+        ] {
+            self.env.define_function(
+                name.to_string(),
+                InferredType::Function {
+                    params: vec![InferredType::String],
+                    ret: Box::new(answer),
+                },
+            );
+        }
+
+        // exit(Int) -> Unit
+        self.env.define_function(
+            "exit".to_string(),
+            InferredType::Function {
+                params: vec![InferredType::Int],
+                ret: Box::new(InferredType::Unit),
+            },
+        );
+
+        self.register_math_module();
+        self.register_decimal_module();
+        self.register_string_module();
+        self.register_array_module();
+        self.register_map_module();
+        self.register_regex_module();
+        self.register_json_module();
+        self.register_csv_module();
+        self.register_config_module();
+        self.register_net_module();
+        self.register_env_module();
+        self.register_process_module();
+        self.register_ui_module();
+        self.register_superpowers_module();
+        self.register_term_module();
+        self.register_crypto_module();
+        self.register_encoding_module();
+        self.register_io_module();
+        self.register_archive_module();
+        self.register_bytes_module();
+        self.register_path_module();
+        self.register_log_module();
+        self.register_test_module();
+        self.register_time_module();
+    }
+
+    /// Register signatures for `std.string`
+    fn register_string_module(&mut self) {
+        use InferredType::{Bool, Int, String};
+        let strings = || InferredType::Array(Box::new(String));
+
+        let signatures = [
+            ("length", vec![String], Int),
+            ("upper", vec![String], String),
+            ("lower", vec![String], String),
+            ("toUpper", vec![String], String),
+            ("toLower", vec![String], String),
+            ("trim", vec![String], String),
+            ("trimStart", vec![String], String),
+            ("trimEnd", vec![String], String),
+            ("contains", vec![String, String], Bool),
+            ("startsWith", vec![String, String], Bool),
+            ("endsWith", vec![String, String], Bool),
+            ("replace", vec![String, String, String], String),
+            ("split", vec![String, String], strings()),
+            ("join", vec![strings(), String], String),
+            ("substring", vec![String, Int, Int], String),
+            ("indexOf", vec![String, String], Int),
+            ("repeat", vec![String, Int], String),
+            ("reverse", vec![String], String),
+            ("padStart", vec![String, Int, String], String),
+            ("padEnd", vec![String, Int, String], String),
+            ("chars", vec![String], strings()),
+            ("charAt", vec![String, Int], String),
+            ("codePointAt", vec![String, Int], Int),
+            ("graphemes", vec![String], strings()),
+            ("isEmpty", vec![String], Bool),
+            // format(template, values...) is variadic
+            ("format", vec![], String),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.string.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+
+        // The end index and pad character may be left out
+        for name in ["substring", "padStart", "padEnd"] {
+            self.optional_params.insert(format!("std.string.{}", name), 1);
+        }
+    }
+
+    /// Register signatures for `std.array`
+    ///
+    /// Element types are type variables, which unify with anything.
+    fn register_array_module(&mut self) {
+        use InferredType::{Bool, Int};
+        let var = |name: &str| InferredType::TypeVar(name.to_string());
+        let array = |inner: InferredType| InferredType::Array(Box::new(inner));
+        let func = |params: Vec<InferredType>, ret: InferredType| InferredType::Function {
+            params,
+            ret: Box::new(ret),
+        };
+        let maybe_element = || InferredType::Result {
+            ok: Box::new(var("T")),
+            err: Box::new(InferredType::String),
+        };
+
+        let signatures = [
+            ("length", vec![array(var("T"))], Int),
+            ("isEmpty", vec![array(var("T"))], Bool),
+            ("first", vec![array(var("T"))], maybe_element()),
+            ("last", vec![array(var("T"))], maybe_element()),
+            ("map", vec![array(var("T")), func(vec![var("T")], var("U"))], array(var("U"))),
+            ("filter", vec![array(var("T")), func(vec![var("T")], Bool)], array(var("T"))),
+            (
+                "reduce",
+                vec![array(var("T")), func(vec![var("U"), var("T")], var("U")), var("U")],
+                var("U"),
+            ),
+            ("sort", vec![array(var("T")), func(vec![var("T"), var("T")], Int)], array(var("T"))),
+            ("reverse", vec![array(var("T"))], array(var("T"))),
+            ("push", vec![array(var("T")), var("T")], array(var("T"))),
+            // [remaining elements, popped element]
+            ("pop", vec![array(var("T"))], array(var("T"))),
+            ("slice", vec![array(var("T")), Int, Int], array(var("T"))),
+            ("concat", vec![array(var("T")), array(var("T"))], array(var("T"))),
+            ("contains", vec![array(var("T")), var("T")], Bool),
+            ("indexOf", vec![array(var("T")), var("T")], Int),
+            ("zip", vec![array(var("T")), array(var("U"))], array(array(var("T")))),
+            ("repeat", vec![var("T"), Int], array(var("T"))),
+            ("range", vec![Int, Int, Int], array(Int)),
+            ("flatten", vec![array(array(var("T")))], array(var("T"))),
+            ("unique", vec![array(var("T"))], array(var("T"))),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.array.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+
+        // Optional comparator, end index, and range start/step
+        for (name, optional) in [("sort", 1), ("slice", 1), ("range", 2)] {
+            self.optional_params.insert(format!("std.array.{}", name), optional);
+        }
+    }
+
+    /// Register signatures for `std.map`
+    ///
+    /// There is no map type yet, so maps and their values are type variables.
+    fn register_map_module(&mut self) {
+        use InferredType::{Bool, Int, String, Unit};
+        let map = || InferredType::TypeVar("Map".to_string());
+        let value = || InferredType::TypeVar("V".to_string());
+        let array = |inner: InferredType| InferredType::Array(Box::new(inner));
+
+        let signatures = [
+            ("new", vec![array(array(value()))], map()),
+            (
+                "get",
+                vec![map(), String],
+                InferredType::Result {
+                    ok: Box::new(value()),
+                    err: Box::new(String),
+                },
+            ),
+            ("set", vec![map(), String, value()], map()),
+            ("remove", vec![map(), String], map()),
+            ("has", vec![map(), String], Bool),
+            ("size", vec![map()], Int),
+            ("keys", vec![map()], array(String)),
+            ("values", vec![map()], array(value())),
+            ("entries", vec![map()], array(array(value()))),
+            ("merge", vec![map(), map()], map()),
+            (
+                "forEach",
+                vec![
+                    map(),
+                    InferredType::Function {
+                        params: vec![String, value()],
+                        ret: Box::new(value()),
+                    },
+                ],
+                Unit,
+            ),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.map.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+
+        // The initial entries may be left out
+        self.optional_params.insert("std.map.new".to_string(), 1);
+    }
+
+    /// Register signatures for `std.regex`
+    ///
+    /// Every function returns a Result, with invalid patterns as `Oops`.
+    fn register_regex_module(&mut self) {
+        use InferredType::{Bool, String};
+        let result = |ok: InferredType| InferredType::Result {
+            ok: Box::new(ok),
+            err: Box::new(String),
+        };
+        let strings = || InferredType::Array(Box::new(String));
+
+        let signatures = [
+            ("match", vec![String, String], result(Bool)),
+            ("find", vec![String, String], result(String)),
+            ("findAll", vec![String, String], result(strings())),
+            ("replace", vec![String, String, String], result(String)),
+            ("split", vec![String, String], result(strings())),
+            (
+                "captures",
+                vec![String, String],
+                result(InferredType::TypeVar("Map".to_string())),
+            ),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.regex.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.json`
+    ///
+    /// Parsed JSON can be any value, so it is left as a type variable.
+    fn register_json_module(&mut self) {
+        let any = |name: &str| InferredType::TypeVar(name.to_string());
+        self.env.define_function(
+            "std.json.parse".to_string(),
+            InferredType::Function {
+                params: vec![InferredType::String],
+                ret: Box::new(any("Json")),
+            },
+        );
+        // The second argument is `true` or an Int indent width
+        self.env.define_function(
+            "std.json.stringify".to_string(),
+            InferredType::Function {
+                params: vec![any("T"), any("Indent")],
                 ret: Box::new(InferredType::String),
             },
         );
+        self.optional_params.insert("std.json.stringify".to_string(), 1);
+    }
+
+    /// Register signatures for `std.csv`
+    ///
+    /// Rows are maps or arrays depending on the header flag, so they are left
+    /// as a type variable.
+    fn register_csv_module(&mut self) {
+        use InferredType::{Bool, Int, String};
+        let row = || InferredType::TypeVar("Row".to_string());
+        let rows = || InferredType::Array(Box::new(row()));
+
+        let signatures = [
+            ("parse", vec![String, Bool], rows()),
+            (
+                "stringify",
+                vec![rows(), InferredType::Array(Box::new(String))],
+                String,
+            ),
+            (
+                "forEachRow",
+                vec![
+                    String,
+                    InferredType::Function {
+                        params: vec![row()],
+                        ret: Box::new(InferredType::TypeVar("T".to_string())),
+                    },
+                    Bool,
+                ],
+                Int,
+            ),
+        ];
+        for (name, params, ret) in signatures {
+            let name = format!("std.csv.{}", name);
+            self.optional_params.insert(name.clone(), 1);
+            self.env.define_function(
+                name,
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.config`
+    fn register_config_module(&mut self) {
+        let signatures = [
+            // A TOML document is always a table
+            ("parseToml", InferredType::TypeVar("Map".to_string())),
+            ("parseYaml", InferredType::TypeVar("Yaml".to_string())),
+        ];
+        for (name, ret) in signatures {
+            self.env.define_function(
+                format!("std.config.{}", name),
+                InferredType::Function {
+                    params: vec![InferredType::String],
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.net`
+    fn register_net_module(&mut self) {
+        use InferredType::{Bool, Int, String};
+        let var = |name: &str| InferredType::TypeVar(name.to_string());
+
+        let signatures = [
+            ("httpGet", vec![String, String], String, 1),
+            ("httpPost", vec![String, String, String], String, 1),
+            ("download", vec![String, String], Bool, 0),
+            (
+                "serve",
+                vec![
+                    Int,
+                    InferredType::Function {
+                        params: vec![var("Request")],
+                        ret: Box::new(var("Response")),
+                    },
+                    var("Map"),
+                ],
+                Int,
+                1,
+            ),
+            // Sockets are Int handles
+            ("tcpConnect", vec![String, Int], Int, 0),
+            ("tcpListen", vec![Int, String], Int, 1),
+            ("tcpAccept", vec![Int], Int, 0),
+            ("tcpSend", vec![Int, String], Int, 0),
+            ("tcpReceive", vec![Int, Int], String, 1),
+            ("udpBind", vec![Int, String], Int, 1),
+            ("udpSend", vec![Int, String, Int, String], Int, 0),
+            ("udpReceive", vec![Int, Int], var("Map"), 1),
+            ("localPort", vec![Int], Int, 0),
+            ("close", vec![Int], Bool, 0),
+        ];
+        for (name, params, ret, optional) in signatures {
+            let name = format!("std.net.{}", name);
+            if optional > 0 {
+                self.optional_params.insert(name.clone(), optional);
+            }
+            self.env.define_function(
+                name,
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.env`
+    fn register_env_module(&mut self) {
+        use InferredType::{String, Unit};
+        let strings = || InferredType::Array(Box::new(String));
+        let signatures = [
+            (
+                "get",
+                vec![String],
+                InferredType::Result {
+                    ok: Box::new(String),
+                    err: Box::new(String),
+                },
+            ),
+            ("set", vec![String, String], Unit),
+            ("all", vec![], InferredType::TypeVar("Map".to_string())),
+            ("args", vec![], strings()),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.env.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+        // args() is std.env.args() without the import
+        self.env.define_function(
+            "args".to_string(),
+            InferredType::Function {
+                params: vec![],
+                ret: Box::new(strings()),
+            },
+        );
+    }
+
+    /// Register signatures for `std.process`
+    ///
+    /// Processes are Int handles; results are maps of code, stdout and stderr.
+    fn register_process_module(&mut self) {
+        use InferredType::{Bool, Int, String};
+        let var = |name: &str| InferredType::TypeVar(name.to_string());
+        let result = || InferredType::Result {
+            ok: Box::new(var("Map")),
+            err: Box::new(String),
+        };
+        let command = || vec![String, InferredType::Array(Box::new(String)), var("Map")];
+
+        let signatures = [
+            ("run", command(), result()),
+            ("spawn", command(), Int),
+            ("wait", vec![Int], result()),
+            ("isRunning", vec![Int], Bool),
+            ("kill", vec![Int], Bool),
+        ];
+        for (name, params, ret) in signatures {
+            let name = format!("std.process.{}", name);
+            if params.len() == 3 {
+                self.optional_params.insert(name.clone(), 1);
+            }
+            self.env.define_function(
+                name,
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.ui`
+    fn register_ui_module(&mut self) {
+        use InferredType::{String, Unit};
+        let result = |ok| InferredType::Result {
+            ok: Box::new(ok),
+            err: Box::new(String),
+        };
+        let signatures = [
+            ("clipboardRead", vec![], result(String)),
+            ("clipboardWrite", vec![String], result(Unit)),
+            ("notify", vec![String, String], result(Unit)),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.ui.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.superpowers` and the builtins that
+    /// call it
+    fn register_superpowers_module(&mut self) {
+        use InferredType::{Bool, String, Unit};
+        let signatures = [
+            ("list", "listSuperpowers", vec![], InferredType::Array(Box::new(String))),
+            ("has", "hasSuperpower", vec![String], Bool),
+            ("renounce", "renounce", vec![String], Unit),
+        ];
+        for (name, builtin, params, ret) in signatures {
+            let ty = InferredType::Function {
+                params,
+                ret: Box::new(ret),
+            };
+            self.env.define_function(format!("std.superpowers.{}", name), ty.clone());
+            self.env.define_function(builtin.to_string(), ty);
+        }
+    }
+
+    /// Register signatures for `std.term`
+    fn register_term_module(&mut self) {
+        use InferredType::{Bool, Int, String, Unit};
+        let signatures = [
+            ("color", vec![String, String], String),
+            ("background", vec![String, String], String),
+            ("style", vec![String, String], String),
+            ("strip", vec![String], String),
+            ("isTty", vec![], Bool),
+            ("setColor", vec![Bool], Unit),
+            ("width", vec![], Int),
+            ("cursorUp", vec![Int], String),
+            ("cursorDown", vec![Int], String),
+            ("cursorColumn", vec![Int], String),
+            ("clearLine", vec![], String),
+            ("hideCursor", vec![], String),
+            ("showCursor", vec![], String),
+            ("progressBar", vec![Int, Int, Int], String),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.term.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+        // Cursors move one line by default; bars are 20 cells wide
+        self.optional_params.insert("std.term.cursorUp".to_string(), 1);
+        self.optional_params.insert("std.term.cursorDown".to_string(), 1);
+        self.optional_params.insert("std.term.progressBar".to_string(), 1);
+    }
+
+    /// Register signatures for `std.math`
+    ///
+    /// Most functions take an Int or a Float, so those parameters are type
+    /// variables.
+    fn register_math_module(&mut self) {
+        use InferredType::{Float, Int, String, Unit};
+        let number = || InferredType::TypeVar("Number".to_string());
+        let item = || InferredType::TypeVar("T".to_string());
+        let signatures = [
+            ("abs", vec![number()], number()),
+            ("sqrt", vec![number()], Float),
+            ("pow", vec![number(), number()], Float),
+            ("sin", vec![number()], Float),
+            ("cos", vec![number()], Float),
+            ("tan", vec![number()], Float),
+            ("floor", vec![number()], Int),
+            ("ceil", vec![number()], Int),
+            ("round", vec![number()], Int),
+            ("min", vec![number(), number()], number()),
+            ("max", vec![number(), number()], number()),
+            ("random", vec![number(), number()], Float),
+            ("randomSeed", vec![Int], Unit),
+            ("randomInt", vec![Int, Int], Int),
+            (
+                "randomChoice",
+                vec![InferredType::Array(Box::new(item()))],
+                InferredType::Result {
+                    ok: Box::new(item()),
+                    err: Box::new(String),
+                },
+            ),
+            (
+                "shuffle",
+                vec![InferredType::Array(Box::new(item()))],
+                InferredType::Array(Box::new(item())),
+            ),
+            ("pi", vec![], Float),
+            ("e", vec![], Float),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.math.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+        // With no range, random gives a Float in [0, 1)
+        self.optional_params.insert("std.math.random".to_string(), 2);
+    }
+
+    /// Register signatures for `std.decimal`
+    fn register_decimal_module(&mut self) {
+        use InferredType::{Decimal, Float, Int, String};
+        let signatures = [
+            ("round", vec![Decimal, Int], Decimal),
+            ("roundHalfEven", vec![Decimal, Int], Decimal),
+            ("truncate", vec![Decimal, Int], Decimal),
+            ("floor", vec![Decimal], Decimal),
+            ("ceil", vec![Decimal], Decimal),
+            ("places", vec![Decimal], Int),
+            (
+                "parse",
+                vec![String],
+                InferredType::Result {
+                    ok: Box::new(Decimal),
+                    err: Box::new(String),
+                },
+            ),
+            ("fromFloat", vec![Float], Decimal),
+            ("toFloat", vec![Decimal], Float),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.decimal.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.crypto`
+    ///
+    /// Hashed data may be a String or Bytes, so it is a type variable.
+    fn register_crypto_module(&mut self) {
+        use InferredType::{Bool, Int, String};
+        let data = || InferredType::TypeVar("Data".to_string());
+        let signatures = [
+            ("sha256", vec![data()], String),
+            ("sha512", vec![data()], String),
+            ("hmac", vec![data(), data(), String], String),
+            ("randomBytes", vec![Int], String),
+            ("constantTimeEquals", vec![String, String], Bool),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.crypto.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+        // The algorithm defaults to sha256
+        self.optional_params.insert("std.crypto.hmac".to_string(), 1);
+    }
+
+    /// Register signatures for `std.encoding`
+    ///
+    /// Encoders take a String or Bytes; decoders return a Result, with
+    /// invalid input as `Oops`.
+    fn register_encoding_module(&mut self) {
+        use InferredType::{Bool, Bytes, String};
+        let data = || InferredType::TypeVar("Data".to_string());
+        let decoded = |ok| InferredType::Result {
+            ok: Box::new(ok),
+            err: Box::new(String),
+        };
+        let signatures = [
+            ("base64Encode", vec![data(), Bool], String),
+            ("base64Decode", vec![String, Bool], decoded(String)),
+            ("base64DecodeBytes", vec![String, Bool], decoded(Bytes)),
+            ("hexEncode", vec![data()], String),
+            ("hexDecode", vec![String], decoded(String)),
+            ("hexDecodeBytes", vec![String], decoded(Bytes)),
+            ("urlEncode", vec![String], String),
+            ("urlDecode", vec![String], decoded(String)),
+        ];
+        for (name, params, ret) in signatures {
+            let name = format!("std.encoding.{}", name);
+            if params.len() == 2 {
+                // The URL-safe flag may be left out
+                self.optional_params.insert(name.clone(), 1);
+            }
+            self.env.define_function(
+                name,
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
 
+    /// Register signatures for text file I/O in `std.io`
+    ///
+    /// Line cursors from `readLines` are Int handles.
+    fn register_io_module(&mut self) {
+        use InferredType::{Bool, Int, String};
+        let signatures = [
+            ("readFile", vec![String], String),
+            ("writeFile", vec![String, String], Bool),
+            ("appendFile", vec![String, String], Bool),
+            ("exists", vec![String], Bool),
+            ("delete", vec![String], Bool),
+            ("listDir", vec![String], InferredType::Array(Box::new(String))),
+            ("createDir", vec![String], Bool),
+            ("readLine", vec![String], String),
+            ("readLines", vec![String], Int),
+            (
+                "nextLine",
+                vec![Int],
+                InferredType::Result {
+                    ok: Box::new(String),
+                    err: Box::new(String),
+                },
+            ),
+            ("closeLines", vec![Int], Bool),
+            (
+                "forEachLine",
+                vec![
+                    String,
+                    InferredType::Function {
+                        params: vec![String],
+                        ret: Box::new(InferredType::TypeVar("T".to_string())),
+                    },
+                ],
+                Int,
+            ),
+            ("streamWrite", vec![String, String], Bool),
+            ("streamClose", vec![String], Bool),
+            ("glob", vec![String], InferredType::Array(Box::new(String))),
+            (
+                "walk",
+                vec![String],
+                InferredType::Array(Box::new(InferredType::TypeVar("Map".to_string()))),
+            ),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.io.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+        // The prompt may be left out
+        self.optional_params.insert("std.io.readLine".to_string(), 1);
+    }
+
+    /// Register signatures for `std.archive`; each returns the number of
+    /// files packed or unpacked
+    fn register_archive_module(&mut self) {
+        use InferredType::{Int, String};
+        for name in ["zip", "unzip", "tarCreate", "tarExtract"] {
+            self.env.define_function(
+                format!("std.archive.{}", name),
+                InferredType::Function {
+                    params: vec![String, String],
+                    ret: Box::new(Int),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.bytes` and binary file I/O
+    fn register_bytes_module(&mut self) {
+        use InferredType::{Bool, Bytes, Int, String};
+        let signatures = [
+            ("std.bytes.fromString", vec![String], Bytes),
+            (
+                "std.bytes.toString",
+                vec![Bytes],
+                InferredType::Result {
+                    ok: Box::new(String),
+                    err: Box::new(String),
+                },
+            ),
+            ("std.bytes.fromArray", vec![InferredType::Array(Box::new(Int))], Bytes),
+            ("std.bytes.toArray", vec![Bytes], InferredType::Array(Box::new(Int))),
+            ("std.bytes.length", vec![Bytes], Int),
+            ("std.bytes.slice", vec![Bytes, Int, Int], Bytes),
+            ("std.bytes.concat", vec![Bytes, Bytes], Bytes),
+            ("std.io.readBytes", vec![String], Bytes),
+            ("std.io.writeBytes", vec![String, Bytes], Bool),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                name.to_string(),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+        // The end index may be left out
+        self.optional_params.insert("std.bytes.slice".to_string(), 1);
+    }
+
+    /// Register signatures for `std.path`
+    fn register_path_module(&mut self) {
+        use InferredType::{Bool, String};
+        let signatures = [
+            // join takes any number of parts; empty params mark it variadic
+            ("join", vec![], String),
+            ("dirname", vec![String], String),
+            ("basename", vec![String], String),
+            ("extension", vec![String], String),
+            ("normalize", vec![String], String),
+            ("absolute", vec![String], String),
+            ("exists", vec![String], Bool),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.path.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.log`
+    fn register_log_module(&mut self) {
+        use InferredType::{String, Unit};
+        let fields = || InferredType::TypeVar("Map".to_string());
+        let signatures = [
+            ("debug", vec![String, fields()], Unit),
+            ("info", vec![String, fields()], Unit),
+            ("warn", vec![String, fields()], Unit),
+            ("error", vec![String, fields()], Unit),
+            ("setLevel", vec![String], Unit),
+            ("setFormat", vec![String], Unit),
+        ];
+        for (name, params, ret) in signatures {
+            let name = format!("std.log.{}", name);
+            if params.len() == 2 {
+                // The fields map may be left out
+                self.optional_params.insert(name.clone(), 1);
+            }
+            self.env.define_function(
+                name,
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.test`
+    ///
+    /// Compared values may be of any type, so they are type variables.
+    fn register_test_module(&mut self) {
+        use InferredType::{Bool, String, Unit};
+        let var = |name: &str| InferredType::TypeVar(name.to_string());
+        let signatures = [
+            ("assertEqual", vec![var("T"), var("T"), String], Unit),
+            ("assertTrue", vec![Bool, String], Unit),
+            ("assertOops", vec![var("Result"), String], Unit),
+        ];
+        for (name, params, ret) in signatures {
+            let name = format!("std.test.{}", name);
+            // The message may be left out
+            self.optional_params.insert(name.clone(), 1);
+            self.env.define_function(
+                name,
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+    }
+
+    /// Register signatures for `std.time`
+    ///
+    /// Timestamps are Ints of milliseconds; `format` takes either a
+    /// timestamp or a DateTime, and `sleep` either milliseconds or a
+    /// Duration, so those parameters are type variables.
+    fn register_time_module(&mut self) {
+        use InferredType::{DateTime, Duration, Float, Int, String, Unit};
+        let signatures = [
+            ("now", vec![], Int),
+            ("timestamp", vec![], Int),
+            ("format", vec![InferredType::TypeVar("Time".to_string()), String], String),
+            ("parse", vec![String, String], Int),
+            ("sleep", vec![InferredType::TypeVar("Delay".to_string())], Unit),
+            ("elapsed", vec![String, String], InferredType::TypeVar("Elapsed".to_string())),
+            (
+                "dateTime",
+                vec![Int, Int, Int, Int, Int, Int, String],
+                DateTime,
+            ),
+            ("current", vec![String], DateTime),
+            (
+                "parseRfc3339",
+                vec![String],
+                InferredType::Result {
+                    ok: Box::new(DateTime),
+                    err: Box::new(String),
+                },
+            ),
+            ("toRfc3339", vec![DateTime], String),
+            ("fromTimestamp", vec![Int, String], DateTime),
+            ("toTimestamp", vec![DateTime], Int),
+            ("inZone", vec![DateTime, String], DateTime),
+            ("year", vec![DateTime], Int),
+            ("month", vec![DateTime], Int),
+            ("day", vec![DateTime], Int),
+            ("hour", vec![DateTime], Int),
+            ("minute", vec![DateTime], Int),
+            ("second", vec![DateTime], Int),
+            ("weekday", vec![DateTime], Int),
+            ("offset", vec![DateTime], String),
+            ("addDays", vec![DateTime, Int], DateTime),
+            ("addMonths", vec![DateTime, Int], DateTime),
+            ("duration", vec![InferredType::TypeVar("Amount".to_string()), String], Duration),
+            ("fromMillis", vec![Int], Duration),
+            ("toMillis", vec![Duration], Int),
+            ("toSeconds", vec![Duration], Float),
+        ];
+        for (name, params, ret) in signatures {
+            self.env.define_function(
+                format!("std.time.{}", name),
+                InferredType::Function {
+                    params,
+                    ret: Box::new(ret),
+                },
+            );
+        }
+
+        // The time of day and zone may be left out; zones default to local
+        self.optional_params.insert("std.time.dateTime".to_string(), 4);
+        self.optional_params.insert("std.time.current".to_string(), 1);
+        self.optional_params.insert("std.time.fromTimestamp".to_string(), 1);
+    }
+
+    /// Expand an imported module alias in a call, e.g. `string.split` to
+    /// `std.string.split`
+    fn resolve_call_name(&self, name: &str) -> String {
+        self.scope.resolve(name)
+    }
+
+    /// An unknown variable, with the name in scope it may be a typo of
+    fn undefined_variable(&self, name: &str) -> TypeError {
+        let names = self.env.scopes.iter().flat_map(|scope| scope.keys()).chain(self.env.functions.keys());
+        TypeError::UndefinedVariable {
+            name: name.to_string(),
+            suggestion: explain::did_you_mean(name, names.map(String::as_str)).map(str::to_string),
+        }
+    }
+
+    /// An unknown function, called as `written`, with the function it may
+    /// be a typo of, written through the same module alias
+    fn undefined_function(&self, written: &str) -> TypeError {
+        let name = self.resolve_call_name(written);
+        let names = self
+            .env
+            .functions
+            .keys()
+            .chain(self.env.scopes.iter().flat_map(|scope| scope.keys()))
+            .map(String::as_str)
+            .chain(BUILTINS.iter().copied());
+        let suggestion = explain::did_you_mean(&name, names).map(|found| self.scope.written_as(written, found));
+        TypeError::UndefinedFunction { name, suggestion }
     }
 
     /// Generate a fresh type variable
@@ -271,7 +1382,11 @@ fn unify(&mut self, t1: &InferredType, t2: &InferredType) -> Result<()> {
             // Same types unify
             (InferredType::Int, InferredType::Int) => Ok(()),
             (InferredType::Float, InferredType::Float) => Ok(()),
+            (InferredType::Decimal, InferredType::Decimal) => Ok(()),
             (InferredType::String, InferredType::String) => Ok(()),
+            (InferredType::Bytes, InferredType::Bytes) => Ok(()),
+            (InferredType::DateTime, InferredType::DateTime) => Ok(()),
+            (InferredType::Duration, InferredType::Duration) => Ok(()),
             (InferredType::Bool, InferredType::Bool) => Ok(()),
             (InferredType::Unit, InferredType::Unit) => Ok(()),
 
@@ -279,6 +1394,11 @@ fn unify(&mut self, t1: &InferredType, t2: &InferredType) -> Result<()> {
             (InferredType::Int, InferredType::Float) => Ok(()),
             (InferredType::Float, InferredType::Int) => Ok(()),
 
+            // Int and Decimal can unify (Int promotes to Decimal), but
+            // Float and Decimal cannot
+            (InferredType::Int, InferredType::Decimal) => Ok(()),
+            (InferredType::Decimal, InferredType::Int) => Ok(()),
+
             // Unknown types get substituted
             (InferredType::Unknown(id), other) => {
                 self.substitutions.insert(*id, other.clone());
@@ -330,82 +1450,212 @@ fn unify(&mut self, t1: &InferredType, t2: &InferredType) -> Result<()> {
         }
     }
 
+    /// Result of arithmetic mixing Durations and DateTimes, or `None` if
+    /// neither side is one of them
+    fn time_arithmetic(
+        &self,
+        op: &BinaryOp,
+        left: &InferredType,
+        right: &InferredType,
+    ) -> Option<InferredType> {
+        use InferredType::{DateTime, Duration, Int};
+        let left = self.apply_substitutions(left);
+        let right = self.apply_substitutions(right);
+        match (op, &left, &right) {
+            (BinaryOp::Add | BinaryOp::Sub, Duration, Duration) => Some(Duration),
+            (BinaryOp::Add, Duration, DateTime) => Some(DateTime),
+            (BinaryOp::Add | BinaryOp::Sub, DateTime, Duration) => Some(DateTime),
+            (BinaryOp::Sub, DateTime, DateTime) => Some(Duration),
+            (BinaryOp::Mul, Duration, Int) | (BinaryOp::Mul, Int, Duration) => Some(Duration),
+            _ => None,
+        }
+    }
+
+    /// Result of arithmetic on a Decimal and a Decimal or Int, or `None`
+    /// if neither side is a Decimal
+    fn decimal_arithmetic(
+        &self,
+        op: &BinaryOp,
+        left: &InferredType,
+        right: &InferredType,
+    ) -> Option<InferredType> {
+        use InferredType::{Decimal, Int};
+        let left = self.apply_substitutions(left);
+        let right = self.apply_substitutions(right);
+        match (op, &left, &right) {
+            (
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod,
+                Decimal,
+                Decimal | Int,
+            )
+            | (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod, Int, Decimal) => {
+                Some(Decimal)
+            }
+            _ => None,
+        }
+    }
+
     /// Convert AST Type to InferredType
     fn ast_type_to_inferred(&self, ty: &Type) -> InferredType {
-        match ty {
-            Type::Basic(name) => match name.as_str() {
-                "Int" => InferredType::Int,
-                "Float" => InferredType::Float,
-                "String" => InferredType::String,
-                "Bool" => InferredType::Bool,
-                "Unit" => InferredType::Unit,
-                "Result" => InferredType::Result {
-                    ok: Box::new(InferredType::Unknown(0)),
-                    err: Box::new(InferredType::String),
-                },
-                _ => InferredType::TypeVar(name.clone()),
-            },
-            Type::Array(inner) => InferredType::Array(Box::new(self.ast_type_to_inferred(inner))),
-            Type::Optional(inner) => InferredType::Maybe(Box::new(self.ast_type_to_inferred(inner))),
-            Type::Reference(inner) => self.ast_type_to_inferred(inner),
-            Type::Function(params, ret) => InferredType::Function {
-                params: params.iter().map(|p| self.ast_type_to_inferred(p)).collect(),
-                ret: Box::new(self.ast_type_to_inferred(ret)),
-            },
-            Type::Generic(name, args) => {
-                let inferred_args: Vec<_> = args.iter().map(|a| self.ast_type_to_inferred(a)).collect();
-                match name.as_str() {
-                    "Result" if args.len() == 2 => InferredType::Result {
-                        ok: Box::new(inferred_args[0].clone()),
-                        err: Box::new(inferred_args[1].clone()),
-                    },
-                    "Result" if args.len() == 1 => InferredType::Result {
-                        ok: Box::new(inferred_args[0].clone()),
-                        err: Box::new(InferredType::String),
-                    },
-                    "Maybe" | "Option" if args.len() == 1 => {
-                        InferredType::Maybe(Box::new(inferred_args[0].clone()))
-                    }
-                    "Array" if args.len() == 1 => {
-                        InferredType::Array(Box::new(inferred_args[0].clone()))
-                    }
-                    _ => {
-                        // For now, treat unknown generics as type variables
-                        // In the future, we'd look up the generic type definition
-                        InferredType::TypeVar(format!("{}<{}>", name,
-                            args.iter().map(|a| format!("{:?}", a)).collect::<Vec<_>>().join(", ")))
-                    }
-                }
+        InferredType::from(ty)
+    }
+
+    /// The type of an expression on its own, with the functions of the
+    /// programs checked so far in scope and what unification found applied
+    pub fn infer_expression(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
+        self.env.push_scope();
+        let ty = self.infer_expr(expr);
+        self.env.pop_scope();
+        Ok(self.apply_substitutions(&ty?))
+    }
+
+    /// Type check statements run at the top level, e.g. typed at a REPL,
+    /// keeping the variables they declare for the code checked after them
+    ///
+    /// If one fails, none of the variables are kept.
+    pub fn check_statements(&mut self, statements: &[Statement]) -> Result<()> {
+        let saved = self.env.scopes.clone();
+        let checked = statements
+            .iter()
+            .try_for_each(|stmt| self.check_statement(stmt, &InferredType::Unit));
+        if checked.is_err() {
+            self.env.scopes = saved;
+        }
+        checked
+    }
+
+    /// Declare a function the host provides, such as a plugin's, taking
+    /// `arity` arguments of any type and giving back any type
+    pub fn declare_function(&mut self, name: &str, arity: usize) {
+        self.host_params.remove(name);
+        let any = |name: &str| InferredType::TypeVar(name.to_string());
+        self.env.define_function(
+            name.to_string(),
+            InferredType::Function {
+                params: vec![any("T"); arity],
+                ret: Box::new(any("R")),
             },
-            Type::TypeVar(name) => InferredType::TypeVar(name.clone()),
+        );
+    }
+
+    /// A type checker that knows the given host functions
+    pub fn with_natives<'a>(natives: impl IntoIterator<Item = &'a NativeFunction>) -> Self {
+        let mut checker = Self::new();
+        for native in natives {
+            match native.signature() {
+                Some(signature) => checker.declare_signature(signature),
+                None => checker.declare_function(native.name(), native.arity()),
+            }
         }
+        checker
+    }
+
+    /// Declare a host function bound with a signature, so that calls to
+    /// it are checked against the types it declares
+    pub fn declare_signature(&mut self, signature: &Signature) {
+        let params = signature.params().iter().map(|(name, _)| name.clone()).collect();
+        self.host_params.insert(signature.name().to_string(), params);
+        self.env.define_function(signature.name().to_string(), signature.function_type());
+    }
+
+    /// The type of a variable declared at the top level, with what
+    /// unification found applied
+    pub fn variable_type(&self, name: &str) -> Option<InferredType> {
+        self.env.scopes.first()?.get(name).map(|ty| self.apply_substitutions(ty))
+    }
+
+    /// Type check a program, giving back what was found out about it for
+    /// the steps after checking, or every error found
+    pub fn check<'p>(&mut self, program: &'p Program) -> std::result::Result<CheckedProgram<'p>, Vec<TypeError>> {
+        self.types = Some(HashMap::new());
+        let errors = self.check_program_all(program);
+        let types = self.types.take().unwrap_or_default();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let functions = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TopLevelItem::Function(def) => Some(def),
+                _ => None,
+            })
+            .map(|def| {
+                let (params, ret) = match self.env.get_function(&def.name).map(|ty| self.apply_substitutions(ty)) {
+                    Some(InferredType::Function { params, ret }) => (params, *ret),
+                    _ => (Vec::new(), InferredType::Unit),
+                };
+                CheckedFunction { def, params, ret }
+            })
+            .collect();
+        let types = types
+            .into_iter()
+            .map(|(node, ty)| (node, self.apply_substitutions(&ty)))
+            .collect();
+        Ok(CheckedProgram::new(program, ModuleScope::of(program), functions, types))
     }
 
     /// Type check a program
     pub fn check_program(&mut self, program: &Program) -> Result<()> {
+        match self.check_program_all(program).into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Type check a program, going on past errors to report all of them;
+    /// each function, consent block, side quest and `expect` block is checked
+    /// on its own
+    pub fn check_program_all(&mut self, program: &Program) -> Vec<TypeError> {
+        let mut errors = Vec::new();
+
         // First pass: collect function signatures
         for item in &program.items {
-            if let TopLevelItem::Function(f) = item {
-                self.register_function(f)?;
+            match item {
+                TopLevelItem::Function(f) => {
+                    if let Err(error) = self.register_function(f) {
+                        errors.push(error);
+                    }
+                }
+                TopLevelItem::ModuleImport(import) => self.scope.import(import),
+                _ => {}
+            }
+        }
+
+        // Top-level `remember`s are in scope in every body, so their types
+        // are known before any body is checked
+        for item in &program.items {
+            if let TopLevelItem::Global(decl) = item {
+                match self.infer_expr(&decl.value) {
+                    Ok(ty) => self.env.define(decl.name.to_string(), ty),
+                    Err(error) => errors.push(error),
+                }
             }
         }
 
         // Second pass: type check function bodies
         for item in &program.items {
-            match item {
-                TopLevelItem::Function(f) => self.check_function(f)?,
-                TopLevelItem::ConsentBlock(c) => {
+            let depth = self.env.scopes.len();
+            let checked = match item {
+                TopLevelItem::Function(f) => self.check_function(f),
+                TopLevelItem::ConsentBlock(ConsentBlock { body, .. })
+                | TopLevelItem::SideQuestDef(SideQuestDef { body, .. })
+                | TopLevelItem::Expect(ExpectBlock { body, .. }) => {
                     self.env.push_scope();
-                    for stmt in &c.body {
-                        self.check_statement(stmt, &InferredType::Unit)?;
-                    }
-                    self.env.pop_scope();
+                    body.iter()
+                        .try_for_each(|stmt| self.check_statement(stmt, &InferredType::Unit))
                 }
-                _ => {}
+                _ => Ok(()),
+            };
+            // An error leaves the scopes it was in open
+            self.env.scopes.truncate(depth);
+            if let Err(error) = checked {
+                errors.push(error);
             }
         }
 
-        Ok(())
+        errors
     }
 
     fn register_function(&mut self, func: &FunctionDef) -> Result<()> {
@@ -430,6 +1680,8 @@ fn register_function(&mut self, func: &FunctionDef) -> Result<()> {
             ret: Box::new(ret),
         };
 
+        // A program's own function comes before a host function of its name
+        self.host_params.remove(&func.name);
         self.env.define_function(func.name.clone(), func_type);
         Ok(())
     }
@@ -444,7 +1696,7 @@ fn check_function(&mut self, func: &FunctionDef) -> Result<()> {
                 .as_ref()
                 .map(|t| self.ast_type_to_inferred(t))
                 .unwrap_or_else(|| self.fresh_type_var());
-            self.env.define(param.name.clone(), param_type);
+            self.env.define(param.name.to_string(), param_type);
         }
 
         // Check body statements
@@ -466,7 +1718,7 @@ fn check_statement(&mut self, stmt: &Statement, expected_return: &InferredType)
         match stmt {
             Statement::VarDecl(decl) => {
                 let expr_type = self.infer_expr(&decl.value)?;
-                self.env.define(decl.name.clone(), expr_type);
+                self.env.define(decl.name.to_string(), expr_type);
                 Ok(())
             }
 
@@ -474,7 +1726,7 @@ fn check_statement(&mut self, stmt: &Statement, expected_return: &InferredType)
                 let var_type = self
                     .env
                     .get(&assign.target)
-                    .ok_or_else(|| TypeError::UndefinedVariable(assign.target.clone()))?
+                    .ok_or_else(|| self.undefined_variable(&assign.target))?
                     .clone();
                 let expr_type = self.infer_expr(&assign.value)?;
                 self.unify(&var_type, &expr_type)
@@ -568,7 +1820,7 @@ fn check_statement(&mut self, stmt: &Statement, expected_return: &InferredType)
     fn bind_pattern_types(&mut self, pattern: &Pattern, expected_type: &InferredType) -> Result<()> {
         match pattern {
             Pattern::Identifier(name) => {
-                self.env.define(name.clone(), expected_type.clone());
+                self.env.define(name.to_string(), expected_type.clone());
                 Ok(())
             }
             Pattern::Wildcard | Pattern::Literal(_) => Ok(()),
@@ -589,7 +1841,7 @@ fn bind_pattern_types(&mut self, pattern: &Pattern, expected_type: &InferredType
                             let err_type = if let InferredType::Result { err, .. } = expected_type {
                                 (**err).clone()
                             } else {
-                                InferredType::String
+                                self.fresh_type_var()
                             };
                             self.bind_pattern_types(inner_pat, &err_type)?;
                         }
@@ -606,11 +1858,22 @@ fn bind_pattern_types(&mut self, pattern: &Pattern, expected_type: &InferredType
         }
     }
 
+    /// The type of an expression, kept by the node while a
+    /// [`CheckedProgram`] is being put together
     fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
+        let ty = self.infer_node(expr)?;
+        if let Some(types) = &mut self.types {
+            types.insert(checked::node(expr), ty.clone());
+        }
+        Ok(ty)
+    }
+
+    fn infer_node(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
         match &expr.node {
             Expr::Literal(lit) => Ok(match lit {
                 Literal::Integer(_) => InferredType::Int,
                 Literal::Float(_) => InferredType::Float,
+                Literal::Decimal(_) => InferredType::Decimal,
                 Literal::String(_) => InferredType::String,
                 Literal::Bool(_) => InferredType::Bool,
                 Literal::Unit => InferredType::Unit,
@@ -620,12 +1883,19 @@ fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
                 .env
                 .get(name)
                 .cloned()
-                .ok_or_else(|| TypeError::UndefinedVariable(name.clone())),
+                .ok_or_else(|| self.undefined_variable(name)),
 
             Expr::Binary(op, left, right) => {
                 let left_type = self.infer_expr(left)?;
                 let right_type = self.infer_expr(right)?;
 
+                if let Some(result) = self.time_arithmetic(op, &left_type, &right_type) {
+                    return Ok(result);
+                }
+                if let Some(result) = self.decimal_arithmetic(op, &left_type, &right_type) {
+                    return Ok(result);
+                }
+
                 match op {
                     BinaryOp::Add => {
                         // String concatenation or numeric addition
@@ -633,23 +1903,24 @@ fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
                         if matches!(left_resolved, InferredType::String) {
                             self.unify(&right_type, &InferredType::String)?;
                             Ok(InferredType::String)
+                        } else if matches!(left_resolved, InferredType::Bytes) {
+                            self.unify(&right_type, &InferredType::Bytes)?;
+                            Ok(InferredType::Bytes)
                         } else {
                             self.unify(&left_type, &right_type)?;
                             let resolved = self.apply_substitutions(&left_type);
-                            if matches!(resolved, InferredType::Float) {
-                                Ok(InferredType::Float)
-                            } else {
-                                Ok(InferredType::Int)
+                            match resolved {
+                                InferredType::Float | InferredType::Decimal => Ok(resolved),
+                                _ => Ok(InferredType::Int),
                             }
                         }
                     }
                     BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
                         self.unify(&left_type, &right_type)?;
                         let resolved = self.apply_substitutions(&left_type);
-                        if matches!(resolved, InferredType::Float) {
-                            Ok(InferredType::Float)
-                        } else {
-                            Ok(InferredType::Int)
+                        match resolved {
+                            InferredType::Float | InferredType::Decimal => Ok(resolved),
+                            _ => Ok(InferredType::Int),
                         }
                     }
                     BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::Gt | BinaryOp::LtEq | BinaryOp::GtEq => {
@@ -678,8 +1949,17 @@ fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
             Expr::Call(name, args) => {
                 // Handle built-in functions
                 match name.as_str() {
-                    "print" => return Ok(InferredType::Unit),
+                    "print" | "prettyPrint" => return Ok(InferredType::Unit),
                     "toString" => return Ok(InferredType::String),
+                    "format" => {
+                        for (i, arg) in args.iter().enumerate() {
+                            let arg_type = self.infer_expr(arg)?;
+                            if i == 0 {
+                                self.unify(&InferredType::String, &arg_type)?;
+                            }
+                        }
+                        return Ok(InferredType::String);
+                    }
                     "len" => return Ok(InferredType::Int),
                     "isOkay" | "isOops" => return Ok(InferredType::Bool),
                     "unwrapOr" => {
@@ -689,39 +1969,51 @@ fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
                         }
                         return Ok(self.fresh_type_var());
                     }
-                    "getError" => return Ok(InferredType::String),
+                    "getError" => {
+                        // The error the result carries, which is not always
+                        // a message
+                        if let [arg] = args.as_slice() {
+                            if let InferredType::Result { err, .. } = self.infer_expr(arg)? {
+                                return Ok(*err);
+                            }
+                        }
+                        return Ok(self.fresh_type_var());
+                    }
                     "toInt" => return Ok(InferredType::Int),
                     "toFloat" => return Ok(InferredType::Float),
                     _ => {}
                 }
 
+                let written = name;
+                let name = self.resolve_call_name(written);
+                let optional = self.optional_params.get(&name).copied().unwrap_or(0);
+
                 // Check if it's a variable holding a function (closure)
-                if let Some(var_type) = self.env.get(name).cloned() {
-                    if let InferredType::Function { params, ret } = var_type {
-                        if params.len() != args.len() {
-                            return Err(TypeError::ArityMismatch {
-                                expected: params.len(),
-                                actual: args.len(),
-                            });
-                        }
-                        for (param_type, arg) in params.iter().zip(args.iter()) {
-                            let arg_type = self.infer_expr(arg)?;
-                            self.unify(param_type, &arg_type)?;
-                        }
-                        return Ok((*ret).clone());
+                if let Some(InferredType::Function { params, ret }) = self.env.get_variable(&name).cloned() {
+                    if !(params.len() - optional..=params.len()).contains(&args.len()) {
+                        return Err(TypeError::ArityMismatch {
+                            expected: params.len(),
+                            actual: args.len(),
+                        });
+                    }
+                    for (param_type, arg) in params.iter().zip(args.iter()) {
+                        let arg_type = self.infer_expr(arg)?;
+                        self.unify(param_type, &arg_type)?;
                     }
+                    return Ok((*ret).clone());
                 }
 
                 // Check defined functions
                 let func_type = self
                     .env
-                    .get_function(name)
+                    .get_function(&name)
                     .cloned()
-                    .ok_or_else(|| TypeError::UndefinedFunction(name.clone()))?;
+                    .ok_or_else(|| self.undefined_function(written))?;
 
                 if let InferredType::Function { params, ret } = func_type {
+                    let accepted = params.len() - optional..=params.len();
                     // Empty params means variadic (like print, speak)
-                    if !params.is_empty() && params.len() != args.len() {
+                    if !params.is_empty() && !accepted.contains(&args.len()) {
                         return Err(TypeError::ArityMismatch {
                             expected: params.len(),
                             actual: args.len(),
@@ -729,9 +2021,21 @@ fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
                     }
 
                     // Type check arguments against parameters (skip for variadic)
-                    for (param_type, arg) in params.iter().zip(args.iter()) {
+                    let host_params = self.host_params.get(&name).cloned();
+                    for (i, (param_type, arg)) in params.iter().zip(args.iter()).enumerate() {
                         let arg_type = self.infer_expr(arg)?;
-                        self.unify(&param_type, &arg_type)?;
+                        let unified = self.unify(param_type, &arg_type);
+                        // Say which parameter of a host function, and where
+                        if let (Err(TypeError::TypeMismatch { .. }), Some(names)) = (&unified, &host_params) {
+                            return Err(TypeError::HostArgument {
+                                function: name.clone(),
+                                parameter: names[i].clone(),
+                                expected: self.apply_substitutions(param_type).to_string(),
+                                actual: self.apply_substitutions(&arg_type).to_string(),
+                                span: arg.span.clone(),
+                            });
+                        }
+                        unified?;
                     }
 
                     // For variadic functions, still infer arg types for side effects
@@ -760,7 +2064,7 @@ fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
 
                     for (param_type, arg) in params.iter().zip(args.iter()) {
                         let arg_type = self.infer_expr(arg)?;
-                        self.unify(&param_type, &arg_type)?;
+                        self.unify(param_type, &arg_type)?;
                     }
 
                     Ok((*ret).clone())
@@ -790,15 +2094,17 @@ fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
                 match target_type {
                     InferredType::Array(inner) => Ok((*inner).clone()),
                     InferredType::String => Ok(InferredType::String),
+                    InferredType::Bytes => Ok(InferredType::Int),
                     _ => Err(TypeError::CannotIndex(target_type.to_string())),
                 }
             }
 
             Expr::Okay(inner) => {
                 let inner_type = self.infer_expr(inner)?;
+                // The error is whatever the function's other results say
                 Ok(InferredType::Result {
                     ok: Box::new(inner_type),
-                    err: Box::new(InferredType::String),
+                    err: Box::new(self.fresh_type_var()),
                 })
             }
 
@@ -830,7 +2136,7 @@ fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
                         let ty = p.ty.as_ref()
                             .map(|t| self.ast_type_to_inferred(t))
                             .unwrap_or_else(|| self.fresh_type_var());
-                        self.env.define(p.name.clone(), ty.clone());
+                        self.env.define(p.name.to_string(), ty.clone());
                         ty
                     })
                     .collect();
@@ -857,9 +2163,17 @@ fn infer_expr(&mut self, expr: &Spanned<Expr>) -> Result<InferredType> {
                 })
             }
 
-            Expr::UnitMeasurement(inner, _unit) => {
-                // For now, unit measurements are transparent
-                self.infer_expr(inner)
+            Expr::UnitMeasurement(inner, unit) => {
+                let inner_type = self.infer_expr(inner)?;
+                // Time units make a Duration; other units are transparent
+                if duration::unit_millis(unit).is_none() {
+                    return Ok(inner_type);
+                }
+                let resolved = self.apply_substitutions(&inner_type);
+                if !matches!(resolved, InferredType::Float) {
+                    self.unify(&inner_type, &InferredType::Int)?;
+                }
+                Ok(InferredType::Duration)
             }
 
             Expr::GratitudeLiteral(_) => Ok(InferredType::String),
@@ -871,3 +2185,673 @@ pub fn errors(&self) -> Vec<String> {
         Vec::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn check(source: &str) -> Result<()> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        TypeChecker::new().check_program(&program)
+    }
+
+    #[test]
+    fn test_string_module_signatures() {
+        assert!(check(
+            r#"
+            use std.string renamed s;
+            to shout(text: String) -> String {
+                remember parts = std.string.split(text, " ");
+                remember head = s.substring(s.join(parts, ","), 1);
+                give back s.toUpper(head);
+            }
+            to main() {
+                remember found = s.contains(shout("a b"), "A");
+                remember code = s.codePointAt(s.charAt("ab", 1), 0) + len(s.graphemes("ab"));
+            }
+        "#
+        )
+        .is_ok());
+
+        assert!(matches!(
+            check(r#"to f() -> Int { give back std.string.trim("a"); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check(r#"to main() { std.string.repeat("a"); }"#),
+            Err(TypeError::ArityMismatch { .. })
+        ));
+        assert!(matches!(
+            check(r#"to main() { string.trim("a"); }"#),
+            Err(TypeError::UndefinedFunction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_array_module_signatures() {
+        check(
+            r#"
+            to main() {
+                remember nums = std.array.range(5);
+                remember doubled = std.array.map(nums, |n: Int| -> n * 2);
+                remember total = std.array.reduce(doubled, |acc: Int, n: Int| -> acc + n, 0);
+                remember sorted = std.array.sort(doubled);
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.array.map([1], 5); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check(r#"to main() { std.array.filter([1]); }"#),
+            Err(TypeError::ArityMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_map_module_signatures() {
+        check(
+            r#"
+            use std.map;
+            to names(m) -> [String] {
+                give back map.keys(m);
+            }
+            to main() {
+                remember m = map.set(map.new(), "a", 1);
+                remember count = len(names(m)) + map.size(m);
+                map.forEach(m, |k: String, v| -> print(k, v));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.map.has(std.map.new(), 1); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_json_module_signatures() {
+        check(
+            r#"
+            use std.json;
+            to main() {
+                remember data = json.parse("{\"a\": [1, 2]}");
+                remember text = json.stringify(data, 2) + json.stringify(data);
+                print(text);
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.json.parse(1); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_csv_module_signatures() {
+        check(
+            r#"
+            use std.csv;
+            to main() {
+                remember rows = csv.parse("a,b\n1,2\n");
+                print(csv.stringify(rows, ["b"]) + csv.stringify(csv.parse("1,2", false)));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.csv.parse("a", "yes"); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_config_module_signatures() {
+        check(
+            r#"
+            use std.config;
+            to main() {
+                remember settings = config.parseToml("port = 1");
+                print(settings["port"], config.parseYaml("- a"));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.config.parseYaml(1); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_net_module_signatures() {
+        check(
+            r#"
+            use std.net;
+            to handle(request) -> String {
+                give back "hello";
+            }
+            to main() {
+                remember served = net.serve(8080, handle) + net.serve(8081, handle, std.map.new());
+                print(served);
+            }
+        "#,
+        )
+        .unwrap();
+
+        check(
+            r#"
+            to main() {
+                remember socket = std.net.tcpConnect("example.com", 7);
+                std.net.tcpSend(socket, "ping");
+                print(std.net.tcpReceive(socket) + std.net.tcpReceive(socket, 16));
+                std.net.close(socket);
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.net.serve("8080", 1); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_env_module_signatures() {
+        check(
+            r#"
+            use std.env;
+            to main() {
+                remember home = unwrapOr(env.get("HOME"), "/");
+                env.set("GREETING", home);
+                print(len(env.args()), env.all());
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.env.set("A", 1); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_process_module_signatures() {
+        check(
+            r#"
+            use std.process;
+            to main() {
+                remember result = process.run("ls", ["-l"]);
+                remember child = process.spawn("sleep", ["1"], std.map.new());
+                when process.isRunning(child) {
+                    process.kill(child);
+                }
+                print(result, process.wait(child));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.process.run("ls", "-l"); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_crypto_module_signatures() {
+        check(
+            r#"
+            use std.crypto;
+            to main() {
+                remember signature = crypto.hmac("secret", "payload");
+                remember expected = crypto.hmac("secret", "payload", "sha512");
+                print(crypto.constantTimeEquals(signature, expected), crypto.randomBytes(8));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.crypto.randomBytes("8"); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_encoding_module_signatures() {
+        check(
+            r#"
+            use std.encoding;
+            to main() {
+                remember token = encoding.base64Encode("woke", true) + encoding.hexEncode("hi");
+                print(unwrapOr(encoding.urlDecode(encoding.urlEncode(token)), ""));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.encoding.hexDecode(12); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bytes_type() {
+        check(
+            r#"
+            use std.bytes;
+            to main() {
+                remember data = bytes.fromString("wo") + bytes.fromArray([107, 101]);
+                remember first = data[0] + 1;
+                remember tail = bytes.slice(data, 2);
+                print(first, bytes.length(tail), std.crypto.sha256(data), std.encoding.hexEncode(tail));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { remember x = std.bytes.fromString("a") + "b"; }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check(r#"to main() { std.bytes.length("text"); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_path_module_signatures() {
+        check(
+            r#"
+            use std.path;
+            to main() {
+                remember file = path.join("data", "2024", "notes.txt");
+                print(path.dirname(file) + "/" + path.basename(file), path.extension(file));
+                print(path.normalize(path.absolute(file)));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { remember n = std.path.basename("a") + 1; }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_log_module_signatures() {
+        check(
+            r#"
+            use std.log;
+            to main() {
+                log.setLevel("debug");
+                log.info("ready");
+                log.warn("slow", std.map.new());
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.log.info(42); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_expect_blocks_are_checked() {
+        check(
+            r#"
+            use std.test;
+            expect "sums" {
+                test.assertEqual(1 + 2, 3, "sum");
+                test.assertTrue(true);
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"expect "bad" { std.test.assertTrue(1); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_globals_are_checked() {
+        check(
+            r#"
+            remember count = 0;
+            to bump() { count = count + 1; }
+            to main() -> Int { bump(); give back count; }
+        "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            check(r#"remember count = 0; to main() { count = "many"; }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(check("remember bad = 1 + true;").is_err());
+    }
+
+    #[test]
+    fn test_side_quests_are_checked() {
+        check(r#"side quest tidyUp { remember n = len("abc") + 1; }"#).unwrap();
+        assert!(matches!(
+            check(r#"side quest tidyUp { remember n = 1 + "x"; }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(check("side quest tidyUp { give back 1; }").is_err());
+    }
+
+    #[test]
+    fn test_datetime_signatures() {
+        check(
+            r#"
+            use std.time;
+            to main() {
+                remember start = time.dateTime(2024, 1, 31, 9, 0, 0, "UTC");
+                remember due = time.addMonths(time.addDays(start, 1), 1);
+                when due > start {
+                    print(time.year(due), time.format(due, "%Y-%m-%d"), time.format(time.now(), "%H:%M"));
+                }
+                print(unwrapOr(time.parseRfc3339("2024-05-01T09:30:00Z"), time.current()));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.time.year(2024); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_math_signatures() {
+        check(
+            r#"
+            use std.math;
+            to main() {
+                math.randomSeed(42);
+                remember roll = math.randomInt(1, 6) + math.floor(math.random() * 10.0);
+                remember pick = unwrapOr(math.randomChoice(["tea", "coffee"]), "water");
+                print(roll, pick + "!", len(math.shuffle([1, 2, 3])), math.sqrt(roll) + math.pi());
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.math.randomInt(1, "six"); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check(r#"to main() { std.math.random(1.0, 2.0, 3.0); }"#),
+            Err(TypeError::ArityMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_io_streaming_signatures() {
+        check(
+            r#"
+            use std.io;
+            to shout(line: String) -> Int {
+                io.streamWrite("loud.txt", line + "!\n");
+                give back len(line);
+            }
+            to main() {
+                remember cursor = io.readLines("notes.txt");
+                remember first = unwrapOr(io.nextLine(cursor), "");
+                io.closeLines(cursor);
+                print(first, io.forEachLine("notes.txt", shout) + 1);
+                io.streamClose("loud.txt");
+                print(len(io.glob("src/**/*.woke")), io.walk("src")[0]["name"]);
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.io.nextLine("notes.txt"); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_term_signatures() {
+        check(
+            r#"
+            use std.term;
+            to main() {
+                when not term.isTty() {
+                    term.setColor(false);
+                }
+                print(term.style(term.color("done", "green"), "bold"), term.width() - 2);
+                print(term.clearLine() + term.progressBar(3, 10), term.cursorUp());
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.term.color("ok"); }"#),
+            Err(TypeError::ArityMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ui_signatures() {
+        check(
+            r#"
+            use std.ui;
+            to main() {
+                remember copied = unwrapOr(ui.clipboardRead(), "");
+                ui.clipboardWrite(copied + "!");
+                ui.notify("Done", "Copied " + copied);
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { std.ui.notify("only a title"); }"#),
+            Err(TypeError::ArityMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_duration_types() {
+        check(
+            r#"
+            use std.time;
+            to taken(start: DateTime, end: DateTime) -> Duration {
+                give back end - start;
+            }
+            to main() {
+                remember start = time.current("UTC");
+                remember wait = 2 measured in hours + time.duration(30, "minutes");
+                remember taken = taken(start, start + wait * 2);
+                when taken > 1 measured in days {
+                    print(time.toMillis(taken));
+                }
+                time.sleep(wait);
+                time.sleep(250);
+                print(time.toSeconds(-wait) + 1.5, 3 measured in metres + 4);
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to half(n: Int) -> Int { give back n / 2; } to main() { half(5 measured in seconds); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check(r#"to main() { remember soon = "soon" measured in seconds; }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decimal_types() {
+        check(
+            r#"
+            use std.decimal;
+            to total(price: Decimal, count: Int) -> Decimal {
+                give back decimal.round(price * count - 0.50d, 2);
+            }
+            to main() {
+                remember cost = total(3, 1);
+                cost = total(1.25d, 3) + cost / 2;
+                when cost > 10 {
+                    print(decimal.toFloat(-cost) + 1.5);
+                }
+                print(decimal.places(decimal.fromFloat(0.1)));
+            }
+        "#,
+        )
+        .unwrap();
+
+        let err = check("to main() { remember total = 1.50d + 0.25; }").unwrap_err();
+        assert_eq!(err.to_string(), "Type mismatch: expected Decimal, got Float");
+        assert!(matches!(
+            check("to half(n: Float) -> Float { give back n / 2.0; } to main() { half(1.5d); }"),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_superpower_builtin_types() {
+        check(
+            r#"
+            to count(names: [String]) -> Int {
+                give back len(names);
+            }
+            to main() {
+                remember held = count(listSuperpowers());
+                when hasSuperpower("network:*") {
+                    renounce("network");
+                }
+            }
+        "#,
+        )
+        .unwrap();
+        assert!(check(r#"to main() { remember n = hasSuperpower("crypto") + 1; }"#).is_err());
+        assert!(check("to main() { renounce(1); }").is_err());
+    }
+
+    #[test]
+    fn test_ask_types() {
+        check(
+            r#"
+            to main() {
+                remember name = ask("Name? ") + "!";
+                remember age = askNumber("Age? ") * 2.0;
+                when askYesNo("Go? ") {
+                    print(name, age);
+                }
+            }
+        "#,
+        )
+        .unwrap();
+        assert!(check("to main() { remember n = askYesNo(\"Go? \") + 1; }").is_err());
+    }
+
+    #[test]
+    fn test_gratitude_entries_type() {
+        check(
+            r#"
+            thanks to {
+                "Ada Lovelace" → "the first program";
+            }
+            to main() {
+                remember entries = gratitudeEntries();
+                remember reason = std.map.get(entries[0], "reason");
+                remember count = len(entries) + 1;
+            }
+        "#,
+        )
+        .unwrap();
+        assert!(check("to main() { remember n = gratitudeEntries() + 1; }").is_err());
+    }
+
+    #[test]
+    fn test_format_types() {
+        check(
+            r#"
+            to label(name: String, count: Int) -> String {
+                give back format("{0}: {1:>4}", name, count);
+            }
+            to main() {
+                remember line = label("items", 3) + std.string.format("{}", 1.5);
+                print(format(line));
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            check(r#"to main() { remember line = format(42, "x"); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check(r#"to twice(n: Int) -> Int { give back n * 2; } to main() { twice(format("{}", 1)); }"#),
+            Err(TypeError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let error = check("to main() { remember count = 1; remember next = cont + 1; }").unwrap_err();
+        assert!(matches!(&error, TypeError::UndefinedVariable { suggestion: Some(s), .. } if s == "count"));
+        assert_eq!(error.help().as_deref(), Some("did you mean `count`?"));
+
+        let error = check(r#"to main() { remember line = toStrng(1); }"#).unwrap_err();
+        assert_eq!(error.help().as_deref(), Some("did you mean `toString`?"));
+        let error = check(r#"use std.string; to main() { remember n = string.lenght("abc"); }"#).unwrap_err();
+        assert_eq!(error.help().as_deref(), Some("did you mean `string.length`?"));
+
+        let error = check("to main() { remember n = mystery(); }").unwrap_err();
+        assert!(matches!(error, TypeError::UndefinedFunction { suggestion: None, .. }));
+        assert_eq!(error.help(), None);
+    }
+
+    #[test]
+    fn test_check_program_all() {
+        let source = r#"
+            to first() -> Int { give back "one"; }
+            to second() { remember x = missing; }
+            to third(n: Int) -> Int { give back n; }
+            expect "runs" { remember y = third("3"); }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let errors = TypeChecker::new().check_program_all(&program);
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(matches!(errors[0], TypeError::TypeMismatch { .. }));
+        assert!(matches!(errors[1], TypeError::UndefinedVariable { ref name, .. } if name == "missing"));
+        assert!(matches!(errors[2], TypeError::TypeMismatch { .. }));
+        // The first is what check_program reports
+        assert!(matches!(check(source), Err(TypeError::TypeMismatch { .. })));
+    }
+}
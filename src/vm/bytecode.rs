@@ -2,11 +2,16 @@
 //!
 //! A stack-based bytecode format for efficient execution.
 
+use super::snapshot;
+use crate::ast::Span;
 use crate::interpreter::Value;
+use crate::security::{Limits, SuperpowerDeclaration};
+use crate::stdlib::io::Answer;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Bytecode instructions for the WokeLang VM
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum OpCode {
     // Stack operations
     /// Push a constant onto the stack
@@ -27,6 +32,9 @@ pub enum OpCode {
     LoadGlobal(String),
     /// Store into a global variable
     StoreGlobal(String),
+    /// Add the top of stack to a local variable, appending in place when
+    /// the local is a String: `text = text + more;`
+    AppendLocal(usize),
 
     // Arithmetic operations (pop operands, push result)
     Add,
@@ -65,8 +73,10 @@ pub enum OpCode {
     Call(usize),
     /// Return from function
     Return,
-    /// Create a closure
-    MakeClosure(usize),
+    /// Create a closure over a function, capturing the top N values
+    MakeClosure(usize, usize),
+    /// Call a standard library function by full name with N arguments
+    CallNative(String, usize),
 
     // Array/Record operations
     /// Create an array from N elements on stack
@@ -87,10 +97,24 @@ pub enum OpCode {
     TryUnwrap,
     /// Check if value is Okay
     IsOkay,
+    /// Check if value is Oops
+    IsOops,
+    /// Extract the error an Oops value carries, or Unit for anything else
+    ErrorValue,
+
+    /// Make the text of the top of stack secret, as `remember secret` does
+    Secret,
 
     // Built-in functions
-    /// Print the top of stack
-    Print,
+    /// Print the top N values on one line, separated by spaces
+    Print(usize),
+    /// Print the top of stack over indented lines, as `prettyPrint`
+    PrettyPrint,
+    /// Ask the user the prompt on top of the stack, replacing it with the
+    /// answer, as `ask`, `askNumber` and `askYesNo`
+    Ask(Answer),
+    /// End the program, with the Int on top of the stack as its result
+    Exit,
     /// Convert to string
     ToString,
 
@@ -101,18 +125,26 @@ pub enum OpCode {
 }
 
 /// A compiled function
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompiledFunction {
     /// Function name (for debugging)
     pub name: String,
     /// Number of parameters
     pub arity: usize,
+    /// Number of values a lambda captures, held in the slots after its
+    /// parameters
+    #[serde(default)]
+    pub captures: usize,
     /// Number of local variables (including parameters)
     pub locals: usize,
     /// Bytecode instructions
     pub code: Vec<OpCode>,
     /// Constant pool for this function
+    #[serde(with = "snapshot::values")]
     pub constants: Vec<Value>,
+    /// Module an imported function is from, as `FunctionDef::module`
+    #[serde(default)]
+    pub module: Option<String>,
 }
 
 impl CompiledFunction {
@@ -120,17 +152,24 @@ pub fn new(name: String, arity: usize) -> Self {
         Self {
             name,
             arity,
+            captures: 0,
             locals: arity,
             code: Vec::new(),
             constants: Vec::new(),
+            module: None,
         }
     }
 
     /// Add a constant and return its index
     pub fn add_constant(&mut self, value: Value) -> usize {
-        // Check if constant already exists
+        // Check if constant already exists; equal Decimals may still show
+        // different places, as 1.5 and 1.50 do
         for (i, c) in self.constants.iter().enumerate() {
-            if c == &value {
+            let same = match (c, &value) {
+                (Value::Decimal(a), Value::Decimal(b)) => a == b && a.scale() == b.scale(),
+                _ => c == &value,
+            };
+            if same {
                 return i;
             }
         }
@@ -162,15 +201,38 @@ pub fn current_offset(&self) -> usize {
     }
 }
 
-/// A compiled program
-#[derive(Debug, Clone)]
+/// A compiled program; it serializes with its values written as a
+/// snapshot writes them, for the build cache to keep
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompiledProgram {
     /// All compiled functions
     pub functions: Vec<CompiledFunction>,
     /// Index of the main/entry function
     pub entry: Option<usize>,
     /// Global variables (name -> value)
+    #[serde(with = "snapshot::value_map")]
     pub globals: HashMap<String, Value>,
+    /// Limits the program declares with `#timeout` and `#maxmemory`
+    #[serde(default)]
+    pub limits: Limits,
+    /// What the program's imports declare they need, with `use ... must have`
+    #[serde(default)]
+    pub superpowers: Vec<SuperpowerDeclaration>,
+    /// Side quests, run after `main` in this order
+    #[serde(default)]
+    pub side_quests: Vec<CompiledSideQuest>,
+    /// Whether `#side quests off` skips them
+    #[serde(default)]
+    pub skip_side_quests: bool,
+}
+
+/// A side quest, compiled as a function of no arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompiledSideQuest {
+    pub name: String,
+    pub span: Span,
+    /// Index of its function
+    pub function: usize,
 }
 
 impl CompiledProgram {
@@ -179,6 +241,10 @@ pub fn new() -> Self {
             functions: Vec::new(),
             entry: None,
             globals: HashMap::new(),
+            limits: Limits::default(),
+            superpowers: Vec::new(),
+            side_quests: Vec::new(),
+            skip_side_quests: false,
         }
     }
 
@@ -192,10 +258,26 @@ pub fn add_function(&mut self, func: CompiledFunction) -> usize {
         idx
     }
 
+    /// Index of the function called `name`
+    pub fn function_index(&self, name: &str) -> Option<usize> {
+        self.functions.iter().position(|f| f.name == name)
+    }
+
     /// Get a function by index
     pub fn get_function(&self, idx: usize) -> Option<&CompiledFunction> {
         self.functions.get(idx)
     }
+
+    /// Stable fingerprint of the compiled code (FNV-1a over the functions)
+    ///
+    /// Used to check that a VM snapshot is resumed against the same program
+    /// it was taken from.
+    pub fn fingerprint(&self) -> u64 {
+        let repr = format!("{:?}", self.functions);
+        repr.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
 }
 
 impl Default for CompiledProgram {
@@ -213,7 +295,7 @@ fn test_compiled_function() {
         let mut func = CompiledFunction::new("test".to_string(), 2);
 
         let c1 = func.add_constant(Value::Int(42));
-        let c2 = func.add_constant(Value::String("hello".to_string()));
+        let c2 = func.add_constant(Value::String("hello".to_string().into()));
         let c3 = func.add_constant(Value::Int(42)); // Should reuse c1
 
         assert_eq!(c1, 0);
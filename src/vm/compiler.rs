@@ -3,11 +3,16 @@
 //! Compiles AST to bytecode for the VM.
 
 use crate::ast::{
-    BinaryOp, Expr, FunctionDef, Literal, Loop, Pattern, Program, Spanned,
-    Statement, TopLevelItem, UnaryOp,
+    BinaryOp, Expr, FunctionDef, LambdaBody, LambdaExpr, Literal, Loop, Pattern, PragmaDirective,
+    Program, SideQuestDef, Spanned, Statement, TopLevelItem, UnaryOp, VarDecl,
 };
 use crate::interpreter::Value;
-use super::bytecode::{CompiledFunction, CompiledProgram, OpCode};
+use crate::security::policy::parse_capability;
+use crate::security::SuperpowerDeclaration;
+use crate::stdlib::io::Answer;
+use crate::stdlib::{duration, StdlibRegistry};
+use crate::typechecker::{CheckedProgram, ModuleScope};
+use super::bytecode::{CompiledFunction, CompiledProgram, CompiledSideQuest, OpCode};
 use std::collections::HashMap;
 
 /// Bytecode compiler
@@ -24,6 +29,18 @@ pub struct BytecodeCompiler {
     break_targets: Vec<Vec<usize>>,
     /// Loop continue targets
     continue_targets: Vec<usize>,
+    /// Standard library functions callable with `CallNative`
+    stdlib: StdlibRegistry,
+    /// The modules the program imports and the functions it defines
+    scope: ModuleScope,
+    /// The program's gratitude entries, as `gratitudeEntries()` gives them
+    gratitude: Value,
+    /// Lambdas compiled so far, added after the program's other functions
+    lambdas: Vec<CompiledFunction>,
+    /// Index of the first lambda
+    lambda_base: usize,
+    /// The program's top-level `remember`s, which `main` runs first
+    globals: Vec<VarDecl>,
 }
 
 impl BytecodeCompiler {
@@ -35,23 +52,70 @@ pub fn new() -> Self {
             function_indices: HashMap::new(),
             break_targets: Vec::new(),
             continue_targets: Vec::new(),
+            stdlib: StdlibRegistry::new(),
+            scope: ModuleScope::new(),
+            gratitude: Value::gratitude([]),
+            lambdas: Vec::new(),
+            lambda_base: 0,
+            globals: Vec::new(),
         }
     }
 
     /// Compile a program to bytecode
     pub fn compile(&mut self, program: &Program) -> Result<CompiledProgram, CompileError> {
-        // First pass: register all function names
-        for item in &program.items {
-            if let TopLevelItem::Function(func) = item {
-                let idx = self.program.functions.len() + self.function_indices.len();
-                self.function_indices.insert(func.name.clone(), idx);
-            }
+        self.compile_in(program, ModuleScope::of(program))
+    }
+
+    /// Compile a program the type checker passed, with the scope it found
+    pub fn compile_checked(&mut self, checked: &CheckedProgram) -> Result<CompiledProgram, CompileError> {
+        self.compile_in(checked.program(), checked.scope().clone())
+    }
+
+    fn compile_in(&mut self, program: &Program, scope: ModuleScope) -> Result<CompiledProgram, CompileError> {
+        // First pass: number the functions
+        for name in scope.functions() {
+            let idx = self.program.functions.len() + self.function_indices.len();
+            self.function_indices.insert(name.clone(), idx);
         }
+        self.scope = scope;
+        self.gratitude =
+            Value::gratitude(program.gratitude().map(|entry| (entry.recipient.as_str(), entry.reason.as_str())));
+        self.globals = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TopLevelItem::Global(decl) => Some(decl.clone()),
+                _ => None,
+            })
+            .collect();
+
+        self.lambda_base = self.program.functions.len()
+            + program
+                .items
+                .iter()
+                .filter(|item| {
+                    matches!(
+                        item,
+                        TopLevelItem::Function(_)
+                            | TopLevelItem::WorkerDef(_)
+                            | TopLevelItem::ConsentBlock(_)
+                            | TopLevelItem::SideQuestDef(_)
+                    )
+                })
+                .count();
 
         // Second pass: compile all items
         for item in &program.items {
             self.compile_item(item)?;
         }
+        // Side quests come after, so the functions keep the numbers the
+        // first pass gave them
+        for item in &program.items {
+            if let TopLevelItem::SideQuestDef(quest) = item {
+                self.compile_side_quest(quest)?;
+            }
+        }
+        self.program.functions.append(&mut self.lambdas);
 
         Ok(self.program.clone())
     }
@@ -88,7 +152,7 @@ fn compile_item(&mut self, item: &TopLevelItem) -> Result<(), CompileError> {
             TopLevelItem::ConsentBlock(consent) => {
                 // Create an anonymous function for consent block
                 let name = format!("__consent_{}__", consent.permission);
-                let mut compiled = CompiledFunction::new(name, 0);
+                let compiled = CompiledFunction::new(name, 0);
                 self.locals.clear();
                 self.current_function = Some(compiled);
 
@@ -108,12 +172,30 @@ fn compile_item(&mut self, item: &TopLevelItem) -> Result<(), CompileError> {
             }
             // Skip metadata items for bytecode
             TopLevelItem::GratitudeDecl(_) => {}
+            // Compiled once every function has its number
             TopLevelItem::SideQuestDef(_) => {}
             TopLevelItem::SuperpowerDecl(_) => {}
-            TopLevelItem::ModuleImport(_) => {}
-            TopLevelItem::ModuleExport(_) => {}
-            TopLevelItem::Pragma(_) => {}
+            TopLevelItem::ModuleImport(import) => {
+                if let Some(needs) = &import.needs {
+                    // The parser has already rejected unknown capabilities
+                    self.program.superpowers.push(SuperpowerDeclaration {
+                        name: import.path.parts.join("."),
+                        capabilities: needs
+                            .iter()
+                            .filter_map(|n| parse_capability(&n.kind, n.value.as_deref()).ok())
+                            .collect(),
+                        description: import.reason.clone(),
+                    });
+                }
+            }
+            TopLevelItem::Pragma(pragma) => match pragma.directive {
+                PragmaDirective::SideQuests => self.program.skip_side_quests = !pragma.enabled,
+                directive => self.program.limits.apply(directive),
+            },
             TopLevelItem::TypeDef(_) => {}
+            TopLevelItem::Expect(_) => {}
+            // Given their values as `main` starts
+            TopLevelItem::Global(_) => {}
             TopLevelItem::ConstDef(const_def) => {
                 // Handle const definitions at compile time if possible
                 // For now, store them as globals
@@ -129,16 +211,29 @@ fn compile_item(&mut self, item: &TopLevelItem) -> Result<(), CompileError> {
     fn compile_function(&mut self, func: &FunctionDef) -> Result<(), CompileError> {
         // Start a new function
         let mut compiled = CompiledFunction::new(func.name.clone(), func.params.len());
+        compiled.module = func.module.clone();
 
         // Set up locals for parameters
         self.locals.clear();
         for (i, param) in func.params.iter().enumerate() {
-            self.locals.insert(param.name.clone(), i);
+            self.locals.insert(param.name.to_string(), i);
         }
         compiled.locals = func.params.len();
 
         self.current_function = Some(compiled);
 
+        // Globals get their values, in the order they are written, before
+        // anything in `main` runs
+        if func.name == "main" {
+            for decl in std::mem::take(&mut self.globals) {
+                self.compile_expr(&decl.value)?;
+                if decl.secret {
+                    self.emit(OpCode::Secret);
+                }
+                self.emit(OpCode::StoreGlobal(decl.name.to_string()));
+            }
+        }
+
         // Compile function body
         for stmt in &func.body {
             self.compile_statement(stmt)?;
@@ -161,11 +256,104 @@ fn compile_function(&mut self, func: &FunctionDef) -> Result<(), CompileError> {
         Ok(())
     }
 
+    /// Compile a side quest as a function of no arguments, which the VM
+    /// runs after `main`
+    fn compile_side_quest(&mut self, quest: &SideQuestDef) -> Result<(), CompileError> {
+        self.locals.clear();
+        self.current_function = Some(CompiledFunction::new(format!("side quest {}", quest.name), 0));
+
+        for stmt in &quest.body {
+            self.compile_statement(stmt)?;
+        }
+
+        if let Some(ref mut func) = self.current_function {
+            if func.code.is_empty() || !matches!(func.code.last(), Some(OpCode::Return)) {
+                let unit_idx = func.add_constant(Value::Unit);
+                func.emit(OpCode::Const(unit_idx));
+                func.emit(OpCode::Return);
+            }
+        }
+
+        if let Some(func) = self.current_function.take() {
+            let function = self.program.add_function(func);
+            self.program.side_quests.push(CompiledSideQuest {
+                name: quest.name.clone(),
+                span: quest.span.clone(),
+                function,
+            });
+        }
+        Ok(())
+    }
+
+    /// Compile a lambda as a function of its own, and make a closure of it
+    ///
+    /// Like the interpreter, a lambda captures the values of the variables
+    /// in scope when it is made; they follow its parameters in its locals.
+    fn compile_lambda(&mut self, lambda: &LambdaExpr) -> Result<(), CompileError> {
+        let mut captured: Vec<(String, usize)> = self
+            .locals
+            .iter()
+            .filter(|(name, _)| !lambda.params.iter().any(|p| p.name.as_str() == name.as_str()))
+            .map(|(name, &slot)| (name.clone(), slot))
+            .collect();
+        captured.sort_by_key(|&(_, slot)| slot);
+        for &(_, slot) in &captured {
+            self.emit(OpCode::LoadLocal(slot));
+        }
+
+        let mut compiled = CompiledFunction::new("<lambda>".to_string(), lambda.params.len());
+        compiled.captures = captured.len();
+        compiled.locals = lambda.params.len() + captured.len();
+        let mut locals = HashMap::new();
+        for (i, param) in lambda.params.iter().enumerate() {
+            locals.insert(param.name.to_string(), i);
+        }
+        for (i, (name, _)) in captured.iter().enumerate() {
+            locals.insert(name.clone(), lambda.params.len() + i);
+        }
+
+        // Reserve the lambda's index before any lambdas inside it take theirs
+        let index = self.lambda_base + self.lambdas.len();
+        self.lambdas.push(CompiledFunction::new(String::new(), 0));
+        let enclosing = self.current_function.replace(compiled);
+        let enclosing_locals = std::mem::replace(&mut self.locals, locals);
+        let break_targets = std::mem::take(&mut self.break_targets);
+        let continue_targets = std::mem::take(&mut self.continue_targets);
+
+        match &lambda.body {
+            LambdaBody::Expr(body) => {
+                self.compile_expr(body)?;
+                self.emit(OpCode::Return);
+            }
+            LambdaBody::Block(body) => {
+                for stmt in body {
+                    self.compile_statement(stmt)?;
+                }
+                let unit_idx = self.add_constant(Value::Unit);
+                self.emit(OpCode::Const(unit_idx));
+                self.emit(OpCode::Return);
+            }
+        }
+
+        if let Some(compiled) = std::mem::replace(&mut self.current_function, enclosing) {
+            self.lambdas[index - self.lambda_base] = compiled;
+        }
+        self.locals = enclosing_locals;
+        self.break_targets = break_targets;
+        self.continue_targets = continue_targets;
+
+        self.emit(OpCode::MakeClosure(index, captured.len()));
+        Ok(())
+    }
+
     fn compile_statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
         match stmt {
             Statement::VarDecl(decl) => {
                 // Compile the initializer
                 self.compile_expr(&decl.value)?;
+                if decl.secret {
+                    self.emit(OpCode::Secret);
+                }
 
                 // Allocate local slot
                 let slot = self.allocate_local(&decl.name);
@@ -173,14 +361,27 @@ fn compile_statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
             }
 
             Statement::Assignment(assign) => {
+                // `text = text + more;` on a local appends to it in place, so
+                // building a string up in a loop stays linear
+                if let Expr::Binary(BinaryOp::Add, left, right) = &assign.value.node {
+                    let slot = self.locals.get(assign.target.as_str()).copied();
+                    if let (Expr::Identifier(name), Some(slot)) = (&left.node, slot) {
+                        if *name == assign.target {
+                            self.compile_expr(right)?;
+                            self.emit(OpCode::AppendLocal(slot));
+                            return Ok(());
+                        }
+                    }
+                }
+
                 // Compile the value
                 self.compile_expr(&assign.value)?;
 
                 // Store to variable
-                if let Some(&slot) = self.locals.get(&assign.target) {
+                if let Some(&slot) = self.locals.get(assign.target.as_str()) {
                     self.emit(OpCode::StoreLocal(slot));
                 } else {
-                    self.emit(OpCode::StoreGlobal(assign.target.clone()));
+                    self.emit(OpCode::StoreGlobal(assign.target.to_string()));
                 }
             }
 
@@ -239,11 +440,8 @@ fn compile_statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
                 let mut end_jumps = Vec::new();
 
                 for arm in &decide.arms {
-                    // Load scrutinee for each arm
-                    self.emit(OpCode::LoadLocal(scrutinee_slot));
-
-                    // Compile pattern match
-                    let skip_jump = self.compile_pattern(&arm.pattern)?;
+                    // Compile pattern match against the stored scrutinee
+                    let skip_jumps = self.compile_pattern(&arm.pattern, scrutinee_slot)?;
 
                     // Compile arm body
                     for stmt in &arm.body {
@@ -254,9 +452,11 @@ fn compile_statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
                     let end_jump = self.emit(OpCode::Jump(0));
                     end_jumps.push(end_jump);
 
-                    // Patch skip jump
+                    // Patch skip jumps
                     let after_arm = self.current_offset();
-                    self.patch_jump(skip_jump, after_arm);
+                    for skip_jump in skip_jumps {
+                        self.patch_jump(skip_jump, after_arm);
+                    }
                 }
 
                 // Patch all end jumps
@@ -288,7 +488,7 @@ fn compile_statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
 
             Statement::Complain(complain) => {
                 // Load error message
-                let msg_idx = self.add_constant(Value::String(complain.message.clone()));
+                let msg_idx = self.add_constant(Value::String(complain.message.clone().into()));
                 self.emit(OpCode::Const(msg_idx));
                 self.emit(OpCode::MakeOops);
                 self.emit(OpCode::Return);
@@ -303,19 +503,6 @@ fn compile_statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
             Statement::WorkerSpawn(_) => {
                 // Worker spawning handled at runtime
             }
-            Statement::SendMessage(send) => {
-                self.compile_expr(&send.value)?;
-                // Message sending handled at runtime
-            }
-            Statement::ReceiveMessage(_) => {
-                // Message receiving handled at runtime
-            }
-            Statement::AwaitWorker(_) => {
-                // Worker awaiting handled at runtime
-            }
-            Statement::CancelWorker(_) => {
-                // Worker cancellation handled at runtime
-            }
         }
         Ok(())
     }
@@ -371,115 +558,58 @@ fn compile_loop(&mut self, loop_stmt: &Loop) -> Result<(), CompileError> {
         Ok(())
     }
 
-    fn compile_pattern(&mut self, pattern: &Pattern) -> Result<usize, CompileError> {
+    /// Compile a pattern test against the value stored in `slot`.
+    ///
+    /// Leaves the stack balanced on both paths and returns the jumps that
+    /// must be patched to the start of the next arm when the match fails.
+    fn compile_pattern(&mut self, pattern: &Pattern, slot: usize) -> Result<Vec<usize>, CompileError> {
         match pattern {
-            Pattern::Wildcard => {
-                // Always matches, just pop the value
-                self.emit(OpCode::Pop);
-                // Return a dummy jump that will be patched but never taken
-                let always_true = self.add_constant(Value::Bool(true));
-                self.emit(OpCode::Const(always_true));
-                Ok(self.emit(OpCode::JumpIfFalse(0)))
-            }
+            Pattern::Wildcard => Ok(Vec::new()),
 
             Pattern::Literal(lit) => {
-                // Compare against literal
-                match lit {
-                    Literal::Integer(n) => {
-                        let idx = self.add_constant(Value::Int(*n));
-                        self.emit(OpCode::Const(idx));
-                    }
-                    Literal::Float(n) => {
-                        let idx = self.add_constant(Value::Float(*n));
-                        self.emit(OpCode::Const(idx));
-                    }
-                    Literal::String(s) => {
-                        let idx = self.add_constant(Value::String(s.clone()));
-                        self.emit(OpCode::Const(idx));
-                    }
-                    Literal::Bool(b) => {
-                        let idx = self.add_constant(Value::Bool(*b));
-                        self.emit(OpCode::Const(idx));
-                    }
-                }
+                self.emit(OpCode::LoadLocal(slot));
+                let idx = self.add_constant(literal_value(lit));
+                self.emit(OpCode::Const(idx));
                 self.emit(OpCode::Eq);
-                Ok(self.emit(OpCode::JumpIfFalse(0)))
+                Ok(vec![self.emit(OpCode::JumpIfFalse(0))])
             }
 
             Pattern::Identifier(name) => {
-                // Bind value to name
-                let slot = self.allocate_local(name);
-                self.emit(OpCode::StoreLocal(slot));
-                // Always matches
-                let always_true = self.add_constant(Value::Bool(true));
-                self.emit(OpCode::Const(always_true));
-                Ok(self.emit(OpCode::JumpIfFalse(0)))
-            }
-
-            Pattern::OkayPattern(binding) => {
-                // Check if value is Okay
-                self.emit(OpCode::Dup);
-                self.emit(OpCode::IsOkay);
-                let skip = self.emit(OpCode::JumpIfFalse(0));
-
-                // If okay, extract inner value
-                if let Some(name) = binding {
-                    self.emit(OpCode::TryUnwrap);
-                    let slot = self.allocate_local(name);
-                    self.emit(OpCode::StoreLocal(slot));
-                } else {
-                    self.emit(OpCode::Pop);
-                }
-
-                Ok(skip)
-            }
-
-            Pattern::OopsPattern(binding) => {
-                // Check if value is Oops (not Okay)
-                self.emit(OpCode::Dup);
-                self.emit(OpCode::IsOkay);
-                self.emit(OpCode::Not);
-                let skip = self.emit(OpCode::JumpIfFalse(0));
-
-                // If oops, extract error
-                if let Some(name) = binding {
-                    // Extract error value (implementation specific)
-                    let slot = self.allocate_local(name);
-                    self.emit(OpCode::StoreLocal(slot));
-                } else {
-                    self.emit(OpCode::Pop);
-                }
-
-                Ok(skip)
-            }
+                // Bind value to name; always matches
+                self.emit(OpCode::LoadLocal(slot));
+                let binding = self.allocate_local(name);
+                self.emit(OpCode::StoreLocal(binding));
+                Ok(Vec::new())
+            }
+
+            Pattern::Constructor(name, inner) => {
+                let (test, extract) = match name.as_str() {
+                    "Okay" => (OpCode::IsOkay, OpCode::TryUnwrap),
+                    "Oops" => (OpCode::IsOops, OpCode::ErrorValue),
+                    other => {
+                        return Err(CompileError {
+                            message: format!("Unknown constructor pattern '{}'", other),
+                        })
+                    }
+                };
 
-            Pattern::Constructor(name, patterns) => {
-                // Constructor pattern matching
-                // For now, just check if it matches the constructor name
-                let name_idx = self.add_constant(Value::String(name.clone()));
-                self.emit(OpCode::Const(name_idx));
-                self.emit(OpCode::Eq);
-                let skip = self.emit(OpCode::JumpIfFalse(0));
+                // Test the variant, discarding the peeked value
+                self.emit(OpCode::LoadLocal(slot));
+                self.emit(test);
+                self.emit(OpCode::Swap);
+                self.emit(OpCode::Pop);
+                let mut skips = vec![self.emit(OpCode::JumpIfFalse(0))];
 
-                // TODO: Match inner patterns
-                for _ in patterns {
-                    // Would need to extract fields and match against inner patterns
+                if let Some(inner) = inner {
+                    // Extract the payload into a temp and match it
+                    self.emit(OpCode::LoadLocal(slot));
+                    self.emit(extract);
+                    let payload = self.allocate_local(&format!("__payload_{}__", slot));
+                    self.emit(OpCode::StoreLocal(payload));
+                    skips.extend(self.compile_pattern(inner, payload)?);
                 }
 
-                Ok(skip)
-            }
-
-            Pattern::Guard(inner, condition) => {
-                // First match inner pattern
-                let inner_skip = self.compile_pattern(inner)?;
-
-                // Then check guard condition
-                self.compile_expr(condition)?;
-                let guard_skip = self.emit(OpCode::JumpIfFalse(0));
-
-                // Both must pass - use the guard skip as the main skip
-                // The inner_skip needs to also jump to the after-arm location
-                Ok(guard_skip)
+                Ok(skips)
             }
         }
     }
@@ -488,33 +618,17 @@ fn compile_expr(&mut self, spanned: &Spanned<Expr>) -> Result<(), CompileError>
         let expr = &spanned.node;
         match expr {
             Expr::Literal(lit) => {
-                match lit {
-                    Literal::Integer(n) => {
-                        let idx = self.add_constant(Value::Int(*n));
-                        self.emit(OpCode::Const(idx));
-                    }
-                    Literal::Float(n) => {
-                        let idx = self.add_constant(Value::Float(*n));
-                        self.emit(OpCode::Const(idx));
-                    }
-                    Literal::String(s) => {
-                        let idx = self.add_constant(Value::String(s.clone()));
-                        self.emit(OpCode::Const(idx));
-                    }
-                    Literal::Bool(b) => {
-                        let idx = self.add_constant(Value::Bool(*b));
-                        self.emit(OpCode::Const(idx));
-                    }
-                }
+                let idx = self.add_constant(literal_value(lit));
+                self.emit(OpCode::Const(idx));
             }
 
             Expr::Identifier(name) => {
-                if let Some(&slot) = self.locals.get(name) {
+                if let Some(&slot) = self.locals.get(name.as_str()) {
                     self.emit(OpCode::LoadLocal(slot));
-                } else if let Some(&func_idx) = self.function_indices.get(name) {
-                    self.emit(OpCode::MakeClosure(func_idx));
+                } else if let Some(&func_idx) = self.function_indices.get(name.as_str()) {
+                    self.emit(OpCode::MakeClosure(func_idx, 0));
                 } else {
-                    self.emit(OpCode::LoadGlobal(name.clone()));
+                    self.emit(OpCode::LoadGlobal(name.to_string()));
                 }
             }
 
@@ -556,7 +670,31 @@ fn compile_expr(&mut self, spanned: &Spanned<Expr>) -> Result<(), CompileError>
                 // Special built-in functions
                 match name.as_str() {
                     "print" => {
-                        self.emit(OpCode::Print);
+                        self.emit(OpCode::Print(args.len()));
+                    }
+                    "exit" => {
+                        if args.len() != 1 {
+                            return Err(CompileError {
+                                message: format!("exit takes one argument, got {}", args.len()),
+                            });
+                        }
+                        self.emit(OpCode::Exit);
+                    }
+                    "ask" | "askNumber" | "askYesNo" => {
+                        if args.len() != 1 {
+                            return Err(CompileError {
+                                message: format!("{} takes one argument, got {}", name, args.len()),
+                            });
+                        }
+                        self.emit(OpCode::Ask(Answer::of_builtin(name).expect("matched above")));
+                    }
+                    "prettyPrint" => {
+                        if args.len() != 1 {
+                            return Err(CompileError {
+                                message: format!("prettyPrint takes one argument, got {}", args.len()),
+                            });
+                        }
+                        self.emit(OpCode::PrettyPrint);
                     }
                     "toString" => {
                         self.emit(OpCode::ToString);
@@ -564,14 +702,53 @@ fn compile_expr(&mut self, spanned: &Spanned<Expr>) -> Result<(), CompileError>
                     "len" => {
                         self.emit(OpCode::Len);
                     }
+                    "getError" => {
+                        if args.len() != 1 {
+                            return Err(CompileError {
+                                message: format!("getError takes one argument, got {}", args.len()),
+                            });
+                        }
+                        self.emit(OpCode::ErrorValue);
+                    }
+                    "format" => {
+                        self.emit(OpCode::CallNative("std.string.format".to_string(), args.len()));
+                    }
+                    "args" => {
+                        self.emit(OpCode::CallNative("std.env.args".to_string(), args.len()));
+                    }
+                    "listSuperpowers" => {
+                        self.emit(OpCode::CallNative("std.superpowers.list".to_string(), args.len()));
+                    }
+                    "hasSuperpower" => {
+                        self.emit(OpCode::CallNative("std.superpowers.has".to_string(), args.len()));
+                    }
+                    "renounce" => {
+                        self.emit(OpCode::CallNative("std.superpowers.renounce".to_string(), args.len()));
+                    }
+                    "gratitudeEntries" => {
+                        if !args.is_empty() {
+                            return Err(CompileError {
+                                message: format!("gratitudeEntries takes no arguments, got {}", args.len()),
+                            });
+                        }
+                        // Known when compiling, so kept as a constant
+                        let idx = self.add_constant(self.gratitude.clone());
+                        self.emit(OpCode::Const(idx));
+                    }
                     _ => {
-                        // Look up function
-                        if let Some(&func_idx) = self.function_indices.get(name) {
-                            self.emit(OpCode::MakeClosure(func_idx));
+                        // A variable holding a function comes first, as on
+                        // the interpreter
+                        if let Some(&slot) = self.locals.get(name.as_str()) {
+                            self.emit(OpCode::LoadLocal(slot));
+                            self.emit(OpCode::Call(args.len()));
+                        } else if let Some(native) = self.stdlib_name(name) {
+                            self.emit(OpCode::CallNative(native, args.len()));
+                        } else if let Some(&func_idx) = self.function_indices.get(name.as_str()) {
+                            self.emit(OpCode::MakeClosure(func_idx, 0));
                             self.emit(OpCode::Call(args.len()));
                         } else {
-                            // Dynamic call via global
-                            self.emit(OpCode::LoadGlobal(name.clone()));
+                            // Dynamic call via global, such as a host function
+                            self.emit(OpCode::LoadGlobal(self.full_name(name)));
                             self.emit(OpCode::Call(args.len()));
                         }
                     }
@@ -585,48 +762,72 @@ fn compile_expr(&mut self, spanned: &Spanned<Expr>) -> Result<(), CompileError>
                 self.emit(OpCode::MakeArray(elements.len()));
             }
 
-            Expr::ResultConstructor { is_okay, value } => {
-                self.compile_expr(value)?;
-                if *is_okay {
-                    self.emit(OpCode::MakeOkay);
-                } else {
-                    self.emit(OpCode::MakeOops);
+            Expr::CallExpr(callee, args) => {
+                for arg in args {
+                    self.compile_expr(arg)?;
                 }
+                self.compile_expr(callee)?;
+                self.emit(OpCode::Call(args.len()));
             }
 
-            Expr::Try(inner) => {
+            Expr::Index(object, index) => {
+                self.compile_expr(object)?;
+                self.compile_expr(index)?;
+                self.emit(OpCode::Index);
+            }
+
+            Expr::Okay(inner) => {
                 self.compile_expr(inner)?;
-                self.emit(OpCode::TryUnwrap);
+                self.emit(OpCode::MakeOkay);
+            }
+
+            Expr::Oops(inner) => {
+                self.compile_expr(inner)?;
+                self.emit(OpCode::MakeOops);
             }
 
+            Expr::Lambda(lambda) => self.compile_lambda(lambda)?,
+
             Expr::Unwrap(inner) => {
                 self.compile_expr(inner)?;
                 self.emit(OpCode::TryUnwrap);
             }
 
-            Expr::UnitMeasurement(value, _unit) => {
-                // Compile the value, unit is metadata
+            Expr::UnitMeasurement(value, unit) => {
                 self.compile_expr(value)?;
+                // Time units make a Duration; other units are metadata
+                if duration::unit_millis(unit).is_some() {
+                    let idx = self.add_constant(Value::String(unit.clone().into()));
+                    self.emit(OpCode::Const(idx));
+                    self.emit(OpCode::CallNative("std.time.duration".to_string(), 2));
+                }
             }
 
             Expr::GratitudeLiteral(name) => {
                 // Gratitude literals are just strings
-                let idx = self.add_constant(Value::String(name.clone()));
+                let idx = self.add_constant(Value::String(name.clone().into()));
                 self.emit(OpCode::Const(idx));
             }
         }
         Ok(())
     }
 
+    /// Full standard library name for a call, expanding an imported module
+    /// alias (`string.trim` -> `std.string.trim`)
+    fn stdlib_name(&self, name: &str) -> Option<String> {
+        let full_name = self.full_name(name);
+        self.stdlib.has(&full_name).then_some(full_name)
+    }
+
+    /// A name with any imported module alias expanded
+    fn full_name(&self, name: &str) -> String {
+        self.scope.resolve(name)
+    }
+
     /// Try to evaluate a constant expression at compile time
     fn try_eval_const(&self, expr: &Expr) -> Option<Value> {
         match expr {
-            Expr::Literal(lit) => match lit {
-                Literal::Integer(n) => Some(Value::Int(*n)),
-                Literal::Float(n) => Some(Value::Float(*n)),
-                Literal::String(s) => Some(Value::String(s.clone())),
-                Literal::Bool(b) => Some(Value::Bool(*b)),
-            },
+            Expr::Literal(lit) => Some(literal_value(lit)),
             _ => None,
         }
     }
@@ -681,6 +882,18 @@ fn allocate_local(&mut self, name: &str) -> usize {
     }
 }
 
+/// Convert a literal to its runtime value
+fn literal_value(lit: &Literal) -> Value {
+    match lit {
+        Literal::Integer(n) => Value::Int(*n),
+        Literal::Float(n) => Value::Float(*n),
+        Literal::Decimal(n) => Value::Decimal(*n),
+        Literal::String(s) => Value::String(s.clone().into()),
+        Literal::Bool(b) => Value::Bool(*b),
+        Literal::Unit => Value::Unit,
+    }
+}
+
 impl Default for BytecodeCompiler {
     fn default() -> Self {
         Self::new()
@@ -762,4 +975,22 @@ fn test_compile_conditional() {
         // Should have JumpIfFalse for condition
         assert!(func.code.iter().any(|op| matches!(op, OpCode::JumpIfFalse(_))));
     }
+
+    #[test]
+    fn test_compile_append() {
+        let source = r#"
+            to shout(text: String) -> String {
+                text = text + "!";
+                text = "<" + text;
+                give back text;
+            }
+        "#;
+
+        let program = compile_source(source).unwrap();
+        let code = &program.functions[0].code;
+
+        // Only adding onto the variable itself appends in place
+        assert_eq!(code.iter().filter(|op| matches!(op, OpCode::AppendLocal(0))).count(), 1);
+        assert_eq!(code.iter().filter(|op| matches!(op, OpCode::Add)).count(), 1);
+    }
 }
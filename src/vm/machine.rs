@@ -2,9 +2,19 @@
 //!
 //! Stack-based VM for executing compiled bytecode.
 
-use crate::interpreter::Value;
-use super::bytecode::{CompiledFunction, CompiledProgram, OpCode};
+use crate::interpreter::{
+    stdlib_error, BindingError, HostFunction, NativeFunction, SideQuestOutcome, SideQuestStatus, Signature, Value,
+    VmClosure,
+};
+use crate::profile::Profiler;
+use crate::security::{function_scope, limits, secrets, Budget, CapabilityRegistry, Footprint, LimitExceeded, Limits};
+use crate::stdlib::{decimal, duration, string, StdlibError, StdlibRegistry};
+use crate::typechecker::TypeChecker;
+use super::bytecode::{CompiledProgram, OpCode};
+use super::snapshot::{FrameSnapshot, VmSnapshot};
 use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
 
 /// Call frame for function execution
 #[derive(Debug, Clone)]
@@ -31,68 +41,461 @@ pub struct VirtualMachine {
     max_stack_size: usize,
     /// Maximum call depth (for safety)
     max_call_depth: usize,
+    /// Standard library for `CallNative`
+    stdlib: StdlibRegistry,
+    /// Capabilities granted to standard library calls
+    capabilities: CapabilityRegistry,
+    /// Functions the host registered, numbered after the program's own
+    natives: Vec<NativeFunction>,
+    /// Limits on the run, from the host and the program's pragmas
+    limits: Limits,
+    /// How far the run has got towards its limits, once it has started
+    budget: Option<Budget>,
+    /// Where `ask` reads answers from; `None` reads stdin
+    input: Option<Box<dyn BufRead>>,
+    /// Where `print`, `prettyPrint` and the questions `ask` asks go
+    output: Box<dyn Write>,
+    /// Told as each call starts and ends, when profiling
+    profiler: Option<Profiler>,
+    /// Name of the program's module, the first part of the capability
+    /// scope of its functions (`main:readConfig`)
+    module_name: String,
+    /// Whether the host skips side quests, as `#side quests off` does
+    skip_side_quests: bool,
+    side_quest_outcomes: Vec<SideQuestOutcome>,
+    /// Whether the program called `exit`, which ends it before its side
+    /// quests
+    exited: bool,
 }
 
 impl VirtualMachine {
     pub fn new(program: CompiledProgram) -> Self {
         // Initialize globals from the compiled program
         let globals = program.globals.clone();
-        Self {
+        let limits = program.limits;
+        let mut vm = Self {
             program,
             stack: Vec::with_capacity(1024),
             call_stack: Vec::with_capacity(64),
             globals,
             max_stack_size: 10000,
             max_call_depth: 1000,
+            stdlib: StdlibRegistry::new(),
+            capabilities: CapabilityRegistry::new(),
+            natives: Vec::new(),
+            limits,
+            budget: None,
+            input: None,
+            output: Box::new(io::stdout()),
+            profiler: None,
+            module_name: "main".to_string(),
+            skip_side_quests: false,
+            side_quest_outcomes: Vec::new(),
+            exited: false,
+        };
+        vm.declare_superpowers();
+        vm
+    }
+
+    /// Send program output (`print`, `prettyPrint` and questions) to
+    /// `output` instead of stdout, as `Interpreter::with_output`
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Skip side quests, as the `#side quests off` pragma does
+    pub fn with_skip_side_quests(mut self, skip: bool) -> Self {
+        self.skip_side_quests = skip;
+        self
+    }
+
+    /// Read the answers to `ask`, `askNumber` and `askYesNo` from `input`
+    /// instead of stdin
+    pub fn with_input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Read answers from `input` from now on, giving back the input read
+    /// so far, e.g. for a host to keep between runs
+    pub(crate) fn swap_input(&mut self, input: Option<Box<dyn BufRead>>) -> Option<Box<dyn BufRead>> {
+        std::mem::replace(&mut self.input, input)
+    }
+
+    /// Stop a run that takes longer or holds more memory than `limits`;
+    /// where the program's own pragmas set lower limits, those hold
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits.tighten(limits);
+        self
+    }
+
+    /// Tell `profiler` as each function call starts and ends
+    pub fn with_profiler(mut self, profiler: &Profiler) -> Self {
+        self.profiler = Some(profiler.clone());
+        self
+    }
+
+    /// Name the program's module, as `Interpreter::with_module_name`
+    pub fn with_module_name(mut self, name: &str) -> Self {
+        self.module_name = name.to_string();
+        self
+    }
+
+    /// Give standard library calls these capabilities instead of an empty
+    /// registry that asks for everything
+    pub fn with_capabilities(mut self, capabilities: CapabilityRegistry) -> Self {
+        self.capabilities = capabilities;
+        self.declare_superpowers();
+        self
+    }
+
+    /// Limit imported modules to what the program's `use ... must have`
+    /// declares they need
+    fn declare_superpowers(&mut self) {
+        for declaration in &self.program.superpowers {
+            self.capabilities.declare(declaration.clone());
         }
     }
 
+    /// The capabilities granted so far, as `Interpreter::capabilities_mut`
+    pub fn capabilities_mut(&mut self) -> &mut CapabilityRegistry {
+        &mut self.capabilities
+    }
+
+    /// Run confined to `root`, as `Interpreter::with_sandbox` does
+    pub fn with_sandbox(mut self, root: &Path) -> Self {
+        self.stdlib = StdlibRegistry::sandboxed();
+        self.capabilities.sandbox(root);
+        self
+    }
+
+    /// Make the Rust function `func` callable from the program as `name`,
+    /// as `Interpreter::register_native` does
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl FnMut(&[Value]) -> Result<Value, StdlibError> + 'static,
+    ) -> &mut NativeFunction {
+        self.add_native(NativeFunction::new(name, arity, func))
+    }
+
+    /// Make the Rust closure `func` callable from the program under a
+    /// WokeLang signature, as `Interpreter::bind` does
+    pub fn bind<Args>(
+        &mut self,
+        signature: &str,
+        func: impl HostFunction<Args>,
+    ) -> Result<&mut NativeFunction, BindingError> {
+        let native = Signature::parse(signature)?.bind(func)?;
+        Ok(self.add_native(native))
+    }
+
+    /// A type checker that knows the host functions registered so far, as
+    /// `Interpreter::type_checker`
+    pub fn type_checker(&self) -> TypeChecker {
+        TypeChecker::with_natives(&self.natives)
+    }
+
+    /// Add the functions of a loaded plugin, by their full names
+    /// (`std.gpio.write`)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_plugin(mut self, plugin: &crate::ffi::Plugin) -> Self {
+        for native in plugin.natives() {
+            self.add_native(native);
+        }
+        self
+    }
+
+    /// The host functions, leaving none registered
+    pub(crate) fn take_natives(&mut self) -> Vec<NativeFunction> {
+        std::mem::take(&mut self.natives)
+    }
+
+    pub(crate) fn add_native(&mut self, native: NativeFunction) -> &mut NativeFunction {
+        let index = match self.natives.iter().position(|n| n.name() == native.name()) {
+            Some(index) => {
+                self.natives[index] = native;
+                index
+            }
+            None => {
+                self.natives.push(native);
+                self.natives.len() - 1
+            }
+        };
+        &mut self.natives[index]
+    }
+
+    /// The function reference of the host function `name`: the program's
+    /// functions come first, then the natives in the order registered
+    fn native_reference(&self, name: &str) -> Option<Value> {
+        let index = self.natives.iter().position(|n| n.name() == name)?;
+        Some(Value::VmFunction(VmClosure::new(self.program.functions.len() + index)))
+    }
+
     /// Run the program starting from main
     pub fn run(&mut self) -> Result<Value, VMError> {
+        self.start()?;
+        self.resume()
+    }
+
+    /// Set up the entry frame without executing any instructions
+    pub fn start(&mut self) -> Result<(), VMError> {
         let entry = self.program.entry.ok_or_else(|| VMError {
             message: "No main function found".to_string(),
+            code: Some("WOKE-E0301"),
         })?;
 
-        self.call_function(entry, 0)?;
+        self.call_function(entry, 0)
+    }
 
+    /// Continue execution until the program finishes, then run its side
+    /// quests
+    pub fn resume(&mut self) -> Result<Value, VMError> {
         while !self.call_stack.is_empty() {
+            self.check_limits()?;
             self.execute_instruction()?;
         }
+        self.check_clock()?;
 
         // Return final value or Unit
-        Ok(self.stack.pop().unwrap_or(Value::Unit))
+        let value = self.stack.pop().unwrap_or(Value::Unit);
+        self.run_side_quests();
+        Ok(value)
+    }
+
+    /// Run the side quests, in the order they are defined, unless they are
+    /// skipped. As on the interpreter, one failing stops neither the others
+    /// nor the program; see [`VirtualMachine::side_quests`] for how each went.
+    fn run_side_quests(&mut self) {
+        let skip = self.skip_side_quests || self.program.skip_side_quests;
+        for quest in self.program.side_quests.clone() {
+            if self.exited {
+                break;
+            }
+            let status = match skip {
+                true => SideQuestStatus::Skipped,
+                false => match self.run_side_quest(quest.function) {
+                    Ok(()) => SideQuestStatus::Done,
+                    Err(e) => SideQuestStatus::Failed {
+                        code: e.code,
+                        message: e.message,
+                    },
+                },
+            };
+            self.side_quest_outcomes.push(SideQuestOutcome {
+                name: quest.name,
+                span: quest.span,
+                status,
+            });
+        }
+    }
+
+    /// Run a side quest's function to its end, leaving the stack as it was
+    fn run_side_quest(&mut self, function: usize) -> Result<(), VMError> {
+        let base = self.stack.len();
+        let result = self.call_function(function, 0).and_then(|()| {
+            while !self.call_stack.is_empty() {
+                self.check_limits()?;
+                self.execute_instruction()?;
+            }
+            Ok(())
+        });
+        self.call_stack.clear();
+        self.stack.truncate(base);
+        result
+    }
+
+    /// How each side quest of the run went, in the order they ran
+    pub fn side_quests(&self) -> &[SideQuestOutcome] {
+        &self.side_quest_outcomes
+    }
+
+    /// The side quest outcomes, leaving none
+    pub(crate) fn take_side_quests(&mut self) -> Vec<SideQuestOutcome> {
+        std::mem::take(&mut self.side_quest_outcomes)
+    }
+
+    /// Execute at most `max_steps` instructions
+    ///
+    /// Returns `Some(result)` once the program has finished, or `None` if it
+    /// is paused and can be resumed (or snapshotted).
+    pub fn run_steps(&mut self, max_steps: usize) -> Result<Option<Value>, VMError> {
+        for _ in 0..max_steps {
+            if self.call_stack.is_empty() {
+                break;
+            }
+            self.check_limits()?;
+            self.execute_instruction()?;
+        }
+
+        if self.call_stack.is_empty() {
+            self.check_clock()?;
+            Ok(Some(self.stack.pop().unwrap_or(Value::Unit)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Global variables, by name
+    pub fn globals(&self) -> Vec<(&str, &Value)> {
+        let mut globals: Vec<_> = self.globals.iter().map(|(name, value)| (name.as_str(), value)).collect();
+        globals.sort_by_key(|(name, _)| *name);
+        globals
+    }
+
+    /// Whether the VM has active frames left to execute
+    pub fn is_running(&self) -> bool {
+        !self.call_stack.is_empty()
+    }
+
+    /// Capture the current execution state
+    pub fn snapshot(&self) -> VmSnapshot {
+        VmSnapshot {
+            program_fingerprint: self.program.fingerprint(),
+            stack: self.stack.clone(),
+            frames: self
+                .call_stack
+                .iter()
+                .map(|frame| FrameSnapshot {
+                    function_idx: frame.function_idx,
+                    ip: frame.ip,
+                    base_ptr: frame.base_ptr,
+                })
+                .collect(),
+            globals: self.globals.clone(),
+        }
+    }
+
+    /// Rebuild a paused VM from a snapshot of the same program
+    pub fn from_snapshot(program: CompiledProgram, snapshot: VmSnapshot) -> Result<Self, VMError> {
+        if snapshot.program_fingerprint != program.fingerprint() {
+            return Err(VMError {
+                message: "Snapshot was taken from a different program".to_string(),
+                code: None,
+            });
+        }
+
+        for frame in &snapshot.frames {
+            let valid = program
+                .get_function(frame.function_idx)
+                .is_some_and(|func| frame.ip <= func.code.len())
+                && frame.base_ptr <= snapshot.stack.len();
+            if !valid {
+                return Err(VMError {
+                    message: format!(
+                        "Snapshot frame (function {}, ip {}) is invalid for this program",
+                        frame.function_idx, frame.ip
+                    ),
+                    code: None,
+                });
+            }
+        }
+
+        let mut vm = Self::new(program);
+        vm.stack = snapshot.stack;
+        vm.globals = snapshot.globals;
+        vm.call_stack = snapshot
+            .frames
+            .into_iter()
+            .map(|frame| CallFrame {
+                function_idx: frame.function_idx,
+                ip: frame.ip,
+                base_ptr: frame.base_ptr,
+            })
+            .collect();
+        Ok(vm)
+    }
+
+    /// Count an instruction towards the run's limits, measuring the memory
+    /// on the stack and in globals every so often. The clock starts at the
+    /// first instruction run, or run again after a snapshot.
+    fn check_limits(&mut self) -> Result<(), VMError> {
+        if self.limits.is_none() {
+            return Ok(());
+        }
+        let limits = self.limits;
+        let budget = self.budget.get_or_insert_with(|| Budget::start(limits));
+        budget.step()?;
+        if !budget.should_measure() {
+            return Ok(());
+        }
+        let mut footprint = Footprint::default();
+        self.stack.iter().chain(self.globals.values()).for_each(|v| v.measure(&mut footprint));
+        if let Some(budget) = &mut self.budget {
+            budget.check_memory(&footprint)?;
+        }
+        Ok(())
     }
 
     /// Call a function with arguments already on the stack
+    /// Check the run has not gone over its timeout, after something that
+    /// may have taken a while without counting instructions
+    fn check_clock(&self) -> Result<(), VMError> {
+        match &self.budget {
+            Some(budget) => Ok(budget.check_clock()?),
+            None => Ok(()),
+        }
+    }
+
     fn call_function(&mut self, func_idx: usize, arg_count: usize) -> Result<(), VMError> {
         if self.call_stack.len() >= self.max_call_depth {
             return Err(VMError {
                 message: "Maximum call depth exceeded".to_string(),
+                code: Some("WOKE-E0309"),
             });
         }
+        if let Some(index) = func_idx
+            .checked_sub(self.program.functions.len())
+            .filter(|&index| index < self.natives.len())
+        {
+            let split = self.stack.len().checked_sub(arg_count).ok_or_else(|| VMError {
+                message: "Stack underflow".to_string(),
+                code: None,
+            })?;
+            let args = self.stack.split_off(split);
+            let name = self.natives[index].name().to_string();
+            let caller = self.enter_native(&name);
+            let handling = handling_secrets(&args);
+            let deadline = limits::enter_call(self.budget.as_ref());
+            let result = self.natives[index].call(&args, &mut self.capabilities);
+            drop(deadline);
+            self.leave_native(caller);
+            self.check_clock()?;
+            let value = result.map_err(|e| VMError::stdlib(&name, e))?;
+            return self.push(value.secret_if(handling.is_some()));
+        }
 
         let func = self.program.get_function(func_idx).ok_or_else(|| VMError {
             message: format!("Function {} not found", func_idx),
+            code: None,
         })?;
 
-        if arg_count != func.arity {
+        if arg_count != func.arity + func.captures {
             return Err(VMError {
                 message: format!(
                     "Function {} expects {} arguments, got {}",
-                    func.name, func.arity, arg_count
+                    func.name,
+                    func.arity,
+                    arg_count.saturating_sub(func.captures)
                 ),
+                code: Some("WOKE-E0308"),
             });
         }
 
         // Calculate base pointer (before args)
         let base_ptr = self.stack.len() - arg_count;
 
-        // Reserve space for locals (beyond parameters)
-        let extra_locals = func.locals - func.arity;
+        // Reserve space for locals (beyond parameters and captured values)
+        let extra_locals = func.locals - func.arity - func.captures;
         for _ in 0..extra_locals {
             self.stack.push(Value::Unit);
         }
 
+        if let Some(profiler) = &self.profiler {
+            profiler.enter(&func.name);
+        }
         self.call_stack.push(CallFrame {
             function_idx: func_idx,
             ip: 0,
@@ -102,20 +505,143 @@ fn call_function(&mut self, func_idx: usize, arg_count: usize) -> Result<(), VME
         Ok(())
     }
 
+    /// Write one line of program output
+    fn write_line(&mut self, line: &str) -> Result<(), VMError> {
+        writeln!(self.output, "{}", line).map_err(|e| VMError {
+            message: format!("Failed to write output: {}", e),
+            code: Some("WOKE-E0310"),
+        })
+    }
+
+    /// Call a closure with its arguments already on the stack, putting the
+    /// values it captured in the slots after them
+    fn call_closure(&mut self, closure: &VmClosure, arg_count: usize) -> Result<(), VMError> {
+        for capture in closure.captures.iter() {
+            self.push(capture.clone())?;
+        }
+        self.call_function(closure.function, arg_count + closure.captures.len())
+    }
+
+    /// Call a function value to completion and return its result
+    ///
+    /// Used when a standard library function calls back into the program.
+    fn call_value(&mut self, callee: &Value, args: Vec<Value>) -> Result<Value, VMError> {
+        let closure = match callee {
+            Value::VmFunction(closure) => closure,
+            other => {
+                return Err(VMError {
+                    message: format!("Cannot call non-function value {}", other),
+                    code: Some("WOKE-E0302"),
+                })
+            }
+        };
+
+        let depth = self.call_stack.len();
+        let arg_count = args.len();
+        for arg in args {
+            self.push(arg)?;
+        }
+        self.call_closure(closure, arg_count)?;
+        while self.call_stack.len() > depth {
+            self.execute_instruction()?;
+        }
+        self.pop()
+    }
+
+    /// Run a standard library function on arguments already on the stack
+    fn call_native(&mut self, name: &str, arg_count: usize) -> Result<(), VMError> {
+        let split = self.stack.len().checked_sub(arg_count).ok_or_else(|| VMError {
+            message: "Stack underflow".to_string(),
+            code: None,
+        })?;
+        let args = self.stack.split_off(split);
+
+        let caller = self.enter_native(name);
+        let handling = handling_secrets(&args);
+        let deadline = limits::enter_call(self.budget.as_ref());
+        let result = match self.stdlib.get_higher_order(name) {
+            Some(func) => {
+                // Errors raised inside callbacks are passed through unchanged
+                let mut callback_error = None;
+                let result = self
+                    .stdlib
+                    .authorize(name, &args, &mut self.capabilities)
+                    .and_then(|()| {
+                        func(&args, &mut |callee, callee_args| {
+                            let outcome = match callee {
+                                Value::VmFunction(_) => self.call_value(callee, callee_args),
+                                other => Err(VMError {
+                                    message: format!("{}: expected a function, got {}", name, other),
+                                    code: Some("WOKE-E0302"),
+                                }),
+                            };
+                            outcome.map_err(|e| {
+                                let message = e.message.clone();
+                                callback_error.get_or_insert(e);
+                                StdlibError::RuntimeError(message)
+                            })
+                        })
+                    });
+                if let Some(e) = callback_error {
+                    self.leave_native(caller);
+                    return Err(e);
+                }
+                result
+            }
+            None => self.stdlib.call(name, &args, &mut self.capabilities),
+        };
+        drop(deadline);
+        self.leave_native(caller);
+
+        // A call cut short by the timeout fails as the timeout
+        self.check_clock()?;
+        let value = result.map_err(|e| VMError::stdlib(name, e))?;
+        self.push(value.secret_if(handling.is_some()))
+    }
+
+    /// Make the checks of the native function `name` as the interpreter
+    /// does: in its module, and in the scope of the function running it,
+    /// a lambda counting as the function that called it. Gives back the
+    /// scope and module to restore afterwards.
+    fn enter_native(&mut self, name: &str) -> (String, Option<String>) {
+        let scope = match self
+            .call_stack
+            .iter()
+            .rev()
+            .filter_map(|frame| self.program.get_function(frame.function_idx))
+            .find(|func| func.name != "<lambda>")
+        {
+            Some(func) => function_scope(func.module.as_deref().unwrap_or(&self.module_name), &func.name),
+            None => self.module_name.clone(),
+        };
+        let module = name.rsplit_once('.').map(|(module, _)| module.to_string());
+        (self.capabilities.set_scope(scope), self.capabilities.set_module(module))
+    }
+
+    fn leave_native(&mut self, (scope, module): (String, Option<String>)) {
+        self.capabilities.set_scope(scope);
+        self.capabilities.set_module(module);
+    }
+
     /// Execute one instruction
     fn execute_instruction(&mut self) -> Result<(), VMError> {
         let frame = self.call_stack.last_mut().ok_or_else(|| VMError {
             message: "No active call frame".to_string(),
+            code: None,
         })?;
 
         let func = self.program.get_function(frame.function_idx).ok_or_else(|| VMError {
             message: "Invalid function index".to_string(),
+            code: None,
         })?;
 
         if frame.ip >= func.code.len() {
             // Implicit return
             let return_value = self.stack.pop().unwrap_or(Value::Unit);
             let frame = self.call_stack.pop().unwrap();
+            if let Some(profiler) = &self.profiler {
+                profiler.leave();
+            }
 
             // Clean up locals
             self.stack.truncate(frame.base_ptr);
@@ -135,6 +661,7 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                 let func = self.program.get_function(func_idx).unwrap();
                 let value = func.constants.get(idx).cloned().ok_or_else(|| VMError {
                     message: format!("Constant {} not found", idx),
+                    code: None,
                 })?;
                 self.push(value)?;
             }
@@ -173,7 +700,10 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
             }
 
             OpCode::LoadGlobal(name) => {
-                let value = self.globals.get(&name).cloned().unwrap_or(Value::Unit);
+                let value = match self.globals.get(&name) {
+                    Some(value) => value.clone(),
+                    None => self.native_reference(&name).unwrap_or(Value::Unit),
+                };
                 self.push(value)?;
             }
 
@@ -185,17 +715,24 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
             OpCode::Add => {
                 let b = self.pop()?;
                 let a = self.pop()?;
-                let result = match (&a, &b) {
-                    (Value::Int(x), Value::Int(y)) => Value::Int(x + y),
-                    (Value::Float(x), Value::Float(y)) => Value::Float(x + y),
-                    (Value::Int(x), Value::Float(y)) => Value::Float(*x as f64 + y),
-                    (Value::Float(x), Value::Int(y)) => Value::Float(x + *y as f64),
-                    (Value::String(x), Value::String(y)) => Value::String(format!("{}{}", x, y)),
-                    _ => return Err(VMError {
-                        message: format!("Cannot add {:?} and {:?}", a, b),
-                    }),
+                self.push(add(a, b)?)?;
+            }
+
+            OpCode::AppendLocal(slot) => {
+                let b = self.pop()?;
+                let idx = base_ptr + slot;
+                let a = match self.stack.get_mut(idx) {
+                    // Take the String out of its slot so it has no other
+                    // holder and `add` can append to it where it is
+                    Some(slot @ Value::String(_)) => std::mem::replace(slot, Value::Unit),
+                    Some(other) => other.clone(),
+                    None => Value::Unit,
                 };
-                self.push(result)?;
+                let result = add(a, b)?;
+                while self.stack.len() <= idx {
+                    self.stack.push(Value::Unit);
+                }
+                self.stack[idx] = result;
             }
 
             OpCode::Sub => {
@@ -206,8 +743,16 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                     (Value::Float(x), Value::Float(y)) => Value::Float(x - y),
                     (Value::Int(x), Value::Float(y)) => Value::Float(*x as f64 - y),
                     (Value::Float(x), Value::Int(y)) => Value::Float(x - *y as f64),
+                    _ if decimal::involves(&a, &b) => {
+                        decimal::subtract(&a, &b).map_err(VMError::type_error)?
+                    }
+                    (
+                        Value::Duration(_) | Value::DateTime(_),
+                        Value::Duration(_) | Value::DateTime(_),
+                    ) => duration::subtract(&a, &b).map_err(VMError::type_error)?,
                     _ => return Err(VMError {
                         message: format!("Cannot subtract {:?} and {:?}", a, b),
+                        code: Some("WOKE-E0302"),
                     }),
                 };
                 self.push(result)?;
@@ -221,8 +766,15 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                     (Value::Float(x), Value::Float(y)) => Value::Float(x * y),
                     (Value::Int(x), Value::Float(y)) => Value::Float(*x as f64 * y),
                     (Value::Float(x), Value::Int(y)) => Value::Float(x * *y as f64),
+                    _ if decimal::involves(&a, &b) => {
+                        decimal::multiply(&a, &b).map_err(VMError::type_error)?
+                    }
+                    (Value::Duration(_), Value::Int(_)) | (Value::Int(_), Value::Duration(_)) => {
+                        duration::multiply(&a, &b).map_err(VMError::type_error)?
+                    }
                     _ => return Err(VMError {
                         message: format!("Cannot multiply {:?} and {:?}", a, b),
+                        code: Some("WOKE-E0302"),
                     }),
                 };
                 self.push(result)?;
@@ -236,6 +788,7 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                         if *y == 0 {
                             return Err(VMError {
                                 message: "Division by zero".to_string(),
+                                code: Some("WOKE-E0303"),
                             });
                         }
                         Value::Int(x / y)
@@ -243,8 +796,10 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                     (Value::Float(x), Value::Float(y)) => Value::Float(x / y),
                     (Value::Int(x), Value::Float(y)) => Value::Float(*x as f64 / y),
                     (Value::Float(x), Value::Int(y)) => Value::Float(x / *y as f64),
+                    _ if decimal::involves(&a, &b) => decimal::divide(&a, &b).map_err(VMError::type_error)?,
                     _ => return Err(VMError {
                         message: format!("Cannot divide {:?} and {:?}", a, b),
+                        code: Some("WOKE-E0302"),
                     }),
                 };
                 self.push(result)?;
@@ -255,8 +810,12 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                 let a = self.pop()?;
                 let result = match (&a, &b) {
                     (Value::Int(x), Value::Int(y)) => Value::Int(x % y),
+                    _ if decimal::involves(&a, &b) => {
+                        decimal::remainder(&a, &b).map_err(VMError::type_error)?
+                    }
                     _ => return Err(VMError {
-                        message: "Modulo requires integers".to_string(),
+                        message: "Modulo requires integers or Decimals".to_string(),
+                        code: Some("WOKE-E0302"),
                     }),
                 };
                 self.push(result)?;
@@ -267,8 +826,11 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                 let result = match a {
                     Value::Int(x) => Value::Int(-x),
                     Value::Float(x) => Value::Float(-x),
+                    Value::Decimal(d) => Value::Decimal(-d),
+                    Value::Duration(d) => Value::Duration(-d),
                     _ => return Err(VMError {
                         message: "Cannot negate non-numeric value".to_string(),
+                        code: Some("WOKE-E0302"),
                     }),
                 };
                 self.push(result)?;
@@ -277,13 +839,21 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
             OpCode::Eq => {
                 let b = self.pop()?;
                 let a = self.pop()?;
-                self.push(Value::Bool(a == b))?;
+                let result = match decimal::involves(&a, &b) {
+                    true => decimal::compare(&a, &b).map_err(VMError::type_error)?.is_eq(),
+                    false => a == b,
+                };
+                self.push(Value::Bool(result))?;
             }
 
             OpCode::Ne => {
                 let b = self.pop()?;
                 let a = self.pop()?;
-                self.push(Value::Bool(a != b))?;
+                let result = match decimal::involves(&a, &b) {
+                    true => decimal::compare(&a, &b).map_err(VMError::type_error)?.is_ne(),
+                    false => a != b,
+                };
+                self.push(Value::Bool(result))?;
             }
 
             OpCode::Lt => {
@@ -294,6 +864,11 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                     (Value::Float(x), Value::Float(y)) => x < y,
                     (Value::Int(x), Value::Float(y)) => (*x as f64) < *y,
                     (Value::Float(x), Value::Int(y)) => *x < (*y as f64),
+                    (Value::DateTime(x), Value::DateTime(y)) => x < y,
+                    (Value::Duration(x), Value::Duration(y)) => x < y,
+                    _ if decimal::involves(&a, &b) => {
+                        decimal::compare(&a, &b).map_err(VMError::type_error)?.is_lt()
+                    }
                     _ => false,
                 };
                 self.push(Value::Bool(result))?;
@@ -307,6 +882,11 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                     (Value::Float(x), Value::Float(y)) => x <= y,
                     (Value::Int(x), Value::Float(y)) => (*x as f64) <= *y,
                     (Value::Float(x), Value::Int(y)) => *x <= (*y as f64),
+                    (Value::DateTime(x), Value::DateTime(y)) => x <= y,
+                    (Value::Duration(x), Value::Duration(y)) => x <= y,
+                    _ if decimal::involves(&a, &b) => {
+                        decimal::compare(&a, &b).map_err(VMError::type_error)?.is_le()
+                    }
                     _ => false,
                 };
                 self.push(Value::Bool(result))?;
@@ -320,6 +900,11 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                     (Value::Float(x), Value::Float(y)) => x > y,
                     (Value::Int(x), Value::Float(y)) => (*x as f64) > *y,
                     (Value::Float(x), Value::Int(y)) => *x > (*y as f64),
+                    (Value::DateTime(x), Value::DateTime(y)) => x > y,
+                    (Value::Duration(x), Value::Duration(y)) => x > y,
+                    _ if decimal::involves(&a, &b) => {
+                        decimal::compare(&a, &b).map_err(VMError::type_error)?.is_gt()
+                    }
                     _ => false,
                 };
                 self.push(Value::Bool(result))?;
@@ -333,6 +918,11 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                     (Value::Float(x), Value::Float(y)) => x >= y,
                     (Value::Int(x), Value::Float(y)) => (*x as f64) >= *y,
                     (Value::Float(x), Value::Int(y)) => *x >= (*y as f64),
+                    (Value::DateTime(x), Value::DateTime(y)) => x >= y,
+                    (Value::Duration(x), Value::Duration(y)) => x >= y,
+                    _ if decimal::involves(&a, &b) => {
+                        decimal::compare(&a, &b).map_err(VMError::type_error)?.is_ge()
+                    }
                     _ => false,
                 };
                 self.push(Value::Bool(result))?;
@@ -358,7 +948,8 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
             OpCode::Concat => {
                 let b = self.pop()?;
                 let a = self.pop()?;
-                let result = Value::String(format!("{}{}", a, b));
+                let secret = a.is_secret() || b.is_secret();
+                let result = Value::String(format!("{}{}", a, b).into()).secret_if(secret);
                 self.push(result)?;
             }
 
@@ -391,29 +982,44 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                 let callee = self.pop()?;
 
                 match callee {
-                    Value::Int(func_idx) => {
-                        self.call_function(func_idx as usize, arg_count)?;
+                    Value::VmFunction(closure) => {
+                        self.call_closure(&closure, arg_count)?;
                     }
-                    _ => {
+                    other => {
                         return Err(VMError {
-                            message: "Cannot call non-function value".to_string(),
+                            message: format!("Cannot call non-function value {}", other),
+                            code: Some("WOKE-E0302"),
                         });
                     }
                 }
             }
 
+            OpCode::CallNative(name, arg_count) => {
+                self.call_native(&name, arg_count)?;
+            }
+
             OpCode::Return => {
                 let return_value = self.stack.pop().unwrap_or(Value::Unit);
                 let frame = self.call_stack.pop().unwrap();
+                if let Some(profiler) = &self.profiler {
+                    profiler.leave();
+                }
 
                 // Clean up locals
                 self.stack.truncate(frame.base_ptr);
                 self.stack.push(return_value);
             }
 
-            OpCode::MakeClosure(func_idx) => {
-                // For now, just push the function index as an integer
-                self.push(Value::Int(func_idx as i64))?;
+            OpCode::MakeClosure(func_idx, capture_count) => {
+                let split = self.stack.len().checked_sub(capture_count).ok_or_else(|| VMError {
+                    message: "Stack underflow".to_string(),
+                    code: None,
+                })?;
+                let captures = self.stack.split_off(split);
+                self.push(Value::VmFunction(VmClosure {
+                    function: func_idx,
+                    captures: captures.into(),
+                }))?;
             }
 
             OpCode::MakeArray(count) => {
@@ -422,7 +1028,7 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                     elements.push(self.pop()?);
                 }
                 elements.reverse();
-                self.push(Value::Array(elements))?;
+                self.push(Value::Array(elements.into()))?;
             }
 
             OpCode::MakeRecord(count) => {
@@ -430,9 +1036,10 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                 for _ in 0..count {
                     let value = self.pop()?;
                     let key = match self.pop()? {
-                        Value::String(s) => s,
+                        Value::String(s) => s.to_string(),
                         _ => return Err(VMError {
                             message: "Record keys must be strings".to_string(),
+                            code: Some("WOKE-E0302"),
                         }),
                     };
                     map.insert(key, value);
@@ -448,12 +1055,24 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                     (Value::Array(arr), Value::Int(i)) => {
                         arr.get(*i as usize).cloned().unwrap_or(Value::Unit)
                     }
-                    (Value::String(s), Value::Int(i)) => {
-                        s.chars()
-                            .nth(*i as usize)
-                            .map(|c| Value::String(c.to_string()))
-                            .unwrap_or(Value::Unit)
+                    (Value::Bytes(bytes), Value::Int(i)) => bytes
+                        .get(*i as usize)
+                        .map(|b| Value::Int(*b as i64))
+                        .unwrap_or(Value::Unit),
+                    // As the interpreter does, a string index that finds no
+                    // character is an error rather than Unit
+                    (Value::String(_), Value::Int(i)) if *i < 0 => {
+                        return Err(VMError {
+                            message: format!("Negative index not allowed: {}", i),
+                            code: Some("WOKE-E0307"),
+                        })
                     }
+                    (Value::String(s), Value::Int(i)) => string::nth_char(s, *i as usize)
+                        .map(|c| Value::String(c.to_string().into()).secret_if(s.is_secret()))
+                        .ok_or_else(|| VMError {
+                            message: format!("Index out of bounds: {}", i),
+                            code: Some("WOKE-E0306"),
+                        })?,
                     (Value::Record(map), Value::String(key)) => {
                         map.get(key.as_str()).cloned().unwrap_or(Value::Unit)
                     }
@@ -466,7 +1085,9 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                 let value = self.pop()?;
                 let len = match value {
                     Value::Array(arr) => arr.len(),
-                    Value::String(s) => s.len(),
+                    // Characters, as indexing counts them, not bytes
+                    Value::String(s) => s.chars().count(),
+                    Value::Bytes(bytes) => bytes.len(),
                     Value::Record(map) => map.len(),
                     _ => 0,
                 };
@@ -480,11 +1101,7 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
 
             OpCode::MakeOops => {
                 let value = self.pop()?;
-                let msg = match value {
-                    Value::String(s) => s,
-                    other => other.to_string(),
-                };
-                self.push(Value::Oops(msg))?;
+                self.push(Value::Oops(Box::new(value)))?;
             }
 
             OpCode::TryUnwrap => {
@@ -509,20 +1126,99 @@ fn execute_instruction(&mut self) -> Result<(), VMError> {
                 self.push(Value::Bool(is_okay))?;
             }
 
-            OpCode::Print => {
+            OpCode::IsOops => {
+                let value = self.peek()?;
+                let is_oops = matches!(value, Value::Oops(_));
+                self.push(Value::Bool(is_oops))?;
+            }
+
+            OpCode::ErrorValue => {
+                let value = self.pop()?;
+                let error = match value {
+                    Value::Oops(error) => *error,
+                    _ => Value::Unit,
+                };
+                self.push(error)?;
+            }
+
+            OpCode::Print(arg_count) => {
+                let split = self.stack.len().checked_sub(arg_count).ok_or_else(|| VMError {
+                    message: "Stack underflow".to_string(),
+                    code: None,
+                })?;
+                let line = self
+                    .stack
+                    .split_off(split)
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                self.write_line(&line)?;
+                self.push(Value::Unit)?;
+            }
+
+            OpCode::PrettyPrint => {
                 let value = self.pop()?;
-                println!("{}", value);
+                self.write_line(&value.pretty())?;
+                self.push(Value::Unit)?;
+            }
+
+            OpCode::Exit => {
+                let Value::Int(code) = self.pop()? else {
+                    return Err(VMError {
+                        message: "exit() requires an Int exit code".to_string(),
+                        code: Some("WOKE-E0302"),
+                    });
+                };
+                // Exiting gives back the code, as `main` giving it back does
+                self.exited = true;
+                self.call_stack.clear();
+                if let Some(profiler) = &self.profiler {
+                    profiler.unwind();
+                }
+                self.stack.clear();
+                self.push(Value::Int(code))?;
+            }
+
+            OpCode::Ask(answer) => {
+                let Value::String(prompt) = self.pop()? else {
+                    return Err(VMError {
+                        message: format!("{}() requires a string prompt", answer.builtin()),
+                        code: Some("WOKE-E0302"),
+                    });
+                };
+                let mut stdin;
+                let input: &mut dyn BufRead = match &mut self.input {
+                    Some(input) => input.as_mut(),
+                    None => {
+                        stdin = io::stdin().lock();
+                        &mut stdin
+                    }
+                };
+                let value = answer
+                    .ask(&prompt, input, &mut self.output)
+                    .map_err(|e| VMError::stdlib(answer.builtin(), e))?;
+                self.push(value)?;
+            }
+
+            OpCode::Secret => {
+                let value = self.pop()?;
+                self.push(value.into_secret())?;
             }
 
             OpCode::ToString => {
                 let value = self.pop()?;
-                self.push(Value::String(value.to_string()))?;
+                let secret = value.is_secret();
+                self.push(Value::String(value.to_string().into()).secret_if(secret))?;
             }
 
             OpCode::Nop => {}
 
             OpCode::Halt => {
                 self.call_stack.clear();
+                if let Some(profiler) = &self.profiler {
+                    profiler.unwind();
+                }
             }
         }
 
@@ -533,6 +1229,7 @@ fn push(&mut self, value: Value) -> Result<(), VMError> {
         if self.stack.len() >= self.max_stack_size {
             return Err(VMError {
                 message: "Stack overflow".to_string(),
+                code: Some("WOKE-E0309"),
             });
         }
         self.stack.push(value);
@@ -542,20 +1239,79 @@ fn push(&mut self, value: Value) -> Result<(), VMError> {
     fn pop(&mut self) -> Result<Value, VMError> {
         self.stack.pop().ok_or_else(|| VMError {
             message: "Stack underflow".to_string(),
+            code: None,
         })
     }
 
     fn peek(&self) -> Result<&Value, VMError> {
         self.stack.last().ok_or_else(|| VMError {
             message: "Stack underflow".to_string(),
+            code: None,
         })
     }
 }
 
+/// Mask the secret text of `args` in the audit entries and errors of a
+/// call given them, while the guard given back is held, as the interpreter
+/// does; `None` if they hold none, when what the call gives back is not
+/// secret either
+fn handling_secrets(args: &[Value]) -> Option<secrets::Handling> {
+    let texts: Vec<String> = args.iter().flat_map(Value::secret_texts).collect();
+    (!texts.is_empty()).then(|| secrets::handling(texts))
+}
+
+/// `a + b`, appending to `a` in place when it is a String no one else holds
+fn add(a: Value, b: Value) -> Result<Value, VMError> {
+    Ok(match (a, b) {
+        (Value::Int(x), Value::Int(y)) => Value::Int(x + y),
+        (Value::Float(x), Value::Float(y)) => Value::Float(x + y),
+        (Value::Int(x), Value::Float(y)) => Value::Float(x as f64 + y),
+        (Value::Float(x), Value::Int(y)) => Value::Float(x + y as f64),
+        (a, b) if decimal::involves(&a, &b) => decimal::add(&a, &b).map_err(VMError::type_error)?,
+        (Value::String(mut x), Value::String(y)) => {
+            x.make_mut().push_str(&y);
+            Value::String(x).secret_if(y.is_secret())
+        }
+        (Value::Bytes(mut x), Value::Bytes(y)) => {
+            x.extend(y);
+            Value::Bytes(x)
+        }
+        (
+            a @ (Value::Duration(_) | Value::DateTime(_)),
+            b @ (Value::Duration(_) | Value::DateTime(_)),
+        ) => duration::add(&a, &b).map_err(VMError::type_error)?,
+        (a, b) => return Err(VMError {
+            message: format!("Cannot add {:?} and {:?}", a, b),
+            code: Some("WOKE-E0302"),
+        }),
+    })
+}
+
 /// VM execution error
 #[derive(Debug, Clone)]
 pub struct VMError {
     pub message: String,
+    /// The error's code in the catalog, as the interpreter would give it;
+    /// `None` for faults in the VM itself, such as a bad snapshot
+    pub code: Option<&'static str>,
+}
+
+impl VMError {
+    fn type_error(message: String) -> Self {
+        VMError {
+            message,
+            code: Some("WOKE-E0302"),
+        }
+    }
+
+    /// An error from the standard library function `name`, with the code
+    /// the interpreter gives it
+    fn stdlib(name: &str, e: StdlibError) -> Self {
+        VMError {
+            message: secrets::redact(&format!("{}: {}", name, e)).into_owned(),
+            code: Some(stdlib_error(name, e).code()),
+        }
+    }
 }
 
 impl std::fmt::Display for VMError {
@@ -566,24 +1322,46 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 
 impl std::error::Error for VMError {}
 
+impl From<LimitExceeded> for VMError {
+    fn from(e: LimitExceeded) -> Self {
+        VMError {
+            message: e.to_string(),
+            code: Some(e.code()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::vm::compiler::BytecodeCompiler;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
+    use crate::pipeline::{Engine, Pipeline};
 
     fn run_source(source: &str) -> Result<Value, String> {
-        let lexer = Lexer::new(source);
-        let tokens = lexer.tokenize().map_err(|e| e.to_string())?;
-        let mut parser = Parser::new(tokens, source);
-        let program = parser.parse().map_err(|e| e.to_string())?;
-
-        let mut compiler = BytecodeCompiler::new();
-        let compiled = compiler.compile(&program).map_err(|e| e.to_string())?;
+        Pipeline::new()
+            .typecheck(false)
+            .engine(Engine::Vm)
+            .run(source)
+            .map_err(|e| e.to_string())
+    }
 
-        let mut vm = VirtualMachine::new(compiled);
-        vm.run().map_err(|e| e.to_string())
+    #[test]
+    fn test_vm_print() {
+        let source = r#"
+            to main() {
+                print("a", 1, true);
+                print();
+                remember nothing = print("x");
+                print(nothing);
+            }
+        "#;
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap(), source).parse().unwrap();
+        let compiled = BytecodeCompiler::new().compile(&program).unwrap();
+        let output = crate::interpreter::OutputBuffer::new();
+        VirtualMachine::new(compiled).with_output(output.clone()).run().unwrap();
+        assert_eq!(output.contents(), "a 1 true\n\nx\n()\n");
     }
 
     #[test]
@@ -645,6 +1423,277 @@ fn test_vm_loop() {
         assert_eq!(result, Value::Int(5));
     }
 
+    #[test]
+    fn test_vm_decide_result_patterns() {
+        let source = r#"
+            to check(r: Int) -> Int {
+                decide based on r {
+                    Okay(v) -> { give back v; }
+                    Oops(e) -> { give back len(e); }
+                }
+                give back 0;
+            }
+
+            to main() {
+                give back check(Okay(40)) + check(Oops("ok"));
+            }
+        "#;
+        let result = run_source(source).unwrap();
+        assert_eq!(result, Value::Int(42));
+    }
+
+    fn compile_source(source: &str) -> CompiledProgram {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        BytecodeCompiler::new().compile(&program).unwrap()
+    }
+
+    const COUNTING_PROGRAM: &str = r#"
+        to main() {
+            remember sum = 0;
+            repeat 50 times {
+                sum = sum + 2;
+            }
+            give back sum;
+        }
+    "#;
+
+    #[test]
+    fn test_vm_run_steps_pauses() {
+        let mut vm = VirtualMachine::new(compile_source(COUNTING_PROGRAM));
+        vm.start().unwrap();
+
+        assert_eq!(vm.run_steps(10).unwrap(), None);
+        assert!(vm.is_running());
+        assert_eq!(vm.resume().unwrap(), Value::Int(100));
+    }
+
+    #[test]
+    fn test_vm_snapshot_and_resume() {
+        let program = compile_source(COUNTING_PROGRAM);
+        let mut vm = VirtualMachine::new(program.clone());
+        vm.start().unwrap();
+        vm.run_steps(37).unwrap();
+
+        let path = std::env::temp_dir().join("wokelang_test_vm_snapshot.wvm");
+        vm.snapshot().save(&path).unwrap();
+        let restored = VmSnapshot::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let mut resumed = VirtualMachine::from_snapshot(program, restored).unwrap();
+        assert_eq!(resumed.resume().unwrap(), Value::Int(100));
+        // The original VM is unaffected by the snapshot
+        assert_eq!(vm.resume().unwrap(), Value::Int(100));
+    }
+
+    #[test]
+    fn test_vm_snapshot_rejects_other_program() {
+        let mut vm = VirtualMachine::new(compile_source(COUNTING_PROGRAM));
+        vm.start().unwrap();
+        vm.run_steps(5).unwrap();
+        let snapshot = vm.snapshot();
+
+        let other = compile_source("to main() { give back 1; }");
+        assert!(VirtualMachine::from_snapshot(other, snapshot).is_err());
+    }
+
+    #[test]
+    fn test_vm_array_module_callbacks() {
+        let source = r#"
+            use std.array;
+
+            to double(n: Int) -> Int {
+                give back n * 2;
+            }
+
+            to isBig(n: Int) -> Bool {
+                give back n > 4;
+            }
+
+            to add(acc: Int, n: Int) -> Int {
+                give back acc + n;
+            }
+
+            to main() {
+                remember doubled = array.map(std.array.range(1, 5), double);
+                remember big = array.filter(doubled, isBig);
+                give back array.reduce(big, add, 0) + len(array.sort([3, 1, 2]));
+            }
+        "#;
+        // [2, 4, 6, 8] -> [6, 8] -> 14, plus 3
+        assert_eq!(run_source(source).unwrap(), Value::Int(17));
+
+        // An Int is not a function, even one that numbers a compiled function
+        let err = run_source("to main() { give back std.array.map([1], 0); }").unwrap_err();
+        assert!(err.contains("std.array.map: expected a function, got 0"), "{}", err);
+        let err = run_source("to main() { remember f = 0; give back f(1); }").unwrap_err();
+        assert!(err.contains("Cannot call non-function value 0"), "{}", err);
+        let err = run_source(r#"to main() { give back std.array.map(1, "f"); }"#).unwrap_err();
+        assert!(err.contains("std.array.map"));
+    }
+
+    #[test]
+    fn test_vm_lambdas() {
+        let source = r#"
+            to main() -> String {
+                remember step = 10;
+                remember add = |n| -> n + step;
+                remember adder = |a| { give back |b| -> a + b; };
+                remember plusTwo = adder(2);
+                remember evens = std.array.filter([1, 2, 3, 4], |n| -> n % 2 == 0);
+                give back toString(std.array.map([1, 2], add)) + " " + toString(plusTwo(5)) + " " + toString(evens);
+            }
+        "#;
+        assert_eq!(run_source(source).unwrap(), Value::String("[11, 12] 7 [2, 4]".to_string().into()));
+    }
+
+    #[test]
+    fn test_vm_format() {
+        let source = r#"
+            to main() {
+                give back format("{}: {:06.2}", "total", 2.5);
+            }
+        "#;
+        assert_eq!(run_source(source).unwrap(), Value::String("total: 002.50".to_string().into()));
+        assert!(run_source(r#"to main() { give back format("{"); }"#).is_err());
+    }
+
+    #[test]
+    fn test_vm_superpowers() {
+        let source = r#"
+            to main() {
+                renounce("crypto");
+                give back hasSuperpower("crypto");
+            }
+        "#;
+        assert_eq!(run_source(source).unwrap(), Value::Bool(false));
+    }
+
+    #[test]
+    fn test_vm_gratitude_entries() {
+        let source = r#"
+            to main() {
+                give back gratitudeEntries();
+            }
+            thanks to {
+                "Ada Lovelace" → "the first program";
+                "Grace Hopper" → "the compiler";
+            }
+        "#;
+        assert_eq!(
+            run_source(source).unwrap(),
+            Value::gratitude([("Ada Lovelace", "the first program"), ("Grace Hopper", "the compiler")])
+        );
+        assert_eq!(run_source("to main() { give back gratitudeEntries(); }").unwrap(), Value::gratitude([]));
+        assert!(run_source("to main() { give back gratitudeEntries(1); }").is_err());
+    }
+
+    #[test]
+    fn test_vm_pretty_print() {
+        assert_eq!(run_source("to main() -> Int { prettyPrint([1, [2]]); give back 1; }").unwrap(), Value::Int(1));
+        assert!(run_source("to main() { prettyPrint(); }").is_err());
+    }
+
+    #[test]
+    fn test_vm_limit_pragmas() {
+        let spin = "#timeout 20ms;\nto main() { remember n = 0; repeat 100000000 times { n = n + 1; } }";
+        assert_eq!(run_source(spin).unwrap_err(), "Runtime error [WOKE-E0313]: Timed out after 20ms");
+
+        let grow = r#"#maxmemory 1mb; to main() { remember s = "x"; repeat 30 times { s = s + s; } }"#;
+        assert_eq!(run_source(grow).unwrap_err(), "Runtime error [WOKE-E0314]: Memory limit of 1mb exceeded");
+        assert!(run_source(&grow.replace("30", "10")).is_ok());
+
+        // The limits are kept with the bytecode, for the build cache
+        let tokens = Lexer::new(grow).tokenize().unwrap();
+        let program = Parser::new(tokens, grow).parse().unwrap();
+        let compiled = BytecodeCompiler::new().compile(&program).unwrap();
+        let json = serde_json::to_string(&compiled).unwrap();
+        let cached: CompiledProgram = serde_json::from_str(&json).unwrap();
+        assert_eq!(cached.limits.max_memory, Some(1 << 20));
+    }
+
+    #[test]
+    fn test_vm_capabilities_and_sandbox() {
+        let source = r#"
+            to main() {
+                give back [hasSuperpower("network:example.com"), hasSuperpower("env:HOME")];
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let compiled = BytecodeCompiler::new().compile(&program).unwrap();
+
+        let caps = || {
+            let mut caps = CapabilityRegistry::new();
+            caps.set_interactive(false);
+            caps.grant("*", crate::security::Capability::Network(None), "test");
+            caps.grant("*", crate::security::Capability::Environment(None), "test");
+            caps
+        };
+        let mut vm = VirtualMachine::new(compiled.clone()).with_capabilities(caps());
+        assert_eq!(vm.run().unwrap(), Value::Array(vec![Value::Bool(true), Value::Bool(true)].into()));
+
+        let mut vm = VirtualMachine::new(compiled)
+            .with_capabilities(caps())
+            .with_sandbox(&std::env::temp_dir());
+        assert_eq!(vm.run().unwrap(), Value::Array(vec![Value::Bool(false), Value::Bool(false)].into()));
+    }
+
+    #[test]
+    fn test_vm_native_functions() {
+        let source = r#"
+            to main() {
+                remember scaled = std.array.map([1, 2, 3], scale);
+                give back greet("Ada") + " " + toString(scaled);
+            }
+        "#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let compiled = BytecodeCompiler::new().compile(&program).unwrap();
+
+        let mut caps = CapabilityRegistry::new();
+        caps.set_interactive(false);
+        caps.set_default_consent(false);
+        let mut vm = VirtualMachine::new(compiled.clone()).with_capabilities(caps);
+        vm.register_native("greet", 1, |args| Ok(Value::String(format!("Hello, {}!", args[0]).into())));
+        vm.register_native("scale", 1, |args| match &args[0] {
+            Value::Int(n) => Ok(Value::Int(n * 10)),
+            other => Err(StdlibError::TypeError { expected: "Int".to_string(), got: other.to_string() }),
+        });
+        assert_eq!(vm.run().unwrap(), Value::String("Hello, Ada! [10, 20, 30]".to_string().into()));
+
+        // Superpowers a host function is tagged with are asked for first
+        let mut vm = VirtualMachine::new(compiled.clone()).with_capabilities(CapabilityRegistry::new());
+        vm.capabilities_mut().set_interactive(false);
+        vm.capabilities_mut().set_default_consent(false);
+        vm.register_native("scale", 1, |args| Ok(args[0].clone()));
+        vm.register_native("greet", 1, |_| Ok(Value::Unit))
+            .requires(crate::security::Capability::Notify);
+        assert_eq!(vm.run().unwrap_err().message, "greet: Permission denied: notify");
+
+        // Functions bound with a signature get their arguments converted
+        let mut vm = VirtualMachine::new(compiled);
+        vm.bind("to scale(n: Int) → Int", |n: i64| n * 2).unwrap();
+        vm.bind("to greet(name: Int) → String", |name: i64| format!("#{}", name)).unwrap();
+        assert_eq!(vm.run().unwrap_err().message, "greet: Expected Int for name, got Ada");
+    }
+
+    #[test]
+    fn test_vm_durations() {
+        let source = r#"
+            to main() {
+                remember start = std.time.dateTime(2024, 3, 1, 12, 0, 0, "UTC");
+                remember later = start + 90 measured in minutes;
+                remember wait = (later - start) * 2 - 30 measured in seconds;
+                when wait > 2 measured in hours {
+                    give back std.time.toMillis(wait);
+                }
+                give back 0;
+            }
+        "#;
+        assert_eq!(run_source(source).unwrap(), Value::Int(10_770_000));
+    }
+
     #[test]
     fn test_vm_recursion() {
         let source = r#"
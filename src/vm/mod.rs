@@ -6,58 +6,32 @@
 pub mod compiler;
 pub mod machine;
 pub mod optimizer;
+pub mod snapshot;
 
 pub use bytecode::{CompiledFunction, CompiledProgram, OpCode};
 pub use compiler::{BytecodeCompiler, CompileError};
 pub use machine::{VirtualMachine, VMError};
 pub use optimizer::Optimizer;
+pub use snapshot::{FrameSnapshot, VmSnapshot};
 
 use crate::interpreter::Value;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
+use crate::pipeline::{Engine, Pipeline};
 
 /// Compile and run WokeLang source code using the VM
 pub fn run_vm(source: &str) -> Result<Value, String> {
-    // Lex
-    let lexer = Lexer::new(source);
-    let tokens = lexer.tokenize().map_err(|e| format!("Lexer error: {}", e))?;
-
-    // Parse
-    let mut parser = Parser::new(tokens, source);
-    let program = parser.parse().map_err(|e| format!("Parse error: {}", e))?;
-
-    // Compile to bytecode
-    let mut compiler = BytecodeCompiler::new();
-    let mut compiled = compiler
-        .compile(&program)
-        .map_err(|e| format!("Compile error: {}", e))?;
-
-    // Optimize
-    let optimizer = Optimizer::new();
-    optimizer.optimize(&mut compiled);
-
-    // Execute
-    let mut vm = VirtualMachine::new(compiled);
-    vm.run().map_err(|e| format!("VM error: {}", e))
+    Pipeline::new()
+        .typecheck(false)
+        .engine(Engine::Vm)
+        .optimize(true)
+        .run(source)
+        .map_err(|e| e.to_string())
 }
 
 /// Compile WokeLang source to bytecode (without running)
 pub fn compile(source: &str) -> Result<CompiledProgram, String> {
-    let lexer = Lexer::new(source);
-    let tokens = lexer.tokenize().map_err(|e| format!("Lexer error: {}", e))?;
-
-    let mut parser = Parser::new(tokens, source);
-    let program = parser.parse().map_err(|e| format!("Parse error: {}", e))?;
-
-    let mut compiler = BytecodeCompiler::new();
-    let mut compiled = compiler
-        .compile(&program)
-        .map_err(|e| format!("Compile error: {}", e))?;
-
-    let optimizer = Optimizer::new();
-    optimizer.optimize(&mut compiled);
-
-    Ok(compiled)
+    let pipeline = Pipeline::new().optimize(true);
+    let program = pipeline.parse(source).map_err(|e| e.to_string())?;
+    pipeline.compile(&program).map_err(|e| e.to_string())
 }
 
 /// Disassemble bytecode for debugging
@@ -65,24 +39,7 @@ pub fn disassemble(program: &CompiledProgram) -> String {
     let mut output = String::new();
 
     for (func_idx, func) in program.functions.iter().enumerate() {
-        output.push_str(&format!(
-            "\n=== Function {}: {} (arity: {}, locals: {}) ===\n",
-            func_idx, func.name, func.arity, func.locals
-        ));
-
-        // Constants
-        if !func.constants.is_empty() {
-            output.push_str("Constants:\n");
-            for (i, c) in func.constants.iter().enumerate() {
-                output.push_str(&format!("  {}: {:?}\n", i, c));
-            }
-        }
-
-        // Instructions
-        output.push_str("Code:\n");
-        for (i, op) in func.code.iter().enumerate() {
-            output.push_str(&format!("  {:04}: {:?}\n", i, op));
-        }
+        output.push_str(&disassemble_function(func_idx, func));
     }
 
     if let Some(entry) = program.entry {
@@ -92,6 +49,29 @@ pub fn disassemble(program: &CompiledProgram) -> String {
     output
 }
 
+/// Disassemble one function, function `index` of its program
+pub fn disassemble_function(index: usize, func: &CompiledFunction) -> String {
+    let mut output = format!(
+        "\n=== Function {}: {} (arity: {}, locals: {}) ===\n",
+        index, func.name, func.arity, func.locals
+    );
+
+    // Constants
+    if !func.constants.is_empty() {
+        output.push_str("Constants:\n");
+        for (i, c) in func.constants.iter().enumerate() {
+            output.push_str(&format!("  {}: {:?}\n", i, c));
+        }
+    }
+
+    // Instructions
+    output.push_str("Code:\n");
+    for (i, op) in func.code.iter().enumerate() {
+        output.push_str(&format!("  {:04}: {:?}\n", i, op));
+    }
+    output
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
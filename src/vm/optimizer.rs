@@ -3,6 +3,7 @@
 //! Optimization passes for improving bytecode performance.
 
 use crate::interpreter::Value;
+use crate::stdlib::decimal;
 use super::bytecode::{CompiledFunction, CompiledProgram, OpCode};
 
 /// Optimizer for bytecode programs
@@ -56,6 +57,8 @@ fn fold_constants(&self, func: &mut CompiledFunction) {
                         OpCode::Sub => self.fold_sub(&a, &b),
                         OpCode::Mul => self.fold_mul(&a, &b),
                         OpCode::Div => self.fold_div(&a, &b),
+                        // Decimals compare with Ints by value, as the VM does
+                        OpCode::Eq | OpCode::Ne if decimal::involves(&a, &b) => None,
                         OpCode::Eq => Some(Value::Bool(a == b)),
                         OpCode::Ne => Some(Value::Bool(a != b)),
                         OpCode::Lt => self.fold_lt(&a, &b),
@@ -89,7 +92,7 @@ fn fold_add(&self, a: &Value, b: &Value) -> Option<Value> {
             (Value::Float(x), Value::Float(y)) => Some(Value::Float(x + y)),
             (Value::Int(x), Value::Float(y)) => Some(Value::Float(*x as f64 + y)),
             (Value::Float(x), Value::Int(y)) => Some(Value::Float(x + *y as f64)),
-            (Value::String(x), Value::String(y)) => Some(Value::String(format!("{}{}", x, y))),
+            (Value::String(x), Value::String(y)) => Some(Value::String(format!("{}{}", x, y).into())),
             _ => None,
         }
     }
@@ -276,16 +279,14 @@ fn remove_nops(&self, func: &mut CompiledFunction) {
         // Update jump targets
         for op in &mut func.code {
             match op {
-                OpCode::Jump(ref mut target) => {
-                    if *target < new_indices.len() {
+                OpCode::Jump(ref mut target)
+                    if *target < new_indices.len() => {
                         *target = new_indices[*target];
                     }
-                }
-                OpCode::JumpIfFalse(ref mut target) | OpCode::JumpIfTrue(ref mut target) => {
-                    if *target < new_indices.len() {
+                OpCode::JumpIfFalse(ref mut target) | OpCode::JumpIfTrue(ref mut target)
+                    if *target < new_indices.len() => {
                         *target = new_indices[*target];
                     }
-                }
                 _ => {}
             }
         }
@@ -360,7 +361,7 @@ fn test_dead_code_elimination() {
         func.emit(OpCode::Const(c1));
         func.emit(OpCode::Return);
         func.emit(OpCode::Const(c1)); // Dead code
-        func.emit(OpCode::Print); // Dead code
+        func.emit(OpCode::Print(1)); // Dead code
 
         let mut program = CompiledProgram::new();
         program.add_function(func);
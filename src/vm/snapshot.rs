@@ -0,0 +1,554 @@
+//! WokeLang VM Snapshots
+//!
+//! Captures the execution state of a paused VM (value stack, call frames and
+//! globals) so it can be written to disk and resumed later.
+//!
+//! The on-disk format is a whitespace-separated token stream. Strings are
+//! length-prefixed (`<bytes>:<data>`) so they may contain any characters,
+//! including whitespace and newlines.
+
+use crate::interpreter::{Text, Value, VmClosure};
+use super::machine::VMError;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Magic header identifying a snapshot file
+const SNAPSHOT_MAGIC: &str = "wokevm";
+
+/// Snapshot format version
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// How deeply values may nest inside one another, so that reading a
+/// snapshot cannot run out of stack; deeper values are not written either
+const MAX_DEPTH: usize = 256;
+
+/// A single saved call frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSnapshot {
+    /// Function being executed
+    pub function_idx: usize,
+    /// Instruction pointer within the function
+    pub ip: usize,
+    /// Base pointer for local variables in the stack
+    pub base_ptr: usize,
+}
+
+/// Saved execution state of a paused virtual machine
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmSnapshot {
+    /// Fingerprint of the program the snapshot was taken from
+    pub program_fingerprint: u64,
+    /// Value stack
+    pub stack: Vec<Value>,
+    /// Call stack, outermost frame first
+    pub frames: Vec<FrameSnapshot>,
+    /// Global variables
+    pub globals: HashMap<String, Value>,
+}
+
+impl VmSnapshot {
+    /// Serialize the snapshot to its textual form
+    pub fn encode(&self) -> Result<String, VMError> {
+        let mut out = format!("{} {}\n", SNAPSHOT_MAGIC, SNAPSHOT_VERSION);
+        out.push_str(&format!("program {:016x}\n", self.program_fingerprint));
+
+        out.push_str(&format!("frames {}", self.frames.len()));
+        for frame in &self.frames {
+            out.push_str(&format!(" {} {} {}", frame.function_idx, frame.ip, frame.base_ptr));
+        }
+        out.push('\n');
+
+        out.push_str(&format!("stack {}", self.stack.len()));
+        for value in &self.stack {
+            out.push(' ');
+            encode_value(value, &mut out)?;
+        }
+        out.push('\n');
+
+        // Sort globals so identical states produce identical files
+        let mut names: Vec<&String> = self.globals.keys().collect();
+        names.sort();
+        out.push_str(&format!("globals {}", names.len()));
+        for name in names {
+            out.push(' ');
+            encode_str(name, &mut out);
+            out.push(' ');
+            encode_value(&self.globals[name], &mut out)?;
+        }
+        out.push('\n');
+
+        Ok(out)
+    }
+
+    /// Parse a snapshot from its textual form
+    pub fn decode(input: &str) -> Result<Self, VMError> {
+        let mut reader = Reader::new(input);
+
+        reader.expect_word(SNAPSHOT_MAGIC)?;
+        let version: u32 = reader.number()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(snapshot_error(format!(
+                "unsupported snapshot version {} (expected {})",
+                version, SNAPSHOT_VERSION
+            )));
+        }
+
+        reader.expect_word("program")?;
+        let fingerprint = u64::from_str_radix(reader.word()?, 16)
+            .map_err(|_| snapshot_error("invalid program fingerprint"))?;
+
+        reader.expect_word("frames")?;
+        let frame_count: usize = reader.number()?;
+        let mut frames = Vec::with_capacity(frame_count.min(1024));
+        for _ in 0..frame_count {
+            frames.push(FrameSnapshot {
+                function_idx: reader.number()?,
+                ip: reader.number()?,
+                base_ptr: reader.number()?,
+            });
+        }
+
+        reader.expect_word("stack")?;
+        let stack_len: usize = reader.number()?;
+        let mut stack = Vec::with_capacity(stack_len.min(1024));
+        for _ in 0..stack_len {
+            stack.push(reader.value()?);
+        }
+
+        reader.expect_word("globals")?;
+        let global_count: usize = reader.number()?;
+        let mut globals = HashMap::new();
+        for _ in 0..global_count {
+            let name = reader.string()?;
+            let value = reader.value()?;
+            globals.insert(name, value);
+        }
+
+        Ok(Self {
+            program_fingerprint: fingerprint,
+            stack,
+            frames,
+            globals,
+        })
+    }
+
+    /// Write the snapshot to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), VMError> {
+        let encoded = self.encode()?;
+        fs::write(path.as_ref(), encoded).map_err(|e| {
+            snapshot_error(format!("failed to write {}: {}", path.as_ref().display(), e))
+        })
+    }
+
+    /// Read a snapshot from a file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, VMError> {
+        let contents = fs::read_to_string(path.as_ref()).map_err(|e| {
+            snapshot_error(format!("failed to read {}: {}", path.as_ref().display(), e))
+        })?;
+        Self::decode(&contents)
+    }
+}
+
+/// Values written as a snapshot writes them, for compiled programs to keep
+/// their constants in: `#[serde(with = "snapshot::values")]`
+pub(crate) mod values {
+    use super::{encode_value, Reader, Value};
+    use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(values: &[Value], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = values.len().to_string();
+        for value in values {
+            out.push(' ');
+            encode_value(value, &mut out).map_err(S::Error::custom)?;
+        }
+        serializer.serialize_str(&out)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Value>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let mut reader = Reader::new(&text);
+        let count: usize = reader.number().map_err(D::Error::custom)?;
+        (0..count).map(|_| reader.value().map_err(D::Error::custom)).collect()
+    }
+}
+
+/// Named values written as a snapshot writes its globals
+pub(crate) mod value_map {
+    use super::{encode_str, encode_value, Reader, VMError, Value};
+    use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serializer};
+    use std::collections::HashMap;
+
+    pub fn serialize<S: Serializer>(values: &HashMap<String, Value>, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut names: Vec<&String> = values.keys().collect();
+        names.sort();
+        let mut out = names.len().to_string();
+        for name in names {
+            out.push(' ');
+            encode_str(name, &mut out);
+            out.push(' ');
+            encode_value(&values[name], &mut out).map_err(S::Error::custom)?;
+        }
+        serializer.serialize_str(&out)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, Value>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let mut reader = Reader::new(&text);
+        let count: usize = reader.number().map_err(D::Error::custom)?;
+        (0..count)
+            .map(|_| Ok((reader.string()?, reader.value()?)))
+            .collect::<Result<_, VMError>>()
+            .map_err(D::Error::custom)
+    }
+}
+
+fn snapshot_error(message: impl Into<String>) -> VMError {
+    VMError {
+        message: format!("Snapshot error: {}", message.into()),
+        code: None,
+    }
+}
+
+fn encode_str(s: &str, out: &mut String) {
+    out.push_str(&format!("{}:{}", s.len(), s));
+}
+
+fn encode_value(value: &Value, out: &mut String) -> Result<(), VMError> {
+    encode_nested(value, out, 0)
+}
+
+fn encode_nested(value: &Value, out: &mut String, depth: usize) -> Result<(), VMError> {
+    if depth > MAX_DEPTH {
+        return Err(snapshot_error(format!("values nest more than {} deep", MAX_DEPTH)));
+    }
+    let encode_value = |value, out: &mut String| encode_nested(value, out, depth + 1);
+    match value {
+        Value::Unit => out.push('u'),
+        Value::Int(n) => out.push_str(&format!("i{}", n)),
+        // Store the raw bits so floats round-trip exactly
+        Value::Float(f) => out.push_str(&format!("f{:016x}", f.to_bits())),
+        // As written, so the places are kept
+        Value::Decimal(d) => out.push_str(&format!("m{}", d)),
+        Value::Bool(b) => out.push_str(if *b { "b1" } else { "b0" }),
+        // Secret text stays secret once resumed
+        Value::String(s) => {
+            out.push(if s.is_secret() { 'S' } else { 's' });
+            encode_str(s, out);
+        }
+        Value::Bytes(bytes) => {
+            out.push('y');
+            for byte in bytes {
+                out.push_str(&format!("{:02x}", byte));
+            }
+        }
+        Value::DateTime(_) => out.push_str(&format!("d{}", value)),
+        // Whole seconds and the signed nanosecond remainder
+        Value::Duration(d) => {
+            let secs = d.num_seconds();
+            let nanos = (*d - chrono::TimeDelta::seconds(secs)).num_nanoseconds().unwrap_or(0);
+            out.push_str(&format!("n{}:{}", secs, nanos));
+        }
+        Value::Array(items) => {
+            out.push_str(&format!("a{}", items.len()));
+            for item in items.iter() {
+                out.push(' ');
+                encode_value(item, out)?;
+            }
+        }
+        Value::Record(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            out.push_str(&format!("r{}", keys.len()));
+            for key in keys {
+                out.push(' ');
+                encode_str(key, out);
+                out.push(' ');
+                encode_value(&fields[key], out)?;
+            }
+        }
+        Value::Okay(inner) => {
+            out.push_str("k ");
+            encode_value(inner, out)?;
+        }
+        // A message keeps the form it had before errors could be any value
+        Value::Oops(error) => match &**error {
+            Value::String(message) if !message.is_secret() => {
+                out.push('o');
+                encode_str(message, out);
+            }
+            other => {
+                out.push_str("e ");
+                encode_value(other, out)?;
+            }
+        },
+        // The function's index, then the values it captured
+        Value::VmFunction(closure) => {
+            out.push_str(&format!("c{}:{}", closure.function, closure.captures.len()));
+            for capture in closure.captures.iter() {
+                out.push(' ');
+                encode_value(capture, out)?;
+            }
+        }
+        Value::Function(_) => return Err(snapshot_error("cannot snapshot a closure value")),
+        Value::Channel(_) => return Err(snapshot_error("cannot snapshot a channel value")),
+    }
+    Ok(())
+}
+
+/// Cursor over the textual snapshot format
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+    /// How many values the one being read is inside
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0, depth: 0 }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Read the next whitespace-delimited token
+    fn word(&mut self) -> Result<&'a str, VMError> {
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return Err(snapshot_error("unexpected end of snapshot"));
+        }
+        self.pos += end;
+        Ok(&rest[..end])
+    }
+
+    fn expect_word(&mut self, expected: &str) -> Result<(), VMError> {
+        let word = self.word()?;
+        if word != expected {
+            return Err(snapshot_error(format!("expected '{}', found '{}'", expected, word)));
+        }
+        Ok(())
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, VMError> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| snapshot_error(format!("invalid number '{}'", word)))
+    }
+
+    /// Read a length-prefixed string (`<len>:<data>`)
+    fn string(&mut self) -> Result<String, VMError> {
+        self.skip_whitespace();
+        self.raw_string()
+    }
+
+    fn raw_string(&mut self) -> Result<String, VMError> {
+        let rest = &self.input[self.pos..];
+        let colon = rest
+            .find(':')
+            .ok_or_else(|| snapshot_error("missing string length"))?;
+        let len: usize = rest[..colon]
+            .parse()
+            .map_err(|_| snapshot_error("invalid string length"))?;
+        let start = colon + 1;
+        let end = start.checked_add(len).ok_or_else(|| snapshot_error("invalid string length"))?;
+        let data = rest.get(start..end).ok_or_else(|| snapshot_error("truncated string"))?;
+        self.pos += end;
+        Ok(data.to_string())
+    }
+
+    /// Read a value inside the one being read
+    fn inner_value(&mut self) -> Result<Value, VMError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(snapshot_error(format!("values nest more than {} deep", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let value = self.value();
+        self.depth -= 1;
+        value
+    }
+
+    fn value(&mut self) -> Result<Value, VMError> {
+        self.skip_whitespace();
+        let tag = self.input[self.pos..]
+            .chars()
+            .next()
+            .ok_or_else(|| snapshot_error("unexpected end of snapshot"))?;
+
+        match tag {
+            's' => {
+                self.pos += 1;
+                Ok(Value::String(self.raw_string()?.into()))
+            }
+            'S' => {
+                self.pos += 1;
+                Ok(Value::String(Text::secret(self.raw_string()?)))
+            }
+            'o' => {
+                self.pos += 1;
+                Ok(Value::oops(self.raw_string()?))
+            }
+            _ => {
+                let word = self.word()?;
+                let body = &word[tag.len_utf8()..];
+                match tag {
+                    'u' if body.is_empty() => Ok(Value::Unit),
+                    'i' => body
+                        .parse()
+                        .map(Value::Int)
+                        .map_err(|_| snapshot_error(format!("invalid integer '{}'", word))),
+                    'f' => u64::from_str_radix(body, 16)
+                        .map(|bits| Value::Float(f64::from_bits(bits)))
+                        .map_err(|_| snapshot_error(format!("invalid float '{}'", word))),
+                    'b' => match body {
+                        "1" => Ok(Value::Bool(true)),
+                        "0" => Ok(Value::Bool(false)),
+                        _ => Err(snapshot_error(format!("invalid bool '{}'", word))),
+                    },
+                    'y' if body.len() % 2 == 0 => (0..body.len())
+                        .step_by(2)
+                        .map(|i| body.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+                        .collect::<Option<Vec<_>>>()
+                        .map(Value::Bytes)
+                        .ok_or_else(|| snapshot_error(format!("invalid bytes '{}'", word))),
+                    'm' => body
+                        .parse()
+                        .map(Value::Decimal)
+                        .map_err(|_| snapshot_error(format!("invalid decimal '{}'", word))),
+                    'd' => chrono::DateTime::parse_from_rfc3339(body)
+                        .map(Value::DateTime)
+                        .map_err(|_| snapshot_error(format!("invalid datetime '{}'", word))),
+                    'n' => body
+                        .split_once(':')
+                        .and_then(|(secs, nanos)| {
+                            let secs = chrono::TimeDelta::try_seconds(secs.parse().ok()?)?;
+                            secs.checked_add(&chrono::TimeDelta::nanoseconds(nanos.parse().ok()?))
+                        })
+                        .map(Value::Duration)
+                        .ok_or_else(|| snapshot_error(format!("invalid duration '{}'", word))),
+                    'k' if body.is_empty() => Ok(Value::Okay(Box::new(self.inner_value()?))),
+                    'e' if body.is_empty() => Ok(Value::Oops(Box::new(self.inner_value()?))),
+                    'a' => {
+                        let len: usize = body
+                            .parse()
+                            .map_err(|_| snapshot_error(format!("invalid array '{}'", word)))?;
+                        let mut items = Vec::with_capacity(len.min(1024));
+                        for _ in 0..len {
+                            items.push(self.inner_value()?);
+                        }
+                        Ok(Value::Array(items.into()))
+                    }
+                    'r' => {
+                        let len: usize = body
+                            .parse()
+                            .map_err(|_| snapshot_error(format!("invalid record '{}'", word)))?;
+                        let mut fields = HashMap::new();
+                        for _ in 0..len {
+                            let key = self.string()?;
+                            fields.insert(key, self.inner_value()?);
+                        }
+                        Ok(Value::Record(fields))
+                    }
+                    'c' => {
+                        let (function, count) = body
+                            .split_once(':')
+                            .and_then(|(function, count)| Some((function.parse().ok()?, count.parse().ok()?)))
+                            .ok_or_else(|| snapshot_error(format!("invalid function '{}'", word)))?;
+                        let captures = (0..count).map(|_| self.inner_value()).collect::<Result<Vec<_>, _>>()?;
+                        Ok(Value::VmFunction(VmClosure {
+                            function,
+                            captures: captures.into(),
+                        }))
+                    }
+                    _ => Err(snapshot_error(format!("unknown value tag in '{}'", word))),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_round_trip() {
+        let mut record = HashMap::new();
+        record.insert("name with space".to_string(), Value::String("a\nb".to_string().into()));
+        record.insert("ratio".to_string(), Value::Float(0.1));
+
+        let snapshot = VmSnapshot {
+            program_fingerprint: 0xdead_beef,
+            stack: vec![
+                Value::Unit,
+                Value::Int(-7),
+                Value::Bool(true),
+                Value::Array(vec![Value::Int(1), Value::String("two 2".to_string().into())].into()),
+                Value::Record(record),
+                Value::Okay(Box::new(Value::Int(3))),
+                Value::oops("bad: thing"),
+                Value::Oops(Box::new(Value::Array(vec![Value::Int(404), Value::oops("nested")].into()))),
+                Value::Bytes(vec![0, 0x7f, 0xff]),
+                Value::Bytes(Vec::new()),
+                Value::Decimal("-12.50".parse().unwrap()),
+                Value::DateTime(
+                    chrono::DateTime::parse_from_rfc3339("2024-02-29T23:59:58.5+05:30").unwrap(),
+                ),
+                Value::Duration(chrono::TimeDelta::milliseconds(-1500)),
+                Value::Duration(chrono::TimeDelta::days(3) + chrono::TimeDelta::nanoseconds(7)),
+            ],
+            frames: vec![FrameSnapshot {
+                function_idx: 1,
+                ip: 4,
+                base_ptr: 0,
+            }],
+            globals: HashMap::from([("g".to_string(), Value::Float(-2.5))]),
+        };
+
+        let encoded = snapshot.encode().unwrap();
+        assert_eq!(VmSnapshot::decode(&encoded).unwrap(), snapshot);
+
+        let secrets = VmSnapshot {
+            stack: vec![Value::String(Text::secret("key")), Value::oops("bad key").into_secret()],
+            ..snapshot
+        };
+        let decoded = VmSnapshot::decode(&secrets.encode().unwrap()).unwrap();
+        assert_eq!(decoded, secrets);
+        assert!(decoded.stack.iter().all(Value::is_secret));
+    }
+
+    #[test]
+    fn test_rejects_malformed_values() {
+        let stack = |values: &str| VmSnapshot::decode(&format!("wokevm 1 program 0 frames 0 stack 1 {} globals 0", values));
+        assert!(stack("i1").is_ok());
+        // Rejected rather than panicking
+        assert!(stack("é1").is_err());
+        assert!(stack("yaé0").is_err());
+        assert!(stack(&format!("s{}:x", usize::MAX)).is_err());
+        assert!(stack(&format!("{}i1", "k ".repeat(100_000))).is_err());
+        assert!(stack(&format!("{}i1", "a1 ".repeat(100_000))).is_err());
+        assert!(stack(&format!("{}i1", "c0:1 ".repeat(100_000))).is_err());
+
+        // Nesting within the limit reads back, and deeper is not written
+        assert!(stack(&format!("{}i1", "k ".repeat(MAX_DEPTH))).is_ok());
+        let deep = (0..=MAX_DEPTH).fold(Value::Int(1), |value, _| Value::Okay(Box::new(value)));
+        let snapshot = VmSnapshot {
+            program_fingerprint: 0,
+            stack: vec![deep],
+            frames: Vec::new(),
+            globals: HashMap::new(),
+        };
+        assert!(snapshot.encode().is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_header() {
+        assert!(VmSnapshot::decode("notvm 1").is_err());
+        assert!(VmSnapshot::decode("wokevm 99").is_err());
+        assert!(VmSnapshot::decode("").is_err());
+    }
+}
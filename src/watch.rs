@@ -0,0 +1,142 @@
+//! Watch Mode for WokeLang
+//!
+//! `woke run --watch` and `woke check --watch` go again whenever the
+//! program, a module it imports from a file, its policy file or its
+//! project's manifest changes.
+//! Files are polled for their modification times, which needs nothing from
+//! the platform and notices editors that save by replacing the file.
+
+use crate::project::{self, MANIFEST_FILE};
+use crate::security::policy::POLICY_FILE;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often watched files are looked at
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The files runs of a program depend on: the program itself, the module
+/// files it was loaded with, its policy file, which may not exist yet, and
+/// the manifest of the project it is in
+pub fn watched_files(file: &Path, modules: &[PathBuf]) -> Vec<PathBuf> {
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let mut files = vec![file.to_path_buf()];
+    for module in modules {
+        if !files.contains(module) {
+            files.push(module.clone());
+        }
+    }
+    files.push(dir.join(POLICY_FILE));
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    if let Some(root) = project::find_root(dir) {
+        files.push(root.join(MANIFEST_FILE));
+    }
+    files
+}
+
+/// Notices when files change
+#[derive(Debug, Clone, Default)]
+pub struct Watcher {
+    /// When each file was last modified, or `None` if it does not exist
+    stamps: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Watcher {
+    /// Watch `files` for changes from how they are now
+    pub fn new(files: impl IntoIterator<Item = PathBuf>) -> Self {
+        let stamps = files.into_iter().map(|file| (file.clone(), modified(&file))).collect();
+        Self { stamps }
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.stamps.keys().map(PathBuf::as_path)
+    }
+
+    /// The files changed, created or removed since they were last looked at
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (file, stamp) in &mut self.stamps {
+            let now = modified(file);
+            if now != *stamp {
+                *stamp = now;
+                changed.push(file.clone());
+            }
+        }
+        changed
+    }
+
+    /// Wait for files to change, returning them. Changes that follow
+    /// within `interval` are taken with the first, so a save that writes
+    /// several times runs the program once.
+    pub fn wait(&mut self, interval: Duration) -> Vec<PathBuf> {
+        loop {
+            let mut changed = self.changed();
+            if !changed.is_empty() {
+                loop {
+                    thread::sleep(interval);
+                    let more = self.changed();
+                    if more.is_empty() {
+                        return changed;
+                    }
+                    for file in more {
+                        if !changed.contains(&file) {
+                            changed.push(file);
+                        }
+                    }
+                }
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+fn modified(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+/// Clear the terminal and move the cursor to its top left
+pub fn clear_screen() {
+    print!("\x1b[2J\x1b[H");
+    let _ = io::stdout().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::project::ModuleLoader;
+
+    #[test]
+    fn test_watch_files() {
+        let dir = std::env::temp_dir().join("wokelang_test_watch");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("shapes")).unwrap();
+        let main = dir.join("main.woke");
+        let source = "use std.math;\nuse shapes.circle;\nuse missing.module;\n";
+        fs::write(&main, source).unwrap();
+        fs::write(dir.join("shapes/circle.woke"), "").unwrap();
+
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens, source).parse().unwrap();
+        let mut loader = ModuleLoader::new();
+        loader.load(&main, program).unwrap();
+        let circle = fs::canonicalize(dir.join("shapes/circle.woke")).unwrap();
+        let files = watched_files(&main, loader.files());
+        assert_eq!(files, [main.clone(), circle.clone(), dir.join(POLICY_FILE)]);
+        assert_eq!(watched_files(&main, &[]), [main.clone(), dir.join(POLICY_FILE)]);
+
+        let mut watcher = Watcher::new(files);
+        assert!(watcher.changed().is_empty());
+        // Creating the policy file counts as a change
+        fs::write(dir.join(POLICY_FILE), "[allow]\n").unwrap();
+        assert_eq!(watcher.changed(), [dir.join(POLICY_FILE)]);
+        assert!(watcher.changed().is_empty());
+        fs::remove_file(&circle).unwrap();
+        assert_eq!(watcher.wait(Duration::from_millis(1)), [circle]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,158 @@
+//! Runs WASI-targeted WokeLang modules under a WASM runtime.
+
+use wasmi::{Caller, Engine, Extern, Linker, Module, Store};
+use wokelang::codegen::{WasmCompiler, WasmTarget, HOST_MODULE, WASI_MODULE};
+use wokelang::{Lexer, Parser};
+
+fn compile_wasi(source: &str) -> Vec<u8> {
+    let tokens = Lexer::new(source).tokenize().expect("Lexer failed");
+    let program = Parser::new(tokens, source).parse().expect("Parser failed");
+    WasmCompiler::new()
+        .with_target(WasmTarget::Wasi)
+        .compile(&program)
+        .expect("WASM compilation failed")
+}
+
+/// Minimal `fd_write` that collects stdout
+fn fd_write(
+    mut caller: Caller<'_, Vec<u8>>,
+    fd: i32,
+    iovs: i32,
+    iovs_len: i32,
+    nwritten: i32,
+) -> i32 {
+    assert_eq!(fd, 1, "only stdout is used");
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        panic!("module does not export memory");
+    };
+
+    let mut total = 0u32;
+    for i in 0..iovs_len as usize {
+        let mut iov = [0u8; 8];
+        memory.read(&caller, iovs as usize + i * 8, &mut iov).unwrap();
+        let ptr = u32::from_le_bytes(iov[..4].try_into().unwrap()) as usize;
+        let len = u32::from_le_bytes(iov[4..].try_into().unwrap()) as usize;
+        let mut bytes = vec![0; len];
+        memory.read(&caller, ptr, &mut bytes).unwrap();
+        caller.data_mut().extend(bytes);
+        total += len as u32;
+    }
+    memory
+        .write(&mut caller, nwritten as usize, &total.to_le_bytes())
+        .unwrap();
+    0
+}
+
+/// Run a module's `_start` and return what it printed
+fn run_wasi(wasm: &[u8]) -> String {
+    let engine = Engine::default();
+    let module = Module::new(&engine, wasm).expect("invalid module");
+    let mut store = Store::new(&engine, Vec::new());
+    let mut linker = Linker::<Vec<u8>>::new(&engine);
+    linker.func_wrap(WASI_MODULE, "fd_write", fd_write).unwrap();
+    // Consent is granted unless the permission starts with "deny"
+    linker
+        .func_wrap(
+            HOST_MODULE,
+            "request_consent",
+            |caller: Caller<'_, Vec<u8>>, ptr: i32, len: i32| -> i32 {
+                let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+                    panic!("module does not export memory");
+                };
+                let mut bytes = vec![0; len as usize];
+                memory.read(&caller, ptr as usize, &mut bytes).unwrap();
+                !bytes.starts_with(b"deny") as i32
+            },
+        )
+        .unwrap();
+
+    let instance = linker
+        .instantiate_and_start(&mut store, &module)
+        .expect("instantiation failed");
+    let start = instance
+        .get_typed_func::<(), ()>(&store, "_start")
+        .expect("missing _start export");
+    start.call(&mut store, ()).expect("_start trapped");
+
+    String::from_utf8(store.into_data()).unwrap()
+}
+
+#[test]
+fn test_wasi_hello_world() {
+    let wasm = compile_wasi(
+        r#"
+        to main() {
+            print("Hello, " + "WASI!");
+        }
+        "#,
+    );
+    assert_eq!(run_wasi(&wasm), "Hello, WASI!\n");
+}
+
+#[test]
+fn test_wasi_prints_numbers() {
+    let wasm = compile_wasi(
+        r#"
+        to main() {
+            print(0);
+            print(1234567890);
+            print(0 - 42);
+            print(3.25);
+            print(0.0 - 2.5);
+            print(7.0);
+            print(0.1 + 0.2);
+            print(1 == 1);
+        }
+        "#,
+    );
+    assert_eq!(
+        run_wasi(&wasm),
+        "0\n1234567890\n-42\n3.25\n-2.5\n7\n0.3\n1\n"
+    );
+}
+
+#[test]
+fn test_wasi_module_without_print_has_no_imports() {
+    let wasm = compile_wasi(
+        r#"
+        to main() -> Int {
+            give back 1;
+        }
+        "#,
+    );
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wasm[..]).unwrap();
+    assert_eq!(module.imports().count(), 0);
+    assert!(module.exports().any(|e| e.name() == "_start"));
+    assert!(module.exports().any(|e| e.name() == "memory"));
+}
+
+#[test]
+fn test_wasi_requires_main() {
+    let source = "to helper() -> Int { give back 1; }";
+    let tokens = Lexer::new(source).tokenize().unwrap();
+    let program = Parser::new(tokens, source).parse().unwrap();
+    let result = WasmCompiler::new()
+        .with_target(WasmTarget::Wasi)
+        .compile(&program);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_wasi_consent_runs_before_main() {
+    let wasm = compile_wasi(
+        r#"
+        only if okay "files" {
+            print("setup");
+        }
+
+        to main() {
+            only if okay "deny.camera" {
+                print("camera");
+            }
+            print("main");
+        }
+        "#,
+    );
+    assert_eq!(run_wasi(&wasm), "setup\nmain\n");
+}
@@ -51,8 +51,32 @@ pub const ValueType = enum(c_int) {
     bool = 3,
     array = 4,
     unit = 5,
+    okay = 6,
+    oops = 7,
+    /// Records, bytes, times, functions and channels
+    other = 8,
 };
 
+/// What went wrong in the last call that failed on this thread; the
+/// strings stay valid until the next call that can fail
+pub const ErrorInfo = extern struct {
+    kind: Result,
+    /// e.g. "WOKE-E0303"
+    code: ?[*:0]const u8,
+    message: [*:0]const u8,
+    /// Where in the source, from 1; 0 if unknown
+    line: u32,
+    column: u32,
+};
+
+/// A host function; see `Interpreter.registerFunction`
+pub const NativeFn = *const fn (
+    user_data: ?*anyopaque,
+    args: [*]const *const WokeValue,
+    arg_count: usize,
+    out_result: *?*WokeValue,
+) callconv(.C) Result;
+
 /// Errors that can occur when using the WokeLang FFI
 pub const Error = error{
     GenericError,
@@ -65,28 +89,51 @@ pub const Error = error{
 // === External C API declarations ===
 
 const WokeInterpreter = opaque {};
-const WokeValue = opaque {};
+pub const WokeValue = opaque {};
 
 extern fn woke_interpreter_new() ?*WokeInterpreter;
 extern fn woke_interpreter_free(interp: *WokeInterpreter) void;
 extern fn woke_exec(interp: *WokeInterpreter, source: [*:0]const u8) Result;
 extern fn woke_eval(interp: *WokeInterpreter, source: [*:0]const u8, out_value: *?*WokeValue) Result;
+extern fn woke_register_function(
+    interp: *WokeInterpreter,
+    name: [*:0]const u8,
+    arity: usize,
+    capabilities: ?[*]const [*:0]const u8,
+    capability_count: usize,
+    callback: NativeFn,
+    user_data: ?*anyopaque,
+) Result;
+extern fn woke_set_global(interp: *WokeInterpreter, name: [*:0]const u8, value: *const WokeValue) Result;
+extern fn woke_get_global(interp: *const WokeInterpreter, name: [*:0]const u8) ?*WokeValue;
 
 extern fn woke_value_free(value: *WokeValue) void;
+extern fn woke_value_clone(value: *const WokeValue) ?*WokeValue;
 extern fn woke_value_type(value: *const WokeValue) ValueType;
 extern fn woke_value_as_int(value: *const WokeValue, out: *i64) Result;
 extern fn woke_value_as_float(value: *const WokeValue, out: *f64) Result;
 extern fn woke_value_as_bool(value: *const WokeValue, out: *c_int) Result;
 extern fn woke_value_as_string(value: *const WokeValue) ?[*:0]u8;
 extern fn woke_string_free(s: [*:0]u8) void;
+extern fn woke_value_array_len(value: *const WokeValue, out: *usize) Result;
+extern fn woke_value_array_get(value: *const WokeValue, index: usize) ?*WokeValue;
+extern fn woke_value_okay_value(value: *const WokeValue) ?*WokeValue;
+extern fn woke_value_oops_message(value: *const WokeValue) ?[*:0]u8;
 
 extern fn woke_value_from_int(n: i64) ?*WokeValue;
 extern fn woke_value_from_float(f: f64) ?*WokeValue;
 extern fn woke_value_from_bool(b: c_int) ?*WokeValue;
 extern fn woke_value_from_string(s: [*:0]const u8) ?*WokeValue;
+extern fn woke_value_unit() ?*WokeValue;
+extern fn woke_value_array_new() ?*WokeValue;
+extern fn woke_value_array_push(array: *WokeValue, item: *const WokeValue) Result;
+extern fn woke_value_okay(value: *const WokeValue) ?*WokeValue;
+extern fn woke_value_oops(message: [*:0]const u8) ?*WokeValue;
 
 extern fn woke_version() [*:0]const u8;
 extern fn woke_last_error() ?[*:0]const u8;
+extern fn woke_last_error_info(out: *ErrorInfo) c_int;
+extern fn woke_set_error(message: [*:0]const u8) void;
 
 // === High-level Zig API ===
 
@@ -127,6 +174,44 @@ pub const Interpreter = struct {
         }
         return Value{ .handle = out_value.? };
     }
+
+    /// Make `callback` callable from programs as `name`, asking for each
+    /// of `capabilities` (e.g. "notify") before every call
+    pub fn registerFunction(
+        self: *Interpreter,
+        name: [:0]const u8,
+        arity: usize,
+        capabilities: []const [*:0]const u8,
+        callback: NativeFn,
+        user_data: ?*anyopaque,
+    ) Error!void {
+        const result = woke_register_function(
+            self.handle,
+            name.ptr,
+            arity,
+            capabilities.ptr,
+            capabilities.len,
+            callback,
+            user_data,
+        );
+        if (result.toError()) |err| {
+            return err;
+        }
+    }
+
+    /// Give a global variable a copy of `value`
+    pub fn setGlobal(self: *Interpreter, name: [:0]const u8, value: Value) Error!void {
+        const result = woke_set_global(self.handle, name.ptr, value.handle);
+        if (result.toError()) |err| {
+            return err;
+        }
+    }
+
+    /// Get a copy of a global variable's value, if there is one
+    pub fn getGlobal(self: *Interpreter, name: [:0]const u8) ?Value {
+        const handle = woke_get_global(self.handle, name.ptr) orelse return null;
+        return .{ .handle = handle };
+    }
 };
 
 /// A WokeLang value
@@ -207,6 +292,78 @@ pub const Value = struct {
         const handle = woke_value_from_string(s.ptr) orelse return null;
         return .{ .handle = handle };
     }
+
+    /// Create Unit
+    pub fn unit() ?Value {
+        const handle = woke_value_unit() orelse return null;
+        return .{ .handle = handle };
+    }
+
+    /// Create an empty array
+    pub fn array() ?Value {
+        const handle = woke_value_array_new() orelse return null;
+        return .{ .handle = handle };
+    }
+
+    /// Create an Okay holding a copy of `value`
+    pub fn okay(value: Value) ?Value {
+        const handle = woke_value_okay(value.handle) orelse return null;
+        return .{ .handle = handle };
+    }
+
+    /// Create an Oops with `message`
+    pub fn oops(message: [:0]const u8) ?Value {
+        const handle = woke_value_oops(message.ptr) orelse return null;
+        return .{ .handle = handle };
+    }
+
+    /// Copy the value
+    pub fn clone(self: Value) ?Value {
+        const handle = woke_value_clone(self.handle) orelse return null;
+        return .{ .handle = handle };
+    }
+
+    /// Add a copy of `item` to the end of an array
+    pub fn push(self: Value, item: Value) Error!void {
+        const result = woke_value_array_push(self.handle, item.handle);
+        if (result.toError()) |err| {
+            return err;
+        }
+    }
+
+    /// Number of elements in an array
+    pub fn len(self: Value) Error!usize {
+        var out: usize = 0;
+        const result = woke_value_array_len(self.handle, &out);
+        if (result.toError()) |err| {
+            return err;
+        }
+        return out;
+    }
+
+    /// Copy of an array's element, if it has one at `index`
+    pub fn get(self: Value, index: usize) ?Value {
+        const handle = woke_value_array_get(self.handle, index) orelse return null;
+        return .{ .handle = handle };
+    }
+
+    /// Copy of the value inside an Okay, if it is one
+    pub fn okayValue(self: Value) ?Value {
+        const handle = woke_value_okay_value(self.handle) orelse return null;
+        return .{ .handle = handle };
+    }
+
+    /// Message of an Oops (allocates), if it is one
+    pub fn oopsMessage(self: Value, allocator: std.mem.Allocator) Error!?[]u8 {
+        const c_str = woke_value_oops_message(self.handle) orelse return null;
+        defer woke_string_free(c_str);
+        return allocator.dupe(u8, std.mem.span(c_str)) catch return Error.GenericError;
+    }
+
+    /// Borrow an argument passed to a host function
+    pub fn borrowed(handle: *const WokeValue) Value {
+        return .{ .handle = @constCast(handle) };
+    }
 };
 
 /// Get the WokeLang version string
@@ -221,6 +378,20 @@ pub fn lastError() ?[]const u8 {
     return std.mem.span(err);
 }
 
+/// Get what went wrong in the last call on this thread, if it failed
+pub fn lastErrorInfo() ?ErrorInfo {
+    var info: ErrorInfo = undefined;
+    if (woke_last_error_info(&info) == 0) {
+        return null;
+    }
+    return info;
+}
+
+/// Say why a host function is failing, before it returns an error
+pub fn setError(message: [:0]const u8) void {
+    woke_set_error(message.ptr);
+}
+
 // === Tests ===
 
 test "version" {